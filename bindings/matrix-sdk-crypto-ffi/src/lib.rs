@@ -34,7 +34,8 @@ use matrix_sdk_crypto::{
     olm::{IdentityKeys, InboundGroupSession, Session},
     store::{Changes, CryptoStore, RoomSettings as RustRoomSettings},
     types::{EventEncryptionAlgorithm as RustEventEncryptionAlgorithm, SigningKey},
-    EncryptionSettings as RustEncryptionSettings, LocalTrust,
+    CollectStrategy as RustCollectStrategy, EncryptionSettings as RustEncryptionSettings,
+    LocalTrust,
 };
 use matrix_sdk_sqlite::SqliteCryptoStore;
 pub use responses::{
@@ -466,6 +467,7 @@ fn collect_sessions(
             imported: session.imported,
             backed_up: session.backed_up,
             history_visibility: None,
+            shared_history: false,
             algorithm: RustEventEncryptionAlgorithm::MegolmV1AesSha2,
         };
 
@@ -611,6 +613,35 @@ impl From<HistoryVisibility> for RustHistoryVisibility {
     }
 }
 
+/// Strategy to collect the devices that should receive a room key.
+#[derive(uniffi::Enum)]
+pub enum CollectStrategy {
+    /// Share the room key with all the devices of the room members, except
+    /// the blacklisted ones.
+    AllDevices,
+    /// Only share the room key with devices that have been cross-signed by
+    /// their owner, or that we have verified manually.
+    OnlyCrossSignedDevices,
+    /// Only share the room key with devices of users that we have verified,
+    /// when the devices have been cross-signed by their owner.
+    OnlyVerifiedUsers,
+    /// Share the room key with all the devices of the room members, but
+    /// refuse to share it at all if a verified user has an unsigned device
+    /// or if a previously verified user has changed their identity.
+    ErrorOnIdentityChange,
+}
+
+impl From<CollectStrategy> for RustCollectStrategy {
+    fn from(value: CollectStrategy) -> Self {
+        match value {
+            CollectStrategy::AllDevices => Self::AllDevices,
+            CollectStrategy::OnlyCrossSignedDevices => Self::OnlyCrossSignedDevices,
+            CollectStrategy::OnlyVerifiedUsers => Self::OnlyVerifiedUsers,
+            CollectStrategy::ErrorOnIdentityChange => Self::ErrorOnIdentityChange,
+        }
+    }
+}
+
 /// Settings that should be used when a room key is shared.
 ///
 /// These settings control which algorithm the room key should use, how long a
@@ -632,6 +663,8 @@ pub struct EncryptionSettings {
     /// Should untrusted devices receive the room key, or should they be
     /// excluded from the conversation.
     pub only_allow_trusted_devices: bool,
+    /// The strategy used to select the devices that receive the room key.
+    pub sharing_strategy: CollectStrategy,
 }
 
 impl From<EncryptionSettings> for RustEncryptionSettings {
@@ -642,6 +675,7 @@ impl From<EncryptionSettings> for RustEncryptionSettings {
             rotation_period_msgs: v.rotation_period_msgs,
             history_visibility: v.history_visibility.into(),
             only_allow_trusted_devices: v.only_allow_trusted_devices,
+            sharing_strategy: v.sharing_strategy.into(),
         }
     }
 }
@@ -835,6 +869,7 @@ impl From<RoomSettings> for RustRoomSettings {
         Self {
             algorithm: value.algorithm.into(),
             only_allow_trusted_devices: value.only_allow_trusted_devices,
+            sharing_strategy: None,
         }
     }
 }
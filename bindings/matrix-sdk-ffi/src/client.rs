@@ -33,7 +33,7 @@ use url::Url;
 use super::{room::Room, session_verification::SessionVerificationController, RUNTIME};
 use crate::{
     app::AppBuilder, client, notification::NotificationClientBuilder,
-    notification_settings::NotificationSettings, ClientError,
+    notification_settings::NotificationSettings, spaces::SpaceService, ClientError,
 };
 
 #[derive(Clone, uniffi::Record)]
@@ -592,6 +592,12 @@ impl Client {
         AppBuilder::new(self.inner.clone())
     }
 
+    pub fn space_service(&self) -> Result<Arc<SpaceService>, ClientError> {
+        RUNTIME.block_on(async move {
+            Ok(SpaceService::new(matrix_sdk_ui::SpaceService::new(self.inner.clone()).await?))
+        })
+    }
+
     pub fn get_notification_settings(&self) -> Arc<NotificationSettings> {
         RUNTIME.block_on(async move {
             Arc::new(NotificationSettings::new(
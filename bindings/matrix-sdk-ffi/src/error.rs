@@ -4,7 +4,7 @@ use matrix_sdk::{
     self, encryption::CryptoStoreError, HttpError, IdParseError,
     NotificationSettingsError as SdkNotificationSettingsError, StoreError,
 };
-use matrix_sdk_ui::{app, encryption_sync, notification_client, timeline};
+use matrix_sdk_ui::{app, encryption_sync, notification_client, spaces, timeline};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ClientError {
@@ -54,6 +54,12 @@ impl From<IdParseError> for ClientError {
     }
 }
 
+impl From<spaces::Error> for ClientError {
+    fn from(e: spaces::Error) -> Self {
+        Self::new(e)
+    }
+}
+
 impl From<serde_json::Error> for ClientError {
     fn from(e: serde_json::Error) -> Self {
         Self::new(e)
@@ -34,6 +34,7 @@ mod room;
 mod room_list;
 mod room_member;
 mod session_verification;
+mod spaces;
 mod task_handle;
 mod timeline;
 mod tracing;
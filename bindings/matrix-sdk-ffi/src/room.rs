@@ -37,7 +37,7 @@ use crate::{
     error::{ClientError, RoomError},
     room_member::RoomMember,
     timeline::{
-        AudioInfo, FileInfo, ImageInfo, ThumbnailInfo, TimelineDiff, TimelineItem,
+        AudioInfo, FileInfo, ImageInfo, PollKind, ThumbnailInfo, TimelineDiff, TimelineItem,
         TimelineListener, VideoInfo,
     },
     TaskHandle,
@@ -48,6 +48,7 @@ pub enum Membership {
     Invited,
     Joined,
     Left,
+    Knocked,
 }
 
 pub(crate) type TimelineLock = Arc<RwLock<Option<Arc<Timeline>>>>;
@@ -115,6 +116,7 @@ impl Room {
             SdkRoom::Invited(_) => Membership::Invited,
             SdkRoom::Joined(_) => Membership::Joined,
             SdkRoom::Left(_) => Membership::Left,
+            SdkRoom::Knocked(_) => Membership::Knocked,
         }
     }
 
@@ -129,6 +131,7 @@ impl Room {
             }),
             SdkRoom::Joined(_) => None,
             SdkRoom::Left(_) => None,
+            SdkRoom::Knocked(_) => None,
         }
     }
 
@@ -364,11 +367,6 @@ impl Room {
         in_reply_to_event_id: String,
         txn_id: Option<String>,
     ) -> Result<(), ClientError> {
-        let room = match &self.inner {
-            SdkRoom::Joined(j) => j.clone(),
-            _ => return Err(anyhow!("Can't send to a room that isn't in joined state").into()),
-        };
-
         let timeline = match &*RUNTIME.block_on(self.timeline.read()) {
             Some(t) => Arc::clone(t),
             None => return Err(anyhow!("Timeline not set up, can't send message").into()),
@@ -377,8 +375,24 @@ impl Room {
         let event_id: &EventId =
             in_reply_to_event_id.as_str().try_into().context("Failed to create EventId.")?;
 
+        let content = RoomMessageEventContent::text_markdown(msg);
+
+        if let Some(replied_to_item) = RUNTIME.block_on(timeline.item_by_event_id(event_id)) {
+            RUNTIME.spawn(async move {
+                let txn_id = txn_id.as_deref().map(Into::into);
+                if let Err(e) =
+                    timeline.send_reply(content, &replied_to_item, ForwardThread::Yes, txn_id).await
+                {
+                    error!("Failed to send reply: {e}");
+                }
+            });
+            return Ok(());
+        }
+
+        // The event isn't loaded in the timeline, fetch it from the homeserver.
         let reply_content = RUNTIME.block_on(async move {
-            let timeline_event = room.event(event_id).await.context("Couldn't find event.")?;
+            let timeline_event =
+                self.inner.event(event_id).await.context("Couldn't find event.")?;
 
             let event_content = timeline_event
                 .event
@@ -388,10 +402,7 @@ impl Room {
             let original_message =
                 event_content.as_original().context("Couldn't retrieve original message.")?;
 
-            anyhow::Ok(
-                RoomMessageEventContent::text_markdown(msg)
-                    .make_reply_to(original_message, ForwardThread::Yes),
-            )
+            anyhow::Ok(content.make_reply_to(original_message, ForwardThread::Yes))
         })?;
 
         RUNTIME.spawn(async move {
@@ -406,11 +417,6 @@ impl Room {
         original_event_id: String,
         txn_id: Option<String>,
     ) -> Result<(), ClientError> {
-        let room = match &self.inner {
-            SdkRoom::Joined(j) => j.clone(),
-            _ => return Err(anyhow!("Can't send to a room that isn't in joined state").into()),
-        };
-
         let timeline = match &*RUNTIME.block_on(self.timeline.read()) {
             Some(t) => Arc::clone(t),
             None => return Err(anyhow!("Timeline not set up, can't send message").into()),
@@ -419,8 +425,25 @@ impl Room {
         let event_id: &EventId =
             original_event_id.as_str().try_into().context("Failed to create EventId.")?;
 
+        if let Some(edit_item) = RUNTIME.block_on(timeline.item_by_event_id(event_id)) {
+            if !edit_item.is_editable() {
+                return Err(anyhow!("Can't edit this event").into());
+            }
+
+            RUNTIME.spawn(async move {
+                let content = RoomMessageEventContent::text_markdown(new_msg);
+                let txn_id = txn_id.as_deref().map(Into::into);
+                if let Err(e) = timeline.edit(&edit_item, content, txn_id).await {
+                    error!("Failed to edit event: {e}");
+                }
+            });
+            return Ok(());
+        }
+
+        // The event isn't loaded in the timeline, fetch it from the homeserver.
         let edited_content = RUNTIME.block_on(async move {
-            let timeline_event = room.event(event_id).await.context("Couldn't find event.")?;
+            let timeline_event =
+                self.inner.event(event_id).await.context("Couldn't find event.")?;
 
             let event_content = timeline_event
                 .event
@@ -437,6 +460,13 @@ impl Room {
             );
 
             let mut edited_content = RoomMessageEventContent::text_markdown(new_msg);
+            // Add the fallback for clients that don't support edits.
+            if let MessageType::Text(text) = &mut edited_content.msgtype {
+                text.body = format!("* {}", text.body);
+                if let Some(formatted) = &mut text.formatted {
+                    formatted.body = format!("* {}", formatted.body);
+                }
+            }
             edited_content.relates_to = Some(Relation::Replacement(replacement));
             Ok(edited_content)
         })?;
@@ -857,6 +887,77 @@ impl Room {
         self.send(Arc::new(room_message_event_content), txn_id)
     }
 
+    pub fn create_poll(
+        &self,
+        question: String,
+        answers: Vec<String>,
+        max_selections: u8,
+        poll_kind: PollKind,
+        txn_id: Option<String>,
+    ) -> Result<(), ClientError> {
+        let room = match &self.inner {
+            SdkRoom::Joined(j) => j.clone(),
+            _ => {
+                return Err(
+                    anyhow!("Can't create a poll in a room that isn't in joined state").into()
+                )
+            }
+        };
+
+        RUNTIME.block_on(async move {
+            let txn_id = txn_id.as_deref().map(Into::into);
+            room.create_poll(question, answers, max_selections, poll_kind.into(), txn_id).await?;
+            Ok(())
+        })
+    }
+
+    pub fn send_poll_response(
+        &self,
+        poll_start_id: String,
+        answers: Vec<String>,
+        txn_id: Option<String>,
+    ) -> Result<(), ClientError> {
+        let room = match &self.inner {
+            SdkRoom::Joined(j) => j.clone(),
+            _ => {
+                return Err(anyhow!(
+                    "Can't send a poll response in a room that isn't in joined state"
+                )
+                .into())
+            }
+        };
+
+        let poll_start_id = EventId::parse(poll_start_id).context("Failed to create EventId.")?;
+
+        RUNTIME.block_on(async move {
+            let txn_id = txn_id.as_deref().map(Into::into);
+            room.send_poll_response(&poll_start_id, answers, txn_id).await?;
+            Ok(())
+        })
+    }
+
+    pub fn end_poll(
+        &self,
+        poll_start_id: String,
+        text: String,
+        txn_id: Option<String>,
+    ) -> Result<(), ClientError> {
+        let room = match &self.inner {
+            SdkRoom::Joined(j) => j.clone(),
+            _ => {
+                return Err(anyhow!("Can't end a poll in a room that isn't in joined state").into())
+            }
+        };
+
+        let poll_start_id = EventId::parse(poll_start_id).context("Failed to create EventId.")?;
+
+        RUNTIME.block_on(async move {
+            let txn_id = txn_id.as_deref().map(Into::into);
+            room.end_poll(&poll_start_id, &text, txn_id).await?;
+            Ok(())
+        })
+    }
+
     pub fn cancel_send(&self, txn_id: String) {
         let timeline = match &*RUNTIME.block_on(self.timeline.read()) {
             Some(t) => Arc::clone(t),
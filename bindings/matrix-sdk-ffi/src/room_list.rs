@@ -8,9 +8,20 @@ use matrix_sdk::{
             v4::RoomSubscription as RumaRoomSubscription,
             UnreadNotificationsCount as RumaUnreadNotificationsCount,
         },
-        assign, RoomId,
+        assign, OwnedRoomId, RoomId,
+    },
+    Client, RoomListEntry as MatrixRoomListEntry,
+};
+use matrix_sdk_ui::room_list_service::{
+    filters::{
+        new_filter_all, new_filter_category, new_filter_favourite,
+        new_filter_fuzzy_match_room_name, new_filter_invite, new_filter_low_priority,
+        new_filter_space, new_filter_unread, BoxedFilterFn, RoomCategory,
+    },
+    sorters::{
+        new_sorter_lexicographic, new_sorter_name, new_sorter_recency, new_sorter_unread,
+        BoxedSorterFn,
     },
-    RoomListEntry as MatrixRoomListEntry,
 };
 use tokio::sync::RwLock;
 
@@ -161,6 +172,28 @@ impl RoomList {
         })
     }
 
+    fn entries_with_dynamic_adapters(
+        &self,
+        listener: Box<dyn RoomListEntriesListener>,
+    ) -> RoomListEntriesWithDynamicAdaptersResult {
+        let (entries, entries_stream, controller) = self.inner.entries_with_dynamic_adapters();
+
+        RoomListEntriesWithDynamicAdaptersResult {
+            entries: entries.into_iter().map(Into::into).collect(),
+            entries_stream: Arc::new(TaskHandle::new(RUNTIME.spawn(async move {
+                pin_mut!(entries_stream);
+
+                while let Some(diff) = entries_stream.next().await {
+                    listener.on_update(diff.into());
+                }
+            }))),
+            controller: Arc::new(RoomListDynamicEntriesController {
+                client: self.room_list_service.inner.client().clone(),
+                inner: controller,
+            }),
+        }
+    }
+
     fn room(&self, room_id: String) -> Result<Arc<RoomListItem>, RoomListError> {
         self.room_list_service.room(room_id)
     }
@@ -172,6 +205,122 @@ pub struct RoomListEntriesResult {
     pub entries_stream: Arc<TaskHandle>,
 }
 
+#[derive(uniffi::Record)]
+pub struct RoomListEntriesWithDynamicAdaptersResult {
+    pub entries: Vec<RoomListEntry>,
+    pub entries_stream: Arc<TaskHandle>,
+    pub controller: Arc<RoomListDynamicEntriesController>,
+}
+
+#[derive(uniffi::Object)]
+pub struct RoomListDynamicEntriesController {
+    client: Client,
+    inner: matrix_sdk_ui::room_list_service::RoomListDynamicEntriesController,
+}
+
+#[uniffi::export]
+impl RoomListDynamicEntriesController {
+    /// Keep only the entries that match all the given filters. An empty list
+    /// of filters keeps all the entries.
+    fn set_filters(&self, filters: Vec<RoomListFilter>) -> Result<(), RoomListError> {
+        if filters.is_empty() {
+            self.inner.reset_filter();
+
+            return Ok(());
+        }
+
+        let filters = filters
+            .into_iter()
+            .map(|filter| filter.into_filter(&self.client))
+            .collect::<Result<_, _>>()?;
+
+        self.inner.set_filter(Box::new(new_filter_all(filters)));
+
+        Ok(())
+    }
+
+    /// Sort the entries with the given sorters, the first sorter being the
+    /// most significant. An empty list of sorters keeps the order of the
+    /// server.
+    fn set_sorters(&self, sorters: Vec<RoomListSorter>) {
+        if sorters.is_empty() {
+            self.inner.reset_sorter();
+
+            return;
+        }
+
+        let sorters = sorters.into_iter().map(|sorter| sorter.into_sorter(&self.client)).collect();
+
+        self.inner.set_sorter(Box::new(new_sorter_lexicographic(sorters)));
+    }
+}
+
+#[derive(uniffi::Enum)]
+pub enum RoomListFilter {
+    Unread,
+    Favourite,
+    LowPriority,
+    Invite,
+    Category { expect: RoomListFilterCategory },
+    FuzzyMatchRoomName { pattern: String },
+    Space { room_ids: Vec<String> },
+}
+
+impl RoomListFilter {
+    fn into_filter(self, client: &Client) -> Result<BoxedFilterFn, RoomListError> {
+        Ok(match self {
+            Self::Unread => Box::new(new_filter_unread(client)),
+            Self::Favourite => Box::new(new_filter_favourite(client)),
+            Self::LowPriority => Box::new(new_filter_low_priority(client)),
+            Self::Invite => Box::new(new_filter_invite(client)),
+            Self::Category { expect } => Box::new(new_filter_category(client, expect.into())),
+            Self::FuzzyMatchRoomName { pattern } => {
+                Box::new(new_filter_fuzzy_match_room_name(client, &pattern))
+            }
+            Self::Space { room_ids } => {
+                let room_ids = room_ids
+                    .into_iter()
+                    .map(OwnedRoomId::try_from)
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Box::new(new_filter_space(room_ids))
+            }
+        })
+    }
+}
+
+#[derive(uniffi::Enum)]
+pub enum RoomListFilterCategory {
+    Group,
+    People,
+}
+
+impl From<RoomListFilterCategory> for RoomCategory {
+    fn from(value: RoomListFilterCategory) -> Self {
+        match value {
+            RoomListFilterCategory::Group => Self::Group,
+            RoomListFilterCategory::People => Self::People,
+        }
+    }
+}
+
+#[derive(uniffi::Enum)]
+pub enum RoomListSorter {
+    Recency,
+    Name,
+    Unread,
+}
+
+impl RoomListSorter {
+    fn into_sorter(self, client: &Client) -> BoxedSorterFn {
+        match self {
+            Self::Recency => Box::new(new_sorter_recency(client)),
+            Self::Name => Box::new(new_sorter_name(client)),
+            Self::Unread => Box::new(new_sorter_unread(client)),
+        }
+    }
+}
+
 #[derive(uniffi::Record)]
 pub struct RoomListLoadingStateResult {
     pub state: RoomListLoadingState,
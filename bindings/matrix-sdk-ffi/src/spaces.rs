@@ -0,0 +1,141 @@
+use std::{fmt::Debug, sync::Arc};
+
+use matrix_sdk::{ruma::RoomId, RoomState};
+use matrix_sdk_ui::spaces::{
+    SpaceHierarchy as SdkSpaceHierarchy, SpaceRoom as SdkSpaceRoom, SpaceService as SdkSpaceService,
+};
+
+use crate::{error::ClientError, TaskHandle, RUNTIME};
+
+#[derive(uniffi::Object)]
+pub struct SpaceService {
+    inner: Arc<SdkSpaceService>,
+}
+
+impl SpaceService {
+    pub(crate) fn new(inner: SdkSpaceService) -> Arc<Self> {
+        Arc::new(Self { inner: Arc::new(inner) })
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl SpaceService {
+    pub fn top_level_joined_spaces(&self) -> Vec<SpaceRoom> {
+        self.inner.top_level_joined_spaces().into_iter().map(Into::into).collect()
+    }
+
+    pub fn subscribe_to_top_level_joined_spaces(
+        &self,
+        listener: Box<dyn SpaceServiceJoinedSpacesListener>,
+    ) -> Arc<TaskHandle> {
+        let mut subscriber = self.inner.subscribe_to_top_level_joined_spaces();
+
+        Arc::new(TaskHandle::new(RUNTIME.spawn(async move {
+            while let Some(spaces) = subscriber.next().await {
+                listener.on_update(spaces.into_iter().map(Into::into).collect());
+            }
+        })))
+    }
+
+    pub fn space_hierarchy(&self, space_id: String) -> Result<Arc<SpaceHierarchy>, ClientError> {
+        let space_id = RoomId::parse(space_id)?;
+        Ok(Arc::new(SpaceHierarchy { inner: self.inner.space_hierarchy(&space_id, None) }))
+    }
+
+    pub async fn add_child_to_space(
+        &self,
+        child_id: String,
+        space_id: String,
+    ) -> Result<(), ClientError> {
+        let child_id = RoomId::parse(child_id)?;
+        let space_id = RoomId::parse(space_id)?;
+        self.inner.add_child_to_space(&child_id, &space_id).await.map_err(ClientError::from)
+    }
+
+    pub async fn remove_child_from_space(
+        &self,
+        child_id: String,
+        space_id: String,
+    ) -> Result<(), ClientError> {
+        let child_id = RoomId::parse(child_id)?;
+        let space_id = RoomId::parse(space_id)?;
+        self.inner.remove_child_from_space(&child_id, &space_id).await.map_err(ClientError::from)
+    }
+}
+
+#[derive(uniffi::Object)]
+pub struct SpaceHierarchy {
+    inner: SdkSpaceHierarchy,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl SpaceHierarchy {
+    pub async fn paginate(&self, limit: Option<u32>) -> Result<(), ClientError> {
+        self.inner.paginate(limit.map(Into::into)).await.map_err(ClientError::from)
+    }
+
+    pub async fn is_at_end(&self) -> bool {
+        self.inner.is_at_end().await
+    }
+
+    pub fn rooms(&self) -> Vec<SpaceRoom> {
+        self.inner.rooms().into_iter().map(Into::into).collect()
+    }
+}
+
+#[uniffi::export(callback_interface)]
+pub trait SpaceServiceJoinedSpacesListener: Send + Sync + Debug {
+    fn on_update(&self, spaces: Vec<SpaceRoom>);
+}
+
+#[derive(uniffi::Enum)]
+pub enum SpaceRoomMembership {
+    Joined,
+    Left,
+    Invited,
+    Knocked,
+}
+
+impl From<RoomState> for SpaceRoomMembership {
+    fn from(value: RoomState) -> Self {
+        match value {
+            RoomState::Joined => Self::Joined,
+            RoomState::Left => Self::Left,
+            RoomState::Invited => Self::Invited,
+            RoomState::Knocked => Self::Knocked,
+        }
+    }
+}
+
+#[derive(uniffi::Record)]
+pub struct SpaceRoom {
+    pub room_id: String,
+    pub canonical_alias: Option<String>,
+    pub name: Option<String>,
+    pub topic: Option<String>,
+    pub avatar_url: Option<String>,
+    pub is_space: bool,
+    pub num_joined_members: u64,
+    pub world_readable: bool,
+    pub guest_can_join: bool,
+    pub children_count: u64,
+    pub membership: Option<SpaceRoomMembership>,
+}
+
+impl From<SdkSpaceRoom> for SpaceRoom {
+    fn from(value: SdkSpaceRoom) -> Self {
+        Self {
+            room_id: value.room_id.to_string(),
+            canonical_alias: value.canonical_alias.map(|alias| alias.to_string()),
+            name: value.name,
+            topic: value.topic,
+            avatar_url: value.avatar_url.map(|url| url.to_string()),
+            is_space: value.is_space,
+            num_joined_members: value.num_joined_members,
+            world_readable: value.world_readable,
+            guest_can_join: value.guest_can_join,
+            children_count: value.children_count,
+            membership: value.state.map(Into::into),
+        }
+    }
+}
@@ -7,6 +7,7 @@ use matrix_sdk::{
     attachment::{BaseAudioInfo, BaseFileInfo, BaseImageInfo, BaseThumbnailInfo, BaseVideoInfo},
     ruma::events::{
         location::AssetType as RumaAssetType,
+        poll::start::PollKind as RumaPollKind,
         room::{
             message::{
                 AudioInfo as RumaAudioInfo,
@@ -411,6 +412,24 @@ impl TimelineItemContent {
                     url: content.url.to_string(),
                 }
             }
+            Content::Poll(poll_state) => TimelineItemContentKind::Poll {
+                question: poll_state.question().to_owned(),
+                kind: poll_state.kind().into(),
+                max_selections: poll_state.max_selections(),
+                answers: poll_state
+                    .answers()
+                    .into_iter()
+                    .map(|answer| PollAnswer { id: answer.id, text: answer.text })
+                    .collect(),
+                votes: poll_state
+                    .votes()
+                    .into_iter()
+                    .map(|(answer_id, users)| {
+                        (answer_id, users.iter().map(ToString::to_string).collect())
+                    })
+                    .collect(),
+                end_time: poll_state.end_time().map(|ts| ts.0.into()),
+            },
             Content::UnableToDecrypt(msg) => {
                 TimelineItemContentKind::UnableToDecrypt { msg: EncryptedMessage::new(msg) }
             }
@@ -474,6 +493,16 @@ pub enum TimelineItemContentKind {
         info: ImageInfo,
         url: String,
     },
+    Poll {
+        question: String,
+        kind: PollKind,
+        max_selections: u64,
+        answers: Vec<PollAnswer>,
+        /// The IDs of the users who voted, by answer ID.
+        votes: HashMap<String, Vec<String>>,
+        /// The time when the poll was ended, if it was.
+        end_time: Option<u64>,
+    },
     UnableToDecrypt {
         msg: EncryptedMessage,
     },
@@ -502,6 +531,37 @@ pub enum TimelineItemContentKind {
     },
 }
 
+#[derive(Clone, uniffi::Enum)]
+pub enum PollKind {
+    Disclosed,
+    Undisclosed,
+}
+
+impl From<PollKind> for RumaPollKind {
+    fn from(value: PollKind) -> Self {
+        match value {
+            PollKind::Disclosed => Self::Disclosed,
+            PollKind::Undisclosed => Self::Undisclosed,
+        }
+    }
+}
+
+impl From<&RumaPollKind> for PollKind {
+    fn from(value: &RumaPollKind) -> Self {
+        match value {
+            RumaPollKind::Disclosed => Self::Disclosed,
+            // Unknown kinds must be treated as undisclosed.
+            _ => Self::Undisclosed,
+        }
+    }
+}
+
+#[derive(Clone, uniffi::Record)]
+pub struct PollAnswer {
+    pub id: String,
+    pub text: String,
+}
+
 #[derive(Clone, uniffi::Object)]
 pub struct Message(matrix_sdk_ui::timeline::Message);
 
@@ -22,10 +22,11 @@ use std::{
 };
 
 use eyeball::{SharedObservable, Subscriber};
-use matrix_sdk_common::instant::Instant;
+use matrix_sdk_common::{instant::Instant, store_locks::CrossProcessStoreLock};
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_crypto::{
-    store::DynCryptoStore, EncryptionSettings, OlmError, OlmMachine, ToDeviceRequest,
+    store::{DynCryptoStore, RoomSettings},
+    EncryptionSettings, OlmError, OlmMachine, ToDeviceRequest,
 };
 #[cfg(feature = "e2e-encryption")]
 use ruma::events::{
@@ -63,8 +64,8 @@ use crate::{
     error::Result,
     rooms::{Room, RoomInfo, RoomState},
     store::{
-        ambiguity_map::AmbiguityCache, DynStateStore, Result as StoreResult, StateChanges,
-        StateStoreDataKey, StateStoreDataValue, StateStoreExt, Store, StoreConfig,
+        ambiguity_map::AmbiguityCache, DynStateStore, LockableStateStore, Result as StoreResult,
+        StateChanges, StateStoreDataKey, StateStoreDataValue, StateStoreExt, Store, StoreConfig,
     },
     sync::{JoinedRoom, LeftRoom, Rooms, SyncResponse, Timeline},
     RoomStateFilter, SessionMeta,
@@ -91,6 +92,9 @@ pub struct BaseClient {
     /// [`BaseClient::set_session_meta`]
     #[cfg(feature = "e2e-encryption")]
     olm_machine: Arc<RwLock<Option<OlmMachine>>>,
+    /// Notified every time the `OlmMachine` is (re)created.
+    #[cfg(feature = "e2e-encryption")]
+    olm_machine_changes_tx: Arc<SharedObservable<()>>,
     pub(crate) ignore_user_list_changes_tx: Arc<SharedObservable<()>>,
 }
 
@@ -123,6 +127,8 @@ impl BaseClient {
             crypto_store: config.crypto_store,
             #[cfg(feature = "e2e-encryption")]
             olm_machine: Default::default(),
+            #[cfg(feature = "e2e-encryption")]
+            olm_machine_changes_tx: Default::default(),
             ignore_user_list_changes_tx: Default::default(),
         }
     }
@@ -164,6 +170,20 @@ impl BaseClient {
         &*self.store
     }
 
+    /// Creates a cross-process lock backed by the state store, that will
+    /// contain the given key and value when held.
+    pub fn create_state_store_lock(
+        &self,
+        lock_key: String,
+        lock_holder: String,
+    ) -> CrossProcessStoreLock<LockableStateStore> {
+        CrossProcessStoreLock::new(
+            LockableStateStore(self.store.inner.clone()),
+            lock_key,
+            lock_holder,
+        )
+    }
+
     /// Is the client logged in.
     pub fn logged_in(&self) -> bool {
         self.store.session_meta().is_some()
@@ -190,6 +210,14 @@ impl BaseClient {
         Ok(())
     }
 
+    /// Forget the meta of the session, after it was logged out.
+    ///
+    /// The client isn't logged in anymore, and a new session can't be set
+    /// afterwards, a new client must be created to log in again.
+    pub fn clear_session_meta(&self) {
+        self.store.clear_session_meta();
+    }
+
     /// Recreate an `OlmMachine` from scratch.
     ///
     /// In particular, this will clear all its caches.
@@ -208,9 +236,22 @@ impl BaseClient {
         .map_err(OlmError::from)?;
 
         *self.olm_machine.write().await = Some(olm_machine);
+        self.olm_machine_changes_tx.set(());
+
         Ok(())
     }
 
+    /// Returns a subscriber that publishes an event every time the
+    /// `OlmMachine` is created or recreated, e.g. after another process
+    /// changed the crypto store.
+    ///
+    /// The streams of the previous `OlmMachine` end when it is recreated, so
+    /// this can be used to subscribe to the new one.
+    #[cfg(feature = "e2e-encryption")]
+    pub fn subscribe_to_olm_machine_changes(&self) -> Subscriber<()> {
+        self.olm_machine_changes_tx.subscribe()
+    }
+
     /// Get the current, if any, sync token of the client.
     /// This will be None if the client didn't sync at least once.
     pub async fn sync_token(&self) -> Option<String> {
@@ -346,12 +387,17 @@ impl BaseClient {
                             AnySyncMessageLikeEvent::RoomEncrypted(
                                 SyncMessageLikeEvent::Original(_),
                             ) => {
-                                if let Ok(Some(e)) = Box::pin(
+                                match Box::pin(
                                     self.decrypt_sync_room_event(&event.event, room.room_id()),
                                 )
                                 .await
                                 {
-                                    event = e;
+                                    Ok(Some(e)) => event = e,
+                                    Ok(None) => {}
+                                    Err(Error::MegolmError(e)) => {
+                                        event.unable_to_decrypt_reason = Some((&e).into());
+                                    }
+                                    Err(_) => {}
                                 }
                             }
                             AnySyncMessageLikeEvent::RoomMessage(
@@ -506,10 +552,15 @@ impl BaseClient {
         &self,
         room_id: &RoomId,
         events: &[Raw<AnyRoomAccountDataEvent>],
+        room_info: &mut RoomInfo,
         changes: &mut StateChanges,
     ) {
         for raw_event in events {
             if let Ok(event) = raw_event.deserialize() {
+                if let AnyRoomAccountDataEvent::Tag(tag_event) = &event {
+                    room_info.update_notable_tags(&tag_event.content.tags);
+                }
+
                 changes.add_room_account_data(room_id, event, raw_event.clone());
             }
         }
@@ -691,6 +742,27 @@ impl BaseClient {
         Ok(room)
     }
 
+    /// User has knocked on a room.
+    ///
+    /// Update the internal and cached state accordingly. Return the final Room.
+    pub async fn room_knocked(&self, room_id: &RoomId) -> Result<Room> {
+        let room = self.store.get_or_create_room(room_id, RoomState::Knocked);
+        if room.state() != RoomState::Knocked {
+            let _sync_lock = self.sync_lock().read().await;
+
+            let mut room_info = room.clone_info();
+            room_info.mark_as_knocked();
+            room_info.mark_state_partially_synced();
+            room_info.mark_members_missing(); // the own member event changed
+            let mut changes = StateChanges::default();
+            changes.add_room(room_info.clone());
+            self.store.save_changes(&changes).await?; // Update the store
+            room.update_summary(room_info); // Update the cached room handle
+        }
+
+        Ok(room)
+    }
+
     /// Get access to the store's sync lock.
     pub fn sync_lock(&self) -> &RwLock<()> {
         self.store.sync_lock()
@@ -794,8 +866,13 @@ impl BaseClient {
                 )
                 .await?;
 
-            self.handle_room_account_data(&room_id, &new_info.account_data.events, &mut changes)
-                .await;
+            self.handle_room_account_data(
+                &room_id,
+                &new_info.account_data.events,
+                &mut room_info,
+                &mut changes,
+            )
+            .await;
 
             #[cfg(feature = "e2e-encryption")]
             if room_info.is_encrypted() {
@@ -862,8 +939,13 @@ impl BaseClient {
                 )
                 .await?;
 
-            self.handle_room_account_data(&room_id, &new_info.account_data.events, &mut changes)
-                .await;
+            self.handle_room_account_data(
+                &room_id,
+                &new_info.account_data.events,
+                &mut room_info,
+                &mut changes,
+            )
+            .await;
 
             changes.add_room(room_info);
             new_rooms.leave.insert(
@@ -885,6 +967,19 @@ impl BaseClient {
             new_rooms.invite.insert(room_id, new_info);
         }
 
+        for (room_id, new_info) in response.rooms.knock {
+            let room = self.store.get_or_create_room(&room_id, RoomState::Knocked);
+            let mut room_info = room.clone_info();
+            room_info.mark_as_knocked();
+            room_info.mark_state_fully_synced();
+
+            self.handle_invited_state(&new_info.knock_state.events, &mut room_info, &mut changes);
+
+            changes.add_room(room_info);
+
+            new_rooms.knock.insert(room_id, new_info);
+        }
+
         // TODO remove this, we're processing account data events here again
         // because we want to have the push rules in place before we process
         // rooms and their events, but we want to create the rooms before we
@@ -1093,14 +1188,11 @@ impl BaseClient {
     pub async fn share_room_key(&self, room_id: &RoomId) -> Result<Vec<Arc<ToDeviceRequest>>> {
         match self.olm_machine().await.as_ref() {
             Some(o) => {
-                let (history_visibility, settings) = self
-                    .get_room(room_id)
-                    .map(|r| (r.history_visibility(), r.encryption_settings()))
-                    .unwrap_or((HistoryVisibility::Joined, None));
+                let settings = self.room_encryption_settings(o, room_id).await?;
 
                 // Don't share the group session with members that are invited
                 // if the history visibility is set to `Joined`
-                let filter = if history_visibility == HistoryVisibility::Joined {
+                let filter = if settings.history_visibility == HistoryVisibility::Joined {
                     RoomMemberships::JOIN
                 } else {
                     RoomMemberships::ACTIVE
@@ -1108,15 +1200,56 @@ impl BaseClient {
 
                 let members = self.store.get_user_ids(room_id, filter).await?;
 
-                let settings = settings.ok_or(Error::EncryptionNotEnabled)?;
-                let settings = EncryptionSettings::new(settings, history_visibility, false);
-
                 Ok(o.share_room_key(room_id, members.iter().map(Deref::deref), settings).await?)
             }
             None => panic!("Olm machine wasn't started"),
         }
     }
 
+    /// Get the to-device requests that will share the history of a room with
+    /// a user that was invited to it.
+    ///
+    /// The devices of the user are chosen like for [`Self::share_room_key()`].
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn share_room_key_history(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<Vec<Arc<ToDeviceRequest>>> {
+        match self.olm_machine().await.as_ref() {
+            Some(o) => {
+                let settings = self.room_encryption_settings(o, room_id).await?;
+
+                Ok(o.share_room_key_history(room_id, user_id, settings).await?)
+            }
+            None => panic!("Olm machine wasn't started"),
+        }
+    }
+
+    /// Get the settings used to share the room keys of the given room.
+    #[cfg(feature = "e2e-encryption")]
+    async fn room_encryption_settings(
+        &self,
+        olm: &OlmMachine,
+        room_id: &RoomId,
+    ) -> Result<EncryptionSettings> {
+        let (history_visibility, settings) = self
+            .get_room(room_id)
+            .map(|r| (r.history_visibility(), r.encryption_settings()))
+            .unwrap_or((HistoryVisibility::Joined, None));
+
+        let settings = settings.ok_or(Error::EncryptionNotEnabled)?;
+        let mut settings = EncryptionSettings::new(settings, history_visibility, false);
+
+        // The room can override the sharing strategy set for the whole client.
+        settings.sharing_strategy = match olm.store().get_room_settings(room_id).await? {
+            Some(RoomSettings { sharing_strategy: Some(strategy), .. }) => strategy,
+            _ => olm.store().get_sharing_strategy().await?,
+        };
+
+        Ok(settings)
+    }
+
     /// Get the room with the given room id.
     ///
     /// # Arguments
@@ -1296,8 +1429,8 @@ impl Default for BaseClient {
 #[cfg(test)]
 mod tests {
     use matrix_sdk_test::{
-        async_test, response_from_file, InvitedRoomBuilder, JoinedRoomBuilder, LeftRoomBuilder,
-        StrippedStateTestEvent, SyncResponseBuilder, TimelineTestEvent,
+        async_test, response_from_file, InvitedRoomBuilder, JoinedRoomBuilder, KnockedRoomBuilder,
+        LeftRoomBuilder, StrippedStateTestEvent, SyncResponseBuilder, TimelineTestEvent,
     };
     use ruma::{
         api::{client as api, IncomingResponse},
@@ -1306,7 +1439,10 @@ mod tests {
     use serde_json::json;
 
     use super::BaseClient;
-    use crate::{store::StateStoreExt, DisplayName, Room, RoomState, SessionMeta, StateChanges};
+    use crate::{
+        store::StateStoreExt, DisplayName, Room, RoomState, RoomStateFilter, SessionMeta,
+        StateChanges,
+    };
 
     #[async_test]
     async fn invite_after_leaving() {
@@ -1354,6 +1490,45 @@ mod tests {
         assert_eq!(client.get_room(room_id).unwrap().state(), RoomState::Invited);
     }
 
+    #[async_test]
+    async fn knocked_room() {
+        let user_id = user_id!("@alice:example.org");
+        let room_id = room_id!("!test:example.org");
+
+        let client = logged_in_client(user_id).await;
+
+        let mut ev_builder = SyncResponseBuilder::new();
+
+        let response = ev_builder
+            .add_knocked_room(
+                KnockedRoomBuilder::new(room_id)
+                    .add_state_event(StrippedStateTestEvent::RoomName)
+                    .add_state_event(StrippedStateTestEvent::Custom(json!({
+                        "content": {
+                            "displayname": "Alice",
+                            "membership": "knock",
+                        },
+                        "sender": user_id,
+                        "state_key": user_id,
+                        "type": "m.room.member",
+                    }))),
+            )
+            .build_sync_response();
+        let sync_response = client.receive_sync_response(response).await.unwrap();
+        assert!(sync_response.rooms.knock.contains_key(room_id));
+
+        let room = client.get_room(room_id).unwrap();
+        assert_eq!(room.state(), RoomState::Knocked);
+        assert_eq!(room.name().as_deref(), Some("room name"));
+        assert_eq!(client.get_rooms_filtered(RoomStateFilter::KNOCKED).len(), 1);
+        assert!(client.get_rooms_filtered(RoomStateFilter::JOINED).is_empty());
+
+        // Our knock was accepted, and we joined the room.
+        let room = client.room_joined(room_id).await.unwrap();
+        assert_eq!(room.state(), RoomState::Joined);
+        assert!(client.get_rooms_filtered(RoomStateFilter::KNOCKED).is_empty());
+    }
+
     #[async_test]
     async fn invite_displayname_integration_test() {
         let user_id = user_id!("@alice:example.org");
@@ -18,7 +18,10 @@ use std::{collections::BTreeMap, fmt};
 
 pub use matrix_sdk_common::debug::*;
 use ruma::{
-    api::client::{push::get_notifications::v3::Notification, sync::sync_events::v3::InvitedRoom},
+    api::client::{
+        push::get_notifications::v3::Notification,
+        sync::sync_events::v3::{InvitedRoom, KnockedRoom},
+    },
     serde::Raw,
     OwnedRoomId,
 };
@@ -90,6 +93,20 @@ impl<'a> fmt::Debug for DebugInvitedRoom<'a> {
     }
 }
 
+/// A wrapper around a knocked room as found in `/sync` responses that
+/// implements `Debug` in a way that only prints the event ID and event type for
+/// the raw events contained in `knock_state`.
+pub struct DebugKnockedRoom<'a>(pub &'a KnockedRoom);
+
+#[cfg(not(tarpaulin_include))]
+impl<'a> fmt::Debug for DebugKnockedRoom<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KnockedRoom")
+            .field("knock_state", &DebugListOfRawEvents(&self.0.knock_state.events))
+            .finish()
+    }
+}
+
 pub(crate) struct DebugListOfRawEvents<'a, T>(pub &'a [Raw<T>]);
 
 #[cfg(not(tarpaulin_include))]
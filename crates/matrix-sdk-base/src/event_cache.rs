@@ -0,0 +1,676 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Data types for the persisted event cache.
+//!
+//! The events of a room are stored as a doubly-linked list of [`Chunk`]s. A
+//! chunk either contains a batch of events in topological order, or a [`Gap`]
+//! marking a hole in the known history of the room, along with the token that
+//! allows to fill it via back-pagination.
+//!
+//! [`RoomEvents`] is the in-memory representation of such a list. Every
+//! mutation of it is recorded as a [`ChunkUpdate`], which can be handed over
+//! to [`StateStore::handle_event_cache_updates`] to persist it.
+//!
+//! [`StateStore::handle_event_cache_updates`]: crate::store::StateStore::handle_event_cache_updates
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    mem,
+};
+
+use ruma::{EventId, OwnedEventId};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::deserialized_responses::SyncTimelineEvent;
+
+/// The default maximum number of events stored in a single chunk.
+pub const DEFAULT_CHUNK_CAPACITY: usize = 128;
+
+/// The unique identifier of a [`Chunk`] within the event cache of a room.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ChunkIdentifier(u64);
+
+impl ChunkIdentifier {
+    /// Create a new `ChunkIdentifier` from its raw value.
+    pub fn new(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Get the raw value of this identifier.
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A hole in the known history of a room.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gap {
+    /// The token to use in a backwards `/messages` request to fill this gap.
+    pub prev_token: String,
+}
+
+/// The content of a [`Chunk`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkContent {
+    /// A batch of events, in topological order.
+    Events(Vec<SyncTimelineEvent>),
+
+    /// A gap in the history of the room.
+    Gap(Gap),
+}
+
+/// A chunk of the event cache of a room.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Chunk {
+    /// The identifier of this chunk.
+    pub identifier: ChunkIdentifier,
+
+    /// The identifier of the chunk that precedes this one, if any.
+    pub previous: Option<ChunkIdentifier>,
+
+    /// The identifier of the chunk that follows this one, if any.
+    pub next: Option<ChunkIdentifier>,
+
+    /// The content of this chunk.
+    pub content: ChunkContent,
+}
+
+impl Chunk {
+    /// Whether this chunk is a gap.
+    pub fn is_gap(&self) -> bool {
+        matches!(self.content, ChunkContent::Gap(_))
+    }
+
+    /// Get the gap of this chunk, if it is one.
+    pub fn gap(&self) -> Option<&Gap> {
+        match &self.content {
+            ChunkContent::Gap(gap) => Some(gap),
+            ChunkContent::Events(_) => None,
+        }
+    }
+
+    /// Get the events of this chunk.
+    ///
+    /// Returns an empty slice if this chunk is a gap.
+    pub fn events(&self) -> &[SyncTimelineEvent] {
+        match &self.content {
+            ChunkContent::Events(events) => events,
+            ChunkContent::Gap(_) => &[],
+        }
+    }
+}
+
+/// A change to the event cache of a room, that must be persisted.
+#[derive(Clone, Debug)]
+pub enum ChunkUpdate {
+    /// A chunk was added or modified.
+    Upsert(Chunk),
+
+    /// The chunk with the given identifier was removed.
+    Remove(ChunkIdentifier),
+}
+
+/// The in-memory event cache of a room.
+///
+/// Chunks are kept from the oldest to the newest one, and every change is
+/// recorded so it can be persisted with [`RoomEvents::take_updates`].
+#[derive(Debug)]
+pub struct RoomEvents {
+    chunks: Vec<Chunk>,
+    /// The chunk containing each event, to find events without going through
+    /// all the chunks.
+    event_index: HashMap<OwnedEventId, ChunkIdentifier>,
+    next_identifier: u64,
+    chunk_capacity: usize,
+    updates: Vec<ChunkUpdate>,
+}
+
+impl Default for RoomEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RoomEvents {
+    /// Create a new empty `RoomEvents`.
+    pub fn new() -> Self {
+        Self {
+            chunks: Vec::new(),
+            event_index: HashMap::new(),
+            next_identifier: 0,
+            chunk_capacity: DEFAULT_CHUNK_CAPACITY,
+            updates: Vec::new(),
+        }
+    }
+
+    /// Rebuild a `RoomEvents` from chunks loaded from the store, in any order.
+    ///
+    /// The chunks are ordered by following their links. If the links are
+    /// inconsistent, the chunks that can't be reached from the first one are
+    /// dropped.
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
+        let mut this = Self::new();
+        if chunks.is_empty() {
+            return this;
+        }
+
+        this.next_identifier = chunks.iter().map(|c| c.identifier.0).max().map_or(0, |max| max + 1);
+
+        let mut by_id: BTreeMap<_, _> = chunks.into_iter().map(|c| (c.identifier, c)).collect();
+        let first = by_id.values().find(|c| c.previous.is_none()).map(|c| c.identifier);
+
+        let mut current = first;
+        while let Some(id) = current {
+            let Some(chunk) = by_id.remove(&id) else {
+                warn!(?id, "Event cache chunk is missing, dropping the rest of the chunks");
+                break;
+            };
+
+            current = chunk.next;
+            this.index_events(chunk.identifier, chunk.events());
+            this.chunks.push(chunk);
+        }
+
+        if !by_id.is_empty() {
+            warn!(num_chunks = by_id.len(), "Dropping unreachable event cache chunks");
+            this.updates.extend(by_id.into_keys().map(ChunkUpdate::Remove));
+        }
+
+        if let Some(last) = this.chunks.last_mut() {
+            if last.next.take().is_some() {
+                this.updates.push(ChunkUpdate::Upsert(last.clone()));
+            }
+        }
+
+        this
+    }
+
+    /// Whether there are no chunks.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// All the chunks, from the oldest to the newest one.
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// Get the chunk with the given identifier.
+    pub fn chunk(&self, identifier: ChunkIdentifier) -> Option<&Chunk> {
+        self.chunks.iter().find(|c| c.identifier == identifier)
+    }
+
+    /// Get the chunk that precedes the chunk with the given identifier.
+    pub fn chunk_before(&self, identifier: ChunkIdentifier) -> Option<&Chunk> {
+        let position = self.position(identifier)?;
+        position.checked_sub(1).map(|p| &self.chunks[p])
+    }
+
+    /// Get the newest chunk.
+    pub fn last_chunk(&self) -> Option<&Chunk> {
+        self.chunks.last()
+    }
+
+    /// Iterate over all the events, from the oldest to the newest one.
+    pub fn events(&self) -> impl Iterator<Item = &SyncTimelineEvent> {
+        self.chunks.iter().flat_map(|c| c.events())
+    }
+
+    /// Whether an event with the given ID is in the cache.
+    pub fn contains_event(&self, event_id: &EventId) -> bool {
+        self.event_index.contains_key(event_id)
+    }
+
+    /// Find the gap that can be filled with the given pagination token.
+    pub fn find_gap(&self, prev_token: &str) -> Option<ChunkIdentifier> {
+        self.chunks
+            .iter()
+            .find(|c| c.gap().is_some_and(|gap| gap.prev_token == prev_token))
+            .map(|c| c.identifier)
+    }
+
+    /// Append events at the end of the cache.
+    ///
+    /// Events that were already in the cache are moved to the end. Every chunk
+    /// that is modified is only recorded once, with its final content.
+    pub fn push_events(&mut self, events: impl IntoIterator<Item = SyncTimelineEvent>) {
+        let mut events: Vec<_> = events.into_iter().collect();
+
+        // Only keep the last occurrence of an event, as if the events were
+        // pushed one by one.
+        let mut event_ids = HashSet::new();
+        events.reverse();
+        events.retain(|ev| ev.event_id().map_or(true, |event_id| event_ids.insert(event_id)));
+        events.reverse();
+
+        let mut touched = BTreeSet::new();
+        for event_id in &event_ids {
+            touched.extend(self.detach_event(event_id));
+        }
+
+        // Drop the chunks that became empty.
+        let (removed, chunks): (Vec<_>, Vec<_>) = mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|c| matches!(&c.content, ChunkContent::Events(evs) if evs.is_empty()));
+        self.chunks = chunks;
+        self.updates.extend(removed.into_iter().map(|c| ChunkUpdate::Remove(c.identifier)));
+        touched.extend(self.relink());
+
+        let mut events = events.into_iter().peekable();
+        while events.peek().is_some() {
+            let free_slots = match self.chunks.last() {
+                Some(Chunk { content: ChunkContent::Events(last_events), .. }) => {
+                    self.chunk_capacity.saturating_sub(last_events.len())
+                }
+                _ => 0,
+            };
+
+            if free_slots == 0 {
+                let identifier = self.next_identifier();
+                let previous = self.chunks.last_mut().map(|last| {
+                    last.next = Some(identifier);
+                    touched.insert(last.identifier);
+                    last.identifier
+                });
+
+                self.chunks.push(Chunk {
+                    identifier,
+                    previous,
+                    next: None,
+                    content: ChunkContent::Events(Vec::new()),
+                });
+                touched.insert(identifier);
+                continue;
+            }
+
+            let last = self.chunks.last_mut().expect("the last chunk was checked above");
+            if let ChunkContent::Events(chunk_events) = &mut last.content {
+                for event in events.by_ref().take(free_slots) {
+                    if let Some(event_id) = event.event_id() {
+                        self.event_index.insert(event_id, last.identifier);
+                    }
+                    chunk_events.push(event);
+                }
+            }
+            touched.insert(last.identifier);
+        }
+
+        self.record_upserts(&touched);
+    }
+
+    /// Append a gap at the end of the cache.
+    ///
+    /// If the newest chunk is already a gap, its token is replaced.
+    pub fn push_gap(&mut self, gap: Gap) {
+        match self.chunks.last_mut() {
+            Some(last @ Chunk { content: ChunkContent::Gap(_), .. }) => {
+                last.content = ChunkContent::Gap(gap);
+                self.updates.push(ChunkUpdate::Upsert(last.clone()));
+            }
+            _ => self.push_chunk(ChunkContent::Gap(gap)),
+        }
+    }
+
+    /// Replace the gap with the given identifier with events received from a
+    /// back-pagination, and optionally a new gap before them.
+    ///
+    /// `events` must be in topological order. Events that are already in the
+    /// cache are skipped, and since that means the hole in the history has
+    /// been filled, no new gap is inserted in this case.
+    ///
+    /// Returns the identifier of the oldest chunk containing the inserted
+    /// events, or `None` if no event was inserted or the gap wasn't found.
+    pub fn replace_gap(
+        &mut self,
+        identifier: ChunkIdentifier,
+        events: Vec<SyncTimelineEvent>,
+        mut gap: Option<Gap>,
+    ) -> Option<ChunkIdentifier> {
+        let position = self.position(identifier)?;
+        if !self.chunks[position].is_gap() {
+            warn!(?identifier, "Trying to replace a chunk that isn't a gap");
+            return None;
+        }
+
+        let num_events = events.len();
+        let events: Vec<_> = events
+            .into_iter()
+            .filter(|ev| ev.event_id().map_or(true, |event_id| !self.contains_event(&event_id)))
+            .collect();
+
+        if events.len() != num_events {
+            // We reached events we already know about, the gap is filled.
+            gap = None;
+        }
+
+        let mut new_contents = Vec::new();
+        if let Some(gap) = gap {
+            new_contents.push(ChunkContent::Gap(gap));
+        }
+        new_contents.extend(
+            events.chunks(self.chunk_capacity).map(|batch| ChunkContent::Events(batch.to_vec())),
+        );
+
+        let removed = self.chunks.remove(position);
+        self.updates.push(ChunkUpdate::Remove(removed.identifier));
+
+        let mut first_events_chunk = None;
+        let num_new_chunks = new_contents.len();
+        for (offset, content) in new_contents.into_iter().enumerate() {
+            let is_events = matches!(content, ChunkContent::Events(_));
+            let identifier = self.next_identifier();
+            if let ChunkContent::Events(events) = &content {
+                self.index_events(identifier, events);
+            }
+            self.chunks.insert(
+                position + offset,
+                Chunk { identifier, previous: None, next: None, content },
+            );
+
+            if is_events && first_events_chunk.is_none() {
+                first_events_chunk = Some(identifier);
+            }
+        }
+
+        let mut touched = self.relink();
+        touched
+            .extend(self.chunks[position..position + num_new_chunks].iter().map(|c| c.identifier));
+        self.record_upserts(&touched);
+
+        first_events_chunk
+    }
+
+    /// Remove all the chunks.
+    pub fn clear(&mut self) {
+        self.event_index.clear();
+        let chunks = mem::take(&mut self.chunks);
+        self.updates.extend(chunks.into_iter().map(|c| ChunkUpdate::Remove(c.identifier)));
+    }
+
+    /// Take the changes that were made since the last call to this method.
+    ///
+    /// They must be persisted in the same order.
+    pub fn take_updates(&mut self) -> Vec<ChunkUpdate> {
+        mem::take(&mut self.updates)
+    }
+
+    fn position(&self, identifier: ChunkIdentifier) -> Option<usize> {
+        self.chunks.iter().position(|c| c.identifier == identifier)
+    }
+
+    fn next_identifier(&mut self) -> ChunkIdentifier {
+        let identifier = ChunkIdentifier(self.next_identifier);
+        self.next_identifier += 1;
+        identifier
+    }
+
+    fn push_chunk(&mut self, content: ChunkContent) {
+        let identifier = self.next_identifier();
+        let previous = self.chunks.last_mut().map(|last| {
+            last.next = Some(identifier);
+            self.updates.push(ChunkUpdate::Upsert(last.clone()));
+            last.identifier
+        });
+
+        let chunk = Chunk { identifier, previous, next: None, content };
+        self.updates.push(ChunkUpdate::Upsert(chunk.clone()));
+        self.chunks.push(chunk);
+    }
+
+    /// Record that the given events are in the chunk with the given
+    /// identifier.
+    fn index_events(&mut self, identifier: ChunkIdentifier, events: &[SyncTimelineEvent]) {
+        for event_id in events.iter().filter_map(|ev| ev.event_id()) {
+            self.event_index.insert(event_id, identifier);
+        }
+    }
+
+    /// Remove the event with the given ID from its chunk, without recording
+    /// it.
+    ///
+    /// Returns the identifier of the chunk that contained the event, which may
+    /// be empty now.
+    fn detach_event(&mut self, event_id: &EventId) -> Option<ChunkIdentifier> {
+        let identifier = self.event_index.remove(event_id)?;
+
+        let found = self.position(identifier).and_then(|chunk_pos| {
+            self.chunks[chunk_pos]
+                .events()
+                .iter()
+                .position(|ev| ev.event_id().as_deref() == Some(event_id))
+                .map(|event_pos| (chunk_pos, event_pos))
+        });
+
+        let Some((chunk_pos, event_pos)) = found else {
+            warn!(?event_id, "The event cache index is out of sync with its chunks");
+            return None;
+        };
+
+        if let ChunkContent::Events(events) = &mut self.chunks[chunk_pos].content {
+            events.remove(event_pos);
+        }
+
+        Some(identifier)
+    }
+
+    /// Fix the links between chunks.
+    ///
+    /// Returns the identifiers of the chunks whose links changed, which must
+    /// be recorded.
+    fn relink(&mut self) -> BTreeSet<ChunkIdentifier> {
+        let identifiers: Vec<_> = self.chunks.iter().map(|c| c.identifier).collect();
+        let mut changed = BTreeSet::new();
+
+        for (position, chunk) in self.chunks.iter_mut().enumerate() {
+            let previous = position.checked_sub(1).map(|p| identifiers[p]);
+            let next = identifiers.get(position + 1).copied();
+
+            if chunk.previous != previous || chunk.next != next {
+                chunk.previous = previous;
+                chunk.next = next;
+                changed.insert(chunk.identifier);
+            }
+        }
+
+        changed
+    }
+
+    /// Record the current content of the chunks with the given identifiers,
+    /// in the order of the chunks.
+    fn record_upserts(&mut self, identifiers: &BTreeSet<ChunkIdentifier>) {
+        if identifiers.is_empty() {
+            return;
+        }
+
+        self.updates.extend(
+            self.chunks
+                .iter()
+                .filter(|c| identifiers.contains(&c.identifier))
+                .map(|c| ChunkUpdate::Upsert(c.clone())),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use assert_matches::assert_matches;
+    use ruma::{event_id, serde::Raw};
+    use serde_json::json;
+
+    use super::{ChunkContent, ChunkUpdate, Gap, RoomEvents};
+    use crate::deserialized_responses::SyncTimelineEvent;
+
+    fn event(id: &str) -> SyncTimelineEvent {
+        SyncTimelineEvent::new(
+            Raw::new(&json!({
+                "content": { "body": "hi", "msgtype": "m.text" },
+                "event_id": id,
+                "origin_server_ts": 0,
+                "sender": "@alice:localhost",
+                "type": "m.room.message",
+            }))
+            .unwrap()
+            .cast(),
+        )
+    }
+
+    #[test]
+    fn push_events_and_gaps() {
+        let mut room_events = RoomEvents::new();
+        room_events.push_gap(Gap { prev_token: "t1".to_owned() });
+        room_events.push_events([event("$a"), event("$b")]);
+        room_events.push_gap(Gap { prev_token: "t2".to_owned() });
+        room_events.push_gap(Gap { prev_token: "t3".to_owned() });
+        room_events.push_events([event("$c")]);
+
+        let chunks = room_events.chunks();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0].gap().unwrap().prev_token, "t1");
+        assert_eq!(chunks[1].events().len(), 2);
+        assert_eq!(chunks[2].gap().unwrap().prev_token, "t3");
+        assert_eq!(chunks[3].events().len(), 1);
+
+        assert_eq!(room_events.find_gap("t3"), Some(chunks[2].identifier));
+        assert_eq!(room_events.find_gap("t2"), None);
+        assert!(room_events.contains_event(event_id!("$b")));
+    }
+
+    #[test]
+    fn duplicated_events_are_moved() {
+        let mut room_events = RoomEvents::new();
+        room_events.push_events([event("$a")]);
+        room_events.push_gap(Gap { prev_token: "t1".to_owned() });
+        room_events.push_events([event("$a"), event("$b")]);
+
+        // The first chunk became empty and was removed.
+        let chunks = room_events.chunks();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].is_gap());
+        assert!(chunks[0].previous.is_none());
+        assert_eq!(chunks[1].events().len(), 2);
+
+        // The event is found again after being moved.
+        room_events.push_gap(Gap { prev_token: "t2".to_owned() });
+        room_events.push_events([event("$a")]);
+        assert!(room_events.contains_event(event_id!("$a")));
+
+        let events: Vec<_> =
+            room_events.events().map(|ev| ev.event_id().unwrap().to_string()).collect();
+        assert_eq!(events, ["$b", "$a"]);
+
+        room_events.clear();
+        assert!(!room_events.contains_event(event_id!("$a")));
+    }
+
+    #[test]
+    fn push_events_records_each_chunk_once() {
+        let mut room_events = RoomEvents::new();
+        room_events.push_events([event("$a"), event("$b")]);
+        room_events.push_gap(Gap { prev_token: "t1".to_owned() });
+        room_events.take_updates();
+
+        // `$a` moves to the end, and the first chunk is modified only once.
+        room_events.push_events([event("$c"), event("$a"), event("$d"), event("$c")]);
+
+        let updates = room_events.take_updates();
+        let mut upserted: Vec<_> = updates
+            .iter()
+            .map(|update| assert_matches!(update, ChunkUpdate::Upsert(chunk) => chunk.identifier))
+            .collect();
+        let num_updates = upserted.len();
+        upserted.dedup();
+        assert_eq!(upserted.len(), num_updates);
+        assert_eq!(num_updates, 3);
+
+        let events: Vec<_> =
+            room_events.events().map(|ev| ev.event_id().unwrap().to_string()).collect();
+        assert_eq!(events, ["$b", "$a", "$d", "$c"]);
+    }
+
+    #[test]
+    fn replace_gap() {
+        let mut room_events = RoomEvents::new();
+        room_events.push_events([event("$a")]);
+        room_events.push_gap(Gap { prev_token: "t1".to_owned() });
+        room_events.push_events([event("$d")]);
+
+        let gap_id = room_events.find_gap("t1").unwrap();
+        let last_id = room_events.last_chunk().unwrap().identifier;
+
+        let new_id = room_events
+            .replace_gap(
+                gap_id,
+                vec![event("$b"), event("$c")],
+                Some(Gap { prev_token: "t0".to_owned() }),
+            )
+            .unwrap();
+
+        let chunks = room_events.chunks();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[1].gap().unwrap().prev_token, "t0");
+        assert_eq!(chunks[2].identifier, new_id);
+        assert_eq!(room_events.chunk_before(last_id).unwrap().identifier, new_id);
+
+        // Paginating into known events fills the gap.
+        let gap_id = room_events.find_gap("t0").unwrap();
+        let new_id = room_events
+            .replace_gap(
+                gap_id,
+                vec![event("$a"), event("$a2")],
+                Some(Gap { prev_token: "t".to_owned() }),
+            )
+            .unwrap();
+
+        let events: Vec<_> =
+            room_events.events().map(|ev| ev.event_id().unwrap().to_string()).collect();
+        assert_eq!(events, ["$a", "$a2", "$b", "$c", "$d"]);
+        assert!(room_events.chunks().iter().all(|c| !c.is_gap()));
+        assert_eq!(room_events.chunk(new_id).unwrap().events().len(), 1);
+    }
+
+    #[test]
+    fn reload_from_updates() {
+        let mut room_events = RoomEvents::new();
+        room_events.push_gap(Gap { prev_token: "t1".to_owned() });
+        room_events.push_events([event("$a"), event("$b")]);
+        let gap_id = room_events.find_gap("t1").unwrap();
+        room_events.replace_gap(gap_id, vec![event("$z")], None);
+
+        // Replay the updates like a store would.
+        let mut stored = BTreeMap::new();
+        for update in room_events.take_updates() {
+            match update {
+                ChunkUpdate::Upsert(chunk) => {
+                    stored.insert(chunk.identifier, chunk);
+                }
+                ChunkUpdate::Remove(id) => {
+                    stored.remove(&id);
+                }
+            }
+        }
+
+        let reloaded = RoomEvents::from_chunks(stored.into_values().collect());
+        let events: Vec<_> =
+            reloaded.events().map(|ev| ev.event_id().unwrap().to_string()).collect();
+        assert_eq!(events, ["$z", "$a", "$b"]);
+        assert!(reloaded.contains_event(event_id!("$z")));
+        assert_matches!(reloaded.chunks()[0].content, ChunkContent::Events(_));
+    }
+}
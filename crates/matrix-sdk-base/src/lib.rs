@@ -27,6 +27,7 @@ mod client;
 pub mod debug;
 pub mod deserialized_responses;
 mod error;
+pub mod event_cache;
 pub mod latest_event;
 pub mod media;
 mod rooms;
@@ -39,7 +39,7 @@ use ruma::{
             redaction::OriginalSyncRoomRedactionEvent,
             tombstone::RoomTombstoneEventContent,
         },
-        tag::Tags,
+        tag::{TagName, Tags},
         AnyRoomAccountDataEvent, AnyStrippedStateEvent, AnySyncStateEvent,
         RoomAccountDataEventType,
     },
@@ -108,17 +108,20 @@ pub enum RoomState {
     Left,
     /// The room is in a invited state.
     Invited,
+    /// The room is in a knocked state, i.e. we asked to join it and are
+    /// waiting for an answer.
+    Knocked,
 }
 
 impl From<&MembershipState> for RoomState {
     fn from(membership_state: &MembershipState) -> Self {
-        // We consider Ban, Knock and Leave to be Left, because they all mean we are not
+        // We consider Ban and Leave to be Left, because they both mean we are not
         // in the room.
         match membership_state {
             MembershipState::Ban => Self::Left,
             MembershipState::Invite => Self::Invited,
             MembershipState::Join => Self::Joined,
-            MembershipState::Knock => Self::Left,
+            MembershipState::Knock => Self::Knocked,
             MembershipState::Leave => Self::Left,
             _ => panic!("Unexpected MembershipState: {}", membership_state),
         }
@@ -267,7 +270,7 @@ impl Room {
     #[instrument(skip_all, fields(room_id = ?self.room_id))]
     pub async fn is_direct(&self) -> StoreResult<bool> {
         match self.state() {
-            RoomState::Joined | RoomState::Left => {
+            RoomState::Joined | RoomState::Left | RoomState::Knocked => {
                 Ok(!self.inner.read().unwrap().base_info.dm_targets.is_empty())
             }
             RoomState::Invited => {
@@ -630,6 +633,22 @@ impl Room {
         })
     }
 
+    /// Whether this room has been tagged as a favourite.
+    ///
+    /// This is computed from the `m.tag` account data of the room, and doesn't
+    /// need to access the store.
+    pub fn is_favourite(&self) -> bool {
+        self.inner.read().unwrap().notable_tags.is_favourite
+    }
+
+    /// Whether this room has been tagged as low priority.
+    ///
+    /// This is computed from the `m.tag` account data of the room, and doesn't
+    /// need to access the store.
+    pub fn is_low_priority(&self) -> bool {
+        self.inner.read().unwrap().notable_tags.is_low_priority
+    }
+
     /// Get the `Tags` for this room.
     pub async fn tags(&self) -> StoreResult<Option<Tags>> {
         if let Some(AnyRoomAccountDataEvent::Tag(event)) = self
@@ -681,6 +700,9 @@ pub struct RoomInfo {
     room_state: RoomState,
     /// The unread notifications counts.
     notification_counts: UnreadNotificationsCount,
+    /// The notable tags of this room, from its `m.tag` account data.
+    #[serde(default)]
+    notable_tags: RoomNotableTags,
     /// The summary of this room.
     summary: RoomSummary,
     /// Flag remembering if the room members are synced.
@@ -743,6 +765,7 @@ impl RoomInfo {
             room_id: room_id.into(),
             room_state,
             notification_counts: Default::default(),
+            notable_tags: Default::default(),
             summary: Default::default(),
             members_synced: false,
             last_prev_batch: None,
@@ -769,6 +792,11 @@ impl RoomInfo {
         self.room_state = RoomState::Invited;
     }
 
+    /// Mark this Room as knocked.
+    pub fn mark_as_knocked(&mut self) {
+        self.room_state = RoomState::Knocked;
+    }
+
     /// Set the membership RoomState of this Room
     pub fn set_state(&mut self, room_state: RoomState) {
         self.room_state = room_state;
@@ -865,6 +893,14 @@ impl RoomInfo {
         }));
     }
 
+    /// Update the notable tags of this room from its `m.tag` account data.
+    pub(crate) fn update_notable_tags(&mut self, tags: &Tags) {
+        self.notable_tags = RoomNotableTags {
+            is_favourite: tags.contains_key(&TagName::Favorite),
+            is_low_priority: tags.contains_key(&TagName::LowPriority),
+        };
+    }
+
     /// Update the notifications count
     pub fn update_notification_count(&mut self, notification_counts: UnreadNotificationsCount) {
         self.notification_counts = notification_counts;
@@ -984,6 +1020,16 @@ impl RoomInfo {
     }
 }
 
+/// The notable tags of a room, i.e. the `m.tag`s that are relevant to present
+/// or filter a room list without hitting the store.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RoomNotableTags {
+    /// The room is tagged as `m.favourite`.
+    is_favourite: bool,
+    /// The room is tagged as `m.lowpriority`.
+    is_low_priority: bool,
+}
+
 bitflags! {
     /// Room state filter as a bitset.
     ///
@@ -997,6 +1043,8 @@ bitflags! {
         const INVITED  = 0b00000010;
         /// The room is in a left state.
         const LEFT     = 0b00000100;
+        /// The room is in a knocked state.
+        const KNOCKED  = 0b00001000;
     }
 }
 
@@ -1011,6 +1059,7 @@ impl RoomStateFilter {
             RoomState::Joined => Self::JOINED,
             RoomState::Left => Self::LEFT,
             RoomState::Invited => Self::INVITED,
+            RoomState::Knocked => Self::KNOCKED,
         };
 
         self.contains(bit_state)
@@ -1029,6 +1078,9 @@ impl RoomStateFilter {
         if self.contains(Self::INVITED) {
             states.push(RoomState::Invited);
         }
+        if self.contains(Self::KNOCKED) {
+            states.push(RoomState::Knocked);
+        }
 
         states
     }
@@ -1077,6 +1129,7 @@ mod test {
                 highlight_count: 1,
                 notification_count: 2,
             },
+            notable_tags: RoomNotableTags { is_favourite: true, is_low_priority: false },
             summary: RoomSummary {
                 heroes: vec!["Somebody".to_owned()],
                 joined_member_count: 5,
@@ -1099,6 +1152,10 @@ mod test {
                 "highlight_count": 1,
                 "notification_count": 2,
             },
+            "notable_tags": {
+                "is_favourite": true,
+                "is_low_priority": false,
+            },
             "summary": {
                 "heroes": ["Somebody"],
                 "joined_member_count": 5,
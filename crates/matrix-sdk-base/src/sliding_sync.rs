@@ -18,10 +18,11 @@ use std::ops::Deref;
 use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
 use ruma::{
     api::client::sync::sync_events::{
-        v3::{self, InvitedRoom, RoomSummary},
+        v3::{self, InvitedRoom, KnockedRoom, RoomSummary},
         v4::{self, AccountData},
     },
-    events::AnySyncStateEvent,
+    events::{room::member::MembershipState, AnyStrippedStateEvent, AnySyncStateEvent},
+    serde::Raw,
     RoomId,
 };
 use tracing::{debug, info, instrument, warn};
@@ -110,7 +111,7 @@ impl BaseClient {
         let mut new_rooms = Rooms::default();
 
         for (room_id, room_data) in rooms {
-            let (room_to_store, joined_room, invited_room) = self
+            let (room_to_store, joined_room, invited_room, knocked_room) = self
                 .process_sliding_sync_room(
                     room_id,
                     room_data,
@@ -127,6 +128,9 @@ impl BaseClient {
             if let Some(invited_room) = invited_room {
                 new_rooms.invite.insert(room_id.clone(), invited_room);
             }
+            if let Some(knocked_room) = knocked_room {
+                new_rooms.knock.insert(room_id.clone(), knocked_room);
+            }
         }
 
         // Process receipts now we have rooms
@@ -196,18 +200,19 @@ impl BaseClient {
         changes: &mut StateChanges,
         ambiguity_cache: &mut AmbiguityCache,
         account_data: &AccountData,
-    ) -> Result<(RoomInfo, Option<JoinedRoom>, Option<InvitedRoom>)> {
+    ) -> Result<(RoomInfo, Option<JoinedRoom>, Option<InvitedRoom>, Option<KnockedRoom>)> {
         let required_state = Self::deserialize_events(&room_data.required_state);
 
         // Find or create the room in the store
         #[allow(unused_mut)] // Required for some feature flag combinations
-        let (mut room, mut room_info, invited_room) = self.process_sliding_sync_room_membership(
-            room_data,
-            &required_state,
-            store,
-            room_id,
-            changes,
-        );
+        let (mut room, mut room_info, invited_room, knocked_room) = self
+            .process_sliding_sync_room_membership(
+                room_data,
+                &required_state,
+                store,
+                room_id,
+                changes,
+            );
 
         room_info.mark_state_partially_synced();
 
@@ -225,7 +230,7 @@ impl BaseClient {
         };
 
         let room_account_data = if let Some(events) = account_data.rooms.get(room_id) {
-            self.handle_room_account_data(room_id, events, changes).await;
+            self.handle_room_account_data(room_id, events, &mut room_info, changes).await;
             Some(events.to_vec())
         } else {
             None
@@ -274,9 +279,12 @@ impl BaseClient {
         let notification_count = room_data.unread_notifications.clone().into();
         room_info.update_notification_count(notification_count);
 
-        // If this room was not an invite, we treat it as joined
+        // If this room was not an invite or a knock, we treat it as joined
         // FIXME: it could be left, or possibly some other state
-        let joined_room = if invited_room.is_none() {
+        let joined_room = if invited_room.is_none()
+            && knocked_room.is_none()
+            && room_info.state() != RoomState::Knocked
+        {
             Some(JoinedRoom::new(
                 timeline,
                 room_data.required_state.clone(),
@@ -288,7 +296,7 @@ impl BaseClient {
             None
         };
 
-        Ok((room_info, joined_room, invited_room))
+        Ok((room_info, joined_room, invited_room, knocked_room))
     }
 
     /// Look through the sliding sync data for this room, find/create it in the
@@ -296,6 +304,9 @@ impl BaseClient {
     /// If any invite_state exists, we take it to mean that we are invited to
     /// this room, unless that state contains membership events that specify
     /// otherwise. https://github.com/matrix-org/matrix-spec-proposals/blob/kegan/sync-v3/proposals/3575-sync.md#room-list-parameters
+    /// The sliding sync proxy also sends the stripped state of rooms we knocked
+    /// on in invite_state, so a knock membership of our own user marks the
+    /// room as knocked instead.
     fn process_sliding_sync_room_membership(
         &self,
         room_data: &v4::SlidingSyncRoom,
@@ -303,8 +314,21 @@ impl BaseClient {
         store: &Store,
         room_id: &RoomId,
         changes: &mut StateChanges,
-    ) -> (Room, RoomInfo, Option<InvitedRoom>) {
+    ) -> (Room, RoomInfo, Option<InvitedRoom>, Option<KnockedRoom>) {
         if let Some(invite_state) = &room_data.invite_state {
+            if self.is_own_knock(invite_state) {
+                let room = store.get_or_create_room(room_id, RoomState::Knocked);
+                let mut room_info = room.clone_info();
+                room_info.mark_as_knocked();
+
+                self.handle_invited_state(invite_state.as_slice(), &mut room_info, changes);
+
+                let mut knocked_room = KnockedRoom::default();
+                knocked_room.knock_state.events = invite_state.clone();
+
+                return (room, room_info, None, Some(knocked_room));
+            }
+
             let room = store.get_or_create_room(room_id, RoomState::Invited);
             let mut room_info = room.clone_info();
 
@@ -327,6 +351,7 @@ impl BaseClient {
                 room,
                 room_info,
                 Some(v3::InvitedRoom::from(v3::InviteState::from(invite_state.clone()))),
+                None,
             )
         } else {
             let room = store.get_or_create_room(room_id, RoomState::Joined);
@@ -345,10 +370,27 @@ impl BaseClient {
             // relevant membership events.
             self.handle_own_room_membership(required_state, &mut room_info);
 
-            (room, room_info, None)
+            (room, room_info, None, None)
         }
     }
 
+    /// Whether the given stripped state contains a knock membership of the
+    /// current user.
+    fn is_own_knock(&self, stripped_state: &[Raw<AnyStrippedStateEvent>]) -> bool {
+        let Some(meta) = self.session_meta() else {
+            return false;
+        };
+
+        stripped_state.iter().any(|raw| {
+            matches!(
+                raw.deserialize(),
+                Ok(AnyStrippedStateEvent::RoomMember(member))
+                    if member.state_key == meta.user_id
+                        && member.content.membership == MembershipState::Knock
+            )
+        })
+    }
+
     /// Find any m.room.member events that refer to the current user, and update
     /// the state in room_info to reflect the "membership" property.
     pub(crate) fn handle_own_room_membership(
@@ -524,6 +566,57 @@ mod test {
         assert_eq!(client_room.name(), Some("little room".to_owned()));
     }
 
+    #[async_test]
+    async fn notable_tags_are_found_when_processing_sliding_sync_response() {
+        // Given a logged-in client
+        let client = logged_in_client().await;
+        let room_id = room_id!("!r:e.uk");
+
+        // When I send sliding sync response containing a room tagged as favourite
+        let mut response = response_with_room(room_id, v4::SlidingSyncRoom::new()).await;
+        response.extensions.account_data.rooms.insert(
+            room_id.to_owned(),
+            vec![Raw::new(&json!({
+                "type": "m.tag",
+                "content": {
+                    "tags": {
+                        "m.favourite": { "order": 0.5 },
+                    },
+                },
+            }))
+            .expect("Failed to create tag event")
+            .cast()],
+        );
+        client.process_sliding_sync(&response).await.expect("Failed to process sync");
+
+        // Then the room is a favourite, but not low priority
+        let client_room = client.get_room(room_id).expect("No room found");
+        assert!(client_room.is_favourite());
+        assert!(!client_room.is_low_priority());
+
+        // When the room is then tagged as low priority only
+        let mut response = response_with_room(room_id, v4::SlidingSyncRoom::new()).await;
+        response.extensions.account_data.rooms.insert(
+            room_id.to_owned(),
+            vec![Raw::new(&json!({
+                "type": "m.tag",
+                "content": {
+                    "tags": {
+                        "m.lowpriority": {},
+                    },
+                },
+            }))
+            .expect("Failed to create tag event")
+            .cast()],
+        );
+        client.process_sliding_sync(&response).await.expect("Failed to process sync");
+
+        // Then the notable tags are updated
+        let client_room = client.get_room(room_id).expect("No room found");
+        assert!(!client_room.is_favourite());
+        assert!(client_room.is_low_priority());
+    }
+
     #[async_test]
     async fn invited_room_name_is_found_when_processing_sliding_sync_response() {
         // Given a logged-in client
@@ -671,6 +764,30 @@ mod test {
         assert!(sync_resp.rooms.join.get(room_id).is_none());
     }
 
+    #[async_test]
+    async fn knocked_room_is_added_to_client_and_knock_list() {
+        // Given a logged-in client
+        let client = logged_in_client().await;
+        let room_id = room_id!("!r:e.uk");
+        let user_id = user_id!("@u:e.uk");
+
+        // When I send sliding sync response containing a room we knocked on
+        let mut room = v4::SlidingSyncRoom::new();
+        set_room_knocked(&mut room, user_id);
+        let response = response_with_room(room_id, room).await;
+        let sync_resp =
+            client.process_sliding_sync(&response).await.expect("Failed to process sync");
+
+        // Then the room is added to the client as knocked
+        let client_room = client.get_room(room_id).expect("No room found");
+        assert_eq!(client_room.state(), RoomState::Knocked);
+
+        // And it is added to the list of knocked rooms, not the invited or joined ones
+        assert!(!sync_resp.rooms.knock[room_id].knock_state.events.is_empty());
+        assert!(sync_resp.rooms.invite.get(room_id).is_none());
+        assert!(sync_resp.rooms.join.get(room_id).is_none());
+    }
+
     #[async_test]
     async fn avatar_is_found_in_invitation_room_when_processing_sliding_sync_response() {
         // Given a logged-in client
@@ -1191,6 +1308,24 @@ mod test {
         room.required_state.push(make_membership_event(user_id, MembershipState::Invite));
     }
 
+    fn set_room_knocked(room: &mut v4::SlidingSyncRoom, user_id: &UserId) {
+        // The stripped state of a room we knocked on contains our own knock
+        // membership.
+        let evt = Raw::new(&json!({
+            "type": "m.room.member",
+            "sender": user_id,
+            "state_key": user_id,
+            "content": {
+                "membership": "knock",
+            },
+        }))
+        .expect("Failed to make raw event")
+        .cast();
+
+        room.invite_state = Some(vec![evt]);
+        room.required_state.push(make_membership_event(user_id, MembershipState::Knock));
+    }
+
     fn set_room_joined(room: &mut v4::SlidingSyncRoom, user_id: &UserId) {
         room.required_state.push(make_membership_event(user_id, MembershipState::Join));
     }
@@ -16,13 +16,15 @@ use ruma::{
                 MembershipState, RoomMemberEventContent, StrippedRoomMemberEvent,
                 SyncRoomMemberEvent,
             },
+            message::RoomMessageEventContent,
             power_levels::RoomPowerLevelsEventContent,
             topic::RoomTopicEventContent,
             MediaSource,
         },
-        AnyEphemeralRoomEventContent, AnyGlobalAccountDataEvent, AnyRoomAccountDataEvent,
-        AnyStrippedStateEvent, AnySyncEphemeralRoomEvent, AnySyncStateEvent,
-        GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType, SyncStateEvent,
+        AnyEphemeralRoomEventContent, AnyGlobalAccountDataEvent, AnyMessageLikeEventContent,
+        AnyRoomAccountDataEvent, AnyStrippedStateEvent, AnySyncEphemeralRoomEvent,
+        AnySyncStateEvent, GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
+        SyncStateEvent,
     },
     mxc_uri, room_id,
     serde::Raw,
@@ -32,9 +34,10 @@ use serde_json::{json, value::Value as JsonValue};
 
 use super::DynStateStore;
 use crate::{
-    deserialized_responses::MemberEvent,
+    deserialized_responses::{MemberEvent, SyncTimelineEvent},
+    event_cache::{Gap, RoomEvents},
     media::{MediaFormat, MediaRequest, MediaThumbnailSize},
-    store::{Result, StateStoreExt},
+    store::{QueuedRequest, QueuedRequestKind, Result, SerializableEventContent, StateStoreExt},
     RoomInfo, RoomMemberships, RoomState, StateChanges, StateStoreDataKey, StateStoreDataValue,
 };
 
@@ -77,6 +80,12 @@ pub trait StateStoreIntegrationTests {
     async fn test_presence_saving(&self);
     /// Test display names saving.
     async fn test_display_names_saving(&self);
+    /// Test event cache saving.
+    async fn test_event_cache_saving(&self) -> Result<()>;
+    /// Test send queue saving.
+    async fn test_send_queue_saving(&self) -> Result<()>;
+    /// Test taking leased locks.
+    async fn test_lease_locks(&self) -> Result<()>;
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -1122,6 +1131,126 @@ impl StateStoreIntegrationTests for DynStateStore {
         let names = self.get_users_with_display_names(room_id, &[]).await;
         assert!(names.unwrap().is_empty());
     }
+
+    async fn test_event_cache_saving(&self) -> Result<()> {
+        let room_id = room_id();
+        let other_room_id = stripped_room_id();
+
+        assert!(self.get_event_cache_chunks(room_id).await?.is_empty());
+
+        let mut room_events = RoomEvents::new();
+        room_events.push_gap(Gap { prev_token: "prev_token".to_owned() });
+        room_events.push_events([
+            timeline_event(event_id!("$first")),
+            timeline_event(event_id!("$second")),
+        ]);
+        self.handle_event_cache_updates(room_id, room_events.take_updates()).await?;
+
+        let mut other_room_events = RoomEvents::new();
+        other_room_events.push_events([timeline_event(event_id!("$other"))]);
+        self.handle_event_cache_updates(other_room_id, other_room_events.take_updates()).await?;
+
+        // Fill the gap.
+        let gap_id = room_events.find_gap("prev_token").unwrap();
+        room_events.replace_gap(gap_id, vec![timeline_event(event_id!("$zeroth"))], None);
+        self.handle_event_cache_updates(room_id, room_events.take_updates()).await?;
+
+        let reloaded = RoomEvents::from_chunks(self.get_event_cache_chunks(room_id).await?);
+        let event_ids: Vec<_> =
+            reloaded.events().map(|ev| ev.event_id().unwrap().to_string()).collect();
+        assert_eq!(event_ids, ["$zeroth", "$first", "$second"]);
+        assert!(reloaded.chunks().iter().all(|chunk| !chunk.is_gap()));
+
+        // Removing the room removes its event cache.
+        self.remove_room(room_id).await?;
+        assert!(self.get_event_cache_chunks(room_id).await?.is_empty());
+        assert_eq!(self.get_event_cache_chunks(other_room_id).await?.len(), 1);
+
+        Ok(())
+    }
+
+    async fn test_send_queue_saving(&self) -> Result<()> {
+        let room_id = room_id();
+        let other_room_id = stripped_room_id();
+
+        assert!(self.load_send_queue_requests(room_id).await?.is_empty());
+        assert!(self.load_rooms_with_unsent_requests().await?.is_empty());
+
+        for txn_id in ["first", "second", "third"] {
+            let content = SerializableEventContent::new(&AnyMessageLikeEventContent::RoomMessage(
+                RoomMessageEventContent::text_plain(txn_id),
+            ))?;
+            let request = QueuedRequest::new(txn_id.into(), QueuedRequestKind::Event { content });
+            self.save_send_queue_request(room_id, request).await?;
+        }
+
+        let redaction = QueuedRequestKind::Redaction {
+            redacts: event_id!("$redacted").to_owned(),
+            reason: Some("spam".to_owned()),
+        };
+        self.save_send_queue_request(other_room_id, QueuedRequest::new("other".into(), redaction))
+            .await?;
+
+        // The requests are returned in the order they were saved.
+        let requests = self.load_send_queue_requests(room_id).await?;
+        let txn_ids: Vec<_> = requests.iter().map(|r| r.transaction_id.as_str()).collect();
+        assert_eq!(txn_ids, ["first", "second", "third"]);
+        assert!(requests.iter().all(|r| !r.is_wedged));
+        let content = assert_matches!(
+            &requests[1].kind,
+            QueuedRequestKind::Event { content } => content.deserialize()?
+        );
+        assert_matches!(
+            content,
+            AnyMessageLikeEventContent::RoomMessage(msg) => assert_eq!(msg.body(), "second")
+        );
+
+        let mut rooms = self.load_rooms_with_unsent_requests().await?;
+        rooms.sort();
+        assert_eq!(rooms, [other_room_id.to_owned(), room_id.to_owned()]);
+
+        // Wedge a request.
+        assert!(self.update_send_queue_request_status(room_id, "second".into(), true).await?);
+        assert!(!self.update_send_queue_request_status(room_id, "unknown".into(), true).await?);
+        let requests = self.load_send_queue_requests(room_id).await?;
+        let wedged: Vec<_> = requests.iter().map(|r| r.is_wedged).collect();
+        assert_eq!(wedged, [false, true, false]);
+
+        // Remove a request.
+        assert!(self.remove_send_queue_request(room_id, "first".into()).await?);
+        assert!(!self.remove_send_queue_request(room_id, "first".into()).await?);
+        let requests = self.load_send_queue_requests(room_id).await?;
+        let txn_ids: Vec<_> = requests.iter().map(|r| r.transaction_id.as_str()).collect();
+        assert_eq!(txn_ids, ["second", "third"]);
+
+        // Removing the last request of a room means it has no unsent requests.
+        assert!(self.remove_send_queue_request(other_room_id, "other".into()).await?);
+        assert_eq!(self.load_rooms_with_unsent_requests().await?, [room_id.to_owned()]);
+
+        // Removing the room removes its send queue.
+        self.remove_room(room_id).await?;
+        assert!(self.load_send_queue_requests(room_id).await?.is_empty());
+        assert!(self.load_rooms_with_unsent_requests().await?.is_empty());
+
+        Ok(())
+    }
+
+    async fn test_lease_locks(&self) -> Result<()> {
+        // Nobody held the lock, so it can be taken.
+        assert!(self.try_take_leased_lock(30_000, "key", "alice").await?);
+
+        // The same holder can extend its lease.
+        assert!(self.try_take_leased_lock(30_000, "key", "alice").await?);
+
+        // Another holder can't take it while the lease is running.
+        assert!(!self.try_take_leased_lock(30_000, "key", "bob").await?);
+
+        // Locks with different keys are independent.
+        assert!(self.try_take_leased_lock(30_000, "other_key", "bob").await?);
+        assert!(!self.try_take_leased_lock(30_000, "other_key", "alice").await?);
+
+        Ok(())
+    }
 }
 
 /// Macro building to allow your StateStore implementation to run the entire
@@ -1258,6 +1387,24 @@ macro_rules! statestore_integration_tests {
             let store = get_store().await.expect("creating store failed").into_state_store();
             store.test_display_names_saving().await;
         }
+
+        #[async_test]
+        async fn test_event_cache_saving() -> StoreResult<()> {
+            let store = get_store().await?.into_state_store();
+            store.test_event_cache_saving().await
+        }
+
+        #[async_test]
+        async fn test_send_queue_saving() -> StoreResult<()> {
+            let store = get_store().await?.into_state_store();
+            store.test_send_queue_saving().await
+        }
+
+        #[async_test]
+        async fn test_lease_locks() -> StoreResult<()> {
+            let store = get_store().await?.into_state_store();
+            store.test_lease_locks().await
+        }
     };
 }
 
@@ -1328,6 +1475,18 @@ fn custom_membership_event(user_id: &UserId, event_id: &EventId) -> Raw<SyncRoom
     Raw::new(&ev_json).unwrap().cast()
 }
 
+fn timeline_event(event_id: &EventId) -> SyncTimelineEvent {
+    let ev_json = json!({
+        "type": "m.room.message",
+        "content": { "body": "Hello", "msgtype": "m.text" },
+        "event_id": event_id,
+        "origin_server_ts": 151393755,
+        "sender": user_id(),
+    });
+
+    SyncTimelineEvent::new(Raw::new(&ev_json).unwrap().cast())
+}
+
 fn custom_presence_event(user_id: &UserId) -> Raw<PresenceEvent> {
     let ev_json = json!({
         "content": {
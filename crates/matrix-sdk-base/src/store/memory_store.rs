@@ -16,10 +16,11 @@ use std::{
     collections::{BTreeMap, BTreeSet},
     iter,
     sync::{Arc, RwLock},
+    time::Duration,
 };
 
 use async_trait::async_trait;
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use matrix_sdk_common::instant::Instant;
 use ruma::{
     canonical_json::redact,
@@ -32,14 +33,16 @@ use ruma::{
     },
     serde::Raw,
     CanonicalJsonObject, EventId, MxcUri, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId,
-    RoomVersionId, UserId,
+    RoomVersionId, TransactionId, UserId,
 };
 use tracing::{debug, warn};
 
-use super::{Result, RoomInfo, StateChanges, StateStore, StoreError};
+use super::{QueuedRequest, Result, RoomInfo, StateChanges, StateStore, StoreError};
 use crate::{
-    deserialized_responses::RawAnySyncOrStrippedState, media::MediaRequest, MinimalRoomMemberEvent,
-    RoomMemberships, RoomState, StateStoreDataKey, StateStoreDataValue,
+    deserialized_responses::RawAnySyncOrStrippedState,
+    event_cache::{Chunk, ChunkIdentifier, ChunkUpdate},
+    media::MediaRequest,
+    MinimalRoomMemberEvent, RoomMemberships, RoomState, StateStoreDataKey, StateStoreDataValue,
 };
 
 /// In-Memory, non-persistent implementation of the `StateStore`
@@ -78,6 +81,9 @@ pub struct MemoryStore {
         >,
     >,
     custom: Arc<DashMap<Vec<u8>, Vec<u8>>>,
+    event_cache: Arc<DashMap<OwnedRoomId, BTreeMap<ChunkIdentifier, Chunk>>>,
+    send_queue: Arc<DashMap<OwnedRoomId, Vec<QueuedRequest>>>,
+    leases: Arc<DashMap<String, (String, Instant)>>,
 }
 
 impl Default for MemoryStore {
@@ -111,6 +117,9 @@ impl MemoryStore {
                 100.try_into().expect("100 is a non-zero usize"),
             ))),
             custom: DashMap::new().into(),
+            event_cache: Default::default(),
+            send_queue: Default::default(),
+            leases: Default::default(),
         }
     }
 
@@ -482,7 +491,7 @@ impl MemoryStore {
         self.room_info
             .iter()
             .filter_map(|r| match r.state() {
-                RoomState::Invited => Some(r.clone()),
+                RoomState::Invited | RoomState::Knocked => Some(r.clone()),
                 _ => None,
             })
             .collect()
@@ -571,6 +580,29 @@ impl MemoryStore {
         Ok(self.custom.remove(key).map(|entry| entry.1))
     }
 
+    fn try_take_leased_lock(&self, lease_duration_ms: u32, key: &str, holder: &str) -> bool {
+        let now = Instant::now();
+        let expiration = now + Duration::from_millis(lease_duration_ms.into());
+
+        match self.leases.entry(key.to_owned()) {
+            Entry::Occupied(mut entry) => {
+                // We can take the lease if we already had it (thus extending it), or if the
+                // previous holder's lease has expired.
+                let prev = entry.get_mut();
+                if prev.0 == holder || prev.1 < now {
+                    *prev = (holder.to_owned(), expiration);
+                    true
+                } else {
+                    false
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((holder.to_owned(), expiration));
+                true
+            }
+        }
+    }
+
     // The in-memory store doesn't cache media
     async fn add_media_content(&self, _request: &MediaRequest, _data: Vec<u8>) -> Result<()> {
         Ok(())
@@ -585,6 +617,96 @@ impl MemoryStore {
         Ok(())
     }
 
+    async fn get_event_cache_chunks(&self, room_id: &RoomId) -> Result<Vec<Chunk>> {
+        Ok(self
+            .event_cache
+            .get(room_id)
+            .map(|chunks| chunks.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn handle_event_cache_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<ChunkUpdate>,
+    ) -> Result<()> {
+        let mut chunks = self.event_cache.entry(room_id.to_owned()).or_default();
+
+        for update in updates {
+            match update {
+                ChunkUpdate::Upsert(chunk) => {
+                    chunks.insert(chunk.identifier, chunk);
+                }
+                ChunkUpdate::Remove(identifier) => {
+                    chunks.remove(&identifier);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<()> {
+        self.send_queue.entry(room_id.to_owned()).or_default().push(request);
+        Ok(())
+    }
+
+    async fn update_send_queue_request_status(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+        is_wedged: bool,
+    ) -> Result<bool> {
+        let Some(mut requests) = self.send_queue.get_mut(room_id) else {
+            return Ok(false);
+        };
+
+        match requests.iter_mut().find(|request| request.transaction_id == transaction_id) {
+            Some(request) => {
+                request.is_wedged = is_wedged;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool> {
+        let Some(mut requests) = self.send_queue.get_mut(room_id) else {
+            return Ok(false);
+        };
+
+        let Some(position) =
+            requests.iter().position(|request| request.transaction_id == transaction_id)
+        else {
+            return Ok(false);
+        };
+
+        requests.remove(position);
+
+        if requests.is_empty() {
+            drop(requests);
+            self.send_queue.remove_if(room_id, |_, requests| requests.is_empty());
+        }
+
+        Ok(true)
+    }
+
+    async fn load_send_queue_requests(&self, room_id: &RoomId) -> Result<Vec<QueuedRequest>> {
+        Ok(self.send_queue.get(room_id).map(|requests| requests.clone()).unwrap_or_default())
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>> {
+        Ok(self.send_queue.iter().map(|entry| entry.key().clone()).collect())
+    }
+
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
         self.profiles.remove(room_id);
         self.display_names.remove(room_id);
@@ -596,6 +718,8 @@ impl MemoryStore {
         self.stripped_members.remove(room_id);
         self.room_user_receipts.remove(room_id);
         self.room_event_receipts.remove(room_id);
+        self.event_cache.remove(room_id);
+        self.send_queue.remove(room_id);
 
         Ok(())
     }
@@ -781,6 +905,15 @@ impl StateStore for MemoryStore {
         self.remove_custom_value(key).await
     }
 
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        Ok(self.try_take_leased_lock(lease_duration_ms, key, holder))
+    }
+
     async fn add_media_content(&self, request: &MediaRequest, data: Vec<u8>) -> Result<()> {
         self.add_media_content(request, data).await
     }
@@ -797,6 +930,51 @@ impl StateStore for MemoryStore {
         self.remove_media_content_for_uri(uri).await
     }
 
+    async fn get_event_cache_chunks(&self, room_id: &RoomId) -> Result<Vec<Chunk>> {
+        self.get_event_cache_chunks(room_id).await
+    }
+
+    async fn handle_event_cache_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<ChunkUpdate>,
+    ) -> Result<()> {
+        self.handle_event_cache_updates(room_id, updates).await
+    }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<()> {
+        self.save_send_queue_request(room_id, request).await
+    }
+
+    async fn update_send_queue_request_status(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+        is_wedged: bool,
+    ) -> Result<bool> {
+        self.update_send_queue_request_status(room_id, transaction_id, is_wedged).await
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool> {
+        self.remove_send_queue_request(room_id, transaction_id).await
+    }
+
+    async fn load_send_queue_requests(&self, room_id: &RoomId) -> Result<Vec<QueuedRequest>> {
+        self.load_send_queue_requests(room_id).await
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>> {
+        self.load_rooms_with_unsent_requests().await
+    }
+
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
         self.remove_room(room_id).await
     }
@@ -27,7 +27,10 @@ use std::{
     pin::Pin,
     result::Result as StdResult,
     str::Utf8Error,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use once_cell::sync::OnceCell;
@@ -37,7 +40,9 @@ use once_cell::sync::OnceCell;
 pub mod integration_tests;
 mod traits;
 
+use async_trait::async_trait;
 use dashmap::DashMap;
+use matrix_sdk_common::store_locks::BackingStore;
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_crypto::store::{DynCryptoStore, IntoCryptoStore};
 pub use matrix_sdk_store_encryption::Error as StoreEncryptionError;
@@ -65,11 +70,13 @@ use crate::{
 
 pub(crate) mod ambiguity_map;
 mod memory_store;
+mod send_queue;
 
 #[cfg(any(test, feature = "testing"))]
 pub use self::integration_tests::StateStoreIntegrationTests;
 pub use self::{
     memory_store::MemoryStore,
+    send_queue::{QueuedRequest, QueuedRequestKind, SerializableEventContent},
     traits::{
         DynStateStore, IntoStateStore, StateStore, StateStoreDataKey, StateStoreDataValue,
         StateStoreExt,
@@ -133,6 +140,28 @@ impl StoreError {
 /// A `StateStore` specific result type.
 pub type Result<T, E = StoreError> = std::result::Result<T, E>;
 
+/// A wrapper around a state store, that can be used as the backing store of a
+/// [`CrossProcessStoreLock`].
+///
+/// [`CrossProcessStoreLock`]: matrix_sdk_common::store_locks::CrossProcessStoreLock
+#[derive(Clone, Debug)]
+pub struct LockableStateStore(pub Arc<DynStateStore>);
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl BackingStore for LockableStateStore {
+    type LockError = StoreError;
+
+    async fn try_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool, Self::LockError> {
+        self.0.try_take_leased_lock(lease_duration_ms, key, holder).await
+    }
+}
+
 /// A state store wrapper for the SDK.
 ///
 /// This adds additional higher level store functionality on top of a
@@ -141,6 +170,9 @@ pub type Result<T, E = StoreError> = std::result::Result<T, E>;
 pub(crate) struct Store {
     pub(super) inner: Arc<DynStateStore>,
     session_meta: Arc<OnceCell<SessionMeta>>,
+    /// Whether the session was logged out, in which case the session meta
+    /// must not be used anymore.
+    logged_out: Arc<AtomicBool>,
     /// The current sync token that should be used for the next sync call.
     pub(super) sync_token: Arc<RwLock<Option<String>>>,
     rooms: Arc<DashMap<OwnedRoomId, Room>>,
@@ -158,6 +190,7 @@ impl Store {
         Self {
             inner,
             session_meta: Default::default(),
+            logged_out: Default::default(),
             sync_token: Default::default(),
             rooms: Default::default(),
             sync_lock: Default::default(),
@@ -192,9 +225,20 @@ impl Store {
 
     /// The current [`SessionMeta`] containing our user ID and device ID.
     pub fn session_meta(&self) -> Option<&SessionMeta> {
+        if self.logged_out.load(Ordering::SeqCst) {
+            return None;
+        }
+
         self.session_meta.get()
     }
 
+    /// Forget the meta of the session, because it was logged out.
+    ///
+    /// A new session can't be set afterwards.
+    pub fn clear_session_meta(&self) {
+        self.logged_out.store(true, Ordering::SeqCst);
+    }
+
     /// Get all the rooms this store knows about.
     pub fn get_rooms(&self) -> Vec<Room> {
         self.rooms.iter().filter_map(|r| self.get_room(r.key())).collect()
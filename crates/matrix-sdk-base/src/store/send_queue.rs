@@ -0,0 +1,115 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types for the requests of the send queue that are persisted in the state
+//! store.
+
+use std::path::PathBuf;
+
+use ruma::{
+    events::{AnyMessageLikeEventContent, EventContent, EventContentFromType},
+    serde::Raw,
+    OwnedEventId, OwnedTransactionId,
+};
+use serde::{Deserialize, Serialize};
+
+/// The content of a message-like event, along with its type, in a form that
+/// can be persisted.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SerializableEventContent {
+    event: Raw<AnyMessageLikeEventContent>,
+    event_type: String,
+}
+
+impl SerializableEventContent {
+    /// Create a new `SerializableEventContent` from the given content.
+    pub fn new(content: &AnyMessageLikeEventContent) -> Result<Self, serde_json::Error> {
+        Ok(Self { event: Raw::new(content)?, event_type: content.event_type().to_string() })
+    }
+
+    /// Create a new `SerializableEventContent` from the raw parts of an event
+    /// content.
+    pub fn from_raw(event: Raw<AnyMessageLikeEventContent>, event_type: String) -> Self {
+        Self { event, event_type }
+    }
+
+    /// Deserialize the event content.
+    pub fn deserialize(&self) -> Result<AnyMessageLikeEventContent, serde_json::Error> {
+        AnyMessageLikeEventContent::from_parts(&self.event_type, self.event.json())
+    }
+
+    /// The raw event content.
+    pub fn raw(&self) -> &Raw<AnyMessageLikeEventContent> {
+        &self.event
+    }
+
+    /// The type of the event.
+    pub fn event_type(&self) -> &str {
+        &self.event_type
+    }
+}
+
+/// The kind of a request in the send queue.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum QueuedRequestKind {
+    /// A message-like event, like a message or a reaction.
+    Event {
+        /// The content of the event.
+        content: SerializableEventContent,
+    },
+
+    /// The redaction of an event.
+    Redaction {
+        /// The ID of the event to redact.
+        redacts: OwnedEventId,
+
+        /// The reason of the redaction.
+        reason: Option<String>,
+    },
+
+    /// A file to upload, that will be sent as a message.
+    Attachment {
+        /// A textual representation of the file, usually its name.
+        body: String,
+
+        /// The MIME type of the file.
+        content_type: String,
+
+        /// The path of the local copy of the file.
+        path: PathBuf,
+    },
+}
+
+/// A request of the send queue, as persisted in the state store.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedRequest {
+    /// The transaction ID of the request, that identifies it in the queue.
+    pub transaction_id: OwnedTransactionId,
+
+    /// What to send.
+    pub kind: QueuedRequestKind,
+
+    /// Whether sending this request failed with an error that can't be fixed
+    /// by retrying it automatically.
+    ///
+    /// A wedged request blocks the requests after it in the same room.
+    pub is_wedged: bool,
+}
+
+impl QueuedRequest {
+    /// Create a new request that isn't wedged.
+    pub fn new(transaction_id: OwnedTransactionId, kind: QueuedRequestKind) -> Self {
+        Self { transaction_id, kind, is_wedged: false }
+    }
+}
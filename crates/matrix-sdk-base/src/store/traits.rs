@@ -31,12 +31,13 @@ use ruma::{
         RoomAccountDataEventType, StateEventType, StaticEventContent, StaticStateEventContent,
     },
     serde::Raw,
-    EventId, MxcUri, OwnedEventId, OwnedUserId, RoomId, UserId,
+    EventId, MxcUri, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, TransactionId, UserId,
 };
 
-use super::{StateChanges, StoreError};
+use super::{QueuedRequest, StateChanges, StoreError};
 use crate::{
     deserialized_responses::{RawAnySyncOrStrippedState, RawMemberEvent, RawSyncOrStrippedState},
+    event_cache::{Chunk, ChunkUpdate},
     media::MediaRequest,
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships,
 };
@@ -321,6 +322,24 @@ pub trait StateStore: AsyncTraitDeps {
     /// * `key` - The key to remove data from
     async fn remove_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
 
+    /// Try to take a leased lock.
+    ///
+    /// This attempts to take a lock for the given lease duration.
+    ///
+    /// - If we already had the lease, this will extend the lease.
+    /// - If we didn't, but the previous lease has expired, we will acquire the
+    ///   lock.
+    /// - If there was no previous lease, we will acquire the lock.
+    /// - Otherwise, we don't get the lock.
+    ///
+    /// Returns whether taking the lock succeeded.
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool, Self::Error>;
+
     /// Add a media file's content in the media store.
     ///
     /// # Arguments
@@ -359,6 +378,86 @@ pub trait StateStore: AsyncTraitDeps {
     /// * `uri` - The `MxcUri` of the media files.
     async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<(), Self::Error>;
 
+    /// Get all the chunks of the event cache of a room, in no particular order.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room to get the event cache of.
+    async fn get_event_cache_chunks(&self, room_id: &RoomId) -> Result<Vec<Chunk>, Self::Error>;
+
+    /// Persist changes to the event cache of a room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the event cache belongs to.
+    ///
+    /// * `updates` - The changes to apply, in order.
+    async fn handle_event_cache_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<ChunkUpdate>,
+    ) -> Result<(), Self::Error>;
+
+    /// Append a request to the send queue of a room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the request is sent to.
+    ///
+    /// * `request` - The request to persist.
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<(), Self::Error>;
+
+    /// Update whether a request of the send queue of a room is wedged.
+    ///
+    /// Returns whether the request was found.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the request is sent to.
+    ///
+    /// * `transaction_id` - The transaction ID of the request.
+    ///
+    /// * `is_wedged` - Whether the request is wedged.
+    async fn update_send_queue_request_status(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+        is_wedged: bool,
+    ) -> Result<bool, Self::Error>;
+
+    /// Remove a request from the send queue of a room.
+    ///
+    /// Returns whether the request was found.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the request is sent to.
+    ///
+    /// * `transaction_id` - The transaction ID of the request.
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool, Self::Error>;
+
+    /// Get the requests of the send queue of a room, in the order they were
+    /// saved.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the requests are sent to.
+    async fn load_send_queue_requests(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<QueuedRequest>, Self::Error>;
+
+    /// Get the IDs of the rooms that have requests in their send queue.
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>, Self::Error>;
+
     /// Removes a room and all elements associated from the state store.
     ///
     /// # Arguments
@@ -561,6 +660,15 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
         self.0.remove_custom_value(key).await.map_err(Into::into)
     }
 
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool, Self::Error> {
+        self.0.try_take_leased_lock(lease_duration_ms, key, holder).await.map_err(Into::into)
+    }
+
     async fn add_media_content(
         &self,
         request: &MediaRequest,
@@ -584,6 +692,57 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
         self.0.remove_media_content_for_uri(uri).await.map_err(Into::into)
     }
 
+    async fn get_event_cache_chunks(&self, room_id: &RoomId) -> Result<Vec<Chunk>, Self::Error> {
+        self.0.get_event_cache_chunks(room_id).await.map_err(Into::into)
+    }
+
+    async fn handle_event_cache_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<ChunkUpdate>,
+    ) -> Result<(), Self::Error> {
+        self.0.handle_event_cache_updates(room_id, updates).await.map_err(Into::into)
+    }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<(), Self::Error> {
+        self.0.save_send_queue_request(room_id, request).await.map_err(Into::into)
+    }
+
+    async fn update_send_queue_request_status(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+        is_wedged: bool,
+    ) -> Result<bool, Self::Error> {
+        self.0
+            .update_send_queue_request_status(room_id, transaction_id, is_wedged)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool, Self::Error> {
+        self.0.remove_send_queue_request(room_id, transaction_id).await.map_err(Into::into)
+    }
+
+    async fn load_send_queue_requests(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<QueuedRequest>, Self::Error> {
+        self.0.load_send_queue_requests(room_id).await.map_err(Into::into)
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>, Self::Error> {
+        self.0.load_rooms_with_unsent_requests().await.map_err(Into::into)
+    }
+
     async fn remove_room(&self, room_id: &RoomId) -> Result<(), Self::Error> {
         self.0.remove_room(room_id).await.map_err(Into::into)
     }
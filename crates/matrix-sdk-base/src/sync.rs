@@ -21,7 +21,8 @@ use ruma::{
     api::client::{
         push::get_notifications::v3::Notification,
         sync::sync_events::{
-            v3::InvitedRoom, DeviceLists, UnreadNotificationsCount as RumaUnreadNotificationsCount,
+            v3::{InvitedRoom, KnockedRoom},
+            DeviceLists, UnreadNotificationsCount as RumaUnreadNotificationsCount,
         },
     },
     events::{
@@ -35,7 +36,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     debug::{
-        DebugInvitedRoom, DebugListOfRawEvents, DebugListOfRawEventsNoId, DebugNotificationMap,
+        DebugInvitedRoom, DebugKnockedRoom, DebugListOfRawEvents, DebugListOfRawEventsNoId,
+        DebugNotificationMap,
     },
     deserialized_responses::AmbiguityChanges,
 };
@@ -91,6 +93,8 @@ pub struct Rooms {
     pub join: BTreeMap<OwnedRoomId, JoinedRoom>,
     /// The rooms that the user has been invited to.
     pub invite: BTreeMap<OwnedRoomId, InvitedRoom>,
+    /// The rooms that the user has knocked on.
+    pub knock: BTreeMap<OwnedRoomId, KnockedRoom>,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -100,6 +104,7 @@ impl fmt::Debug for Rooms {
             .field("leave", &self.leave)
             .field("join", &self.join)
             .field("invite", &DebugInvitedRooms(&self.invite))
+            .field("knock", &DebugKnockedRooms(&self.knock))
             .finish()
     }
 }
@@ -230,3 +235,12 @@ impl<'a> fmt::Debug for DebugInvitedRooms<'a> {
         f.debug_map().entries(self.0.iter().map(|(k, v)| (k, DebugInvitedRoom(v)))).finish()
     }
 }
+
+struct DebugKnockedRooms<'a>(&'a BTreeMap<OwnedRoomId, KnockedRoom>);
+
+#[cfg(not(tarpaulin_include))]
+impl<'a> fmt::Debug for DebugKnockedRooms<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.0.iter().map(|(k, v)| (k, DebugKnockedRoom(v)))).finish()
+    }
+}
@@ -16,8 +16,9 @@ use std::{collections::BTreeMap, fmt};
 
 use ruma::{
     events::{AnySyncTimelineEvent, AnyTimelineEvent},
+    exports::ruma_macros::AsStrAsRefStr,
     push::Action,
-    serde::Raw,
+    serde::{AsRefStr, DebugAsRefStr, DeserializeFromCowStr, FromString, Raw, SerializeAsRefStr},
     DeviceKeyAlgorithm, OwnedDeviceId, OwnedEventId, OwnedUserId,
 };
 use serde::{Deserialize, Serialize};
@@ -223,6 +224,121 @@ pub struct EncryptionInfo {
     pub verification_state: VerificationState,
 }
 
+/// A machine-readable code for why the megolm key was not sent.
+#[derive(
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    AsStrAsRefStr,
+    AsRefStr,
+    FromString,
+    DebugAsRefStr,
+    SerializeAsRefStr,
+    DeserializeFromCowStr,
+)]
+#[non_exhaustive]
+pub enum WithheldCode {
+    /// the user/device was blacklisted.
+    #[ruma_enum(rename = "m.blacklisted")]
+    Blacklisted,
+
+    /// the user/devices is unverified.
+    #[ruma_enum(rename = "m.unverified")]
+    Unverified,
+
+    /// The user/device is not allowed have the key. For example, this would
+    /// usually be sent in response to a key request if the user was not in
+    /// the room when the message was sent.
+    #[ruma_enum(rename = "m.unauthorised")]
+    Unauthorised,
+
+    /// Sent in reply to a key request if the device that the key is requested
+    /// from does not have the requested key.
+    #[ruma_enum(rename = "m.unavailable")]
+    Unavailable,
+
+    /// An olm session could not be established.
+    /// This may happen, for example, if the sender was unable to obtain a
+    /// one-time key from the recipient.
+    #[ruma_enum(rename = "m.no_olm")]
+    NoOlm,
+
+    #[doc(hidden)]
+    _Custom(PrivOwnedStr),
+}
+
+impl std::fmt::Display for WithheldCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let string = match self {
+            WithheldCode::Blacklisted => "The sender has blocked you.",
+            WithheldCode::Unverified => "The sender has disabled encrypting to unverified devices.",
+            WithheldCode::Unauthorised => "You are not authorised to read the message.",
+            WithheldCode::Unavailable => "The requested key was not found.",
+            WithheldCode::NoOlm => "Unable to establish a secure channel.",
+            _ => self.as_str(),
+        };
+
+        f.write_str(string)
+    }
+}
+
+/// The reason why an encrypted room event couldn't be decrypted.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum UnableToDecryptReason {
+    /// We don't know why the event couldn't be decrypted.
+    #[default]
+    Unknown,
+
+    /// The encrypted event, or its decrypted payload, is malformed or uses an
+    /// unsupported algorithm.
+    MalformedEvent,
+
+    /// We don't have the room key that was used to encrypt the event.
+    MissingMegolmSession {
+        /// The code the sender gave when they refused to share the room key
+        /// with us, if they did so.
+        withheld_code: Option<WithheldCode>,
+    },
+
+    /// We have the room key, but only starting from a later message index than
+    /// the one of the event.
+    UnknownMegolmMessageIndex,
+
+    /// Decrypting the event with the room key failed.
+    MegolmDecryptionFailure,
+
+    /// The identity keys of the device that sent us the room key don't match
+    /// the ones recorded in the room key.
+    MismatchedIdentityKeys,
+
+    /// The event was sent by a device that isn't verified, and we refuse to
+    /// decrypt the events of such devices.
+    UnverifiedSenderDevice(VerificationLevel),
+}
+
+impl UnableToDecryptReason {
+    /// Could the event be decrypted if we received the room key later, either
+    /// from the sender, another one of our devices, or a backup?
+    pub fn is_missing_room_key(&self) -> bool {
+        matches!(
+            self,
+            Self::MissingMegolmSession { withheld_code: None } | Self::UnknownMegolmMessageIndex
+        )
+    }
+
+    /// The withheld code explaining why the sender didn't share the room key
+    /// with us, if any.
+    pub fn withheld_code(&self) -> Option<&WithheldCode> {
+        match self {
+            Self::MissingMegolmSession { withheld_code } => withheld_code.as_ref(),
+            _ => None,
+        }
+    }
+}
+
 /// A customized version of a room event coming from a sync that holds optional
 /// encryption info.
 #[derive(Clone, Deserialize, Serialize)]
@@ -235,6 +351,10 @@ pub struct SyncTimelineEvent {
     /// The push actions associated with this event.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub push_actions: Vec<Action>,
+    /// Why the event couldn't be decrypted, if it is an encrypted event that
+    /// we tried to decrypt and failed to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unable_to_decrypt_reason: Option<UnableToDecryptReason>,
 }
 
 impl SyncTimelineEvent {
@@ -243,7 +363,7 @@ impl SyncTimelineEvent {
     /// This is a convenience constructor for when you don't need to set
     /// `encryption_info` or `push_action`, for example inside a test.
     pub fn new(event: Raw<AnySyncTimelineEvent>) -> Self {
-        Self { event, encryption_info: None, push_actions: vec![] }
+        Self { event, encryption_info: None, push_actions: vec![], unable_to_decrypt_reason: None }
     }
 
     /// Get the event id of this `SyncTimelineEvent` if the event has any valid
@@ -256,20 +376,22 @@ impl SyncTimelineEvent {
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for SyncTimelineEvent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let SyncTimelineEvent { event, encryption_info, push_actions } = self;
+        let SyncTimelineEvent { event, encryption_info, push_actions, unable_to_decrypt_reason } =
+            self;
         let mut s = f.debug_struct("SyncTimelineEvent");
         s.field("event", &DebugRawEvent(event));
         s.maybe_field("encryption_info", encryption_info);
         if !push_actions.is_empty() {
             s.field("push_actions", push_actions);
         }
+        s.maybe_field("unable_to_decrypt_reason", unable_to_decrypt_reason);
         s.finish()
     }
 }
 
 impl From<Raw<AnySyncTimelineEvent>> for SyncTimelineEvent {
     fn from(inner: Raw<AnySyncTimelineEvent>) -> Self {
-        Self { encryption_info: None, event: inner, push_actions: Vec::default() }
+        Self::new(inner)
     }
 }
 
@@ -283,6 +405,7 @@ impl From<TimelineEvent> for SyncTimelineEvent {
             event: o.event.cast(),
             encryption_info: o.encryption_info,
             push_actions: o.push_actions,
+            unable_to_decrypt_reason: o.unable_to_decrypt_reason,
         }
     }
 }
@@ -296,6 +419,9 @@ pub struct TimelineEvent {
     pub encryption_info: Option<EncryptionInfo>,
     /// The push actions associated with this event.
     pub push_actions: Vec<Action>,
+    /// Why the event couldn't be decrypted, if it is an encrypted event that
+    /// we tried to decrypt and failed to.
+    pub unable_to_decrypt_reason: Option<UnableToDecryptReason>,
 }
 
 impl TimelineEvent {
@@ -304,24 +430,39 @@ impl TimelineEvent {
     /// This is a convenience constructor for when you don't need to set
     /// `encryption_info` or `push_action`, for example inside a test.
     pub fn new(event: Raw<AnyTimelineEvent>) -> Self {
-        Self { event, encryption_info: None, push_actions: vec![] }
+        Self { event, encryption_info: None, push_actions: vec![], unable_to_decrypt_reason: None }
     }
 }
 
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for TimelineEvent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let TimelineEvent { event, encryption_info, push_actions } = self;
+        let TimelineEvent { event, encryption_info, push_actions, unable_to_decrypt_reason } = self;
         let mut s = f.debug_struct("TimelineEvent");
         s.field("event", &DebugRawEvent(event));
         s.maybe_field("encryption_info", encryption_info);
         if !push_actions.is_empty() {
             s.field("push_actions", push_actions);
         }
+        s.maybe_field("unable_to_decrypt_reason", unable_to_decrypt_reason);
         s.finish()
     }
 }
 
+// Wrapper around `Box<str>` that cannot be used in a meaningful way outside of
+// this crate. Used for string enums because their `_Custom` variant can't be
+// truly private (only `#[doc(hidden)]`).
+#[doc(hidden)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PrivOwnedStr(Box<str>);
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for PrivOwnedStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ruma::{
@@ -24,6 +24,8 @@ pub mod debug;
 pub mod deserialized_responses;
 pub mod executor;
 pub mod ring_buffer;
+pub mod sleep;
+pub mod store_locks;
 pub mod timeout;
 
 /// Alias for `Send` on non-wasm, empty trait (implemented by everything) on
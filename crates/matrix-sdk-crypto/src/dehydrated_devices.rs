@@ -0,0 +1,380 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Submodule for dehydrated devices, as defined in [MSC3814].
+//!
+//! A dehydrated device is a device whose Olm account is encrypted and stored
+//! on the homeserver. Other devices can send to-device messages, and
+//! especially room keys, to it while the user doesn't have any other device
+//! online. When the user logs in again, the new device fetches the dehydrated
+//! device, rehydrates it with the pickle key, and imports the room keys that
+//! were sent to it.
+//!
+//! The pickle key is a random 32 byte key, it should be stored in the secret
+//! storage so every new device of the user can use it.
+//!
+//! [MSC3814]: https://github.com/matrix-org/matrix-spec-proposals/pull/3814
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use ruma::{
+    api::client::dehydrated_device::{
+        put_dehydrated_device::unstable::Request as PutDehydratedDeviceRequest,
+        DehydratedDeviceData, DehydratedDeviceV1,
+    },
+    assign,
+    events::AnyToDeviceEvent,
+    serde::Raw,
+    DeviceId,
+};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::{debug, instrument, trace};
+use vodozemac::{megolm::SessionOrdering, PickleError};
+
+use crate::{
+    olm::{PrivateCrossSigningIdentity, ReadOnlyAccount},
+    store::{Changes, IntoCryptoStore, MemoryStore, RoomKeyInfo},
+    CryptoStoreError, OlmError, OlmMachine, SignatureError,
+};
+
+/// Error type for the creation and the rehydration of dehydrated devices.
+#[derive(Debug, Error)]
+pub enum DehydrationError {
+    /// The dehydrated device couldn't be pickled or unpickled, the pickle key
+    /// is probably wrong.
+    #[error(transparent)]
+    Pickle(#[from] PickleError),
+
+    /// The data of the dehydrated device couldn't be deserialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The data of the dehydrated device uses an unsupported algorithm.
+    #[error("the dehydrated device uses an unsupported algorithm")]
+    UnsupportedAlgorithm,
+
+    /// The device keys of the dehydrated device couldn't be signed with our
+    /// self-signing key.
+    #[error(transparent)]
+    Signature(#[from] SignatureError),
+
+    /// The crypto store failed to load or save the room keys.
+    #[error(transparent)]
+    Store(#[from] CryptoStoreError),
+}
+
+/// The manager of the dehydrated devices of our own user.
+///
+/// To get this, use [`OlmMachine::dehydrated_devices()`].
+#[derive(Debug)]
+pub struct DehydratedDevices {
+    pub(crate) inner: OlmMachine,
+}
+
+impl DehydratedDevices {
+    /// Create a new dehydrated device, with a new random device ID.
+    ///
+    /// The device isn't known by the homeserver until the request returned by
+    /// [`DehydratedDevice::keys_for_upload()`] is sent.
+    pub async fn create(&self) -> DehydratedDevice {
+        let account = ReadOnlyAccount::new(self.inner.user_id(), &DeviceId::new());
+        account.generate_fallback_key_helper().await;
+
+        DehydratedDevice { account, user_identity: self.inner.store().private_identity() }
+    }
+
+    /// Rehydrate a dehydrated device that was fetched from the homeserver.
+    ///
+    /// # Arguments
+    ///
+    /// * `pickle_key` - The key the dehydrated device was pickled with.
+    ///
+    /// * `device_id` - The ID of the dehydrated device.
+    ///
+    /// * `device_data` - The data of the dehydrated device, as returned by the
+    /// homeserver.
+    #[instrument(skip(self, pickle_key, device_data))]
+    pub async fn rehydrate(
+        &self,
+        pickle_key: &[u8; 32],
+        device_id: &DeviceId,
+        device_data: Raw<DehydratedDeviceData>,
+    ) -> Result<RehydratedDevice, DehydrationError> {
+        let device_pickle = match device_data.deserialize()? {
+            DehydratedDeviceData::V1(data) => data.device_pickle,
+            _ => return Err(DehydrationError::UnsupportedAlgorithm),
+        };
+
+        let user_id = self.inner.user_id();
+        let account = ReadOnlyAccount::rehydrate(pickle_key, user_id, device_id, &device_pickle)?;
+        let user_identity = PrivateCrossSigningIdentity::empty(user_id);
+
+        let rehydrated = OlmMachine::new_helper(
+            user_id,
+            device_id,
+            MemoryStore::new().into_crypto_store(),
+            account,
+            user_identity,
+        );
+
+        debug!("Rehydrated a dehydrated device");
+
+        Ok(RehydratedDevice { rehydrated, original: self.inner.clone() })
+    }
+}
+
+/// A dehydrated device that was just created, and that can be uploaded to the
+/// homeserver.
+pub struct DehydratedDevice {
+    account: ReadOnlyAccount,
+    user_identity: Arc<Mutex<PrivateCrossSigningIdentity>>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for DehydratedDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DehydratedDevice").field("device_id", &self.account.device_id()).finish()
+    }
+}
+
+impl DehydratedDevice {
+    /// The ID of the dehydrated device.
+    pub fn device_id(&self) -> &DeviceId {
+        self.account.device_id()
+    }
+
+    /// Create the request to upload the dehydrated device to the homeserver.
+    ///
+    /// The device keys are signed with our self-signing key, so the
+    /// dehydrated device is trusted by the devices that trust our identity.
+    /// This replaces any previous dehydrated device of the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_device_display_name` - The display name of the dehydrated
+    /// device.
+    ///
+    /// * `pickle_key` - The key used to encrypt the Olm account of the
+    /// dehydrated device.
+    #[instrument(skip(self, pickle_key), fields(device_id = ?self.device_id()))]
+    pub async fn keys_for_upload(
+        &self,
+        initial_device_display_name: String,
+        pickle_key: &[u8; 32],
+    ) -> Result<PutDehydratedDeviceRequest, DehydrationError> {
+        let mut device_keys = self.account.device_keys().await;
+        self.user_identity.lock().await.sign_device_keys(&mut device_keys).await?;
+
+        let one_time_keys = self.account.signed_one_time_keys().await;
+        let fallback_keys = self.account.signed_fallback_keys().await;
+
+        // The keys are now part of the pickle, they won't be uploaded again.
+        self.account.mark_keys_as_published().await;
+
+        let device_pickle = self.account.dehydrate(pickle_key).await;
+        let device_data =
+            Raw::new(&DehydratedDeviceData::V1(DehydratedDeviceV1::new(device_pickle)))?;
+
+        trace!(
+            one_time_keys = one_time_keys.len(),
+            fallback_keys = fallback_keys.len(),
+            "Created the request to upload a dehydrated device"
+        );
+
+        Ok(assign!(
+            PutDehydratedDeviceRequest::new(
+                self.device_id().to_owned(),
+                initial_device_display_name,
+                device_data,
+                device_keys.to_raw(),
+            ),
+            { one_time_keys, fallback_keys }
+        ))
+    }
+}
+
+/// A dehydrated device that was rehydrated, and that can receive the
+/// to-device events that were sent to it.
+#[derive(Debug)]
+pub struct RehydratedDevice {
+    rehydrated: OlmMachine,
+    original: OlmMachine,
+}
+
+impl RehydratedDevice {
+    /// The ID of the rehydrated device.
+    pub fn device_id(&self) -> &DeviceId {
+        self.rehydrated.device_id()
+    }
+
+    /// Decrypt the to-device events that were sent to the dehydrated device,
+    /// and import the room keys they contain into our own crypto store.
+    ///
+    /// This should be called with every batch of events returned by the
+    /// homeserver, until there are no more events.
+    ///
+    /// Returns the info about the room keys that were imported.
+    #[instrument(skip_all, fields(device_id = ?self.device_id()))]
+    pub async fn receive_events(
+        &self,
+        events: Vec<Raw<AnyToDeviceEvent>>,
+    ) -> Result<Vec<RoomKeyInfo>, OlmError> {
+        let (_, room_key_updates) = self
+            .rehydrated
+            .receive_sync_changes(events, &Default::default(), &BTreeMap::new(), None)
+            .await?;
+
+        let mut sessions = Vec::new();
+
+        for room_key in &room_key_updates {
+            let Some(session) = self
+                .rehydrated
+                .store()
+                .get_inbound_group_session(&room_key.room_id, &room_key.session_id)
+                .await?
+            else {
+                continue;
+            };
+
+            // Only import the session if it's better than the one we have.
+            if self.original.store().compare_group_session(&session).await?
+                == SessionOrdering::Better
+            {
+                sessions.push(session);
+            }
+        }
+
+        let room_keys: Vec<_> = sessions.iter().map(RoomKeyInfo::from).collect();
+        debug!(count = room_keys.len(), "Imported room keys from the dehydrated device");
+
+        let changes = Changes { inbound_group_sessions: sessions, ..Default::default() };
+        self.original.store().save_changes(changes).await?;
+
+        Ok(room_keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use matrix_sdk_test::async_test;
+    use ruma::{
+        api::client::keys::claim_keys::v3::Response as KeysClaimResponse, device_id, room_id,
+        user_id, TransactionId,
+    };
+    use serde_json::json;
+
+    use crate::{types::DeviceKeys, EncryptionSettings, OlmMachine, ReadOnlyDevice};
+
+    const PICKLE_KEY: &[u8; 32] = &[0; 32];
+
+    async fn alice_machine() -> OlmMachine {
+        let alice = OlmMachine::new(user_id!("@alice:localhost"), device_id!("ALICEDEVICE")).await;
+        alice.bootstrap_cross_signing(false).await.unwrap();
+        alice
+    }
+
+    #[async_test]
+    async fn dehydrated_device_creation() {
+        let alice = alice_machine().await;
+
+        let dehydrated_device = alice.dehydrated_devices().create().await;
+        let request = dehydrated_device
+            .keys_for_upload("Dehydrated device".to_owned(), PICKLE_KEY)
+            .await
+            .unwrap();
+
+        assert_eq!(request.device_id, dehydrated_device.device_id());
+        assert_ne!(request.device_id, alice.device_id());
+        assert!(!request.one_time_keys.is_empty());
+        assert!(!request.fallback_keys.is_empty());
+
+        // The device keys are signed by the device and by our self-signing key.
+        let device_keys: DeviceKeys = request.device_keys.deserialize_as().unwrap();
+        assert_eq!(device_keys.signatures.get(alice.user_id()).unwrap().len(), 2);
+
+        // The device can't be rehydrated with another key.
+        let rehydrated = alice
+            .dehydrated_devices()
+            .rehydrate(&[1; 32], &request.device_id, request.device_data)
+            .await;
+        assert!(rehydrated.is_err());
+    }
+
+    #[async_test]
+    async fn dehydrated_device_receives_room_keys() {
+        let alice = alice_machine().await;
+        let bob = OlmMachine::new(user_id!("@bob:localhost"), device_id!("BOBDEVICE")).await;
+        let room_id = room_id!("!test:localhost");
+
+        let dehydrated_device = alice.dehydrated_devices().create().await;
+        let request = dehydrated_device
+            .keys_for_upload("Dehydrated device".to_owned(), PICKLE_KEY)
+            .await
+            .unwrap();
+
+        // Bob learns about the dehydrated device and claims one of its
+        // one-time keys.
+        let device_keys: DeviceKeys = request.device_keys.deserialize_as().unwrap();
+        let device = ReadOnlyDevice::try_from(&device_keys).unwrap();
+        bob.store().save_devices(&[device]).await.unwrap();
+
+        let (key_id, one_time_key) = request.one_time_keys.iter().next().unwrap();
+        let one_time_keys = [(
+            alice.user_id().to_owned(),
+            [(request.device_id.clone(), [(key_id.clone(), one_time_key.clone())].into())].into(),
+        )]
+        .into();
+        let response = KeysClaimResponse::new(one_time_keys);
+        bob.mark_request_as_sent(&TransactionId::new(), &response).await.unwrap();
+
+        // Bob shares a room key with the dehydrated device.
+        let requests = bob
+            .share_room_key(room_id, iter::once(alice.user_id()), EncryptionSettings::default())
+            .await
+            .unwrap();
+        let content = requests[0]
+            .messages
+            .get(alice.user_id())
+            .unwrap()
+            .get(&request.device_id.clone().into())
+            .unwrap();
+        let event = json!({
+            "sender": bob.user_id(),
+            "type": "m.room.encrypted",
+            "content": content,
+        });
+
+        let rehydrated = alice
+            .dehydrated_devices()
+            .rehydrate(PICKLE_KEY, &request.device_id, request.device_data)
+            .await
+            .unwrap();
+        let room_keys =
+            rehydrated.receive_events(vec![serde_json::from_value(event).unwrap()]).await.unwrap();
+
+        assert_eq!(room_keys.len(), 1);
+        assert_eq!(room_keys[0].room_id, room_id);
+
+        // The room key was imported into the store of Alice's device.
+        let session = alice
+            .store()
+            .get_inbound_group_session(room_id, &room_keys[0].session_id)
+            .await
+            .unwrap();
+        assert!(session.is_some());
+    }
+}
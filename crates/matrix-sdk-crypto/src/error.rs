@@ -12,6 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
+
+use matrix_sdk_common::deserialized_responses::{UnableToDecryptReason, VerificationLevel};
 use ruma::{CanonicalJsonError, IdParseError, OwnedDeviceId, OwnedRoomId, OwnedUserId};
 use serde_json::Error as SerdeError;
 use thiserror::Error;
@@ -73,6 +76,49 @@ pub enum OlmError {
             have a valid Olm session with us"
     )]
     MissingSession,
+
+    /// The room key couldn't be shared because of the sharing strategy of the
+    /// room.
+    #[error(transparent)]
+    SessionRecipientCollectionError(#[from] SessionRecipientCollectionError),
+}
+
+/// Error representing a problem when collecting the recipients of a room key,
+/// which prevents the room key from being shared.
+///
+/// These errors are only raised by the
+/// [`CollectStrategy::ErrorOnIdentityChange`] strategy.
+///
+/// [`CollectStrategy::ErrorOnIdentityChange`]: crate::CollectStrategy::ErrorOnIdentityChange
+#[derive(Error, Debug)]
+pub enum SessionRecipientCollectionError {
+    /// Some verified users have devices that they didn't cross-sign.
+    ///
+    /// The devices need to be verified or blacklisted before the room key
+    /// can be shared.
+    #[error("one or more verified users have unsigned devices")]
+    VerifiedUserHasUnsignedDevice(BTreeMap<OwnedUserId, Vec<OwnedDeviceId>>),
+
+    /// Some users that were verified before have changed their identity.
+    ///
+    /// The identity change needs to be accepted with
+    /// [`UserIdentity::withdraw_verification()`], or the users need to be
+    /// verified again, before the room key can be shared.
+    ///
+    /// [`UserIdentity::withdraw_verification()`]: crate::UserIdentity::withdraw_verification
+    #[error("one or more previously verified users have changed their identity")]
+    VerifiedUserChangedIdentity(Vec<OwnedUserId>),
+
+    /// Some users have reset their identity, and the change wasn't
+    /// acknowledged.
+    ///
+    /// The identity changes need to be acknowledged with
+    /// [`UserIdentity::acknowledge_identity_change()`], or the users need to
+    /// be verified, before the room key can be shared.
+    ///
+    /// [`UserIdentity::acknowledge_identity_change()`]: crate::UserIdentity::acknowledge_identity_change
+    #[error("one or more users have changed their identity without it being acknowledged")]
+    UnacknowledgedIdentityChange(Vec<OwnedUserId>),
 }
 
 /// Error representing a failure during a group encryption operation.
@@ -108,6 +154,11 @@ pub enum MegolmError {
         device_curve25519: Option<Box<Curve25519PublicKey>>,
     },
 
+    /// The event was decrypted, but the device that sent it isn't verified,
+    /// and we only decrypt the events of verified devices.
+    #[error("the device that sent the event isn't verified: {0:?}")]
+    UnverifiedSenderDevice(VerificationLevel),
+
     /// The encrypted megolm message couldn't be decoded.
     #[error(transparent)]
     Decode(#[from] vodozemac::DecodeError),
@@ -121,6 +172,30 @@ pub enum MegolmError {
     Store(#[from] CryptoStoreError),
 }
 
+impl From<&MegolmError> for UnableToDecryptReason {
+    fn from(error: &MegolmError) -> Self {
+        use vodozemac::megolm::DecryptionError;
+
+        match error {
+            MegolmError::EventError(_) | MegolmError::JsonError(_) | MegolmError::Decode(_) => {
+                Self::MalformedEvent
+            }
+            MegolmError::MissingRoomKey(withheld_code) => {
+                Self::MissingMegolmSession { withheld_code: withheld_code.clone() }
+            }
+            MegolmError::MismatchedIdentityKeys { .. } => Self::MismatchedIdentityKeys,
+            MegolmError::UnverifiedSenderDevice(level) => {
+                Self::UnverifiedSenderDevice(level.clone())
+            }
+            MegolmError::Decryption(DecryptionError::UnknownMessageIndex(_, _)) => {
+                Self::UnknownMegolmMessageIndex
+            }
+            MegolmError::Decryption(_) => Self::MegolmDecryptionFailure,
+            MegolmError::Store(_) => Self::Unknown,
+        }
+    }
+}
+
 /// Error that occurs when decrypting an event that is malformed.
 #[derive(Error, Debug)]
 pub enum EventError {
@@ -279,6 +279,7 @@ mod tests {
         io::Cursor,
     };
 
+    use futures_util::{FutureExt, StreamExt};
     use indoc::indoc;
     use matrix_sdk_test::async_test;
     use ruma::room_id;
@@ -403,6 +404,37 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    async fn test_importing_notifies_room_keys_received_stream() -> OlmResult<()> {
+        let (machine, _) = get_prepared_machine(false).await;
+        let room_id = room_id!("!test:localhost");
+        let session = machine.create_inbound_session(room_id).await?;
+
+        let export = vec![session.export_at_index(10).await];
+
+        let (other_machine, _) = get_prepared_machine(false).await;
+        let mut room_keys_received_stream =
+            Box::pin(other_machine.store().room_keys_received_stream());
+
+        other_machine.import_room_keys(export.clone(), false, |_, _| {}).await?;
+
+        let room_keys = room_keys_received_stream
+            .next()
+            .now_or_never()
+            .flatten()
+            .expect("We should have received an update of room key infos");
+        assert_eq!(room_keys.len(), 1);
+        assert_eq!(room_keys[0].room_id, room_id);
+        assert_eq!(room_keys[0].session_id, session.session_id());
+
+        // Importing the same key again doesn't store anything, so no update is
+        // sent.
+        other_machine.import_room_keys(export, false, |_, _| {}).await?;
+        assert!(room_keys_received_stream.next().now_or_never().is_none());
+
+        Ok(())
+    }
+
     #[test]
     fn test_real_decrypt() {
         let reader = Cursor::new(TEST_EXPORT);
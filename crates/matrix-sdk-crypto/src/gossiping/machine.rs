@@ -47,7 +47,9 @@ use crate::{
     store::{Changes, CryptoStoreError, SecretImportError, Store},
     types::events::{
         forwarded_room_key::ForwardedRoomKeyContent,
-        olm_v1::{DecryptedForwardedRoomKeyEvent, DecryptedSecretSendEvent},
+        olm_v1::{
+            DecryptedForwardedRoomKeyEvent, DecryptedRoomKeyHistoryEvent, DecryptedSecretSendEvent,
+        },
         room::encrypted::EncryptedEvent,
         room_key_request::RoomKeyRequestEvent,
         secret_send::SecretSendContent,
@@ -733,6 +735,32 @@ impl GossipMachine {
         Ok(false)
     }
 
+    /// Re-send the key and secret requests that were already sent out, with a
+    /// new request ID.
+    ///
+    /// This should be called when a new Olm session was created to unwedge
+    /// one of our own devices, the answers it sent us over the wedged session
+    /// couldn't be decrypted. The old requests are cancelled.
+    pub async fn resend_sent_requests(&self) -> Result<(), CryptoStoreError> {
+        for request in self.inner.store.get_sent_secret_requests().await? {
+            trace!(
+                request_type = request.request_type(),
+                request_id = request.request_id.to_string().as_str(),
+                "Re-sending an outgoing secret request"
+            );
+
+            let cancel = request.to_cancellation(self.device_id());
+            self.inner.outgoing_requests.insert(cancel.request_id.clone(), cancel);
+            self.delete_key_info(&request).await?;
+
+            let request =
+                GossipRequest { request_id: TransactionId::new(), sent_out: false, ..request };
+            self.save_outgoing_key_info(request).await?;
+        }
+
+        Ok(())
+    }
+
     /// Save an outgoing key info.
     async fn save_outgoing_key_info(&self, info: GossipRequest) -> Result<(), CryptoStoreError> {
         let mut changes = Changes::default();
@@ -999,6 +1027,106 @@ impl GossipMachine {
         }
     }
 
+    /// Receive the room keys that were sent to us as the history of a room we
+    /// were invited to.
+    ///
+    /// The room keys are held back until we join the room, see
+    /// [`OlmMachine::import_room_key_history()`]. If we recently joined the
+    /// room without having received them, and the room keys were sent by the
+    /// user that invited us, they are returned so they can be imported right
+    /// away.
+    ///
+    /// [`OlmMachine::import_room_key_history()`]: crate::OlmMachine::import_room_key_history
+    pub(crate) async fn receive_room_key_history(
+        &self,
+        sender_key: Curve25519PublicKey,
+        event: &DecryptedRoomKeyHistoryEvent,
+    ) -> Result<Vec<InboundGroupSession>, CryptoStoreError> {
+        // Make sure the room keys were sent by a device of the user that claims
+        // to have sent them, they will only be imported if this user invited
+        // us.
+        if self.inner.store.get_device_from_curve_key(&event.sender, sender_key).await?.is_none() {
+            warn!(
+                sender = ?event.sender,
+                ?sender_key,
+                "Received a room key history from an unknown device, ignoring",
+            );
+            return Ok(Vec::new());
+        }
+
+        let room_id = &event.content.room_id;
+        let mut sessions = Vec::new();
+
+        for room_key in &event.content.room_keys {
+            if !room_key.shared_history() {
+                warn!(
+                    sender = ?event.sender,
+                    ?room_id,
+                    "Received a room key history containing a room key that isn't marked \
+                     as shareable, ignoring it",
+                );
+                continue;
+            }
+
+            match InboundGroupSession::try_from(room_key) {
+                Ok(session) if session.room_id() == room_id => sessions.push(session),
+                Ok(session) => {
+                    warn!(
+                        sender = ?event.sender,
+                        ?room_id,
+                        session_room_id = ?session.room_id(),
+                        "Received a room key history containing a room key of another room, \
+                         ignoring it",
+                    );
+                }
+                Err(e) => {
+                    warn!(
+                        ?sender_key,
+                        "Couldn't create a group session from a room key history: {e}"
+                    );
+                }
+            }
+        }
+
+        info!(
+            sender = ?event.sender,
+            ?sender_key,
+            ?room_id,
+            room_key_count = sessions.len(),
+            "Received the room key history of a room we were invited to",
+        );
+
+        if self.inner.store.take_room_key_history_inviter(room_id, &event.sender).await? {
+            // We already joined the room, import the room keys now.
+            let mut better_sessions = Vec::new();
+
+            for session in sessions {
+                if self.inner.store.compare_group_session(&session).await?
+                    == SessionOrdering::Better
+                {
+                    better_sessions.push(session);
+                } else {
+                    info!(
+                        session_id = session.session_id(),
+                        "We already have a better version of the room key, ignoring",
+                    );
+                }
+            }
+
+            Ok(better_sessions)
+        } else {
+            let mut room_keys = Vec::with_capacity(sessions.len());
+
+            for session in &sessions {
+                room_keys.push(session.export().await);
+            }
+
+            self.inner.store.save_pending_room_key_history(&event.sender, room_keys).await?;
+
+            Ok(Vec::new())
+        }
+    }
+
     /// Receive a forwarded room key event.
     pub async fn receive_forwarded_room_key(
         &self,
@@ -1306,6 +1434,38 @@ mod tests {
         assert!(cancel.is_some());
     }
 
+    #[async_test]
+    async fn resend_sent_requests() {
+        let machine = get_machine().await;
+        let account = account();
+
+        let (outbound, session) = account.create_group_session_pair_with_defaults(room_id()).await;
+
+        let content = outbound.encrypt(json!({}), "m.dummy").await;
+        let event = wrap_encrypted_content(machine.user_id(), content);
+
+        let (_, request) = machine.request_key(session.room_id(), &event).await.unwrap();
+        machine.mark_outgoing_request_as_sent(&request.request_id).await.unwrap();
+        assert!(machine.outgoing_to_device_requests().await.unwrap().is_empty());
+
+        machine.resend_sent_requests().await.unwrap();
+
+        // The old request is cancelled, and a new one is sent out.
+        let requests = machine.outgoing_to_device_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| r.request_id != request.request_id));
+        assert!(machine
+            .inner
+            .store
+            .get_outgoing_secret_requests(&request.request_id)
+            .await
+            .unwrap()
+            .is_none());
+
+        let (_, new_request) = machine.request_key(session.room_id(), &event).await.unwrap();
+        assert!(requests.iter().any(|r| r.request_id == new_request.request_id));
+    }
+
     #[async_test]
     #[cfg(feature = "automatic-room-key-forwarding")]
     async fn create_key_request() {
@@ -512,6 +512,30 @@ impl Device {
 
         self.encrypt(event_type, content).await
     }
+
+    /// Encrypt an arbitrary event for this device.
+    ///
+    /// The Olm session that was used for the encryption is persisted in the
+    /// store, the returned content can be sent as an `m.room.encrypted`
+    /// to-device event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the event that should be encrypted.
+    ///
+    /// * `content` - The content of the event that should be encrypted.
+    pub async fn encrypt_event_raw(
+        &self,
+        event_type: &str,
+        content: Value,
+    ) -> OlmResult<Raw<ToDeviceEncryptedEventContent>> {
+        let (session, encrypted) = self.encrypt(event_type, content).await?;
+
+        let changes = Changes { sessions: vec![session], ..Default::default() };
+        self.verification_machine.store.save_changes(changes).await?;
+
+        Ok(encrypted)
+    }
 }
 
 /// A read only view over all devices belonging to a user.
@@ -463,7 +463,16 @@ impl IdentityManager {
                 }
             }
             ReadOnlyUserIdentities::Other(mut identity) => {
+                let master_key_changed = identity.master_key() != &master_key;
                 identity.update(master_key, self_signing)?;
+
+                if master_key_changed && identity.has_pin_violation() {
+                    info!(
+                        user_id = ?identity.user_id(),
+                        "A user has reset their identity, the change needs to be acknowledged"
+                    );
+                }
+
                 Ok(IdentityChange { public: identity.into(), private: None })
             }
         }
@@ -617,9 +626,38 @@ impl IdentityManager {
             .await?;
         }
 
+        self.mark_verified_identities(&changes).await?;
+
         Ok((changes, changed_identity))
     }
 
+    /// Remember which of the new or changed identities are verified by us, and
+    /// pin their master key.
+    ///
+    /// This lets us notice later on that a verified user replaced their
+    /// identity, even though the new identity isn't verified anymore.
+    async fn mark_verified_identities(&self, changes: &IdentityChanges) -> StoreResult<()> {
+        let own_identity =
+            match changes.new.iter().chain(&changes.changed).find_map(|i| i.own()).cloned() {
+                Some(own_identity) => Some(own_identity),
+                None => {
+                    self.store.get_user_identity(self.user_id()).await?.and_then(|i| i.into_own())
+                }
+            };
+
+        if let Some(own_identity) = own_identity.filter(|i| i.is_verified()) {
+            for identity in changes.new.iter().chain(&changes.changed).filter_map(|i| i.other()) {
+                if own_identity.is_identity_signed(identity).is_ok() {
+                    identity.mark_as_previously_verified();
+                    // A verified identity doesn't need to be acknowledged.
+                    identity.pin_current_master_key();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Generate an "out-of-band" key query request for the given set of users.
     ///
     /// Unlike the regular key query requests returned by `users_for_key_query`,
@@ -17,7 +17,7 @@ use std::{
     ops::Deref,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, RwLock as StdRwLock,
     },
 };
 
@@ -28,7 +28,7 @@ use ruma::{
     },
     DeviceId, EventId, OwnedDeviceId, OwnedUserId, RoomId, UserId,
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tracing::error;
 
 use super::{atomic_bool_deserializer, atomic_bool_serializer};
@@ -203,6 +203,50 @@ impl UserIdentity {
         self.own_identity.as_ref().is_some_and(|o| o.is_identity_signed(&self.inner).is_ok())
     }
 
+    /// Has this user reset their identity since we last acknowledged it,
+    /// without being verified again?
+    ///
+    /// The change can be acknowledged with
+    /// [`UserIdentity::acknowledge_identity_change()`].
+    pub fn identity_needs_user_approval(&self) -> bool {
+        self.inner.has_pin_violation() && !self.is_verified()
+    }
+
+    /// Acknowledge that this user has reset their identity.
+    ///
+    /// The current master key of the user is pinned, and the user isn't
+    /// considered as previously verified anymore.
+    pub async fn acknowledge_identity_change(&self) -> Result<(), CryptoStoreError> {
+        self.inner.pin_current_master_key();
+        self.inner.withdraw_verification();
+
+        let changes = Changes {
+            identities: IdentityChanges { changed: vec![self.inner.clone().into()], new: vec![] },
+            ..Default::default()
+        };
+
+        self.verification_machine.store.save_changes(changes).await
+    }
+
+    /// Accept the identity change of a previously verified user.
+    ///
+    /// After this, sharing a room key with this user doesn't fail anymore
+    /// when the [`CollectStrategy::ErrorOnIdentityChange`] strategy is used,
+    /// unless the identity change also needs to be acknowledged with
+    /// [`UserIdentity::acknowledge_identity_change()`].
+    ///
+    /// [`CollectStrategy::ErrorOnIdentityChange`]: crate::olm::CollectStrategy::ErrorOnIdentityChange
+    pub async fn withdraw_verification(&self) -> Result<(), CryptoStoreError> {
+        self.inner.withdraw_verification();
+
+        let changes = Changes {
+            identities: IdentityChanges { changed: vec![self.inner.clone().into()], new: vec![] },
+            ..Default::default()
+        };
+
+        self.verification_machine.store.save_changes(changes).await
+    }
+
     /// Manually verify this user.
     ///
     /// This method will attempt to sign the user identity using our private
@@ -215,14 +259,32 @@ impl UserIdentity {
     /// as verified.
     pub async fn verify(&self) -> Result<SignatureUploadRequest, SignatureError> {
         if self.user_id() != self.verification_machine.own_user_id() {
-            Ok(self
+            let request = self
                 .verification_machine
                 .store
                 .private_identity
                 .lock()
                 .await
                 .sign_user(&self.inner)
-                .await?)
+                .await?;
+
+            // A verified identity doesn't need to be acknowledged.
+            self.inner.mark_as_previously_verified();
+            self.inner.pin_current_master_key();
+
+            let changes = Changes {
+                identities: IdentityChanges {
+                    changed: vec![self.inner.clone().into()],
+                    new: vec![],
+                },
+                ..Default::default()
+            };
+
+            if let Err(e) = self.verification_machine.store.save_changes(changes).await {
+                error!(error = ?e, "Couldn't store the user identity after marking it as verified");
+            }
+
+            Ok(request)
         } else {
             Err(SignatureError::UserIdMismatch)
         }
@@ -360,6 +422,45 @@ pub struct ReadOnlyUserIdentity {
     user_id: OwnedUserId,
     pub(crate) master_key: MasterPubkey,
     self_signing_key: SelfSigningPubkey,
+    /// Whether this identity has been verified by us at some point, this
+    /// stays true if the identity gets replaced by an unverified one.
+    #[serde(
+        default,
+        serialize_with = "atomic_bool_serializer",
+        deserialize_with = "atomic_bool_deserializer"
+    )]
+    previously_verified: Arc<AtomicBool>,
+    /// The master key that the user has approved, or that we saw first.
+    ///
+    /// A `None` value comes from an identity that was stored before master
+    /// keys were pinned, it's considered to be pinned to the current master
+    /// key.
+    #[serde(
+        default,
+        serialize_with = "pinned_master_key_serializer",
+        deserialize_with = "pinned_master_key_deserializer"
+    )]
+    pinned_master_key: Arc<StdRwLock<Option<MasterPubkey>>>,
+}
+
+fn pinned_master_key_serializer<S>(
+    x: &StdRwLock<Option<MasterPubkey>>,
+    s: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    x.read().unwrap().serialize(s)
+}
+
+fn pinned_master_key_deserializer<'de, D>(
+    deserializer: D,
+) -> Result<Arc<StdRwLock<Option<MasterPubkey>>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<MasterPubkey>::deserialize(deserializer)?;
+    Ok(Arc::new(StdRwLock::new(value)))
 }
 
 impl ReadOnlyUserIdentity {
@@ -379,7 +480,13 @@ impl ReadOnlyUserIdentity {
     ) -> Result<Self, SignatureError> {
         master_key.verify_subkey(&self_signing_key)?;
 
-        Ok(Self { user_id: master_key.user_id().into(), master_key, self_signing_key })
+        Ok(Self {
+            user_id: master_key.user_id().into(),
+            pinned_master_key: Arc::new(StdRwLock::new(Some(master_key.clone()))),
+            master_key,
+            self_signing_key,
+            previously_verified: Default::default(),
+        })
     }
 
     #[cfg(test)]
@@ -388,7 +495,13 @@ impl ReadOnlyUserIdentity {
         let self_signing_key =
             identity.self_signing_key.lock().await.as_ref().unwrap().public_key.clone();
 
-        Self { user_id: identity.user_id().into(), master_key, self_signing_key }
+        Self {
+            user_id: identity.user_id().into(),
+            pinned_master_key: Arc::new(StdRwLock::new(Some(master_key.clone()))),
+            master_key,
+            self_signing_key,
+            previously_verified: Default::default(),
+        }
     }
 
     /// Get the user id of this identity.
@@ -406,6 +519,45 @@ impl ReadOnlyUserIdentity {
         &self.self_signing_key
     }
 
+    /// Was this identity verified by us at some point?
+    ///
+    /// Unlike the verification state, this isn't reset if the user changes
+    /// their identity. It can be used to detect that a verified user has
+    /// replaced their cross-signing keys.
+    pub fn was_previously_verified(&self) -> bool {
+        self.previously_verified.load(Ordering::SeqCst)
+    }
+
+    /// Remember that this identity has been verified by us.
+    pub(crate) fn mark_as_previously_verified(&self) {
+        self.previously_verified.store(true, Ordering::SeqCst)
+    }
+
+    /// Forget that this identity has been verified by us.
+    pub(crate) fn withdraw_verification(&self) {
+        self.previously_verified.store(false, Ordering::SeqCst)
+    }
+
+    /// Get the master key that was approved by the user, or the first one we
+    /// saw for this identity.
+    pub fn pinned_master_key(&self) -> MasterPubkey {
+        self.pinned_master_key.read().unwrap().clone().unwrap_or_else(|| self.master_key.clone())
+    }
+
+    /// Has the master key of this identity changed since it was pinned?
+    ///
+    /// This means that the user has reset their identity, and that the change
+    /// hasn't been acknowledged yet.
+    pub fn has_pin_violation(&self) -> bool {
+        self.pinned_master_key.read().unwrap().as_ref().is_some_and(|k| *k != self.master_key)
+    }
+
+    /// Pin the current master key of this identity, acknowledging any
+    /// identity change.
+    pub(crate) fn pin_current_master_key(&self) {
+        *self.pinned_master_key.write().unwrap() = Some(self.master_key.clone());
+    }
+
     /// Update the identity with a new master key and self signing key.
     ///
     /// # Arguments
@@ -422,6 +574,10 @@ impl ReadOnlyUserIdentity {
     ) -> Result<(), SignatureError> {
         master_key.verify_subkey(&self_signing_key)?;
 
+        // Identities stored before master keys were pinned are pinned to the
+        // master key we knew, before it gets replaced.
+        self.pinned_master_key.write().unwrap().get_or_insert_with(|| self.master_key.clone());
+
         self.master_key = master_key;
         self.self_signing_key = self_signing_key;
 
@@ -717,7 +873,7 @@ pub(crate) mod tests {
 
     use super::{
         testing::{device, get_other_identity, get_own_identity},
-        ReadOnlyOwnUserIdentity, ReadOnlyUserIdentities,
+        ReadOnlyOwnUserIdentity, ReadOnlyUserIdentities, ReadOnlyUserIdentity,
     };
     use crate::{
         identities::{manager::testing::own_key_query, Device},
@@ -752,6 +908,60 @@ pub(crate) mod tests {
         get_other_identity();
     }
 
+    #[test]
+    fn other_identity_previously_verified_serialization() {
+        let identity = get_other_identity();
+        assert!(!identity.was_previously_verified());
+
+        // Identities stored before the flag existed are not previously verified.
+        let mut value = serde_json::to_value(&identity).unwrap();
+        value.as_object_mut().unwrap().remove("previously_verified");
+        let identity: ReadOnlyUserIdentity = serde_json::from_value(value).unwrap();
+        assert!(!identity.was_previously_verified());
+
+        identity.mark_as_previously_verified();
+        let value = serde_json::to_value(&identity).unwrap();
+        let identity: ReadOnlyUserIdentity = serde_json::from_value(value).unwrap();
+        assert!(identity.was_previously_verified());
+
+        identity.withdraw_verification();
+        assert!(!identity.was_previously_verified());
+    }
+
+    #[test]
+    fn other_identity_master_key_pinning() {
+        let own_identity = get_own_identity();
+        let mut identity = get_other_identity();
+        assert!(!identity.has_pin_violation());
+
+        // Identities stored before pinning existed are pinned to their current key.
+        let mut value = serde_json::to_value(&identity).unwrap();
+        value.as_object_mut().unwrap().remove("pinned_master_key");
+        let mut legacy: ReadOnlyUserIdentity = serde_json::from_value(value).unwrap();
+        assert!(!legacy.has_pin_violation());
+        assert_eq!(legacy.pinned_master_key(), *identity.master_key());
+
+        let old_master_key = identity.master_key().clone();
+
+        for identity in [&mut identity, &mut legacy] {
+            identity
+                .update(own_identity.master_key().clone(), own_identity.self_signing_key().clone())
+                .unwrap();
+
+            assert!(identity.has_pin_violation());
+            assert_eq!(identity.pinned_master_key(), old_master_key);
+
+            // The pin survives a serialization round trip.
+            let value = serde_json::to_value(&*identity).unwrap();
+            let deserialized: ReadOnlyUserIdentity = serde_json::from_value(value).unwrap();
+            assert!(deserialized.has_pin_violation());
+
+            identity.pin_current_master_key();
+            assert!(!identity.has_pin_violation());
+            assert_eq!(identity.pinned_master_key(), *own_identity.master_key());
+        }
+    }
+
     #[test]
     fn own_identity_check_signatures() {
         let response = own_key_query();
@@ -14,7 +14,10 @@
 
 use std::{
     collections::{BTreeMap, BTreeSet, HashSet},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
@@ -35,9 +38,11 @@ use ruma::{
     },
     assign,
     events::{
-        secret::request::SecretName, AnyMessageLikeEvent, AnyToDeviceEvent, MessageLikeEventContent,
+        secret::request::SecretName, AnyMessageLikeEvent, AnyToDeviceEvent,
+        MessageLikeEventContent, ToDeviceEventType,
     },
     serde::Raw,
+    to_device::DeviceIdOrAllDevices,
     DeviceId, DeviceKeyAlgorithm, OwnedDeviceId, OwnedDeviceKeyId, OwnedTransactionId, OwnedUserId,
     RoomId, TransactionId, UInt, UserId,
 };
@@ -56,6 +61,7 @@ use vodozemac::{
 #[cfg(feature = "backups_v1")]
 use crate::backups::BackupMachine;
 use crate::{
+    dehydrated_devices::DehydratedDevices,
     error::{EventError, MegolmError, MegolmResult, OlmError, OlmResult},
     gossiping::GossipMachine,
     identities::{user::UserIdentities, Device, IdentityManager, UserDevices},
@@ -65,23 +71,25 @@ use crate::{
         SessionType,
     },
     requests::{IncomingResponse, OutgoingRequest, UploadSigningKeysRequest},
-    session_manager::{GroupSessionManager, SessionManager},
+    session_manager::{GroupSessionManager, SessionManager, WedgedDevicesStats},
     store::{
         locks::LockStoreError, Changes, DeviceChanges, DynCryptoStore, IdentityChanges,
         IntoCryptoStore, MemoryStore, Result as StoreResult, RoomKeyInfo, SecretImportError, Store,
     },
     types::{
         events::{
+            forwarded_room_key::ForwardedRoomKeyContent,
             olm_v1::{AnyDecryptedOlmEvent, DecryptedRoomKeyEvent},
             room::encrypted::{
                 EncryptedEvent, EncryptedToDeviceEvent, RoomEncryptedEventContent,
                 RoomEventEncryptionScheme, SupportedEventEncryptionSchemes,
             },
             room_key::{MegolmV1AesSha2Content, RoomKeyContent},
+            room_key_history::RoomKeyHistoryContent,
             room_key_withheld::{
                 MegolmV1AesSha2WithheldContent, RoomKeyWithheldContent, RoomKeyWithheldEvent,
             },
-            ToDeviceEvents,
+            EventType, ToDeviceEvents,
         },
         Signatures,
     },
@@ -141,6 +149,9 @@ pub struct OlmMachineInner {
     /// memory, when reading from the store indicates that somebody else has
     /// written into the database under our feet.
     pub(crate) crypto_store_generation: Arc<Mutex<Option<u64>>>,
+    /// Whether we refuse to decrypt the room events sent by devices that
+    /// aren't verified.
+    only_decrypt_from_verified_devices: AtomicBool,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -172,7 +183,7 @@ impl OlmMachine {
             .expect("Reading and writing to the memory store always succeeds")
     }
 
-    fn new_helper(
+    pub(crate) fn new_helper(
         user_id: &UserId,
         device_id: &DeviceId,
         store: Arc<DynCryptoStore>,
@@ -227,6 +238,7 @@ impl OlmMachine {
             #[cfg(feature = "backups_v1")]
             backup_machine,
             crypto_store_generation: Arc::new(Mutex::new(None)),
+            only_decrypt_from_verified_devices: AtomicBool::new(false),
         });
 
         Self { inner }
@@ -367,6 +379,21 @@ impl OlmMachine {
         self.inner.key_request_machine.is_room_key_forwarding_enabled()
     }
 
+    /// Refuse, or not, to decrypt the room events sent by devices that aren't
+    /// verified.
+    ///
+    /// When enabled, decrypting such an event fails with
+    /// [`MegolmError::UnverifiedSenderDevice`]. Disabled by default.
+    pub fn set_only_decrypt_from_verified_devices(&self, enable: bool) {
+        self.inner.only_decrypt_from_verified_devices.store(enable, Ordering::SeqCst)
+    }
+
+    /// Do we refuse to decrypt the room events sent by devices that aren't
+    /// verified?
+    pub fn only_decrypt_from_verified_devices(&self) -> bool {
+        self.inner.only_decrypt_from_verified_devices.load(Ordering::SeqCst)
+    }
+
     /// Get the outgoing requests that need to be sent out.
     ///
     /// This returns a list of [`OutgoingRequest`]. Those requests need to be
@@ -398,6 +425,7 @@ impl OlmMachine {
 
         requests.append(&mut self.inner.verification_machine.outgoing_messages());
         requests.append(&mut self.inner.key_request_machine.outgoing_to_device_requests().await?);
+        requests.append(&mut self.inner.session_manager.outgoing_to_device_requests());
 
         Ok(requests)
     }
@@ -597,6 +625,17 @@ impl OlmMachine {
         self.inner.session_manager.receive_keys_claim_response(response).await
     }
 
+    /// Get statistics about the devices we had a wedged Olm session with,
+    /// grouped by user.
+    ///
+    /// Whether a new Olm session is created for a wedged device is decided by
+    /// the [`UnwedgingPolicy`], see [`Store::set_unwedging_policy()`].
+    ///
+    /// [`UnwedgingPolicy`]: crate::UnwedgingPolicy
+    pub fn wedged_devices_stats(&self) -> BTreeMap<OwnedUserId, WedgedDevicesStats> {
+        self.inner.session_manager.wedged_devices_stats()
+    }
+
     /// Receive a successful keys query response.
     ///
     /// Returns a list of devices newly discovered devices and devices that
@@ -678,7 +717,8 @@ impl OlmMachine {
             &content.session_key,
             event.content.algorithm(),
             None,
-        );
+        )
+        .map(|session| session.with_shared_history(content.shared_history));
 
         match session {
             Ok(session) => {
@@ -856,6 +896,163 @@ impl OlmMachine {
         self.inner.group_session_manager.share_room_key(room_id, users, encryption_settings).await
     }
 
+    /// Get to-device requests to share the history of a room with a user that
+    /// was invited to it, as defined in [MSC3061].
+    ///
+    /// Only the room keys that may be shared with newly invited users are
+    /// sent, see [`InboundGroupSession::shared_history()`]. They are all sent
+    /// in a single `org.matrix.msc3061.room_key_history` event to every device
+    /// of the user that would receive a room key according to the given
+    /// settings, and that we have an Olm session with, so
+    /// [`OlmMachine::get_missing_sessions()`] should be used beforehand.
+    ///
+    /// The receiving side needs to call
+    /// [`OlmMachine::import_room_key_history()`] once the invite is accepted.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The id of the room whose history should be shared.
+    ///
+    /// `user_id` - The user that was invited to the room.
+    ///
+    /// `encryption_settings` - The encryption settings of the room, deciding
+    /// which devices of the user may receive its room keys.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    #[instrument(skip(self, encryption_settings))]
+    pub async fn share_room_key_history(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        encryption_settings: impl Into<EncryptionSettings>,
+    ) -> OlmResult<Vec<Arc<ToDeviceRequest>>> {
+        let sessions: Vec<_> = self
+            .store()
+            .get_inbound_group_sessions_for_room(room_id)
+            .await?
+            .into_iter()
+            .filter(|s| s.shared_history())
+            .collect();
+
+        if sessions.is_empty() {
+            debug!("No room keys to share with the invited user");
+            return Ok(Vec::new());
+        }
+
+        // The devices are chosen like for the room keys shared with the
+        // members of the room.
+        let (devices, _) = self
+            .inner
+            .group_session_manager
+            .collect_recipient_devices(std::iter::once(user_id), &encryption_settings.into())
+            .await?;
+
+        let mut room_keys = Vec::with_capacity(sessions.len());
+        for session in &sessions {
+            room_keys.push(ForwardedRoomKeyContent::try_from(session.export().await)?);
+        }
+        let content =
+            serde_json::to_value(RoomKeyHistoryContent::new(room_id.to_owned(), room_keys))?;
+
+        let mut messages = BTreeMap::new();
+        let mut changed_sessions = Vec::new();
+
+        for device in devices.into_values().flatten() {
+            match device.encrypt(RoomKeyHistoryContent::EVENT_TYPE, content.clone()).await {
+                Ok((used_session, encrypted)) => {
+                    changed_sessions.push(used_session);
+
+                    messages
+                        .entry(device.user_id().to_owned())
+                        .or_insert_with(BTreeMap::new)
+                        .insert(
+                            DeviceIdOrAllDevices::DeviceId(device.device_id().to_owned()),
+                            encrypted.cast(),
+                        );
+                }
+                Err(OlmError::MissingSession) => {
+                    warn!(
+                        device_id = ?device.device_id(),
+                        "Can't share the room key history with a device we don't have an \
+                         Olm session with",
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.store().save_sessions(&changed_sessions).await?;
+
+        if messages.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        info!(
+            room_key_count = sessions.len(),
+            device_count = changed_sessions.len(),
+            "Sharing the room key history with an invited user"
+        );
+
+        Ok(vec![Arc::new(ToDeviceRequest {
+            event_type: ToDeviceEventType::RoomEncrypted,
+            txn_id: TransactionId::new(),
+            messages,
+        })])
+    }
+
+    /// Import the room keys that were shared with us as the history of a room
+    /// we were invited to, as defined in [MSC3061].
+    ///
+    /// Room keys that are shared with us as room history, see
+    /// [`OlmMachine::share_room_key_history()`], are held back until the
+    /// invite is accepted. This method should be called at that point, only
+    /// the keys sent by the user that invited us are imported. The keys
+    /// other users sent for this room are discarded.
+    ///
+    /// If the room key history didn't arrive yet, the first one the user that
+    /// invited us shares with us for this room in the next 10 minutes is
+    /// imported right away, unless
+    /// [`OlmMachine::discard_room_key_history()`] is called.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The id of the room that we joined.
+    ///
+    /// `inviter` - The user that invited us to the room.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    #[instrument(skip(self))]
+    pub async fn import_room_key_history(
+        &self,
+        room_id: &RoomId,
+        inviter: &UserId,
+    ) -> StoreResult<RoomKeyImportResult> {
+        let room_keys = self.store().take_pending_room_key_history(room_id, inviter).await?;
+
+        if room_keys.is_empty() {
+            self.store().save_room_key_history_inviter(room_id, inviter).await?;
+        }
+
+        self.import_room_keys(room_keys, false, |_, _| {}).await
+    }
+
+    /// Discard the room keys that were shared with us as the history of a
+    /// room, as defined in [MSC3061].
+    ///
+    /// This should be called when we reject the invite to the room, or leave
+    /// it, so the room keys that are held back are dropped and new ones
+    /// aren't accepted anymore.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The id of the room that we didn't join, or left.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    #[instrument(skip(self))]
+    pub async fn discard_room_key_history(&self, room_id: &RoomId) -> StoreResult<()> {
+        self.store().discard_room_key_history(room_id).await
+    }
+
     /// Receive an unencrypted verification event.
     ///
     /// This method can be used to pass verification events that are happening
@@ -910,6 +1107,13 @@ impl OlmMachine {
                     .await?;
                 decrypted.inbound_group_session = session;
             }
+            AnyDecryptedOlmEvent::RoomKeyHistory(e) => {
+                decrypted.room_key_history = self
+                    .inner
+                    .key_request_machine
+                    .receive_room_key_history(decrypted.result.sender_key, e)
+                    .await?;
+            }
             AnyDecryptedOlmEvent::SecretSend(e) => {
                 let name = self
                     .inner
@@ -1087,6 +1291,8 @@ impl OlmMachine {
                     changes.inbound_group_sessions.push(group_session);
                 }
 
+                changes.inbound_group_sessions.extend(decrypted.room_key_history);
+
                 match decrypted.result.raw_event.deserialize_as() {
                     Ok(event) => {
                         self.handle_to_device_event(changes, &event).await;
@@ -1317,10 +1523,20 @@ impl OlmMachine {
             match result {
                 Ok((decrypted_event, _)) => {
                     let encryption_info = self.get_encryption_info(&session, &event.sender).await?;
+
+                    if self.only_decrypt_from_verified_devices() {
+                        if let VerificationState::Unverified(level) =
+                            &encryption_info.verification_state
+                        {
+                            return Err(MegolmError::UnverifiedSenderDevice(level.clone()));
+                        }
+                    }
+
                     Ok(TimelineEvent {
                         encryption_info: Some(encryption_info),
                         event: decrypted_event,
                         push_actions: Vec::default(),
+                        unable_to_decrypt_reason: None,
                     })
                 }
                 Err(error) => Err(
@@ -1777,6 +1993,11 @@ impl OlmMachine {
         &self.inner.backup_machine
     }
 
+    /// Get the manager of the dehydrated devices of our own user.
+    pub fn dehydrated_devices(&self) -> DehydratedDevices {
+        DehydratedDevices { inner: self.clone() }
+    }
+
     /// Syncs the database and in-memory generation counter.
     ///
     /// This requires that the crypto store lock has been acquired already.
@@ -1814,7 +2035,9 @@ impl OlmMachine {
     ///
     /// Returns true whether another user has modified the internal generation
     /// counter, and as such we've incremented and updated it in the
-    /// database.
+    /// database. In that case, the caches of the underlying store have been
+    /// cleared, but the caller must still recreate the `OlmMachine`, so that
+    /// the in-memory account and identities are reloaded from the store.
     ///
     /// ## Requirements
     ///
@@ -1874,6 +2097,10 @@ impl OlmMachine {
             )
             .await?;
 
+        // Somebody else wrote into the store under our feet, so the in-memory caches
+        // of the store (e.g. the Olm sessions) can't be trusted anymore.
+        self.inner.store.clear_caches().await;
+
         Ok(true)
     }
 
@@ -1908,7 +2135,7 @@ pub(crate) mod tests {
     use assert_matches::assert_matches;
     use futures_util::{FutureExt, StreamExt};
     use matrix_sdk_common::deserialized_responses::{
-        DeviceLinkProblem, ShieldState, VerificationLevel, VerificationState,
+        DeviceLinkProblem, ShieldState, UnableToDecryptReason, VerificationLevel, VerificationState,
     };
     use matrix_sdk_test::{async_test, test_json};
     use ruma::{
@@ -1927,7 +2154,10 @@ pub(crate) mod tests {
         events::{
             dummy::ToDeviceDummyEventContent,
             key::verification::VerificationMethod,
-            room::message::{MessageType, RoomMessageEventContent},
+            room::{
+                history_visibility::HistoryVisibility,
+                message::{MessageType, RoomMessageEventContent},
+            },
             AnyMessageLikeEvent, AnyMessageLikeEventContent, AnyTimelineEvent, AnyToDeviceEvent,
             MessageLikeEvent, OriginalMessageLikeEvent,
         },
@@ -1946,7 +2176,9 @@ pub(crate) mod tests {
     use crate::{
         error::EventError,
         machine::OlmMachine,
-        olm::{InboundGroupSession, OutboundGroupSession, VerifyJson},
+        olm::{
+            CollectStrategy, InboundGroupSession, OutboundGroupSession, SessionType, VerifyJson,
+        },
         types::{
             events::{
                 room::encrypted::{EncryptedToDeviceEvent, ToDeviceEncryptedEventContent},
@@ -1957,8 +2189,8 @@ pub(crate) mod tests {
         },
         utilities::json_convert,
         verification::tests::{outgoing_request_to_event, request_to_event},
-        EncryptionSettings, LocalTrust, MegolmError, OlmError, ReadOnlyDevice, ToDeviceRequest,
-        UserIdentities,
+        EncryptionSettings, LocalTrust, MegolmError, OlmError, OutgoingRequests, ReadOnlyDevice,
+        ToDeviceRequest, UnwedgingPolicy, UserIdentities,
     };
 
     /// These keys need to be periodically uploaded to the server.
@@ -2446,6 +2678,92 @@ pub(crate) mod tests {
         olm_encryption_test(true).await;
     }
 
+    #[async_test]
+    async fn test_session_unwedging() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+
+        let policy = UnwedgingPolicy { enabled: true, min_interval: Duration::ZERO };
+        alice.store().set_unwedging_policy(policy).await.unwrap();
+
+        // Bob sends a message to Alice that none of her Olm sessions can decrypt,
+        // as if their session got corrupted.
+        let alice_device =
+            bob.get_device(alice.user_id(), alice.device_id(), None).await.unwrap().unwrap();
+        let (_, content) = alice_device
+            .encrypt("m.dummy", serde_json::to_value(ToDeviceDummyEventContent::new()).unwrap())
+            .await
+            .unwrap();
+
+        let mut content = serde_json::to_value(content).unwrap();
+        let message = &mut content["ciphertext"][alice.identity_keys().curve25519.to_base64()];
+        assert_eq!(message["type"], 1, "Bob should send a normal Olm message");
+
+        // Flip a character of the MAC at the end of the message.
+        let mut body = message["body"].as_str().unwrap().to_owned();
+        let position = body.len() - 5;
+        let replacement = if &body[position..=position] == "A" { "B" } else { "A" };
+        body.replace_range(position..=position, replacement);
+        message["body"] = body.into();
+
+        let content: ToDeviceEncryptedEventContent = serde_json::from_value(content).unwrap();
+        let event = json_convert(&ToDeviceEvent::new(bob.user_id().to_owned(), content)).unwrap();
+
+        alice
+            .receive_sync_changes(vec![event], &Default::default(), &Default::default(), None)
+            .await
+            .unwrap();
+
+        let stats = alice.wedged_devices_stats().remove(bob.user_id()).unwrap();
+        assert!(stats.pending.contains(bob.device_id()));
+        assert_eq!(stats.wedged, 1);
+        assert_eq!(stats.unwedged, 0);
+
+        // Alice claims a new one-time key of Bob's device, even though she already
+        // shares a session with it.
+        let (_, request) = alice.get_missing_sessions(iter::empty()).await.unwrap().unwrap();
+        assert!(request.one_time_keys[bob.user_id()].contains_key(bob.device_id()));
+
+        bob.account().generate_one_time_keys_helper(1).await;
+        let (key_id, one_time_key) =
+            bob.account().signed_one_time_keys().await.into_iter().next().unwrap();
+        bob.account().mark_keys_as_published().await;
+
+        let bob_keys = BTreeMap::from([(key_id, one_time_key)]);
+        let one_time_keys = BTreeMap::from([(
+            bob.user_id().to_owned(),
+            BTreeMap::from([(bob.device_id().to_owned(), bob_keys)]),
+        )]);
+        alice
+            .receive_keys_claim_response(&claim_keys::v3::Response::new(one_time_keys))
+            .await
+            .unwrap();
+
+        let stats = alice.wedged_devices_stats().remove(bob.user_id()).unwrap();
+        assert!(stats.pending.is_empty());
+        assert_eq!(stats.unwedged, 1);
+        assert_eq!(stats.skipped, 0);
+
+        // Alice sends an `m.dummy` event over the new session, which lets Bob create
+        // the session as well.
+        let to_device_request = alice
+            .outgoing_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .find_map(|r| match r.request() {
+                OutgoingRequests::ToDeviceRequest(r) => Some(r.clone()),
+                _ => None,
+            })
+            .expect("Alice should send an m.dummy event to Bob");
+
+        let content = to_device_requests_to_content(vec![Arc::new(to_device_request)]);
+        let event = ToDeviceEvent::new(alice.user_id().to_owned(), content);
+        let decrypted = bob.decrypt_to_device_event(&event).await.unwrap();
+
+        assert_matches!(decrypted.session, SessionType::New(_));
+        assert_matches!(decrypted.result.raw_event.deserialize(), Ok(AnyToDeviceEvent::Dummy(_)));
+    }
+
     #[async_test]
     async fn test_room_key_sharing() {
         let (alice, bob) = get_machine_pair_with_session(false).await;
@@ -2565,6 +2883,255 @@ pub(crate) mod tests {
         }
     }
 
+    #[async_test]
+    async fn test_room_key_history_sharing() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+        let room_id = room_id!("!test:example.org");
+        let private_room_id = room_id!("!private:example.org");
+
+        let settings = EncryptionSettings {
+            history_visibility: HistoryVisibility::Shared,
+            ..Default::default()
+        };
+        alice.share_room_key(room_id, iter::empty(), settings).await.unwrap();
+
+        let settings = EncryptionSettings {
+            history_visibility: HistoryVisibility::Joined,
+            ..Default::default()
+        };
+        alice.share_room_key(private_room_id, iter::empty(), settings).await.unwrap();
+
+        // The room keys created while the history was restricted to the joined
+        // members aren't shared.
+        let requests = alice
+            .share_room_key_history(private_room_id, bob.user_id(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        assert!(requests.is_empty());
+
+        let requests = alice
+            .share_room_key_history(room_id, bob.user_id(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        assert_eq!(requests.len(), 1);
+
+        let event =
+            ToDeviceEvent::new(alice.user_id().to_owned(), to_device_requests_to_content(requests));
+        let decrypted = bob.decrypt_to_device_event(&event).await.unwrap();
+
+        // The room key is held back until the invite is accepted.
+        assert!(decrypted.room_key_history.is_empty());
+        assert!(bob.store().get_inbound_group_sessions().await.unwrap().is_empty());
+
+        let result = bob.import_room_key_history(room_id, alice.user_id()).await.unwrap();
+        assert_eq!(result.imported_count, 1);
+
+        let sessions = bob.store().get_inbound_group_sessions().await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert!(sessions[0].shared_history());
+        assert!(sessions[0].has_been_imported());
+
+        // The room key history can only be imported once.
+        let result = bob.import_room_key_history(room_id, alice.user_id()).await.unwrap();
+        assert_eq!(result.imported_count, 0);
+    }
+
+    #[async_test]
+    async fn test_room_key_history_from_another_user_is_discarded() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+        let room_id = room_id!("!test:example.org");
+
+        let settings = EncryptionSettings {
+            history_visibility: HistoryVisibility::WorldReadable,
+            ..Default::default()
+        };
+        alice.share_room_key(room_id, iter::empty(), settings).await.unwrap();
+
+        let requests = alice
+            .share_room_key_history(room_id, bob.user_id(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        let event =
+            ToDeviceEvent::new(alice.user_id().to_owned(), to_device_requests_to_content(requests));
+        bob.decrypt_to_device_event(&event).await.unwrap();
+
+        // Bob was invited by someone else, the room key Alice sent is dropped.
+        let result =
+            bob.import_room_key_history(room_id, user_id!("@carol:example.org")).await.unwrap();
+        assert_eq!(result.imported_count, 0);
+
+        let result = bob.import_room_key_history(room_id, alice.user_id()).await.unwrap();
+        assert_eq!(result.imported_count, 0);
+    }
+
+    #[async_test]
+    async fn test_room_key_history_is_imported_once_joined() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+        let room_id = room_id!("!test:example.org");
+
+        let settings = EncryptionSettings {
+            history_visibility: HistoryVisibility::Shared,
+            ..Default::default()
+        };
+        alice.share_room_key(room_id, iter::empty(), settings).await.unwrap();
+
+        // Bob already joined the room when Alice shares the history with him.
+        let result = bob.import_room_key_history(room_id, alice.user_id()).await.unwrap();
+        assert_eq!(result.imported_count, 0);
+
+        let requests = alice
+            .share_room_key_history(room_id, bob.user_id(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        let event =
+            ToDeviceEvent::new(alice.user_id().to_owned(), to_device_requests_to_content(requests));
+        let decrypted = bob.decrypt_to_device_event(&event).await.unwrap();
+        assert_eq!(decrypted.room_key_history.len(), 1);
+
+        // The room key history is only accepted once.
+        let requests = alice
+            .share_room_key_history(room_id, bob.user_id(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        let event =
+            ToDeviceEvent::new(alice.user_id().to_owned(), to_device_requests_to_content(requests));
+        let decrypted = bob.decrypt_to_device_event(&event).await.unwrap();
+        assert!(decrypted.room_key_history.is_empty());
+
+        // Once Bob leaves the room, the room keys aren't accepted anymore.
+        bob.import_room_key_history(room_id, alice.user_id()).await.unwrap();
+        bob.discard_room_key_history(room_id).await.unwrap();
+
+        let requests = alice
+            .share_room_key_history(room_id, bob.user_id(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        let event =
+            ToDeviceEvent::new(alice.user_id().to_owned(), to_device_requests_to_content(requests));
+        let decrypted = bob.decrypt_to_device_event(&event).await.unwrap();
+        assert!(decrypted.room_key_history.is_empty());
+    }
+
+    #[async_test]
+    async fn test_room_key_history_is_not_accepted_long_after_joining() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+        let room_id = room_id!("!test:example.org");
+
+        let settings = EncryptionSettings {
+            history_visibility: HistoryVisibility::Shared,
+            ..Default::default()
+        };
+        alice.share_room_key(room_id, iter::empty(), settings).await.unwrap();
+
+        // Bob joined the room a long time ago.
+        bob.store()
+            .set_value(
+                "room_key_history_inviters",
+                &json!({ room_id.as_str(): { "inviter": alice.user_id(), "joined_at": 0 } }),
+            )
+            .await
+            .unwrap();
+
+        let requests = alice
+            .share_room_key_history(room_id, bob.user_id(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        let event =
+            ToDeviceEvent::new(alice.user_id().to_owned(), to_device_requests_to_content(requests));
+        let decrypted = bob.decrypt_to_device_event(&event).await.unwrap();
+        assert!(decrypted.room_key_history.is_empty());
+    }
+
+    #[async_test]
+    async fn test_room_key_history_is_discarded_when_rejecting_invite() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+        let room_id = room_id!("!test:example.org");
+
+        let settings = EncryptionSettings {
+            history_visibility: HistoryVisibility::Shared,
+            ..Default::default()
+        };
+        alice.share_room_key(room_id, iter::empty(), settings).await.unwrap();
+
+        let requests = alice
+            .share_room_key_history(room_id, bob.user_id(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        let event =
+            ToDeviceEvent::new(alice.user_id().to_owned(), to_device_requests_to_content(requests));
+        bob.decrypt_to_device_event(&event).await.unwrap();
+
+        bob.discard_room_key_history(room_id).await.unwrap();
+
+        let result = bob.import_room_key_history(room_id, alice.user_id()).await.unwrap();
+        assert_eq!(result.imported_count, 0);
+    }
+
+    #[async_test]
+    async fn test_room_key_history_is_shared_in_a_single_request() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+        let room_id = room_id!("!test:example.org");
+
+        let settings = EncryptionSettings {
+            history_visibility: HistoryVisibility::Shared,
+            ..Default::default()
+        };
+        alice.share_room_key(room_id, iter::empty(), settings.clone()).await.unwrap();
+        alice.invalidate_group_session(room_id).await.unwrap();
+        alice.share_room_key(room_id, iter::empty(), settings).await.unwrap();
+
+        let requests = alice
+            .share_room_key_history(room_id, bob.user_id(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].message_count(), 1);
+
+        let event =
+            ToDeviceEvent::new(alice.user_id().to_owned(), to_device_requests_to_content(requests));
+        bob.decrypt_to_device_event(&event).await.unwrap();
+
+        let result = bob.import_room_key_history(room_id, alice.user_id()).await.unwrap();
+        assert_eq!(result.imported_count, 2);
+    }
+
+    #[async_test]
+    async fn test_room_key_history_follows_the_sharing_strategy() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+        let room_id = room_id!("!test:example.org");
+
+        let settings = EncryptionSettings {
+            history_visibility: HistoryVisibility::Shared,
+            ..Default::default()
+        };
+        alice.share_room_key(room_id, iter::empty(), settings).await.unwrap();
+
+        // Bob's device isn't verified, so it doesn't get the room key history
+        // when only verified devices receive room keys.
+        let settings = EncryptionSettings {
+            sharing_strategy: CollectStrategy::OnlyVerifiedUsers,
+            ..Default::default()
+        };
+        let requests =
+            alice.share_room_key_history(room_id, bob.user_id(), settings).await.unwrap();
+        assert!(requests.is_empty());
+
+        // Blacklisted devices never get it.
+        alice
+            .get_device(bob.user_id(), bob.device_id(), None)
+            .await
+            .unwrap()
+            .unwrap()
+            .set_local_trust(LocalTrust::BlackListed)
+            .await
+            .unwrap();
+        let requests = alice
+            .share_room_key_history(room_id, bob.user_id(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        assert!(requests.is_empty());
+    }
+
     #[async_test]
     async fn test_withheld_unverified() {
         let (alice, bob) = get_machine_pair_with_setup_sessions().await;
@@ -2625,6 +3192,58 @@ pub(crate) mod tests {
 
         let err = decrypt_result.err().unwrap();
         assert_matches!(err, MegolmError::MissingRoomKey(Some(WithheldCode::Unverified)));
+
+        let reason = UnableToDecryptReason::from(&err);
+        assert_eq!(reason.withheld_code(), Some(&WithheldCode::Unverified));
+        assert!(!reason.is_missing_room_key());
+    }
+
+    #[async_test]
+    async fn test_only_decrypt_from_verified_devices() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+        let room_id = room_id!("!test:example.org");
+
+        let to_device_requests = alice
+            .share_room_key(room_id, iter::once(bob.user_id()), EncryptionSettings::default())
+            .await
+            .unwrap();
+        let event = ToDeviceEvent::new(
+            alice.user_id().to_owned(),
+            to_device_requests_to_content(to_device_requests),
+        );
+        let group_session =
+            bob.decrypt_to_device_event(&event).await.unwrap().inbound_group_session;
+        bob.store().save_inbound_group_sessions(&[group_session.unwrap()]).await.unwrap();
+
+        let content = RoomMessageEventContent::text_plain("It is a secret to everybody");
+        let encrypted_content = alice
+            .encrypt_room_event(room_id, AnyMessageLikeEventContent::RoomMessage(content))
+            .await
+            .unwrap();
+        let event = json!({
+            "event_id": "$xxxxx:example.org",
+            "origin_server_ts": MilliSecondsSinceUnixEpoch::now(),
+            "sender": alice.user_id(),
+            "type": "m.room.encrypted",
+            "content": encrypted_content,
+        });
+        let event = json_convert(&event).unwrap();
+
+        assert!(!bob.only_decrypt_from_verified_devices());
+        bob.decrypt_room_event(&event, room_id).await.unwrap();
+
+        // Alice's device isn't verified, Bob refuses to decrypt her events now.
+        bob.set_only_decrypt_from_verified_devices(true);
+        let err = bob.decrypt_room_event(&event, room_id).await.unwrap_err();
+        assert_matches!(
+            &err,
+            MegolmError::UnverifiedSenderDevice(VerificationLevel::UnsignedDevice)
+        );
+        assert_eq!(
+            UnableToDecryptReason::from(&err),
+            UnableToDecryptReason::UnverifiedSenderDevice(VerificationLevel::UnsignedDevice)
+        );
+        assert!(!UnableToDecryptReason::from(&err).is_missing_room_key());
     }
 
     #[async_test]
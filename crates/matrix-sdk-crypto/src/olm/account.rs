@@ -108,6 +108,7 @@ pub(crate) struct OlmDecryptionInfo {
     pub session: SessionType,
     pub message_hash: OlmMessageHash,
     pub inbound_group_session: Option<InboundGroupSession>,
+    pub room_key_history: Vec<InboundGroupSession>,
     pub result: DecryptionResult,
 }
 
@@ -167,9 +168,13 @@ impl Account {
         let message_hash = OlmMessageHash::new(sender_key, ciphertext);
 
         match self.decrypt_olm_message(sender, sender_key, ciphertext).await {
-            Ok((session, result)) => {
-                Ok(OlmDecryptionInfo { session, message_hash, result, inbound_group_session: None })
-            }
+            Ok((session, result)) => Ok(OlmDecryptionInfo {
+                session,
+                message_hash,
+                result,
+                inbound_group_session: None,
+                room_key_history: Vec::new(),
+            }),
             Err(OlmError::SessionWedged(user_id, sender_key)) => {
                 if self.store.is_message_known(&message_hash).await? {
                     info!(?sender_key, "An Olm message got replayed, decryption failed");
@@ -840,6 +845,37 @@ impl ReadOnlyAccount {
         })
     }
 
+    /// Pickle the Olm account and encrypt it with the given key, to be
+    /// uploaded as the data of a dehydrated device.
+    pub(crate) async fn dehydrate(&self, pickle_key: &[u8; 32]) -> String {
+        self.inner.lock().await.pickle().encrypt(pickle_key)
+    }
+
+    /// Restore the account of a dehydrated device from its encrypted pickle.
+    ///
+    /// The device keys of a dehydrated device were uploaded when the device
+    /// was created, so the restored account is marked as shared.
+    pub(crate) fn rehydrate(
+        pickle_key: &[u8; 32],
+        user_id: &UserId,
+        device_id: &DeviceId,
+        device_pickle: &str,
+    ) -> Result<Self, PickleError> {
+        let account: InnerAccount =
+            AccountPickle::from_encrypted(device_pickle, pickle_key)?.into();
+        let identity_keys = account.identity_keys();
+
+        Ok(Self {
+            user_id: user_id.into(),
+            device_id: device_id.into(),
+            inner: Arc::new(Mutex::new(account)),
+            identity_keys: Arc::new(identity_keys),
+            shared: Arc::new(AtomicBool::new(true)),
+            uploaded_signed_key_count: Arc::new(AtomicU64::new(0)),
+            creation_local_time: MilliSecondsSinceUnixEpoch::now(),
+        })
+    }
+
     /// Generate the unsigned `DeviceKeys` from this ReadOnlyAccount
     pub fn unsigned_device_keys(&self) -> DeviceKeys {
         let identity_keys = self.identity_keys();
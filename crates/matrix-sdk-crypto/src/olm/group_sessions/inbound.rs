@@ -38,7 +38,8 @@ use vodozemac::{
 };
 
 use super::{
-    BackedUpRoomKey, ExportedRoomKey, OutboundGroupSession, SessionCreationError, SessionKey,
+    shared_history, BackedUpRoomKey, ExportedRoomKey, OutboundGroupSession, SessionCreationError,
+    SessionKey,
 };
 use crate::{
     error::{EventError, MegolmResult},
@@ -144,6 +145,12 @@ pub struct InboundGroupSession {
     /// created.
     history_visibility: Arc<Option<HistoryVisibility>>,
 
+    /// A flag recording whether the `InboundGroupSession` may be shared with
+    /// users that are invited to the room later on, as defined in [MSC3061].
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    shared_history: bool,
+
     /// Was this room key backed up to the server.
     backed_up: Arc<AtomicBool>,
 }
@@ -182,9 +189,12 @@ impl InboundGroupSession {
         let mut keys = SigningKeys::new();
         keys.insert(DeviceKeyAlgorithm::Ed25519, signing_key.into());
 
+        let shared_history = history_visibility.as_ref().is_some_and(shared_history);
+
         Ok(InboundGroupSession {
             inner: Arc::new(Mutex::new(session)),
             history_visibility: history_visibility.into(),
+            shared_history,
             session_id: session_id.into(),
             first_known_index,
             creator_info: SessionCreatorInfo {
@@ -227,6 +237,7 @@ impl InboundGroupSession {
             forwarding_curve25519_key_chain: vec![],
             session_key: backup.session_key,
             sender_claimed_keys: backup.sender_claimed_keys,
+            shared_history: backup.shared_history,
         })
     }
 
@@ -247,6 +258,7 @@ impl InboundGroupSession {
             imported: self.imported,
             backed_up: self.backed_up(),
             history_visibility: self.history_visibility.as_ref().clone(),
+            shared_history: self.shared_history,
             algorithm: (*self.algorithm).to_owned(),
         }
     }
@@ -300,6 +312,7 @@ impl InboundGroupSession {
             forwarding_curve25519_key_chain: vec![],
             sender_claimed_keys: (*self.creator_info.signing_keys).clone(),
             session_key,
+            shared_history: self.shared_history,
         }
     }
 
@@ -327,6 +340,7 @@ impl InboundGroupSession {
                 signing_keys: pickle.signing_key.into(),
             },
             history_visibility: pickle.history_visibility.into(),
+            shared_history: pickle.shared_history,
             first_known_index,
             room_id: (*pickle.room_id).into(),
             backed_up: AtomicBool::from(pickle.backed_up).into(),
@@ -362,6 +376,24 @@ impl InboundGroupSession {
         self.imported
     }
 
+    /// May the session be shared with users that are invited to the room
+    /// later on, as defined in [MSC3061]?
+    ///
+    /// This is the case if the room had a `shared` or `world_readable` history
+    /// visibility when the session was created.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub fn shared_history(&self) -> bool {
+        self.shared_history
+    }
+
+    /// Set whether the session may be shared with users that are invited to
+    /// the room later on, as claimed by the `m.room_key` that created it.
+    pub(crate) fn with_shared_history(mut self, shared_history: bool) -> Self {
+        self.shared_history = shared_history;
+        self
+    }
+
     /// Check if the `InboundGroupSession` is better than the given other
     /// `InboundGroupSession`
     pub async fn compare(&self, other: &InboundGroupSession) -> SessionOrdering {
@@ -505,6 +537,10 @@ pub struct PickledInboundGroupSession {
     pub backed_up: bool,
     /// History visibility of the room when the session was created.
     pub history_visibility: Option<HistoryVisibility>,
+    /// Flag remembering if the session may be shared with users that are
+    /// invited to the room later on.
+    #[serde(default)]
+    pub shared_history: bool,
     /// The algorithm of this inbound group session.
     #[serde(default = "default_algorithm")]
     pub algorithm: EventEncryptionAlgorithm,
@@ -530,6 +566,7 @@ impl TryFrom<&ExportedRoomKey> for InboundGroupSession {
                 signing_keys: key.sender_claimed_keys.to_owned().into(),
             },
             history_visibility: None.into(),
+            shared_history: key.shared_history,
             first_known_index,
             room_id: key.room_id.to_owned(),
             imported: true,
@@ -557,6 +594,7 @@ impl From<&ForwardedMegolmV1AesSha2Content> for InboundGroupSession {
                 .into(),
             },
             history_visibility: None.into(),
+            shared_history: value.shared_history,
             first_known_index,
             room_id: value.room_id.to_owned(),
             imported: true,
@@ -580,6 +618,7 @@ impl From<&ForwardedMegolmV2AesSha2Content> for InboundGroupSession {
                 signing_keys: value.claimed_signing_keys.to_owned().into(),
             },
             history_visibility: None.into(),
+            shared_history: value.shared_history,
             first_known_index,
             room_id: value.room_id.to_owned(),
             imported: true,
@@ -593,7 +632,15 @@ impl TryFrom<&DecryptedForwardedRoomKeyEvent> for InboundGroupSession {
     type Error = SessionCreationError;
 
     fn try_from(value: &DecryptedForwardedRoomKeyEvent) -> Result<Self, Self::Error> {
-        match &value.content {
+        Self::try_from(&value.content)
+    }
+}
+
+impl TryFrom<&ForwardedRoomKeyContent> for InboundGroupSession {
+    type Error = SessionCreationError;
+
+    fn try_from(value: &ForwardedRoomKeyContent) -> Result<Self, Self::Error> {
+        match value {
             ForwardedRoomKeyContent::MegolmV1AesSha2(c) => Ok(Self::from(c.deref())),
             #[cfg(feature = "experimental-algorithms")]
             ForwardedRoomKeyContent::MegolmV2AesSha2(c) => Ok(Self::from(c.deref())),
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use ruma::{DeviceKeyAlgorithm, OwnedRoomId};
+use ruma::{events::room::history_visibility::HistoryVisibility, DeviceKeyAlgorithm, OwnedRoomId};
 use serde::{Deserialize, Serialize};
 
 mod inbound;
@@ -21,7 +21,8 @@ mod outbound;
 pub use inbound::{InboundGroupSession, PickledInboundGroupSession};
 pub(crate) use outbound::ShareState;
 pub use outbound::{
-    EncryptionSettings, GroupSession, OutboundGroupSession, PickledOutboundGroupSession, ShareInfo,
+    CollectStrategy, EncryptionSettings, GroupSession, OutboundGroupSession,
+    PickledOutboundGroupSession, ShareInfo,
 };
 use thiserror::Error;
 pub use vodozemac::megolm::{ExportedSessionKey, SessionKey};
@@ -61,6 +62,15 @@ pub enum SessionExportError {
     MissingEd25519Key,
 }
 
+/// Whether room keys created while a room has the given history visibility may
+/// be shared with users that are invited to the room later on, as defined in
+/// [MSC3061].
+///
+/// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+pub(crate) fn shared_history(history_visibility: &HistoryVisibility) -> bool {
+    matches!(history_visibility, HistoryVisibility::Shared | HistoryVisibility::WorldReadable)
+}
+
 /// An exported version of an `InboundGroupSession`
 ///
 /// This can be used to share the `InboundGroupSession` in an exported file.
@@ -95,6 +105,16 @@ pub struct ExportedRoomKey {
         serialize_with = "serialize_curve_key_vec"
     )]
     pub forwarding_curve25519_key_chain: Vec<Curve25519PublicKey>,
+    /// Whether the session may be shared with users that are invited to the
+    /// room later on, as defined in [MSC3061].
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    #[serde(
+        default,
+        rename = "org.matrix.msc3061.shared_history",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub shared_history: bool,
 }
 
 /// A backed up version of an `InboundGroupSession`
@@ -119,6 +139,16 @@ pub struct BackedUpRoomKey {
     /// Chain of Curve25519 keys through which this session was forwarded, via
     /// m.forwarded_room_key events.
     pub forwarding_curve25519_key_chain: Vec<Curve25519PublicKey>,
+    /// Whether the session may be shared with users that are invited to the
+    /// room later on, as defined in [MSC3061].
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    #[serde(
+        default,
+        rename = "org.matrix.msc3061.shared_history",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub shared_history: bool,
 }
 
 impl TryFrom<ExportedRoomKey> for ForwardedRoomKeyContent {
@@ -151,6 +181,7 @@ impl TryFrom<ExportedRoomKey> for ForwardedRoomKeyContent {
                             forwarding_curve25519_key_chain: room_key
                                 .forwarding_curve25519_key_chain
                                 .clone(),
+                            shared_history: room_key.shared_history,
                             other: Default::default(),
                         }
                         .into(),
@@ -168,6 +199,7 @@ impl TryFrom<ExportedRoomKey> for ForwardedRoomKeyContent {
                         session_key: room_key.session_key,
                         claimed_sender_key: room_key.sender_key,
                         claimed_signing_keys: room_key.sender_claimed_keys,
+                        shared_history: room_key.shared_history,
                         other: Default::default(),
                     }
                     .into(),
@@ -186,6 +218,7 @@ impl From<ExportedRoomKey> for BackedUpRoomKey {
             session_key: k.session_key,
             sender_claimed_keys: k.sender_claimed_keys,
             forwarding_curve25519_key_chain: k.forwarding_curve25519_key_chain,
+            shared_history: k.shared_history,
         }
     }
 }
@@ -211,6 +244,7 @@ impl TryFrom<ForwardedRoomKeyContent> for ExportedRoomKey {
                     sender_claimed_keys,
                     sender_key: content.claimed_sender_key,
                     session_key: content.session_key,
+                    shared_history: content.shared_history,
                 })
             }
             #[cfg(feature = "experimental-algorithms")]
@@ -222,6 +256,7 @@ impl TryFrom<ForwardedRoomKeyContent> for ExportedRoomKey {
                 sender_claimed_keys: content.claimed_signing_keys,
                 sender_key: content.claimed_sender_key,
                 session_key: content.session_key,
+                shared_history: content.shared_history,
             }),
             ForwardedRoomKeyContent::Unknown(c) => Err(SessionExportError::Algorithm(c.algorithm)),
         }
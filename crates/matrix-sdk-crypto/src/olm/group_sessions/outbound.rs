@@ -41,7 +41,7 @@ pub use vodozemac::{
     PickleError,
 };
 
-use super::SessionCreationError;
+use super::{shared_history, SessionCreationError};
 #[cfg(feature = "experimental-algorithms")]
 use crate::types::events::room::encrypted::MegolmV2AesSha2Content;
 use crate::{
@@ -68,6 +68,30 @@ pub(crate) enum ShareState {
     Shared(u32),
 }
 
+/// Strategy to collect the devices that should receive room keys for the
+/// current discussion.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectStrategy {
+    /// Share the room key with all the devices of the room members, except
+    /// the blacklisted ones.
+    #[default]
+    AllDevices,
+
+    /// Only share the room key with devices that have been cross-signed by
+    /// their owner, or that we have verified manually.
+    OnlyCrossSignedDevices,
+
+    /// Only share the room key with devices of users that we have verified,
+    /// when the devices have been cross-signed by their owner.
+    OnlyVerifiedUsers,
+
+    /// Share the room key with all the devices of the room members, but
+    /// refuse to share it at all if a verified user has an unsigned device
+    /// or if a previously verified user has changed their identity.
+    ErrorOnIdentityChange,
+}
+
 /// Settings for an encrypted room.
 ///
 /// This determines the algorithm and rotation periods of a group session.
@@ -85,6 +109,9 @@ pub struct EncryptionSettings {
     /// excluded from the conversation.
     #[serde(default)]
     pub only_allow_trusted_devices: bool,
+    /// The strategy used to select the devices that receive the room key.
+    #[serde(default)]
+    pub sharing_strategy: CollectStrategy,
 }
 
 impl Default for EncryptionSettings {
@@ -95,6 +122,7 @@ impl Default for EncryptionSettings {
             rotation_period_msgs: ROTATION_MESSAGES,
             history_visibility: HistoryVisibility::Shared,
             only_allow_trusted_devices: false,
+            sharing_strategy: CollectStrategy::default(),
         }
     }
 }
@@ -119,6 +147,7 @@ impl EncryptionSettings {
             rotation_period_msgs,
             history_visibility,
             only_allow_trusted_devices,
+            sharing_strategy: CollectStrategy::default(),
         }
     }
 }
@@ -486,6 +515,7 @@ impl OutboundGroupSession {
                 self.room_id().to_owned(),
                 self.session_id().to_owned(),
                 session_key,
+                shared_history(&self.settings().history_visibility),
             )
             .into(),
         )
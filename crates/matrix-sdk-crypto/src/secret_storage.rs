@@ -0,0 +1,707 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Primitives for the server-side secret storage, also known as SSSS.
+//!
+//! Secrets are stored encrypted in the global account data of the user, in
+//! events named after the secret. They are encrypted with a secret storage
+//! key, whose description lives in the `m.secret_storage.key.<key_id>` account
+//! data event. The ID of the key to use by default is stored in the
+//! `m.secret_storage.default_key` event.
+//!
+//! Only the `m.secret_storage.v1.aes-hmac-sha2` algorithm is supported.
+//!
+//! More info can be found in the [spec].
+//!
+//! [spec]: https://spec.matrix.org/v1.8/client-server-api/#storage
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{Cursor, Read},
+};
+
+use aes::{
+    cipher::{generic_array::GenericArray, KeyIvInit, StreamCipher},
+    Aes256,
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use pbkdf2::pbkdf2;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    thread_rng, RngCore,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Sha512};
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::utilities::{decode, encode};
+
+type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+
+/// The type of the account data event containing the ID of the default secret
+/// storage key.
+pub const DEFAULT_KEY_EVENT_TYPE: &str = "m.secret_storage.default_key";
+
+/// The prefix of the type of the account data events describing a secret
+/// storage key.
+pub const KEY_EVENT_TYPE_PREFIX: &str = "m.secret_storage.key.";
+
+/// The only supported secret storage algorithm.
+pub const AES_HMAC_SHA2_ALGORITHM: &str = "m.secret_storage.v1.aes-hmac-sha2";
+
+/// The only supported algorithm to derive a key from a passphrase.
+pub const PBKDF2_ALGORITHM: &str = "m.pbkdf2";
+
+const KEY_SIZE: usize = 32;
+const IV_SIZE: usize = 16;
+const KEY_ID_SIZE: usize = 32;
+const SALT_SIZE: usize = 32;
+const PBKDF2_ITERATIONS: u32 = 500_000;
+/// The maximum number of PBKDF2 iterations we accept from a key description,
+/// so a malicious one can't make us spin for hours.
+const MAX_PBKDF2_ITERATIONS: u32 = 10 * PBKDF2_ITERATIONS;
+
+const PREFIX: [u8; 2] = [0x8b, 0x01];
+const DISPLAY_CHUNK_SIZE: usize = 4;
+
+/// The content of the `m.secret_storage.default_key` account data event.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SecretStorageDefaultKeyEventContent {
+    /// The ID of the default secret storage key.
+    pub key: String,
+}
+
+/// The content of a `m.secret_storage.key.<key_id>` account data event,
+/// describing a secret storage key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SecretStorageKeyEventContent {
+    /// The algorithm used by the key.
+    pub algorithm: String,
+
+    /// The name of the key, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// How to derive the key from a passphrase, if it was created from one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<PassPhrase>,
+
+    /// The IV used to compute the MAC checking the key, encoded as base64.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iv: Option<String>,
+
+    /// The MAC of 32 zero bytes encrypted with the key, encoded as base64.
+    ///
+    /// It is used to check that a key is correct.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+}
+
+/// The parameters to derive a secret storage key from a passphrase.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PassPhrase {
+    /// The algorithm used to derive the key.
+    pub algorithm: String,
+
+    /// The salt used to derive the key.
+    pub salt: String,
+
+    /// The number of PBKDF2 iterations.
+    pub iterations: u32,
+
+    /// The number of bits of the key.
+    #[serde(default = "default_bits")]
+    pub bits: u32,
+}
+
+fn default_bits() -> u32 {
+    (KEY_SIZE * 8) as u32
+}
+
+/// The content of an account data event containing a secret, encrypted with
+/// one or more secret storage keys.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SecretEventContent {
+    /// The encrypted secret, by ID of the secret storage key.
+    pub encrypted: BTreeMap<String, AesHmacSha2EncryptedData>,
+}
+
+/// A secret encrypted with the `m.secret_storage.v1.aes-hmac-sha2`
+/// algorithm.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AesHmacSha2EncryptedData {
+    /// The IV used for the encryption, encoded as base64.
+    pub iv: String,
+
+    /// The encrypted secret, encoded as base64.
+    pub ciphertext: String,
+
+    /// The MAC of the ciphertext, encoded as base64.
+    pub mac: String,
+}
+
+/// Error type for the decoding and checking of a [`SecretStorageKey`].
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    /// The key uses an unsupported algorithm.
+    #[error("the secret storage key uses an unsupported algorithm: {0}")]
+    UnsupportedAlgorithm(String),
+
+    /// The passphrase of the key is derived with an unsupported algorithm.
+    #[error("the secret storage key passphrase uses an unsupported algorithm: {0}")]
+    UnsupportedPassphraseAlgorithm(String),
+
+    /// The passphrase of the key is derived with a number of PBKDF2 iterations
+    /// that is zero or above the maximum we accept.
+    #[error("the secret storage key passphrase uses an invalid number of iterations: {0}")]
+    InvalidPassphraseIterations(u32),
+
+    /// The passphrase of the key derives a key with an unsupported size.
+    #[error("the secret storage key passphrase derives a key of unsupported size: {0} bits")]
+    UnsupportedPassphraseBits(u32),
+
+    /// The input is neither a valid base58 encoded key, nor a passphrase that
+    /// can be used with this key.
+    #[error("the input is not a valid secret storage key")]
+    InvalidKey,
+
+    /// The base58 encoded key doesn't have the expected prefix.
+    #[error("the decoded secret storage key has an invalid prefix: expected {0:?}, got {1:?}")]
+    Prefix([u8; 2], [u8; 2]),
+
+    /// The parity byte of the base58 encoded key doesn't match.
+    #[error("the parity byte of the secret storage key doesn't match: expected {0:?}, got {1:?}")]
+    Parity(u8, u8),
+
+    /// The key doesn't match the MAC of its description.
+    #[error("the secret storage key doesn't match its description")]
+    Mismatch,
+
+    /// The description of the key contains invalid base64.
+    #[error(transparent)]
+    Base64(#[from] base64::DecodeError),
+}
+
+/// Error type for the decryption of a secret.
+#[derive(Debug, Error)]
+pub enum DecryptionError {
+    /// The MAC of the secret doesn't match, either the secret was tampered with
+    /// or it wasn't encrypted with this key and secret name.
+    #[error("the MAC of the secret doesn't match")]
+    Mac,
+
+    /// The IV doesn't have the expected length.
+    #[error("the IV of the secret has an invalid length: {0}")]
+    IvLength(usize),
+
+    /// The encrypted secret contains invalid base64.
+    #[error(transparent)]
+    Base64(#[from] base64::DecodeError),
+}
+
+/// A secret storage key, that can be used to encrypt and decrypt secrets.
+pub struct SecretStorageKey {
+    key_id: String,
+    content: SecretStorageKeyEventContent,
+    secret_key: Box<[u8; KEY_SIZE]>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for SecretStorageKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretStorageKey")
+            .field("key_id", &self.key_id)
+            .field("content", &self.content)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for SecretStorageKey {
+    fn drop(&mut self) {
+        self.secret_key.zeroize();
+    }
+}
+
+impl SecretStorageKey {
+    /// Create a new random secret storage key.
+    pub fn new() -> Self {
+        let mut secret_key = Box::new([0u8; KEY_SIZE]);
+        thread_rng().fill_bytes(secret_key.as_mut_slice());
+
+        Self::from_parts(secret_key, None)
+    }
+
+    /// Create a new secret storage key derived from the given passphrase.
+    pub fn new_from_passphrase(passphrase: &str) -> Self {
+        let salt = Alphanumeric.sample_string(&mut thread_rng(), SALT_SIZE);
+        let passphrase_info = PassPhrase {
+            algorithm: PBKDF2_ALGORITHM.to_owned(),
+            salt,
+            iterations: PBKDF2_ITERATIONS,
+            bits: default_bits(),
+        };
+
+        let secret_key = derive_from_passphrase(passphrase, &passphrase_info)
+            .expect("The default passphrase parameters should be valid");
+
+        Self::from_parts(secret_key, Some(passphrase_info))
+    }
+
+    fn from_parts(secret_key: Box<[u8; KEY_SIZE]>, passphrase: Option<PassPhrase>) -> Self {
+        let key_id = Alphanumeric.sample_string(&mut thread_rng(), KEY_ID_SIZE);
+
+        let mut key = Self {
+            key_id,
+            content: SecretStorageKeyEventContent {
+                algorithm: AES_HMAC_SHA2_ALGORITHM.to_owned(),
+                name: None,
+                passphrase,
+                iv: None,
+                mac: None,
+            },
+            secret_key,
+        };
+
+        let iv = random_iv();
+        let mac = key.key_check_hmac(&iv).finalize().into_bytes();
+        key.content.iv = Some(encode(iv));
+        key.content.mac = Some(encode(mac));
+
+        key
+    }
+
+    /// Restore a secret storage key from its description in the account data,
+    /// and the key provided by the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Either the base58 encoded key, or the passphrase it was
+    /// derived from.
+    ///
+    /// * `key_id` - The ID of the key.
+    ///
+    /// * `content` - The content of the `m.secret_storage.key.<key_id>`
+    /// account data event.
+    pub fn from_account_data(
+        input: &str,
+        key_id: String,
+        content: SecretStorageKeyEventContent,
+    ) -> Result<Self, DecodeError> {
+        if content.algorithm != AES_HMAC_SHA2_ALGORITHM {
+            return Err(DecodeError::UnsupportedAlgorithm(content.algorithm));
+        }
+
+        let secret_key = match (decode_base58(input), &content.passphrase) {
+            (Ok(secret_key), _) => secret_key,
+            (Err(_), Some(passphrase)) if passphrase.algorithm == PBKDF2_ALGORITHM => {
+                derive_from_passphrase(input, passphrase)?
+            }
+            (Err(_), Some(passphrase)) => {
+                return Err(DecodeError::UnsupportedPassphraseAlgorithm(
+                    passphrase.algorithm.clone(),
+                ))
+            }
+            (Err(e), None) => return Err(e),
+        };
+
+        let key = Self { key_id, content, secret_key };
+        key.check()?;
+
+        Ok(key)
+    }
+
+    /// Check that the key matches the MAC of its description, if there is
+    /// one.
+    fn check(&self) -> Result<(), DecodeError> {
+        let (Some(iv), Some(mac)) = (&self.content.iv, &self.content.mac) else {
+            return Ok(());
+        };
+
+        let iv: [u8; IV_SIZE] = decode(iv)?.try_into().map_err(|_| DecodeError::Mismatch)?;
+        let mac = decode(mac)?;
+
+        self.key_check_hmac(&iv).verify_slice(&mac).map_err(|_| DecodeError::Mismatch)
+    }
+
+    /// Get the HMAC of 32 zero bytes encrypted with this key and the given
+    /// IV, used to check the key.
+    fn key_check_hmac(&self, iv: &[u8; IV_SIZE]) -> Hmac<Sha256> {
+        let (aes_key, hmac_key) = self.derive_keys("");
+        let ciphertext = encrypt_zeroes(&aes_key, iv);
+
+        let mut hmac = Hmac::<Sha256>::new_from_slice(hmac_key.as_slice())
+            .expect("Can't create an HMAC object");
+        hmac.update(&ciphertext);
+
+        hmac
+    }
+
+    /// Derive the AES and HMAC keys for the given secret name.
+    fn derive_keys(
+        &self,
+        secret_name: &str,
+    ) -> (Zeroizing<[u8; KEY_SIZE]>, Zeroizing<[u8; KEY_SIZE]>) {
+        let hkdf = Hkdf::<Sha256>::new(Some(&[0u8; KEY_SIZE]), self.secret_key.as_slice());
+
+        let mut expanded = Zeroizing::new([0u8; KEY_SIZE * 2]);
+        hkdf.expand(secret_name.as_bytes(), expanded.as_mut_slice())
+            .expect("We should be able to expand the secret storage key into two keys");
+
+        let mut aes_key = Zeroizing::new([0u8; KEY_SIZE]);
+        let mut hmac_key = Zeroizing::new([0u8; KEY_SIZE]);
+        aes_key.copy_from_slice(&expanded[..KEY_SIZE]);
+        hmac_key.copy_from_slice(&expanded[KEY_SIZE..]);
+
+        (aes_key, hmac_key)
+    }
+
+    /// The ID of the key.
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// The type of the account data event describing this key.
+    pub fn event_type(&self) -> String {
+        format!("{KEY_EVENT_TYPE_PREFIX}{}", self.key_id)
+    }
+
+    /// The content of the account data event describing this key.
+    pub fn event_content(&self) -> &SecretStorageKeyEventContent {
+        &self.content
+    }
+
+    /// Export the key as a base58 encoded string, split in groups of four
+    /// characters to be displayed to the user.
+    pub fn to_base58(&self) -> String {
+        let bytes = Zeroizing::new(
+            [PREFIX.as_slice(), self.secret_key.as_slice(), &[parity_byte(&*self.secret_key)]]
+                .concat(),
+        );
+
+        let encoded = Zeroizing::new(
+            bs58::encode(bytes.as_slice()).with_alphabet(bs58::Alphabet::BITCOIN).into_string(),
+        );
+
+        encoded
+            .as_bytes()
+            .chunks(DISPLAY_CHUNK_SIZE)
+            .map(|chunk| String::from_utf8_lossy(chunk))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Encrypt the given secret with this key.
+    ///
+    /// # Arguments
+    ///
+    /// * `plaintext` - The secret to encrypt.
+    ///
+    /// * `secret_name` - The name of the secret, which is also the type of the
+    /// account data event it will be stored in.
+    pub fn encrypt(&self, plaintext: &[u8], secret_name: &str) -> AesHmacSha2EncryptedData {
+        let (aes_key, hmac_key) = self.derive_keys(secret_name);
+        let iv = random_iv();
+
+        let mut ciphertext = plaintext.to_vec();
+        let mut aes = Aes256Ctr::new(GenericArray::from_slice(aes_key.as_slice()), &iv.into());
+        aes.apply_keystream(&mut ciphertext);
+
+        let mut hmac = Hmac::<Sha256>::new_from_slice(hmac_key.as_slice())
+            .expect("Can't create an HMAC object");
+        hmac.update(&ciphertext);
+        let mac = hmac.finalize().into_bytes();
+
+        AesHmacSha2EncryptedData {
+            iv: encode(iv),
+            ciphertext: encode(ciphertext),
+            mac: encode(mac),
+        }
+    }
+
+    /// Decrypt the given secret with this key.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The encrypted secret.
+    ///
+    /// * `secret_name` - The name of the secret, which is also the type of the
+    /// account data event it was stored in.
+    pub fn decrypt(
+        &self,
+        data: &AesHmacSha2EncryptedData,
+        secret_name: &str,
+    ) -> Result<Zeroizing<Vec<u8>>, DecryptionError> {
+        let iv = decode(&data.iv)?;
+        let iv: [u8; IV_SIZE] =
+            iv.try_into().map_err(|iv: Vec<u8>| DecryptionError::IvLength(iv.len()))?;
+        let mut ciphertext = Zeroizing::new(decode(&data.ciphertext)?);
+        let mac = decode(&data.mac)?;
+
+        let (aes_key, hmac_key) = self.derive_keys(secret_name);
+
+        let mut hmac = Hmac::<Sha256>::new_from_slice(hmac_key.as_slice())
+            .expect("Can't create an HMAC object");
+        hmac.update(&ciphertext);
+        hmac.verify_slice(&mac).map_err(|_| DecryptionError::Mac)?;
+
+        let mut aes = Aes256Ctr::new(GenericArray::from_slice(aes_key.as_slice()), &iv.into());
+        aes.apply_keystream(&mut ciphertext);
+
+        Ok(ciphertext)
+    }
+}
+
+impl Default for SecretStorageKey {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parity_byte(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(PREFIX[0] ^ PREFIX[1], |acc, x| acc ^ x)
+}
+
+/// Generate a random IV, with the 64th bit cleared to avoid issues with the
+/// counter overflowing in some AES-CTR implementations.
+fn random_iv() -> [u8; IV_SIZE] {
+    let mut iv = [0u8; IV_SIZE];
+    thread_rng().fill_bytes(&mut iv);
+
+    let iv = u128::from_be_bytes(iv) & !(1 << 63);
+    iv.to_be_bytes()
+}
+
+/// Encrypt 32 zero bytes with the given AES key and IV.
+fn encrypt_zeroes(aes_key: &[u8; KEY_SIZE], iv: &[u8; IV_SIZE]) -> [u8; KEY_SIZE] {
+    let mut zeroes = [0u8; KEY_SIZE];
+    let mut aes = Aes256Ctr::new(GenericArray::from_slice(aes_key), &(*iv).into());
+    aes.apply_keystream(&mut zeroes);
+    zeroes
+}
+
+fn derive_from_passphrase(
+    passphrase: &str,
+    passphrase_info: &PassPhrase,
+) -> Result<Box<[u8; KEY_SIZE]>, DecodeError> {
+    if !(1..=MAX_PBKDF2_ITERATIONS).contains(&passphrase_info.iterations) {
+        return Err(DecodeError::InvalidPassphraseIterations(passphrase_info.iterations));
+    }
+
+    if passphrase_info.bits != default_bits() {
+        return Err(DecodeError::UnsupportedPassphraseBits(passphrase_info.bits));
+    }
+
+    let mut secret_key = Box::new([0u8; KEY_SIZE]);
+    pbkdf2::<Hmac<Sha512>>(
+        passphrase.as_bytes(),
+        passphrase_info.salt.as_bytes(),
+        passphrase_info.iterations,
+        secret_key.as_mut_slice(),
+    );
+
+    Ok(secret_key)
+}
+
+fn decode_base58(input: &str) -> Result<Box<[u8; KEY_SIZE]>, DecodeError> {
+    // Remove the spaces used to display the key.
+    let input: Zeroizing<String> =
+        Zeroizing::new(input.chars().filter(|c| !c.is_whitespace()).collect());
+
+    let decoded = Zeroizing::new(
+        bs58::decode(input.as_str())
+            .with_alphabet(bs58::Alphabet::BITCOIN)
+            .into_vec()
+            .map_err(|_| DecodeError::InvalidKey)?,
+    );
+
+    if decoded.len() != PREFIX.len() + KEY_SIZE + 1 {
+        return Err(DecodeError::InvalidKey);
+    }
+
+    let mut decoded = Cursor::new(decoded.as_slice());
+
+    let mut prefix = [0u8; 2];
+    let mut secret_key = Box::new([0u8; KEY_SIZE]);
+    let mut expected_parity = [0u8; 1];
+
+    decoded.read_exact(&mut prefix).map_err(|_| DecodeError::InvalidKey)?;
+    decoded.read_exact(secret_key.as_mut_slice()).map_err(|_| DecodeError::InvalidKey)?;
+    decoded.read_exact(&mut expected_parity).map_err(|_| DecodeError::InvalidKey)?;
+
+    let expected_parity = expected_parity[0];
+    let parity = parity_byte(secret_key.as_slice());
+
+    if prefix != PREFIX {
+        Err(DecodeError::Prefix(PREFIX, prefix))
+    } else if expected_parity != parity {
+        Err(DecodeError::Parity(expected_parity, parity))
+    } else {
+        Ok(secret_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::{
+        DecodeError, DecryptionError, SecretStorageKey, AES_HMAC_SHA2_ALGORITHM,
+        MAX_PBKDF2_ITERATIONS,
+    };
+
+    const SECRET_NAME: &str = "m.cross_signing.master";
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = SecretStorageKey::new();
+
+        let encrypted = key.encrypt(b"It's a secret to everybody", SECRET_NAME);
+        let decrypted = key.decrypt(&encrypted, SECRET_NAME).unwrap();
+
+        assert_eq!(decrypted.as_slice(), b"It's a secret to everybody");
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_secret_name() {
+        let key = SecretStorageKey::new();
+
+        let encrypted = key.encrypt(b"It's a secret to everybody", SECRET_NAME);
+
+        assert_matches!(
+            key.decrypt(&encrypted, "m.cross_signing.self_signing"),
+            Err(DecryptionError::Mac)
+        );
+    }
+
+    #[test]
+    fn test_restore_from_base58() {
+        let key = SecretStorageKey::new();
+        assert_eq!(key.event_content().algorithm, AES_HMAC_SHA2_ALGORITHM);
+        assert!(key.event_type().ends_with(key.key_id()));
+
+        let restored = SecretStorageKey::from_account_data(
+            &key.to_base58(),
+            key.key_id().to_owned(),
+            key.event_content().clone(),
+        )
+        .unwrap();
+
+        let encrypted = key.encrypt(b"It's a secret to everybody", SECRET_NAME);
+        let decrypted = restored.decrypt(&encrypted, SECRET_NAME).unwrap();
+        assert_eq!(decrypted.as_slice(), b"It's a secret to everybody");
+    }
+
+    #[test]
+    fn test_restore_from_passphrase() {
+        let key = SecretStorageKey::new_from_passphrase("It's a secret to everybody");
+        assert!(key.event_content().passphrase.is_some());
+
+        let restored = SecretStorageKey::from_account_data(
+            "It's a secret to everybody",
+            key.key_id().to_owned(),
+            key.event_content().clone(),
+        )
+        .unwrap();
+        assert_eq!(restored.to_base58(), key.to_base58());
+
+        // The base58 representation of the key can be used too.
+        SecretStorageKey::from_account_data(
+            &key.to_base58(),
+            key.key_id().to_owned(),
+            key.event_content().clone(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_restore_wrong_key() {
+        let key = SecretStorageKey::new_from_passphrase("It's a secret to everybody");
+        let other_key = SecretStorageKey::new();
+
+        assert_matches!(
+            SecretStorageKey::from_account_data(
+                &other_key.to_base58(),
+                key.key_id().to_owned(),
+                key.event_content().clone(),
+            ),
+            Err(DecodeError::Mismatch)
+        );
+        assert_matches!(
+            SecretStorageKey::from_account_data(
+                "wrong passphrase",
+                key.key_id().to_owned(),
+                key.event_content().clone(),
+            ),
+            Err(DecodeError::Mismatch)
+        );
+
+        // Without a passphrase, only the base58 key is accepted.
+        assert_matches!(
+            SecretStorageKey::from_account_data(
+                "wrong passphrase",
+                other_key.key_id().to_owned(),
+                other_key.event_content().clone(),
+            ),
+            Err(DecodeError::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn test_restore_with_invalid_passphrase_parameters() {
+        let key = SecretStorageKey::new_from_passphrase("It's a secret to everybody");
+
+        let mut content = key.event_content().clone();
+        content.passphrase.as_mut().unwrap().iterations = MAX_PBKDF2_ITERATIONS + 1;
+        assert_matches!(
+            SecretStorageKey::from_account_data(
+                "It's a secret to everybody",
+                key.key_id().to_owned(),
+                content,
+            ),
+            Err(DecodeError::InvalidPassphraseIterations(i)) if i == MAX_PBKDF2_ITERATIONS + 1
+        );
+
+        let mut content = key.event_content().clone();
+        content.passphrase.as_mut().unwrap().iterations = 0;
+        assert_matches!(
+            SecretStorageKey::from_account_data(
+                "It's a secret to everybody",
+                key.key_id().to_owned(),
+                content,
+            ),
+            Err(DecodeError::InvalidPassphraseIterations(0))
+        );
+
+        let mut content = key.event_content().clone();
+        content.passphrase.as_mut().unwrap().bits = 128;
+        assert_matches!(
+            SecretStorageKey::from_account_data(
+                "It's a secret to everybody",
+                key.key_id().to_owned(),
+                content,
+            ),
+            Err(DecodeError::UnsupportedPassphraseBits(128))
+        );
+
+        // The base58 representation of the key doesn't need the passphrase
+        // parameters.
+        let mut content = key.event_content().clone();
+        content.passphrase.as_mut().unwrap().bits = 128;
+        SecretStorageKey::from_account_data(&key.to_base58(), key.key_id().to_owned(), content)
+            .unwrap();
+    }
+}
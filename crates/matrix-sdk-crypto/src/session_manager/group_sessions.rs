@@ -28,15 +28,18 @@ use ruma::{
     OwnedDeviceId, OwnedRoomId, OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UserId,
 };
 use serde_json::Value;
-use tracing::{debug, error, info, instrument, trace};
+use tracing::{debug, error, info, instrument, trace, warn};
 
 use crate::{
-    error::{EventError, MegolmResult, OlmResult},
+    error::{EventError, MegolmResult, OlmResult, SessionRecipientCollectionError},
     identities::device::MaybeEncryptedRoomKey,
-    olm::{Account, InboundGroupSession, OutboundGroupSession, Session, ShareInfo, ShareState},
+    olm::{
+        Account, CollectStrategy, InboundGroupSession, OutboundGroupSession, Session, ShareInfo,
+        ShareState,
+    },
     store::{Changes, Result as StoreResult, Store},
     types::events::{room::encrypted::RoomEncryptedEventContent, room_key_withheld::WithheldCode},
-    Device, EncryptionSettings, OlmError, ToDeviceRequest,
+    Device, EncryptionSettings, OlmError, ReadOnlyUserIdentity, ToDeviceRequest, UserDevices,
 };
 
 #[derive(Clone, Debug)]
@@ -339,8 +342,6 @@ impl GroupSessionManager {
         outbound: &OutboundGroupSession,
     ) -> OlmResult<CollectRecipientsResult> {
         let users: BTreeSet<&UserId> = users.collect();
-        let mut devices: BTreeMap<OwnedUserId, Vec<Device>> = Default::default();
-        let mut withheld_devices: Vec<(Device, WithheldCode)> = Default::default();
 
         trace!(
             ?users,
@@ -374,6 +375,69 @@ impl GroupSessionManager {
         // This is calculated in the following code and stored in this variable.
         let mut should_rotate = user_left || visibility_changed || algorithm_changed;
 
+        let (devices, withheld_devices) =
+            self.collect_recipient_devices(users.into_iter(), settings).await?;
+
+        // If we haven't already concluded that the session should be
+        // rotated for other reasons, we also need to check whether any
+        // of the devices in the session got deleted or blacklisted in the
+        // meantime. If so, we should also rotate the session.
+        for (user_id, recipients) in &devices {
+            if should_rotate {
+                break;
+            }
+
+            // Device IDs that should receive this session
+            let recipient_device_ids: BTreeSet<&DeviceId> =
+                recipients.iter().map(|d| d.device_id()).collect();
+
+            if let Some(shared) = outbound.shared_with_set.get(user_id) {
+                // Devices that received this session
+                let shared: BTreeSet<OwnedDeviceId> =
+                    shared.iter().map(|d| d.key().clone()).collect();
+                let shared: BTreeSet<&DeviceId> = shared.iter().map(|d| d.as_ref()).collect();
+
+                // The set difference between
+                //
+                // 1. Devices that had previously received the session, and
+                // 2. Devices that would now receive the session
+                //
+                // Represents newly deleted or blacklisted devices. If this
+                // set is non-empty, we must rotate.
+                let newly_deleted_or_blacklisted =
+                    shared.difference(&recipient_device_ids).collect::<BTreeSet<_>>();
+
+                should_rotate = !newly_deleted_or_blacklisted.is_empty();
+            };
+        }
+
+        trace!(
+            should_rotate = should_rotate,
+            session_id = outbound.session_id(),
+            room_id = outbound.room_id().as_str(),
+            "Done calculating group session recipients"
+        );
+
+        Ok(CollectRecipientsResult { should_rotate, devices, withheld_devices })
+    }
+
+    /// Split the devices of the given users into the ones that may receive a
+    /// room key, and the ones that must not receive it with the withheld code
+    /// explaining why, according to the sharing strategy of the settings.
+    ///
+    /// With the [`CollectStrategy::ErrorOnIdentityChange`] strategy, an error
+    /// is returned if one of the users has a problem with their identity.
+    pub(crate) async fn collect_recipient_devices(
+        &self,
+        users: impl Iterator<Item = &UserId>,
+        settings: &EncryptionSettings,
+    ) -> OlmResult<(BTreeMap<OwnedUserId, Vec<Device>>, Vec<(Device, WithheldCode)>)> {
+        let mut devices: BTreeMap<OwnedUserId, Vec<Device>> = Default::default();
+        let mut withheld_devices: Vec<(Device, WithheldCode)> = Default::default();
+        let mut unsigned_devices: BTreeMap<OwnedUserId, Vec<OwnedDeviceId>> = Default::default();
+        let mut changed_identities: Vec<OwnedUserId> = Default::default();
+        let mut unacknowledged_identities: Vec<OwnedUserId> = Default::default();
+
         for user_id in users {
             let user_devices = self.store.get_user_devices_filtered(user_id).await?;
 
@@ -385,54 +449,114 @@ impl GroupSessionManager {
                 user_devices.devices().partition_map(|d| {
                     if d.is_blacklisted() {
                         Either::Right((d, WithheldCode::Blacklisted))
-                    } else if settings.only_allow_trusted_devices && !d.is_verified() {
+                    } else if !Self::is_device_allowed(&d, settings) {
                         Either::Right((d, WithheldCode::Unverified))
                     } else {
                         Either::Left(d)
                     }
                 });
 
-            // If we haven't already concluded that the session should be
-            // rotated for other reasons, we also need to check whether any
-            // of the devices in the session got deleted or blacklisted in the
-            // meantime. If so, we should also rotate the session.
-            if !should_rotate {
-                // Device IDs that should receive this session
-                let recipient_device_ids: BTreeSet<&DeviceId> =
-                    recipients.iter().map(|d| d.device_id()).collect();
-
-                if let Some(shared) = outbound.shared_with_set.get(user_id) {
-                    // Devices that received this session
-                    let shared: BTreeSet<OwnedDeviceId> =
-                        shared.iter().map(|d| d.key().clone()).collect();
-                    let shared: BTreeSet<&DeviceId> = shared.iter().map(|d| d.as_ref()).collect();
-
-                    // The set difference between
-                    //
-                    // 1. Devices that had previously received the session, and
-                    // 2. Devices that would now receive the session
-                    //
-                    // Represents newly deleted or blacklisted devices. If this
-                    // set is non-empty, we must rotate.
-                    let newly_deleted_or_blacklisted =
-                        shared.difference(&recipient_device_ids).collect::<BTreeSet<_>>();
-
-                    should_rotate = !newly_deleted_or_blacklisted.is_empty();
-                };
+            let needs_user_approval = Self::identity_needs_user_approval(&user_devices);
+
+            if settings.sharing_strategy == CollectStrategy::ErrorOnIdentityChange {
+                if Self::has_verification_been_broken(&user_devices) {
+                    changed_identities.push(user_id.to_owned());
+                } else if needs_user_approval {
+                    unacknowledged_identities.push(user_id.to_owned());
+                }
+
+                let unsigned: Vec<OwnedDeviceId> = recipients
+                    .iter()
+                    .filter(|d| {
+                        d.is_device_owner_verified()
+                            && !d.is_cross_signed_by_owner()
+                            && !d.is_locally_trusted()
+                            && !d.is_our_own_device()
+                    })
+                    .map(|d| d.device_id().to_owned())
+                    .collect();
+
+                if !unsigned.is_empty() {
+                    unsigned_devices.insert(user_id.to_owned(), unsigned);
+                }
+            } else if needs_user_approval {
+                warn!(
+                    ?user_id,
+                    "Sharing a room key with a user whose identity change wasn't acknowledged"
+                );
             }
 
             devices.entry(user_id.to_owned()).or_default().extend(recipients);
             withheld_devices.extend(withheld_recipients);
         }
 
-        trace!(
-            should_rotate = should_rotate,
-            session_id = outbound.session_id(),
-            room_id = outbound.room_id().as_str(),
-            "Done calculating group session recipients"
-        );
+        // With the `ErrorOnIdentityChange` strategy, the room key isn't shared
+        // at all if one of the verified users has a problem.
+        if !changed_identities.is_empty() {
+            return Err(SessionRecipientCollectionError::VerifiedUserChangedIdentity(
+                changed_identities,
+            )
+            .into());
+        }
 
-        Ok(CollectRecipientsResult { should_rotate, devices, withheld_devices })
+        if !unacknowledged_identities.is_empty() {
+            return Err(SessionRecipientCollectionError::UnacknowledgedIdentityChange(
+                unacknowledged_identities,
+            )
+            .into());
+        }
+
+        if !unsigned_devices.is_empty() {
+            return Err(SessionRecipientCollectionError::VerifiedUserHasUnsignedDevice(
+                unsigned_devices,
+            )
+            .into());
+        }
+
+        Ok((devices, withheld_devices))
+    }
+
+    /// Should the given device receive the room key, according to the
+    /// sharing strategy of the room?
+    fn is_device_allowed(device: &Device, settings: &EncryptionSettings) -> bool {
+        if settings.only_allow_trusted_devices && !device.is_verified() {
+            return false;
+        }
+
+        match settings.sharing_strategy {
+            CollectStrategy::AllDevices | CollectStrategy::ErrorOnIdentityChange => true,
+            CollectStrategy::OnlyCrossSignedDevices => {
+                device.is_verified() || device.is_cross_signed_by_owner()
+            }
+            CollectStrategy::OnlyVerifiedUsers => device.is_cross_signing_trusted(),
+        }
+    }
+
+    /// Get the identity of the owner of the given devices, if it isn't our
+    /// own, and whether we verified it.
+    fn other_identity(user_devices: &UserDevices) -> Option<(&ReadOnlyUserIdentity, bool)> {
+        let identity = user_devices.device_owner_identity.as_ref()?.other()?;
+        let is_verified = user_devices
+            .own_identity
+            .as_ref()
+            .is_some_and(|own| own.is_verified() && own.is_identity_signed(identity).is_ok());
+
+        Some((identity, is_verified))
+    }
+
+    /// Was the owner of the given devices verified by us at some point, while
+    /// their current identity isn't verified anymore?
+    fn has_verification_been_broken(user_devices: &UserDevices) -> bool {
+        Self::other_identity(user_devices).is_some_and(|(identity, is_verified)| {
+            identity.was_previously_verified() && !is_verified
+        })
+    }
+
+    /// Has the owner of the given devices reset their identity without us
+    /// acknowledging it?
+    fn identity_needs_user_approval(user_devices: &UserDevices) -> bool {
+        Self::other_identity(user_devices)
+            .is_some_and(|(identity, is_verified)| identity.has_pin_violation() && !is_verified)
     }
 
     pub async fn encrypt_request(
@@ -849,6 +973,8 @@ impl GroupSessionManager {
 mod tests {
     use std::{collections::BTreeSet, ops::Deref, sync::Arc};
 
+    use assert_matches::assert_matches;
+    use futures_util::{FutureExt, StreamExt};
     use matrix_sdk_test::{async_test, response_from_file};
     use ruma::{
         api::{
@@ -867,14 +993,17 @@ mod tests {
     use serde_json::{json, Value};
 
     use crate::{
+        olm::{CollectStrategy, PrivateCrossSigningIdentity},
         session_manager::group_sessions::CollectRecipientsResult,
+        store::{Changes, IdentityChanges},
         types::{
             events::room_key_withheld::{
                 RoomKeyWithheldContent, RoomKeyWithheldContent::MegolmV1AesSha2, WithheldCode,
             },
             EventEncryptionAlgorithm,
         },
-        EncryptionSettings, LocalTrust, OlmMachine, ToDeviceRequest,
+        EncryptionSettings, LocalTrust, OlmError, OlmMachine, ReadOnlyUserIdentity,
+        SessionRecipientCollectionError, ToDeviceRequest,
     };
 
     fn alice_id() -> &'static UserId {
@@ -1001,6 +1130,59 @@ mod tests {
         machine
     }
 
+    async fn other_identity(machine: &OlmMachine, user_id: &UserId) -> ReadOnlyUserIdentity {
+        machine.store().get_identity(user_id).await.unwrap().unwrap().other().unwrap().inner
+    }
+
+    async fn save_identity(machine: &OlmMachine, identity: ReadOnlyUserIdentity) {
+        let changes = Changes {
+            identities: IdentityChanges { changed: vec![identity.into()], new: vec![] },
+            ..Default::default()
+        };
+        machine.store().save_changes(changes).await.unwrap();
+    }
+
+    /// Sign the identity of the given user with the user-signing key of the
+    /// machine, creating our own cross-signing identity if needed.
+    async fn verify_identity(machine: &OlmMachine, user_id: &UserId) {
+        machine.bootstrap_cross_signing(false).await.unwrap();
+
+        let identity = machine.get_identity(user_id, None).await.unwrap().unwrap();
+        identity.other().unwrap().verify().await.unwrap();
+
+        // Pretend that the signature was uploaded and that we got it back.
+        let mut identity = other_identity(machine, user_id).await;
+        let private_identity = machine.store().private_identity();
+        let master_key = private_identity
+            .lock()
+            .await
+            .user_signing_key
+            .lock()
+            .await
+            .as_ref()
+            .unwrap()
+            .sign_user(&identity)
+            .unwrap();
+
+        identity.master_key = master_key.try_into().unwrap();
+        save_identity(machine, identity).await;
+    }
+
+    /// Replace the cross-signing keys of the given user, as if they reset
+    /// their identity.
+    async fn reset_identity(machine: &OlmMachine, user_id: &UserId) {
+        let mut identity = other_identity(machine, user_id).await;
+        let new_identity = ReadOnlyUserIdentity::from_private(
+            &PrivateCrossSigningIdentity::new(user_id.to_owned()).await,
+        )
+        .await;
+
+        identity
+            .update(new_identity.master_key().clone(), new_identity.self_signing_key().clone())
+            .unwrap();
+        save_identity(machine, identity).await;
+    }
+
     #[async_test]
     async fn test_sharing() {
         let machine = machine().await;
@@ -1266,6 +1448,217 @@ mod tests {
         assert_eq!(149, withheld.len());
     }
 
+    #[async_test]
+    async fn key_recipient_collecting_with_strategies() {
+        let machine = machine().await;
+        let user_id = user_id!("@example:localhost");
+        let room_id = room_id!("!test:localhost");
+
+        let (outbound, _) = machine
+            .inner
+            .group_session_manager
+            .get_or_create_outbound_session(room_id, EncryptionSettings::default())
+            .await
+            .expect("We should be able to create a new session");
+
+        let device_id = device_id!("AFGUOBTZWM");
+        let device = machine.get_device(user_id, device_id, None).await.unwrap().unwrap();
+        device.set_local_trust(LocalTrust::Verified).await.unwrap();
+
+        // Only the devices that are cross-signed by their owner or that we
+        // verified manually receive the key.
+        let settings = EncryptionSettings {
+            sharing_strategy: CollectStrategy::OnlyCrossSignedDevices,
+            ..Default::default()
+        };
+        let CollectRecipientsResult { devices: recipients, withheld_devices: withheld, .. } =
+            machine
+                .inner
+                .group_session_manager
+                .collect_session_recipients([user_id].into_iter(), &settings, &outbound)
+                .await
+                .expect("We should be able to collect the session recipients");
+
+        assert!(recipients[user_id].iter().any(|d| d.device_id() == device_id));
+        assert!(recipients[user_id]
+            .iter()
+            .all(|d| d.is_verified() || d.is_cross_signed_by_owner()));
+        assert!(withheld.iter().all(|(d, code)| {
+            !d.is_cross_signed_by_owner()
+                && (d.is_blacklisted() || code == &WithheldCode::Unverified)
+        }));
+
+        // We don't have a verified identity, so no user can be verified.
+        let settings = EncryptionSettings {
+            sharing_strategy: CollectStrategy::OnlyVerifiedUsers,
+            ..Default::default()
+        };
+        let CollectRecipientsResult { devices: recipients, .. } = machine
+            .inner
+            .group_session_manager
+            .collect_session_recipients([user_id].into_iter(), &settings, &outbound)
+            .await
+            .expect("We should be able to collect the session recipients");
+
+        assert!(recipients[user_id].is_empty());
+
+        // No verified user changed their identity, so this is the same as
+        // sharing with all the devices.
+        let settings = EncryptionSettings {
+            sharing_strategy: CollectStrategy::ErrorOnIdentityChange,
+            ..Default::default()
+        };
+        let CollectRecipientsResult { devices: recipients, .. } = machine
+            .inner
+            .group_session_manager
+            .collect_session_recipients([user_id].into_iter(), &settings, &outbound)
+            .await
+            .expect("We should be able to collect the session recipients");
+
+        let CollectRecipientsResult { devices: all_recipients, .. } = machine
+            .inner
+            .group_session_manager
+            .collect_session_recipients(
+                [user_id].into_iter(),
+                &EncryptionSettings::default(),
+                &outbound,
+            )
+            .await
+            .expect("We should be able to collect the session recipients");
+
+        assert_eq!(recipients[user_id].len(), all_recipients[user_id].len());
+    }
+
+    #[async_test]
+    async fn key_recipient_collecting_with_verified_user_unsigned_devices() {
+        let machine = machine().await;
+        let user_id = user_id!("@example:localhost");
+        let room_id = room_id!("!test:localhost");
+        let settings = EncryptionSettings {
+            sharing_strategy: CollectStrategy::ErrorOnIdentityChange,
+            ..Default::default()
+        };
+
+        verify_identity(&machine, user_id).await;
+
+        // The verified user has devices that they didn't cross-sign, the room
+        // key isn't shared with any of them.
+        let error = machine
+            .share_room_key(room_id, [user_id].into_iter(), settings.clone())
+            .await
+            .unwrap_err();
+        let unsigned_devices = assert_matches!(
+            error,
+            OlmError::SessionRecipientCollectionError(
+                SessionRecipientCollectionError::VerifiedUserHasUnsignedDevice(devices)
+            ) => devices
+        );
+
+        let devices = machine.get_user_devices(user_id, None).await.unwrap();
+        assert!(unsigned_devices[user_id].contains(&device_id!("AFGUOBTZWM").to_owned()));
+        assert!(!unsigned_devices[user_id].contains(&device_id!("XOWLHHFSWM").to_owned()));
+        assert!(unsigned_devices[user_id]
+            .iter()
+            .all(|d| !devices.get(d).unwrap().is_cross_signed_by_owner()));
+
+        // Once the unsigned devices are blacklisted, the room key can be
+        // shared again.
+        for device_id in &unsigned_devices[user_id] {
+            devices.get(device_id).unwrap().set_local_trust(LocalTrust::BlackListed).await.unwrap();
+        }
+
+        machine.share_room_key(room_id, [user_id].into_iter(), settings).await.unwrap();
+    }
+
+    #[async_test]
+    async fn key_recipient_collecting_with_verified_user_identity_change() {
+        let machine = machine().await;
+        let user_id = user_id!("@example:localhost");
+        let room_id = room_id!("!test:localhost");
+        let settings = EncryptionSettings {
+            sharing_strategy: CollectStrategy::ErrorOnIdentityChange,
+            ..Default::default()
+        };
+
+        verify_identity(&machine, user_id).await;
+        reset_identity(&machine, user_id).await;
+
+        let error = machine
+            .share_room_key(room_id, [user_id].into_iter(), settings.clone())
+            .await
+            .unwrap_err();
+        assert_matches!(
+            error,
+            OlmError::SessionRecipientCollectionError(
+                SessionRecipientCollectionError::VerifiedUserChangedIdentity(users)
+            ) if users == [user_id.to_owned()]
+        );
+
+        // Withdrawing the verification isn't enough, the identity change
+        // still needs to be acknowledged.
+        let identity = machine.get_identity(user_id, None).await.unwrap().unwrap().other().unwrap();
+        identity.withdraw_verification().await.unwrap();
+
+        let error = machine
+            .share_room_key(room_id, [user_id].into_iter(), settings.clone())
+            .await
+            .unwrap_err();
+        assert_matches!(
+            error,
+            OlmError::SessionRecipientCollectionError(
+                SessionRecipientCollectionError::UnacknowledgedIdentityChange(users)
+            ) if users == [user_id.to_owned()]
+        );
+
+        identity.acknowledge_identity_change().await.unwrap();
+        machine.share_room_key(room_id, [user_id].into_iter(), settings).await.unwrap();
+    }
+
+    #[async_test]
+    async fn key_recipient_collecting_with_unacknowledged_identity_change() {
+        let machine = machine().await;
+        let user_id = user_id!("@example:localhost");
+        let room_id = room_id!("!test:localhost");
+        let settings = EncryptionSettings {
+            sharing_strategy: CollectStrategy::ErrorOnIdentityChange,
+            ..Default::default()
+        };
+
+        let mut identities_stream = machine.store().user_identities_stream();
+        reset_identity(&machine, user_id).await;
+
+        // The identity change is sent to the stream, and needs to be approved.
+        let updates = identities_stream.next().now_or_never().flatten().unwrap();
+        let identity = updates.changed[user_id].clone().other().unwrap();
+        assert!(identity.identity_needs_user_approval());
+
+        // The room key can't be shared until the change is acknowledged.
+        let error = machine
+            .share_room_key(room_id, [user_id].into_iter(), settings.clone())
+            .await
+            .unwrap_err();
+        assert_matches!(
+            error,
+            OlmError::SessionRecipientCollectionError(
+                SessionRecipientCollectionError::UnacknowledgedIdentityChange(users)
+            ) if users == [user_id.to_owned()]
+        );
+
+        // Other strategies still share the room key.
+        machine
+            .share_room_key(room_id, [user_id].into_iter(), EncryptionSettings::default())
+            .await
+            .unwrap();
+
+        identity.acknowledge_identity_change().await.unwrap();
+
+        let updates = identities_stream.next().now_or_never().flatten().unwrap();
+        let identity = updates.changed[user_id].clone().other().unwrap();
+        assert!(!identity.identity_needs_user_approval());
+
+        machine.share_room_key(room_id, [user_id].into_iter(), settings).await.unwrap();
+    }
+
     #[async_test]
     async fn test_sharing_withheld_only_trusted() {
         let machine = machine().await;
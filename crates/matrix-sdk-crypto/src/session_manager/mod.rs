@@ -17,3 +17,4 @@ mod sessions;
 
 pub(crate) use group_sessions::{GroupSessionCache, GroupSessionManager};
 pub(crate) use sessions::SessionManager;
+pub use sessions::{UnwedgingPolicy, WedgedDevicesStats};
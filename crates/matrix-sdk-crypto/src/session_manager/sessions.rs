@@ -28,6 +28,7 @@ use ruma::{
     DeviceId, DeviceKeyAlgorithm, OwnedDeviceId, OwnedServerName, OwnedTransactionId, OwnedUserId,
     SecondsSinceUnixEpoch, ServerName, TransactionId, UserId,
 };
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info, warn};
 use vodozemac::Curve25519PublicKey;
 
@@ -42,6 +43,55 @@ use crate::{
     ReadOnlyDevice,
 };
 
+/// The policy deciding whether we try to fix, or unwedge, Olm sessions that
+/// another device can't decrypt with anymore.
+///
+/// When a to-device message can't be decrypted with any of the Olm sessions
+/// we share with its sender, the session is considered to be wedged. The
+/// only way to recover is to claim a new one-time key of the device, create a
+/// new Olm session, and send an `m.dummy` event over it so the other side
+/// creates the session as well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UnwedgingPolicy {
+    /// Should new Olm sessions be created automatically for wedged devices.
+    pub enabled: bool,
+    /// The minimal age the newest Olm session with a device needs to have
+    /// before we create a new one to unwedge it.
+    ///
+    /// This prevents two devices from replacing their sessions with each
+    /// other in a loop, and limits the amount of one-time keys we claim.
+    pub min_interval: Duration,
+}
+
+impl Default for UnwedgingPolicy {
+    fn default() -> Self {
+        Self { enabled: true, min_interval: Duration::from_secs(60 * 60) }
+    }
+}
+
+/// Statistics about the wedged devices of a single user, see
+/// [`OlmMachine::wedged_devices_stats()`].
+///
+/// The statistics are only kept in memory, they are reset every time the
+/// [`OlmMachine`] is created.
+///
+/// [`OlmMachine`]: crate::OlmMachine
+/// [`OlmMachine::wedged_devices_stats()`]: crate::OlmMachine::wedged_devices_stats
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WedgedDevicesStats {
+    /// The devices that are wedged, and for which a new Olm session still
+    /// needs to be created.
+    pub pending: BTreeSet<OwnedDeviceId>,
+    /// How many times a device of the user was found to be wedged.
+    pub wedged: u64,
+    /// How many times we created a new Olm session to unwedge a device of the
+    /// user.
+    pub unwedged: u64,
+    /// How many times we didn't try to unwedge a device of the user, because
+    /// the [`UnwedgingPolicy`] disallowed it.
+    pub skipped: u64,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct SessionManager {
     account: Account,
@@ -52,6 +102,7 @@ pub(crate) struct SessionManager {
     /// [`get_missing_sessions`](#method.get_missing_sessions) is called.
     users_for_key_claim: Arc<DashMap<OwnedUserId, DashSet<OwnedDeviceId>>>,
     wedged_devices: Arc<DashMap<OwnedUserId, DashSet<OwnedDeviceId>>>,
+    wedged_devices_stats: Arc<DashMap<OwnedUserId, WedgedDevicesStats>>,
     key_request_machine: GossipMachine,
     outgoing_to_device_requests: Arc<DashMap<OwnedTransactionId, OutgoingRequest>>,
     failures: FailuresCache<OwnedServerName>,
@@ -60,7 +111,6 @@ pub(crate) struct SessionManager {
 
 impl SessionManager {
     const KEY_CLAIM_TIMEOUT: Duration = Duration::from_secs(10);
-    const UNWEDGING_INTERVAL: Duration = Duration::from_secs(60 * 60);
     const KEYS_QUERY_WAIT_TIME: Duration = Duration::from_secs(5);
 
     pub fn new(
@@ -75,59 +125,100 @@ impl SessionManager {
             key_request_machine,
             users_for_key_claim,
             wedged_devices: Default::default(),
+            wedged_devices_stats: Default::default(),
             outgoing_to_device_requests: Default::default(),
             failures: Default::default(),
             failed_devices: Default::default(),
         }
     }
 
+    /// Get the to-device requests, e.g. the `m.dummy` events used to unwedge
+    /// devices, that need to be sent out.
+    pub fn outgoing_to_device_requests(&self) -> Vec<OutgoingRequest> {
+        self.outgoing_to_device_requests.iter().map(|r| r.value().clone()).collect()
+    }
+
     /// Mark the outgoing request as sent.
     pub fn mark_outgoing_request_as_sent(&self, id: &TransactionId) {
         self.outgoing_to_device_requests.remove(id);
     }
 
+    /// Mark the device owning the given Curve25519 key as wedged.
+    ///
+    /// Depending on the [`UnwedgingPolicy`], a new Olm session will be created
+    /// with the device the next time [`SessionManager::get_missing_sessions`]
+    /// is called.
     pub async fn mark_device_as_wedged(
         &self,
         sender: &UserId,
         curve_key: Curve25519PublicKey,
     ) -> StoreResult<()> {
-        if let Some(device) = self.store.get_device_from_curve_key(sender, curve_key).await? {
-            let sessions = device.get_sessions().await?;
+        let Some(device) = self.store.get_device_from_curve_key(sender, curve_key).await? else {
+            return Ok(());
+        };
 
-            if let Some(sessions) = sessions {
-                let mut sessions = sessions.lock().await;
-                sessions.sort_by_key(|s| s.creation_time);
+        let Some(sessions) = device.get_sessions().await? else {
+            return Ok(());
+        };
 
-                let session = sessions.get(0);
+        // Unwedging a device creates a new session, so only the newest session
+        // tells us whether we recently tried to unwedge the device already. The
+        // oldest session stays around after unwedging, checking its age instead
+        // would let us replace the session with every undecryptable message.
+        let Some(creation_time) = sessions.lock().await.iter().map(|s| s.creation_time).max()
+        else {
+            return Ok(());
+        };
 
-                if let Some(session) = session {
-                    info!(sender_key = ?curve_key, "Marking session to be unwedged");
+        info!(sender_key = ?curve_key, "Marking session to be unwedged");
 
-                    let creation_time = Duration::from_secs(session.creation_time.get().into());
-                    let now = Duration::from_secs(SecondsSinceUnixEpoch::now().get().into());
+        let policy = self.store.get_unwedging_policy().await?;
 
-                    let should_unwedge = now
-                        .checked_sub(creation_time)
-                        .map(|elapsed| elapsed > Self::UNWEDGING_INTERVAL)
-                        .unwrap_or(true);
+        let creation_time = Duration::from_secs(creation_time.get().into());
+        let now = Duration::from_secs(SecondsSinceUnixEpoch::now().get().into());
 
-                    if should_unwedge {
-                        self.users_for_key_claim
-                            .entry(device.user_id().to_owned())
-                            .or_default()
-                            .insert(device.device_id().into());
-                        self.wedged_devices
-                            .entry(device.user_id().to_owned())
-                            .or_default()
-                            .insert(device.device_id().into());
-                    }
-                }
-            }
+        let should_unwedge = policy.enabled
+            && now
+                .checked_sub(creation_time)
+                .map(|elapsed| elapsed >= policy.min_interval)
+                .unwrap_or(true);
+
+        let mut stats = self.wedged_devices_stats.entry(device.user_id().to_owned()).or_default();
+        stats.wedged += 1;
+
+        if should_unwedge {
+            stats.pending.insert(device.device_id().to_owned());
+
+            self.users_for_key_claim
+                .entry(device.user_id().to_owned())
+                .or_default()
+                .insert(device.device_id().into());
+            self.wedged_devices
+                .entry(device.user_id().to_owned())
+                .or_default()
+                .insert(device.device_id().into());
+        } else {
+            info!(
+                sender_key = ?curve_key,
+                policy_enabled = policy.enabled,
+                "Not unwedging the session, the unwedging policy doesn't allow it"
+            );
+
+            stats.skipped += 1;
         }
 
         Ok(())
     }
 
+    /// Get the statistics about the wedged devices of every user we had a
+    /// wedged Olm session with.
+    pub fn wedged_devices_stats(&self) -> BTreeMap<OwnedUserId, WedgedDevicesStats> {
+        self.wedged_devices_stats
+            .iter()
+            .map(|entry| (entry.key().to_owned(), entry.value().to_owned()))
+            .collect()
+    }
+
     #[allow(dead_code)]
     pub fn is_device_wedged(&self, device: &ReadOnlyDevice) -> bool {
         self.wedged_devices.get(device.user_id()).is_some_and(|d| d.contains(device.device_id()))
@@ -138,6 +229,17 @@ impl SessionManager {
     /// If the device was wedged this will queue up a dummy to-device message.
     async fn check_if_unwedged(&self, user_id: &UserId, device_id: &DeviceId) -> OlmResult<()> {
         if self.wedged_devices.get(user_id).and_then(|d| d.remove(device_id)).is_some() {
+            if let Some(mut stats) = self.wedged_devices_stats.get_mut(user_id) {
+                stats.pending.remove(device_id);
+                stats.unwedged += 1;
+            }
+
+            // Our key requests only go to our own devices. If one of them
+            // answered over the wedged session, we never got the answer.
+            if user_id == self.store.user_id() {
+                self.key_request_machine.resend_sent_requests().await?;
+            }
+
             if let Some(device) = self.store.get_device(user_id, device_id).await? {
                 let content = serde_json::to_value(ToDeviceDummyEventContent::new())?;
                 let (_, content) = device.encrypt("m.dummy", content).await?;
@@ -441,7 +543,7 @@ impl SessionManager {
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::BTreeMap, iter, ops::Deref, sync::Arc};
+    use std::{collections::BTreeMap, iter, ops::Deref, sync::Arc, time::Duration};
 
     use dashmap::DashMap;
     use matrix_sdk_test::{async_test, response_from_file};
@@ -459,7 +561,7 @@ mod tests {
     use tokio::sync::Mutex;
     use tracing::info;
 
-    use super::SessionManager;
+    use super::{SessionManager, UnwedgingPolicy};
     use crate::{
         gossiping::GossipMachine,
         identities::{IdentityManager, ReadOnlyDevice},
@@ -631,7 +733,8 @@ mod tests {
     }
 
     // This test doesn't run on macos because we're modifying the session
-    // creation time so we can get around the UNWEDGING_INTERVAL.
+    // creation time so we can get around the minimal interval of the default
+    // UnwedgingPolicy.
     #[async_test]
     #[cfg(target_os = "linux")]
     async fn session_unwedging() {
@@ -659,6 +762,11 @@ mod tests {
         assert!(manager.is_device_wedged(&bob_device));
         assert!(manager.users_for_key_claim.contains_key(bob.user_id()));
 
+        let stats = manager.wedged_devices_stats().remove(bob.user_id()).unwrap();
+        assert!(stats.pending.contains(bob.device_id()));
+        assert_eq!(stats.wedged, 1);
+        assert_eq!(stats.unwedged, 0);
+
         let (_, request) =
             manager.get_missing_sessions(iter::once(bob.user_id())).await.unwrap().unwrap();
 
@@ -683,7 +791,64 @@ mod tests {
 
         assert!(!manager.is_device_wedged(&bob_device));
         assert!(manager.get_missing_sessions(iter::once(bob.user_id())).await.unwrap().is_none());
-        assert!(!manager.outgoing_to_device_requests.is_empty())
+        assert!(!manager.outgoing_to_device_requests.is_empty());
+
+        let stats = manager.wedged_devices_stats().remove(bob.user_id()).unwrap();
+        assert!(stats.pending.is_empty());
+        assert_eq!(stats.unwedged, 1);
+
+        // The old session is still around, but the new one is too recent for the
+        // device to be unwedged again.
+        manager.mark_device_as_wedged(bob_device.user_id(), curve_key).await.unwrap();
+        assert!(!manager.is_device_wedged(&bob_device));
+
+        let stats = manager.wedged_devices_stats().remove(bob.user_id()).unwrap();
+        assert_eq!(stats.wedged, 2);
+        assert_eq!(stats.skipped, 1);
+    }
+
+    #[async_test]
+    async fn session_unwedging_policy() {
+        let manager = session_manager().await;
+        let bob = bob_account();
+        let (_, session) = bob.create_session_for(&manager.account).await;
+
+        let bob_device = ReadOnlyDevice::from_account(&bob).await;
+        manager.store.save_devices(&[bob_device.clone()]).await.unwrap();
+        manager.store.save_sessions(&[session]).await.unwrap();
+
+        let curve_key = bob_device.curve25519_key().unwrap();
+
+        // The session was just created, so the default policy doesn't allow us to
+        // replace it yet.
+        manager.mark_device_as_wedged(bob_device.user_id(), curve_key).await.unwrap();
+        assert!(!manager.is_device_wedged(&bob_device));
+        assert!(!manager.users_for_key_claim.contains_key(bob.user_id()));
+
+        // Neither does a disabled policy.
+        let policy = UnwedgingPolicy { enabled: false, min_interval: Duration::ZERO };
+        manager.store.set_unwedging_policy(policy).await.unwrap();
+        manager.mark_device_as_wedged(bob_device.user_id(), curve_key).await.unwrap();
+        assert!(!manager.is_device_wedged(&bob_device));
+
+        let stats = manager.wedged_devices_stats().remove(bob.user_id()).unwrap();
+        assert!(stats.pending.is_empty());
+        assert_eq!(stats.wedged, 2);
+        assert_eq!(stats.skipped, 2);
+
+        // Once enabled, the device gets unwedged.
+        let policy = UnwedgingPolicy { enabled: true, min_interval: Duration::ZERO };
+        manager.store.set_unwedging_policy(policy).await.unwrap();
+        assert_eq!(manager.store.get_unwedging_policy().await.unwrap(), policy);
+
+        manager.mark_device_as_wedged(bob_device.user_id(), curve_key).await.unwrap();
+        assert!(manager.is_device_wedged(&bob_device));
+        assert!(manager.users_for_key_claim.contains_key(bob.user_id()));
+
+        let stats = manager.wedged_devices_stats().remove(bob.user_id()).unwrap();
+        assert!(stats.pending.contains(bob.device_id()));
+        assert_eq!(stats.wedged, 3);
+        assert_eq!(stats.skipped, 2);
     }
 
     #[async_test]
@@ -71,6 +71,11 @@ impl SessionStore {
     pub fn set_for_sender(&self, sender_key: &str, sessions: Vec<Session>) {
         self.entries.insert(sender_key.to_owned(), Arc::new(Mutex::new(sessions)));
     }
+
+    /// Remove all the sessions from the store.
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -105,6 +110,11 @@ impl GroupSessionStore {
             .collect()
     }
 
+    /// Get all the group sessions the store knows about for the given room.
+    pub fn get_for_room(&self, room_id: &RoomId) -> Vec<InboundGroupSession> {
+        self.entries.get(room_id).map(|keys| keys.values().cloned().collect()).unwrap_or_default()
+    }
+
     /// Get the number of `InboundGroupSession`s we have.
     pub fn count(&self) -> usize {
         self.entries.iter().map(|d| d.value().len()).sum()
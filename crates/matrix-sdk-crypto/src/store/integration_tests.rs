@@ -18,7 +18,7 @@ macro_rules! cryptostore_integration_tests {
             use serde_json::value::to_raw_value;
             use $crate::{
                 olm::{
-                    Curve25519PublicKey, InboundGroupSession, OlmMessageHash,
+                    CollectStrategy, Curve25519PublicKey, InboundGroupSession, OlmMessageHash,
                     PrivateCrossSigningIdentity, ReadOnlyAccount, Session,
                 },
                 store::{
@@ -282,6 +282,15 @@ macro_rules! cryptostore_integration_tests {
                     .unwrap();
                 assert_eq!(session, loaded_session);
                 assert_eq!(store.get_inbound_group_sessions().await.unwrap().len(), 1);
+                assert_eq!(
+                    store.get_inbound_group_sessions_for_room(room_id).await.unwrap(),
+                    vec![session.clone()]
+                );
+                assert!(store
+                    .get_inbound_group_sessions_for_room(room_id!("!other:localhost"))
+                    .await
+                    .unwrap()
+                    .is_empty());
                 assert_eq!(store.inbound_group_session_counts().await.unwrap().total, 1);
                 assert_eq!(store.inbound_group_session_counts().await.unwrap().backed_up, 0);
 
@@ -615,6 +624,7 @@ macro_rules! cryptostore_integration_tests {
                 let stored_request = store.get_secret_request_by_info(&info).await.unwrap();
                 assert_eq!(request, stored_request);
                 assert!(!store.get_unsent_secret_requests().await.unwrap().is_empty());
+                assert!(store.get_sent_secret_requests().await.unwrap().is_empty());
 
                 let request = GossipRequest {
                     request_recipient: account.user_id().to_owned(),
@@ -628,6 +638,7 @@ macro_rules! cryptostore_integration_tests {
                 store.save_changes(changes).await.unwrap();
 
                 assert!(store.get_unsent_secret_requests().await.unwrap().is_empty());
+                assert_eq!(store.get_sent_secret_requests().await.unwrap(), [request.clone()]);
                 let stored_request = store.get_outgoing_secret_requests(&id).await.unwrap();
                 assert_eq!(Some(request), stored_request);
 
@@ -639,6 +650,7 @@ macro_rules! cryptostore_integration_tests {
                 let stored_request = store.get_secret_request_by_info(&info).await.unwrap();
                 assert_eq!(None, stored_request);
                 assert!(store.get_unsent_secret_requests().await.unwrap().is_empty());
+                assert!(store.get_sent_secret_requests().await.unwrap().is_empty());
             }
 
             #[async_test]
@@ -727,12 +739,14 @@ macro_rules! cryptostore_integration_tests {
                 let settings_1 = RoomSettings {
                     algorithm: EventEncryptionAlgorithm::MegolmV1AesSha2,
                     only_allow_trusted_devices: true,
+                    sharing_strategy: Some(CollectStrategy::OnlyVerifiedUsers),
                 };
 
                 let room_2 = room_id!("!test_2:localhost");
                 let settings_2 = RoomSettings {
                     algorithm: EventEncryptionAlgorithm::OlmV1Curve25519AesSha2,
                     only_allow_trusted_devices: false,
+                    sharing_strategy: None,
                 };
 
                 let room_3 = room_id!("!test_3:localhost");
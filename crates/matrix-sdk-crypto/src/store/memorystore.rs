@@ -15,7 +15,7 @@
 use std::{
     collections::HashMap,
     convert::Infallible,
-    sync::Arc,
+    sync::{Arc, RwLock as StdRwLock},
     time::{Duration, Instant},
 };
 
@@ -30,8 +30,8 @@ use tracing::warn;
 
 use super::{
     caches::{DeviceStore, GroupSessionStore, SessionStore},
-    BackupKeys, Changes, CryptoStore, InboundGroupSession, ReadOnlyAccount, RoomKeyCounts,
-    RoomSettings, Session,
+    BackupKeys, Changes, CryptoStore, InboundGroupSession, ReadOnlyAccount, RecoveryKey,
+    RoomKeyCounts, RoomSettings, Session,
 };
 use crate::{
     gossiping::{GossipRequest, SecretInfo},
@@ -63,6 +63,8 @@ pub struct MemoryStore {
     direct_withheld_info: Arc<DashMap<OwnedRoomId, DashMap<String, RoomKeyWithheldEvent>>>,
     custom_values: Arc<DashMap<String, Vec<u8>>>,
     leases: Arc<DashMap<String, (String, Instant)>>,
+    recovery_key: Arc<StdRwLock<Option<RecoveryKey>>>,
+    backup_version: Arc<StdRwLock<Option<String>>>,
 }
 
 impl Default for MemoryStore {
@@ -78,6 +80,8 @@ impl Default for MemoryStore {
             direct_withheld_info: Default::default(),
             custom_values: Default::default(),
             leases: Default::default(),
+            recovery_key: Default::default(),
+            backup_version: Default::default(),
         }
     }
 }
@@ -168,6 +172,14 @@ impl CryptoStore for MemoryStore {
             }
         }
 
+        if let Some(recovery_key) = changes.recovery_key {
+            *self.recovery_key.write().unwrap() = Some(recovery_key);
+        }
+
+        if let Some(backup_version) = changes.backup_version {
+            *self.backup_version.write().unwrap() = Some(backup_version);
+        }
+
         Ok(())
     }
 
@@ -187,6 +199,13 @@ impl CryptoStore for MemoryStore {
         Ok(self.inbound_group_sessions.get_all())
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        Ok(self.inbound_group_sessions.get_for_room(room_id))
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let backed_up =
             self.get_inbound_group_sessions().await?.into_iter().filter(|s| s.backed_up()).count();
@@ -282,6 +301,15 @@ impl CryptoStore for MemoryStore {
             .collect())
     }
 
+    async fn get_sent_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        Ok(self
+            .outgoing_key_requests
+            .iter()
+            .filter(|i| i.value().sent_out)
+            .map(|i| i.value().clone())
+            .collect())
+    }
+
     async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
         self.outgoing_key_requests.remove(request_id).and_then(|(_, i)| {
             let key_info_string = encode_key_info(&i.info);
@@ -292,7 +320,15 @@ impl CryptoStore for MemoryStore {
     }
 
     async fn load_backup_keys(&self) -> Result<BackupKeys> {
-        Ok(BackupKeys::default())
+        let recovery_key = self
+            .recovery_key
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|key| RecoveryKey { inner: key.inner.clone() });
+        let backup_version = self.backup_version.read().unwrap().clone();
+
+        Ok(BackupKeys { recovery_key, backup_version })
     }
 
     async fn get_withheld_info(
@@ -346,13 +382,7 @@ impl CryptoStore for MemoryStore {
                 }
             }
         } else {
-            self.leases.insert(
-                key.to_owned(),
-                (
-                    holder.to_owned(),
-                    Instant::now() + Duration::from_millis(lease_duration_ms.into()),
-                ),
-            );
+            self.leases.insert(key.to_owned(), (holder.to_owned(), expiration));
             Ok(true)
         }
     }
@@ -52,7 +52,8 @@ use dashmap::DashSet;
 use futures_core::Stream;
 use futures_util::stream::StreamExt;
 use ruma::{
-    events::secret::request::SecretName, DeviceId, OwnedDeviceId, OwnedRoomId, OwnedUserId, UserId,
+    events::secret::request::SecretName, uint, DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId,
+    OwnedRoomId, OwnedUserId, RoomId, SecondsSinceUnixEpoch, UInt, UserId,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
@@ -68,13 +69,13 @@ use crate::{
         Device, ReadOnlyDevice, ReadOnlyUserIdentities, UserDevices,
     },
     olm::{
-        InboundGroupSession, OlmMessageHash, OutboundGroupSession, PrivateCrossSigningIdentity,
-        ReadOnlyAccount, Session,
+        CollectStrategy, ExportedRoomKey, InboundGroupSession, OlmMessageHash,
+        OutboundGroupSession, PrivateCrossSigningIdentity, ReadOnlyAccount, Session,
     },
     types::{events::room_key_withheld::RoomKeyWithheldEvent, EventEncryptionAlgorithm},
     utilities::encode,
     verification::VerificationMachine,
-    CrossSigningStatus,
+    CrossSigningStatus, UnwedgingPolicy,
 };
 
 pub mod caches;
@@ -94,7 +95,7 @@ use matrix_sdk_common::timeout::timeout;
 pub use memorystore::MemoryStore;
 pub use traits::{CryptoStore, DynCryptoStore, IntoCryptoStore};
 
-use self::locks::CryptoStoreLock;
+use self::locks::{CryptoStoreLock, LockableCryptoStore};
 pub use crate::gossiping::{GossipRequest, SecretInfo};
 
 /// A wrapper for our CryptoStore trait object.
@@ -132,6 +133,10 @@ struct StoreInner {
     /// The sender side of a broadcast stream that is notified whenever we get
     /// an update to an inbound group session.
     room_keys_received_sender: broadcast::Sender<Vec<RoomKeyInfo>>,
+
+    /// The sender side of a broadcast stream that is notified whenever user
+    /// identities are created or updated.
+    identities_sender: broadcast::Sender<IdentityUpdates>,
 }
 
 #[derive(Default, Debug)]
@@ -193,6 +198,19 @@ impl IdentityChanges {
     }
 }
 
+/// Updates to the user identities, as sent by
+/// [`Store::user_identities_stream()`].
+#[derive(Debug, Clone, Default)]
+pub struct IdentityUpdates {
+    /// Identities that we saw for the first time.
+    pub new: BTreeMap<OwnedUserId, UserIdentities>,
+    /// Identities that we already knew about, and that were updated.
+    ///
+    /// Use [`UserIdentity::identity_needs_user_approval()`] to check if a
+    /// user has reset their identity.
+    pub changed: BTreeMap<OwnedUserId, UserIdentities>,
+}
+
 #[derive(Debug, Clone, Default)]
 #[allow(missing_docs)]
 pub struct DeviceChanges {
@@ -329,6 +347,10 @@ pub struct RoomSettings {
     /// Should untrusted devices receive the room key, or should they be
     /// excluded from the conversation.
     pub only_allow_trusted_devices: bool,
+    /// The strategy used to select the devices that receive the room key, if
+    /// it differs from the global one.
+    #[serde(default)]
+    pub sharing_strategy: Option<CollectStrategy>,
 }
 
 impl Default for RoomSettings {
@@ -336,6 +358,7 @@ impl Default for RoomSettings {
         Self {
             algorithm: EventEncryptionAlgorithm::MegolmV1AesSha2,
             only_allow_trusted_devices: false,
+            sharing_strategy: None,
         }
     }
 }
@@ -370,6 +393,37 @@ impl From<&InboundGroupSession> for RoomKeyInfo {
     }
 }
 
+/// A room key that was shared with us as the history of a room we were invited
+/// to, and that is held back until we join the room.
+#[derive(Deserialize, Serialize)]
+struct PendingRoomKeyHistory {
+    /// The user that shared the room key with us.
+    sender: OwnedUserId,
+    /// The shared room key.
+    room_key: ExportedRoomKey,
+}
+
+/// The user whose room key history we accept for a room we joined, because
+/// it didn't arrive before we joined.
+#[derive(Deserialize, Serialize)]
+struct RoomKeyHistoryInviter {
+    /// The user that invited us to the room.
+    inviter: OwnedUserId,
+    /// When we joined the room.
+    joined_at: MilliSecondsSinceUnixEpoch,
+}
+
+impl RoomKeyHistoryInviter {
+    /// For how long, in seconds, the room key history is accepted after we
+    /// joined the room.
+    const ACCEPTANCE_WINDOW: UInt = uint!(600);
+
+    fn has_expired(&self) -> bool {
+        let now = SecondsSinceUnixEpoch::now().get();
+        now.saturating_sub(self.joined_at.as_secs()) > Self::ACCEPTANCE_WINDOW
+    }
+}
+
 impl Store {
     /// Create a new Store
     pub(crate) fn new(
@@ -379,6 +433,7 @@ impl Store {
         verification_machine: VerificationMachine,
     ) -> Self {
         let (room_keys_received_sender, _) = broadcast::channel(10);
+        let (identities_sender, _) = broadcast::channel(10);
         let inner = Arc::new(StoreInner {
             user_id,
             identity,
@@ -390,6 +445,7 @@ impl Store {
             tracked_users_loaded: AtomicBool::new(false),
             tracked_user_loading_lock: Mutex::new(()),
             room_keys_received_sender,
+            identities_sender,
         });
         Self { inner }
     }
@@ -431,6 +487,11 @@ impl Store {
         let room_key_updates: Vec<_> =
             changes.inbound_group_sessions.iter().map(RoomKeyInfo::from).collect();
 
+        // Only clone the identities if someone is interested in them.
+        let identity_changes = (self.inner.identities_sender.receiver_count() > 0
+            && !changes.identities.is_empty())
+        .then(|| changes.identities.clone());
+
         self.inner.store.save_changes(changes).await?;
 
         if !room_key_updates.is_empty() {
@@ -438,9 +499,46 @@ impl Store {
             let _ = self.inner.room_keys_received_sender.send(room_key_updates);
         }
 
+        if let Some(identity_changes) = identity_changes {
+            let updates = self.identity_updates(identity_changes).await?;
+            // Ignore the result. It can only fail if there are no listeners.
+            let _ = self.inner.identities_sender.send(updates);
+        }
+
         Ok(())
     }
 
+    /// Wrap the given identity changes, so they can be sent to the
+    /// identities stream.
+    async fn identity_updates(&self, changes: IdentityChanges) -> Result<IdentityUpdates> {
+        let own_identity =
+            self.inner.store.get_user_identity(self.user_id()).await?.and_then(|i| i.into_own());
+
+        let wrap = |identity: ReadOnlyUserIdentities| {
+            let user_id = identity.user_id().to_owned();
+            let identity: UserIdentities = match identity {
+                ReadOnlyUserIdentities::Own(i) => OwnUserIdentity {
+                    inner: i,
+                    verification_machine: self.inner.verification_machine.clone(),
+                }
+                .into(),
+                ReadOnlyUserIdentities::Other(i) => UserIdentity {
+                    inner: i,
+                    verification_machine: self.inner.verification_machine.clone(),
+                    own_identity: own_identity.clone(),
+                }
+                .into(),
+            };
+
+            (user_id, identity)
+        };
+
+        Ok(IdentityUpdates {
+            new: changes.new.into_iter().map(wrap).collect(),
+            changed: changes.changed.into_iter().map(wrap).collect(),
+        })
+    }
+
     /// Compare the given `InboundGroupSession` with an existing session we have
     /// in the store.
     ///
@@ -939,6 +1037,166 @@ impl Store {
         self.set_value("only_allow_trusted_devices", &block_untrusted_devices).await
     }
 
+    /// Get the global strategy used to select the devices that receive room
+    /// keys, for rooms that don't have a strategy of their own.
+    pub async fn get_sharing_strategy(&self) -> Result<CollectStrategy> {
+        let value = self.get_value("sharing_strategy").await?.unwrap_or_default();
+        Ok(value)
+    }
+
+    /// Set the global strategy used to select the devices that receive room
+    /// keys.
+    pub async fn set_sharing_strategy(&self, strategy: CollectStrategy) -> Result<()> {
+        self.set_value("sharing_strategy", &strategy).await
+    }
+
+    /// Get the policy deciding whether wedged Olm sessions are replaced
+    /// automatically.
+    pub async fn get_unwedging_policy(&self) -> Result<UnwedgingPolicy> {
+        let value = self.get_value("unwedging_policy").await?.unwrap_or_default();
+        Ok(value)
+    }
+
+    /// Set the policy deciding whether wedged Olm sessions are replaced
+    /// automatically.
+    pub async fn set_unwedging_policy(&self, policy: UnwedgingPolicy) -> Result<()> {
+        self.set_value("unwedging_policy", &policy).await
+    }
+
+    /// Hold back the room keys that `sender` shared with us as the history of
+    /// a room we were invited to, until we join the room.
+    pub(crate) async fn save_pending_room_key_history(
+        &self,
+        sender: &UserId,
+        room_keys: Vec<ExportedRoomKey>,
+    ) -> Result<()> {
+        let mut pending: Vec<PendingRoomKeyHistory> =
+            self.get_value("pending_room_key_history").await?.unwrap_or_default();
+
+        // Only keep the latest copy of a room key a user sent us.
+        pending.retain(|p| {
+            p.sender != sender
+                || !room_keys.iter().any(|k| {
+                    p.room_key.room_id == k.room_id && p.room_key.session_id == k.session_id
+                })
+        });
+        pending.extend(
+            room_keys
+                .into_iter()
+                .map(|room_key| PendingRoomKeyHistory { sender: sender.to_owned(), room_key }),
+        );
+
+        self.set_value("pending_room_key_history", &pending).await
+    }
+
+    /// Take the room keys that `sender` shared with us as the history of the
+    /// given room.
+    ///
+    /// The room keys other users shared for this room are discarded.
+    pub(crate) async fn take_pending_room_key_history(
+        &self,
+        room_id: &RoomId,
+        sender: &UserId,
+    ) -> Result<Vec<ExportedRoomKey>> {
+        let pending: Vec<PendingRoomKeyHistory> =
+            self.get_value("pending_room_key_history").await?.unwrap_or_default();
+
+        let (for_room, remaining): (Vec<_>, Vec<_>) =
+            pending.into_iter().partition(|p| p.room_key.room_id == room_id);
+
+        if !for_room.is_empty() {
+            self.set_value("pending_room_key_history", &remaining).await?;
+        }
+
+        Ok(for_room.into_iter().filter(|p| p.sender == sender).map(|p| p.room_key).collect())
+    }
+
+    /// Accept the room key history `inviter` shares with us for the given
+    /// room for a while, because we joined it but didn't receive it yet.
+    pub(crate) async fn save_room_key_history_inviter(
+        &self,
+        room_id: &RoomId,
+        inviter: &UserId,
+    ) -> Result<()> {
+        let mut inviters: BTreeMap<OwnedRoomId, RoomKeyHistoryInviter> =
+            self.get_value("room_key_history_inviters").await?.unwrap_or_default();
+
+        inviters.retain(|_, i| !i.has_expired());
+        inviters.insert(
+            room_id.to_owned(),
+            RoomKeyHistoryInviter {
+                inviter: inviter.to_owned(),
+                joined_at: MilliSecondsSinceUnixEpoch::now(),
+            },
+        );
+
+        self.set_value("room_key_history_inviters", &inviters).await
+    }
+
+    /// Check whether we accept the room key history that `sender` shares with
+    /// us for the given room, see [`Store::save_room_key_history_inviter()`].
+    ///
+    /// The room key history is only accepted once, so we stop accepting it if
+    /// this returns `true`.
+    pub(crate) async fn take_room_key_history_inviter(
+        &self,
+        room_id: &RoomId,
+        sender: &UserId,
+    ) -> Result<bool> {
+        let mut inviters: BTreeMap<OwnedRoomId, RoomKeyHistoryInviter> =
+            self.get_value("room_key_history_inviters").await?.unwrap_or_default();
+        let count = inviters.len();
+
+        inviters.retain(|_, i| !i.has_expired());
+
+        let accepted = inviters.get(room_id).is_some_and(|i| i.inviter == sender);
+        if accepted {
+            inviters.remove(room_id);
+        }
+
+        if inviters.len() != count {
+            self.set_value("room_key_history_inviters", &inviters).await?;
+        }
+
+        Ok(accepted)
+    }
+
+    /// Forget the room key history that was shared with us for the given room,
+    /// and stop accepting new room keys as its history.
+    pub(crate) async fn discard_room_key_history(&self, room_id: &RoomId) -> Result<()> {
+        let pending: Vec<PendingRoomKeyHistory> =
+            self.get_value("pending_room_key_history").await?.unwrap_or_default();
+        let (discarded, remaining): (Vec<_>, Vec<_>) =
+            pending.into_iter().partition(|p| p.room_key.room_id == room_id);
+
+        if !discarded.is_empty() {
+            self.set_value("pending_room_key_history", &remaining).await?;
+        }
+
+        let mut inviters: BTreeMap<OwnedRoomId, RoomKeyHistoryInviter> =
+            self.get_value("room_key_history_inviters").await?.unwrap_or_default();
+
+        if inviters.remove(room_id).is_some() {
+            self.set_value("room_key_history_inviters", &inviters).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Is the history of encrypted rooms shared with the users we invite to
+    /// them, see [`OlmMachine::share_room_key_history()`].
+    ///
+    /// [`OlmMachine::share_room_key_history()`]: crate::OlmMachine::share_room_key_history
+    pub async fn is_room_key_history_sharing_enabled(&self) -> Result<bool> {
+        Ok(self.get_value("room_key_history_sharing").await?.unwrap_or_default())
+    }
+
+    /// Set whether the history of encrypted rooms is shared with the users we
+    /// invite to them.
+    pub async fn set_room_key_history_sharing_enabled(&self, enabled: bool) -> Result<()> {
+        self.set_value("room_key_history_sharing", &enabled).await
+    }
+
     /// Get custom stored value associated with a key
     pub async fn get_value<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
         let Some(value) = self.get_custom_value(key).await? else {
@@ -992,10 +1250,46 @@ impl Store {
         })
     }
 
+    /// Receive notifications of room keys being received as a [`Stream`],
+    /// including the lags of the reader.
+    ///
+    /// This is the same as [`Store::room_keys_received_stream()`], except that
+    /// a [`BroadcastStreamRecvError::Lagged`] error is sent to the stream when
+    /// the reader lags too far behind and updates were dropped, so the reader
+    /// knows that it can't rely on the updates it received.
+    pub fn room_keys_received_stream_with_lags(
+        &self,
+    ) -> impl Stream<Item = Result<Vec<RoomKeyInfo>, BroadcastStreamRecvError>> {
+        BroadcastStream::new(self.inner.room_keys_received_sender.subscribe())
+    }
+
+    /// Receive notifications of user identities being created or updated as a
+    /// [`Stream`].
+    ///
+    /// Each time identities are received from the server or changed locally,
+    /// an update will be sent to the stream. This can be used to notice that
+    /// a user has reset their identity.
+    ///
+    /// If the reader of the stream lags too far behind, a warning will be
+    /// logged and items will be dropped.
+    pub fn user_identities_stream(&self) -> impl Stream<Item = IdentityUpdates> {
+        let stream = BroadcastStream::new(self.inner.identities_sender.subscribe());
+
+        stream.filter_map(|result| async move {
+            match result {
+                Ok(r) => Some(r),
+                Err(BroadcastStreamRecvError::Lagged(lag)) => {
+                    warn!("user_identities_stream missed {} updates", lag);
+                    None
+                }
+            }
+        })
+    }
+
     /// Creates a `CryptoStoreLock` for this store, that will contain the given
     /// key and value when hold.
     pub fn create_store_lock(&self, lock_key: String, lock_value: String) -> CryptoStoreLock {
-        CryptoStoreLock::new(self.inner.store.clone(), lock_key, lock_value)
+        CryptoStoreLock::new(LockableCryptoStore(self.inner.store.clone()), lock_key, lock_value)
     }
 }
 
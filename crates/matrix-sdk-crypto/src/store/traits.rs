@@ -96,6 +96,12 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// Get all the inbound group sessions we have stored.
     async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>, Self::Error>;
 
+    /// Get all the inbound group sessions we have stored for the given room.
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>, Self::Error>;
+
     /// Get the number inbound group sessions we have and how many of them are
     /// backed up.
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts, Self::Error>;
@@ -188,6 +194,9 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// Get all outgoing secret requests that we have in the store.
     async fn get_unsent_secret_requests(&self) -> Result<Vec<GossipRequest>, Self::Error>;
 
+    /// Get all outgoing secret requests that were already sent out.
+    async fn get_sent_secret_requests(&self) -> Result<Vec<GossipRequest>, Self::Error>;
+
     /// Delete an outgoing key request that we created that matches the given
     /// request id.
     ///
@@ -244,6 +253,13 @@ pub trait CryptoStore: AsyncTraitDeps {
         key: &str,
         holder: &str,
     ) -> Result<bool, Self::Error>;
+
+    /// Clear any in-memory caches, because they may be out of sync with the
+    /// underlying data store, e.g. after another process wrote into it.
+    ///
+    /// The default implementation does nothing, for stores that don't cache
+    /// anything.
+    async fn clear_caches(&self) {}
 }
 
 #[repr(transparent)]
@@ -292,6 +308,13 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_inbound_group_sessions().await.map_err(Into::into)
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.0.get_inbound_group_sessions_for_room(room_id).await.map_err(Into::into)
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         self.0.inbound_group_session_counts().await.map_err(Into::into)
     }
@@ -367,6 +390,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_unsent_secret_requests().await.map_err(Into::into)
     }
 
+    async fn get_sent_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        self.0.get_sent_secret_requests().await.map_err(Into::into)
+    }
+
     async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
         self.0.delete_outgoing_secret_requests(request_id).await.map_err(Into::into)
     }
@@ -399,6 +426,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
     ) -> Result<bool, Self::Error> {
         self.0.try_take_leased_lock(lease_duration_ms, key, holder).await.map_err(Into::into)
     }
+
+    async fn clear_caches(&self) {
+        self.0.clear_caches().await
+    }
 }
 
 /// A type-erased [`CryptoStore`].
@@ -73,6 +73,19 @@ impl ForwardedRoomKeyContent {
             ForwardedRoomKeyContent::Unknown(c) => c.algorithm.to_owned(),
         }
     }
+
+    /// Was the forwarded room key marked as shareable with users that are
+    /// invited to the room later on, as defined in [MSC3061].
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub fn shared_history(&self) -> bool {
+        match self {
+            ForwardedRoomKeyContent::MegolmV1AesSha2(c) => c.shared_history,
+            #[cfg(feature = "experimental-algorithms")]
+            ForwardedRoomKeyContent::MegolmV2AesSha2(c) => c.shared_history,
+            ForwardedRoomKeyContent::Unknown(_) => false,
+        }
+    }
 }
 
 impl EventType for ForwardedRoomKeyContent {
@@ -127,6 +140,17 @@ pub struct ForwardedMegolmV1AesSha2Content {
     )]
     pub claimed_ed25519_key: Ed25519PublicKey,
 
+    /// Whether the room key may be shared with users that are invited to the
+    /// room later on, as defined in [MSC3061].
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    #[serde(
+        default,
+        rename = "org.matrix.msc3061.shared_history",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub shared_history: bool,
+
     #[serde(flatten)]
     pub(crate) other: BTreeMap<String, Value>,
 }
@@ -162,6 +186,17 @@ pub struct ForwardedMegolmV2AesSha2Content {
     #[serde(default)]
     pub claimed_signing_keys: SigningKeys<DeviceKeyAlgorithm>,
 
+    /// Whether the room key may be shared with users that are invited to the
+    /// room later on, as defined in [MSC3061].
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    #[serde(
+        default,
+        rename = "org.matrix.msc3061.shared_history",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub shared_history: bool,
+
     #[serde(flatten)]
     pub(crate) other: BTreeMap<String, Value>,
 }
@@ -184,6 +219,7 @@ impl std::fmt::Debug for ForwardedMegolmV1AesSha2Content {
             .field("forwarding_curve25519_key_chain", &self.forwarding_curve25519_key_chain)
             .field("claimed_sender_key", &self.claimed_sender_key)
             .field("claimed_ed25519_key", &self.claimed_ed25519_key)
+            .field("shared_history", &self.shared_history)
             .finish_non_exhaustive()
     }
 }
@@ -195,6 +231,7 @@ impl std::fmt::Debug for ForwardedMegolmV2AesSha2Content {
             .field("session_id", &self.session_id)
             .field("claimed_sender_key", &self.claimed_sender_key)
             .field("sender_claimed_keys", &self.claimed_signing_keys)
+            .field("shared_history", &self.shared_history)
             .finish_non_exhaustive()
     }
 }
@@ -26,6 +26,7 @@ use super::{
     dummy::DummyEventContent,
     forwarded_room_key::ForwardedRoomKeyContent,
     room_key::RoomKeyContent,
+    room_key_history::RoomKeyHistoryContent,
     room_key_request::{self, SupportedKeyInfo},
     secret_send::SecretSendContent,
     EventType,
@@ -73,6 +74,10 @@ impl DecryptedForwardedRoomKeyEvent {
     }
 }
 
+/// An `org.matrix.msc3061.room_key_history` event that was decrypted using the
+/// `m.olm.v1.curve25519-aes-sha2` algorithm
+pub type DecryptedRoomKeyHistoryEvent = DecryptedOlmV1Event<RoomKeyHistoryContent>;
+
 /// An `m.secret.send` event that was decrypted using the
 /// `m.olm.v1.curve25519-aes-sha2` algorithm
 pub type DecryptedSecretSendEvent = DecryptedOlmV1Event<SecretSendContent>;
@@ -85,6 +90,8 @@ pub enum AnyDecryptedOlmEvent {
     RoomKey(DecryptedRoomKeyEvent),
     /// The `m.forwarded_room_key` decrypted to-device event.
     ForwardedRoomKey(DecryptedForwardedRoomKeyEvent),
+    /// The `org.matrix.msc3061.room_key_history` decrypted to-device event.
+    RoomKeyHistory(DecryptedRoomKeyHistoryEvent),
     /// The `m.secret.send` decrypted to-device event.
     SecretSend(DecryptedSecretSendEvent),
     /// The `m.dummy` decrypted to-device event.
@@ -99,6 +106,7 @@ impl AnyDecryptedOlmEvent {
         match self {
             AnyDecryptedOlmEvent::RoomKey(e) => &e.sender,
             AnyDecryptedOlmEvent::ForwardedRoomKey(e) => &e.sender,
+            AnyDecryptedOlmEvent::RoomKeyHistory(e) => &e.sender,
             AnyDecryptedOlmEvent::SecretSend(e) => &e.sender,
             AnyDecryptedOlmEvent::Custom(e) => &e.sender,
             AnyDecryptedOlmEvent::Dummy(e) => &e.sender,
@@ -110,6 +118,7 @@ impl AnyDecryptedOlmEvent {
         match self {
             AnyDecryptedOlmEvent::RoomKey(e) => &e.recipient,
             AnyDecryptedOlmEvent::ForwardedRoomKey(e) => &e.recipient,
+            AnyDecryptedOlmEvent::RoomKeyHistory(e) => &e.recipient,
             AnyDecryptedOlmEvent::SecretSend(e) => &e.recipient,
             AnyDecryptedOlmEvent::Custom(e) => &e.recipient,
             AnyDecryptedOlmEvent::Dummy(e) => &e.recipient,
@@ -121,6 +130,7 @@ impl AnyDecryptedOlmEvent {
         match self {
             AnyDecryptedOlmEvent::RoomKey(e) => &e.keys,
             AnyDecryptedOlmEvent::ForwardedRoomKey(e) => &e.keys,
+            AnyDecryptedOlmEvent::RoomKeyHistory(e) => &e.keys,
             AnyDecryptedOlmEvent::SecretSend(e) => &e.keys,
             AnyDecryptedOlmEvent::Custom(e) => &e.keys,
             AnyDecryptedOlmEvent::Dummy(e) => &e.keys,
@@ -132,6 +142,7 @@ impl AnyDecryptedOlmEvent {
         match self {
             AnyDecryptedOlmEvent::RoomKey(e) => &e.recipient_keys,
             AnyDecryptedOlmEvent::ForwardedRoomKey(e) => &e.recipient_keys,
+            AnyDecryptedOlmEvent::RoomKeyHistory(e) => &e.recipient_keys,
             AnyDecryptedOlmEvent::SecretSend(e) => &e.recipient_keys,
             AnyDecryptedOlmEvent::Custom(e) => &e.recipient_keys,
             AnyDecryptedOlmEvent::Dummy(e) => &e.recipient_keys,
@@ -144,6 +155,7 @@ impl AnyDecryptedOlmEvent {
             AnyDecryptedOlmEvent::Custom(e) => &e.event_type,
             AnyDecryptedOlmEvent::RoomKey(e) => e.content.event_type(),
             AnyDecryptedOlmEvent::ForwardedRoomKey(e) => e.content.event_type(),
+            AnyDecryptedOlmEvent::RoomKeyHistory(e) => e.content.event_type(),
             AnyDecryptedOlmEvent::SecretSend(e) => e.content.event_type(),
             AnyDecryptedOlmEvent::Dummy(e) => e.content.event_type(),
         }
@@ -228,6 +240,9 @@ impl<'de> Deserialize<'de> for AnyDecryptedOlmEvent {
         Ok(match helper.event_type {
             "m.room_key" => AnyDecryptedOlmEvent::RoomKey(from_str(json)?),
             "m.forwarded_room_key" => AnyDecryptedOlmEvent::ForwardedRoomKey(from_str(json)?),
+            "org.matrix.msc3061.room_key_history" => {
+                AnyDecryptedOlmEvent::RoomKeyHistory(from_str(json)?)
+            }
             "m.secret.send" => AnyDecryptedOlmEvent::SecretSend(from_str(json)?),
             "m.dummy" => AnyDecryptedOlmEvent::Dummy(from_str(json)?),
 
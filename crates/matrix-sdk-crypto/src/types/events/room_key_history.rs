@@ -0,0 +1,51 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types for `org.matrix.msc3061.room_key_history` to-device events.
+//!
+//! These events carry all the room keys of a room that are shared with a user
+//! that was invited to it, as defined in [MSC3061], so the whole history of
+//! the room is sent to a device in a single event.
+//!
+//! [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+
+use ruma::OwnedRoomId;
+use serde::{Deserialize, Serialize};
+
+use super::{forwarded_room_key::ForwardedRoomKeyContent, EventType, ToDeviceEvent};
+
+/// The `org.matrix.msc3061.room_key_history` to-device event.
+pub type RoomKeyHistoryEvent = ToDeviceEvent<RoomKeyHistoryContent>;
+
+/// The content of an `org.matrix.msc3061.room_key_history` event.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomKeyHistoryContent {
+    /// The room whose history is shared.
+    pub room_id: OwnedRoomId,
+
+    /// The room keys of the room, in the format of the content of
+    /// `m.forwarded_room_key` events.
+    pub room_keys: Vec<ForwardedRoomKeyContent>,
+}
+
+impl RoomKeyHistoryContent {
+    /// Create a new `org.matrix.msc3061.room_key_history` event content.
+    pub fn new(room_id: OwnedRoomId, room_keys: Vec<ForwardedRoomKeyContent>) -> Self {
+        Self { room_id, room_keys }
+    }
+}
+
+impl EventType for RoomKeyHistoryContent {
+    const EVENT_TYPE: &'static str = "org.matrix.msc3061.room_key_history";
+}
@@ -40,6 +40,7 @@ use super::{
     forwarded_room_key::{ForwardedRoomKeyContent, ForwardedRoomKeyEvent},
     room::encrypted::EncryptedToDeviceEvent,
     room_key::RoomKeyEvent,
+    room_key_history::RoomKeyHistoryEvent,
     room_key_request::RoomKeyRequestEvent,
     room_key_withheld::RoomKeyWithheldEvent,
     secret_send::SecretSendEvent,
@@ -80,6 +81,8 @@ pub enum ToDeviceEvents {
     RoomKeyRequest(RoomKeyRequestEvent),
     /// The `m.forwarded_room_key` to-device event.
     ForwardedRoomKey(Box<ForwardedRoomKeyEvent>),
+    /// The `org.matrix.msc3061.room_key_history` to-device event.
+    RoomKeyHistory(Box<RoomKeyHistoryEvent>),
     /// The `m.secret.send` to-device event.
     SecretSend(SecretSendEvent),
     /// The `m.secret.request` to-device event.
@@ -108,6 +111,7 @@ impl ToDeviceEvents {
             ToDeviceEvents::RoomKey(e) => &e.sender,
             ToDeviceEvents::RoomKeyRequest(e) => &e.sender,
             ToDeviceEvents::ForwardedRoomKey(e) => &e.sender,
+            ToDeviceEvents::RoomKeyHistory(e) => &e.sender,
 
             ToDeviceEvents::SecretSend(e) => &e.sender,
             ToDeviceEvents::SecretRequest(e) => &e.sender,
@@ -134,6 +138,9 @@ impl ToDeviceEvents {
             ToDeviceEvents::RoomKey(_) => ToDeviceEventType::RoomKey,
             ToDeviceEvents::RoomKeyRequest(_) => ToDeviceEventType::RoomKeyRequest,
             ToDeviceEvents::ForwardedRoomKey(_) => ToDeviceEventType::ForwardedRoomKey,
+            ToDeviceEvents::RoomKeyHistory(e) => {
+                ToDeviceEventType::from(e.content.event_type().to_owned())
+            }
 
             ToDeviceEvents::SecretSend(_) => ToDeviceEventType::SecretSend,
             ToDeviceEvents::SecretRequest(e) => e.content.event_type(),
@@ -154,6 +161,8 @@ impl ToDeviceEvents {
     ///
     /// * `m.room_key` - The `session_key` field.
     /// * `m.forwarded_room_key` - The `session_key` field.
+    /// * `org.matrix.msc3061.room_key_history` - The `session_key` field of
+    /// every room key.
     /// * `m.secret.send` - The `secret` field will be zeroized, unless the
     /// secret name of the matching `m.secret.request` event was
     /// `m.megolm_backup.v1`.
@@ -217,6 +226,18 @@ impl ToDeviceEvents {
 
                 Raw::from_json(to_raw_value(&e)?)
             }
+            ToDeviceEvents::RoomKeyHistory(mut e) => {
+                for room_key in &mut e.content.room_keys {
+                    match room_key {
+                        ForwardedRoomKeyContent::MegolmV1AesSha2(c) => c.session_key.zeroize(),
+                        #[cfg(feature = "experimental-algorithms")]
+                        ForwardedRoomKeyContent::MegolmV2AesSha2(c) => c.session_key.zeroize(),
+                        ForwardedRoomKeyContent::Unknown(_) => (),
+                    }
+                }
+
+                Raw::from_json(to_raw_value(&e)?)
+            }
             ToDeviceEvents::SecretSend(mut e) => {
                 if let Some(SecretName::RecoveryKey) = e.content.secret_name {
                     // We don't zeroize the recovery key since it requires
@@ -362,6 +383,9 @@ impl<'de> Deserialize<'de> for ToDeviceEvents {
             "m.room.encrypted" => ToDeviceEvents::RoomEncrypted(from_str(json)?),
             "m.room_key" => ToDeviceEvents::RoomKey(from_str(json)?),
             "m.forwarded_room_key" => ToDeviceEvents::ForwardedRoomKey(from_str(json)?),
+            "org.matrix.msc3061.room_key_history" => {
+                ToDeviceEvents::RoomKeyHistory(from_str(json)?)
+            }
             "m.room_key_request" => ToDeviceEvents::RoomKeyRequest(from_str(json)?),
             "m.room_key.withheld" => ToDeviceEvents::RoomKeyWithheld(from_str(json)?),
 
@@ -395,6 +419,7 @@ impl Serialize for ToDeviceEvents {
             ToDeviceEvents::RoomKey(e) => e.serialize(serializer),
             ToDeviceEvents::RoomKeyRequest(e) => e.serialize(serializer),
             ToDeviceEvents::ForwardedRoomKey(e) => e.serialize(serializer),
+            ToDeviceEvents::RoomKeyHistory(e) => e.serialize(serializer),
 
             ToDeviceEvents::SecretSend(e) => e.serialize(serializer),
             ToDeviceEvents::SecretRequest(e) => e.serialize(serializer),
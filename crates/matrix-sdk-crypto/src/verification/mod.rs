@@ -565,7 +565,12 @@ impl IdentitiesBeingVerified {
             let request = if let Some(i) = i.other() {
                 // Signing can fail if the user signing key is missing.
                 match self.private_identity.sign_user(i).await {
-                    Ok(r) => Some(r),
+                    Ok(r) => {
+                        // A verified identity doesn't need to be acknowledged.
+                        i.mark_as_previously_verified();
+                        i.pin_current_master_key();
+                        Some(r)
+                    }
                     Err(SignatureError::MissingSigningKey) => {
                         warn!(
                             user_id = ?i.user_id(),
@@ -795,6 +795,18 @@ impl_crypto_store! {
             .collect())
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        Ok(self
+            .get_inbound_group_sessions()
+            .await?
+            .into_iter()
+            .filter(|s| s.room_id() == room_id)
+            .collect())
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let all = self.get_inbound_group_sessions().await?;
         let backed_up = all.iter().filter(|s| s.backed_up()).count();
@@ -942,6 +954,21 @@ impl_crypto_store! {
             .collect())
     }
 
+    async fn get_sent_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        Ok(self
+            .inner
+            .transaction_on_one_with_mode(
+                keys::OUTGOING_SECRET_REQUESTS,
+                IdbTransactionMode::Readonly,
+            )?
+            .object_store(keys::OUTGOING_SECRET_REQUESTS)?
+            .get_all()?
+            .await?
+            .iter()
+            .filter_map(|i| self.deserialize_value(i).ok())
+            .collect())
+    }
+
     async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
         let jskey = self.encode_key(keys::KEY_REQUEST, request_id); //.as_str());
         let dbs = [
@@ -1065,39 +1092,42 @@ impl_crypto_store! {
         key: &str,
         holder: &str,
     ) -> Result<bool> {
-        // As of 2023-06-23, the code below hasn't been tested yet.
-        let key = JsValue::from_str(key);
-        let txn = self
-            .inner
-            .transaction_on_one_with_mode(keys::CORE, IdbTransactionMode::Readwrite)?;
-        let object_store = txn
-            .object_store(keys::CORE)?;
-
         #[derive(serde::Deserialize, serde::Serialize)]
         struct Lease {
             holder: String,
             expiration_ts: u64,
         }
 
+        let key = JsValue::from_str(key);
+        let txn =
+            self.inner.transaction_on_one_with_mode(keys::CORE, IdbTransactionMode::Readwrite)?;
+        let object_store = txn.object_store(keys::CORE)?;
+
         let now_ts: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
         let expiration_ts = now_ts + lease_duration_ms as u64;
 
-        let prev = object_store.get(&key)?.await?;
-        match prev {
+        // The whole read-then-write sequence happens in a single readwrite transaction,
+        // so it's atomic with respect to other tabs or workers using the same database.
+        let acquired = match object_store.get(&key)?.await? {
             Some(prev) => {
                 let lease: Lease = self.deserialize_value(prev)?;
-                if lease.holder == holder || lease.expiration_ts < now_ts {
-                    object_store.put_key_val(&key, &self.serialize_value(&Lease { holder: holder.to_owned(), expiration_ts })?)?;
-                    Ok(true)
-                } else {
-                    Ok(false)
-                }
-            }
-            None => {
-                object_store.put_key_val(&key, &self.serialize_value(&Lease { holder: holder.to_owned(), expiration_ts })?)?;
-                Ok(true)
+                lease.holder == holder || lease.expiration_ts < now_ts
             }
+            None => true,
+        };
+
+        if acquired {
+            let lease = Lease { holder: holder.to_owned(), expiration_ts };
+            object_store.put_key_val(&key, &self.serialize_value(&lease)?)?;
         }
+
+        txn.await.into_result()?;
+
+        Ok(acquired)
+    }
+
+    async fn clear_caches(&self) {
+        self.session_cache.clear()
     }
 }
 
@@ -40,7 +40,7 @@ use super::{
 };
 use crate::IndexeddbStateStoreError;
 
-const CURRENT_DB_VERSION: u32 = 7;
+const CURRENT_DB_VERSION: u32 = 9;
 const CURRENT_META_DB_VERSION: u32 = 2;
 
 /// Sometimes Migrations can't proceed without having to drop existing
@@ -223,6 +223,12 @@ pub async fn upgrade_inner_db(
             if old_version < 7 {
                 migration.merge(migrate_to_v7(&pre_db, store_cipher).await?);
             }
+            if old_version < 8 {
+                migration.merge(migrate_to_v8());
+            }
+            if old_version < 9 {
+                migration.merge(migrate_to_v9());
+            }
         }
 
         pre_db.close();
@@ -641,6 +647,19 @@ async fn migrate_to_v7(
     })
 }
 
+/// Add the store for the chunks of the event cache.
+fn migrate_to_v8() -> OngoingMigration {
+    OngoingMigration {
+        create_stores: HashSet::from_iter([keys::EVENT_CACHE_CHUNKS]),
+        ..Default::default()
+    }
+}
+
+/// Add the store for the send queues of the rooms.
+fn migrate_to_v9() -> OngoingMigration {
+    OngoingMigration { create_stores: HashSet::from_iter([keys::SEND_QUEUE]), ..Default::default() }
+}
+
 #[cfg(all(test, target_arch = "wasm32"))]
 mod tests {
     wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
@@ -23,8 +23,9 @@ use gloo_utils::format::JsValueSerdeExt;
 use indexed_db_futures::prelude::*;
 use matrix_sdk_base::{
     deserialized_responses::RawAnySyncOrStrippedState,
+    event_cache::{Chunk, ChunkUpdate},
     media::{MediaRequest, UniqueKey},
-    store::{StateChanges, StateStore, StoreError},
+    store::{QueuedRequest, StateChanges, StateStore, StoreError},
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships, RoomState, StateStoreDataKey,
     StateStoreDataValue,
 };
@@ -41,7 +42,8 @@ use ruma::{
         GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType, SyncStateEvent,
     },
     serde::Raw,
-    CanonicalJsonObject, EventId, MxcUri, OwnedEventId, OwnedUserId, RoomId, RoomVersionId, UserId,
+    CanonicalJsonObject, EventId, MilliSecondsSinceUnixEpoch, MxcUri, OwnedEventId, OwnedRoomId,
+    OwnedUserId, RoomId, RoomVersionId, TransactionId, UserId,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tracing::{debug, warn};
@@ -112,9 +114,15 @@ mod keys {
 
     pub const MEDIA: &str = "media";
 
+    pub const EVENT_CACHE_CHUNKS: &str = "event_cache_chunks";
+
+    pub const SEND_QUEUE: &str = "send_queue";
+
     pub const CUSTOM: &str = "custom";
     pub const KV: &str = "kv";
 
+    pub const LEASE_LOCKS: &str = "lease_locks";
+
     /// All names of the current state stores for convenience.
     pub const ALL_STORES: &[&str] = &[
         ACCOUNT_DATA,
@@ -130,6 +138,8 @@ mod keys {
         ROOM_USER_RECEIPTS,
         ROOM_EVENT_RECEIPTS,
         MEDIA,
+        EVENT_CACHE_CHUNKS,
+        SEND_QUEUE,
         CUSTOM,
         KV,
     ];
@@ -1002,7 +1012,7 @@ impl_state_store!({
                 let value = cursor.value();
                 let info = self.deserialize_event::<RoomInfo>(&value)?;
 
-                if info.state() == RoomState::Invited {
+                if matches!(info.state(), RoomState::Invited | RoomState::Knocked) {
                     infos.push(info);
                 }
 
@@ -1192,6 +1202,44 @@ impl_state_store!({
         Ok(prev)
     }
 
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        #[derive(Deserialize, Serialize)]
+        struct Lease {
+            holder: String,
+            expiration_ts: u64,
+        }
+
+        let key = self.encode_key(keys::KV, (keys::LEASE_LOCKS, key));
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::KV, IdbTransactionMode::Readwrite)?;
+        let obj = tx.object_store(keys::KV)?;
+
+        let now_ts: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
+        let expiration_ts = now_ts + lease_duration_ms as u64;
+
+        let acquired = match obj.get(&key)?.await? {
+            Some(prev) => {
+                let lease: Lease = self.deserialize_event(&prev)?;
+                lease.holder == holder || lease.expiration_ts < now_ts
+            }
+            None => true,
+        };
+
+        if acquired {
+            let lease = Lease { holder: holder.to_owned(), expiration_ts };
+            obj.put_key_val(&key, &self.serialize_event(&lease)?)?;
+        }
+
+        tx.await.into_result()?;
+
+        Ok(acquired)
+    }
+
     async fn remove_media_content(&self, request: &MediaRequest) -> Result<()> {
         let key = self
             .encode_key(keys::MEDIA, (request.source.unique_key(), request.format.unique_key()));
@@ -1216,8 +1264,169 @@ impl_state_store!({
         tx.await.into_result().map_err(|e| e.into())
     }
 
+    async fn get_event_cache_chunks(&self, room_id: &RoomId) -> Result<Vec<Chunk>> {
+        let range = self.encode_to_range(keys::EVENT_CACHE_CHUNKS, room_id)?;
+        self.inner
+            .transaction_on_one_with_mode(keys::EVENT_CACHE_CHUNKS, IdbTransactionMode::Readonly)?
+            .object_store(keys::EVENT_CACHE_CHUNKS)?
+            .get_all_with_key(&range)?
+            .await?
+            .iter()
+            .map(|f| self.deserialize_event(&f))
+            .collect()
+    }
+
+    async fn handle_event_cache_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<ChunkUpdate>,
+    ) -> Result<()> {
+        if updates.is_empty() {
+            return Ok(());
+        }
+
+        let tx = self.inner.transaction_on_one_with_mode(
+            keys::EVENT_CACHE_CHUNKS,
+            IdbTransactionMode::Readwrite,
+        )?;
+        let store = tx.object_store(keys::EVENT_CACHE_CHUNKS)?;
+
+        for update in updates {
+            match update {
+                ChunkUpdate::Upsert(chunk) => {
+                    let key = self.encode_key(
+                        keys::EVENT_CACHE_CHUNKS,
+                        (room_id, chunk.identifier.value().to_string()),
+                    );
+                    store.put_key_val(&key, &self.serialize_event(&chunk)?)?;
+                }
+                ChunkUpdate::Remove(identifier) => {
+                    let key = self.encode_key(
+                        keys::EVENT_CACHE_CHUNKS,
+                        (room_id, identifier.value().to_string()),
+                    );
+                    store.delete(&key)?;
+                }
+            }
+        }
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<()> {
+        let encoded_key = self.encode_key(keys::SEND_QUEUE, room_id);
+
+        let tx = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEND_QUEUE, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::SEND_QUEUE)?;
+
+        let mut queue = match store.get(&encoded_key)?.await? {
+            Some(value) => self.deserialize_event(&value)?,
+            None => PersistedSendQueue { room_id: room_id.to_owned(), requests: Vec::new() },
+        };
+        queue.requests.push(request);
+
+        store.put_key_val(&encoded_key, &self.serialize_event(&queue)?)?;
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
+    async fn update_send_queue_request_status(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+        is_wedged: bool,
+    ) -> Result<bool> {
+        let encoded_key = self.encode_key(keys::SEND_QUEUE, room_id);
+
+        let tx = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEND_QUEUE, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::SEND_QUEUE)?;
+
+        let Some(value) = store.get(&encoded_key)?.await? else {
+            return Ok(false);
+        };
+        let mut queue: PersistedSendQueue = self.deserialize_event(&value)?;
+
+        let Some(request) =
+            queue.requests.iter_mut().find(|request| request.transaction_id == transaction_id)
+        else {
+            return Ok(false);
+        };
+        request.is_wedged = is_wedged;
+
+        store.put_key_val(&encoded_key, &self.serialize_event(&queue)?)?;
+
+        tx.await.into_result()?;
+        Ok(true)
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool> {
+        let encoded_key = self.encode_key(keys::SEND_QUEUE, room_id);
+
+        let tx = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEND_QUEUE, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::SEND_QUEUE)?;
+
+        let Some(value) = store.get(&encoded_key)?.await? else {
+            return Ok(false);
+        };
+        let mut queue: PersistedSendQueue = self.deserialize_event(&value)?;
+
+        let Some(position) =
+            queue.requests.iter().position(|request| request.transaction_id == transaction_id)
+        else {
+            return Ok(false);
+        };
+        queue.requests.remove(position);
+
+        if queue.requests.is_empty() {
+            store.delete(&encoded_key)?;
+        } else {
+            store.put_key_val(&encoded_key, &self.serialize_event(&queue)?)?;
+        }
+
+        tx.await.into_result()?;
+        Ok(true)
+    }
+
+    async fn load_send_queue_requests(&self, room_id: &RoomId) -> Result<Vec<QueuedRequest>> {
+        let queue: Option<PersistedSendQueue> = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEND_QUEUE, IdbTransactionMode::Readonly)?
+            .object_store(keys::SEND_QUEUE)?
+            .get(&self.encode_key(keys::SEND_QUEUE, room_id))?
+            .await?
+            .map(|f| self.deserialize_event(&f))
+            .transpose()?;
+
+        Ok(queue.map(|queue| queue.requests).unwrap_or_default())
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>> {
+        self.inner
+            .transaction_on_one_with_mode(keys::SEND_QUEUE, IdbTransactionMode::Readonly)?
+            .object_store(keys::SEND_QUEUE)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|f| self.deserialize_event::<PersistedSendQueue>(&f).map(|queue| queue.room_id))
+            .collect()
+    }
+
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
-        let direct_stores = [keys::ROOM_INFOS];
+        let direct_stores = [keys::ROOM_INFOS, keys::SEND_QUEUE];
 
         let prefixed_stores = [
             keys::PROFILES,
@@ -1229,6 +1438,7 @@ impl_state_store!({
             keys::ROOM_USER_RECEIPTS,
             keys::STRIPPED_ROOM_STATE,
             keys::STRIPPED_USER_IDS,
+            keys::EVENT_CACHE_CHUNKS,
         ];
 
         let all_stores = {
@@ -1277,6 +1487,13 @@ impl_state_store!({
     }
 });
 
+/// The send queue of a room, as it is persisted.
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedSendQueue {
+    room_id: OwnedRoomId,
+    requests: Vec<QueuedRequest>,
+}
+
 /// A room member.
 #[derive(Debug, Serialize, Deserialize)]
 struct RoomMember {
@@ -0,0 +1,7 @@
+CREATE TABLE "event_cache_chunk" (
+    "room_id" BLOB NOT NULL,
+    "chunk_id" INTEGER NOT NULL,
+    "data" BLOB NOT NULL,
+
+    PRIMARY KEY ("room_id", "chunk_id")
+);
@@ -0,0 +1,10 @@
+-- requests are sent in the order they were inserted, i.e. by rowid
+CREATE TABLE "send_queue_request" (
+    "room_id" BLOB NOT NULL,
+    -- the serialized room ID, to list the rooms that have unsent requests
+    "room_id_val" BLOB NOT NULL,
+    "transaction_id" BLOB NOT NULL,
+    "data" BLOB NOT NULL,
+
+    PRIMARY KEY ("room_id", "transaction_id")
+);
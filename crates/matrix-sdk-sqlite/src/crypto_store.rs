@@ -458,6 +458,20 @@ trait SqliteObjectCryptoStoreExt: SqliteObjectExt {
             .await?)
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: Key,
+    ) -> Result<Vec<(Vec<u8>, bool)>> {
+        Ok(self
+            .prepare(
+                "SELECT data, backed_up FROM inbound_group_session WHERE room_id = ?",
+                |mut stmt| {
+                    stmt.query((room_id,))?.mapped(|row| Ok((row.get(0)?, row.get(1)?))).collect()
+                },
+            )
+            .await?)
+    }
+
     async fn get_inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let total = self
             .query_row("SELECT count(*) FROM inbound_group_session", (), |row| row.get(0))
@@ -587,6 +601,14 @@ trait SqliteObjectCryptoStoreExt: SqliteObjectExt {
             .await?)
     }
 
+    async fn get_sent_secret_requests(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT data FROM key_requests WHERE sent_out = TRUE", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
+
     async fn delete_key_request(&self, request_id: Key) -> Result<()> {
         self.execute("DELETE FROM key_requests WHERE request_id = ?", (request_id,)).await?;
         Ok(())
@@ -876,6 +898,23 @@ impl CryptoStore for SqliteCryptoStore {
             .collect()
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        let room_id = self.encode_key("inbound_group_session", room_id.as_bytes());
+        self.acquire()
+            .await?
+            .get_inbound_group_sessions_for_room(room_id)
+            .await?
+            .into_iter()
+            .map(|(value, backed_up)| {
+                let pickle = self.deserialize_pickled_inbound_group_session(&value, backed_up)?;
+                Ok(InboundGroupSession::from_pickle(pickle)?)
+            })
+            .collect()
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         Ok(self.acquire().await?.get_inbound_group_session_counts().await?)
     }
@@ -1057,6 +1096,19 @@ impl CryptoStore for SqliteCryptoStore {
             .collect()
     }
 
+    async fn get_sent_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        self.acquire()
+            .await?
+            .get_sent_secret_requests()
+            .await?
+            .iter()
+            .map(|value| {
+                let request = self.deserialize_key_request(value, true)?;
+                Ok(request)
+            })
+            .collect()
+    }
+
     async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
         let request_id = self.encode_key("key_requests", request_id.as_bytes());
         Ok(self.acquire().await?.delete_key_request(request_id).await?)
@@ -1150,6 +1202,10 @@ impl CryptoStore for SqliteCryptoStore {
 
         Ok(num_touched == 1)
     }
+
+    async fn clear_caches(&self) {
+        self.session_cache.clear()
+    }
 }
 
 #[cfg(test)]
@@ -14,7 +14,9 @@ use deadpool_sqlite::{Object as SqliteConn, Pool as SqlitePool, Runtime};
 use itertools::Itertools;
 use matrix_sdk_base::{
     deserialized_responses::RawAnySyncOrStrippedState,
+    event_cache::{Chunk, ChunkUpdate},
     media::{MediaRequest, UniqueKey},
+    store::QueuedRequest,
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships, RoomState, StateChanges, StateStore,
     StateStoreDataKey, StateStoreDataValue,
 };
@@ -29,7 +31,8 @@ use ruma::{
         GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
     },
     serde::Raw,
-    CanonicalJsonObject, EventId, OwnedEventId, OwnedUserId, RoomId, RoomVersionId, UserId,
+    CanonicalJsonObject, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId,
+    OwnedUserId, RoomId, RoomVersionId, TransactionId, UserId,
 };
 use rusqlite::{limits::Limit, OptionalExtension, Transaction};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -55,9 +58,11 @@ mod keys {
     pub const RECEIPT: &str = "receipt";
     pub const DISPLAY_NAME: &str = "display_name";
     pub const MEDIA: &str = "media";
+    pub const EVENT_CACHE_CHUNK: &str = "event_cache_chunk";
+    pub const SEND_QUEUE: &str = "send_queue_request";
 }
 
-const DATABASE_VERSION: u8 = 2;
+const DATABASE_VERSION: u8 = 5;
 
 /// A sqlite based cryptostore.
 #[derive(Clone)]
@@ -162,6 +167,27 @@ impl SqliteStateStore {
             .await?;
         }
 
+        if from < 3 && to >= 3 {
+            conn.with_transaction(move |txn| {
+                txn.execute_batch(include_str!("../migrations/state_store/003_event_cache.sql"))
+            })
+            .await?;
+        }
+
+        if from < 4 && to >= 4 {
+            conn.with_transaction(move |txn| {
+                txn.execute_batch(include_str!("../migrations/state_store/004_send_queue.sql"))
+            })
+            .await?;
+        }
+
+        if from < 5 && to >= 5 {
+            conn.with_transaction(move |txn| {
+                txn.execute_batch(include_str!("../migrations/state_store/005_lease_locks.sql"))
+            })
+            .await?;
+        }
+
         conn.set_kv("version", vec![to]).await?;
 
         Ok(())
@@ -342,6 +368,17 @@ trait SqliteConnectionStateStoreExt {
     fn set_display_name(&self, room_id: &[u8], name: &[u8], data: &[u8]) -> rusqlite::Result<()>;
     fn remove_display_name(&self, room_id: &[u8], name: &[u8]) -> rusqlite::Result<()>;
     fn remove_room_display_names(&self, room_id: &[u8]) -> rusqlite::Result<()>;
+
+    fn set_event_cache_chunk(
+        &self,
+        room_id: &[u8],
+        chunk_id: u64,
+        data: &[u8],
+    ) -> rusqlite::Result<()>;
+    fn remove_event_cache_chunk(&self, room_id: &[u8], chunk_id: u64) -> rusqlite::Result<()>;
+    fn remove_room_event_cache_chunks(&self, room_id: &[u8]) -> rusqlite::Result<()>;
+
+    fn remove_room_send_queue_requests(&self, room_id: &[u8]) -> rusqlite::Result<()>;
 }
 
 impl SqliteConnectionStateStoreExt for rusqlite::Connection {
@@ -542,6 +579,37 @@ impl SqliteConnectionStateStoreExt for rusqlite::Connection {
         self.prepare("DELETE FROM display_name WHERE room_id = ?")?.execute((room_id,))?;
         Ok(())
     }
+
+    fn set_event_cache_chunk(
+        &self,
+        room_id: &[u8],
+        chunk_id: u64,
+        data: &[u8],
+    ) -> rusqlite::Result<()> {
+        self.prepare_cached(
+            "INSERT OR REPLACE
+             INTO event_cache_chunk (room_id, chunk_id, data)
+             VALUES (?, ?, ?)",
+        )?
+        .execute((room_id, chunk_id, data))?;
+        Ok(())
+    }
+
+    fn remove_event_cache_chunk(&self, room_id: &[u8], chunk_id: u64) -> rusqlite::Result<()> {
+        self.prepare_cached("DELETE FROM event_cache_chunk WHERE room_id = ? AND chunk_id = ?")?
+            .execute((room_id, chunk_id))?;
+        Ok(())
+    }
+
+    fn remove_room_event_cache_chunks(&self, room_id: &[u8]) -> rusqlite::Result<()> {
+        self.prepare("DELETE FROM event_cache_chunk WHERE room_id = ?")?.execute((room_id,))?;
+        Ok(())
+    }
+
+    fn remove_room_send_queue_requests(&self, room_id: &[u8]) -> rusqlite::Result<()> {
+        self.prepare("DELETE FROM send_queue_request WHERE room_id = ?")?.execute((room_id,))?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -803,6 +871,57 @@ trait SqliteObjectStateStoreExt: SqliteObjectExt {
         self.execute("DELETE FROM media WHERE uri = ?", (uri,)).await?;
         Ok(())
     }
+
+    async fn get_event_cache_chunks(&self, room_id: Key) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT data FROM event_cache_chunk WHERE room_id = ?", move |mut stmt| {
+                stmt.query_map((room_id,), |row| row.get(0))?.collect()
+            })
+            .await?)
+    }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: Key,
+        room_id_val: Vec<u8>,
+        transaction_id: Key,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        self.execute(
+            "INSERT INTO send_queue_request (room_id, room_id_val, transaction_id, data)
+             VALUES (?, ?, ?, ?)",
+            (room_id, room_id_val, transaction_id, data),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_send_queue_request(&self, room_id: Key, transaction_id: Key) -> Result<usize> {
+        Ok(self
+            .execute(
+                "DELETE FROM send_queue_request WHERE room_id = ? AND transaction_id = ?",
+                (room_id, transaction_id),
+            )
+            .await?)
+    }
+
+    async fn get_send_queue_requests(&self, room_id: Key) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare(
+                "SELECT data FROM send_queue_request WHERE room_id = ? ORDER BY rowid",
+                move |mut stmt| stmt.query_map((room_id,), |row| row.get(0))?.collect(),
+            )
+            .await?)
+    }
+
+    async fn get_rooms_with_unsent_requests(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare(
+                "SELECT room_id_val FROM send_queue_request GROUP BY room_id",
+                move |mut stmt| stmt.query_map((), |row| row.get(0))?.collect(),
+            )
+            .await?)
+    }
 }
 
 #[async_trait]
@@ -910,7 +1029,8 @@ impl StateStore for SqliteStateStore {
                 }
 
                 for (room_id, room_info) in room_infos {
-                    let stripped = room_info.state() == RoomState::Invited;
+                    let stripped =
+                        matches!(room_info.state(), RoomState::Invited | RoomState::Knocked);
                     // Remove non-stripped data for stripped rooms and vice-versa.
                     this.remove_maybe_stripped_room_data(txn, &room_id, !stripped)?;
 
@@ -1323,8 +1443,10 @@ impl StateStore for SqliteStateStore {
     }
 
     async fn get_stripped_room_infos(&self) -> Result<Vec<RoomInfo>> {
-        let states =
-            vec![self.encode_key(keys::ROOM_INFO, serde_json::to_string(&RoomState::Invited)?)];
+        let states = vec![
+            self.encode_key(keys::ROOM_INFO, serde_json::to_string(&RoomState::Invited)?),
+            self.encode_key(keys::ROOM_INFO, serde_json::to_string(&RoomState::Knocked)?),
+        ];
         self.acquire()
             .await?
             .get_room_infos(states)
@@ -1485,6 +1607,39 @@ impl StateStore for SqliteStateStore {
         Ok(previous)
     }
 
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        let key = key.to_owned();
+        let holder = holder.to_owned();
+
+        let now_ts: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
+        let expiration_ts = now_ts + lease_duration_ms as u64;
+
+        let num_touched = self
+            .acquire()
+            .await?
+            .with_transaction(move |txn| {
+                txn.execute(
+                    "INSERT INTO lease_locks (key, holder, expiration_ts)
+                    VALUES (?1, ?2, ?3)
+                    ON CONFLICT (key)
+                    DO
+                        UPDATE SET holder = ?2, expiration_ts = ?3
+                        WHERE holder = ?2
+                        OR expiration_ts < ?4
+                ",
+                    (key, holder, expiration_ts, now_ts),
+                )
+            })
+            .await?;
+
+        Ok(num_touched == 1)
+    }
+
     async fn add_media_content(&self, request: &MediaRequest, content: Vec<u8>) -> Result<()> {
         let uri = self.encode_key(keys::MEDIA, request.source.unique_key());
         let format = self.encode_key(keys::MEDIA, request.format.unique_key());
@@ -1510,6 +1665,141 @@ impl StateStore for SqliteStateStore {
         self.acquire().await?.remove_uri_medias(uri).await
     }
 
+    async fn get_event_cache_chunks(&self, room_id: &RoomId) -> Result<Vec<Chunk>> {
+        let room_id = self.encode_key(keys::EVENT_CACHE_CHUNK, room_id);
+        self.acquire()
+            .await?
+            .get_event_cache_chunks(room_id)
+            .await?
+            .iter()
+            .map(|data| self.deserialize_json(data))
+            .collect()
+    }
+
+    async fn handle_event_cache_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<ChunkUpdate>,
+    ) -> Result<()> {
+        if updates.is_empty() {
+            return Ok(());
+        }
+
+        let this = self.clone();
+        let room_id = self.encode_key(keys::EVENT_CACHE_CHUNK, room_id);
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                for update in updates {
+                    match update {
+                        ChunkUpdate::Upsert(chunk) => {
+                            let data = this.serialize_json(&chunk)?;
+                            txn.set_event_cache_chunk(&room_id, chunk.identifier.value(), &data)?;
+                        }
+                        ChunkUpdate::Remove(identifier) => {
+                            txn.remove_event_cache_chunk(&room_id, identifier.value())?;
+                        }
+                    }
+                }
+
+                Ok::<_, Error>(())
+            })
+            .await
+    }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<()> {
+        let room_id_key = self.encode_key(keys::SEND_QUEUE, room_id);
+        let room_id_val = self.serialize_value(&room_id)?;
+        let transaction_id = self.encode_key(keys::SEND_QUEUE, &request.transaction_id);
+        let data = self.serialize_json(&request)?;
+
+        self.acquire()
+            .await?
+            .save_send_queue_request(room_id_key, room_id_val, transaction_id, data)
+            .await
+    }
+
+    async fn update_send_queue_request_status(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+        is_wedged: bool,
+    ) -> Result<bool> {
+        let this = self.clone();
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+        let transaction_id = self.encode_key(keys::SEND_QUEUE, transaction_id);
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                let data: Option<Vec<u8>> = txn
+                    .query_row(
+                        "SELECT data FROM send_queue_request
+                         WHERE room_id = ? AND transaction_id = ?",
+                        (&room_id, &transaction_id),
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+
+                let Some(data) = data else {
+                    return Ok(false);
+                };
+
+                let mut request: QueuedRequest = this.deserialize_json(&data)?;
+                request.is_wedged = is_wedged;
+                let data = this.serialize_json(&request)?;
+
+                txn.execute(
+                    "UPDATE send_queue_request SET data = ?
+                     WHERE room_id = ? AND transaction_id = ?",
+                    (data, &room_id, &transaction_id),
+                )?;
+
+                Ok(true)
+            })
+            .await
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+        let transaction_id = self.encode_key(keys::SEND_QUEUE, transaction_id);
+
+        let num_removed =
+            self.acquire().await?.remove_send_queue_request(room_id, transaction_id).await?;
+
+        Ok(num_removed > 0)
+    }
+
+    async fn load_send_queue_requests(&self, room_id: &RoomId) -> Result<Vec<QueuedRequest>> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+        self.acquire()
+            .await?
+            .get_send_queue_requests(room_id)
+            .await?
+            .iter()
+            .map(|data| self.deserialize_json(data))
+            .collect()
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>> {
+        self.acquire()
+            .await?
+            .get_rooms_with_unsent_requests()
+            .await?
+            .iter()
+            .map(|data| self.deserialize_value(data))
+            .collect()
+    }
+
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
         let this = self.clone();
         let room_id = room_id.to_owned();
@@ -1538,6 +1828,12 @@ impl StateStore for SqliteStateStore {
                 let display_name_room_id = this.encode_key(keys::DISPLAY_NAME, &room_id);
                 txn.remove_room_display_names(&display_name_room_id)?;
 
+                let event_cache_room_id = this.encode_key(keys::EVENT_CACHE_CHUNK, &room_id);
+                txn.remove_room_event_cache_chunks(&event_cache_room_id)?;
+
+                let send_queue_room_id = this.encode_key(keys::SEND_QUEUE, &room_id);
+                txn.remove_room_send_queue_requests(&send_queue_room_id)?;
+
                 Ok(())
             })
             .await
@@ -29,6 +29,7 @@ use tokio::task::{spawn, JoinHandle};
 use crate::{
     encryption_sync::{self, EncryptionSync, WithLocking},
     room_list_service::{self, RoomListService},
+    unable_to_decrypt_hook::{UnableToDecryptHook, UtdHookManager},
 };
 
 /// Current state of the application.
@@ -185,6 +186,9 @@ pub struct AppBuilder {
     /// Application identifier, used the cross-process lock value, if
     /// applicable.
     identifier: String,
+
+    /// The hook reporting the events that couldn't be decrypted, if any.
+    unable_to_decrypt_hook: Option<Arc<dyn UnableToDecryptHook>>,
 }
 
 impl AppBuilder {
@@ -194,9 +198,18 @@ impl AppBuilder {
             with_cross_process_lock: false,
             with_encryption_sync: false,
             identifier: "app".to_owned(),
+            unable_to_decrypt_hook: None,
         }
     }
 
+    /// Report the events that couldn't be decrypted in the timelines of all the
+    /// rooms, and the time it took to decrypt them if they eventually were,
+    /// to the given hook.
+    pub fn with_unable_to_decrypt_hook(mut self, hook: Arc<dyn UnableToDecryptHook>) -> Self {
+        self.unable_to_decrypt_hook = Some(hook);
+        self
+    }
+
     /// Enables the encryption sync for this application.
     ///
     /// This will run a second sliding sync instance, that can independently
@@ -231,6 +244,8 @@ impl AppBuilder {
     /// background. The resulting `App` must be kept alive as long as the
     /// sliding syncs are supposed to run.
     pub async fn build(self) -> Result<App, Error> {
+        let utd_hook = self.unable_to_decrypt_hook.map(|hook| Arc::new(UtdHookManager::new(hook)));
+
         let (room_list, encryption_sync) = if self.with_encryption_sync {
             let room_list = RoomListService::new(self.client.clone()).await?;
             let encryption_sync = EncryptionSync::new(
@@ -246,6 +261,11 @@ impl AppBuilder {
             (room_list, None)
         };
 
+        let room_list = match utd_hook {
+            Some(hook) => room_list.with_unable_to_decrypt_hook(hook),
+            None => room_list,
+        };
+
         let app = App {
             room_list_service: Arc::new(room_list),
             encryption_sync,
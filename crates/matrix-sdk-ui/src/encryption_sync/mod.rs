@@ -31,8 +31,7 @@ use std::time::Duration;
 use async_stream::stream;
 use futures_core::stream::Stream;
 use futures_util::{pin_mut, StreamExt};
-use matrix_sdk::{Client, SlidingSync};
-use matrix_sdk_crypto::store::locks::CryptoStoreLock;
+use matrix_sdk::{store_locks::LEASE_DURATION_MS, Client, SlidingSync};
 use ruma::{api::client::sync::sync_events::v4, assign};
 use tracing::{error, trace};
 
@@ -131,13 +130,10 @@ impl EncryptionSync {
                 // yet. In case it's the latter, wait a bit and retry.
                 tracing::debug!(
                     "Lock was already taken, and we're not the main loop; retrying in {}ms...",
-                    CryptoStoreLock::LEASE_DURATION_MS
+                    LEASE_DURATION_MS
                 );
 
-                tokio::time::sleep(Duration::from_millis(
-                    CryptoStoreLock::LEASE_DURATION_MS.into(),
-                ))
-                .await;
+                tokio::time::sleep(Duration::from_millis(LEASE_DURATION_MS.into())).await;
 
                 lock_guard = self
                     .client
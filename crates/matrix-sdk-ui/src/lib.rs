@@ -22,11 +22,13 @@ pub mod encryption_sync;
 pub mod notification_client;
 #[cfg(feature = "experimental-room-list")]
 pub mod room_list_service;
+pub mod spaces;
 pub mod timeline;
+pub mod unable_to_decrypt_hook;
 
 #[cfg(feature = "experimental-room-list")]
 pub use self::room_list_service::RoomListService;
-pub use self::timeline::Timeline;
+pub use self::{spaces::SpaceService, timeline::Timeline};
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 #[ctor::ctor]
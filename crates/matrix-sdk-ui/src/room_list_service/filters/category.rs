@@ -0,0 +1,107 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk::{Client, RoomListEntry};
+
+use super::{room_for_entry, Filter};
+
+/// An enum to represent whether a room is about “people” (strictly 2 users) or
+/// “group” (1 or more than 2 users).
+///
+/// Ideally, this would be only about the number of members, but it's simpler
+/// to rely on the direct message targets of the room: a room with direct
+/// message targets is about people, otherwise it's a group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoomCategory {
+    /// The room is a group, i.e. not a direct message.
+    Group,
+
+    /// The room is a direct message with other people.
+    People,
+}
+
+type DirectTargetsLength = usize;
+
+struct CategoryRoomMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<DirectTargetsLength>,
+{
+    /// _Direct targets_ mean the number of users in a direct room, except us.
+    number_of_direct_targets: F,
+}
+
+impl<F> CategoryRoomMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<DirectTargetsLength>,
+{
+    fn matches(&self, room_list_entry: &RoomListEntry, expected_kind: RoomCategory) -> bool {
+        let Some(number_of_direct_targets) = (self.number_of_direct_targets)(room_list_entry)
+        else {
+            return false;
+        };
+
+        let kind =
+            if number_of_direct_targets == 0 { RoomCategory::Group } else { RoomCategory::People };
+
+        kind == expected_kind
+    }
+}
+
+/// Create a new filter that will accept all filled or invalidated entries, but
+/// filters out rooms that are not of the expected [`RoomCategory`].
+pub fn new_filter(client: &Client, expected_kind: RoomCategory) -> impl Filter {
+    let client = client.clone();
+
+    let matcher = CategoryRoomMatcher {
+        number_of_direct_targets: move |room_list_entry| {
+            Some(room_for_entry(&client, room_list_entry)?.direct_targets().len())
+        },
+    };
+
+    move |room_list_entry| -> bool { matcher.matches(room_list_entry, expected_kind) }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::{CategoryRoomMatcher, RoomCategory};
+
+    #[test]
+    fn test_kind_is_group() {
+        let matcher = CategoryRoomMatcher { number_of_direct_targets: |_| Some(0) };
+        let room_list_entry = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+
+        assert!(matcher.matches(&room_list_entry, RoomCategory::Group));
+        assert!(!matcher.matches(&room_list_entry, RoomCategory::People));
+    }
+
+    #[test]
+    fn test_kind_is_people() {
+        let matcher = CategoryRoomMatcher { number_of_direct_targets: |_| Some(1) };
+        let room_list_entry = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+
+        assert!(!matcher.matches(&room_list_entry, RoomCategory::Group));
+        assert!(matcher.matches(&room_list_entry, RoomCategory::People));
+    }
+
+    #[test]
+    fn test_unknown_room() {
+        let matcher = CategoryRoomMatcher { number_of_direct_targets: |_| None };
+
+        assert!(!matcher.matches(&RoomListEntry::Empty, RoomCategory::Group));
+        assert!(!matcher.matches(&RoomListEntry::Empty, RoomCategory::People));
+    }
+}
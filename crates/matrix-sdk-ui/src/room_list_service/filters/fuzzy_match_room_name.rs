@@ -0,0 +1,132 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk::{Client, RoomListEntry};
+
+use super::{room_for_entry, Filter};
+
+struct FuzzyMatcher {
+    pattern: Vec<char>,
+}
+
+impl FuzzyMatcher {
+    fn new(pattern: &str) -> Self {
+        Self { pattern: pattern.chars().flat_map(char::to_lowercase).collect() }
+    }
+
+    /// Whether all the characters of the pattern appear in `subject`, in the
+    /// same order, ignoring the case.
+    ///
+    /// An empty pattern matches everything.
+    fn matches(&self, subject: &str) -> bool {
+        let mut pattern = self.pattern.iter().peekable();
+
+        for character in subject.chars().flat_map(char::to_lowercase) {
+            if pattern.peek() == Some(&&character) {
+                pattern.next();
+            }
+        }
+
+        pattern.peek().is_none()
+    }
+}
+
+struct FuzzyMatchRoomNameMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<String>,
+{
+    matcher: FuzzyMatcher,
+    room_name: F,
+}
+
+impl<F> FuzzyMatchRoomNameMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<String>,
+{
+    fn matches(&self, room_list_entry: &RoomListEntry) -> bool {
+        if self.matcher.pattern.is_empty() {
+            return true;
+        }
+
+        (self.room_name)(room_list_entry).is_some_and(|room_name| self.matcher.matches(&room_name))
+    }
+}
+
+/// Create a new filter that will fuzzy match a pattern on room names.
+///
+/// The name of a room is its `m.room.name` if any, otherwise its canonical
+/// alias. Rooms without a name nor a canonical alias never match, unless the
+/// pattern is empty.
+pub fn new_filter(client: &Client, pattern: &str) -> impl Filter {
+    let client = client.clone();
+
+    let matcher = FuzzyMatchRoomNameMatcher {
+        matcher: FuzzyMatcher::new(pattern),
+        room_name: move |room_list_entry| {
+            let room = room_for_entry(&client, room_list_entry)?;
+
+            room.name().or_else(|| room.canonical_alias().map(|alias| alias.to_string()))
+        },
+    };
+
+    move |room_list_entry| -> bool { matcher.matches(room_list_entry) }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::{FuzzyMatchRoomNameMatcher, FuzzyMatcher};
+
+    #[test]
+    fn test_fuzzy_matcher() {
+        let matcher = FuzzyMatcher::new("mtx");
+
+        assert!(matcher.matches("Matrix"));
+        assert!(matcher.matches("MATRIX Rust SDK"));
+        assert!(!matcher.matches("Mixer"));
+        assert!(!matcher.matches("Rust"));
+
+        assert!(FuzzyMatcher::new("").matches("Anything"));
+    }
+
+    #[test]
+    fn test_room_name() {
+        let room_list_entry = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+
+        let matcher = FuzzyMatchRoomNameMatcher {
+            matcher: FuzzyMatcher::new("Rust"),
+            room_name: |_| Some("Matrix Rust SDK".to_owned()),
+        };
+        assert!(matcher.matches(&room_list_entry));
+
+        let matcher = FuzzyMatchRoomNameMatcher {
+            matcher: FuzzyMatcher::new("Python"),
+            room_name: |_| Some("Matrix Rust SDK".to_owned()),
+        };
+        assert!(!matcher.matches(&room_list_entry));
+    }
+
+    #[test]
+    fn test_no_room_name() {
+        let matcher =
+            FuzzyMatchRoomNameMatcher { matcher: FuzzyMatcher::new("Rust"), room_name: |_| None };
+        assert!(!matcher.matches(&RoomListEntry::Empty));
+
+        let matcher =
+            FuzzyMatchRoomNameMatcher { matcher: FuzzyMatcher::new(""), room_name: |_| None };
+        assert!(matcher.matches(&RoomListEntry::Empty));
+    }
+}
@@ -0,0 +1,23 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk::{Client, RoomState};
+
+use super::{new_room_filter, Filter};
+
+/// Create a new filter that will accept all filled or invalidated entries, but
+/// filters out rooms that we are not invited to.
+pub fn new_filter(client: &Client) -> impl Filter {
+    new_room_filter(client, |room| matches!(room.state(), RoomState::Invited))
+}
@@ -0,0 +1,145 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A collection of room filters.
+//!
+//! The room list service provides some built-in filters to filter the
+//! [`RoomListEntry`]s of a [`RoomList`](super::RoomList). Filters can be
+//! combined with [`new_filter_all`], [`new_filter_any`] and
+//! [`new_filter_not`], and used with
+//! [`RoomList::entries_filtered`](super::RoomList::entries_filtered) or with
+//! a [`RoomListDynamicEntriesController`](super::RoomListDynamicEntriesController).
+//!
+//! ```rust,no_run
+//! # async {
+//! # let client: matrix_sdk::Client = todo!();
+//! # let room_list_service = matrix_sdk_ui::RoomListService::new(client.clone()).await?;
+//! use matrix_sdk_ui::room_list_service::filters::{
+//!     new_filter_all, new_filter_category, new_filter_unread, RoomCategory,
+//! };
+//!
+//! let all_rooms = room_list_service.all_rooms().await?;
+//!
+//! // Only the unread direct messages.
+//! let (entries, entries_stream) = all_rooms.entries_filtered(new_filter_all(vec![
+//!     Box::new(new_filter_unread(&client)),
+//!     Box::new(new_filter_category(&client, RoomCategory::People)),
+//! ]));
+//! # anyhow::Ok(()) };
+//! ```
+
+mod all;
+mod any;
+mod category;
+mod favourite;
+mod fuzzy_match_room_name;
+mod invite;
+mod low_priority;
+mod not;
+mod space;
+mod unread;
+
+pub use all::new_filter as new_filter_all;
+pub use any::new_filter as new_filter_any;
+pub use category::{new_filter as new_filter_category, RoomCategory};
+pub use favourite::new_filter as new_filter_favourite;
+pub use fuzzy_match_room_name::new_filter as new_filter_fuzzy_match_room_name;
+pub use invite::new_filter as new_filter_invite;
+pub use low_priority::new_filter as new_filter_low_priority;
+use matrix_sdk::{room::Room, Client, RoomListEntry};
+pub use not::new_filter as new_filter_not;
+pub use space::new_filter as new_filter_space;
+pub use unread::new_filter as new_filter_unread;
+
+/// A trait “alias” that represents a _filter_.
+///
+/// A filter is simply a function that receives a `&RoomListEntry` and returns
+/// a `bool`.
+pub trait Filter: Fn(&RoomListEntry) -> bool {}
+
+impl<F> Filter for F where F: Fn(&RoomListEntry) -> bool {}
+
+/// Type alias for a boxed filter function.
+pub type BoxedFilterFn = Box<dyn Filter + Send + Sync>;
+
+/// Get the client room behind a room list entry, if the entry is filled or
+/// invalidated, and if the room is known by the client.
+pub(super) fn room_for_entry(client: &Client, room_list_entry: &RoomListEntry) -> Option<Room> {
+    client.get_room(room_list_entry.as_room_id()?)
+}
+
+/// Matches the entries whose room has some property, i.e. for which
+/// `has_property` returns `Some(true)`.
+struct RoomMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<bool>,
+{
+    /// Returns `None` if the entry has no room, or if the room is unknown.
+    has_property: F,
+}
+
+impl<F> RoomMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<bool>,
+{
+    fn matches(&self, room_list_entry: &RoomListEntry) -> bool {
+        (self.has_property)(room_list_entry).unwrap_or(false)
+    }
+}
+
+/// Create a new filter that will accept the filled or invalidated entries whose
+/// room is known by the client and matches `predicate`.
+fn new_room_filter<P>(client: &Client, predicate: P) -> impl Filter
+where
+    P: Fn(&Room) -> bool + Send + Sync + 'static,
+{
+    let client = client.clone();
+
+    let matcher = RoomMatcher {
+        has_property: move |room_list_entry| {
+            Some(predicate(&room_for_entry(&client, room_list_entry)?))
+        },
+    };
+
+    move |room_list_entry| -> bool { matcher.matches(room_list_entry) }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::RoomMatcher;
+
+    #[test]
+    fn test_has_property() {
+        let matcher = RoomMatcher { has_property: |_| Some(true) };
+
+        assert!(matcher.matches(&RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned())));
+    }
+
+    #[test]
+    fn test_does_not_have_property() {
+        let matcher = RoomMatcher { has_property: |_| Some(false) };
+
+        assert!(!matcher.matches(&RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned())));
+    }
+
+    #[test]
+    fn test_unknown_room() {
+        let matcher = RoomMatcher { has_property: |_| None };
+
+        assert!(!matcher.matches(&RoomListEntry::Empty));
+    }
+}
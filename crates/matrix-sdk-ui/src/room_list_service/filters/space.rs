@@ -0,0 +1,55 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeSet;
+
+use matrix_sdk::RoomListEntry;
+use ruma::OwnedRoomId;
+
+use super::Filter;
+
+/// Create a new filter that will accept all filled or invalidated entries, but
+/// filters out rooms that are not part of the given set of rooms.
+///
+/// It's typically used to only keep the children of a space, or the rooms of
+/// a custom category: spaces aren't part of the room list, so the caller is
+/// responsible for providing the room IDs of the children.
+///
+/// To keep the rooms of a joined space up to date with its children, see
+/// [`SpaceService::new_room_list_filter()`](crate::SpaceService::new_room_list_filter).
+pub fn new_filter(room_ids: impl IntoIterator<Item = OwnedRoomId>) -> impl Filter {
+    let room_ids: BTreeSet<_> = room_ids.into_iter().collect();
+
+    move |room_list_entry: &RoomListEntry| -> bool {
+        room_list_entry.as_room_id().is_some_and(|room_id| room_ids.contains(room_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::new_filter;
+
+    #[test]
+    fn test_space_children() {
+        let filter = new_filter([room_id!("!r0:bar.org").to_owned()]);
+
+        assert!(filter(&RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned())));
+        assert!(filter(&RoomListEntry::Invalidated(room_id!("!r0:bar.org").to_owned())));
+        assert!(!filter(&RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned())));
+        assert!(!filter(&RoomListEntry::Empty));
+    }
+}
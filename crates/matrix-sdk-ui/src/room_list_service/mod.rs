@@ -59,11 +59,19 @@
 //! stream of room list entry. This stream can be filtered, and the filter can
 //! be changed over time.
 //!
+//! [`RoomList::entries_with_dynamic_adapters`] provides a stream of room list
+//! entries that are filtered and sorted on the client side, with a
+//! [`RoomListDynamicEntriesController`] to change the filter and the sorter at
+//! runtime. Built-in filters are available in the [`filters`] module, and
+//! built-in sorters are available in the [`sorters`] module.
+//!
 //! [`RoomListService::state`] provides a way to get a stream of the state
 //! machine's state, which can be pretty helpful for the client app.
 
+pub mod filters;
 mod room;
 mod room_list;
+pub mod sorters;
 mod state;
 
 use std::{future::ready, sync::Arc};
@@ -91,9 +99,14 @@ pub use state::*;
 use thiserror::Error;
 use tokio::sync::{Mutex, RwLock};
 
+use crate::unable_to_decrypt_hook::UtdHookManager;
+
 /// The [`RoomListService`] type. See the module's documentation to learn more.
 #[derive(Debug)]
 pub struct RoomListService {
+    /// The client that runs the room list.
+    client: Client,
+
     /// The Sliding Sync instance.
     sliding_sync: Arc<SlidingSync>,
 
@@ -110,6 +123,10 @@ pub struct RoomListService {
     /// This is useful to avoid resetting the ranges to the same value,
     /// which would cancel the current in-flight sync request.
     viewport_ranges: Mutex<Ranges>,
+
+    /// The hook reporting the events that couldn't be decrypted, shared by the
+    /// timelines of all the rooms.
+    unable_to_decrypt_hook: Option<Arc<UtdHookManager>>,
 }
 
 impl RoomListService {
@@ -174,13 +191,22 @@ impl RoomListService {
             .map_err(Error::SlidingSync)?;
 
         Ok(Self {
+            client,
             sliding_sync,
             state: SharedObservable::new(State::Init),
             rooms: Arc::new(RwLock::new(RingBuffer::new(Self::ROOM_OBJECT_CACHE_SIZE))),
             viewport_ranges: Mutex::new(vec![VISIBLE_ROOMS_DEFAULT_RANGE]),
+            unable_to_decrypt_hook: None,
         })
     }
 
+    /// Report the events that couldn't be decrypted in the timelines of the
+    /// rooms to the given hook.
+    pub fn with_unable_to_decrypt_hook(mut self, hook: Arc<UtdHookManager>) -> Self {
+        self.unable_to_decrypt_hook = Some(hook);
+        self
+    }
+
     /// Start to sync the room list.
     ///
     /// It's the main method of this entire API. Calling `sync` allows to
@@ -257,6 +283,13 @@ impl RoomListService {
         self.sliding_sync.stop_sync().map_err(Error::SlidingSync)
     }
 
+    /// Get the [`Client`] that runs this `RoomListService`.
+    ///
+    /// It's useful to create the built-in [`filters`] and [`sorters`].
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
     /// Get a subscriber to the state.
     pub fn state(&self) -> Subscriber<State> {
         self.state.subscribe()
@@ -319,7 +352,9 @@ impl RoomListService {
         }
 
         let room = match self.sliding_sync.get_room(room_id).await {
-            Some(room) => Room::new(self.sliding_sync.clone(), room)?,
+            Some(room) => {
+                Room::new(self.sliding_sync.clone(), room, self.unable_to_decrypt_hook.clone())?
+            }
             None => return Err(Error::RoomNotFound(room_id.to_owned())),
         };
 
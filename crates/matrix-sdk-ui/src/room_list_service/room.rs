@@ -26,6 +26,7 @@ use ruma::{
 use super::Error;
 use crate::{
     timeline::{EventTimelineItem, SlidingSyncRoomExt},
+    unable_to_decrypt_hook::UtdHookManager,
     Timeline,
 };
 
@@ -50,6 +51,10 @@ struct RoomInner {
 
     /// The timeline of the room.
     timeline: AsyncOnceCell<Arc<Timeline>>,
+
+    /// The hook reporting the events of the timeline that couldn't be
+    /// decrypted.
+    unable_to_decrypt_hook: Option<Arc<UtdHookManager>>,
 }
 
 impl Room {
@@ -57,6 +62,7 @@ impl Room {
     pub(super) fn new(
         sliding_sync: Arc<SlidingSync>,
         sliding_sync_room: SlidingSyncRoom,
+        unable_to_decrypt_hook: Option<Arc<UtdHookManager>>,
     ) -> Result<Self, Error> {
         let room = sliding_sync_room
             .client()
@@ -69,6 +75,7 @@ impl Room {
                 sliding_sync_room,
                 room,
                 timeline: AsyncOnceCell::new(),
+                unable_to_decrypt_hook,
             }),
         })
     }
@@ -114,16 +121,18 @@ impl Room {
         self.inner
             .timeline
             .get_or_init(async {
-                Arc::new(
-                    Timeline::builder(&self.inner.room)
-                        .events(
-                            self.inner.sliding_sync_room.prev_batch(),
-                            self.inner.sliding_sync_room.timeline_queue(),
-                        )
-                        .track_read_marker_and_receipts()
-                        .build()
-                        .await,
-                )
+                let mut builder = Timeline::builder(&self.inner.room)
+                    .events(
+                        self.inner.sliding_sync_room.prev_batch(),
+                        self.inner.sliding_sync_room.timeline_queue(),
+                    )
+                    .track_read_marker_and_receipts();
+
+                if let Some(hook) = &self.inner.unable_to_decrypt_hook {
+                    builder = builder.with_unable_to_decrypt_hook(hook.clone());
+                }
+
+                Arc::new(builder.build().await)
             })
             .await
             .clone()
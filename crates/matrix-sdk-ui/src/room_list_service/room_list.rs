@@ -12,8 +12,9 @@
 // See the License for that specific language governing permissions and
 // limitations under the License.
 
-use std::future::ready;
+use std::{collections::BTreeMap, future::ready, sync::Arc};
 
+use async_stream::stream;
 use eyeball::{SharedObservable, Subscriber};
 use eyeball_im::{Vector, VectorDiff};
 use futures_util::{pin_mut, Stream, StreamExt};
@@ -21,8 +22,9 @@ use matrix_sdk::{
     executor::{spawn, JoinHandle},
     RoomListEntry, SlidingSync, SlidingSyncList,
 };
+use ruma::OwnedRoomId;
 
-use super::{Error, State};
+use super::{filters::BoxedFilterFn, sorters::BoxedSorterFn, Error, State};
 
 /// A `RoomList` represents a list of rooms, from a
 /// [`RoomListService`](super::RoomListService).
@@ -111,6 +113,351 @@ impl RoomList {
     {
         self.sliding_sync_list.room_list_filtered_stream(filter)
     }
+
+    /// Similar to [`Self::entries`] except that the room list entries can be
+    /// filtered and sorted on the client side, with filters and sorters that
+    /// can be changed at runtime with the returned
+    /// [`RoomListDynamicEntriesController`].
+    ///
+    /// By default, no filter and no sorter are applied: the entries are the
+    /// same as the ones returned by [`Self::entries`].
+    ///
+    /// Every time the filter or the sorter changes, the stream emits a
+    /// [`VectorDiff::Reset`] with the new entries. Otherwise, the updates of
+    /// the room list are translated into the updates of the filtered and
+    /// sorted entries, so that applying the [`VectorDiff`]s onto the previous
+    /// entries always gives the current entries. The updates received
+    /// together, e.g. from the same sync response, are handled at once, and
+    /// the filter only runs again on the entries that were updated.
+    ///
+    /// See the [`filters`](super::filters) and [`sorters`](super::sorters)
+    /// modules for the built-in filters and sorters.
+    pub fn entries_with_dynamic_adapters(
+        &self,
+    ) -> (
+        Vector<RoomListEntry>,
+        impl Stream<Item = VectorDiff<RoomListEntry>>,
+        RoomListDynamicEntriesController,
+    ) {
+        let adapters = SharedObservable::new(DynamicAdapters::default());
+        let mut adapters_stream = adapters.subscribe();
+
+        let (mut raw_entries, raw_entries_stream) = self.sliding_sync_list.room_list_stream();
+        let mut filter_results = BTreeMap::new();
+        let entries = adapters.get().apply(&raw_entries, &mut filter_results);
+
+        let stream = {
+            let mut current_entries = entries.clone();
+            let mut is_controller_dropped = false;
+
+            stream! {
+                let raw_entries_stream = raw_entries_stream.ready_chunks(MAXIMUM_DIFFS_BATCH_SIZE);
+                pin_mut!(raw_entries_stream);
+
+                loop {
+                    let diffs = tokio::select! {
+                        raw_diffs = raw_entries_stream.next() => {
+                            let Some(raw_diffs) = raw_diffs else { break };
+
+                            let mut updated = Vec::new();
+                            for diff in raw_diffs {
+                                updated.extend(updated_entries(&diff));
+                                apply_diff(&mut raw_entries, diff);
+                            }
+
+                            // The rooms of the updated entries may not match the filter anymore.
+                            for entry in &updated {
+                                if let Some(room_id) = entry.as_room_id() {
+                                    filter_results.remove(room_id);
+                                }
+                            }
+
+                            let new_entries =
+                                adapters_stream.get().apply(&raw_entries, &mut filter_results);
+                            let diffs = compute_diffs(&current_entries, &new_entries, &updated);
+                            current_entries = new_entries;
+
+                            diffs
+                        }
+
+                        adapters = adapters_stream.next(), if !is_controller_dropped => {
+                            let Some(adapters) = adapters else {
+                                // The controller has been dropped, the adapters
+                                // won't change anymore.
+                                is_controller_dropped = true;
+                                continue;
+                            };
+
+                            filter_results.clear();
+                            current_entries = adapters.apply(&raw_entries, &mut filter_results);
+
+                            vec![VectorDiff::Reset { values: current_entries.clone() }]
+                        }
+                    };
+
+                    for diff in diffs {
+                        yield diff;
+                    }
+                }
+            }
+        };
+
+        (entries, stream, RoomListDynamicEntriesController { adapters })
+    }
+}
+
+/// Controller for the adapters of the stream returned by
+/// [`RoomList::entries_with_dynamic_adapters`].
+///
+/// Dropping the controller doesn't stop the stream: the last filter and sorter
+/// stay in place.
+pub struct RoomListDynamicEntriesController {
+    adapters: SharedObservable<DynamicAdapters>,
+}
+
+impl RoomListDynamicEntriesController {
+    /// Filter the entries with a new filter, replacing the previous one.
+    ///
+    /// Filters can be combined with
+    /// [`new_filter_all`](super::filters::new_filter_all) and friends.
+    pub fn set_filter(&self, filter: BoxedFilterFn) {
+        self.update(|adapters| adapters.filter = Some(Arc::new(filter)));
+    }
+
+    /// Remove the filter, so that all the entries are kept.
+    pub fn reset_filter(&self) {
+        self.update(|adapters| adapters.filter = None);
+    }
+
+    /// Sort the entries with a new sorter, replacing the previous one.
+    ///
+    /// Sorters can be chained with
+    /// [`new_sorter_lexicographic`](super::sorters::new_sorter_lexicographic).
+    pub fn set_sorter(&self, sorter: BoxedSorterFn) {
+        self.update(|adapters| adapters.sorter = Some(Arc::new(sorter)));
+    }
+
+    /// Remove the sorter, so that the entries are in the order given by the
+    /// server.
+    pub fn reset_sorter(&self) {
+        self.update(|adapters| adapters.sorter = None);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut DynamicAdapters)) {
+        let mut adapters = self.adapters.get();
+        f(&mut adapters);
+        self.adapters.set(adapters);
+    }
+}
+
+/// The filter and the sorter used by
+/// [`RoomList::entries_with_dynamic_adapters`].
+#[derive(Clone, Default)]
+struct DynamicAdapters {
+    filter: Option<Arc<BoxedFilterFn>>,
+    sorter: Option<Arc<BoxedSorterFn>>,
+}
+
+impl DynamicAdapters {
+    /// Filter, then sort, the given room list entries.
+    ///
+    /// `filter_results` holds whether the rooms matched the filter the last
+    /// time it ran, the filter only runs on the rooms that aren't in it.
+    fn apply(
+        &self,
+        entries: &Vector<RoomListEntry>,
+        filter_results: &mut BTreeMap<OwnedRoomId, bool>,
+    ) -> Vector<RoomListEntry> {
+        let mut entries: Vec<_> = match &self.filter {
+            Some(filter) => entries
+                .iter()
+                .filter(|entry| match entry.as_room_id() {
+                    Some(room_id) => {
+                        *filter_results.entry(room_id.to_owned()).or_insert_with(|| filter(entry))
+                    }
+                    None => filter(entry),
+                })
+                .cloned()
+                .collect(),
+            None => entries.iter().cloned().collect(),
+        };
+
+        if let Some(sorter) = &self.sorter {
+            // `sort_by` is stable, which is important to not shuffle equal entries.
+            entries.sort_by(|left, right| sorter(left, right));
+        }
+
+        entries.into_iter().collect()
+    }
+}
+
+/// Apply a [`VectorDiff`] onto a [`Vector`].
+fn apply_diff(entries: &mut Vector<RoomListEntry>, diff: VectorDiff<RoomListEntry>) {
+    match diff {
+        VectorDiff::Append { values } => entries.append(values),
+        VectorDiff::Clear => entries.clear(),
+        VectorDiff::PushFront { value } => entries.push_front(value),
+        VectorDiff::PushBack { value } => entries.push_back(value),
+        VectorDiff::PopFront => {
+            entries.pop_front();
+        }
+        VectorDiff::PopBack => {
+            entries.pop_back();
+        }
+        VectorDiff::Insert { index, value } => entries.insert(index, value),
+        VectorDiff::Set { index, value } => {
+            entries.set(index, value);
+        }
+        VectorDiff::Remove { index } => {
+            entries.remove(index);
+        }
+        VectorDiff::Reset { values } => *entries = values,
+    }
+}
+
+/// Get the room entries that are inserted or updated by a [`VectorDiff`].
+///
+/// Those entries must be re-emitted, even if their position doesn't change,
+/// so that the room they represent can be refreshed.
+fn updated_entries(diff: &VectorDiff<RoomListEntry>) -> Vec<RoomListEntry> {
+    let entries: Vec<_> = match diff {
+        VectorDiff::Append { values } | VectorDiff::Reset { values } => {
+            values.iter().cloned().collect()
+        }
+        VectorDiff::PushFront { value }
+        | VectorDiff::PushBack { value }
+        | VectorDiff::Insert { value, .. }
+        | VectorDiff::Set { value, .. } => vec![value.clone()],
+        VectorDiff::Clear
+        | VectorDiff::PopFront
+        | VectorDiff::PopBack
+        | VectorDiff::Remove { .. } => Vec::new(),
+    };
+
+    entries.into_iter().filter(|entry| entry.as_room_id().is_some()).collect()
+}
+
+/// The maximum number of updates of the room list that are handled at once by
+/// [`RoomList::entries_with_dynamic_adapters`].
+const MAXIMUM_DIFFS_BATCH_SIZE: usize = 512;
+
+/// Beyond this size, computing the difference between the old and the new
+/// entries is considered too expensive, and a [`VectorDiff::Reset`] is emitted
+/// instead.
+const MAXIMUM_DIFF_COMPLEXITY: usize = 256 * 256;
+
+/// Compute the [`VectorDiff`]s to go from `old_entries` to `new_entries`.
+///
+/// The common prefix and suffix of both lists are skipped, then the changes in
+/// the middle are computed with a longest common subsequence algorithm, so
+/// that a room moving in the list results in a removal and an insertion, and
+/// a room replacing another one results in a [`VectorDiff::Set`]. Entries that
+/// are kept and are in `updated_entries` are re-emitted with a
+/// [`VectorDiff::Set`].
+fn compute_diffs(
+    old_entries: &Vector<RoomListEntry>,
+    new_entries: &Vector<RoomListEntry>,
+    updated_entries: &[RoomListEntry],
+) -> Vec<VectorDiff<RoomListEntry>> {
+    if old_entries.is_empty() {
+        return if new_entries.is_empty() {
+            Vec::new()
+        } else {
+            vec![VectorDiff::Append { values: new_entries.clone() }]
+        };
+    }
+
+    if new_entries.is_empty() {
+        return vec![VectorDiff::Clear];
+    }
+
+    let prefix_length =
+        old_entries.iter().zip(new_entries.iter()).take_while(|(old, new)| old == new).count();
+    let suffix_length = old_entries
+        .iter()
+        .skip(prefix_length)
+        .rev()
+        .zip(new_entries.iter().skip(prefix_length).rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    let old_middle: Vec<_> = old_entries
+        .iter()
+        .skip(prefix_length)
+        .take(old_entries.len() - prefix_length - suffix_length)
+        .collect();
+    let new_middle: Vec<_> = new_entries
+        .iter()
+        .skip(prefix_length)
+        .take(new_entries.len() - prefix_length - suffix_length)
+        .collect();
+
+    let (old_length, new_length) = (old_middle.len(), new_middle.len());
+
+    if old_length.saturating_mul(new_length) > MAXIMUM_DIFF_COMPLEXITY {
+        return vec![VectorDiff::Reset { values: new_entries.clone() }];
+    }
+
+    let mut diffs = Vec::new();
+    let is_updated = |entry: &RoomListEntry| updated_entries.contains(entry);
+
+    for (index, entry) in new_entries.iter().enumerate().take(prefix_length) {
+        if is_updated(entry) {
+            diffs.push(VectorDiff::Set { index, value: entry.clone() });
+        }
+    }
+
+    // `lcs[i][j]` is the length of the longest common subsequence of
+    // `old_middle[i..]` and `new_middle[j..]`.
+    let mut lcs = vec![vec![0usize; new_length + 1]; old_length + 1];
+
+    for i in (0..old_length).rev() {
+        for j in (0..new_length).rev() {
+            lcs[i][j] = if old_middle[i] == new_middle[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j, mut index) = (0, 0, prefix_length);
+
+    while i < old_length || j < new_length {
+        if i < old_length && j < new_length && old_middle[i] == new_middle[j] {
+            if is_updated(new_middle[j]) {
+                diffs.push(VectorDiff::Set { index, value: new_middle[j].clone() });
+            }
+
+            i += 1;
+            j += 1;
+            index += 1;
+        } else if i < old_length && j < new_length && lcs[i][j] == lcs[i + 1][j + 1] {
+            // Replacing the entry doesn't break the longest common subsequence.
+            diffs.push(VectorDiff::Set { index, value: new_middle[j].clone() });
+
+            i += 1;
+            j += 1;
+            index += 1;
+        } else if j < new_length && (i == old_length || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            diffs.push(VectorDiff::Insert { index, value: new_middle[j].clone() });
+
+            j += 1;
+            index += 1;
+        } else {
+            diffs.push(VectorDiff::Remove { index });
+
+            i += 1;
+        }
+    }
+
+    for (index, entry) in new_entries.iter().enumerate().skip(new_entries.len() - suffix_length) {
+        if is_updated(entry) {
+            diffs.push(VectorDiff::Set { index, value: entry.clone() });
+        }
+    }
+
+    diffs
 }
 
 /// The loading state of a [`RoomList`].
@@ -153,3 +500,127 @@ pub enum RoomListLoadingState {
         maximum_number_of_rooms: Option<u32>,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use eyeball_im::{Vector, VectorDiff};
+    use imbl::vector;
+    use matrix_sdk::RoomListEntry;
+    use ruma::{room_id, RoomId};
+
+    use super::{apply_diff, compute_diffs, DynamicAdapters};
+
+    fn entry(room_id: &str) -> RoomListEntry {
+        RoomListEntry::Filled(<&RoomId>::try_from(room_id).unwrap().to_owned())
+    }
+
+    fn assert_diffs_apply(
+        old_entries: &Vector<RoomListEntry>,
+        new_entries: &Vector<RoomListEntry>,
+        diffs: Vec<VectorDiff<RoomListEntry>>,
+    ) {
+        let mut entries = old_entries.clone();
+
+        for diff in diffs {
+            apply_diff(&mut entries, diff);
+        }
+
+        assert_eq!(&entries, new_entries);
+    }
+
+    #[test]
+    fn test_compute_diffs_move() {
+        let old_entries = vector![entry("!r0:bar.org"), entry("!r1:bar.org"), entry("!r2:bar.org")];
+        let new_entries = vector![entry("!r2:bar.org"), entry("!r0:bar.org"), entry("!r1:bar.org")];
+
+        let diffs = compute_diffs(&old_entries, &new_entries, &[]);
+
+        assert_eq!(
+            diffs,
+            vec![
+                VectorDiff::Insert { index: 0, value: entry("!r2:bar.org") },
+                VectorDiff::Remove { index: 3 },
+            ]
+        );
+        assert_diffs_apply(&old_entries, &new_entries, diffs);
+    }
+
+    #[test]
+    fn test_compute_diffs_insert_remove_and_replace() {
+        let old_entries = vector![
+            entry("!r0:bar.org"),
+            entry("!r1:bar.org"),
+            RoomListEntry::Empty,
+            entry("!r3:bar.org"),
+        ];
+        let new_entries = vector![
+            entry("!r1:bar.org"),
+            entry("!r2:bar.org"),
+            entry("!r3:bar.org"),
+            entry("!r4:bar.org"),
+        ];
+
+        let diffs = compute_diffs(&old_entries, &new_entries, &[]);
+
+        assert_eq!(
+            diffs,
+            vec![
+                VectorDiff::Remove { index: 0 },
+                VectorDiff::Set { index: 1, value: entry("!r2:bar.org") },
+                VectorDiff::Insert { index: 3, value: entry("!r4:bar.org") },
+            ]
+        );
+        assert_diffs_apply(&old_entries, &new_entries, diffs);
+    }
+
+    #[test]
+    fn test_compute_diffs_updated_entries() {
+        let old_entries = vector![entry("!r0:bar.org"), entry("!r1:bar.org"), entry("!r2:bar.org")];
+        let new_entries = old_entries.clone();
+
+        // Nothing has changed.
+        assert!(compute_diffs(&old_entries, &new_entries, &[]).is_empty());
+
+        // The entry has been updated, it must be re-emitted.
+        assert_eq!(
+            compute_diffs(&old_entries, &new_entries, &[entry("!r1:bar.org")]),
+            vec![VectorDiff::Set { index: 1, value: entry("!r1:bar.org") }]
+        );
+    }
+
+    #[test]
+    fn test_filter_results_are_reused() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let filter = {
+            let calls = calls.clone();
+            move |entry: &RoomListEntry| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                entry.as_room_id() != Some(room_id!("!r1:bar.org"))
+            }
+        };
+        let adapters = DynamicAdapters { filter: Some(Arc::new(Box::new(filter))), sorter: None };
+
+        let entries = vector![entry("!r0:bar.org"), entry("!r1:bar.org")];
+        let mut filter_results = BTreeMap::new();
+
+        assert_eq!(adapters.apply(&entries, &mut filter_results), vector![entry("!r0:bar.org")]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // The rooms have already been filtered.
+        assert_eq!(adapters.apply(&entries, &mut filter_results), vector![entry("!r0:bar.org")]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Only the room that has been updated is filtered again.
+        filter_results.remove(room_id!("!r1:bar.org"));
+        assert_eq!(adapters.apply(&entries, &mut filter_results), vector![entry("!r0:bar.org")]);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use super::{BoxedSorterFn, Sorter};
+
+/// Create a new sorter that will run multiple sorters. When the `n`-th sorter
+/// returns [`Ordering::Equal`], the next sorter is called. It stops at the
+/// first sorter that doesn't return [`Ordering::Equal`].
+pub fn new_sorter(sorters: Vec<BoxedSorterFn>) -> impl Sorter {
+    move |left, right| -> Ordering {
+        sorters
+            .iter()
+            .map(|sorter| sorter(left, right))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::new_sorter;
+
+    #[test]
+    fn test_chained_sorters() {
+        let left = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let right = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        {
+            let sorter = new_sorter(vec![
+                Box::new(|_: &RoomListEntry, _: &RoomListEntry| Ordering::Equal),
+                Box::new(|_: &RoomListEntry, _: &RoomListEntry| Ordering::Greater),
+                Box::new(|_: &RoomListEntry, _: &RoomListEntry| Ordering::Less),
+            ]);
+
+            assert_eq!(sorter(&left, &right), Ordering::Greater);
+        }
+
+        {
+            let sorter = new_sorter(vec![
+                Box::new(|_: &RoomListEntry, _: &RoomListEntry| Ordering::Equal),
+                Box::new(|_: &RoomListEntry, _: &RoomListEntry| Ordering::Equal),
+            ]);
+
+            assert_eq!(sorter(&left, &right), Ordering::Equal);
+        }
+
+        {
+            let sorter = new_sorter(vec![]);
+
+            assert_eq!(sorter(&left, &right), Ordering::Equal);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A collection of room sorters.
+//!
+//! The room list service provides some built-in sorters to sort the
+//! [`RoomListEntry`]s of a [`RoomList`](super::RoomList) on the client side.
+//! Sorters can be chained with [`new_sorter_lexicographic`], and used with a
+//! [`RoomListDynamicEntriesController`](super::RoomListDynamicEntriesController).
+//!
+//! All the sorters are stable: entries that are equal for a sorter keep their
+//! relative order, i.e. the order given by the server.
+
+mod lexicographic;
+mod name;
+mod recency;
+mod unread;
+
+use std::cmp::Ordering;
+
+pub use lexicographic::new_sorter as new_sorter_lexicographic;
+use matrix_sdk::RoomListEntry;
+pub use name::new_sorter as new_sorter_name;
+pub use recency::new_sorter as new_sorter_recency;
+pub use unread::new_sorter as new_sorter_unread;
+
+/// A trait “alias” that represents a _sorter_.
+///
+/// A sorter is simply a function that receives two `&RoomListEntry`s and
+/// returns an [`Ordering`].
+pub trait Sorter: Fn(&RoomListEntry, &RoomListEntry) -> Ordering {}
+
+impl<F> Sorter for F where F: Fn(&RoomListEntry, &RoomListEntry) -> Ordering {}
+
+/// Type alias for a boxed sorter function.
+pub type BoxedSorterFn = Box<dyn Sorter + Send + Sync>;
+
+/// Compare two optional keys, so that entries with a key always come before
+/// the entries without a key.
+fn cmp_keys<K>(left: Option<K>, right: Option<K>, cmp: impl FnOnce(K, K) -> Ordering) -> Ordering {
+    match (left, right) {
+        (Some(left), Some(right)) => cmp(left, right),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
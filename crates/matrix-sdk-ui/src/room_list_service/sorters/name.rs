@@ -0,0 +1,91 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use matrix_sdk::{Client, RoomListEntry};
+
+use super::{cmp_keys, Sorter};
+use crate::room_list_service::filters::room_for_entry;
+
+struct NameMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<String>,
+{
+    room_name: F,
+}
+
+impl<F> NameMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<String>,
+{
+    fn cmp(&self, left: &RoomListEntry, right: &RoomListEntry) -> Ordering {
+        cmp_keys(
+            (self.room_name)(left).map(|name| name.to_lowercase()),
+            (self.room_name)(right).map(|name| name.to_lowercase()),
+            |left, right| left.cmp(&right),
+        )
+    }
+}
+
+/// Create a new sorter that will sort the rooms alphabetically by name,
+/// ignoring the case.
+///
+/// The name of a room is its `m.room.name` if any, otherwise its canonical
+/// alias. Rooms without a name nor a canonical alias come last.
+pub fn new_sorter(client: &Client) -> impl Sorter {
+    let client = client.clone();
+
+    let matcher = NameMatcher {
+        room_name: move |room_list_entry| {
+            let room = room_for_entry(&client, room_list_entry)?;
+
+            room.name().or_else(|| room.canonical_alias().map(|alias| alias.to_string()))
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.cmp(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::NameMatcher;
+
+    #[test]
+    fn test_alphabetical_order() {
+        let matcher = NameMatcher {
+            room_name: |room_list_entry| match room_list_entry.as_room_id()?.as_str() {
+                "!r0:bar.org" => Some("beta".to_owned()),
+                "!r1:bar.org" => Some("Alpha".to_owned()),
+                _ => None,
+            },
+        };
+
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+        let r2 = RoomListEntry::Filled(room_id!("!r2:bar.org").to_owned());
+
+        assert_eq!(matcher.cmp(&r0, &r1), Ordering::Greater);
+        assert_eq!(matcher.cmp(&r1, &r0), Ordering::Less);
+
+        // Rooms without a name come last.
+        assert_eq!(matcher.cmp(&r2, &r0), Ordering::Greater);
+        assert_eq!(matcher.cmp(&r2, &RoomListEntry::Empty), Ordering::Equal);
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use matrix_sdk::{Client, RoomListEntry};
+use ruma::MilliSecondsSinceUnixEpoch;
+
+use super::{cmp_keys, Sorter};
+use crate::room_list_service::filters::room_for_entry;
+
+struct RecencyMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<MilliSecondsSinceUnixEpoch>,
+{
+    latest_event_timestamp: F,
+}
+
+impl<F> RecencyMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<MilliSecondsSinceUnixEpoch>,
+{
+    fn cmp(&self, left: &RoomListEntry, right: &RoomListEntry) -> Ordering {
+        cmp_keys(
+            (self.latest_event_timestamp)(left),
+            (self.latest_event_timestamp)(right),
+            // The most recent room comes first.
+            |left, right| right.cmp(&left),
+        )
+    }
+}
+
+/// Create a new sorter that will sort the rooms by recency, i.e. by the
+/// timestamp of their latest event, the most recent first.
+///
+/// Rooms without a known latest event come last.
+pub fn new_sorter(client: &Client) -> impl Sorter {
+    let client = client.clone();
+
+    let matcher = RecencyMatcher {
+        latest_event_timestamp: move |room_list_entry| {
+            room_for_entry(&client, room_list_entry)?
+                .latest_event()?
+                .event
+                .get_field("origin_server_ts")
+                .ok()
+                .flatten()
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.cmp(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::{room_id, uint, MilliSecondsSinceUnixEpoch, RoomId};
+
+    use super::RecencyMatcher;
+
+    fn entry(room_id: &RoomId) -> RoomListEntry {
+        RoomListEntry::Filled(room_id.to_owned())
+    }
+
+    #[test]
+    fn test_most_recent_first() {
+        let matcher = RecencyMatcher {
+            latest_event_timestamp: |room_list_entry| match room_list_entry.as_room_id()?.as_str() {
+                "!r0:bar.org" => Some(MilliSecondsSinceUnixEpoch(uint!(42))),
+                "!r1:bar.org" => Some(MilliSecondsSinceUnixEpoch(uint!(43))),
+                _ => None,
+            },
+        };
+
+        let r0 = entry(room_id!("!r0:bar.org"));
+        let r1 = entry(room_id!("!r1:bar.org"));
+        let r2 = entry(room_id!("!r2:bar.org"));
+
+        assert_eq!(matcher.cmp(&r0, &r1), Ordering::Greater);
+        assert_eq!(matcher.cmp(&r1, &r0), Ordering::Less);
+        assert_eq!(matcher.cmp(&r0, &r0), Ordering::Equal);
+
+        // Rooms without a latest event come last.
+        assert_eq!(matcher.cmp(&r0, &r2), Ordering::Less);
+        assert_eq!(matcher.cmp(&r2, &r1), Ordering::Greater);
+        assert_eq!(matcher.cmp(&r2, &RoomListEntry::Empty), Ordering::Equal);
+    }
+}
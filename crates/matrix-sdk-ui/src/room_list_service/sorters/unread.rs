@@ -0,0 +1,97 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use matrix_sdk::{Client, RoomListEntry};
+
+use super::Sorter;
+use crate::room_list_service::filters::room_for_entry;
+
+struct UnreadMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<u64>,
+{
+    number_of_unread_notifications: F,
+}
+
+impl<F> UnreadMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<u64>,
+{
+    fn cmp(&self, left: &RoomListEntry, right: &RoomListEntry) -> Ordering {
+        let is_unread = |room_list_entry: &RoomListEntry| {
+            (self.number_of_unread_notifications)(room_list_entry).is_some_and(|count| count > 0)
+        };
+
+        // Unread rooms come first.
+        is_unread(right).cmp(&is_unread(left))
+    }
+}
+
+/// Create a new sorter that will put the rooms with unread notifications
+/// first.
+///
+/// The rooms are not sorted further: chain this sorter with other sorters with
+/// [`new_sorter_lexicographic`](super::new_sorter_lexicographic), e.g. to sort
+/// the unread rooms by recency.
+pub fn new_sorter(client: &Client) -> impl Sorter {
+    let client = client.clone();
+
+    let matcher = UnreadMatcher {
+        number_of_unread_notifications: move |room_list_entry| {
+            Some(
+                room_for_entry(&client, room_list_entry)?
+                    .unread_notification_counts()
+                    .notification_count,
+            )
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.cmp(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::UnreadMatcher;
+
+    #[test]
+    fn test_unread_first() {
+        let matcher = UnreadMatcher {
+            number_of_unread_notifications: |room_list_entry| match room_list_entry
+                .as_room_id()?
+                .as_str()
+            {
+                "!r0:bar.org" => Some(0),
+                "!r1:bar.org" => Some(3),
+                "!r2:bar.org" => Some(1),
+                _ => None,
+            },
+        };
+
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+        let r2 = RoomListEntry::Filled(room_id!("!r2:bar.org").to_owned());
+
+        assert_eq!(matcher.cmp(&r0, &r1), Ordering::Greater);
+        assert_eq!(matcher.cmp(&r1, &r0), Ordering::Less);
+        assert_eq!(matcher.cmp(&r1, &r2), Ordering::Equal);
+        assert_eq!(matcher.cmp(&r0, &RoomListEntry::Empty), Ordering::Equal);
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Mutex as StdMutex;
+
+use eyeball_im::{ObservableVector, VectorDiff};
+use futures_core::Stream;
+use imbl::Vector;
+use matrix_sdk::Client;
+use ruma::{api::client::space::get_hierarchy, OwnedRoomId, RoomId, UInt};
+use tokio::sync::Mutex;
+use tracing::{debug, instrument};
+
+use super::{Error, SpaceRoom};
+
+/// The rooms of a space hierarchy, fetched from the server page by page.
+///
+/// The rooms are listed in depth-first order, as returned by the
+/// [`/hierarchy`] endpoint. The space itself is not part of the list.
+///
+/// [`/hierarchy`]: https://spec.matrix.org/v1.8/client-server-api/#get_matrixclientv1roomsroomidhierarchy
+#[derive(Debug)]
+pub struct SpaceHierarchy {
+    client: Client,
+    space_id: OwnedRoomId,
+    max_depth: Option<UInt>,
+    pagination: Mutex<PaginationState>,
+    rooms: StdMutex<ObservableVector<SpaceRoom>>,
+}
+
+#[derive(Debug, Default)]
+struct PaginationState {
+    /// The token to fetch the next page, if a page has been fetched already.
+    next_batch: Option<String>,
+    /// Whether all the pages have been fetched.
+    is_at_end: bool,
+}
+
+impl SpaceHierarchy {
+    pub(super) fn new(client: Client, space_id: OwnedRoomId, max_depth: Option<UInt>) -> Self {
+        Self {
+            client,
+            space_id,
+            max_depth,
+            pagination: Default::default(),
+            rooms: StdMutex::new(ObservableVector::new()),
+        }
+    }
+
+    /// The ID of the space at the root of this hierarchy.
+    pub fn space_id(&self) -> &RoomId {
+        &self.space_id
+    }
+
+    /// Fetch the next page of the hierarchy from the server.
+    ///
+    /// The new rooms are appended to the list. Does nothing if all the pages
+    /// have been fetched already.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of rooms to fetch. The server uses its
+    ///   own default if it is `None`.
+    #[instrument(skip(self), fields(space_id = ?self.space_id))]
+    pub async fn paginate(&self, limit: Option<UInt>) -> Result<(), Error> {
+        let mut pagination = self.pagination.lock().await;
+
+        if pagination.is_at_end {
+            debug!("The end of the hierarchy has been reached already");
+            return Ok(());
+        }
+
+        let mut request = get_hierarchy::v1::Request::new(self.space_id.clone());
+        request.from = pagination.next_batch.clone();
+        request.limit = limit;
+        request.max_depth = self.max_depth;
+
+        let response = self.client.send(request, None).await?;
+
+        pagination.is_at_end = response.next_batch.is_none();
+        pagination.next_batch = response.next_batch;
+
+        let mut rooms = self.rooms.lock().unwrap();
+        let mut new_rooms = Vector::new();
+
+        for chunk in response.rooms {
+            // The space itself is returned in the first page, and a room can
+            // be a child of several spaces of the hierarchy.
+            if chunk.room_id == self.space_id
+                || rooms.iter().chain(&new_rooms).any(|room| room.room_id == chunk.room_id)
+            {
+                continue;
+            }
+
+            new_rooms.push_back(SpaceRoom::from_hierarchy_chunk(&self.client, chunk));
+        }
+
+        if !new_rooms.is_empty() {
+            rooms.append(new_rooms);
+        }
+
+        debug!(num_rooms = rooms.len(), is_at_end = pagination.is_at_end, "Fetched a page");
+
+        Ok(())
+    }
+
+    /// Whether all the pages of the hierarchy have been fetched.
+    pub async fn is_at_end(&self) -> bool {
+        self.pagination.lock().await.is_at_end
+    }
+
+    /// Get the rooms fetched so far.
+    pub fn rooms(&self) -> Vector<SpaceRoom> {
+        self.rooms.lock().unwrap().clone()
+    }
+
+    /// Get the rooms fetched so far, and a stream of the updates of the list.
+    pub fn subscribe(&self) -> (Vector<SpaceRoom>, impl Stream<Item = VectorDiff<SpaceRoom>>) {
+        let rooms = self.rooms.lock().unwrap();
+        (rooms.clone(), rooms.subscribe())
+    }
+}
@@ -0,0 +1,488 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! High-level API for [spaces].
+//!
+//! The [`SpaceService`] keeps track of the spaces the user has joined, and of
+//! their children, from the local state of the rooms. It is updated as the
+//! `m.space.child` and `m.space.parent` state events, and the memberships of
+//! the user, are received from the sync.
+//!
+//! The rooms of a space that the user hasn't joined yet can be explored with
+//! a [`SpaceHierarchy`], which paginates the `/hierarchy` endpoint.
+//!
+//! [spaces]: https://spec.matrix.org/v1.8/client-server-api/#spaces
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, RwLock as StdRwLock},
+};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk::{
+    event_handler::EventHandlerHandle,
+    room::{Joined, SpaceChild, SpaceParent},
+    Client, HttpError,
+};
+use ruma::{
+    events::{
+        room::member::SyncRoomMemberEvent,
+        space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
+        StateEventType, SyncStateEvent,
+    },
+    serde::Raw,
+    OwnedRoomId, OwnedServerName, RoomId, UInt,
+};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::{debug, error, instrument, warn};
+
+mod hierarchy;
+mod room;
+
+pub use self::{hierarchy::SpaceHierarchy, room::SpaceRoom};
+
+/// A service to interact with the spaces of the user.
+///
+/// The event handlers it registers on the client to keep its state up to date
+/// are removed when it is dropped.
+#[derive(Debug)]
+pub struct SpaceService {
+    client: Client,
+    state: Arc<SpaceState>,
+    event_handler_handles: Vec<EventHandlerHandle>,
+}
+
+impl SpaceService {
+    /// Create a new `SpaceService`, and compute the initial state from the
+    /// rooms known by the client.
+    pub async fn new(client: Client) -> Result<Self, Error> {
+        let state = Arc::new(SpaceState::default());
+        state.refresh(&client).await?;
+
+        // The space events are not deserialized, because removed children and
+        // parents might have an empty content.
+        let event_handler_handles = vec![
+            client.add_event_handler({
+                let state = state.clone();
+                move |_: Raw<SyncStateEvent<SpaceChildEventContent>>, client: Client| {
+                    let state = state.clone();
+                    async move { state.refresh_or_log(&client).await }
+                }
+            }),
+            client.add_event_handler({
+                let state = state.clone();
+                move |_: Raw<SyncStateEvent<SpaceParentEventContent>>, client: Client| {
+                    let state = state.clone();
+                    async move { state.refresh_or_log(&client).await }
+                }
+            }),
+            client.add_event_handler({
+                let state = state.clone();
+                move |event: SyncRoomMemberEvent, client: Client| {
+                    let state = state.clone();
+                    async move {
+                        // Only the memberships of the current user change the
+                        // spaces they have joined.
+                        if client.user_id() == Some(event.state_key()) {
+                            state.refresh_or_log(&client).await;
+                        }
+                    }
+                }
+            }),
+        ];
+
+        Ok(Self { client, state, event_handler_handles })
+    }
+
+    /// Get the spaces joined by the user that are not the child of another
+    /// joined space.
+    ///
+    /// The spaces are sorted by room ID.
+    pub fn top_level_joined_spaces(&self) -> Vec<SpaceRoom> {
+        self.state.top_level_joined_spaces.get()
+    }
+
+    /// Get the top-level joined spaces, and a stream of their updates.
+    ///
+    /// See [`SpaceService::top_level_joined_spaces()`].
+    pub fn subscribe_to_top_level_joined_spaces(&self) -> Subscriber<Vec<SpaceRoom>> {
+        self.state.top_level_joined_spaces.subscribe()
+    }
+
+    /// Get the children of the given joined space, as known locally.
+    ///
+    /// Returns an empty list if the space is unknown, or if it is not a joined
+    /// space.
+    pub fn joined_space_children(&self, space_id: &RoomId) -> Vec<SpaceChild> {
+        self.state.children.read().unwrap().get(space_id).cloned().unwrap_or_default()
+    }
+
+    /// Get the IDs of all the rooms in the given joined space, including the
+    /// rooms of its subspaces, as known locally.
+    pub fn joined_space_descendants(&self, space_id: &RoomId) -> BTreeSet<OwnedRoomId> {
+        self.state.descendants.read().unwrap().get(space_id).cloned().unwrap_or_default()
+    }
+
+    /// Create a [`SpaceHierarchy`] to explore the rooms of the given space
+    /// from the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `space_id` - The ID of the space at the root of the hierarchy.
+    ///
+    /// * `max_depth` - The maximum depth of the hierarchy to explore. The
+    ///   server uses its own default if it is `None`.
+    pub fn space_hierarchy(&self, space_id: &RoomId, max_depth: Option<UInt>) -> SpaceHierarchy {
+        SpaceHierarchy::new(self.client.clone(), space_id.to_owned(), max_depth)
+    }
+
+    /// Create a room list filter that only keeps the rooms of the given
+    /// joined space, including the rooms of its subspaces.
+    ///
+    /// The filter uses the up-to-date state of the space every time it is
+    /// called. The rooms of the space are only computed again when the state
+    /// of the spaces changes.
+    #[cfg(feature = "experimental-room-list")]
+    pub fn new_room_list_filter(
+        &self,
+        space_id: &RoomId,
+    ) -> impl crate::room_list_service::filters::Filter + Send + Sync {
+        let state = self.state.clone();
+        let space_id = space_id.to_owned();
+
+        move |room_list_entry: &matrix_sdk::RoomListEntry| {
+            room_list_entry.as_room_id().is_some_and(|room_id| {
+                state
+                    .descendants
+                    .read()
+                    .unwrap()
+                    .get(&space_id)
+                    .is_some_and(|descendants| descendants.contains(room_id))
+            })
+        }
+    }
+
+    /// Add a room to a space.
+    ///
+    /// The `via` servers of the child are computed from the state of the child
+    /// room if it is known, and fall back to the server of the user.
+    ///
+    /// If the user is allowed to do so, the space is also declared as a parent
+    /// of the child room. Failing to do so is not considered an error, because
+    /// it is optional.
+    #[instrument(skip(self))]
+    pub async fn add_child_to_space(
+        &self,
+        child_id: &RoomId,
+        space_id: &RoomId,
+    ) -> Result<(), Error> {
+        let space = self.joined_room(space_id)?;
+        let child = self.client.get_joined_room(child_id);
+
+        let via = match &child {
+            Some(child) => child.route().await?,
+            None => Vec::new(),
+        };
+        let via = if via.is_empty() { self.own_server()? } else { via };
+
+        space.add_space_child(child_id, via, false).await?;
+
+        if let Some(child) = child {
+            if self.can_send_state(&child, StateEventType::SpaceParent).await {
+                let via = space.route().await?;
+                let via = if via.is_empty() { self.own_server()? } else { via };
+
+                if let Err(error) = child.add_space_parent(space_id, via, false).await {
+                    warn!("Failed to declare the space as a parent of the room: {error}");
+                }
+            } else {
+                debug!("Not allowed to declare the space as a parent of the room");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove a room from a space.
+    ///
+    /// If the child room declares the space as one of its parents, and the user
+    /// is allowed to do so, this declaration is also removed. Failing to do so
+    /// is not considered an error.
+    #[instrument(skip(self))]
+    pub async fn remove_child_from_space(
+        &self,
+        child_id: &RoomId,
+        space_id: &RoomId,
+    ) -> Result<(), Error> {
+        let space = self.joined_room(space_id)?;
+
+        space.remove_space_child(child_id).await?;
+
+        if let Some(child) = self.client.get_joined_room(child_id) {
+            let has_parent =
+                child.space_parents().await?.iter().any(|parent| &*parent.room_id == space_id);
+
+            if has_parent && self.can_send_state(&child, StateEventType::SpaceParent).await {
+                if let Err(error) = child.remove_space_parent(space_id).await {
+                    warn!("Failed to remove the space from the parents of the room: {error}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn joined_room(&self, room_id: &RoomId) -> Result<Joined, Error> {
+        self.client.get_joined_room(room_id).ok_or_else(|| Error::RoomNotJoined(room_id.to_owned()))
+    }
+
+    fn own_server(&self) -> Result<Vec<OwnedServerName>, Error> {
+        let user_id = self.client.user_id().ok_or(Error::NotLoggedIn)?;
+        Ok(vec![user_id.server_name().to_owned()])
+    }
+
+    async fn can_send_state(&self, room: &Joined, event_type: StateEventType) -> bool {
+        let Some(user_id) = self.client.user_id() else { return false };
+
+        match room.get_member_no_sync(user_id).await {
+            Ok(Some(member)) => member.can_send_state(event_type),
+            Ok(None) => false,
+            Err(error) => {
+                warn!("Failed to load the current user's membership: {error}");
+                false
+            }
+        }
+    }
+}
+
+impl Drop for SpaceService {
+    fn drop(&mut self) {
+        for handle in self.event_handler_handles.drain(..) {
+            self.client.remove_event_handler(handle);
+        }
+    }
+}
+
+/// The state of the joined spaces, shared with the event handlers.
+#[derive(Debug, Default)]
+struct SpaceState {
+    /// Lock to make sure that only one refresh happens at a time.
+    refresh_lock: Mutex<()>,
+
+    /// Joined space ID => Children of the space.
+    children: StdRwLock<BTreeMap<OwnedRoomId, Vec<SpaceChild>>>,
+
+    /// Joined space ID => IDs of all the rooms in the space, including the
+    /// rooms of its subspaces.
+    descendants: StdRwLock<BTreeMap<OwnedRoomId, BTreeSet<OwnedRoomId>>>,
+
+    top_level_joined_spaces: SharedObservable<Vec<SpaceRoom>>,
+}
+
+impl SpaceState {
+    /// Recompute the state from the rooms known by the client.
+    async fn refresh(&self, client: &Client) -> Result<(), Error> {
+        let _refresh_guard = self.refresh_lock.lock().await;
+
+        let joined_spaces: Vec<_> =
+            client.joined_rooms().into_iter().filter(|room| room.is_space()).collect();
+
+        let mut children = BTreeMap::new();
+        let mut parents = BTreeMap::new();
+
+        for space in &joined_spaces {
+            children.insert(space.room_id().to_owned(), space.space_children().await?);
+            parents.insert(space.room_id().to_owned(), space.space_parents().await?);
+        }
+
+        let top_level_space_ids = top_level_space_ids(&children, &parents);
+        let mut top_level_joined_spaces: Vec<_> = joined_spaces
+            .iter()
+            .filter(|space| top_level_space_ids.contains(space.room_id()))
+            .map(|space| SpaceRoom::from_room(space, children[space.room_id()].len()))
+            .collect();
+        top_level_joined_spaces.sort_by(|a, b| a.room_id.cmp(&b.room_id));
+
+        let descendants = children
+            .keys()
+            .map(|space_id| (space_id.clone(), descendants(&children, space_id)))
+            .collect();
+
+        *self.children.write().unwrap() = children;
+        *self.descendants.write().unwrap() = descendants;
+        self.top_level_joined_spaces.set_if_not_eq(top_level_joined_spaces);
+
+        Ok(())
+    }
+
+    async fn refresh_or_log(&self, client: &Client) {
+        if let Err(error) = self.refresh(client).await {
+            error!("Failed to refresh the state of the spaces: {error}");
+        }
+    }
+}
+
+/// Compute the IDs of the joined spaces that are not the child of another
+/// joined space.
+///
+/// A space is the child of another one if the other space lists it in its
+/// children, or if it declares the other space as one of its parents.
+fn top_level_space_ids(
+    children: &BTreeMap<OwnedRoomId, Vec<SpaceChild>>,
+    parents: &BTreeMap<OwnedRoomId, Vec<SpaceParent>>,
+) -> BTreeSet<OwnedRoomId> {
+    children
+        .keys()
+        .filter(|space_id| {
+            let is_listed_as_child = children.iter().any(|(other_id, other_children)| {
+                other_id != *space_id
+                    && other_children.iter().any(|child| &child.room_id == *space_id)
+            });
+
+            let has_joined_parent = parents.get(*space_id).is_some_and(|space_parents| {
+                space_parents.iter().any(|parent| {
+                    &parent.room_id != *space_id && children.contains_key(&parent.room_id)
+                })
+            });
+
+            !is_listed_as_child && !has_joined_parent
+        })
+        .cloned()
+        .collect()
+}
+
+/// Compute the IDs of all the rooms in the given space, including the rooms
+/// of its subspaces.
+fn descendants(
+    children: &BTreeMap<OwnedRoomId, Vec<SpaceChild>>,
+    space_id: &RoomId,
+) -> BTreeSet<OwnedRoomId> {
+    let mut descendants = BTreeSet::new();
+    let mut spaces_to_visit = vec![space_id.to_owned()];
+
+    while let Some(space_id) = spaces_to_visit.pop() {
+        for child in children.get(&space_id).into_iter().flatten() {
+            // Spaces can form cycles, only visit each room once.
+            if child.room_id != space_id && descendants.insert(child.room_id.clone()) {
+                spaces_to_visit.push(child.room_id.clone());
+            }
+        }
+    }
+
+    descendants
+}
+
+/// An error for the [`SpaceService`].
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The client is not logged in.
+    #[error("the client is not logged in")]
+    NotLoggedIn,
+
+    /// The room is unknown, or the user has not joined it.
+    #[error("the room {0} is not joined")]
+    RoomNotJoined(OwnedRoomId),
+
+    /// An error from the server.
+    #[error(transparent)]
+    Http(#[from] HttpError),
+
+    /// An error forwarded from the client.
+    #[error(transparent)]
+    SdkError(#[from] matrix_sdk::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use matrix_sdk::room::{SpaceChild, SpaceParent};
+    use ruma::{owned_room_id, server_name, OwnedRoomId};
+
+    use super::{descendants, top_level_space_ids};
+
+    fn child(room_id: OwnedRoomId) -> SpaceChild {
+        SpaceChild {
+            room_id,
+            via: vec![server_name!("example.org").to_owned()],
+            order: None,
+            suggested: false,
+        }
+    }
+
+    fn parent(room_id: OwnedRoomId) -> SpaceParent {
+        SpaceParent { room_id, via: vec![server_name!("example.org").to_owned()], canonical: true }
+    }
+
+    #[test]
+    fn test_top_level_space_ids() {
+        let children = BTreeMap::from([
+            (
+                owned_room_id!("!a:example.org"),
+                vec![
+                    child(owned_room_id!("!b:example.org")),
+                    child(owned_room_id!("!r:example.org")),
+                ],
+            ),
+            (owned_room_id!("!b:example.org"), vec![]),
+            (owned_room_id!("!c:example.org"), vec![]),
+            (owned_room_id!("!d:example.org"), vec![]),
+        ]);
+        let parents = BTreeMap::from([
+            // A joined parent.
+            (owned_room_id!("!c:example.org"), vec![parent(owned_room_id!("!a:example.org"))]),
+            // A parent that is not joined.
+            (owned_room_id!("!d:example.org"), vec![parent(owned_room_id!("!z:example.org"))]),
+        ]);
+
+        let top_level = top_level_space_ids(&children, &parents);
+        assert_eq!(
+            top_level.into_iter().collect::<Vec<_>>(),
+            [owned_room_id!("!a:example.org"), owned_room_id!("!d:example.org")]
+        );
+    }
+
+    #[test]
+    fn test_descendants() {
+        let children = BTreeMap::from([
+            (
+                owned_room_id!("!a:example.org"),
+                vec![
+                    child(owned_room_id!("!b:example.org")),
+                    child(owned_room_id!("!r1:example.org")),
+                ],
+            ),
+            (
+                owned_room_id!("!b:example.org"),
+                vec![
+                    child(owned_room_id!("!r2:example.org")),
+                    // A cycle.
+                    child(owned_room_id!("!a:example.org")),
+                ],
+            ),
+        ]);
+
+        let descendants = descendants(&children, &owned_room_id!("!b:example.org"));
+        assert_eq!(
+            descendants.into_iter().collect::<Vec<_>>(),
+            [
+                owned_room_id!("!a:example.org"),
+                owned_room_id!("!b:example.org"),
+                owned_room_id!("!r1:example.org"),
+                owned_room_id!("!r2:example.org"),
+            ]
+        );
+    }
+}
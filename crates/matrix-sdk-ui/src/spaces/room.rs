@@ -0,0 +1,101 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk::{room::Common, Client, RoomState};
+use ruma::{
+    api::client::space::SpaceHierarchyRoomsChunk,
+    events::room::{guest_access::GuestAccess, history_visibility::HistoryVisibility},
+    room::RoomType,
+    OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId,
+};
+
+/// A room or a space, as seen from the spaces API.
+///
+/// It is built either from the local state of a room known by the client, or
+/// from a chunk of a `/hierarchy` response for rooms the user might not have
+/// joined yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpaceRoom {
+    /// The ID of the room.
+    pub room_id: OwnedRoomId,
+
+    /// The canonical alias of the room, if any.
+    pub canonical_alias: Option<OwnedRoomAliasId>,
+
+    /// The name of the room, if any.
+    pub name: Option<String>,
+
+    /// The topic of the room, if any.
+    pub topic: Option<String>,
+
+    /// The URL of the avatar of the room, if any.
+    pub avatar_url: Option<OwnedMxcUri>,
+
+    /// Whether the room is a space.
+    pub is_space: bool,
+
+    /// The number of members joined to the room.
+    pub num_joined_members: u64,
+
+    /// Whether the room can be previewed by users that are not members.
+    pub world_readable: bool,
+
+    /// Whether guest users may join the room.
+    pub guest_can_join: bool,
+
+    /// The number of children of the room, if it is a space.
+    pub children_count: u64,
+
+    /// The state of the current user in the room, if the room is known by the
+    /// client.
+    pub state: Option<RoomState>,
+}
+
+impl SpaceRoom {
+    /// Create a `SpaceRoom` from the local state of a room.
+    pub(super) fn from_room(room: &Common, children_count: usize) -> Self {
+        Self {
+            room_id: room.room_id().to_owned(),
+            canonical_alias: room.canonical_alias(),
+            name: room.name(),
+            topic: room.topic(),
+            avatar_url: room.avatar_url(),
+            is_space: room.is_space(),
+            num_joined_members: room.joined_members_count(),
+            world_readable: room.history_visibility() == HistoryVisibility::WorldReadable,
+            guest_can_join: room.guest_access() == GuestAccess::CanJoin,
+            children_count: children_count as u64,
+            state: Some(room.state()),
+        }
+    }
+
+    /// Create a `SpaceRoom` from a chunk of a `/hierarchy` response.
+    pub(super) fn from_hierarchy_chunk(client: &Client, chunk: SpaceHierarchyRoomsChunk) -> Self {
+        let state = client.get_room(&chunk.room_id).map(|room| room.state());
+
+        Self {
+            canonical_alias: chunk.canonical_alias,
+            name: chunk.name,
+            topic: chunk.topic,
+            avatar_url: chunk.avatar_url,
+            is_space: chunk.room_type == Some(RoomType::Space),
+            num_joined_members: chunk.num_joined_members.into(),
+            world_readable: chunk.world_readable,
+            guest_can_join: chunk.guest_can_join,
+            children_count: chunk.children_state.len() as u64,
+            state,
+            room_id: chunk.room_id,
+        }
+    }
+}
@@ -18,22 +18,24 @@ use async_std::sync::Mutex;
 use eyeball::SharedObservable;
 use imbl::Vector;
 use matrix_sdk::{
-    deserialized_responses::SyncTimelineEvent, executor::spawn, room, sync::RoomUpdate,
+    deserialized_responses::SyncTimelineEvent, event_cache::BackPaginationCursor, executor::spawn,
+    room, sync::RoomUpdate,
 };
-use ruma::events::{
-    receipt::{ReceiptThread, ReceiptType},
-    AnySyncTimelineEvent,
+use ruma::{
+    events::{receipt::ReceiptType, AnySyncTimelineEvent},
+    OwnedEventId,
 };
-use tokio::sync::{broadcast, mpsc};
-use tracing::{error, info, warn};
+use tokio::sync::broadcast;
+use tracing::{error, warn};
 
 #[cfg(feature = "e2e-encryption")]
-use super::to_device::{handle_forwarded_room_key_event, handle_room_key_event};
+use super::room_keys::handle_room_keys_received;
 use super::{
-    inner::{TimelineInner, TimelineInnerSettings},
-    queue::send_queued_messages,
+    inner::{TimelineFocus, TimelineInner, TimelineInnerSettings},
+    queue::{add_local_echoes, handle_send_queue_updates},
     BackPaginationStatus, Timeline, TimelineDropHandle,
 };
+use crate::unable_to_decrypt_hook::UtdHookManager;
 
 /// Builder that allows creating and configuring various parts of a
 /// [`Timeline`].
@@ -107,6 +109,28 @@ impl TimelineBuilder {
         self
     }
 
+    /// Focus the timeline on the thread with the given root.
+    ///
+    /// The timeline only contains the thread root and the replies in the
+    /// thread, and its read receipts are the ones in the thread. Paginating
+    /// backwards loads the older replies, then the thread root.
+    ///
+    /// By default, the timeline shows the live timeline of the room.
+    pub fn focus_on_thread(mut self, root_event_id: OwnedEventId) -> Self {
+        self.settings.focus = TimelineFocus::Thread { root_event_id };
+        self
+    }
+
+    /// Report the events that couldn't be decrypted, and their late
+    /// decryptions, to the given hook.
+    ///
+    /// The same [`UtdHookManager`] should be shared by all the timelines of a
+    /// client, so every event is reported once.
+    pub fn with_unable_to_decrypt_hook(mut self, hook: Arc<UtdHookManager>) -> Self {
+        self.settings.unable_to_decrypt_hook = Some(hook);
+        self
+    }
+
     /// Create a [`Timeline`] with the options set on this builder.
     #[tracing::instrument(
         skip(self),
@@ -118,20 +142,39 @@ impl TimelineBuilder {
         )
     )]
     pub async fn build(self) -> Timeline {
-        let Self { room, prev_token, events, settings } = self;
+        let Self { room, prev_token, mut events, settings } = self;
+
+        // Subscribe before loading the events from the event cache, to not miss
+        // any update in between.
+        let mut room_update_rx = room.subscribe_to_updates();
+
+        let is_thread = settings.focus.thread_root().is_some();
+
+        // The events of a thread are only loaded with pagination.
+        let mut event_cache_cursor = None;
+        if events.is_empty() && !is_thread {
+            match room.event_cache().latest_events().await {
+                Ok(Some((cached_events, cursor))) => {
+                    events = cached_events.into_iter().collect();
+                    event_cache_cursor = Some(cursor);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Failed to load the events from the event cache: {e}");
+                }
+            }
+        }
+
         let has_events = !events.is_empty();
         let track_read_marker_and_receipts = settings.track_read_receipts;
+        let receipt_thread = settings.focus.receipt_thread();
 
         let mut inner = TimelineInner::new(room).with_settings(settings);
 
         if track_read_marker_and_receipts {
             match inner
                 .room()
-                .user_receipt(
-                    ReceiptType::Read,
-                    ReceiptThread::Unthreaded,
-                    inner.room().own_user_id(),
-                )
+                .user_receipt(ReceiptType::Read, receipt_thread.clone(), inner.room().own_user_id())
                 .await
             {
                 Ok(Some(read_receipt)) => {
@@ -144,11 +187,7 @@ impl TimelineBuilder {
             }
             match inner
                 .room()
-                .user_receipt(
-                    ReceiptType::ReadPrivate,
-                    ReceiptThread::Unthreaded,
-                    inner.room().own_user_id(),
-                )
+                .user_receipt(ReceiptType::ReadPrivate, receipt_thread, inner.room().own_user_id())
                 .await
             {
                 Ok(Some(private_read_receipt)) => {
@@ -171,14 +210,24 @@ impl TimelineBuilder {
         }
 
         let room = inner.room();
-        let client = room.client();
+
+        // Subscribe before loading the local echoes, to not miss any update.
+        let send_queue = room.send_queue();
+        let send_queue_updates = send_queue.subscribe();
+        match send_queue.local_echoes().await {
+            Ok(requests) => add_local_echoes(&inner, requests).await,
+            Err(e) => error!("Failed to load the local echoes from the send queue: {e}"),
+        }
 
         let start_token = Arc::new(Mutex::new(prev_token));
+        let event_cache_cursor = Arc::new(Mutex::new(event_cache_cursor));
+        let back_pagination_status = SharedObservable::new(BackPaginationStatus::Idle);
 
-        let mut room_update_rx = room.subscribe_to_updates();
         let room_update_join_handle = spawn({
             let inner = inner.clone();
             let start_token = start_token.clone();
+            let event_cache_cursor = event_cache_cursor.clone();
+            let back_pagination_status = back_pagination_status.clone();
             async move {
                 loop {
                     let update = match room_update_rx.recv().await {
@@ -187,11 +236,18 @@ impl TimelineBuilder {
                         Err(broadcast::error::RecvError::Lagged(_)) => {
                             warn!("Lagged behind sync responses, resetting timeline");
                             inner.clear().await;
+                            *event_cache_cursor.lock().await = None;
                             continue;
                         }
                     };
 
                     let update_start_token = |prev_batch: &Option<_>| {
+                        // The events of a thread are paginated with the
+                        // relations API, which doesn't use the same tokens.
+                        if is_thread {
+                            return;
+                        }
+
                         // Only update start_token if it's not currently locked.
                         // If it is locked, pagination is currently in progress.
                         if let Some(mut start_token) = start_token.try_lock() {
@@ -204,54 +260,61 @@ impl TimelineBuilder {
                     match update {
                         RoomUpdate::Left { updates, .. } => {
                             update_start_token(&updates.timeline.prev_batch);
+                            if updates.timeline.limited {
+                                reset_event_cache_cursor(inner.room(), &event_cache_cursor).await;
+                                if is_thread {
+                                    reset_thread_pagination(&start_token, &back_pagination_status)
+                                        .await;
+                                }
+                            }
                             inner.handle_sync_timeline(updates.timeline).await;
                         }
                         RoomUpdate::Joined { updates, .. } => {
                             update_start_token(&updates.timeline.prev_batch);
+                            if updates.timeline.limited {
+                                reset_event_cache_cursor(inner.room(), &event_cache_cursor).await;
+                                if is_thread {
+                                    reset_thread_pagination(&start_token, &back_pagination_status)
+                                        .await;
+                                }
+                            }
                             inner.handle_joined_room_update(updates).await;
                         }
                         RoomUpdate::Invited { .. } => {
                             warn!("Room is in invited state, can't build or update its timeline");
                         }
+                        RoomUpdate::Knocked { .. } => {
+                            warn!("Room is in knocked state, can't build or update its timeline");
+                        }
                     }
                 }
             }
         });
 
-        // Not using room.add_event_handler here because RoomKey events are
-        // to-device events that are not received in the context of a room.
-
+        // Subscribe before retrying the decryption of the initial events, so
+        // room keys received in the meantime aren't missed.
         #[cfg(feature = "e2e-encryption")]
-        let room_key_handle = client
-            .add_event_handler(handle_room_key_event(inner.clone(), room.room_id().to_owned()));
-        #[cfg(feature = "e2e-encryption")]
-        let forwarded_room_key_handle = client.add_event_handler(handle_forwarded_room_key_event(
+        let room_keys_join_handle = spawn(handle_room_keys_received(
             inner.clone(),
-            room.room_id().to_owned(),
+            room.client().encryption().room_keys_received_stream(),
         ));
 
-        let handles = vec![
-            #[cfg(feature = "e2e-encryption")]
-            room_key_handle,
-            #[cfg(feature = "e2e-encryption")]
-            forwarded_room_key_handle,
-        ];
-
-        let (msg_sender, msg_receiver) = mpsc::channel(1);
-        info!("Starting message-sending loop");
-        spawn(send_queued_messages(inner.clone(), room.clone(), msg_receiver));
+        let send_queue_join_handle =
+            spawn(handle_send_queue_updates(inner.clone(), send_queue.clone(), send_queue_updates));
 
         let timeline = Timeline {
             inner,
             start_token,
             start_token_condvar: Default::default(),
-            back_pagination_status: SharedObservable::new(BackPaginationStatus::Idle),
+            event_cache_cursor,
+            back_pagination_status,
             _end_token: Mutex::new(None),
-            msg_sender,
+            send_queue,
             drop_handle: Arc::new(TimelineDropHandle {
-                client,
-                event_handler_handles: handles,
                 room_update_join_handle,
+                send_queue_join_handle,
+                #[cfg(feature = "e2e-encryption")]
+                room_keys_join_handle,
             }),
         };
 
@@ -259,7 +322,7 @@ impl TimelineBuilder {
         if has_events {
             // The events we're injecting might be encrypted events, but we might
             // have received the room key to decrypt them while nobody was listening to the
-            // `m.room_key` event, let's retry now.
+            // received room keys, let's retry now.
             //
             // TODO: We could spawn a task here and put this into the background, though it
             // might not be worth it depending on the number of events we injected.
@@ -270,3 +333,34 @@ impl TimelineBuilder {
         timeline
     }
 }
+
+/// Move the position of a timeline in the event cache after the latest gap,
+/// once the timeline was reset by a limited sync.
+async fn reset_event_cache_cursor(
+    room: &room::Common,
+    event_cache_cursor: &Mutex<Option<BackPaginationCursor>>,
+) {
+    let mut cursor = event_cache_cursor.lock().await;
+    if cursor.is_none() {
+        // The timeline isn't loaded from the event cache.
+        return;
+    }
+
+    *cursor = match room.event_cache().latest_events().await {
+        Ok(latest_events) => latest_events.map(|(_, cursor)| cursor),
+        Err(e) => {
+            error!("Failed to load the latest events from the event cache: {e}");
+            None
+        }
+    };
+}
+
+/// Restart the pagination of a thread from its latest reply, once the timeline
+/// was reset by a limited sync.
+async fn reset_thread_pagination(
+    start_token: &Mutex<Option<String>>,
+    back_pagination_status: &SharedObservable<BackPaginationStatus>,
+) {
+    *start_token.lock().await = None;
+    back_pagination_status.set(BackPaginationStatus::Idle);
+}
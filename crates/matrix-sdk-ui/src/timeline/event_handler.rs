@@ -17,14 +17,19 @@ use std::{collections::HashMap, sync::Arc};
 use chrono::{Datelike, Local, TimeZone};
 use eyeball_im::ObservableVector;
 use indexmap::{map::Entry, IndexMap, IndexSet};
-use matrix_sdk::deserialized_responses::EncryptionInfo;
+use matrix_sdk::deserialized_responses::{EncryptionInfo, UnableToDecryptReason};
 use ruma::{
     events::{
+        poll::{
+            unstable_end::UnstablePollEndEventContent,
+            unstable_response::UnstablePollResponseEventContent,
+            unstable_start::UnstablePollStartEventContent,
+        },
         reaction::ReactionEventContent,
         receipt::{Receipt, ReceiptType},
         relation::{Annotation, Replacement},
         room::{
-            encrypted::RoomEncryptedEventContent,
+            encrypted::{self, RoomEncryptedEventContent},
             member::RoomMemberEventContent,
             message::{
                 self, sanitize::RemoveReplyFallback, RoomMessageEventContent,
@@ -51,9 +56,10 @@ use super::{
     },
     find_read_marker,
     item::{new_timeline_item, timeline_item},
+    polls::{PollPendingEvents, PollState, ResponseData},
     read_receipts::maybe_add_implicit_read_receipt,
     rfind_event_by_id, rfind_event_item, EventTimelineItem, Message, OtherState, ReactionGroup,
-    Sticker, TimelineDetails, TimelineInnerState, TimelineItem, TimelineItemContent,
+    Sticker, ThreadSummary, TimelineDetails, TimelineInnerState, TimelineItem, TimelineItemContent,
     VirtualTimelineItem, DEFAULT_SANITIZER_MODE,
 };
 use crate::{events::SyncTimelineEventWithoutContent, timeline::event_item::ReactionSenderData};
@@ -69,6 +75,7 @@ pub(super) enum Flow {
         raw_event: Raw<AnySyncTimelineEvent>,
         position: TimelineItemPosition,
         should_add: bool,
+        unable_to_decrypt_reason: Option<UnableToDecryptReason>,
     },
 }
 
@@ -81,6 +88,7 @@ pub(super) struct TimelineEventMetadata {
     pub(super) encryption_info: Option<EncryptionInfo>,
     pub(super) read_receipts: IndexMap<OwnedUserId, Receipt>,
     pub(super) is_highlighted: bool,
+    pub(super) thread_summary: Option<ThreadSummary>,
 }
 
 #[derive(Clone, Debug)]
@@ -221,6 +229,7 @@ pub(super) struct TimelineEventHandler<'a> {
     track_read_receipts: bool,
     users_read_receipts:
         &'a mut HashMap<OwnedUserId, HashMap<ReceiptType, (OwnedEventId, Receipt)>>,
+    poll_pending_events: &'a mut PollPendingEvents,
     result: HandleEventResult,
 }
 
@@ -257,6 +266,7 @@ impl<'a> TimelineEventHandler<'a> {
             event_should_update_fully_read_marker: &mut state.event_should_update_fully_read_marker,
             track_read_receipts,
             users_read_receipts: &mut state.users_read_receipts,
+            poll_pending_events: &mut state.poll_pending_events,
             result: HandleEventResult::default(),
         }
     }
@@ -301,12 +311,32 @@ impl<'a> TimelineEventHandler<'a> {
                     self.handle_room_message_edit(re);
                 }
                 AnyMessageLikeEventContent::RoomMessage(c) => {
+                    let thread_root = match &c.relates_to {
+                        Some(message::Relation::Thread(thread)) => Some(thread.event_id.clone()),
+                        _ => None,
+                    };
+
                     self.add(should_add, TimelineItemContent::message(c, relations, self.items));
+
+                    if let Some(thread_root) = thread_root {
+                        self.update_thread_summary(&thread_root);
+                    }
+                }
+                AnyMessageLikeEventContent::RoomEncrypted(c) => {
+                    self.handle_room_encrypted(should_add, c);
                 }
-                AnyMessageLikeEventContent::RoomEncrypted(c) => self.handle_room_encrypted(c),
                 AnyMessageLikeEventContent::Sticker(content) => {
                     self.add(should_add, TimelineItemContent::Sticker(Sticker { content }));
                 }
+                AnyMessageLikeEventContent::UnstablePollStart(c) => {
+                    self.handle_poll_start(should_add, c);
+                }
+                AnyMessageLikeEventContent::UnstablePollResponse(c) => {
+                    self.handle_poll_response(c);
+                }
+                AnyMessageLikeEventContent::UnstablePollEnd(c) => {
+                    self.handle_poll_end(c);
+                }
                 // TODO
                 _ => {
                     debug!(
@@ -397,6 +427,10 @@ impl<'a> TimelineEventHandler<'a> {
                     info!("Edit event applies to a sticker, discarding");
                     return None;
                 }
+                TimelineItemContent::Poll(_) => {
+                    info!("Edit event applies to a poll, discarding");
+                    return None;
+                }
                 TimelineItemContent::UnableToDecrypt(_) => {
                     info!("Edit event applies to event that couldn't be decrypted, discarding");
                     return None;
@@ -421,6 +455,7 @@ impl<'a> TimelineEventHandler<'a> {
             let new_content = TimelineItemContent::Message(Message {
                 msgtype,
                 in_reply_to: msg.in_reply_to.clone(),
+                thread_root: msg.thread_root.clone(),
                 edited: true,
             });
 
@@ -517,9 +552,140 @@ impl<'a> TimelineEventHandler<'a> {
     }
 
     #[instrument(skip_all)]
-    fn handle_room_encrypted(&mut self, c: RoomEncryptedEventContent) {
+    fn handle_room_encrypted(&mut self, should_add: bool, c: RoomEncryptedEventContent) {
+        let thread_root = match &c.relates_to {
+            Some(encrypted::Relation::Thread(thread)) => Some(thread.event_id.clone()),
+            _ => None,
+        };
+
+        let reason = match &self.flow {
+            Flow::Remote { unable_to_decrypt_reason, .. } => {
+                unable_to_decrypt_reason.clone().unwrap_or_default()
+            }
+            Flow::Local { .. } => UnableToDecryptReason::Unknown,
+        };
+
         // TODO: Handle replacements if the replaced event is also UTD
-        self.add(true, TimelineItemContent::unable_to_decrypt(c));
+        self.add(should_add, TimelineItemContent::unable_to_decrypt(c, reason));
+
+        if let Some(thread_root) = thread_root {
+            self.update_thread_summary(&thread_root);
+        }
+    }
+
+    #[instrument(skip_all)]
+    fn handle_poll_start(&mut self, should_add: bool, c: UnstablePollStartEventContent) {
+        let mut poll_state = PollState::new(c);
+        if let Flow::Remote { event_id, .. } = &self.flow {
+            // Responses and the end event may have been received before the
+            // start event, e.g. with back-pagination.
+            self.poll_pending_events.apply(event_id, &self.meta.sender, &mut poll_state);
+        }
+
+        self.add(should_add, TimelineItemContent::Poll(poll_state));
+    }
+
+    #[instrument(skip_all, fields(poll_start_event_id = ?c.relates_to.event_id))]
+    fn handle_poll_response(&mut self, c: UnstablePollResponseEventContent) {
+        let start_event_id = c.relates_to.event_id;
+        let response = ResponseData {
+            sender: self.meta.sender.clone(),
+            timestamp: self.meta.timestamp,
+            answers: c.poll_response.answers,
+        };
+
+        let Some((idx, event_item)) = rfind_event_by_id(self.items, &start_event_id) else {
+            trace!("Poll start event not found, adding response to the pending list");
+            self.poll_pending_events.add_response(start_event_id, response);
+            return;
+        };
+
+        let TimelineItemContent::Poll(poll_state) = event_item.content() else {
+            info!("Poll response applies to an event that isn't a poll, discarding");
+            return;
+        };
+
+        trace!("Adding poll response");
+        let mut new_item = event_item.inner.clone();
+        new_item.set_content(TimelineItemContent::Poll(poll_state.add_response(response)));
+        self.items.set(idx, timeline_item(new_item, event_item.internal_id));
+        self.result.items_updated += 1;
+    }
+
+    #[instrument(skip_all, fields(poll_start_event_id = ?c.relates_to.event_id))]
+    fn handle_poll_end(&mut self, c: UnstablePollEndEventContent) {
+        let start_event_id = c.relates_to.event_id;
+
+        let Some((idx, event_item)) = rfind_event_by_id(self.items, &start_event_id) else {
+            trace!("Poll start event not found, adding end event to the pending list");
+            self.poll_pending_events.add_end(
+                start_event_id,
+                self.meta.sender.clone(),
+                self.meta.timestamp,
+            );
+            return;
+        };
+
+        let TimelineItemContent::Poll(poll_state) = event_item.content() else {
+            info!("Poll end event applies to an event that isn't a poll, discarding");
+            return;
+        };
+
+        if self.meta.sender != event_item.sender() {
+            info!("Poll end event wasn't sent by the sender of the poll, discarding");
+            return;
+        }
+
+        let Some(poll_state) = poll_state.end(self.meta.timestamp) else {
+            debug!("Poll was already ended earlier, discarding end event");
+            return;
+        };
+
+        trace!("Ending poll");
+        let mut new_item = event_item.inner.clone();
+        new_item.set_content(TimelineItemContent::Poll(poll_state));
+        self.items.set(idx, timeline_item(new_item, event_item.internal_id));
+        self.result.items_updated += 1;
+    }
+
+    /// Count the current event as a new reply in the summary of the thread
+    /// with the given root, if it is a live event.
+    ///
+    /// Replies that are loaded from the cache or with pagination should
+    /// already be counted in the summary bundled with the thread root.
+    #[instrument(skip_all, fields(thread_root = ?thread_root))]
+    fn update_thread_summary(&mut self, thread_root: &EventId) {
+        let Flow::Remote {
+            event_id,
+            position: TimelineItemPosition::End { from_cache: false },
+            ..
+        } = &self.flow
+        else {
+            return;
+        };
+
+        let event_id = event_id.clone();
+        let is_own_event = self.meta.is_own_event;
+
+        update_timeline_item!(self, thread_root, "thread reply", |event_item| {
+            let Some(remote_event_item) = event_item.as_remote() else {
+                debug!("Thread root is a local echo, can't update its thread summary");
+                return None;
+            };
+
+            let mut thread_summary = remote_event_item.thread_summary.clone().unwrap_or_default();
+            if thread_summary.latest_reply.as_ref() == Some(&event_id) {
+                // This reply was already counted.
+                return None;
+            }
+
+            thread_summary.num_replies += 1;
+            thread_summary.latest_reply = Some(event_id);
+            thread_summary.user_participated |= is_own_event;
+
+            trace!("Updating thread summary");
+            Some(event_item.with_kind(remote_event_item.with_thread_summary(thread_summary)))
+        });
     }
 
     // Redacted redactions are no-ops (unfortunately)
@@ -698,6 +864,7 @@ impl<'a> TimelineEventHandler<'a> {
                     original_json: raw_event.clone(),
                     latest_edit_json: None,
                     origin,
+                    thread_summary: self.meta.thread_summary.clone(),
                 }
                 .into()
             }
@@ -17,7 +17,10 @@ use std::{fmt, ops::Deref, sync::Arc};
 use imbl::{vector, Vector};
 use indexmap::IndexMap;
 use itertools::Itertools;
-use matrix_sdk::{deserialized_responses::TimelineEvent, Result};
+use matrix_sdk::{
+    deserialized_responses::{TimelineEvent, UnableToDecryptReason},
+    Result,
+};
 #[cfg(feature = "experimental-sliding-sync")]
 use matrix_sdk_base::latest_event::{is_suitable_for_latest_event, PossibleLatestEvent};
 #[cfg(feature = "experimental-sliding-sync")]
@@ -29,7 +32,7 @@ use ruma::{
             room::PolicyRuleRoomEventContent, server::PolicyRuleServerEventContent,
             user::PolicyRuleUserEventContent,
         },
-        relation::InReplyTo,
+        relation::{InReplyTo, Thread},
         room::{
             aliases::RoomAliasesEventContent,
             avatar::RoomAvatarEventContent,
@@ -59,7 +62,7 @@ use ruma::{
         AnyTimelineEvent, BundledMessageLikeRelations, FullStateEventContent, MessageLikeEventType,
         StateEventType,
     },
-    OwnedDeviceId, OwnedEventId, OwnedMxcUri, OwnedTransactionId, OwnedUserId, UserId,
+    EventId, OwnedDeviceId, OwnedEventId, OwnedMxcUri, OwnedTransactionId, OwnedUserId, UserId,
 };
 #[cfg(feature = "experimental-sliding-sync")]
 use tracing::warn;
@@ -67,7 +70,8 @@ use tracing::{debug, error};
 
 use super::{EventItemIdentifier, EventTimelineItem, Profile, ReactionSenderData, TimelineDetails};
 use crate::timeline::{
-    traits::RoomDataProvider, Error as TimelineError, TimelineItem, DEFAULT_SANITIZER_MODE,
+    polls::PollState, traits::RoomDataProvider, Error as TimelineError, TimelineItem,
+    DEFAULT_SANITIZER_MODE,
 };
 
 /// The content of an [`EventTimelineItem`][super::EventTimelineItem].
@@ -85,6 +89,9 @@ pub enum TimelineItemContent {
     /// An `m.room.encrypted` event that could not be decrypted.
     UnableToDecrypt(EncryptedMessage),
 
+    /// An `m.poll.start` event, aggregated with its responses and end event.
+    Poll(PollState),
+
     /// A room membership change.
     MembershipChange(RoomMembershipChange),
 
@@ -189,6 +196,15 @@ impl TimelineItemContent {
         }
     }
 
+    /// If `self` is of the [`Poll`][Self::Poll] variant, return the inner
+    /// [`PollState`].
+    pub fn as_poll(&self) -> Option<&PollState> {
+        match self {
+            Self::Poll(v) => Some(v),
+            _ => None,
+        }
+    }
+
     /// If `self` is of the [`UnableToDecrypt`][Self::UnableToDecrypt] variant,
     /// return the inner [`EncryptedMessage`].
     pub fn as_unable_to_decrypt(&self) -> Option<&EncryptedMessage> {
@@ -212,8 +228,11 @@ impl TimelineItemContent {
         Self::Message(Message::from_event(c, relations, timeline_items))
     }
 
-    pub(crate) fn unable_to_decrypt(content: RoomEncryptedEventContent) -> Self {
-        TimelineItemContent::UnableToDecrypt(content.into())
+    pub(crate) fn unable_to_decrypt(
+        content: RoomEncryptedEventContent,
+        reason: UnableToDecryptReason,
+    ) -> Self {
+        TimelineItemContent::UnableToDecrypt(EncryptedMessage::from_content(content, reason))
     }
 
     pub(crate) fn room_member(
@@ -289,6 +308,7 @@ impl TimelineItemContent {
 pub struct Message {
     pub(in crate::timeline) msgtype: MessageType,
     pub(in crate::timeline) in_reply_to: Option<InReplyToDetails>,
+    pub(in crate::timeline) thread_root: Option<OwnedEventId>,
     pub(in crate::timeline) edited: bool,
 }
 
@@ -318,13 +338,17 @@ impl Message {
             }
         });
 
+        let mut thread_root = None;
         let in_reply_to = c.relates_to.and_then(|relation| match relation {
             message::Relation::Reply { in_reply_to } => {
                 Some(InReplyToDetails::new(in_reply_to.event_id, timeline_items))
             }
-            message::Relation::Thread(thread) => thread
-                .in_reply_to
-                .map(|in_reply_to| InReplyToDetails::new(in_reply_to.event_id, timeline_items)),
+            message::Relation::Thread(thread) => {
+                thread_root = Some(thread.event_id);
+                thread
+                    .in_reply_to
+                    .map(|in_reply_to| InReplyToDetails::new(in_reply_to.event_id, timeline_items))
+            }
             _ => None,
         });
 
@@ -347,7 +371,7 @@ impl Message {
             }
         };
 
-        Self { msgtype, in_reply_to, edited }
+        Self { msgtype, in_reply_to, thread_root, edited }
     }
 
     /// Get the `msgtype`-specific data of this message.
@@ -367,6 +391,11 @@ impl Message {
         self.in_reply_to.as_ref()
     }
 
+    /// Get the ID of the root of the thread this message is in, if any.
+    pub fn thread_root(&self) -> Option<&EventId> {
+        self.thread_root.as_deref()
+    }
+
     /// Get the edit state of this message (has been edited: `true` / `false`).
     pub fn is_edited(&self) -> bool {
         self.edited
@@ -379,9 +408,18 @@ impl Message {
 
 impl From<Message> for RoomMessageEventContent {
     fn from(msg: Message) -> Self {
-        let relates_to = msg.in_reply_to.map(|details| message::Relation::Reply {
-            in_reply_to: InReplyTo::new(details.event_id),
-        });
+        let relates_to = match (msg.thread_root, msg.in_reply_to) {
+            (Some(thread_root), Some(details)) => {
+                Some(message::Relation::Thread(Thread::plain(thread_root, details.event_id)))
+            }
+            (Some(thread_root), None) => {
+                Some(message::Relation::Thread(Thread::without_fallback(thread_root)))
+            }
+            (None, Some(details)) => {
+                Some(message::Relation::Reply { in_reply_to: InReplyTo::new(details.event_id) })
+            }
+            (None, None) => None,
+        };
         assign!(Self::new(msg.msgtype), { relates_to })
     }
 }
@@ -389,11 +427,12 @@ impl From<Message> for RoomMessageEventContent {
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for Message {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Self { msgtype: _, in_reply_to, edited } = self;
+        let Self { msgtype: _, in_reply_to, thread_root, edited } = self;
         // since timeline items are logged, don't include all fields here so
         // people don't leak personal data in bug reports
         f.debug_struct("Message")
             .field("in_reply_to", in_reply_to)
+            .field("thread_root", thread_root)
             .field("edited", edited)
             .finish_non_exhaustive()
     }
@@ -518,13 +557,16 @@ pub enum EncryptedMessage {
 
         /// The ID of the session used to encrypt the message.
         session_id: String,
+
+        /// Why the message couldn't be decrypted.
+        reason: UnableToDecryptReason,
     },
     /// No metadata because the event uses an unknown algorithm.
     Unknown,
 }
 
-impl From<RoomEncryptedEventContent> for EncryptedMessage {
-    fn from(c: RoomEncryptedEventContent) -> Self {
+impl EncryptedMessage {
+    fn from_content(c: RoomEncryptedEventContent, reason: UnableToDecryptReason) -> Self {
         match c.scheme {
             EncryptedEventScheme::OlmV1Curve25519AesSha2(s) => {
                 Self::OlmV1Curve25519AesSha2 { sender_key: s.sender_key }
@@ -532,7 +574,7 @@ impl From<RoomEncryptedEventContent> for EncryptedMessage {
             #[allow(deprecated)]
             EncryptedEventScheme::MegolmV1AesSha2(s) => {
                 let MegolmV1AesSha2Content { sender_key, device_id, session_id, .. } = s;
-                Self::MegolmV1AesSha2 { sender_key, device_id, session_id }
+                Self::MegolmV1AesSha2 { sender_key, device_id, session_id, reason }
             }
             _ => Self::Unknown,
         }
@@ -30,6 +30,8 @@ use ruma::{
 #[cfg(feature = "experimental-sliding-sync")]
 use tracing::warn;
 
+use super::ThreadSummary;
+
 mod content;
 mod local;
 mod remote;
@@ -147,6 +149,9 @@ impl EventTimelineItem {
         // Probably the origin of the event doesn't matter for the preview.
         let origin = RemoteEventOrigin::Sync;
 
+        // The message preview doesn't show threads.
+        let thread_summary = None;
+
         let event_kind = RemoteEventTimelineItem {
             event_id,
             reactions,
@@ -157,6 +162,7 @@ impl EventTimelineItem {
             original_json: raw_sync_event,
             latest_edit_json,
             origin,
+            thread_summary,
         }
         .into();
 
@@ -268,7 +274,9 @@ impl EventTimelineItem {
     /// The key is the ID of a room member and the value are details about the
     /// read receipt.
     ///
-    /// Note that currently this ignores threads.
+    /// In a timeline focused on a thread, these are the receipts in that
+    /// thread. Otherwise, these are the unthreaded receipts and the receipts in
+    /// the main timeline.
     pub fn read_receipts(&self) -> &IndexMap<OwnedUserId, Receipt> {
         static EMPTY_RECEIPTS: Lazy<IndexMap<OwnedUserId, Receipt>> = Lazy::new(Default::default);
         match &self.kind {
@@ -277,6 +285,14 @@ impl EventTimelineItem {
         }
     }
 
+    /// Get the summary of the thread of this item, if it is a thread root.
+    pub fn thread_summary(&self) -> Option<&ThreadSummary> {
+        match &self.kind {
+            EventTimelineItemKind::Local(_) => None,
+            EventTimelineItemKind::Remote(remote_event) => remote_event.thread_summary.as_ref(),
+        }
+    }
+
     /// Get the timestamp of this item.
     ///
     /// If this event hasn't been echoed back by the server yet, returns the
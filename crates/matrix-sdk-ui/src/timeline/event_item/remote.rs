@@ -23,6 +23,7 @@ use ruma::{
 };
 
 use super::BundledReactions;
+use crate::timeline::ThreadSummary;
 
 /// An item for an event that was received from the homeserver.
 #[derive(Clone)]
@@ -36,7 +37,7 @@ pub(in crate::timeline) struct RemoteEventTimelineItem {
     /// The key is the ID of a room member and the value are details about the
     /// read receipt.
     ///
-    /// Note that currently this ignores threads.
+    /// Only the receipts for the thread of the timeline are included.
     pub read_receipts: IndexMap<OwnedUserId, Receipt>,
     /// Whether the event has been sent by the the logged-in user themselves.
     pub is_own: bool,
@@ -53,6 +54,8 @@ pub(in crate::timeline) struct RemoteEventTimelineItem {
     pub latest_edit_json: Option<Raw<AnySyncTimelineEvent>>,
     /// Where we got this event from: A sync response or pagination.
     pub origin: RemoteEventOrigin,
+    /// The summary of the thread, if this event is a thread root.
+    pub thread_summary: Option<ThreadSummary>,
 }
 
 impl RemoteEventTimelineItem {
@@ -72,6 +75,11 @@ impl RemoteEventTimelineItem {
         Self { reactions, ..self.clone() }
     }
 
+    /// Clone the current event item, and update its `thread_summary`.
+    pub fn with_thread_summary(&self, thread_summary: ThreadSummary) -> Self {
+        Self { thread_summary: Some(thread_summary), ..self.clone() }
+    }
+
     /// Clone the current event item, and reset its `reactions`.
     pub fn without_reactions(&self) -> Self {
         Self { reactions: BundledReactions::default(), ..self.clone() }
@@ -106,6 +114,7 @@ impl fmt::Debug for RemoteEventTimelineItem {
             latest_edit_json: _,
             is_highlighted,
             origin,
+            thread_summary,
         } = self;
 
         f.debug_struct("RemoteEventTimelineItem")
@@ -116,6 +125,7 @@ impl fmt::Debug for RemoteEventTimelineItem {
             .field("is_highlighted", is_highlighted)
             .field("encryption_info", encryption_info)
             .field("origin", origin)
+            .field("thread_summary", thread_summary)
             .finish_non_exhaustive()
     }
 }
@@ -25,7 +25,7 @@ use itertools::Itertools;
 #[cfg(all(test, feature = "e2e-encryption"))]
 use matrix_sdk::crypto::OlmMachine;
 use matrix_sdk::{
-    deserialized_responses::{SyncTimelineEvent, TimelineEvent},
+    deserialized_responses::SyncTimelineEvent,
     room,
     sync::{JoinedRoom, Timeline},
     Error, Result,
@@ -41,11 +41,15 @@ use ruma::{
         reaction::ReactionEventContent,
         receipt::{Receipt, ReceiptThread, ReceiptType},
         relation::Annotation,
-        room::redaction::RoomRedactionEventContent,
+        room::{
+            message::{Relation, RoomMessageEventContent},
+            redaction::RoomRedactionEventContent,
+        },
         AnyMessageLikeEventContent, AnyRoomAccountDataEvent, AnySyncEphemeralRoomEvent,
-        AnySyncTimelineEvent,
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent,
     },
     push::Action,
+    serde::Raw,
     EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedTransactionId, OwnedUserId,
     TransactionId, UserId,
 };
@@ -64,14 +68,16 @@ use super::{
     },
     event_item::{EventItemIdentifier, ReactionSenderData},
     item::{new_timeline_item, timeline_item},
+    polls::PollPendingEvents,
     reactions::ReactionToggleResult,
     rfind_event_by_id, rfind_event_item,
+    threads::{bundled_thread_summary, thread_root},
     traits::RoomDataProvider,
     AnnotationKey, EventSendState, EventTimelineItem, InReplyToDetails, Message, Profile,
     RelativePosition, RepliedToEvent, TimelineDetails, TimelineItem, TimelineItemContent,
     TimelineItemKind,
 };
-use crate::events::SyncTimelineEventWithoutContent;
+use crate::{events::SyncTimelineEventWithoutContent, unable_to_decrypt_hook::UtdHookManager};
 
 #[derive(Clone, Debug)]
 pub(super) struct TimelineInner<P: RoomDataProvider = room::Common> {
@@ -103,6 +109,10 @@ pub(super) struct TimelineInnerState {
     pub(super) reaction_state: IndexMap<AnnotationKey, ReactionState>,
     /// the in flight reaction request state that is ongoing
     pub(super) in_flight_reaction: IndexMap<AnnotationKey, ReactionState>,
+    /// Transaction ID => Local echo of an edit that is being sent.
+    pub(super) local_edits: IndexMap<OwnedTransactionId, LocalEdit>,
+    /// Responses and end events of polls that are not in the timeline yet.
+    pub(super) poll_pending_events: PollPendingEvents,
 }
 
 #[derive(Debug, Clone)]
@@ -123,11 +133,36 @@ pub(super) enum ReactionState {
     Sending(OwnedTransactionId),
 }
 
+/// The local echo of an edit.
+///
+/// Edits don't have their own timeline item, they are applied directly to the
+/// item of the edited event, so their send state is tracked separately.
+#[derive(Debug, Clone)]
+pub(super) struct LocalEdit {
+    /// The ID of the edited event.
+    pub(super) edited_event_id: OwnedEventId,
+    /// The content of the edit event.
+    pub(super) content: RoomMessageEventContent,
+    /// The content and latest edit JSON of the edited item before the edit
+    /// was applied, used to revert the edit if it is cancelled.
+    ///
+    /// When several edits of the same event are pending, only the latest one
+    /// is displayed, so cancelling an older one hands its previous content
+    /// over to the next one instead of reverting the item. It is `None` if
+    /// the edit must not be reverted, e.g. because a more recent edit was
+    /// sent.
+    pub(super) previous: Option<(TimelineItemContent, Option<Raw<AnySyncTimelineEvent>>)>,
+    /// The send state of the edit.
+    pub(super) send_state: EventSendState,
+}
+
 #[derive(Clone)]
 pub(super) struct TimelineInnerSettings {
     pub(super) track_read_receipts: bool,
     pub(super) event_filter: Arc<TimelineEventFilterFn>,
     pub(super) add_failed_to_parse: bool,
+    pub(super) focus: TimelineFocus,
+    pub(super) unable_to_decrypt_hook: Option<Arc<UtdHookManager>>,
 }
 
 impl fmt::Debug for TimelineInnerSettings {
@@ -135,6 +170,8 @@ impl fmt::Debug for TimelineInnerSettings {
         f.debug_struct("TimelineInnerSettings")
             .field("track_read_receipts", &self.track_read_receipts)
             .field("add_failed_to_parse", &self.add_failed_to_parse)
+            .field("focus", &self.focus)
+            .field("unable_to_decrypt_hook", &self.unable_to_decrypt_hook)
             .finish_non_exhaustive()
     }
 }
@@ -145,6 +182,56 @@ impl Default for TimelineInnerSettings {
             track_read_receipts: false,
             event_filter: Arc::new(|_| true),
             add_failed_to_parse: true,
+            focus: TimelineFocus::Live,
+            unable_to_decrypt_hook: None,
+        }
+    }
+}
+
+/// The events a timeline is made of.
+#[derive(Clone, Debug)]
+pub(super) enum TimelineFocus {
+    /// The live timeline of the room.
+    Live,
+
+    /// A thread of the room, made of the thread root and its replies.
+    Thread { root_event_id: OwnedEventId },
+}
+
+impl TimelineFocus {
+    /// The ID of the thread root, if this is a thread.
+    pub(super) fn thread_root(&self) -> Option<&EventId> {
+        match self {
+            Self::Live => None,
+            Self::Thread { root_event_id } => Some(root_event_id),
+        }
+    }
+
+    /// Whether the given event belongs to this timeline.
+    fn includes(&self, event: &AnySyncTimelineEvent) -> bool {
+        let Some(root_event_id) = self.thread_root() else { return true };
+        event.event_id() == root_event_id || thread_root(event) == Some(root_event_id)
+    }
+
+    /// The thread of the receipts sent for this timeline.
+    pub(super) fn receipt_thread(&self) -> ReceiptThread {
+        match self {
+            Self::Live => ReceiptThread::Unthreaded,
+            Self::Thread { root_event_id } => ReceiptThread::Thread(root_event_id.clone()),
+        }
+    }
+
+    /// Whether receipts in the given thread apply to this timeline.
+    ///
+    /// Unthreaded receipts apply to every timeline.
+    pub(super) fn accepts_receipt_thread(&self, thread: &ReceiptThread) -> bool {
+        match (self, thread) {
+            (_, ReceiptThread::Unthreaded) => true,
+            (Self::Live, ReceiptThread::Main) => true,
+            (Self::Thread { root_event_id }, ReceiptThread::Thread(thread_root)) => {
+                root_event_id == thread_root
+            }
+            _ => false,
         }
     }
 }
@@ -172,6 +259,10 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         self
     }
 
+    pub(super) fn focus(&self) -> &TimelineFocus {
+        &self.settings.focus
+    }
+
     /// Get a copy of the current items in the list.
     ///
     /// Cheap because `im::Vector` is cheap to clone.
@@ -386,7 +477,11 @@ impl<P: RoomDataProvider> TimelineInner<P> {
             for raw_event in update.ephemeral {
                 match raw_event.deserialize() {
                     Ok(AnySyncEphemeralRoomEvent::Receipt(ev)) => {
-                        state.handle_explicit_read_receipts(ev.content, own_user_id);
+                        state.handle_explicit_read_receipts(
+                            ev.content,
+                            own_user_id,
+                            &self.settings.focus,
+                        );
                     }
                     Ok(_) => {}
                     Err(e) => {
@@ -448,8 +543,30 @@ impl<P: RoomDataProvider> TimelineInner<P> {
             read_receipts: Default::default(),
             // An event sent by ourself is never matched against push rules.
             is_highlighted: false,
+            thread_summary: None,
         };
 
+        if let AnyMessageLikeEventContent::RoomMessage(
+            content @ RoomMessageEventContent {
+                relates_to: Some(Relation::Replacement(re)), ..
+            },
+        ) = &content
+        {
+            // The same local echo can be handled twice, when it is sent through
+            // the timeline and when it is received from the send queue.
+            if !state.local_edits.contains_key(&txn_id) {
+                let previous = rfind_event_by_id(&state.items, &re.event_id)
+                    .map(|(_, item)| (item.content().clone(), item.latest_edit_json().cloned()));
+                let local_edit = LocalEdit {
+                    edited_event_id: re.event_id.clone(),
+                    content: content.clone(),
+                    previous,
+                    send_state: EventSendState::NotSentYet,
+                };
+                state.local_edits.insert(txn_id.clone(), local_edit);
+            }
+        }
+
         let flow = Flow::Local { txn_id };
         let kind = TimelineEventKind::Message { content, relations: Default::default() };
 
@@ -497,6 +614,7 @@ impl<P: RoomDataProvider> TimelineInner<P> {
             read_receipts: Default::default(),
             // An event sent by ourself is never matched against push rules.
             is_highlighted: false,
+            thread_summary: None,
         };
 
         match to_redact {
@@ -537,6 +655,25 @@ impl<P: RoomDataProvider> TimelineInner<P> {
     ) {
         let mut state = self.state.lock().await;
 
+        if let Some(local_edit) = state.local_edits.get_mut(txn_id) {
+            if matches!(send_state, EventSendState::Sent { .. }) {
+                // The edit is already applied, the remote echo will only
+                // update the latest edit JSON.
+                if let Some((idx, _, local_edit)) = state.local_edits.shift_remove_full(txn_id) {
+                    // The older edits of the same event are superseded by this
+                    // one, cancelling them must not revert it.
+                    for older_edit in state.local_edits.values_mut().take(idx) {
+                        if older_edit.edited_event_id == local_edit.edited_event_id {
+                            older_edit.previous = None;
+                        }
+                    }
+                }
+            } else {
+                local_edit.send_state = send_state;
+            }
+            return;
+        }
+
         let new_event_id: Option<&EventId> = match &send_state {
             EventSendState::Sent { event_id } => Some(event_id),
             _ => None,
@@ -703,12 +840,98 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         Some(content)
     }
 
+    /// Prepare the local echo of an edit for retrying to send it.
+    ///
+    /// Returns the content of the edit if a local edit with the given
+    /// transaction ID failed to be sent.
+    pub(super) async fn prepare_edit_retry(
+        &self,
+        txn_id: &TransactionId,
+    ) -> Option<RoomMessageEventContent> {
+        let mut state = self.state.lock().await;
+        let local_edit = state.local_edits.get_mut(txn_id)?;
+
+        match &local_edit.send_state {
+            EventSendState::NotSentYet => {
+                warn!("Attempted to retry the sending of an edit that is already pending");
+                return None;
+            }
+            EventSendState::Sent { .. } => {
+                warn!("Attempted to retry the sending of an edit that has already succeeded");
+                return None;
+            }
+            EventSendState::SendingFailed { .. } | EventSendState::Cancelled => {}
+        }
+
+        local_edit.send_state = EventSendState::NotSentYet;
+        Some(local_edit.content.clone())
+    }
+
+    /// Get the send state of the local echo with the given transaction ID, if
+    /// it is in the timeline.
+    pub(super) async fn local_echo_send_state(
+        &self,
+        txn_id: &TransactionId,
+    ) -> Option<EventSendState> {
+        let state = self.state.lock().await;
+        if let Some(local_edit) = state.local_edits.get(txn_id) {
+            return Some(local_edit.send_state.clone());
+        }
+
+        let (_, item) = rfind_event_item(&state.items, |it| it.transaction_id() == Some(txn_id))?;
+        Some(item.as_local()?.send_state.clone())
+    }
+
+    /// Get the transaction IDs and send states of the local echoes that were
+    /// not sent yet, including the ones of edits.
+    pub(super) async fn unsent_local_echoes(&self) -> Vec<(OwnedTransactionId, EventSendState)> {
+        let state = self.state.lock().await;
+
+        let items = state
+            .items
+            .iter()
+            .filter_map(|item| item.as_event()?.as_local())
+            .map(|local| (local.transaction_id.clone(), local.send_state.clone()));
+        let edits = state
+            .local_edits
+            .iter()
+            .map(|(txn_id, local_edit)| (txn_id.clone(), local_edit.send_state.clone()));
+
+        items
+            .chain(edits)
+            .filter(|(_, send_state)| !matches!(send_state, EventSendState::Sent { .. }))
+            .collect()
+    }
+
     pub(super) async fn discard_local_echo(&self, txn_id: &TransactionId) -> bool {
         let mut state = self.state.lock().await;
         if let Some((idx, _)) =
             rfind_event_item(&state.items, |it| it.transaction_id() == Some(txn_id))
         {
             state.items.remove(idx);
+            true
+        } else if let Some((idx, _, local_edit)) = state.local_edits.shift_remove_full(txn_id) {
+            let newer_edit = state
+                .local_edits
+                .values_mut()
+                .skip(idx)
+                .find(|edit| edit.edited_event_id == local_edit.edited_event_id);
+
+            if let Some(newer_edit) = newer_edit {
+                // A newer edit of the same event is displayed, it must revert
+                // to what was displayed before this one.
+                newer_edit.previous = local_edit.previous;
+            } else if let Some((content, edit_json)) = local_edit.previous {
+                // Revert the edit.
+                if let Some((idx, item)) =
+                    rfind_event_by_id(&state.items, &local_edit.edited_event_id)
+                {
+                    let new_item =
+                        timeline_item(item.with_content(content, edit_json), item.internal_id);
+                    state.items.set(idx, new_item);
+                }
+            }
+
             true
         } else {
             false
@@ -721,13 +944,13 @@ impl<P: RoomDataProvider> TimelineInner<P> {
     #[instrument(skip_all)]
     pub(super) async fn handle_back_paginated_event(
         &self,
-        event: TimelineEvent,
+        event: SyncTimelineEvent,
     ) -> HandleEventResult {
         self.state
             .lock()
             .await
             .handle_remote_event(
-                event.into(),
+                event,
                 TimelineItemPosition::Start,
                 &self.room_data_provider,
                 &self.settings,
@@ -739,6 +962,27 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         self.state.lock().await.set_fully_read_event(fully_read_event_id)
     }
 
+    /// Get the ID of the latest event in the thread with the given root, as
+    /// far as this timeline knows.
+    ///
+    /// Falls back to the ID of the thread root.
+    pub(super) async fn latest_event_in_thread(&self, root_event_id: &EventId) -> OwnedEventId {
+        let state = self.state.lock().await;
+
+        if self.settings.focus.thread_root() == Some(root_event_id) {
+            // All the events of this timeline are in the thread.
+            if let Some(event_id) =
+                state.items.iter().rev().find_map(|item| item.as_event()?.event_id())
+            {
+                return event_id.to_owned();
+            }
+        }
+
+        rfind_event_by_id(&state.items, root_event_id)
+            .and_then(|(_, item)| item.thread_summary()?.latest_reply.clone())
+            .unwrap_or_else(|| root_event_id.to_owned())
+    }
+
     #[cfg(feature = "e2e-encryption")]
     #[instrument(skip(self, room), fields(room_id = ?room.room_id()))]
     pub(super) async fn retry_event_decryption(
@@ -835,7 +1079,7 @@ impl<P: RoomDataProvider> TimelineInner<P> {
                             trace!(
                                 "Successfully decrypted event that previously failed to decrypt"
                             );
-                            Some(event)
+                            Some((remote_event.event_id.clone(), event))
                         }
                         Err(e) => {
                             info!("Failed to decrypt event after receiving room key: {e}");
@@ -856,7 +1100,7 @@ impl<P: RoomDataProvider> TimelineInner<P> {
             let mut offset = 0;
             for idx in retry_indices {
                 let idx = idx - offset;
-                let Some(mut event) = retry_one(state.items[idx].clone()).await else {
+                let Some((event_id, mut event)) = retry_one(state.items[idx].clone()).await else {
                     continue;
                 };
 
@@ -876,6 +1120,10 @@ impl<P: RoomDataProvider> TimelineInner<P> {
                     )
                     .await;
 
+                if let Some(hook) = &settings.unable_to_decrypt_hook {
+                    hook.on_late_decrypt(&event_id);
+                }
+
                 // If the UTD was removed rather than updated, offset all
                 // subsequent loop iterations.
                 if result.item_removed {
@@ -942,7 +1190,11 @@ impl<P: RoomDataProvider> TimelineInner<P> {
     #[cfg(test)]
     pub(super) async fn handle_read_receipts(&self, receipt_event_content: ReceiptEventContent) {
         let own_user_id = self.room_data_provider.own_user_id();
-        self.state.lock().await.handle_explicit_read_receipts(receipt_event_content, own_user_id);
+        self.state.lock().await.handle_explicit_read_receipts(
+            receipt_event_content,
+            own_user_id,
+            &self.settings.focus,
+        );
     }
 }
 
@@ -1050,8 +1302,9 @@ impl TimelineInner {
     ) -> Option<(OwnedEventId, Receipt)> {
         let state = self.state.lock().await;
         let room = self.room();
+        let thread = self.settings.focus.receipt_thread();
 
-        state.latest_user_read_receipt(user_id, room).await
+        state.latest_user_read_receipt(user_id, &thread, room).await
     }
 
     /// Check whether the given receipt should be sent.
@@ -1063,8 +1316,9 @@ impl TimelineInner {
         thread: &ReceiptThread,
         event_id: &EventId,
     ) -> bool {
-        // We don't support threaded receipts yet.
-        if *thread != ReceiptThread::Unthreaded {
+        // We can't compare the positions of receipts for threads that are not
+        // in this timeline.
+        if !self.settings.focus.accepts_receipt_thread(thread) {
             return true;
         }
 
@@ -1075,7 +1329,7 @@ impl TimelineInner {
         match receipt_type {
             SendReceiptType::Read => {
                 if let Some((old_pub_read, _)) =
-                    state.user_receipt(own_user_id, ReceiptType::Read, room).await
+                    state.user_receipt(own_user_id, ReceiptType::Read, thread, room).await
                 {
                     if let Some(relative_pos) =
                         compare_events_positions(&old_pub_read, event_id, &state.items)
@@ -1088,7 +1342,7 @@ impl TimelineInner {
             // doesn't make sense to have a private read receipt behind a public one.
             SendReceiptType::ReadPrivate => {
                 if let Some((old_priv_read, _)) =
-                    state.latest_user_read_receipt(own_user_id, room).await
+                    state.latest_user_read_receipt(own_user_id, thread, room).await
                 {
                     if let Some(relative_pos) =
                         compare_events_positions(&old_priv_read, event_id, &state.items)
@@ -1164,49 +1418,74 @@ impl TimelineInnerState {
         settings: &TimelineInnerSettings,
     ) -> HandleEventResult {
         let should_add_event = &*settings.event_filter;
+        let unable_to_decrypt_reason = event.unable_to_decrypt_reason;
         let raw = event.event;
-        let (event_id, sender, timestamp, txn_id, event_kind, should_add) = match raw.deserialize()
-        {
-            Ok(event) => {
-                let should_add = should_add_event(&event);
-                (
-                    event.event_id().to_owned(),
-                    event.sender().to_owned(),
-                    event.origin_server_ts(),
-                    event.transaction_id().map(ToOwned::to_owned),
-                    event.into(),
-                    should_add,
-                )
-            }
-            Err(e) => match raw.deserialize_as::<SyncTimelineEventWithoutContent>() {
-                Ok(event) if settings.add_failed_to_parse => (
-                    event.event_id().to_owned(),
-                    event.sender().to_owned(),
-                    event.origin_server_ts(),
-                    event.transaction_id().map(ToOwned::to_owned),
-                    TimelineEventKind::failed_to_parse(event, e),
-                    true,
-                ),
+        let (event_id, sender, timestamp, txn_id, event_kind, should_add, thread_summary) =
+            match raw.deserialize() {
                 Ok(event) => {
-                    let event_type = event.event_type();
-                    let event_id = event.event_id();
-                    warn!(%event_type, %event_id, "Failed to deserialize timeline event: {e}");
-                    return HandleEventResult::default();
-                }
-                Err(e) => {
-                    let event_type: Option<String> = raw.get_field("type").ok().flatten();
-                    let event_id: Option<String> = raw.get_field("event_id").ok().flatten();
-                    warn!(event_type, event_id, "Failed to deserialize timeline event: {e}");
-                    return HandleEventResult::default();
+                    // `m.room.encrypted` events can't be filtered out, otherwise they
+                    // couldn't be decrypted when the appropriate room key arrives.
+                    let is_encrypted = matches!(
+                        event,
+                        AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
+                            _
+                        ))
+                    );
+                    let should_add = settings.focus.includes(&event)
+                        && (is_encrypted || should_add_event(&event));
+                    let thread_summary = bundled_thread_summary(&event);
+                    (
+                        event.event_id().to_owned(),
+                        event.sender().to_owned(),
+                        event.origin_server_ts(),
+                        event.transaction_id().map(ToOwned::to_owned),
+                        event.into(),
+                        should_add,
+                        thread_summary,
+                    )
                 }
-            },
-        };
+                Err(e) => match raw.deserialize_as::<SyncTimelineEventWithoutContent>() {
+                    Ok(event) if settings.add_failed_to_parse => {
+                        // We can't know in which thread this event is.
+                        let should_add = settings
+                            .focus
+                            .thread_root()
+                            .map_or(true, |root_event_id| event.event_id() == root_event_id);
+                        (
+                            event.event_id().to_owned(),
+                            event.sender().to_owned(),
+                            event.origin_server_ts(),
+                            event.transaction_id().map(ToOwned::to_owned),
+                            TimelineEventKind::failed_to_parse(event, e),
+                            should_add,
+                            None,
+                        )
+                    }
+                    Ok(event) => {
+                        let event_type = event.event_type();
+                        let event_id = event.event_id();
+                        warn!(%event_type, %event_id, "Failed to deserialize timeline event: {e}");
+                        return HandleEventResult::default();
+                    }
+                    Err(e) => {
+                        let event_type: Option<String> = raw.get_field("type").ok().flatten();
+                        let event_id: Option<String> = raw.get_field("event_id").ok().flatten();
+                        warn!(event_type, event_id, "Failed to deserialize timeline event: {e}");
+                        return HandleEventResult::default();
+                    }
+                },
+            };
 
         let is_own_event = sender == room_data_provider.own_user_id();
         let encryption_info = event.encryption_info;
         let sender_profile = room_data_provider.profile(&sender).await;
         let read_receipts = if settings.track_read_receipts {
-            self.load_read_receipts_for_event(&event_id, room_data_provider).await
+            self.load_read_receipts_for_event(
+                &event_id,
+                settings.focus.receipt_thread(),
+                room_data_provider,
+            )
+            .await
         } else {
             Default::default()
         };
@@ -1219,16 +1498,45 @@ impl TimelineInnerState {
             encryption_info,
             read_receipts,
             is_highlighted,
+            thread_summary,
         };
-        let flow = Flow::Remote { event_id, raw_event: raw, txn_id, position, should_add };
+        // Keep what's needed to report the event to the UTD hook, if it couldn't be
+        // decrypted.
+        let utd = matches!(
+            &event_kind,
+            TimelineEventKind::Message {
+                content: AnyMessageLikeEventContent::RoomEncrypted(_),
+                ..
+            }
+        )
+        .then(|| (event_id.clone(), unable_to_decrypt_reason.clone().unwrap_or_default()));
+
+        let flow = Flow::Remote {
+            event_id,
+            raw_event: raw,
+            txn_id,
+            position,
+            should_add,
+            unable_to_decrypt_reason,
+        };
+
+        let result =
+            TimelineEventHandler::new(event_meta, flow, self, settings.track_read_receipts)
+                .handle_event(event_kind);
+
+        if let (Some(hook), Some((event_id, reason))) = (&settings.unable_to_decrypt_hook, utd) {
+            if result.item_added {
+                hook.on_utd(&event_id, reason);
+            }
+        }
 
-        TimelineEventHandler::new(event_meta, flow, self, settings.track_read_receipts)
-            .handle_event(event_kind)
+        result
     }
 
     pub(super) fn clear(&mut self) {
         self.items.clear();
         self.reaction_map.clear();
+        self.poll_pending_events.clear();
         self.fully_read_event = None;
         self.event_should_update_fully_read_marker = false;
     }
@@ -25,27 +25,40 @@ use futures_core::Stream;
 use imbl::Vector;
 use matrix_sdk::{
     attachment::AttachmentConfig,
-    event_handler::EventHandlerHandle,
+    deserialized_responses::SyncTimelineEvent,
+    event_cache::{BackPaginationCursor, BackPaginationOutcome},
     executor::JoinHandle,
     room::{self, Joined, MessagesOptions, Receipts, Room},
-    Client, Result,
+    send_queue::RoomSendQueue,
+    Result,
 };
 use mime::Mime;
 use pin_project_lite::pin_project;
+#[cfg(feature = "e2e-encryption")]
+use ruma::events::{AnySyncMessageLikeEvent, SyncMessageLikeEvent};
 use ruma::{
-    api::client::receipt::create_receipt::v3::ReceiptType,
+    api::client::{
+        receipt::create_receipt::v3::ReceiptType, relations::get_relating_events_with_rel_type,
+    },
     assign,
     events::{
+        poll::start::PollKind,
         reaction::ReactionEventContent,
         receipt::{Receipt, ReceiptThread},
-        relation::Annotation,
-        room::{message::sanitize::HtmlSanitizerMode, redaction::RoomRedactionEventContent},
-        AnyMessageLikeEventContent,
+        relation::{Annotation, RelationType, Replacement, Thread},
+        room::{
+            message::{
+                sanitize::HtmlSanitizerMode, ForwardThread, MessageType, Relation,
+                RoomMessageEventContent, SyncRoomMessageEvent,
+            },
+            redaction::RoomRedactionEventContent,
+        },
+        AnyMessageLikeEventContent, AnySyncTimelineEvent,
     },
+    serde::Raw,
     EventId, OwnedEventId, OwnedTransactionId, TransactionId, UserId,
 };
 use thiserror::Error;
-use tokio::sync::mpsc::Sender;
 use tracing::{debug, error, info, instrument, warn};
 
 mod builder;
@@ -55,15 +68,17 @@ mod futures;
 mod inner;
 mod item;
 mod pagination;
+mod polls;
 mod queue;
 mod reactions;
 mod read_receipts;
+#[cfg(feature = "e2e-encryption")]
+mod room_keys;
 #[cfg(feature = "experimental-sliding-sync")]
 mod sliding_sync_ext;
 #[cfg(test)]
 mod tests;
-#[cfg(feature = "e2e-encryption")]
-mod to_device;
+mod threads;
 mod traits;
 mod virtual_item;
 
@@ -80,13 +95,14 @@ pub use self::{
     futures::SendAttachment,
     item::{TimelineItem, TimelineItemKind},
     pagination::{PaginationOptions, PaginationOutcome},
+    polls::{PollAnswer, PollState},
+    threads::ThreadSummary,
     traits::RoomExt,
     virtual_item::VirtualTimelineItem,
 };
 use self::{
     event_item::EventTimelineItemKind,
     inner::{ReactionAction, TimelineInner, TimelineInnerState},
-    queue::LocalMessage,
     reactions::ReactionToggleResult,
 };
 
@@ -104,11 +120,14 @@ pub struct Timeline {
 
     start_token: Arc<Mutex<Option<String>>>,
     start_token_condvar: Arc<Condvar>,
+    /// The position of the timeline in the event cache of the room, if its
+    /// events are loaded from there.
+    event_cache_cursor: Arc<Mutex<Option<BackPaginationCursor>>>,
     /// Observable for whether a pagination is currently running
     back_pagination_status: SharedObservable<BackPaginationStatus>,
 
     _end_token: Mutex<Option<String>>,
-    msg_sender: Sender<LocalMessage>,
+    send_queue: RoomSendQueue,
     drop_handle: Arc<TimelineDropHandle>,
 }
 
@@ -171,6 +190,15 @@ impl Timeline {
 
         self.back_pagination_status.set(BackPaginationStatus::Paginating);
 
+        if let Some(root_event_id) = self.inner.focus().thread_root() {
+            return self.paginate_thread_backwards(root_event_id, &mut start_lock, options).await;
+        }
+
+        if let Some(status) = self.paginate_backwards_from_cache(&mut options).await? {
+            self.back_pagination_status.set(status);
+            return Ok(());
+        }
+
         if start_lock.is_none() && options.wait_for_token {
             info!("No prev_batch token, waiting");
             (start_lock, _) = self
@@ -199,29 +227,73 @@ impl Timeline {
                     e
                 })?;
 
-            let process_events_result = async {
-                outcome.events_received = messages.chunk.len().try_into().ok()?;
-                outcome.total_events_received =
-                    outcome.total_events_received.checked_add(outcome.events_received)?;
-                outcome.items_added = 0;
-                outcome.items_updated = 0;
-
-                for room_ev in messages.chunk {
-                    let res = self.inner.handle_back_paginated_event(room_ev).await;
-                    outcome.items_added = outcome.items_added.checked_add(res.item_added as u16)?;
-                    outcome.items_updated = outcome.items_updated.checked_add(res.items_updated)?;
-                }
+            let process_events_result = self
+                .handle_back_paginated_events(
+                    messages.chunk.into_iter().map(Into::into),
+                    &mut outcome,
+                )
+                .await;
 
-                outcome.total_items_added =
-                    outcome.total_items_added.checked_add(outcome.items_added)?;
-                outcome.total_items_updated =
-                    outcome.total_items_updated.checked_add(outcome.items_updated)?;
+            from = messages.end;
 
-                Some(())
+            if from.is_none() {
+                break;
             }
-            .await;
 
-            from = messages.end;
+            if process_events_result.is_none() {
+                error!("Received an excessive number of events, ending pagination (u16 overflow)");
+                break;
+            }
+        }
+
+        let status = if from.is_some() {
+            BackPaginationStatus::Idle
+        } else {
+            BackPaginationStatus::TimelineStartReached
+        };
+        self.back_pagination_status.set(status);
+        *start_lock = from;
+
+        Ok(())
+    }
+
+    /// Paginate backwards in the thread with the given root.
+    ///
+    /// The replies are loaded from the newest to the oldest one, and the
+    /// thread root is added once all the replies are in the timeline.
+    async fn paginate_thread_backwards(
+        &self,
+        root_event_id: &EventId,
+        start_token: &mut Option<String>,
+        mut options: PaginationOptions<'_>,
+    ) -> Result<()> {
+        let room = self.room();
+        let mut from = start_token.clone();
+        let mut outcome = PaginationOutcome::new();
+
+        while let Some(limit) = options.next_event_limit(outcome) {
+            let request = assign!(get_relating_events_with_rel_type::v1::Request::new(
+                room.room_id().to_owned(),
+                root_event_id.to_owned(),
+                RelationType::Thread,
+            ), {
+                from,
+                limit: Some(limit.into()),
+            });
+            let response = room.client().send(request, None).await.map_err(|e| {
+                self.back_pagination_status.set(BackPaginationStatus::Idle);
+                e
+            })?;
+
+            let mut events = Vec::with_capacity(response.chunk.len());
+            for event in response.chunk {
+                events.push(self.thread_event(event.cast()).await);
+            }
+
+            let process_events_result =
+                self.handle_back_paginated_events(events.into_iter(), &mut outcome).await;
+
+            from = response.next_batch;
 
             if from.is_none() {
                 break;
@@ -236,17 +308,128 @@ impl Timeline {
         let status = if from.is_some() {
             BackPaginationStatus::Idle
         } else {
+            // All the replies were loaded, the thread root is the only event
+            // left.
+            let root_event = room.event(root_event_id).await.map_err(|e| {
+                self.back_pagination_status.set(BackPaginationStatus::Idle);
+                e
+            })?;
+            self.inner.handle_back_paginated_event(root_event.into()).await;
+
             BackPaginationStatus::TimelineStartReached
         };
         self.back_pagination_status.set(status);
-        *start_lock = from;
+        *start_token = from;
 
         Ok(())
     }
 
+    /// Decrypt the given event received from the relations API, if
+    /// possible.
+    async fn thread_event(&self, event: Raw<AnySyncTimelineEvent>) -> SyncTimelineEvent {
+        #[cfg(feature = "e2e-encryption")]
+        if let Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
+            SyncMessageLikeEvent::Original(_),
+        ))) = event.deserialize()
+        {
+            if let Ok(event) = self.room().decrypt_event(event.cast_ref()).await {
+                return event.into();
+            }
+        }
+
+        SyncTimelineEvent::new(event)
+    }
+
+    /// Paginate backwards with the events of the event cache of the room.
+    ///
+    /// Returns `None` if the timeline isn't loaded from the event cache, in
+    /// which case the events must be requested from the server directly.
+    async fn paginate_backwards_from_cache(
+        &self,
+        options: &mut PaginationOptions<'_>,
+    ) -> Result<Option<BackPaginationStatus>> {
+        let mut cursor_lock = self.event_cache_cursor.lock().await;
+        let Some(mut cursor) = *cursor_lock else {
+            return Ok(None);
+        };
+
+        let event_cache = self.room().event_cache();
+        let mut outcome = PaginationOutcome::new();
+
+        while let Some(limit) = options.next_event_limit(outcome) {
+            let result = event_cache.back_paginate(cursor, limit).await.map_err(|e| {
+                self.back_pagination_status.set(BackPaginationStatus::Idle);
+                e
+            })?;
+
+            let events = match result {
+                Some(BackPaginationOutcome::Events { events, cursor: next_cursor }) => {
+                    cursor = next_cursor;
+                    events
+                }
+                Some(BackPaginationOutcome::StartReached) => {
+                    *cursor_lock = Some(cursor);
+                    return Ok(Some(BackPaginationStatus::TimelineStartReached));
+                }
+                None => {
+                    debug!("Lost the position in the event cache, paginating from the server");
+                    *cursor_lock = None;
+                    return Ok(None);
+                }
+            };
+
+            // The events are in topological order, but each one is added at
+            // the start of the timeline.
+            if self
+                .handle_back_paginated_events(events.into_iter().rev(), &mut outcome)
+                .await
+                .is_none()
+            {
+                error!("Received an excessive number of events, ending pagination (u16 overflow)");
+                break;
+            }
+        }
+
+        *cursor_lock = Some(cursor);
+
+        Ok(Some(BackPaginationStatus::Idle))
+    }
+
+    /// Add the given back-paginated events to the start of the timeline, from
+    /// the newest to the oldest one, and update the pagination outcome.
+    ///
+    /// Returns `None` if a counter of the outcome overflowed.
+    async fn handle_back_paginated_events(
+        &self,
+        events: impl ExactSizeIterator<Item = SyncTimelineEvent>,
+        outcome: &mut PaginationOutcome,
+    ) -> Option<()> {
+        outcome.events_received = events.len().try_into().ok()?;
+        outcome.total_events_received =
+            outcome.total_events_received.checked_add(outcome.events_received)?;
+        outcome.items_added = 0;
+        outcome.items_updated = 0;
+
+        for event in events {
+            let res = self.inner.handle_back_paginated_event(event).await;
+            outcome.items_added = outcome.items_added.checked_add(res.item_added as u16)?;
+            outcome.items_updated = outcome.items_updated.checked_add(res.items_updated)?;
+        }
+
+        outcome.total_items_added = outcome.total_items_added.checked_add(outcome.items_added)?;
+        outcome.total_items_updated =
+            outcome.total_items_updated.checked_add(outcome.items_updated)?;
+
+        Some(())
+    }
+
     /// Retry decryption of previously un-decryptable events given a list of
     /// session IDs whose keys have been imported.
     ///
+    /// The timeline already does this automatically whenever room keys for
+    /// its room are received or imported, so this is only useful to force a
+    /// retry.
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -339,8 +522,11 @@ impl Timeline {
     /// If the encryption feature is enabled, this method will transparently
     /// encrypt the room message if the room is encrypted.
     ///
-    /// If sending the message fails, the local echo item will change its
-    /// `send_state` to [`EventSendState::SendingFailed`].
+    /// The message is added to the [send queue](RoomSendQueue) of the room, so
+    /// it is still sent if the application is restarted before that. If
+    /// sending the message fails with an error that can't be solved by
+    /// retrying, the local echo item will change its `send_state` to
+    /// [`EventSendState::SendingFailed`].
     ///
     /// # Arguments
     ///
@@ -360,12 +546,277 @@ impl Timeline {
     ///
     /// [`MessageLikeUnsigned`]: ruma::events::MessageLikeUnsigned
     /// [`SyncMessageLikeEvent`]: ruma::events::SyncMessageLikeEvent
+    ///
+    /// In a timeline focused on a thread, room messages without a relation are
+    /// sent in the thread.
     #[instrument(skip(self, content), fields(room_id = ?self.room().room_id()))]
     pub async fn send(&self, content: AnyMessageLikeEventContent, txn_id: Option<&TransactionId>) {
+        let content = match content {
+            AnyMessageLikeEventContent::RoomMessage(mut content)
+                if content.relates_to.is_none() =>
+            {
+                if let Some(root_event_id) = self.inner.focus().thread_root() {
+                    let latest_event_id = self.inner.latest_event_in_thread(root_event_id).await;
+                    content.relates_to = Some(Relation::Thread(Thread::plain(
+                        root_event_id.to_owned(),
+                        latest_event_id,
+                    )));
+                }
+
+                content.into()
+            }
+            content => content,
+        };
+
+        let txn_id = txn_id.map_or_else(TransactionId::new, ToOwned::to_owned);
+        self.inner.handle_local_event(txn_id.clone(), content.clone()).await;
+        self.queue_local_event(content, &txn_id).await;
+    }
+
+    /// Send a message in the thread with the given root, and add it to the
+    /// timeline as a local echo.
+    ///
+    /// Any existing relation of the message is replaced. For clients that
+    /// don't support threads, the message falls back to a reply to the latest
+    /// event of the thread known by this timeline.
+    ///
+    /// See [`Timeline::send()`] for more details.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the message event.
+    ///
+    /// * `thread_root` - The ID of the root event of the thread.
+    ///
+    /// * `txn_id` - A locally-unique ID describing a message transaction with
+    ///   the homeserver.
+    #[instrument(skip(self, content), fields(room_id = ?self.room().room_id()))]
+    pub async fn send_in_thread(
+        &self,
+        mut content: RoomMessageEventContent,
+        thread_root: &EventId,
+        txn_id: Option<&TransactionId>,
+    ) {
+        let latest_event_id = self.inner.latest_event_in_thread(thread_root).await;
+        content.relates_to =
+            Some(Relation::Thread(Thread::plain(thread_root.to_owned(), latest_event_id)));
+
+        self.send(content.into(), txn_id).await;
+    }
+
+    /// Send a reply to the given event item, and add it to the timeline as a
+    /// local echo.
+    ///
+    /// The reply includes the fallback for clients that don't support
+    /// replies, which quotes the latest content of the replied-to item.
+    ///
+    /// See [`Timeline::send()`] for more details.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the reply.
+    ///
+    /// * `replied_to_item` - The event item to reply to. It must be a message
+    ///   that was echoed back by the server.
+    ///
+    /// * `forward_thread` - Whether the reply should be sent in the thread of
+    ///   the replied-to event, if any.
+    ///
+    /// * `txn_id` - A locally-unique ID describing a message transaction with
+    ///   the homeserver.
+    #[instrument(skip(self, content, replied_to_item), fields(room_id = ?self.room().room_id()))]
+    pub async fn send_reply(
+        &self,
+        content: RoomMessageEventContent,
+        replied_to_item: &EventTimelineItem,
+        forward_thread: ForwardThread,
+        txn_id: Option<&TransactionId>,
+    ) -> Result<(), Error> {
+        let TimelineItemContent::Message(message) = replied_to_item.content() else {
+            return Err(Error::UnsupportedReplyItem);
+        };
+        let Some(raw_event) = replied_to_item.original_json() else {
+            return Err(Error::UnsupportedReplyItem);
+        };
+
+        let event = match raw_event.deserialize_as::<SyncRoomMessageEvent>() {
+            Ok(SyncRoomMessageEvent::Original(event)) => event,
+            Ok(SyncRoomMessageEvent::Redacted(_)) => return Err(Error::UnsupportedReplyItem),
+            Err(e) => {
+                warn!("Failed to deserialize the replied-to event: {e}");
+                return Err(Error::UnsupportedReplyItem);
+            }
+        };
+
+        let mut replied_to_event = event.into_full_event(self.room().room_id().to_owned());
+        // Quote the content that is displayed, in case the event was edited.
+        replied_to_event.content.msgtype = message.msgtype().clone();
+
+        let content = content.make_reply_to(&replied_to_event, forward_thread);
+        self.send(content.into(), txn_id).await;
+
+        Ok(())
+    }
+
+    /// Edit the given event item, and apply the edit to the timeline as a
+    /// local echo.
+    ///
+    /// The edit includes the fallback for clients that don't support edits.
+    /// Like other local echoes, the send state of the edit is tracked with its
+    /// transaction ID, so it can be retried with [`Timeline::retry_send()`]
+    /// or cancelled with [`Timeline::cancel_send()`]. Cancelling it reverts
+    /// the edit in the timeline.
+    ///
+    /// See [`Timeline::send()`] for more details.
+    ///
+    /// # Arguments
+    ///
+    /// * `edit_item` - The event item to edit. It must be
+    ///   [editable](EventTimelineItem::is_editable) and echoed back by the
+    ///   server.
+    ///
+    /// * `new_content` - The new content of the event. Its relation is ignored.
+    ///
+    /// * `txn_id` - A locally-unique ID describing a message transaction with
+    ///   the homeserver.
+    #[instrument(skip(self, edit_item, new_content), fields(room_id = ?self.room().room_id()))]
+    pub async fn edit(
+        &self,
+        edit_item: &EventTimelineItem,
+        new_content: RoomMessageEventContent,
+        txn_id: Option<&TransactionId>,
+    ) -> Result<(), Error> {
+        if !edit_item.is_editable() {
+            return Err(Error::UnsupportedEditItem);
+        }
+        let Some(event_id) = edit_item.event_id() else {
+            return Err(Error::UnsupportedEditItem);
+        };
+
+        let replacement = Replacement::new(event_id.to_owned(), new_content.msgtype.clone().into());
+        let mut content = RoomMessageEventContent::new(edit_fallback(new_content.msgtype));
+        content.relates_to = Some(Relation::Replacement(replacement));
+
         let txn_id = txn_id.map_or_else(TransactionId::new, ToOwned::to_owned);
+        let content = AnyMessageLikeEventContent::RoomMessage(content);
         self.inner.handle_local_event(txn_id.clone(), content.clone()).await;
-        if self.msg_sender.send(LocalMessage { content, txn_id }).await.is_err() {
-            error!("Internal error: timeline message receiver is closed");
+        self.queue_local_event(content, &txn_id).await;
+
+        Ok(())
+    }
+
+    /// Send a poll to the room, see [`Joined::create_poll()`].
+    ///
+    /// The local echo of the poll is added to the timeline once it is in the
+    /// send queue of the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `question` - The question of the poll.
+    ///
+    /// * `answers` - The possible answers of the poll. Their number is limited,
+    ///   see [`UnstablePollAnswers`].
+    ///
+    /// * `max_selections` - The maximum number of answers a user can select.
+    ///
+    /// * `kind` - Whether the results are visible before the poll is ended.
+    ///
+    /// * `txn_id` - A locally-unique ID describing a message transaction with
+    ///   the homeserver.
+    ///
+    /// [`UnstablePollAnswers`]: ruma::events::poll::unstable_start::UnstablePollAnswers
+    pub async fn create_poll(
+        &self,
+        question: String,
+        answers: Vec<String>,
+        max_selections: u8,
+        kind: PollKind,
+        txn_id: Option<&TransactionId>,
+    ) -> Result<(), Error> {
+        let room = self.joined_room()?;
+
+        match room.create_poll(question, answers, max_selections, kind, txn_id).await {
+            Ok(_) => {}
+            Err(matrix_sdk::Error::InvalidPollAnswers) => return Err(Error::InvalidPollAnswers),
+            Err(e) => error!("Failed to add the poll to the send queue: {e}"),
+        }
+
+        Ok(())
+    }
+
+    /// Vote in a poll, see [`Joined::send_poll_response()`].
+    ///
+    /// The local echo of the response is added to the timeline once it is in
+    /// the send queue of the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_start_id` - The ID of the start event of the poll.
+    ///
+    /// * `answers` - The IDs of the selected answers.
+    ///
+    /// * `txn_id` - A locally-unique ID describing a message transaction with
+    ///   the homeserver.
+    pub async fn send_poll_response(
+        &self,
+        poll_start_id: &EventId,
+        answers: Vec<String>,
+        txn_id: Option<&TransactionId>,
+    ) {
+        let room = match self.joined_room() {
+            Ok(room) => room,
+            Err(e) => {
+                error!("Failed to send the poll response: {e}");
+                return;
+            }
+        };
+
+        if let Err(e) = room.send_poll_response(poll_start_id, answers, txn_id).await {
+            error!("Failed to add the poll response to the send queue: {e}");
+        }
+    }
+
+    /// End a poll, see [`Joined::end_poll()`].
+    ///
+    /// The local echo of the end event is added to the timeline once it is in
+    /// the send queue of the room.
+    ///
+    /// Only the sender of the poll can end it.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_start_id` - The ID of the start event of the poll.
+    ///
+    /// * `text` - A textual fallback for clients that don't support polls.
+    ///
+    /// * `txn_id` - A locally-unique ID describing a message transaction with
+    ///   the homeserver.
+    pub async fn end_poll(
+        &self,
+        poll_start_id: &EventId,
+        text: &str,
+        txn_id: Option<&TransactionId>,
+    ) {
+        let room = match self.joined_room() {
+            Ok(room) => room,
+            Err(e) => {
+                error!("Failed to end the poll: {e}");
+                return;
+            }
+        };
+
+        if let Err(e) = room.end_poll(poll_start_id, text, txn_id).await {
+            error!("Failed to add the poll end event to the send queue: {e}");
+        }
+    }
+
+    /// Add an event whose local echo is already in the timeline to the send
+    /// queue of the room.
+    async fn queue_local_event(&self, content: AnyMessageLikeEventContent, txn_id: &TransactionId) {
+        if let Err(e) = self.send_queue.send_with_transaction_id(content, txn_id).await {
+            error!("Failed to add the event to the send queue: {e}");
+            let send_state = EventSendState::SendingFailed { error: Arc::new(e) };
+            self.inner.update_event_send_state(txn_id, send_state).await;
         }
     }
 
@@ -482,6 +933,16 @@ impl Timeline {
             }};
         }
 
+        // Retrying would send the event a second time.
+        if self.send_queue.is_being_sent(txn_id).await {
+            return Err(Error::RetryEventBeingSent);
+        }
+
+        if let Some(content) = self.inner.prepare_edit_retry(txn_id).await {
+            self.retry_queued_event(content.into(), txn_id).await;
+            return Ok(());
+        }
+
         let item = self.inner.prepare_retry(txn_id).await.ok_or(Error::RetryEventNotInTimeline)?;
         let content = match item {
             TimelineItemContent::Message(msg) => {
@@ -493,6 +954,9 @@ impl Timeline {
             TimelineItemContent::Sticker(sticker) => {
                 AnyMessageLikeEventContent::Sticker(sticker.content)
             }
+            TimelineItemContent::Poll(poll_state) => {
+                AnyMessageLikeEventContent::UnstablePollStart(poll_state.start_event_content)
+            }
             TimelineItemContent::UnableToDecrypt(_) => {
                 error_return!("Invalid state: attempting to retry a UTD item");
             }
@@ -507,14 +971,32 @@ impl Timeline {
             }
         };
 
-        let txn_id = txn_id.to_owned();
-        if self.msg_sender.send(LocalMessage { content, txn_id }).await.is_err() {
-            error!("Internal error: timeline message receiver is closed");
-        }
+        self.retry_queued_event(content, txn_id).await;
 
         Ok(())
     }
 
+    /// Retry sending an event of the send queue whose local echo was prepared
+    /// for retrying.
+    async fn retry_queued_event(
+        &self,
+        content: AnyMessageLikeEventContent,
+        txn_id: &TransactionId,
+    ) {
+        match self.send_queue.unwedge(txn_id).await {
+            Ok(true) => {}
+            // The event started being sent in the meantime.
+            Ok(false) if self.send_queue.is_being_sent(txn_id).await => {}
+            // The event isn't in the send queue anymore, add it again.
+            Ok(false) => self.queue_local_event(content, txn_id).await,
+            Err(e) => {
+                error!("Failed to retry sending the event: {e}");
+                let send_state = EventSendState::SendingFailed { error: Arc::new(e) };
+                self.inner.update_event_send_state(txn_id, send_state).await;
+            }
+        }
+    }
+
     /// Discard a local echo for a message that failed to send.
     ///
     /// Returns whether the local echo with the given transaction ID was found.
@@ -527,6 +1009,10 @@ impl Timeline {
     ///   well, but there can be no guarantee for that actually stopping the
     ///   event from reaching the server.
     pub async fn cancel_send(&self, txn_id: &TransactionId) -> bool {
+        if let Err(e) = self.send_queue.cancel(txn_id).await {
+            error!("Failed to remove the event from the send queue: {e}");
+        }
+
         self.inner.discard_local_echo(txn_id).await
     }
 
@@ -672,17 +1158,18 @@ impl Timeline {
 
 #[derive(Debug)]
 struct TimelineDropHandle {
-    client: Client,
-    event_handler_handles: Vec<EventHandlerHandle>,
     room_update_join_handle: JoinHandle<()>,
+    send_queue_join_handle: JoinHandle<()>,
+    #[cfg(feature = "e2e-encryption")]
+    room_keys_join_handle: JoinHandle<()>,
 }
 
 impl Drop for TimelineDropHandle {
     fn drop(&mut self) {
-        for handle in self.event_handler_handles.drain(..) {
-            self.client.remove_event_handler(handle);
-        }
         self.room_update_join_handle.abort();
+        self.send_queue_join_handle.abort();
+        #[cfg(feature = "e2e-encryption")]
+        self.room_keys_join_handle.abort();
     }
 }
 
@@ -781,6 +1268,10 @@ pub enum Error {
     #[error("Event not found, can't retry sending")]
     RetryEventNotInTimeline,
 
+    /// The event with the given transaction ID is being sent, can't retry.
+    #[error("Event is being sent, can't retry sending")]
+    RetryEventBeingSent,
+
     /// The event is currently unsupported for this use case.
     #[error("Unsupported event")]
     UnsupportedEvent,
@@ -808,6 +1299,47 @@ pub enum Error {
     /// Could not get user
     #[error("User ID is not available")]
     UserIdNotAvailable,
+
+    /// The event item can't be edited.
+    #[error("Unsupported event item for an edit")]
+    UnsupportedEditItem,
+
+    /// The event item can't be replied to.
+    #[error("Unsupported event item for a reply")]
+    UnsupportedReplyItem,
+
+    /// The poll doesn't have a valid number of answers.
+    #[error("Invalid number of poll answers")]
+    InvalidPollAnswers,
+}
+
+/// Add the fallback for clients that don't support edits to the given message
+/// type, i.e. prefix its body with `* `.
+fn edit_fallback(msgtype: MessageType) -> MessageType {
+    macro_rules! prefix_body {
+        ($content:ident) => {{
+            $content.body = format!("* {}", $content.body);
+            if let Some(formatted) = &mut $content.formatted {
+                formatted.body = format!("* {}", formatted.body);
+            }
+        }};
+    }
+
+    match msgtype {
+        MessageType::Text(mut c) => {
+            prefix_body!(c);
+            MessageType::Text(c)
+        }
+        MessageType::Emote(mut c) => {
+            prefix_body!(c);
+            MessageType::Emote(c)
+        }
+        MessageType::Notice(mut c) => {
+            prefix_body!(c);
+            MessageType::Notice(c)
+        }
+        msgtype => msgtype,
+    }
 }
 
 /// Result of comparing events position in the timeline.
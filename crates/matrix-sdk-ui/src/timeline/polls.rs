@@ -0,0 +1,246 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Aggregation of [MSC3381] polls in the timeline.
+//!
+//! [MSC3381]: https://github.com/matrix-org/matrix-spec-proposals/pull/3381
+
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+use ruma::{
+    events::poll::{start::PollKind, unstable_start::UnstablePollStartEventContent},
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId, UserId,
+};
+
+/// The state of a poll, aggregated from its start event and the responses
+/// and end event that relate to it.
+#[derive(Clone, Debug)]
+pub struct PollState {
+    pub(super) start_event_content: UnstablePollStartEventContent,
+    pub(super) response_data: Vec<ResponseData>,
+    pub(super) end_event_timestamp: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+/// A response to a poll.
+#[derive(Clone, Debug)]
+pub(super) struct ResponseData {
+    pub(super) sender: OwnedUserId,
+    pub(super) timestamp: MilliSecondsSinceUnixEpoch,
+    pub(super) answers: Vec<String>,
+}
+
+/// A possible answer of a poll.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PollAnswer {
+    /// The ID of the answer, used by the responses.
+    pub id: String,
+
+    /// The text of the answer.
+    pub text: String,
+}
+
+impl PollState {
+    pub(super) fn new(content: UnstablePollStartEventContent) -> Self {
+        Self { start_event_content: content, response_data: Vec::new(), end_event_timestamp: None }
+    }
+
+    /// Add a response to this poll.
+    pub(super) fn add_response(&self, response: ResponseData) -> Self {
+        let mut clone = self.clone();
+        clone.response_data.push(response);
+        clone
+    }
+
+    /// End this poll at the given time.
+    ///
+    /// The earliest end event wins, so this returns `None` if the poll was
+    /// already ended at that time or before.
+    pub(super) fn end(&self, timestamp: MilliSecondsSinceUnixEpoch) -> Option<Self> {
+        if self.end_event_timestamp.is_some_and(|end| end <= timestamp) {
+            return None;
+        }
+
+        let mut clone = self.clone();
+        clone.end_event_timestamp = Some(timestamp);
+        Some(clone)
+    }
+
+    /// The question of the poll.
+    pub fn question(&self) -> &str {
+        &self.start_event_content.poll_start.question.text
+    }
+
+    /// The kind of the poll, i.e. whether the results are visible before it
+    /// is ended.
+    pub fn kind(&self) -> &PollKind {
+        &self.start_event_content.poll_start.kind
+    }
+
+    /// The maximum number of answers a user can select.
+    pub fn max_selections(&self) -> u64 {
+        self.start_event_content.poll_start.max_selections.into()
+    }
+
+    /// The possible answers of the poll.
+    pub fn answers(&self) -> Vec<PollAnswer> {
+        self.start_event_content
+            .poll_start
+            .answers
+            .iter()
+            .map(|answer| PollAnswer { id: answer.id.clone(), text: answer.text.clone() })
+            .collect()
+    }
+
+    /// Whether the poll was ended.
+    pub fn is_ended(&self) -> bool {
+        self.end_event_timestamp.is_some()
+    }
+
+    /// The time when the poll was ended, if it was.
+    pub fn end_time(&self) -> Option<MilliSecondsSinceUnixEpoch> {
+        self.end_event_timestamp
+    }
+
+    /// The users who voted for each answer of the poll, by answer ID.
+    ///
+    /// All the answers of the poll are present, in the same order as
+    /// [`PollState::answers()`]. Only the latest valid response of each user
+    /// sent before the poll was ended is counted.
+    pub fn votes(&self) -> IndexMap<String, Vec<OwnedUserId>> {
+        let mut votes: IndexMap<_, _> = self
+            .start_event_content
+            .poll_start
+            .answers
+            .iter()
+            .map(|answer| (answer.id.clone(), Vec::new()))
+            .collect();
+
+        for (user_id, answers) in self.user_selections() {
+            for answer in answers {
+                if let Some(voters) = votes.get_mut(answer) {
+                    voters.push(user_id.to_owned());
+                }
+            }
+        }
+
+        votes
+    }
+
+    /// The IDs of the answers selected by the given user.
+    ///
+    /// Returns an empty list if the user didn't vote, or if their latest
+    /// response is not valid.
+    pub fn user_selection(&self, user_id: &UserId) -> Vec<String> {
+        self.user_selections()
+            .remove(user_id)
+            .map(|answers| answers.into_iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The valid selections of the users that voted, by user ID.
+    fn user_selections(&self) -> HashMap<&UserId, Vec<&String>> {
+        // Only the latest response of each user, sent before the poll was
+        // ended, counts.
+        let mut latest_responses: HashMap<&UserId, &ResponseData> = HashMap::new();
+        for response in &self.response_data {
+            if self.end_event_timestamp.is_some_and(|end| response.timestamp > end) {
+                continue;
+            }
+
+            let latest = latest_responses.entry(&*response.sender).or_insert(response);
+            if latest.timestamp <= response.timestamp {
+                *latest = response;
+            }
+        }
+
+        let poll_start = &self.start_event_content.poll_start;
+        let max_selections = u64::from(poll_start.max_selections);
+
+        latest_responses
+            .into_iter()
+            .map(|(user_id, response)| {
+                // Unknown answers are ignored, and only the first valid
+                // answers up to the maximum number of selections are counted.
+                let answers = response
+                    .answers
+                    .iter()
+                    .filter(|id| poll_start.answers.iter().any(|answer| answer.id == **id))
+                    .take(max_selections.try_into().unwrap_or(usize::MAX))
+                    .collect();
+                (user_id, answers)
+            })
+            .collect()
+    }
+}
+
+/// Responses and end events of polls whose start event is not in the
+/// timeline yet.
+#[derive(Debug, Default)]
+pub(super) struct PollPendingEvents {
+    /// Start event ID => Responses to the poll.
+    pub(super) pending_responses: HashMap<OwnedEventId, Vec<ResponseData>>,
+    /// Start event ID => Senders and timestamps of the end events of the poll.
+    ///
+    /// All of them are kept, because we don't know yet who sent the poll and
+    /// thus who is allowed to end it.
+    pub(super) pending_ends: HashMap<OwnedEventId, Vec<(OwnedUserId, MilliSecondsSinceUnixEpoch)>>,
+}
+
+impl PollPendingEvents {
+    pub(super) fn add_response(&mut self, start_event_id: OwnedEventId, response: ResponseData) {
+        self.pending_responses.entry(start_event_id).or_default().push(response);
+    }
+
+    pub(super) fn add_end(
+        &mut self,
+        start_event_id: OwnedEventId,
+        sender: OwnedUserId,
+        timestamp: MilliSecondsSinceUnixEpoch,
+    ) {
+        self.pending_ends.entry(start_event_id).or_default().push((sender, timestamp));
+    }
+
+    /// Apply the pending events of the poll with the given start event ID and
+    /// sender to its state.
+    pub(super) fn apply(
+        &mut self,
+        start_event_id: &EventId,
+        poll_sender: &UserId,
+        poll_state: &mut PollState,
+    ) {
+        if let Some(responses) = self.pending_responses.remove(start_event_id) {
+            poll_state.response_data.extend(responses);
+        }
+
+        if let Some(ends) = self.pending_ends.remove(start_event_id) {
+            // Only the sender of the poll can end it, and the first end event
+            // wins.
+            let end_timestamp = ends
+                .into_iter()
+                .filter(|(sender, _)| sender == poll_sender)
+                .map(|(_, timestamp)| timestamp)
+                .min();
+
+            if end_timestamp.is_some() {
+                poll_state.end_event_timestamp = end_timestamp;
+            }
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.pending_responses.clear();
+        self.pending_ends.clear();
+    }
+}
@@ -12,233 +12,165 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{
-    collections::VecDeque,
-    future::{pending, Future, Pending},
-    mem,
-    pin::Pin,
-    sync::Arc,
-    task::{Context, Poll},
-};
+use std::{collections::HashMap, sync::Arc};
 
-use futures_util::future::Either;
-use matrix_sdk::{
-    executor::{spawn, JoinError, JoinHandle},
-    room::{self, Room},
+use matrix_sdk::send_queue::{
+    QueuedRequest, QueuedRequestKind, RoomSendQueue, RoomSendQueueError, RoomSendQueueUpdate,
 };
-use ruma::{events::AnyMessageLikeEventContent, OwnedTransactionId};
-use tokio::{select, sync::mpsc::Receiver};
-use tracing::{debug, error, info, instrument, trace, warn};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tracing::{error, info, instrument, trace, warn};
 
 use super::{inner::TimelineInner, EventSendState};
 
-/// A locally-created message that is supposed to be sent.
-pub(super) struct LocalMessage {
-    /// The transaction ID.
-    ///
-    /// Used for finding the corresponding local echo in the timeline.
-    pub txn_id: OwnedTransactionId,
-    /// The message contents.
-    pub content: AnyMessageLikeEventContent,
+/// Add the local echoes of the requests of the send queue of the room to the
+/// timeline.
+///
+/// This is used to restore the local echoes of the events that were not sent
+/// yet when the timeline is created.
+pub(super) async fn add_local_echoes(timeline_inner: &TimelineInner, requests: Vec<QueuedRequest>) {
+    let mut wedged = Vec::new();
+
+    for request in requests {
+        if request.is_wedged {
+            wedged.push(request.transaction_id.clone());
+        }
+
+        add_local_echo(timeline_inner, request).await;
+    }
+
+    // Marking an event as failed cancels the following ones, so do it once all
+    // the local echoes are in the timeline.
+    for txn_id in wedged {
+        let send_state = EventSendState::SendingFailed {
+            error: Arc::new(RoomSendQueueError::FailedInPreviousSession.into()),
+        };
+        timeline_inner.update_event_send_state(&txn_id, send_state).await;
+    }
 }
 
-#[instrument(skip_all, fields(room_id = ?room.room_id()))]
-pub(super) async fn send_queued_messages(
+/// Apply the updates of the send queue of the room to the local echoes of the
+/// timeline.
+#[instrument(skip_all, fields(room_id = ?timeline_inner.room().room_id()))]
+pub(super) async fn handle_send_queue_updates(
     timeline_inner: TimelineInner,
-    room: room::Common,
-    mut msg_receiver: Receiver<LocalMessage>,
+    send_queue: RoomSendQueue,
+    mut updates: Receiver<RoomSendQueueUpdate>,
 ) {
-    let mut queue = VecDeque::new();
-    let mut send_task: SendMessageTask = SendMessageTask::Idle;
-    let mut recv_fut: Either<_, Pending<Option<LocalMessage>>> =
-        Either::Left(Box::pin(msg_receiver.recv()));
-
     loop {
-        select! {
-            result = &mut send_task => {
-                trace!("SendMessageTask finished");
-                send_task.reset();
-                handle_task_ready(
-                    result,
-                    &mut send_task,
-                    &mut queue,
-                    &timeline_inner,
-                ).await;
+        let update = match updates.recv().await {
+            Ok(update) => update,
+            Err(RecvError::Closed) => break,
+            Err(RecvError::Lagged(num_skipped)) => {
+                warn!(num_skipped, "Lagged behind send queue updates, resyncing local echoes");
+                resync_local_echoes(&timeline_inner, &send_queue).await;
+                continue;
+            }
+        };
+
+        match update {
+            RoomSendQueueUpdate::NewLocalEvent(request) => {
+                // The local echo was already added if the event was sent
+                // through the timeline.
+                if timeline_inner.local_echo_send_state(&request.transaction_id).await.is_none() {
+                    add_local_echo(&timeline_inner, request).await;
+                }
             }
-            recv_res = &mut recv_fut => {
-                recv_fut = if let Some(msg) = recv_res {
-                    trace!("Got a LocalMessage");
-                    handle_message(
-                        msg,
-                        room.clone(),
-                        &mut send_task,
-                        &mut queue,
-                        &timeline_inner,
-                    ).await;
-
-                    // appease the borrow checker
-                    drop(recv_fut);
-
-                    Either::Left(Box::pin(msg_receiver.recv()))
+            RoomSendQueueUpdate::CancelledLocalEvent { transaction_id } => {
+                timeline_inner.discard_local_echo(&transaction_id).await;
+            }
+            RoomSendQueueUpdate::SendError { transaction_id, error, is_recoverable } => {
+                if is_recoverable {
+                    trace!(?transaction_id, "Sending failed, the event will be retried");
                 } else {
-                    info!("Message receiver closed");
-                    Either::Right(pending())
-                };
+                    let send_state = EventSendState::SendingFailed { error };
+                    timeline_inner.update_event_send_state(&transaction_id, send_state).await;
+                }
             }
-        }
-
-        if send_task.is_idle() && matches!(recv_fut, Either::Right(_)) {
-            break;
-        }
-    }
-
-    info!("Stopped");
-}
-
-async fn handle_message(
-    msg: LocalMessage,
-    room: room::Common,
-    send_task: &mut SendMessageTask,
-    queue: &mut VecDeque<LocalMessage>,
-    timeline_inner: &TimelineInner,
-) {
-    if queue.is_empty() && send_task.is_idle() {
-        match Room::from(room) {
-            Room::Joined(room) => {
-                send_task.start(room, timeline_inner.clone(), msg);
+            RoomSendQueueUpdate::RetryEvent { transaction_id } => {
+                // The local echo was already moved if the event was retried
+                // through the timeline.
+                if matches!(
+                    timeline_inner.local_echo_send_state(&transaction_id).await,
+                    Some(EventSendState::SendingFailed { .. } | EventSendState::Cancelled)
+                ) {
+                    if timeline_inner.prepare_edit_retry(&transaction_id).await.is_none() {
+                        timeline_inner.prepare_retry(&transaction_id).await;
+                    }
+                }
             }
-            _ => {
-                info!("Refusing to send message, room is not joined");
-                timeline_inner
-                    .update_event_send_state(
-                        &msg.txn_id,
-                        EventSendState::SendingFailed {
-                            // FIXME: Probably not exactly right
-                            error: Arc::new(matrix_sdk::Error::InconsistentState),
-                        },
-                    )
-                    .await;
+            RoomSendQueueUpdate::SentEvent { transaction_id, event_id } => {
+                let send_state = EventSendState::Sent { event_id };
+                timeline_inner.update_event_send_state(&transaction_id, send_state).await;
             }
         }
-    } else {
-        queue.push_back(msg);
     }
+
+    info!("Send queue updates stream closed");
 }
 
-async fn handle_task_ready(
-    result: SendMessageResult,
-    send_task: &mut SendMessageTask,
-    queue: &mut VecDeque<LocalMessage>,
-    timeline_inner: &TimelineInner,
-) {
-    match result {
-        SendMessageResult::Success { room } => {
-            if let Some(msg) = queue.pop_front() {
-                send_task.start(room, timeline_inner.clone(), msg);
-            }
-        }
-        SendMessageResult::SendingFailed => {
-            // Timeline items are marked as failed / cancelled in this case.
-            // Clear the timeline and wait for the user to explicitly retry.
-            queue.clear();
+/// Reconcile the local echoes of the timeline with the requests of the send
+/// queue, after some updates of the queue were missed.
+async fn resync_local_echoes(timeline_inner: &TimelineInner, send_queue: &RoomSendQueue) {
+    let requests = match send_queue.local_echoes().await {
+        Ok(requests) => requests,
+        Err(e) => {
+            error!("Failed to load the local echoes from the send queue: {e}");
+            return;
         }
-        SendMessageResult::TaskError { join_error, txn_id } => {
-            error!("Message-sending task failed: {join_error}");
-            queue.clear();
-
-            let send_state = EventSendState::SendingFailed {
-                // FIXME: Probably not exactly right
-                error: Arc::new(matrix_sdk::Error::InconsistentState),
-            };
-            timeline_inner.update_event_send_state(&txn_id, send_state).await;
+    };
+
+    let unsent: HashMap<_, _> = timeline_inner.unsent_local_echoes().await.into_iter().collect();
+
+    // The requests that aren't in the queue anymore were either cancelled, or
+    // sent and their remote echo will come with the sync.
+    for txn_id in unsent.keys() {
+        if !requests.iter().any(|request| request.transaction_id == *txn_id) {
+            timeline_inner.discard_local_echo(txn_id).await;
         }
     }
-}
-
-/// Result of [`SendMessageTask`].
-enum SendMessageResult {
-    /// The message was sent successfully, and the local echo was updated to
-    /// indicate this.
-    Success {
-        /// The joined room object, used to start sending of the next message
-        /// in the queue, if it isn't empty.
-        room: room::Joined,
-    },
-    /// Sending failed, and the local echo was updated to indicate this.
-    SendingFailed,
-    /// The [`SendMessageTask`] failed, likely due to a panic.
-    ///
-    /// This means that the timeline item was likely not updated yet, which thus
-    /// becomes the responsibility of the code observing this result.
-    TaskError {
-        /// The error with which the task failed.
-        join_error: JoinError,
-        /// The transaction ID of the message that was being sent by the task.
-        txn_id: OwnedTransactionId,
-    },
-}
 
-/// Future that tracks the process of an event-sending background task, if one
-/// is currently active for a given message-sending queue.
-enum SendMessageTask {
-    /// No background task is active right now.
-    Idle,
-    /// A background task has been spawned, we're waiting for its result.
-    Running {
-        /// The transaction ID of the message that is being sent.
-        txn_id: OwnedTransactionId,
-        /// Handle to the task itself.
-        join_handle: JoinHandle<Option<room::Joined>>,
-    },
-}
+    let mut wedged = Vec::new();
 
-impl SendMessageTask {
-    #[must_use]
-    fn is_idle(&self) -> bool {
-        matches!(self, Self::Idle)
-    }
+    for request in requests {
+        match unsent.get(&request.transaction_id) {
+            None => {
+                if request.is_wedged {
+                    wedged.push(request.transaction_id.clone());
+                }
 
-    fn start(&mut self, room: room::Joined, timeline_inner: TimelineInner, msg: LocalMessage) {
-        debug!("Spawning message-sending task");
-        let txn_id = msg.txn_id.clone();
-        let join_handle = spawn(async move {
-            let result = room.send(msg.content, Some(&msg.txn_id)).await;
-            let (room, send_state) = match result {
-                Ok(response) => (Some(room), EventSendState::Sent { event_id: response.event_id }),
-                Err(error) => (None, EventSendState::SendingFailed { error: Arc::new(error) }),
-            };
-
-            timeline_inner.update_event_send_state(&msg.txn_id, send_state).await;
-            room
-        });
-        *self = Self::Running { txn_id, join_handle };
+                add_local_echo(timeline_inner, request).await;
+            }
+            Some(EventSendState::NotSentYet) if request.is_wedged => {
+                wedged.push(request.transaction_id);
+            }
+            Some(EventSendState::SendingFailed { .. }) if !request.is_wedged => {
+                // The request was unwedged.
+                if timeline_inner.prepare_edit_retry(&request.transaction_id).await.is_none() {
+                    timeline_inner.prepare_retry(&request.transaction_id).await;
+                }
+            }
+            Some(_) => {}
+        }
     }
 
-    fn reset(&mut self) {
-        *self = Self::Idle;
+    // Like when restoring the local echoes, mark the events as failed once all
+    // the local echoes are in the timeline.
+    for txn_id in wedged {
+        let send_state = EventSendState::SendingFailed {
+            error: Arc::new(RoomSendQueueError::UnknownFailure.into()),
+        };
+        timeline_inner.update_event_send_state(&txn_id, send_state).await;
     }
 }
 
-impl Future for SendMessageTask {
-    type Output = SendMessageResult;
-
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match &mut *self {
-            SendMessageTask::Idle => Poll::Pending,
-            SendMessageTask::Running { txn_id, join_handle } => {
-                Pin::new(join_handle).poll(cx).map(|result| {
-                    let txn_id = mem::replace(txn_id, OwnedTransactionId::from(""));
-                    if txn_id.as_str().is_empty() {
-                        warn!("SendMessageTask polled after returning Poll::Ready!");
-                    }
+async fn add_local_echo(timeline_inner: &TimelineInner, request: QueuedRequest) {
+    // Only events have a local echo for now.
+    let QueuedRequestKind::Event { content } = request.kind else {
+        return;
+    };
 
-                    match result {
-                        Ok(Some(room)) => SendMessageResult::Success { room },
-                        Ok(None) => SendMessageResult::SendingFailed,
-                        Err(join_error) => SendMessageResult::TaskError { join_error, txn_id },
-                    }
-                })
-            }
-        }
+    match content.deserialize() {
+        Ok(content) => timeline_inner.handle_local_event(request.transaction_id, content).await,
+        Err(e) => error!(txn_id = ?request.transaction_id, "Failed to deserialize local echo: {e}"),
     }
 }
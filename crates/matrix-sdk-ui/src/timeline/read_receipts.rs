@@ -24,9 +24,13 @@ use ruma::{
 use tracing::{error, warn};
 
 use super::{
-    compare_events_positions, event_item::EventTimelineItemKind, inner::TimelineInnerState,
-    item::timeline_item, rfind_event_by_id, traits::RoomDataProvider, EventTimelineItem,
-    RelativePosition, TimelineItem,
+    compare_events_positions,
+    event_item::EventTimelineItemKind,
+    inner::{TimelineFocus, TimelineInnerState},
+    item::timeline_item,
+    rfind_event_by_id,
+    traits::RoomDataProvider,
+    EventTimelineItem, RelativePosition, TimelineItem,
 };
 
 struct FullReceipt<'a> {
@@ -56,6 +60,7 @@ impl TimelineInnerState {
         &mut self,
         receipt_event_content: ReceiptEventContent,
         own_user_id: &UserId,
+        focus: &TimelineFocus,
     ) {
         for (event_id, receipt_types) in receipt_event_content.0 {
             for (receipt_type, receipts) in receipt_types {
@@ -65,7 +70,7 @@ impl TimelineInnerState {
                 }
 
                 for (user_id, receipt) in receipts {
-                    if receipt.thread != ReceiptThread::Unthreaded {
+                    if !focus.accepts_receipt_thread(&receipt.thread) {
                         continue;
                     }
 
@@ -95,13 +100,15 @@ impl TimelineInnerState {
         }
     }
 
-    /// Load the read receipts from the store for the given event ID.
+    /// Load the read receipts in the given thread from the store for the given
+    /// event ID.
     pub(super) async fn load_read_receipts_for_event<P: RoomDataProvider>(
         &mut self,
         event_id: &EventId,
+        thread: ReceiptThread,
         room_data_provider: &P,
     ) -> IndexMap<OwnedUserId, Receipt> {
-        let read_receipts = room_data_provider.read_receipts_for_event(event_id).await;
+        let read_receipts = room_data_provider.read_receipts_for_event(event_id, thread).await;
 
         // Filter out receipts for our own user.
         let own_user_id = room_data_provider.own_user_id();
@@ -123,12 +130,15 @@ impl TimelineInnerState {
         read_receipts
     }
 
-    /// Get the unthreaded receipt of the given type for the given user in the
-    /// timeline.
+    /// Get the receipt of the given type for the given user in the timeline.
+    ///
+    /// If the receipt is not known by the timeline, the receipt in the given
+    /// thread is loaded from the store.
     pub(super) async fn user_receipt(
         &self,
         user_id: &UserId,
         receipt_type: ReceiptType,
+        thread: &ReceiptThread,
         room: &room::Common,
     ) -> Option<(OwnedEventId, Receipt)> {
         if let Some(receipt) = self
@@ -140,12 +150,10 @@ impl TimelineInnerState {
             return Some(receipt);
         }
 
-        room.user_receipt(receipt_type.clone(), ReceiptThread::Unthreaded, user_id)
-            .await
-            .unwrap_or_else(|e| {
-                error!("Could not get user read receipt of type {receipt_type:?}: {e}");
-                None
-            })
+        room.user_receipt(receipt_type.clone(), thread.clone(), user_id).await.unwrap_or_else(|e| {
+            error!("Could not get user read receipt of type {receipt_type:?}: {e}");
+            None
+        })
     }
 
     /// Get the latest read receipt for the given user.
@@ -154,10 +162,12 @@ impl TimelineInnerState {
     pub(super) async fn latest_user_read_receipt(
         &self,
         user_id: &UserId,
+        thread: &ReceiptThread,
         room: &room::Common,
     ) -> Option<(OwnedEventId, Receipt)> {
-        let public_read_receipt = self.user_receipt(user_id, ReceiptType::Read, room).await;
-        let private_read_receipt = self.user_receipt(user_id, ReceiptType::ReadPrivate, room).await;
+        let public_read_receipt = self.user_receipt(user_id, ReceiptType::Read, thread, room).await;
+        let private_read_receipt =
+            self.user_receipt(user_id, ReceiptType::ReadPrivate, thread, room).await;
 
         // If we only have one, return it.
         let Some((pub_event_id, pub_receipt)) = &public_read_receipt else {
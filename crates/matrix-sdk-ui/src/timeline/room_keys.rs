@@ -0,0 +1,63 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeSet;
+
+use futures_core::Stream;
+use futures_util::{pin_mut, StreamExt};
+use matrix_sdk::encryption::RoomKeysUpdate;
+use tracing::{debug_span, trace, Instrument};
+
+use super::inner::TimelineInner;
+
+/// Retry decrypting the events of the timeline each time room keys for its
+/// room are received.
+///
+/// This covers keys received over to-device messages, forwarded keys, keys
+/// imported from a file and keys downloaded from the key backup. When updates
+/// might have been missed, the decryption of all the events is retried.
+pub(super) async fn handle_room_keys_received(
+    inner: TimelineInner,
+    room_keys_stream: impl Stream<Item = RoomKeysUpdate>,
+) {
+    pin_mut!(room_keys_stream);
+
+    while let Some(update) = room_keys_stream.next().await {
+        let room = inner.room();
+        let session_ids = match update {
+            RoomKeysUpdate::Received(room_keys) => {
+                let session_ids: BTreeSet<_> = room_keys
+                    .into_iter()
+                    .filter(|info| info.room_id == room.room_id())
+                    .map(|info| info.session_id)
+                    .collect();
+
+                if session_ids.is_empty() {
+                    continue;
+                }
+
+                Some(session_ids)
+            }
+            RoomKeysUpdate::Unknown => None,
+        };
+
+        let span = debug_span!("handle_room_keys_received", room_id = ?room.room_id());
+        async {
+            trace!(?session_ids, "Received room keys, retrying decryption");
+            inner.retry_event_decryption(room, session_ids).await;
+        }
+        .instrument(span)
+        .await;
+    }
+}
@@ -12,11 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+
 use assert_matches::assert_matches;
 use eyeball_im::VectorDiff;
 use matrix_sdk_test::async_test;
 use ruma::{
-    assign,
+    assign, event_id,
     events::{
         relation::Replacement,
         room::message::{
@@ -30,7 +32,7 @@ use serde_json::json;
 use stream_assert::assert_next_matches;
 
 use super::{TestTimeline, ALICE};
-use crate::timeline::TimelineItemContent;
+use crate::timeline::{EventSendState, TimelineItemContent};
 
 #[async_test]
 async fn live_redacted() {
@@ -160,3 +162,131 @@ async fn aggregated_sanitized() {
     assert_eq!(text.body, "!!edited!! **better** message");
     assert_eq!(text.formatted.as_ref().unwrap().body, " <strong>better</strong> message");
 }
+
+#[async_test]
+async fn local_edit_reverted_on_cancel() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    timeline
+        .handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("original"))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let event_id = item.event_id().unwrap();
+
+    let edit = assign!(RoomMessageEventContent::text_plain("* edited"), {
+        relates_to: Some(message::Relation::Replacement(Replacement::new(
+            event_id.to_owned(),
+            MessageType::text_plain("edited").into(),
+        ))),
+    });
+    let txn_id = timeline.handle_local_event(edit.into()).await;
+
+    // The local echo of the edit is applied immediately.
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    let message = assert_matches!(item.content(), TimelineItemContent::Message(msg) => msg);
+    assert_eq!(message.body(), "edited");
+    assert!(message.is_edited());
+
+    // The edit can be retried after it failed to be sent.
+    let send_state =
+        EventSendState::SendingFailed { error: Arc::new(matrix_sdk::Error::InconsistentState) };
+    timeline.inner.update_event_send_state(&txn_id, send_state).await;
+    assert_matches!(
+        timeline.inner.local_echo_send_state(&txn_id).await,
+        Some(EventSendState::SendingFailed { .. })
+    );
+    assert!(timeline.inner.prepare_edit_retry(&txn_id).await.is_some());
+    assert_matches!(
+        timeline.inner.local_echo_send_state(&txn_id).await,
+        Some(EventSendState::NotSentYet)
+    );
+
+    // Cancelling the edit reverts it.
+    assert!(timeline.inner.discard_local_echo(&txn_id).await);
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    let message = assert_matches!(item.content(), TimelineItemContent::Message(msg) => msg);
+    assert_eq!(message.body(), "original");
+    assert!(!message.is_edited());
+    assert_matches!(timeline.inner.local_echo_send_state(&txn_id).await, None);
+}
+
+#[async_test]
+async fn cancel_one_of_several_local_edits() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    timeline
+        .handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("original"))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let event_id = item.event_id().unwrap().to_owned();
+
+    let edit = |body: &str| {
+        assign!(RoomMessageEventContent::text_plain(format!("* {body}")), {
+            relates_to: Some(message::Relation::Replacement(Replacement::new(
+                event_id.clone(),
+                MessageType::text_plain(body).into(),
+            ))),
+        })
+    };
+
+    let first_txn_id = timeline.handle_local_event(edit("first").into()).await;
+    assert_next_matches!(stream, VectorDiff::Set { index: 0, .. });
+    let second_txn_id = timeline.handle_local_event(edit("second").into()).await;
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    let message = assert_matches!(item.content(), TimelineItemContent::Message(msg) => msg);
+    assert_eq!(message.body(), "second");
+
+    // Cancelling the first edit keeps the second one displayed.
+    assert!(timeline.inner.discard_local_echo(&first_txn_id).await);
+    let items = timeline.inner.items().await;
+    let item = items.last().unwrap().as_event().unwrap();
+    let message = assert_matches!(item.content(), TimelineItemContent::Message(msg) => msg);
+    assert_eq!(message.body(), "second");
+
+    // Cancelling the second edit reverts to the original content, not to the
+    // first edit.
+    assert!(timeline.inner.discard_local_echo(&second_txn_id).await);
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    let message = assert_matches!(item.content(), TimelineItemContent::Message(msg) => msg);
+    assert_eq!(message.body(), "original");
+    assert!(!message.is_edited());
+}
+
+#[async_test]
+async fn cancel_local_edit_superseded_by_sent_edit() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    timeline
+        .handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("original"))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let event_id = item.event_id().unwrap().to_owned();
+
+    let edit = |body: &str| {
+        assign!(RoomMessageEventContent::text_plain(format!("* {body}")), {
+            relates_to: Some(message::Relation::Replacement(Replacement::new(
+                event_id.clone(),
+                MessageType::text_plain(body).into(),
+            ))),
+        })
+    };
+
+    let first_txn_id = timeline.handle_local_event(edit("first").into()).await;
+    assert_next_matches!(stream, VectorDiff::Set { index: 0, .. });
+    let second_txn_id = timeline.handle_local_event(edit("second").into()).await;
+    assert_next_matches!(stream, VectorDiff::Set { index: 0, .. });
+
+    // The second edit is sent before the first one.
+    let send_state = EventSendState::Sent { event_id: event_id!("$second").to_owned() };
+    timeline.inner.update_event_send_state(&second_txn_id, send_state).await;
+
+    // Cancelling the first edit doesn't revert the sent one.
+    assert!(timeline.inner.discard_local_echo(&first_txn_id).await);
+    let items = timeline.inner.items().await;
+    let item = items.last().unwrap().as_event().unwrap();
+    let message = assert_matches!(item.content(), TimelineItemContent::Message(msg) => msg);
+    assert_eq!(message.body(), "second");
+}
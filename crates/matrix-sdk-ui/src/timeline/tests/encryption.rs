@@ -14,11 +14,18 @@
 
 #![cfg(not(target_arch = "wasm32"))]
 
-use std::{io::Cursor, iter};
+use std::{
+    io::Cursor,
+    iter,
+    sync::{Arc, Mutex},
+};
 
 use assert_matches::assert_matches;
 use eyeball_im::VectorDiff;
-use matrix_sdk::crypto::{decrypt_room_key_export, OlmMachine};
+use matrix_sdk::{
+    crypto::{decrypt_room_key_export, OlmMachine},
+    deserialized_responses::UnableToDecryptReason,
+};
 use matrix_sdk_test::async_test;
 use ruma::{
     assign,
@@ -31,7 +38,21 @@ use ruma::{
 use stream_assert::assert_next_matches;
 
 use super::{TestTimeline, BOB};
-use crate::timeline::{EncryptedMessage, TimelineItemContent};
+use crate::{
+    timeline::{inner::TimelineInnerSettings, EncryptedMessage, TimelineItemContent},
+    unable_to_decrypt_hook::{UnableToDecryptHook, UnableToDecryptInfo, UtdHookManager},
+};
+
+#[derive(Debug, Default)]
+struct DummyUtdHook {
+    utds: Mutex<Vec<UnableToDecryptInfo>>,
+}
+
+impl UnableToDecryptHook for DummyUtdHook {
+    fn on_utd(&self, info: UnableToDecryptInfo) {
+        self.utds.lock().unwrap().push(info);
+    }
+}
 
 #[async_test]
 async fn retry_message_decryption() {
@@ -50,7 +71,11 @@ async fn retry_message_decryption() {
         HztoSJUr/2Y\n\
         -----END MEGOLM SESSION DATA-----";
 
-    let timeline = TestTimeline::new();
+    let hook = Arc::new(DummyUtdHook::default());
+    let timeline = TestTimeline::new().with_settings(TimelineInnerSettings {
+        unable_to_decrypt_hook: Some(Arc::new(UtdHookManager::new(hook.clone()))),
+        ..Default::default()
+    });
     let mut stream = timeline.subscribe().await;
 
     timeline
@@ -83,13 +108,21 @@ async fn retry_message_decryption() {
     let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
     let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
     let event = item.as_event().unwrap();
-    let session_id = assert_matches!(
+    let (session_id, reason) = assert_matches!(
         event.content(),
         TimelineItemContent::UnableToDecrypt(
-            EncryptedMessage::MegolmV1AesSha2 { session_id, .. },
-        ) => session_id
+            EncryptedMessage::MegolmV1AesSha2 { session_id, reason, .. },
+        ) => (session_id, reason)
     );
     assert_eq!(session_id, SESSION_ID);
+    assert_eq!(*reason, UnableToDecryptReason::Unknown);
+
+    {
+        let utds = hook.utds.lock().unwrap();
+        assert_eq!(utds.len(), 1);
+        assert_eq!(utds[0].event_id, event.event_id().unwrap());
+        assert!(utds[0].time_to_decrypt.is_none());
+    }
 
     let own_user_id = user_id!("@example:morheus.localhost");
     let exported_keys = decrypt_room_key_export(Cursor::new(SESSION_KEY), "1234").unwrap();
@@ -114,6 +147,11 @@ async fn retry_message_decryption() {
     let text = assert_matches!(event.content(), TimelineItemContent::Message(msg) => msg.body());
     assert_eq!(text, "It's a secret to everybody");
     assert!(!event.is_highlighted());
+
+    let utds = hook.utds.lock().unwrap();
+    assert_eq!(utds.len(), 2);
+    assert_eq!(utds[1].event_id, event.event_id().unwrap());
+    assert!(utds[1].time_to_decrypt.is_some());
 }
 
 #[async_test]
@@ -28,7 +28,7 @@ use eyeball_im::VectorDiff;
 use futures_core::Stream;
 use futures_util::{FutureExt, StreamExt};
 use indexmap::IndexMap;
-use matrix_sdk::deserialized_responses::{SyncTimelineEvent, TimelineEvent};
+use matrix_sdk::deserialized_responses::SyncTimelineEvent;
 use once_cell::sync::Lazy;
 use ruma::{
     events::{
@@ -64,10 +64,12 @@ mod edit;
 mod encryption;
 mod event_filter;
 mod invalid;
+mod polls;
 mod reaction_group;
 mod reactions;
 mod read_receipts;
 mod redaction;
+mod threads;
 mod virt;
 
 static ALICE: Lazy<&UserId> = Lazy::new(|| user_id!("@alice:server.name"));
@@ -75,7 +77,7 @@ static BOB: Lazy<&UserId> = Lazy::new(|| user_id!("@bob:other.server"));
 
 fn sync_timeline_event(event: JsonValue) -> SyncTimelineEvent {
     let event = serde_json::from_value(event).unwrap();
-    SyncTimelineEvent { event, encryption_info: None, push_actions: Vec::default() }
+    SyncTimelineEvent::new(event)
 }
 
 struct TestTimeline {
@@ -206,7 +208,7 @@ impl TestTimeline {
     }
 
     async fn handle_live_event(&self, event: Raw<AnySyncTimelineEvent>) {
-        let event = SyncTimelineEvent { event, encryption_info: None, push_actions: vec![] };
+        let event = SyncTimelineEvent::new(event);
         self.inner.handle_live_event(event).await
     }
 
@@ -227,7 +229,7 @@ impl TestTimeline {
     }
 
     async fn handle_back_paginated_custom_event(&self, event: JsonValue) {
-        let timeline_event = TimelineEvent::new(Raw::new(&event).unwrap().cast());
+        let timeline_event = SyncTimelineEvent::new(Raw::new(&event).unwrap().cast());
         self.inner.handle_back_paginated_event(timeline_event).await;
     }
 
@@ -414,7 +416,11 @@ impl RoomDataProvider for TestRoomDataProvider {
         None
     }
 
-    async fn read_receipts_for_event(&self, _event_id: &EventId) -> IndexMap<OwnedUserId, Receipt> {
+    async fn read_receipts_for_event(
+        &self,
+        _event_id: &EventId,
+        _thread: ReceiptThread,
+    ) -> IndexMap<OwnedUserId, Receipt> {
         IndexMap::new()
     }
 
@@ -0,0 +1,230 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches::assert_matches;
+use eyeball_im::VectorDiff;
+use matrix_sdk_test::async_test;
+use ruma::{event_id, events::poll::start::PollKind, EventId, UserId};
+use serde_json::{json, Value as JsonValue};
+use stream_assert::assert_next_matches;
+
+use super::{TestTimeline, ALICE, BOB};
+use crate::timeline::{PollAnswer, PollState, TimelineItemContent};
+
+impl TestTimeline {
+    fn make_poll_start_event(&self, sender: &UserId, event_id: &EventId) -> JsonValue {
+        json!({
+            "type": "org.matrix.msc3381.poll.start",
+            "content": {
+                "org.matrix.msc3381.poll.start": {
+                    "question": { "org.matrix.msc1767.text": "Pizza or pasta?" },
+                    "kind": "org.matrix.msc3381.poll.disclosed",
+                    "max_selections": 1,
+                    "answers": [
+                        { "id": "pizza", "org.matrix.msc1767.text": "Pizza" },
+                        { "id": "pasta", "org.matrix.msc1767.text": "Pasta" },
+                    ],
+                },
+                "org.matrix.msc1767.text": "Pizza or pasta?\n1. Pizza\n2. Pasta",
+            },
+            "event_id": event_id,
+            "sender": sender,
+            "origin_server_ts": self.next_server_ts(),
+        })
+    }
+
+    fn make_poll_response_event(
+        &self,
+        sender: &UserId,
+        poll_start_id: &EventId,
+        answers: &[&str],
+    ) -> JsonValue {
+        json!({
+            "type": "org.matrix.msc3381.poll.response",
+            "content": {
+                "org.matrix.msc3381.poll.response": { "answers": answers },
+                "m.relates_to": { "rel_type": "m.reference", "event_id": poll_start_id },
+            },
+            "event_id": EventId::new(ruma::server_name!("dummy.server")),
+            "sender": sender,
+            "origin_server_ts": self.next_server_ts(),
+        })
+    }
+
+    fn make_poll_end_event(&self, sender: &UserId, poll_start_id: &EventId) -> JsonValue {
+        json!({
+            "type": "org.matrix.msc3381.poll.end",
+            "content": {
+                "org.matrix.msc3381.poll.end": {},
+                "org.matrix.msc1767.text": "The poll has ended",
+                "m.relates_to": { "rel_type": "m.reference", "event_id": poll_start_id },
+            },
+            "event_id": EventId::new(ruma::server_name!("dummy.server")),
+            "sender": sender,
+            "origin_server_ts": self.next_server_ts(),
+        })
+    }
+}
+
+fn assert_poll(item: &crate::timeline::EventTimelineItem) -> &PollState {
+    assert_matches!(item.content(), TimelineItemContent::Poll(poll_state) => poll_state)
+}
+
+#[async_test]
+async fn poll_votes_are_aggregated() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+    let poll_id = event_id!("$poll");
+
+    timeline.handle_live_custom_event(timeline.make_poll_start_event(&ALICE, poll_id)).await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let poll_state = assert_poll(&item);
+    assert_eq!(poll_state.question(), "Pizza or pasta?");
+    assert_eq!(*poll_state.kind(), PollKind::Disclosed);
+    assert_eq!(poll_state.max_selections(), 1);
+    assert_eq!(
+        poll_state.answers(),
+        vec![
+            PollAnswer { id: "pizza".to_owned(), text: "Pizza".to_owned() },
+            PollAnswer { id: "pasta".to_owned(), text: "Pasta".to_owned() },
+        ]
+    );
+    assert!(!poll_state.is_ended());
+
+    timeline
+        .handle_live_custom_event(timeline.make_poll_response_event(&BOB, poll_id, &["pizza"]))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    let votes = assert_poll(&item).votes();
+    assert_eq!(votes["pizza"], vec![BOB.to_owned()]);
+    assert!(votes["pasta"].is_empty());
+
+    // Only the latest response of a user counts, and only up to the maximum
+    // number of selections.
+    timeline
+        .handle_live_custom_event(timeline.make_poll_response_event(
+            &BOB,
+            poll_id,
+            &["pasta", "pizza"],
+        ))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    let poll_state = assert_poll(&item);
+    let votes = poll_state.votes();
+    assert!(votes["pizza"].is_empty());
+    assert_eq!(votes["pasta"], vec![BOB.to_owned()]);
+    assert_eq!(poll_state.user_selection(&BOB), vec!["pasta".to_owned()]);
+    assert!(poll_state.user_selection(&ALICE).is_empty());
+
+    // Unknown answers are ignored.
+    timeline
+        .handle_live_custom_event(timeline.make_poll_response_event(&ALICE, poll_id, &["salad"]))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    let votes = assert_poll(&item).votes();
+    assert!(votes["pizza"].is_empty());
+    assert_eq!(votes["pasta"], vec![BOB.to_owned()]);
+}
+
+#[async_test]
+async fn ended_poll_ignores_later_responses() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+    let poll_id = event_id!("$poll");
+
+    timeline.handle_live_custom_event(timeline.make_poll_start_event(&ALICE, poll_id)).await;
+    let _item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    // Only the sender of the poll can end it.
+    timeline.handle_live_custom_event(timeline.make_poll_end_event(&BOB, poll_id)).await;
+    assert_eq!(timeline.len().await, 2);
+
+    timeline.handle_live_custom_event(timeline.make_poll_end_event(&ALICE, poll_id)).await;
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    assert!(assert_poll(&item).is_ended());
+
+    timeline
+        .handle_live_custom_event(timeline.make_poll_response_event(&BOB, poll_id, &["pizza"]))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    assert!(assert_poll(&item).votes()["pizza"].is_empty());
+}
+
+#[async_test]
+async fn earliest_poll_end_wins() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+    let poll_id = event_id!("$poll");
+
+    let start_event = timeline.make_poll_start_event(&ALICE, poll_id);
+    let early_end_event = timeline.make_poll_end_event(&ALICE, poll_id);
+    let late_end_event = timeline.make_poll_end_event(&ALICE, poll_id);
+    let early_end_ts = early_end_event["origin_server_ts"].as_u64().unwrap();
+
+    timeline.handle_live_custom_event(start_event).await;
+    let _item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    // The end events are received out of order.
+    timeline.handle_live_custom_event(late_end_event).await;
+    let _item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+
+    timeline.handle_live_custom_event(early_end_event).await;
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    assert_eq!(u64::from(assert_poll(&item).end_time().unwrap().0), early_end_ts);
+}
+
+#[async_test]
+async fn pending_poll_events_are_applied() {
+    let timeline = TestTimeline::new();
+    let poll_id = event_id!("$poll");
+
+    let start_event = timeline.make_poll_start_event(&ALICE, poll_id);
+    let response_event = timeline.make_poll_response_event(&BOB, poll_id, &["pasta"]);
+    let end_event = timeline.make_poll_end_event(&ALICE, poll_id);
+
+    // With back-pagination, the responses and end event are received before
+    // the start event.
+    timeline.handle_back_paginated_custom_event(end_event).await;
+    timeline.handle_back_paginated_custom_event(response_event).await;
+    timeline.handle_back_paginated_custom_event(start_event).await;
+
+    let items = timeline.inner.items().await;
+    assert_eq!(items.len(), 2);
+    let poll_state = assert_poll(items[1].as_event().unwrap());
+    assert!(poll_state.is_ended());
+    assert_eq!(poll_state.votes()["pasta"], vec![BOB.to_owned()]);
+}
+
+#[async_test]
+async fn pending_poll_end_from_other_user_is_ignored() {
+    let timeline = TestTimeline::new();
+    let poll_id = event_id!("$poll");
+
+    let start_event = timeline.make_poll_start_event(&ALICE, poll_id);
+    let alice_end_event = timeline.make_poll_end_event(&ALICE, poll_id);
+    let bob_end_event = timeline.make_poll_end_event(&BOB, poll_id);
+    let alice_end_ts = alice_end_event["origin_server_ts"].as_u64().unwrap();
+
+    // The end event of Bob is received first, but it doesn't prevent the end
+    // event of the sender of the poll from being applied.
+    timeline.handle_back_paginated_custom_event(bob_end_event).await;
+    timeline.handle_back_paginated_custom_event(alice_end_event).await;
+    timeline.handle_back_paginated_custom_event(start_event).await;
+
+    let items = timeline.inner.items().await;
+    let poll_state = assert_poll(items[1].as_event().unwrap());
+    assert!(poll_state.is_ended());
+    assert_eq!(u64::from(poll_state.end_time().unwrap().0), alice_end_ts);
+}
@@ -0,0 +1,233 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches::assert_matches;
+use eyeball_im::VectorDiff;
+use matrix_sdk_test::async_test;
+use ruma::{
+    assign, event_id,
+    events::{
+        receipt::{ReceiptThread, ReceiptType},
+        relation::Thread,
+        room::message::{Relation, RoomMessageEventContent},
+    },
+    EventId,
+};
+use serde_json::json;
+use stream_assert::assert_next_matches;
+
+use super::{assert_event_is_updated, assert_no_more_updates, TestTimeline, ALICE, BOB};
+use crate::timeline::{
+    inner::{TimelineFocus, TimelineInnerSettings},
+    ThreadSummary, TimelineItemContent,
+};
+
+fn thread_reply(body: &str, root_event_id: &EventId) -> RoomMessageEventContent {
+    assign!(RoomMessageEventContent::text_plain(body), {
+        relates_to: Some(Relation::Thread(
+            Thread::plain(root_event_id.to_owned(), root_event_id.to_owned()),
+        )),
+    })
+}
+
+#[async_test]
+async fn live_reply_updates_thread_summary() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    let root_event_id = event_id!("$root");
+    let reply_event_id = event_id!("$reply");
+
+    timeline
+        .handle_live_custom_event(timeline.make_message_event_with_id(
+            &BOB,
+            RoomMessageEventContent::text_plain("Let's talk about it"),
+            root_event_id.to_owned(),
+        ))
+        .await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let root = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_matches!(root.as_event().unwrap().thread_summary(), None);
+
+    timeline
+        .handle_live_custom_event(timeline.make_message_event_with_id(
+            &ALICE,
+            thread_reply("Sure", root_event_id),
+            reply_event_id.to_owned(),
+        ))
+        .await;
+
+    let reply = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let message = assert_matches!(
+        reply.as_event().unwrap().content(),
+        TimelineItemContent::Message(message) => message
+    );
+    assert_eq!(message.thread_root(), Some(root_event_id));
+
+    let root = assert_event_is_updated(&mut stream, root_event_id, 1).await;
+    let thread_summary = root.thread_summary().unwrap();
+    assert_eq!(thread_summary.num_replies, 1);
+    assert_eq!(thread_summary.latest_reply.as_deref(), Some(reply_event_id));
+    assert!(thread_summary.user_participated);
+
+    assert_no_more_updates(&mut stream).await;
+}
+
+#[async_test]
+async fn bundled_thread_summary() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    timeline
+        .handle_live_custom_event(json!({
+            "type": "m.room.message",
+            "content": {
+                "msgtype": "m.text",
+                "body": "Let's talk about it",
+            },
+            "event_id": "$root",
+            "sender": *BOB,
+            "origin_server_ts": 10,
+            "unsigned": {
+                "m.relations": {
+                    "m.thread": {
+                        "latest_event": {
+                            "type": "m.room.message",
+                            "content": {
+                                "msgtype": "m.text",
+                                "body": "Sure",
+                            },
+                            "event_id": "$latest_reply",
+                            "room_id": "!my_room:server.name",
+                            "sender": *BOB,
+                            "origin_server_ts": 12,
+                        },
+                        "count": 2,
+                        "current_user_participated": false,
+                    },
+                },
+            },
+        }))
+        .await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let root = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(
+        root.as_event().unwrap().thread_summary(),
+        Some(&ThreadSummary {
+            num_replies: 2,
+            latest_reply: Some(event_id!("$latest_reply").to_owned()),
+            user_participated: false,
+        })
+    );
+}
+
+#[async_test]
+async fn thread_focus_only_contains_thread_events() {
+    let root_event_id = event_id!("$root");
+    let timeline = TestTimeline::new().with_settings(TimelineInnerSettings {
+        focus: TimelineFocus::Thread { root_event_id: root_event_id.to_owned() },
+        ..Default::default()
+    });
+    let mut stream = timeline.subscribe_events().await;
+
+    timeline
+        .handle_live_custom_event(timeline.make_message_event_with_id(
+            &BOB,
+            RoomMessageEventContent::text_plain("Let's talk about it"),
+            root_event_id.to_owned(),
+        ))
+        .await;
+    timeline
+        .handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("Unrelated"))
+        .await;
+    timeline
+        .handle_live_message_event(
+            &BOB,
+            thread_reply("In another thread", event_id!("$other_root")),
+        )
+        .await;
+    timeline.handle_live_message_event(&ALICE, thread_reply("Sure", root_event_id)).await;
+
+    let root = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(root.event_id(), Some(root_event_id));
+
+    let reply = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let message =
+        assert_matches!(reply.content(), TimelineItemContent::Message(message) => message);
+    assert_eq!(message.body(), "Sure");
+
+    // The thread summary of the root is updated.
+    let root = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    assert_eq!(root.thread_summary().unwrap().num_replies, 1);
+
+    assert_eq!(timeline.inner.items().await.len(), 3);
+}
+
+#[async_test]
+async fn thread_focus_read_receipts() {
+    let root_event_id = event_id!("$root");
+    let reply_event_id = event_id!("$reply");
+    let timeline = TestTimeline::new().with_settings(TimelineInnerSettings {
+        track_read_receipts: true,
+        focus: TimelineFocus::Thread { root_event_id: root_event_id.to_owned() },
+        ..Default::default()
+    });
+    let mut stream = timeline.subscribe().await;
+
+    timeline
+        .handle_live_custom_event(timeline.make_message_event_with_id(
+            &ALICE,
+            RoomMessageEventContent::text_plain("Let's talk about it"),
+            root_event_id.to_owned(),
+        ))
+        .await;
+    timeline
+        .handle_live_custom_event(timeline.make_message_event_with_id(
+            &ALICE,
+            thread_reply("Anyone?", root_event_id),
+            reply_event_id.to_owned(),
+        ))
+        .await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _root = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _reply = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _root = assert_event_is_updated(&mut stream, root_event_id, 1).await;
+
+    // A receipt in the main timeline is ignored.
+    timeline
+        .handle_read_receipts([(
+            reply_event_id.to_owned(),
+            ReceiptType::Read,
+            BOB.to_owned(),
+            ReceiptThread::Main,
+        )])
+        .await;
+    assert_no_more_updates(&mut stream).await;
+
+    // A receipt in the thread is added.
+    timeline
+        .handle_read_receipts([(
+            reply_event_id.to_owned(),
+            ReceiptType::Read,
+            BOB.to_owned(),
+            ReceiptThread::Thread(root_event_id.to_owned()),
+        )])
+        .await;
+
+    let reply = assert_event_is_updated(&mut stream, reply_event_id, 2).await;
+    assert!(reply.read_receipts().get(*BOB).is_some());
+}
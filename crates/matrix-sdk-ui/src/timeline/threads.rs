@@ -0,0 +1,74 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ruma::{
+    events::{
+        relation::BundledThread,
+        room::{encrypted, message},
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent, SyncMessageLikeEvent,
+    },
+    EventId, OwnedEventId,
+};
+
+/// A summary of the replies to an event that is the root of a thread.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ThreadSummary {
+    /// The number of replies in the thread.
+    pub num_replies: u64,
+
+    /// The ID of the latest reply in the thread, if it is known.
+    pub latest_reply: Option<OwnedEventId>,
+
+    /// Whether the logged-in user sent a reply in the thread.
+    pub user_participated: bool,
+}
+
+impl ThreadSummary {
+    fn from_bundled(thread: &BundledThread) -> Self {
+        Self {
+            num_replies: thread.count.into(),
+            latest_reply: thread.latest_event.get_field("event_id").ok().flatten(),
+            user_participated: thread.current_user_participated,
+        }
+    }
+}
+
+/// Get the summary of the thread that the server bundled with the given
+/// event, if it is a thread root.
+pub(super) fn bundled_thread_summary(event: &AnySyncTimelineEvent) -> Option<ThreadSummary> {
+    let AnySyncTimelineEvent::MessageLike(event) = event else { return None };
+    event.relations().thread.as_deref().map(ThreadSummary::from_bundled)
+}
+
+/// Get the ID of the root of the thread the given event is a reply in, if
+/// any.
+pub(super) fn thread_root(event: &AnySyncTimelineEvent) -> Option<&EventId> {
+    match event {
+        AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+            SyncMessageLikeEvent::Original(ev),
+        )) => match &ev.content.relates_to {
+            Some(message::Relation::Thread(thread)) => Some(&thread.event_id),
+            _ => None,
+        },
+        // The relation of encrypted events is not encrypted, so we know in
+        // which thread they are even if we can't decrypt them.
+        AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
+            SyncMessageLikeEvent::Original(ev),
+        )) => match &ev.content.relates_to {
+            Some(encrypted::Relation::Thread(thread)) => Some(&thread.event_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
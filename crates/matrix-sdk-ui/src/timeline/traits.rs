@@ -66,7 +66,11 @@ impl RoomExt for room::Common {
 pub(super) trait RoomDataProvider: Clone + Send + Sync + 'static {
     fn own_user_id(&self) -> &UserId;
     async fn profile(&self, user_id: &UserId) -> Option<Profile>;
-    async fn read_receipts_for_event(&self, event_id: &EventId) -> IndexMap<OwnedUserId, Receipt>;
+    async fn read_receipts_for_event(
+        &self,
+        event_id: &EventId,
+        thread: ReceiptThread,
+    ) -> IndexMap<OwnedUserId, Receipt>;
     async fn push_rules_and_context(&self) -> Option<(Ruleset, PushConditionRoomCtx)>;
 }
 
@@ -96,8 +100,12 @@ impl RoomDataProvider for room::Common {
         }
     }
 
-    async fn read_receipts_for_event(&self, event_id: &EventId) -> IndexMap<OwnedUserId, Receipt> {
-        match self.event_receipts(ReceiptType::Read, ReceiptThread::Unthreaded, event_id).await {
+    async fn read_receipts_for_event(
+        &self,
+        event_id: &EventId,
+        thread: ReceiptThread,
+    ) -> IndexMap<OwnedUserId, Receipt> {
+        match self.event_receipts(ReceiptType::Read, thread, event_id).await {
             Ok(receipts) => receipts.into_iter().collect(),
             Err(e) => {
                 error!(?event_id, "Failed to get read receipts for event: {e}");
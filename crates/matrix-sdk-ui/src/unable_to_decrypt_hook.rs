@@ -0,0 +1,275 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! A hook to get notified about unable-to-decrypt (UTD) events, and about the
+//! ones that eventually got decrypted.
+//!
+//! A single [`UtdHookManager`] is meant to be shared by all the timelines of a
+//! client, so every UTD is reported once, no matter how many timelines display
+//! it.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use matrix_sdk::{deserialized_responses::UnableToDecryptReason, instant::Instant};
+use ruma::{EventId, OwnedEventId};
+
+/// A hook that gets notified about unable-to-decrypt (UTD) events.
+pub trait UnableToDecryptHook: std::fmt::Debug + Send + Sync {
+    /// Called every time a UTD is first seen, and a second time if it got
+    /// decrypted later on.
+    ///
+    /// In the latter case, [`UnableToDecryptInfo::time_to_decrypt`] is set.
+    fn on_utd(&self, info: UnableToDecryptInfo);
+}
+
+/// Information about an event that couldn't be decrypted.
+#[derive(Clone, Debug)]
+pub struct UnableToDecryptInfo {
+    /// The ID of the event that couldn't be decrypted.
+    pub event_id: OwnedEventId,
+
+    /// Why the event couldn't be decrypted, when it was first seen.
+    pub reason: UnableToDecryptReason,
+
+    /// If the event was eventually decrypted, how long it took from the moment
+    /// it was first seen as a UTD.
+    ///
+    /// `None` if the event hasn't been decrypted (yet).
+    pub time_to_decrypt: Option<Duration>,
+}
+
+/// The maximum number of UTDs waiting to be decrypted that are remembered.
+///
+/// Once reached, the oldest UTD is forgotten: it's unlikely to be decrypted
+/// anymore, and if it is, its late decryption isn't reported.
+const MAX_PENDING_UTDS: usize = 1000;
+
+/// The maximum number of UTDs that are remembered as reported, whether they
+/// were decrypted or not.
+///
+/// Once reached, the oldest UTD is forgotten, and would be reported again if it
+/// was seen again.
+const MAX_REPORTED_UTDS: usize = 10_000;
+
+/// The IDs of the UTDs that were reported, in the order they were reported.
+#[derive(Debug, Default)]
+struct ReportedUtds {
+    event_ids: HashSet<OwnedEventId>,
+    order: VecDeque<OwnedEventId>,
+}
+
+impl ReportedUtds {
+    /// Remember the given UTD as reported.
+    ///
+    /// Returns `false` if it was reported already.
+    fn insert(&mut self, event_id: &EventId) -> bool {
+        if self.event_ids.contains(event_id) {
+            return false;
+        }
+
+        if self.order.len() >= MAX_REPORTED_UTDS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.event_ids.remove(&oldest);
+            }
+        }
+
+        self.event_ids.insert(event_id.to_owned());
+        self.order.push_back(event_id.to_owned());
+
+        true
+    }
+}
+
+#[derive(Debug)]
+struct PendingUtd {
+    reason: UnableToDecryptReason,
+    first_seen: Instant,
+}
+
+/// Wraps an [`UnableToDecryptHook`], making sure every UTD gets reported only
+/// once, and computing how long it took to decrypt the late decryptions.
+#[derive(Debug)]
+pub struct UtdHookManager {
+    parent: Arc<dyn UnableToDecryptHook>,
+    /// The UTDs that were reported, and that haven't been decrypted yet.
+    pending_utds: Mutex<HashMap<OwnedEventId, PendingUtd>>,
+    /// The UTDs that were reported, kept longer than the pending ones so a UTD
+    /// isn't reported again once it's evicted from the pending ones.
+    reported_utds: Mutex<ReportedUtds>,
+}
+
+impl UtdHookManager {
+    /// Create a new manager, reporting the UTDs to the given hook.
+    pub fn new(parent: Arc<dyn UnableToDecryptHook>) -> Self {
+        Self { parent, pending_utds: Default::default(), reported_utds: Default::default() }
+    }
+
+    /// The given event couldn't be decrypted.
+    ///
+    /// The hook is called, unless the event has been reported already.
+    pub(crate) fn on_utd(&self, event_id: &EventId, reason: UnableToDecryptReason) {
+        {
+            let mut reported_utds = self.reported_utds.lock().unwrap();
+            if !reported_utds.insert(event_id) {
+                return;
+            }
+
+            let mut pending_utds = self.pending_utds.lock().unwrap();
+
+            if pending_utds.len() >= MAX_PENDING_UTDS {
+                let oldest = pending_utds
+                    .iter()
+                    .min_by_key(|(_, utd)| utd.first_seen)
+                    .map(|(event_id, _)| event_id.clone());
+                if let Some(oldest) = oldest {
+                    pending_utds.remove(&oldest);
+                }
+            }
+
+            pending_utds.insert(
+                event_id.to_owned(),
+                PendingUtd { reason: reason.clone(), first_seen: Instant::now() },
+            );
+        }
+
+        self.parent.on_utd(UnableToDecryptInfo {
+            event_id: event_id.to_owned(),
+            reason,
+            time_to_decrypt: None,
+        });
+    }
+
+    /// The given event, which previously couldn't be decrypted, has been
+    /// decrypted.
+    ///
+    /// The hook is called with the time it took to decrypt the event, if it was
+    /// reported as a UTD before. The event is forgotten afterwards.
+    pub(crate) fn on_late_decrypt(&self, event_id: &EventId) {
+        let info = {
+            let Some(utd) = self.pending_utds.lock().unwrap().remove(event_id) else {
+                return;
+            };
+
+            UnableToDecryptInfo {
+                event_id: event_id.to_owned(),
+                reason: utd.reason,
+                time_to_decrypt: Some(utd.first_seen.elapsed()),
+            }
+        };
+
+        self.parent.on_utd(info);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use matrix_sdk::deserialized_responses::UnableToDecryptReason;
+    use ruma::{event_id, EventId};
+
+    use super::{UnableToDecryptHook, UnableToDecryptInfo, UtdHookManager, MAX_PENDING_UTDS};
+
+    #[derive(Debug, Default)]
+    struct Dummy {
+        utds: Mutex<Vec<UnableToDecryptInfo>>,
+    }
+
+    impl UnableToDecryptHook for Dummy {
+        fn on_utd(&self, info: UnableToDecryptInfo) {
+            self.utds.lock().unwrap().push(info);
+        }
+    }
+
+    #[test]
+    fn utds_are_deduplicated_and_late_decryptions_reported() {
+        let hook = Arc::new(Dummy::default());
+        let manager = UtdHookManager::new(hook.clone());
+
+        let reason = UnableToDecryptReason::MissingMegolmSession { withheld_code: None };
+        manager.on_utd(event_id!("$1"), reason.clone());
+        manager.on_utd(event_id!("$1"), UnableToDecryptReason::Unknown);
+        manager.on_utd(event_id!("$2"), UnableToDecryptReason::UnknownMegolmMessageIndex);
+
+        // An event that was never reported as a UTD is ignored.
+        manager.on_late_decrypt(event_id!("$3"));
+
+        {
+            let utds = hook.utds.lock().unwrap();
+            assert_eq!(utds.len(), 2);
+            assert_eq!(utds[0].event_id, event_id!("$1"));
+            assert_eq!(utds[0].reason, reason);
+            assert!(utds[0].time_to_decrypt.is_none());
+            assert_eq!(utds[1].event_id, event_id!("$2"));
+        }
+
+        manager.on_late_decrypt(event_id!("$1"));
+        manager.on_late_decrypt(event_id!("$1"));
+
+        {
+            let utds = hook.utds.lock().unwrap();
+            assert_eq!(utds.len(), 3);
+            assert_eq!(utds[2].event_id, event_id!("$1"));
+            assert_eq!(utds[2].reason, reason);
+            assert!(utds[2].time_to_decrypt.is_some());
+        }
+
+        // The decrypted event is forgotten, only the other one is still pending.
+        let pending_utds = manager.pending_utds.lock().unwrap();
+        assert_eq!(pending_utds.len(), 1);
+        assert!(pending_utds.contains_key(event_id!("$2")));
+    }
+
+    #[test]
+    fn oldest_pending_utds_are_evicted() {
+        let hook = Arc::new(Dummy::default());
+        let manager = UtdHookManager::new(hook.clone());
+
+        for i in 0..=MAX_PENDING_UTDS {
+            let event_id = EventId::parse(format!("${i}")).unwrap();
+            manager.on_utd(&event_id, UnableToDecryptReason::Unknown);
+        }
+
+        assert_eq!(hook.utds.lock().unwrap().len(), MAX_PENDING_UTDS + 1);
+
+        let pending_utds = manager.pending_utds.lock().unwrap();
+        assert_eq!(pending_utds.len(), MAX_PENDING_UTDS);
+        assert!(pending_utds.contains_key(event_id!("$1000")));
+    }
+
+    #[test]
+    fn evicted_pending_utds_are_not_reported_again() {
+        let hook = Arc::new(Dummy::default());
+        let manager = UtdHookManager::new(hook.clone());
+
+        for i in 0..=MAX_PENDING_UTDS {
+            let event_id = EventId::parse(format!("${i}")).unwrap();
+            manager.on_utd(&event_id, UnableToDecryptReason::Unknown);
+        }
+
+        // The first UTD isn't pending anymore, but it was reported already.
+        assert!(!manager.pending_utds.lock().unwrap().contains_key(event_id!("$0")));
+        manager.on_utd(event_id!("$0"), UnableToDecryptReason::Unknown);
+        assert_eq!(hook.utds.lock().unwrap().len(), MAX_PENDING_UTDS + 1);
+
+        // Neither is a UTD that was decrypted.
+        manager.on_late_decrypt(event_id!("$1"));
+        manager.on_utd(event_id!("$1"), UnableToDecryptReason::Unknown);
+        assert_eq!(hook.utds.lock().unwrap().len(), MAX_PENDING_UTDS + 2);
+    }
+}
@@ -33,6 +33,7 @@ mod notification_client;
 #[cfg(feature = "experimental-room-list")]
 mod room_list_service;
 mod sliding_sync;
+mod spaces;
 mod timeline;
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
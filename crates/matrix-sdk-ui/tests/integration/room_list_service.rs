@@ -7,8 +7,8 @@ use imbl::vector;
 use matrix_sdk_test::async_test;
 use matrix_sdk_ui::{
     room_list_service::{
-        Error, Input, InputResult, RoomListEntry, RoomListLoadingState, State,
-        ALL_ROOMS_LIST_NAME as ALL_ROOMS, INVITES_LIST_NAME as INVITES,
+        filters::new_filter_space, Error, Input, InputResult, RoomListEntry, RoomListLoadingState,
+        State, ALL_ROOMS_LIST_NAME as ALL_ROOMS, INVITES_LIST_NAME as INVITES,
         VISIBLE_ROOMS_LIST_NAME as VISIBLE_ROOMS,
     },
     timeline::{TimelineItemKind, VirtualTimelineItem},
@@ -110,6 +110,24 @@ macro_rules! entries {
 }
 
 macro_rules! assert_entries_stream {
+    // `reset [$entries]`
+    ( @_ [ $stream:ident ] [ reset [ $( $entries:tt )+ ] ; $( $rest:tt )* ] [ $( $accumulator:tt )* ] ) => {
+        assert_entries_stream!(
+            @_
+            [ $stream ]
+            [ $( $rest )* ]
+            [
+                $( $accumulator )*
+                assert_matches!(
+                    $stream.next().now_or_never(),
+                    Some(Some(VectorDiff::Reset { values })) => {
+                        assert_eq!(values, entries!( $( $entries )+ ));
+                    }
+                );
+            ]
+        )
+    };
+
     // `append [$entries]`
     ( @_ [ $stream:ident ] [ append [ $( $entries:tt )+ ] ; $( $rest:tt )* ] [ $( $accumulator:tt )* ] ) => {
         assert_entries_stream!(
@@ -1500,6 +1518,178 @@ async fn test_entries_stream_with_updated_filter() -> Result<(), Error> {
     Ok(())
 }
 
+#[async_test]
+async fn test_entries_stream_with_dynamic_adapters() -> Result<(), Error> {
+    let (server, room_list) = new_room_list_service().await?;
+
+    let sync = room_list.sync();
+    pin_mut!(sync);
+
+    let all_rooms = room_list.all_rooms().await?;
+
+    let (previous_entries, entries_stream, controller) = all_rooms.entries_with_dynamic_adapters();
+    pin_mut!(entries_stream);
+
+    sync_then_assert_request_and_fake_response! {
+        [server, room_list, sync]
+        states = Init => SettingUp,
+        assert request >= {
+            "lists": {
+                ALL_ROOMS: {
+                    "ranges": [[0, 19]],
+                },
+            },
+        },
+        respond with = {
+            "pos": "0",
+            "lists": {
+                ALL_ROOMS: {
+                    "count": 10,
+                    "ops": [
+                        {
+                            "op": "SYNC",
+                            "range": [0, 0],
+                            "room_ids": [
+                                "!r0:bar.org",
+                            ],
+                        },
+                    ],
+                },
+            },
+            "rooms": {
+                "!r0:bar.org": {
+                    "name": "Room #0",
+                    "initial": true,
+                    "timeline": [],
+                },
+            },
+        },
+    };
+
+    assert!(previous_entries.is_empty());
+    assert_entries_stream! {
+        [entries_stream]
+        append [ E, E, E, E, E, E, E, E, E, E ];
+        set[0] [ F("!r0:bar.org") ];
+        pending;
+    };
+
+    // Only keep some rooms.
+    controller.set_filter(Box::new(new_filter_space([
+        room_id!("!r0:bar.org").to_owned(),
+        room_id!("!r1:bar.org").to_owned(),
+        room_id!("!r4:bar.org").to_owned(),
+    ])));
+
+    assert_entries_stream! {
+        [entries_stream]
+        reset [ F("!r0:bar.org") ];
+        pending;
+    };
+
+    sync_then_assert_request_and_fake_response! {
+        [server, room_list, sync]
+        states = SettingUp => Running,
+        assert request >= {
+            "lists": {
+                ALL_ROOMS: {
+                    "ranges": [[0, 9]],
+                },
+                VISIBLE_ROOMS: {
+                    "ranges": [[0, 19]],
+                },
+                INVITES: {
+                    "ranges": [[0, 99]],
+                },
+            },
+        },
+        respond with = {
+            "pos": "1",
+            "lists": {
+                ALL_ROOMS: {
+                    "count": 10,
+                    "ops": [
+                        {
+                            "op": "SYNC",
+                            "range": [1, 4],
+                            "room_ids": [
+                                "!r1:bar.org",
+                                "!r2:qux.org",
+                                "!r3:qux.org",
+                                "!r4:bar.org",
+                            ],
+                        },
+                    ],
+                },
+                VISIBLE_ROOMS: {
+                    "count": 0,
+                },
+                INVITES: {
+                    "count": 0,
+                },
+            },
+            "rooms": {
+                "!r1:bar.org": {
+                    "name": "Room #1",
+                    "initial": true,
+                    "timeline": [],
+                },
+                "!r2:qux.org": {
+                    "name": "Room #2",
+                    "initial": true,
+                    "timeline": [],
+                },
+                "!r3:qux.org": {
+                    "name": "Room #3",
+                    "initial": true,
+                    "timeline": [],
+                },
+                "!r4:bar.org": {
+                    "name": "Room #4",
+                    "initial": true,
+                    "timeline": [],
+                },
+            },
+        },
+    };
+
+    assert_entries_stream! {
+        [entries_stream]
+        insert[1] [ F("!r1:bar.org") ];
+        insert[2] [ F("!r4:bar.org") ];
+        pending;
+    };
+
+    // Sort the rooms by descending room ID.
+    controller.set_sorter(Box::new(|left: &RoomListEntry, right: &RoomListEntry| {
+        right.as_room_id().cmp(&left.as_room_id())
+    }));
+
+    assert_entries_stream! {
+        [entries_stream]
+        reset [ F("!r4:bar.org"), F("!r1:bar.org"), F("!r0:bar.org") ];
+        pending;
+    };
+
+    // Remove the filter, the rooms are still sorted.
+    controller.reset_filter();
+
+    assert_entries_stream! {
+        [entries_stream]
+        reset [
+            F("!r4:bar.org"),
+            F("!r3:qux.org"),
+            F("!r2:qux.org"),
+            F("!r1:bar.org"),
+            F("!r0:bar.org"),
+            E, E, E, E, E,
+        ];
+        pending;
+    };
+
+    Ok(())
+}
+
 #[async_test]
 async fn test_invites_stream() -> Result<(), Error> {
     let (server, room_list) = new_room_list_service().await?;
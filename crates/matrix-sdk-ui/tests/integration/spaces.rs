@@ -0,0 +1,206 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use eyeball_im::VectorDiff;
+use futures_util::pin_mut;
+use matrix_sdk::config::SyncSettings;
+use matrix_sdk_test::{async_test, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder};
+use matrix_sdk_ui::SpaceService;
+use ruma::{room_id, uint, RoomId};
+use serde_json::{json, Value as JsonValue};
+use stream_assert::{assert_next_matches, assert_pending};
+use wiremock::{
+    matchers::{method, path_regex, query_param, query_param_is_missing},
+    Mock, ResponseTemplate,
+};
+
+use crate::{logged_in_client, mock_sync};
+
+fn space_create_event() -> StateTestEvent {
+    StateTestEvent::Custom(json!({
+        "content": {
+            "creator": "@example:localhost",
+            "room_version": "10",
+            "type": "m.space",
+        },
+        "event_id": "$create",
+        "origin_server_ts": 151957878,
+        "sender": "@example:localhost",
+        "state_key": "",
+        "type": "m.room.create",
+    }))
+}
+
+fn space_child_event(child_id: &RoomId, content: JsonValue) -> StateTestEvent {
+    StateTestEvent::Custom(json!({
+        "content": content,
+        "event_id": format!("$child_{child_id}"),
+        "origin_server_ts": 151957878,
+        "sender": "@example:localhost",
+        "state_key": child_id,
+        "type": "m.space.child",
+    }))
+}
+
+#[async_test]
+async fn test_top_level_joined_spaces() {
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let space_a = room_id!("!a:localhost");
+    let space_b = room_id!("!b:localhost");
+    let room = room_id!("!room:localhost");
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder
+        .add_joined_room(
+            JoinedRoomBuilder::new(space_a)
+                .add_state_event(space_create_event())
+                .add_state_event(space_child_event(space_b, json!({ "via": ["localhost"] })))
+                .add_state_event(space_child_event(room, json!({ "via": ["localhost"] }))),
+        )
+        .add_joined_room(JoinedRoomBuilder::new(space_b).add_state_event(space_create_event()))
+        .add_joined_room(JoinedRoomBuilder::new(room));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let space_service = SpaceService::new(client.clone()).await.unwrap();
+
+    let top_level_spaces = space_service.top_level_joined_spaces();
+    assert_eq!(top_level_spaces.len(), 1);
+    assert_eq!(top_level_spaces[0].room_id, space_a);
+    assert!(top_level_spaces[0].is_space);
+    assert_eq!(top_level_spaces[0].children_count, 2);
+
+    let descendants = space_service.joined_space_descendants(space_a);
+    assert!(descendants.contains(space_b));
+    assert!(descendants.contains(room));
+
+    let mut top_level_spaces_stream = space_service.subscribe_to_top_level_joined_spaces();
+    assert_pending!(top_level_spaces_stream);
+
+    // The subspace is removed from the space, so it becomes a top-level space.
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(space_a).add_state_event(space_child_event(space_b, json!({}))),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let top_level_spaces = assert_next_matches!(top_level_spaces_stream, spaces => spaces);
+    let top_level_space_ids: Vec<_> =
+        top_level_spaces.iter().map(|space| space.room_id.clone()).collect();
+    assert_eq!(top_level_space_ids, [space_a, space_b]);
+    assert_eq!(top_level_spaces[0].children_count, 1);
+    assert_eq!(space_service.joined_space_children(space_a)[0].room_id, room);
+}
+
+#[async_test]
+async fn test_space_hierarchy_pagination() {
+    let (client, server) = logged_in_client().await;
+
+    let space_id = room_id!("!space:localhost");
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/rooms/.*/hierarchy$"))
+        .and(query_param_is_missing("from"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rooms": [
+                {
+                    "room_id": space_id,
+                    "room_type": "m.space",
+                    "num_joined_members": 2,
+                    "world_readable": false,
+                    "guest_can_join": false,
+                    "join_rule": "public",
+                    "children_state": [
+                        {
+                            "type": "m.space.child",
+                            "state_key": "!room:localhost",
+                            "content": { "via": ["localhost"] },
+                            "sender": "@example:localhost",
+                            "origin_server_ts": 151957878,
+                        },
+                    ],
+                },
+                {
+                    "room_id": "!room:localhost",
+                    "name": "Room",
+                    "num_joined_members": 5,
+                    "world_readable": true,
+                    "guest_can_join": false,
+                    "join_rule": "public",
+                    "children_state": [],
+                },
+            ],
+            "next_batch": "next",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/rooms/.*/hierarchy$"))
+        .and(query_param("from", "next"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rooms": [
+                {
+                    "room_id": "!other_room:localhost",
+                    "num_joined_members": 1,
+                    "world_readable": false,
+                    "guest_can_join": false,
+                    "join_rule": "public",
+                    "children_state": [],
+                },
+            ],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let space_service = SpaceService::new(client).await.unwrap();
+    let hierarchy = space_service.space_hierarchy(space_id, None);
+    let (rooms, rooms_stream) = hierarchy.subscribe();
+    pin_mut!(rooms_stream);
+    assert!(rooms.is_empty());
+
+    hierarchy.paginate(Some(uint!(2))).await.unwrap();
+    assert!(!hierarchy.is_at_end().await);
+
+    // The space itself is not part of the list.
+    let rooms = assert_next_matches!(rooms_stream, VectorDiff::Append { values } => values);
+    assert_eq!(rooms.len(), 1);
+    assert_eq!(rooms[0].room_id, "!room:localhost");
+    assert_eq!(rooms[0].name.as_deref(), Some("Room"));
+    assert_eq!(rooms[0].num_joined_members, 5);
+    assert!(rooms[0].world_readable);
+    assert_eq!(rooms[0].state, None);
+
+    hierarchy.paginate(Some(uint!(2))).await.unwrap();
+    assert!(hierarchy.is_at_end().await);
+
+    let rooms = assert_next_matches!(rooms_stream, VectorDiff::Append { values } => values);
+    assert_eq!(rooms.len(), 1);
+    assert_eq!(rooms[0].room_id, "!other_room:localhost");
+
+    // Paginating after the end doesn't send a request.
+    hierarchy.paginate(None).await.unwrap();
+    assert_pending!(rooms_stream);
+    assert_eq!(hierarchy.rooms().len(), 2);
+}
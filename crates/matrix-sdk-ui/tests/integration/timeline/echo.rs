@@ -17,20 +17,27 @@ use std::{sync::Arc, time::Duration};
 use assert_matches::assert_matches;
 use eyeball_im::VectorDiff;
 use futures_util::StreamExt;
-use matrix_sdk::{config::SyncSettings, executor::spawn, ruma::MilliSecondsSinceUnixEpoch};
+use matrix_sdk::{
+    config::SyncSettings, executor::spawn, ruma::MilliSecondsSinceUnixEpoch,
+    send_queue::RoomSendQueueUpdate,
+};
 use matrix_sdk_test::{async_test, JoinedRoomBuilder, SyncResponseBuilder, TimelineTestEvent};
 use matrix_sdk_ui::timeline::{
-    EventSendState, RoomExt, TimelineItemContent, TimelineItemKind, VirtualTimelineItem,
+    Error as TimelineError, EventSendState, RoomExt, TimelineItemContent, TimelineItemKind,
+    VirtualTimelineItem,
 };
 use ruma::{
     event_id,
-    events::room::message::{MessageType, RoomMessageEventContent},
-    room_id, uint, TransactionId,
+    events::{
+        poll::start::PollKind,
+        room::message::{ForwardThread, MessageType, RoomMessageEventContent},
+    },
+    room_id, uint, RoomId, TransactionId,
 };
 use serde_json::json;
 use stream_assert::assert_next_matches;
 use wiremock::{
-    matchers::{header, method, path_regex},
+    matchers::{body_partial_json, header, method, path_regex},
     Mock, ResponseTemplate,
 };
 
@@ -296,3 +303,243 @@ async fn cancel_failed() {
     // Observable local echo being removed
     assert_matches!(timeline_stream.next().await, Some(VectorDiff::Remove { index: 0 }));
 }
+
+#[async_test]
+async fn poll_echo() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    mock_encryption_state(&server, false).await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&json!({ "event_id": "$poll" })))
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room.timeline().await;
+    let (_, mut timeline_stream) = timeline.subscribe().await;
+
+    // A poll can't have that many answers.
+    let too_many_answers = (0..21).map(|i| i.to_string()).collect();
+    assert_matches!(
+        timeline
+            .create_poll("Pick one".to_owned(), too_many_answers, 1, PollKind::Disclosed, None)
+            .await,
+        Err(TimelineError::InvalidPollAnswers)
+    );
+
+    timeline
+        .create_poll(
+            "Pizza or pasta?".to_owned(),
+            vec!["Pizza".to_owned(), "Pasta".to_owned()],
+            1,
+            PollKind::Disclosed,
+            None,
+        )
+        .await
+        .unwrap();
+
+    // The local echo is added once the poll is in the send queue.
+    let _day_divider = assert_matches!(
+        timeline_stream.next().await,
+        Some(VectorDiff::PushBack { value }) => value
+    );
+    let local_echo = assert_matches!(
+        timeline_stream.next().await,
+        Some(VectorDiff::PushBack { value }) => value
+    );
+    let poll_state = assert_matches!(
+        local_echo.as_event().unwrap().content(),
+        TimelineItemContent::Poll(poll_state) => poll_state
+    );
+    assert_eq!(poll_state.question(), "Pizza or pasta?");
+    assert_eq!(
+        poll_state.answers().into_iter().map(|answer| answer.text).collect::<Vec<_>>(),
+        ["Pizza", "Pasta"]
+    );
+}
+
+/// Add a message from Alice, a message from us and a topic change to the
+/// timeline of the given room.
+fn add_events_to_reply_to_and_edit(ev_builder: &mut SyncResponseBuilder, room_id: &RoomId) {
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(TimelineTestEvent::Custom(json!({
+                "content": { "body": "Hello", "msgtype": "m.text" },
+                "event_id": "$alice",
+                "origin_server_ts": 152037280,
+                "sender": "@alice:example.org",
+                "type": "m.room.message",
+            })))
+            .add_timeline_event(TimelineTestEvent::Custom(json!({
+                "content": { "body": "Hi", "msgtype": "m.text" },
+                "event_id": "$own",
+                "origin_server_ts": 152037290,
+                "sender": "@example:localhost",
+                "type": "m.room.message",
+            })))
+            .add_timeline_event(TimelineTestEvent::Custom(json!({
+                "content": { "topic": "Greetings" },
+                "event_id": "$topic",
+                "origin_server_ts": 152037300,
+                "sender": "@alice:example.org",
+                "state_key": "",
+                "type": "m.room.topic",
+            }))),
+    );
+}
+
+#[async_test]
+async fn send_reply() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room.timeline().await;
+    let (_, mut timeline_stream) =
+        timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+
+    add_events_to_reply_to_and_edit(&mut ev_builder, room_id);
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    for _ in 0..3 {
+        assert_matches!(timeline_stream.next().await, Some(VectorDiff::PushBack { .. }));
+    }
+
+    mock_encryption_state(&server, false).await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .and(body_partial_json(json!({
+            "m.relates_to": { "m.in_reply_to": { "event_id": "$alice" } },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$reply" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let replied_to_item = timeline.item_by_event_id(event_id!("$alice")).await.unwrap();
+    timeline
+        .send_reply(
+            RoomMessageEventContent::text_plain("Hello back"),
+            &replied_to_item,
+            ForwardThread::Yes,
+            None,
+        )
+        .await
+        .unwrap();
+
+    // The local echo of the reply is added.
+    let item = assert_next_matches!(timeline_stream, VectorDiff::PushBack { value } => value);
+    let message = assert_matches!(item.content(), TimelineItemContent::Message(msg) => msg);
+    assert_eq!(message.in_reply_to().unwrap().event_id, "$alice");
+
+    // And sent.
+    assert_matches!(timeline_stream.next().await, Some(VectorDiff::Set { value, .. }) => {
+        assert_matches!(value.send_state(), Some(EventSendState::Sent { .. }));
+    });
+
+    // State events can't be replied to.
+    let topic_item = timeline.item_by_event_id(event_id!("$topic")).await.unwrap();
+    let result = timeline
+        .send_reply(
+            RoomMessageEventContent::text_plain("Nice topic"),
+            &topic_item,
+            ForwardThread::Yes,
+            None,
+        )
+        .await;
+    assert_matches!(result, Err(TimelineError::UnsupportedReplyItem));
+}
+
+#[async_test]
+async fn edit_echo() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room.timeline().await;
+    let (_, mut timeline_stream) =
+        timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+
+    add_events_to_reply_to_and_edit(&mut ev_builder, room_id);
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    for _ in 0..3 {
+        assert_matches!(timeline_stream.next().await, Some(VectorDiff::PushBack { .. }));
+    }
+    let mut send_queue_updates = room.send_queue().subscribe();
+
+    // The edit has a fallback for clients that don't support edits.
+    mock_encryption_state(&server, false).await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/m\.room\.message/.*"))
+        .and(body_partial_json(json!({
+            "body": "* Hi, edited",
+            "msgtype": "m.text",
+            "m.new_content": { "body": "Hi, edited", "msgtype": "m.text" },
+            "m.relates_to": { "rel_type": "m.replace", "event_id": "$own" },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$edit" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let edit_item = timeline.item_by_event_id(event_id!("$own")).await.unwrap();
+    timeline
+        .edit(&edit_item, RoomMessageEventContent::text_plain("Hi, edited"), None)
+        .await
+        .unwrap();
+
+    // The edit is applied to the edited item right away.
+    let item = assert_next_matches!(timeline_stream, VectorDiff::Set { index: 1, value } => value);
+    let message = assert_matches!(item.content(), TimelineItemContent::Message(msg) => msg);
+    assert_eq!(message.body(), "Hi, edited");
+    assert!(message.is_edited());
+
+    // Wait for the edit to be sent.
+    loop {
+        if let RoomSendQueueUpdate::SentEvent { event_id, .. } =
+            send_queue_updates.recv().await.unwrap()
+        {
+            assert_eq!(event_id, "$edit");
+            break;
+        }
+    }
+
+    // Only our own messages can be edited.
+    let alice_item = timeline.item_by_event_id(event_id!("$alice")).await.unwrap();
+    let result = timeline
+        .edit(&alice_item, RoomMessageEventContent::text_plain("Hello, edited"), None)
+        .await;
+    assert_matches!(result, Err(TimelineError::UnsupportedEditItem));
+}
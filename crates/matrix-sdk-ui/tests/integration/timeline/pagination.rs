@@ -20,6 +20,7 @@ use futures_util::future::join;
 use matrix_sdk::config::SyncSettings;
 use matrix_sdk_test::{
     async_test, test_json, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder,
+    TimelineTestEvent,
 };
 use matrix_sdk_ui::timeline::{
     AnyOtherFullStateEventContent, BackPaginationStatus, PaginationOptions, RoomExt,
@@ -32,7 +33,7 @@ use ruma::{
 use serde_json::json;
 use stream_assert::{assert_next_eq, assert_next_matches};
 use wiremock::{
-    matchers::{header, method, path_regex},
+    matchers::{header, method, path_regex, query_param},
     Mock, ResponseTemplate,
 };
 
@@ -224,3 +225,94 @@ async fn back_pagination_highlighted() {
     // `m.room.tombstone` should be highlighted by default.
     assert!(remote_event.is_highlighted());
 }
+
+#[async_test]
+async fn back_pagination_with_event_cache() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(TimelineTestEvent::Custom(json!({
+                "content": {
+                    "body": "latest",
+                    "msgtype": "m.text",
+                },
+                "event_id": "$latest",
+                "origin_server_ts": 1444812214000i64,
+                "sender": "@alice:example.com",
+                "type": "m.room.message",
+            })))
+            .set_timeline_limited()
+            .set_timeline_prev_batch("prev_batch_token".to_owned()),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    // The timeline is loaded from the event cache.
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room.timeline().await;
+    let items = timeline.items().await;
+    let event_ids: Vec<_> = items
+        .iter()
+        .filter_map(|item| item.as_event()?.event_id().map(ToString::to_string))
+        .collect();
+    assert_eq!(event_ids, ["$latest"]);
+
+    // The gap before the synced events is filled from the server.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("from", "prev_batch_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::ROOM_MESSAGES_BATCH_1))
+        .expect(1)
+        .named("messages_batch_1")
+        .mount(&server)
+        .await;
+
+    timeline.paginate_backwards(PaginationOptions::single_request(10)).await.unwrap();
+    server.reset().await;
+
+    let items = timeline.items().await;
+    assert_eq!(items.iter().filter(|item| item.as_event().is_some()).count(), 4);
+
+    // A new timeline gets the back-paginated events from the cache, without
+    // any request.
+    let timeline = room.timeline().await;
+    let items = timeline.items().await;
+    let event_ids: Vec<_> = items
+        .iter()
+        .filter_map(|item| item.as_event()?.event_id().map(ToString::to_string))
+        .collect();
+    assert_eq!(
+        event_ids,
+        [
+            "$1444812213350496Ccccf:example.com",
+            "$1444812213350496Cbbbf:example.com",
+            "$1444812213350496Caaaf:example.com",
+            "$latest",
+        ]
+    );
+
+    // The next gap is filled from the server, until the start of the room.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("from", "t47409-4357353_219380_26003_2269"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [],
+            "start": "t47409-4357353_219380_26003_2269"
+        })))
+        .expect(1)
+        .named("messages_batch_2")
+        .mount(&server)
+        .await;
+
+    let mut back_pagination_status = timeline.back_pagination_status();
+    timeline.paginate_backwards(PaginationOptions::single_request(10)).await.unwrap();
+    assert_next_eq!(back_pagination_status, BackPaginationStatus::TimelineStartReached);
+}
@@ -26,7 +26,8 @@ use ruma::{
             add_3pid, change_password, deactivate, delete_3pid, get_3pids,
             request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn,
         },
-        config::set_global_account_data,
+        config::{get_global_account_data, set_global_account_data},
+        error::ErrorKind,
         profile::{
             get_avatar_url, get_display_name, get_profile, set_avatar_url, set_display_name,
         },
@@ -694,6 +695,31 @@ impl Account {
         get_raw_content(self.client.store().get_account_data_event(event_type).await?)
     }
 
+    /// Fetch the content of an account data event of a given type from the
+    /// homeserver.
+    ///
+    /// Unlike [`Account::account_data_raw()`], this doesn't use the local store
+    /// and sends a request to the homeserver, so it can be used before the
+    /// first sync or to make sure the content is up to date.
+    ///
+    /// Returns `None` if the homeserver doesn't have any account data of this
+    /// type.
+    pub async fn fetch_account_data(
+        &self,
+        event_type: GlobalAccountDataEventType,
+    ) -> Result<Option<Raw<AnyGlobalAccountDataEventContent>>> {
+        let own_user =
+            self.client.user_id().ok_or_else(|| Error::from(HttpError::AuthenticationRequired))?;
+
+        let request = get_global_account_data::v3::Request::new(own_user.to_owned(), event_type);
+
+        match self.client.send(request, None).await {
+            Ok(response) => Ok(Some(response.account_data)),
+            Err(err) if err.client_api_error_kind() == Some(&ErrorKind::NotFound) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     /// Set the given account data event.
     ///
     /// # Examples
@@ -16,6 +16,8 @@ use matrix_sdk_base::SessionMeta;
 use serde::{Deserialize, Serialize};
 
 use crate::matrix_auth::{self, MatrixAuth, MatrixAuthData};
+#[cfg(feature = "experimental-oidc")]
+use crate::oidc::{self, Oidc, OidcAuthData};
 
 /// An enum over all the possible authentication APIs.
 #[derive(Debug, Clone)]
@@ -23,6 +25,10 @@ use crate::matrix_auth::{self, MatrixAuth, MatrixAuthData};
 pub enum AuthApi {
     /// The native Matrix authentication API.
     Matrix(MatrixAuth),
+
+    /// The OpenID Connect API.
+    #[cfg(feature = "experimental-oidc")]
+    Oidc(Oidc),
 }
 
 /// A user session using one of the available authentication APIs.
@@ -31,6 +37,10 @@ pub enum AuthApi {
 pub enum AuthSession {
     /// A session using the native Matrix authentication API.
     Matrix(matrix_auth::Session),
+
+    /// A session using the OpenID Connect API.
+    #[cfg(feature = "experimental-oidc")]
+    Oidc(oidc::OidcSession),
 }
 
 impl AuthSession {
@@ -38,6 +48,8 @@ impl AuthSession {
     pub fn meta(&self) -> &SessionMeta {
         match self {
             AuthSession::Matrix(session) => &session.meta,
+            #[cfg(feature = "experimental-oidc")]
+            AuthSession::Oidc(session) => &session.user.meta,
         }
     }
 
@@ -45,6 +57,8 @@ impl AuthSession {
     pub fn access_token(&self) -> &str {
         match self {
             AuthSession::Matrix(session) => &session.tokens.access_token,
+            #[cfg(feature = "experimental-oidc")]
+            AuthSession::Oidc(session) => &session.user.tokens.access_token,
         }
     }
 }
@@ -55,23 +69,46 @@ impl From<matrix_auth::Session> for AuthSession {
     }
 }
 
+#[cfg(feature = "experimental-oidc")]
+impl From<oidc::OidcSession> for AuthSession {
+    fn from(session: oidc::OidcSession) -> Self {
+        Self::Oidc(session)
+    }
+}
+
 /// Data for an authentication API.
 #[derive(Clone, Debug)]
 pub(crate) enum AuthData {
     /// Data for the native Matrix authentication API.
     Matrix(MatrixAuthData),
+
+    /// Data for the OpenID Connect API.
+    #[cfg(feature = "experimental-oidc")]
+    Oidc(OidcAuthData),
 }
 
 impl AuthData {
     pub(crate) fn as_matrix(&self) -> Option<&MatrixAuthData> {
         match self {
             AuthData::Matrix(d) => Some(d),
+            #[cfg(feature = "experimental-oidc")]
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "experimental-oidc")]
+    pub(crate) fn as_oidc(&self) -> Option<&OidcAuthData> {
+        match self {
+            AuthData::Oidc(d) => Some(d),
+            _ => None,
         }
     }
 
-    pub(crate) fn access_token(&self) -> String {
+    pub(crate) fn access_token(&self) -> Option<String> {
         match self {
-            AuthData::Matrix(d) => d.tokens.get().access_token,
+            AuthData::Matrix(d) => Some(d.tokens.get().access_token),
+            #[cfg(feature = "experimental-oidc")]
+            AuthData::Oidc(d) => d.tokens.get().map(|tokens| tokens.access_token),
         }
     }
 }
@@ -15,7 +15,7 @@
 
 #[cfg(feature = "experimental-sliding-sync")]
 use std::sync::RwLock as StdRwLock;
-use std::{fmt, sync::Arc};
+use std::{collections::BTreeMap, fmt, sync::Arc};
 
 use matrix_sdk_base::{store::StoreConfig, BaseClient};
 use ruma::{
@@ -30,7 +30,15 @@ use url::Url;
 use super::{Client, ClientInner};
 #[cfg(not(target_arch = "wasm32"))]
 use crate::http_client::HttpSettings;
-use crate::{config::RequestConfig, error::RumaApiError, http_client::HttpClient, HttpError};
+use crate::{
+    config::RequestConfig,
+    error::RumaApiError,
+    http_client::{
+        EndpointClass, HttpClient, RequestScheduler, DEFAULT_MAX_CONCURRENT_MEDIA_REQUESTS,
+        DEFAULT_MAX_CONCURRENT_TO_DEVICE_REQUESTS,
+    },
+    HttpError,
+};
 
 /// Builder that allows creating and configuring various parts of a [`Client`].
 ///
@@ -77,6 +85,7 @@ pub struct ClientBuilder {
     http_cfg: Option<HttpConfig>,
     store_config: BuilderStoreConfig,
     request_config: RequestConfig,
+    concurrency_limits: BTreeMap<EndpointClass, usize>,
     respect_login_well_known: bool,
     appservice_mode: bool,
     server_versions: Option<Box<[MatrixVersion]>>,
@@ -91,6 +100,10 @@ impl ClientBuilder {
             http_cfg: None,
             store_config: BuilderStoreConfig::Custom(StoreConfig::default()),
             request_config: Default::default(),
+            concurrency_limits: BTreeMap::from([
+                (EndpointClass::Media, DEFAULT_MAX_CONCURRENT_MEDIA_REQUESTS),
+                (EndpointClass::ToDevice, DEFAULT_MAX_CONCURRENT_TO_DEVICE_REQUESTS),
+            ]),
             respect_login_well_known: true,
             appservice_mode: false,
             server_versions: None,
@@ -190,6 +203,17 @@ impl ClientBuilder {
         self
     }
 
+    /// Set the maximum number of requests to the given class of endpoints that
+    /// can be in flight at the same time.
+    ///
+    /// By default, up to 4 media requests and 4 to-device requests are sent at
+    /// the same time, and the other requests aren't limited. At least one
+    /// request is always allowed.
+    pub fn max_concurrent_requests(mut self, class: EndpointClass, limit: usize) -> Self {
+        self.concurrency_limits.insert(class, limit);
+        self
+    }
+
     /// Set the proxy through which all the HTTP requests should go.
     ///
     /// Note, only HTTP proxies are supported.
@@ -357,7 +381,9 @@ impl ClientBuilder {
             BaseClient::with_store_config(store_config)
         };
 
-        let http_client = HttpClient::new(inner_http_client.clone(), self.request_config);
+        let scheduler = RequestScheduler::new(&self.concurrency_limits);
+        let http_client =
+            HttpClient::new(inner_http_client.clone(), self.request_config, scheduler);
 
         let mut authentication_server_info = None;
         #[cfg(feature = "experimental-sliding-sync")]
@@ -402,6 +428,8 @@ impl ClientBuilder {
         let homeserver = RwLock::new(Url::parse(&homeserver)?);
 
         let (unknown_token_error_sender, _) = broadcast::channel(1);
+        #[cfg(feature = "experimental-widget-api")]
+        let (revoked_widget_capabilities, _) = broadcast::channel(8);
 
         let inner = Arc::new(ClientInner {
             homeserver,
@@ -415,8 +443,16 @@ impl ClientBuilder {
             group_session_locks: Default::default(),
             #[cfg(feature = "e2e-encryption")]
             key_claim_lock: Default::default(),
+            #[cfg(feature = "e2e-encryption")]
+            backup_state: Default::default(),
+            #[cfg(feature = "e2e-encryption")]
+            recovery_state: Default::default(),
+            #[cfg(feature = "e2e-encryption")]
+            dehydration_state: Default::default(),
             members_request_locks: Default::default(),
             encryption_state_request_locks: Default::default(),
+            event_caches: Default::default(),
+            send_queue_data: Default::default(),
             typing_notice_times: Default::default(),
             event_handlers: Default::default(),
             notification_handlers: Default::default(),
@@ -428,6 +464,8 @@ impl ClientBuilder {
             handle_refresh_tokens: self.handle_refresh_tokens,
             refresh_token_lock: Mutex::new(Ok(())),
             unknown_token_error_sender,
+            #[cfg(feature = "experimental-widget-api")]
+            revoked_widget_capabilities,
             auth_data: Default::default(),
             #[cfg(feature = "e2e-encryption")]
             cross_process_crypto_store_lock: OnceCell::new(),
@@ -21,7 +21,7 @@ use std::{
     fmt::{self, Debug},
     future::Future,
     pin::Pin,
-    sync::{Arc, Mutex as StdMutex},
+    sync::{Arc, Mutex as StdMutex, Weak},
 };
 
 use dashmap::DashMap;
@@ -30,10 +30,10 @@ use futures_core::Stream;
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_base::crypto::store::locks::CryptoStoreLock;
 use matrix_sdk_base::{
-    store::DynStateStore, BaseClient, RoomState, RoomStateFilter, SendOutsideWasm, SessionMeta,
-    SyncOutsideWasm,
+    store::{DynStateStore, LockableStateStore},
+    BaseClient, RoomState, RoomStateFilter, SendOutsideWasm, SessionMeta, SyncOutsideWasm,
 };
-use matrix_sdk_common::instant::Instant;
+use matrix_sdk_common::{instant::Instant, store_locks::CrossProcessStoreLock};
 #[cfg(feature = "appservice")]
 use ruma::TransactionId;
 use ruma::{
@@ -50,6 +50,7 @@ use ruma::{
             },
             error::ErrorKind,
             filter::{create_filter::v3::Request as FilterUploadRequest, FilterDefinition},
+            knock::knock_room,
             membership::{join_room_by_id, join_room_by_id_or_alias},
             profile::get_profile,
             push::{get_notifications::v3::Notification, set_pusher, Pusher},
@@ -73,18 +74,25 @@ use tracing::{debug, error, instrument, trace, Instrument, Span};
 use url::Url;
 
 #[cfg(feature = "e2e-encryption")]
-use crate::encryption::Encryption;
+use crate::encryption::{
+    backups::BackupClientState, dehydrated_devices::DehydrationClientState,
+    recovery::RecoveryState, Encryption,
+};
+#[cfg(feature = "experimental-oidc")]
+use crate::oidc::Oidc;
 use crate::{
     authentication::AuthData,
     config::RequestConfig,
     error::{HttpError, HttpResult},
+    event_cache::EventCaches,
     event_handler::{
         EventHandler, EventHandlerDropGuard, EventHandlerHandle, EventHandlerStore, SyncEvent,
     },
-    http_client::HttpClient,
+    http_client::{HttpClient, RateLimitState},
     matrix_auth::MatrixAuth,
     notification_settings::NotificationSettings,
     room,
+    send_queue::{SendQueue, SendQueueData},
     sync::{RoomUpdate, SyncResponse},
     Account, AuthApi, AuthSession, Error, Media, RefreshTokenError, Result, TransmissionProgress,
 };
@@ -147,7 +155,7 @@ pub(crate) struct ClientInner {
     #[cfg(feature = "experimental-sliding-sync")]
     sliding_sync_proxy: StdRwLock<Option<Url>>,
     /// The underlying HTTP client.
-    http_client: HttpClient,
+    pub(crate) http_client: HttpClient,
     /// User session data.
     base_client: BaseClient,
     /// The Matrix versions the server supports (well-known ones only)
@@ -159,10 +167,23 @@ pub(crate) struct ClientInner {
     /// Lock making sure we're only doing one key claim request at a time.
     #[cfg(feature = "e2e-encryption")]
     pub(crate) key_claim_lock: Mutex<()>,
+    /// The state of the backups.
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) backup_state: BackupClientState,
+    /// The state of the recovery.
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) recovery_state: SharedObservable<RecoveryState>,
+    /// The state of the dehydrated devices.
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) dehydration_state: DehydrationClientState,
     pub(crate) members_request_locks: Mutex<BTreeMap<OwnedRoomId, Arc<Mutex<()>>>>,
     /// Locks for requests on the encryption state of rooms.
     pub(crate) encryption_state_request_locks: DashMap<OwnedRoomId, Arc<Mutex<()>>>,
     pub(crate) typing_notice_times: DashMap<OwnedRoomId, Instant>,
+    /// The event caches of the rooms, lazily loaded from the store.
+    pub(crate) event_caches: EventCaches,
+    /// The send queues of the rooms.
+    pub(crate) send_queue_data: SendQueueData,
     /// Event handlers. See `add_event_handler`.
     pub(crate) event_handlers: EventHandlerStore,
     /// Notification handlers. See `register_notification_handler`.
@@ -190,6 +211,10 @@ pub(crate) struct ClientInner {
     /// Client API UnknownToken error publisher. Allows the subscriber logout
     /// the user when any request fails because of an invalid access token
     pub(crate) unknown_token_error_sender: broadcast::Sender<UnknownToken>,
+    /// Publishes the keys of the widget capabilities that were revoked, so the
+    /// running widgets lose them right away.
+    #[cfg(feature = "experimental-widget-api")]
+    pub(crate) revoked_widget_capabilities: broadcast::Sender<Vec<u8>>,
     /// Authentication data to keep in memory.
     pub(crate) auth_data: OnceCell<AuthData>,
 
@@ -197,6 +222,26 @@ pub(crate) struct ClientInner {
     pub(crate) cross_process_crypto_store_lock: OnceCell<CryptoStoreLock>,
 }
 
+/// A weak reference to the inner state of a [`Client`].
+///
+/// It can be held by long-lived tasks without keeping the client alive.
+#[derive(Clone)]
+pub(crate) struct WeakClient {
+    client: Weak<ClientInner>,
+}
+
+impl WeakClient {
+    /// Create a new `WeakClient` pointing to the given client.
+    pub(crate) fn from_client(client: &Client) -> Self {
+        Self { client: Arc::downgrade(&client.inner) }
+    }
+
+    /// Get the client, if it is still alive.
+    pub(crate) fn get(&self) -> Option<Client> {
+        self.client.upgrade().map(|inner| Client { inner })
+    }
+}
+
 #[cfg(not(tarpaulin_include))]
 impl Debug for Client {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
@@ -224,6 +269,21 @@ impl Client {
         self.inner.base_client.subscribe_to_ignore_user_list_changes()
     }
 
+    /// Get the current rate-limiting state of the homeserver.
+    ///
+    /// This tells which classes of endpoints requests are currently held back
+    /// for, because the homeserver asked us to slow down.
+    pub fn rate_limit_state(&self) -> RateLimitState {
+        self.inner.http_client.scheduler.rate_limit_state()
+    }
+
+    /// Returns a subscriber that publishes the rate-limiting state of the
+    /// homeserver every time it changes, e.g. to let the user know that
+    /// requests are delayed.
+    pub fn subscribe_to_rate_limit_state(&self) -> Subscriber<RateLimitState> {
+        self.inner.http_client.scheduler.subscribe()
+    }
+
     /// Create a new [`ClientBuilder`].
     pub fn builder() -> ClientBuilder {
         ClientBuilder::new()
@@ -409,7 +469,7 @@ impl Client {
     ///
     /// Will be `None` if the client has not been logged in.
     pub fn access_token(&self) -> Option<String> {
-        Some(self.inner.auth_data.get()?.access_token())
+        self.inner.auth_data.get()?.access_token()
     }
 
     /// Access the authentication API used to log in this client.
@@ -418,6 +478,8 @@ impl Client {
     pub fn auth_api(&self) -> Option<AuthApi> {
         match self.inner.auth_data.get()? {
             AuthData::Matrix(_) => Some(AuthApi::Matrix(self.matrix_auth())),
+            #[cfg(feature = "experimental-oidc")]
+            AuthData::Oidc(_) => Some(AuthApi::Oidc(self.oidc())),
         }
     }
 
@@ -430,6 +492,8 @@ impl Client {
     pub fn session(&self) -> Option<AuthSession> {
         match self.auth_api()? {
             AuthApi::Matrix(api) => api.session().map(Into::into),
+            #[cfg(feature = "experimental-oidc")]
+            AuthApi::Oidc(api) => api.full_session().map(Into::into),
         }
     }
 
@@ -438,11 +502,31 @@ impl Client {
         self.base_client().store()
     }
 
+    /// Create a cross-process lock backed by the state store.
+    ///
+    /// This can be used to make sure that only one of several processes
+    /// sharing the same state store (e.g. the main app and a notification
+    /// extension) performs a given operation at a time. The `lock_holder`
+    /// must be a unique identifier for this process.
+    pub fn create_state_store_lock(
+        &self,
+        lock_key: String,
+        lock_holder: String,
+    ) -> CrossProcessStoreLock<LockableStateStore> {
+        self.base_client().create_state_store_lock(lock_key, lock_holder)
+    }
+
     /// Access the native Matrix authentication API with this client.
     pub fn matrix_auth(&self) -> MatrixAuth {
         MatrixAuth::new(self.clone())
     }
 
+    /// Access the OpenID Connect API with this client.
+    #[cfg(feature = "experimental-oidc")]
+    pub fn oidc(&self) -> Oidc {
+        Oidc::new(self.clone())
+    }
+
     /// Get the account of the current owner of the client.
     pub fn account(&self) -> Account {
         Account::new(self.clone())
@@ -459,6 +543,11 @@ impl Client {
         Media::new(self.clone())
     }
 
+    /// Get the send queue of the client.
+    pub fn send_queue(&self) -> SendQueue {
+        SendQueue::new(self.clone())
+    }
+
     /// Register a handler for a specific event type.
     ///
     /// The handler is a function or closure with one or more arguments. The
@@ -777,7 +866,7 @@ impl Client {
 
     /// Get all the rooms the client knows about.
     ///
-    /// This will return the list of joined, invited, and left rooms.
+    /// This will return the list of joined, invited, knocked and left rooms.
     pub fn rooms(&self) -> Vec<room::Room> {
         self.base_client()
             .get_rooms()
@@ -822,6 +911,15 @@ impl Client {
             .collect()
     }
 
+    /// Returns the knocked rooms this client knows about.
+    pub fn knocked_rooms(&self) -> Vec<room::Knocked> {
+        self.base_client()
+            .get_rooms_filtered(RoomStateFilter::KNOCKED)
+            .into_iter()
+            .filter_map(|room| room::Knocked::new(self, room))
+            .collect()
+    }
+
     /// Get a room with the given room id.
     ///
     /// # Arguments
@@ -860,6 +958,15 @@ impl Client {
         self.base_client().get_room(room_id).and_then(|room| room::Left::new(self, room))
     }
 
+    /// Get a knocked room with the given room id.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The unique id of the room that should be fetched.
+    pub fn get_knocked_room(&self, room_id: &RoomId) -> Option<room::Knocked> {
+        self.base_client().get_room(room_id).and_then(|room| room::Knocked::new(self, room))
+    }
+
     /// Resolve a room alias to a room id and a list of servers which know
     /// about it.
     ///
@@ -907,6 +1014,8 @@ impl Client {
         let session = session.into();
         match session {
             AuthSession::Matrix(s) => self.matrix_auth().restore_session(s).await,
+            #[cfg(feature = "experimental-oidc")]
+            AuthSession::Oidc(s) => self.oidc().restore_session(s).await,
         }
     }
 
@@ -924,6 +1033,10 @@ impl Client {
             AuthApi::Matrix(a) => {
                 a.refresh_access_token().await?;
             }
+            #[cfg(feature = "experimental-oidc")]
+            AuthApi::Oidc(a) => {
+                a.refresh_access_token().await?;
+            }
         }
 
         Ok(())
@@ -1032,6 +1145,38 @@ impl Client {
         room::Joined::new(self, base_room).ok_or(Error::InconsistentState)
     }
 
+    /// Ask to join a room, as defined in [MSC2403].
+    ///
+    /// The room needs to have the `knock` join rule. Once one of its members
+    /// accepts the request, we are invited to the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id_or_alias` - The `RoomId` or `RoomAliasId` of the room to
+    ///   knock on.
+    ///
+    /// * `reason` - The reason for wanting to join the room, shown to its
+    ///   members.
+    ///
+    /// * `via` - The servers to attempt to knock on the room through. One of
+    ///   the servers must be participating in the room.
+    ///
+    /// [MSC2403]: https://github.com/matrix-org/matrix-spec-proposals/pull/2403
+    pub async fn knock(
+        &self,
+        room_id_or_alias: &RoomOrAliasId,
+        reason: Option<String>,
+        via: &[OwnedServerName],
+    ) -> Result<room::Knocked> {
+        let request = assign!(knock_room::v3::Request::new(room_id_or_alias.to_owned()), {
+            reason,
+            server_name: via.to_owned(),
+        });
+        let response = self.send(request, None).await?;
+        let base_room = self.base_client().room_knocked(&response.room_id).await?;
+        room::Knocked::new(self, base_room).ok_or(Error::InconsistentState)
+    }
+
     /// Search the homeserver's directory of public rooms.
     ///
     /// Sends a request to "_matrix/client/r0/publicRooms", returns
@@ -0,0 +1,660 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Server-side backup of the room keys.
+//!
+//! Room keys can be backed up to the homeserver, encrypted with the public
+//! part of a recovery key, so that they can be restored on a new device that
+//! has access to the recovery key.
+//!
+//! Once backups are enabled, the room keys are uploaded in the background
+//! after every sync. Missing room keys are downloaded from the backup when an
+//! event can't be decrypted with [`Common::decrypt_event()`].
+//!
+//! [`Common::decrypt_event()`]: crate::room::Common::decrypt_event
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use matrix_sdk::Client;
+//! # use url::Url;
+//! # async {
+//! # let homeserver = Url::parse("http://example.com")?;
+//! # let client = Client::new(homeserver).await?;
+//! let backups = client.encryption().backups();
+//!
+//! if backups.exists_on_server().await? {
+//!     // The recovery key must have been imported before, for example from the
+//!     // secret storage.
+//!     backups.enable().await?;
+//! } else {
+//!     backups.create().await?;
+//! }
+//! # anyhow::Ok(()) };
+//! ```
+
+use std::sync::{atomic::Ordering, Arc};
+
+use eyeball::Subscriber;
+use matrix_sdk_base::crypto::{
+    backups::MegolmV1BackupKey,
+    olm::{BackedUpRoomKey, ExportedRoomKey},
+    store::RecoveryKey,
+    types::RoomKeyBackupInfo,
+    OlmMachine, RoomKeyImportResult,
+};
+use ruma::{
+    api::client::{
+        backup::{
+            create_backup_version, delete_backup_version, get_backups_for_room,
+            get_backups_for_session, get_latest_backup_info, BackupAlgorithm, KeyBackupData,
+        },
+        error::ErrorKind,
+    },
+    events::room::encrypted::{EncryptedEventScheme, OriginalSyncRoomEncryptedEvent},
+    serde::Raw,
+    OwnedRoomId, RoomId,
+};
+use serde_json::json;
+use tokio::sync::Notify;
+use tracing::{debug, info, instrument, trace, warn};
+
+use crate::{client::WeakClient, executor::spawn, Client, Error, Result};
+
+mod types;
+
+pub(crate) use self::types::BackupClientState;
+pub use self::types::{BackupState, Error as BackupError};
+
+/// The maximum number of room keys we remember having tried to download from
+/// the backup.
+const MAX_ATTEMPTED_DOWNLOADS: usize = 1000;
+
+/// A high-level API to manage the server-side backup of the room keys.
+///
+/// To get this, use [`Encryption::backups()`].
+///
+/// [`Encryption::backups()`]: crate::encryption::Encryption::backups
+#[derive(Debug, Clone)]
+pub struct Backups {
+    /// The underlying client.
+    client: Client,
+}
+
+impl Backups {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn data(&self) -> &BackupClientState {
+        &self.client.inner.backup_state
+    }
+
+    fn set_state(&self, state: BackupState) {
+        trace!(?state, "Backup state changed");
+
+        if state == BackupState::Enabled {
+            // The backup might contain room keys that we didn't find before.
+            self.data().attempted_downloads.lock().unwrap().clear();
+        }
+
+        self.data().global_state.set(state);
+    }
+
+    /// Get the current state of the backups.
+    pub fn state(&self) -> BackupState {
+        self.data().global_state.get()
+    }
+
+    /// Get a stream of updates of the state of the backups.
+    ///
+    /// The current state can be retrieved with [`Backups::state()`].
+    pub fn state_stream(&self) -> Subscriber<BackupState> {
+        self.data().global_state.subscribe()
+    }
+
+    /// Whether room keys are currently backed up.
+    pub async fn are_enabled(&self) -> bool {
+        match self.client.olm_machine().await.as_ref() {
+            Some(olm) => olm.backup_machine().enabled().await,
+            None => false,
+        }
+    }
+
+    /// Check whether the homeserver has a backup version.
+    pub async fn exists_on_server(&self) -> Result<bool> {
+        Ok(self.fetch_latest_backup_info().await?.is_some())
+    }
+
+    /// Create a new backup version on the homeserver, with a new random
+    /// recovery key, and start backing up the room keys to it.
+    ///
+    /// The recovery key is saved in the crypto store. It should be stored
+    /// somewhere else too, for example in the secret storage, to be able to
+    /// restore the room keys on another device.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it can't get enough randomness from the OS to
+    /// create the recovery key.
+    #[instrument(skip(self))]
+    pub async fn create(&self) -> Result<()> {
+        self.set_state(BackupState::Creating);
+
+        let result = self.create_helper().await;
+        self.handle_enable_result(&result);
+
+        result
+    }
+
+    async fn create_helper(&self) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let recovery_key = RecoveryKey::new().expect("Can't create a new recovery key");
+        let backup_key = recovery_key.megolm_v1_public_key();
+
+        let algorithm = backup_algorithm(olm, &backup_key).await?;
+        let request = create_backup_version::v3::Request::new(algorithm);
+        let version = self.client.send(request, None).await?.version;
+
+        info!(version, "Created a new backup version");
+
+        // Stop backing up the room keys to the previous version, if any, and
+        // reset their backup state so they are all uploaded to the new one.
+        olm.backup_machine().disable_backup().await?;
+
+        backup_key.set_version(version.clone());
+        olm.backup_machine().save_recovery_key(Some(recovery_key), Some(version)).await?;
+        olm.backup_machine().enable_backup_v1(backup_key).await?;
+
+        Ok(())
+    }
+
+    /// Start backing up the room keys to the current backup version on the
+    /// homeserver.
+    ///
+    /// The recovery key of the backup version must be in the crypto store, for
+    /// example after importing it from the secret storage.
+    #[instrument(skip(self))]
+    pub async fn enable(&self) -> Result<()> {
+        self.set_state(BackupState::Enabling);
+
+        let result = self.enable_helper().await;
+        self.handle_enable_result(&result);
+
+        result
+    }
+
+    async fn enable_helper(&self) -> Result<()> {
+        let (version, backup_info) =
+            self.fetch_latest_backup_info().await?.ok_or(BackupError::NoBackupOnServer)?;
+
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let recovery_key = olm
+            .backup_machine()
+            .get_backup_keys()
+            .await?
+            .recovery_key
+            .ok_or(BackupError::MissingRecoveryKey)?;
+
+        let backup_key = check_recovery_key(&recovery_key, backup_info)?;
+
+        backup_key.set_version(version.clone());
+        olm.backup_machine().save_recovery_key(Some(recovery_key), Some(version)).await?;
+        olm.backup_machine().enable_backup_v1(backup_key).await?;
+
+        Ok(())
+    }
+
+    fn handle_enable_result(&self, result: &Result<()>) {
+        match result {
+            Ok(()) => {
+                self.set_state(BackupState::Enabled);
+                self.trigger_upload();
+            }
+            Err(error) => {
+                warn!("Failed to enable backups: {error}");
+                self.set_state(BackupState::Unknown);
+            }
+        }
+    }
+
+    /// Stop backing up the room keys.
+    ///
+    /// The backup version on the homeserver is left untouched, use
+    /// [`Backups::delete()`] to remove it. Backups will be resumed the next
+    /// time the client is restored if the backup version still exists on the
+    /// homeserver.
+    #[instrument(skip(self))]
+    pub async fn disable(&self) -> Result<()> {
+        self.set_state(BackupState::Disabling);
+
+        let result = async {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+            olm.backup_machine().disable_backup().await?;
+            Ok(())
+        }
+        .await;
+
+        self.set_state(BackupState::Unknown);
+
+        result
+    }
+
+    /// Delete the current backup version from the homeserver and stop backing
+    /// up the room keys.
+    #[instrument(skip(self))]
+    pub async fn delete(&self) -> Result<()> {
+        let version = match self.backup_version().await? {
+            Some(version) => Some(version),
+            None => self.fetch_latest_backup_info().await?.map(|(version, _)| version),
+        };
+
+        if let Some(version) = version {
+            self.set_state(BackupState::Disabling);
+
+            let request = delete_backup_version::v3::Request::new(version.clone());
+            if let Err(error) = self.client.send(request, None).await {
+                if error.client_api_error_kind() != Some(&ErrorKind::NotFound) {
+                    self.set_state(if self.are_enabled().await {
+                        BackupState::Enabled
+                    } else {
+                        BackupState::Unknown
+                    });
+
+                    return Err(error.into());
+                }
+            }
+
+            info!(version, "Deleted the backup version");
+        }
+
+        self.disable().await
+    }
+
+    /// Download the room keys of the given room from the backup, and import
+    /// them into the crypto store.
+    #[instrument(skip(self))]
+    pub async fn download_room_keys_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<RoomKeyImportResult> {
+        let (recovery_key, version) = self.backup_keys().await?;
+
+        let request = get_backups_for_room::v3::Request::new(version, room_id.to_owned());
+        let response = self.client.send(request, None).await?;
+
+        let keys = response
+            .sessions
+            .into_iter()
+            .filter_map(|(session_id, data)| {
+                decrypt_room_key(&recovery_key, room_id, session_id, data)
+            })
+            .collect();
+
+        self.import_room_keys(keys).await
+    }
+
+    /// Download a single room key from the backup, and import it into the
+    /// crypto store.
+    #[instrument(skip(self))]
+    pub async fn download_room_key(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<RoomKeyImportResult> {
+        let (recovery_key, version) = self.backup_keys().await?;
+
+        let request = get_backups_for_session::v3::Request::new(
+            version,
+            room_id.to_owned(),
+            session_id.to_owned(),
+        );
+        let response = self.client.send(request, None).await?;
+
+        let keys =
+            decrypt_room_key(&recovery_key, room_id, session_id.to_owned(), response.key_data)
+                .into_iter()
+                .collect();
+
+        self.import_room_keys(keys).await
+    }
+
+    async fn import_room_keys(&self, keys: Vec<ExportedRoomKey>) -> Result<RoomKeyImportResult> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let result = olm.import_room_keys(keys, true, |_, _| {}).await?;
+        debug!(
+            imported = result.imported_count,
+            total = result.total_count,
+            "Imported room keys from the backup"
+        );
+
+        Ok(result)
+    }
+
+    /// The recovery key and the version of the backup in use.
+    async fn backup_keys(&self) -> Result<(RecoveryKey, String)> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let keys = olm.backup_machine().get_backup_keys().await?;
+
+        match (keys.recovery_key, keys.backup_version) {
+            (Some(recovery_key), Some(version)) => Ok((recovery_key, version)),
+            _ => Err(BackupError::NotEnabled.into()),
+        }
+    }
+
+    async fn backup_version(&self) -> Result<Option<String>> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.backup_machine().get_backup_keys().await?.backup_version)
+    }
+
+    async fn fetch_latest_backup_info(&self) -> Result<Option<(String, RoomKeyBackupInfo)>> {
+        let request = get_latest_backup_info::v3::Request::new();
+
+        match self.client.send(request, None).await {
+            Ok(response) => Ok(Some((response.version, response.algorithm.deserialize_as()?))),
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Resume the backups of a previous session, if the backup version it used
+    /// still exists on the homeserver.
+    async fn resume(&self) -> Result<()> {
+        let (recovery_key, version) = match self.backup_keys().await {
+            Ok(keys) => keys,
+            Err(Error::Backup(BackupError::NotEnabled)) => return Ok(()),
+            Err(error) => return Err(error),
+        };
+
+        self.set_state(BackupState::Resuming);
+
+        let result = async {
+            match self.fetch_latest_backup_info().await? {
+                Some((latest_version, backup_info)) if latest_version == version => {
+                    let backup_key = check_recovery_key(&recovery_key, backup_info)?;
+                    backup_key.set_version(version);
+
+                    let olm = self.client.olm_machine().await;
+                    let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+                    olm.backup_machine().enable_backup_v1(backup_key).await?;
+
+                    Ok(true)
+                }
+                _ => {
+                    info!(version, "The backup version is not the current one anymore");
+                    Ok(false)
+                }
+            }
+        }
+        .await;
+
+        match result {
+            Ok(true) => {
+                self.set_state(BackupState::Enabled);
+                Ok(())
+            }
+            Ok(false) => {
+                self.set_state(BackupState::Unknown);
+                Ok(())
+            }
+            Err(error) => {
+                self.set_state(BackupState::Unknown);
+                Err(error)
+            }
+        }
+    }
+
+    /// Resume the backups if needed, and wake up the upload task.
+    ///
+    /// This is called after every sync.
+    pub(crate) async fn maybe_trigger_backup(&self) {
+        let data = self.data();
+
+        if self.state() == BackupState::Unknown
+            && !data.resume_attempted.swap(true, Ordering::SeqCst)
+        {
+            if let Err(error) = self.resume().await {
+                warn!("Failed to resume backups: {error}");
+                // Try again after the next sync.
+                data.resume_attempted.store(false, Ordering::SeqCst);
+            }
+        }
+
+        if self.are_enabled().await {
+            self.trigger_upload();
+        }
+    }
+
+    /// Wake up the upload task, spawning it if needed.
+    fn trigger_upload(&self) {
+        let data = self.data();
+
+        if !data.upload_task_spawned.swap(true, Ordering::SeqCst) {
+            spawn(upload_task(WeakClient::from_client(&self.client), data.upload_notifier.clone()));
+        }
+
+        data.upload_notifier.notify_one();
+    }
+
+    /// Upload the room keys that aren't backed up yet.
+    async fn upload_room_keys(&self) -> Result<()> {
+        loop {
+            let request = {
+                let olm = self.client.olm_machine().await;
+                let Some(olm) = olm.as_ref() else {
+                    return Ok(());
+                };
+
+                olm.backup_machine().backup().await?
+            };
+
+            let Some(request) = request else {
+                trace!("All the room keys are backed up");
+                return Ok(());
+            };
+
+            if let Err(error) = self.client.send_outgoing_request(request).await {
+                if error.client_api_error_kind() == Some(&ErrorKind::NotFound) {
+                    warn!("The backup version was deleted from the homeserver, disabling backups");
+                    self.disable().await?;
+                }
+
+                return Err(error);
+            }
+        }
+    }
+
+    /// Try to download the room key of the given event from the backup, in the
+    /// background.
+    ///
+    /// The room key of a given session is only requested once while backups
+    /// are enabled.
+    pub(crate) fn maybe_download_room_key(
+        &self,
+        room_id: OwnedRoomId,
+        event: Raw<OriginalSyncRoomEncryptedEvent>,
+    ) {
+        let Ok(event) = event.deserialize() else {
+            return;
+        };
+
+        let EncryptedEventScheme::MegolmV1AesSha2(content) = event.content.scheme else {
+            return;
+        };
+
+        let session_id = content.session_id;
+        let key = (room_id, session_id);
+
+        if self.data().attempted_downloads.lock().unwrap().contains(&key) {
+            return;
+        }
+
+        let backups = self.clone();
+        spawn(async move {
+            if !backups.are_enabled().await {
+                return;
+            }
+
+            {
+                let mut attempted_downloads = backups.data().attempted_downloads.lock().unwrap();
+
+                // Forget the previous attempts rather than growing forever, at
+                // the price of retrying some of them.
+                if attempted_downloads.len() >= MAX_ATTEMPTED_DOWNLOADS {
+                    attempted_downloads.clear();
+                }
+
+                if !attempted_downloads.insert(key.clone()) {
+                    return;
+                }
+            }
+
+            let (room_id, session_id) = key;
+
+            match backups.download_room_key(&room_id, &session_id).await {
+                Ok(result) => debug!(
+                    ?room_id,
+                    session_id,
+                    imported = result.imported_count,
+                    "Downloaded a missing room key"
+                ),
+                Err(error) => {
+                    warn!(?room_id, session_id, "Failed to download a missing room key: {error}")
+                }
+            }
+        });
+    }
+}
+
+async fn upload_task(weak_client: WeakClient, notifier: Arc<Notify>) {
+    debug!("Starting the backup upload task");
+
+    loop {
+        notifier.notified().await;
+
+        let Some(client) = weak_client.get() else {
+            break;
+        };
+
+        if let Err(error) = client.encryption().backups().upload_room_keys().await {
+            // Try again after the next sync.
+            warn!("Failed to back up room keys: {error}");
+        }
+    }
+
+    debug!("The client was dropped, stopping the backup upload task");
+}
+
+/// Create the backup algorithm of a new backup version, signed with our own
+/// device and cross-signing identity.
+async fn backup_algorithm(
+    olm: &OlmMachine,
+    backup_key: &MegolmV1BackupKey,
+) -> Result<Raw<BackupAlgorithm>> {
+    let public_key = backup_key.to_base64();
+
+    // The auth data only has a single field, so it is already in the
+    // canonical JSON form.
+    let auth_data = serde_json::to_string(&json!({ "public_key": public_key }))?;
+    let signatures = olm.sign(&auth_data).await;
+
+    let algorithm = json!({
+        "algorithm": backup_key.backup_algorithm(),
+        "auth_data": {
+            "public_key": public_key,
+            "signatures": signatures,
+        },
+    });
+
+    Ok(Raw::new(&algorithm)?.cast())
+}
+
+/// Check that the recovery key matches the given backup version, and get its
+/// public key.
+fn check_recovery_key(
+    recovery_key: &RecoveryKey,
+    backup_info: RoomKeyBackupInfo,
+) -> Result<MegolmV1BackupKey, BackupError> {
+    let auth_data = match backup_info {
+        RoomKeyBackupInfo::MegolmBackupV1Curve25519AesSha2(auth_data) => auth_data,
+        RoomKeyBackupInfo::Other { algorithm, .. } => {
+            return Err(BackupError::UnsupportedAlgorithm(algorithm))
+        }
+    };
+
+    let backup_key = recovery_key.megolm_v1_public_key();
+
+    if backup_key.to_base64() == auth_data.public_key.to_base64() {
+        Ok(backup_key)
+    } else {
+        Err(BackupError::RecoveryKeyMismatch)
+    }
+}
+
+fn decrypt_room_key(
+    recovery_key: &RecoveryKey,
+    room_id: &RoomId,
+    session_id: String,
+    data: Raw<KeyBackupData>,
+) -> Option<ExportedRoomKey> {
+    let data = match data.deserialize() {
+        Ok(data) => data,
+        Err(error) => {
+            warn!(session_id, "Failed to deserialize a backed up room key: {error}");
+            return None;
+        }
+    };
+
+    let decrypted = match recovery_key.decrypt_v1(
+        &data.session_data.ephemeral.encode(),
+        &data.session_data.mac.encode(),
+        &data.session_data.ciphertext.encode(),
+    ) {
+        Ok(decrypted) => decrypted,
+        Err(error) => {
+            warn!(session_id, "Failed to decrypt a backed up room key: {error}");
+            return None;
+        }
+    };
+
+    let key: BackedUpRoomKey = match serde_json::from_str(&decrypted) {
+        Ok(key) => key,
+        Err(error) => {
+            warn!(session_id, "Failed to deserialize a decrypted room key: {error}");
+            return None;
+        }
+    };
+
+    Some(ExportedRoomKey {
+        algorithm: key.algorithm,
+        room_id: room_id.to_owned(),
+        sender_key: key.sender_key,
+        session_id,
+        session_key: key.session_key,
+        sender_claimed_keys: key.sender_claimed_keys,
+        forwarding_curve25519_key_chain: key.forwarding_curve25519_key_chain,
+        shared_history: key.shared_history,
+    })
+}
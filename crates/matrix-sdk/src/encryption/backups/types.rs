@@ -0,0 +1,100 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+};
+
+use eyeball::SharedObservable;
+use ruma::OwnedRoomId;
+use thiserror::Error;
+use tokio::sync::Notify;
+
+/// The states the backup support of the client can be in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackupState {
+    /// The state of backups is not known yet.
+    ///
+    /// This is the case before the backup key of a previous session was
+    /// loaded from the crypto store, or after backups have been disabled.
+    #[default]
+    Unknown,
+
+    /// A new backup version is being created on the homeserver.
+    Creating,
+
+    /// Backups are being enabled for an existing backup version.
+    Enabling,
+
+    /// The backup key of a previous session is being loaded from the crypto
+    /// store.
+    Resuming,
+
+    /// Backups are enabled, room keys are uploaded to the homeserver.
+    Enabled,
+
+    /// Backups are being disabled.
+    Disabling,
+}
+
+/// Error type for the backups.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The homeserver doesn't have any backup version.
+    #[error("the homeserver doesn't have any backup version")]
+    NoBackupOnServer,
+
+    /// The backup version on the homeserver uses an unsupported algorithm.
+    #[error("the backup version on the homeserver uses an unsupported algorithm: {0}")]
+    UnsupportedAlgorithm(String),
+
+    /// The recovery key is missing from the crypto store.
+    #[error("the recovery key is missing from the crypto store")]
+    MissingRecoveryKey,
+
+    /// The recovery key doesn't match the public key of the backup version on
+    /// the homeserver.
+    #[error("the recovery key doesn't match the backup version on the homeserver")]
+    RecoveryKeyMismatch,
+
+    /// Room keys can't be downloaded because backups aren't enabled.
+    #[error("backups aren't enabled")]
+    NotEnabled,
+}
+
+/// The state of the backups, shared by all the clones of a client.
+#[derive(Default)]
+pub(crate) struct BackupClientState {
+    pub(super) global_state: SharedObservable<BackupState>,
+    /// Wakes up the upload task when there might be room keys to back up.
+    pub(super) upload_notifier: Arc<Notify>,
+    pub(super) upload_task_spawned: AtomicBool,
+    pub(super) resume_attempted: AtomicBool,
+    /// The room keys that we already tried to download, by room ID and session
+    /// ID.
+    pub(super) attempted_downloads: StdMutex<HashSet<(OwnedRoomId, String)>>,
+}
+
+impl Drop for BackupClientState {
+    fn drop(&mut self) {
+        // Wake up the upload task so it notices that the client is gone.
+        if self.upload_task_spawned.load(Ordering::SeqCst) {
+            self.upload_notifier.notify_one();
+        }
+    }
+}
@@ -0,0 +1,372 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Dehydrated devices, as defined in [MSC3814].
+//!
+//! A dehydrated device is stored, encrypted, on the homeserver, and receives
+//! the room keys that are sent while the user has no other device online. A
+//! new session can rehydrate it to import those room keys, and decrypt the
+//! messages that were sent while the user was offline.
+//!
+//! The dehydrated device is encrypted with a pickle key that is stored in the
+//! secret storage, so every session that can open the secret store can
+//! rehydrate it.
+//!
+//! [MSC3814]: https://github.com/matrix-org/matrix-spec-proposals/pull/3814
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use matrix_sdk::Client;
+//! # use url::Url;
+//! # async {
+//! # let homeserver = Url::parse("http://example.com")?;
+//! # let client = Client::new(homeserver).await?;
+//! let secret_store = client
+//!     .encryption()
+//!     .secret_storage()
+//!     .open_secret_store("It's a secret to everybody")
+//!     .await?;
+//! let dehydrated_devices = client.encryption().dehydrated_devices();
+//!
+//! // Import the room keys we received while we were offline.
+//! dehydrated_devices.rehydrate(&secret_store).await?;
+//!
+//! // Replace the dehydrated device with a new one every week.
+//! dehydrated_devices
+//!     .start_rotation(&secret_store, Duration::from_secs(7 * 24 * 60 * 60))
+//!     .await?;
+//! # anyhow::Ok(()) };
+//! ```
+
+use std::{
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use futures_util::future::{select, Either};
+use matrix_sdk_base::crypto::dehydrated_devices::DehydrationError;
+use matrix_sdk_common::sleep::sleep;
+use rand::{thread_rng, RngCore};
+use ruma::{
+    api::client::{
+        dehydrated_device::{delete_dehydrated_device, get_dehydrated_device, get_events},
+        error::ErrorKind,
+    },
+    events::secret::request::SecretName,
+    serde::Base64,
+    OwnedDeviceId,
+};
+use thiserror::Error;
+use tokio::sync::Notify;
+use tracing::{debug, info, instrument, warn};
+use zeroize::Zeroizing;
+
+use super::secret_storage::{SecretStorageError, SecretStore};
+use crate::{client::WeakClient, executor::spawn, Client, Error};
+
+/// The name of the secret containing the pickle key of the dehydrated device.
+const PICKLE_KEY_SECRET_NAME: &str = "org.matrix.msc3814";
+
+/// The display name of the dehydrated devices we create.
+const DEVICE_DISPLAY_NAME: &str = "Dehydrated device";
+
+/// Error type for the dehydrated devices.
+#[derive(Debug, Error)]
+pub enum DehydratedDeviceError {
+    /// A request or another operation of the client failed.
+    #[error(transparent)]
+    Sdk(#[from] Error),
+
+    /// The pickle key couldn't be retrieved from or stored in the secret
+    /// storage.
+    #[error(transparent)]
+    SecretStorage(#[from] SecretStorageError),
+
+    /// The pickle key is missing from the secret storage.
+    #[error("the pickle key of the dehydrated device is missing from the secret storage")]
+    MissingPickleKey,
+
+    /// The pickle key in the secret storage isn't a valid key.
+    #[error("the pickle key of the dehydrated device is invalid")]
+    InvalidPickleKey,
+
+    /// The dehydrated device couldn't be created or rehydrated.
+    #[error(transparent)]
+    Dehydration(#[from] DehydrationError),
+}
+
+/// Convenience type alias for the results of the dehydrated devices.
+pub type Result<T, E = DehydratedDeviceError> = std::result::Result<T, E>;
+
+/// The state of the dehydrated devices, shared by all the clones of a client.
+#[derive(Default)]
+pub(crate) struct DehydrationClientState {
+    /// Stops the rotation task, if it is running.
+    rotation_stopper: StdMutex<Option<Arc<Notify>>>,
+}
+
+impl DehydrationClientState {
+    fn stop_rotation(&self) -> bool {
+        match self.rotation_stopper.lock().unwrap().take() {
+            Some(stopper) => {
+                stopper.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for DehydrationClientState {
+    fn drop(&mut self) {
+        // Wake up the rotation task so it notices that the client is gone.
+        self.stop_rotation();
+    }
+}
+
+/// A high-level API to manage the dehydrated device of our own user.
+///
+/// To get this, use [`Encryption::dehydrated_devices()`].
+///
+/// [`Encryption::dehydrated_devices()`]: crate::encryption::Encryption::dehydrated_devices
+#[derive(Debug, Clone)]
+pub struct DehydratedDevices {
+    /// The underlying client.
+    client: Client,
+}
+
+impl DehydratedDevices {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn data(&self) -> &DehydrationClientState {
+        &self.client.inner.dehydration_state
+    }
+
+    /// Create a new dehydrated device and upload it to the homeserver,
+    /// replacing the previous one, if any.
+    ///
+    /// If the secret storage doesn't contain a pickle key yet, a new one is
+    /// created and stored in it.
+    ///
+    /// Returns the ID of the new dehydrated device.
+    #[instrument(skip_all)]
+    pub async fn create(&self, secret_store: &SecretStore) -> Result<OwnedDeviceId> {
+        let pickle_key = self.get_or_create_pickle_key(secret_store).await?;
+        self.upload(&pickle_key).await
+    }
+
+    /// Rehydrate the dehydrated device of our user, if there is one, and
+    /// import the room keys that were sent to it.
+    ///
+    /// The dehydrated device can't be rehydrated again after this, a new one
+    /// should be created with [`DehydratedDevices::create()`] or
+    /// [`DehydratedDevices::start_rotation()`].
+    ///
+    /// Returns the number of room keys that were imported, or `None` if there
+    /// is no dehydrated device on the homeserver.
+    #[instrument(skip_all)]
+    pub async fn rehydrate(&self, secret_store: &SecretStore) -> Result<Option<usize>> {
+        let request = get_dehydrated_device::unstable::Request::new();
+        let response = match self.client.send(request, None).await {
+            Ok(response) => response,
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => {
+                debug!("There is no dehydrated device on the homeserver");
+                return Ok(None);
+            }
+            Err(error) => return Err(Error::from(error).into()),
+        };
+
+        let pickle_key = self
+            .get_pickle_key(secret_store)
+            .await?
+            .ok_or(DehydratedDeviceError::MissingPickleKey)?;
+
+        let rehydrated = {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+            olm.dehydrated_devices()
+                .rehydrate(&pickle_key, &response.device_id, response.device_data)
+                .await?
+        };
+
+        let mut room_key_count = 0;
+        let mut next_batch = None;
+
+        loop {
+            let mut request = get_events::unstable::Request::new(response.device_id.clone());
+            request.next_batch = next_batch;
+
+            let response = self.client.send(request, None).await.map_err(Error::from)?;
+
+            if response.events.is_empty() {
+                break;
+            }
+
+            room_key_count +=
+                rehydrated.receive_events(response.events).await.map_err(Error::from)?.len();
+            next_batch = response.next_batch;
+        }
+
+        info!(device_id = ?response.device_id, room_key_count, "Rehydrated the dehydrated device");
+
+        Ok(Some(room_key_count))
+    }
+
+    /// Upload a new dehydrated device now, then replace it with a new one
+    /// every `period`.
+    ///
+    /// Rotating the dehydrated device limits the number of room keys that
+    /// need to be imported when it is rehydrated, and makes sure that its
+    /// one-time keys don't run out.
+    ///
+    /// The rotation stops when the client is dropped, or when
+    /// [`DehydratedDevices::stop_rotation()`] is called. Calling this method
+    /// again replaces the previous rotation.
+    #[instrument(skip(self, secret_store))]
+    pub async fn start_rotation(&self, secret_store: &SecretStore, period: Duration) -> Result<()> {
+        let pickle_key = self.get_or_create_pickle_key(secret_store).await?;
+        self.upload(&pickle_key).await?;
+
+        let stopper = Arc::new(Notify::new());
+        if let Some(previous) =
+            self.data().rotation_stopper.lock().unwrap().replace(stopper.clone())
+        {
+            previous.notify_one();
+        }
+
+        spawn(rotation_task(WeakClient::from_client(&self.client), pickle_key, period, stopper));
+
+        Ok(())
+    }
+
+    /// Stop replacing the dehydrated device periodically.
+    ///
+    /// The current dehydrated device is left on the homeserver, use
+    /// [`DehydratedDevices::delete()`] to remove it.
+    pub fn stop_rotation(&self) {
+        if self.data().stop_rotation() {
+            debug!("Stopped the rotation of the dehydrated device");
+        }
+    }
+
+    /// Stop the rotation and delete the dehydrated device from the homeserver.
+    #[instrument(skip_all)]
+    pub async fn delete(&self) -> Result<()> {
+        self.stop_rotation();
+
+        let request = delete_dehydrated_device::unstable::Request::new();
+        match self.client.send(request, None).await {
+            Ok(_) => {
+                info!("Deleted the dehydrated device");
+                Ok(())
+            }
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => Ok(()),
+            Err(error) => Err(Error::from(error).into()),
+        }
+    }
+
+    /// Create a new dehydrated device pickled with the given key, and upload
+    /// it.
+    async fn upload(&self, pickle_key: &[u8; 32]) -> Result<OwnedDeviceId> {
+        let request = {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+            let device = olm.dehydrated_devices().create().await;
+            device.keys_for_upload(DEVICE_DISPLAY_NAME.to_owned(), pickle_key).await?
+        };
+
+        let device_id = self.client.send(request, None).await.map_err(Error::from)?.device_id;
+        info!(?device_id, "Uploaded a new dehydrated device");
+
+        Ok(device_id)
+    }
+
+    async fn get_pickle_key(
+        &self,
+        secret_store: &SecretStore,
+    ) -> Result<Option<Zeroizing<[u8; 32]>>> {
+        let Some(secret) = secret_store.get_secret(PICKLE_KEY_SECRET_NAME.into()).await? else {
+            return Ok(None);
+        };
+        let secret = Zeroizing::new(secret);
+
+        let bytes: Base64 =
+            Base64::parse(secret.as_str()).map_err(|_| DehydratedDeviceError::InvalidPickleKey)?;
+        let bytes = Zeroizing::new(bytes.into_inner());
+
+        let mut pickle_key = Zeroizing::new([0u8; 32]);
+        if bytes.len() != pickle_key.len() {
+            return Err(DehydratedDeviceError::InvalidPickleKey);
+        }
+        pickle_key.copy_from_slice(&bytes);
+
+        Ok(Some(pickle_key))
+    }
+
+    async fn get_or_create_pickle_key(
+        &self,
+        secret_store: &SecretStore,
+    ) -> Result<Zeroizing<[u8; 32]>> {
+        if let Some(pickle_key) = self.get_pickle_key(secret_store).await? {
+            return Ok(pickle_key);
+        }
+
+        let mut pickle_key = Zeroizing::new([0u8; 32]);
+        thread_rng().fill_bytes(pickle_key.as_mut_slice());
+
+        let encoded: Base64 = Base64::new(pickle_key.to_vec());
+        let secret = Zeroizing::new(encoded.encode());
+        let secret_name: SecretName = PICKLE_KEY_SECRET_NAME.into();
+        secret_store.put_secret(secret_name, &secret).await?;
+
+        info!("Stored a new pickle key for the dehydrated device in the secret storage");
+
+        Ok(pickle_key)
+    }
+}
+
+async fn rotation_task(
+    weak_client: WeakClient,
+    pickle_key: Zeroizing<[u8; 32]>,
+    period: Duration,
+    stopper: Arc<Notify>,
+) {
+    debug!(?period, "Starting the dehydrated device rotation task");
+
+    loop {
+        if let Either::Right(_) =
+            select(Box::pin(sleep(period)), Box::pin(stopper.notified())).await
+        {
+            break;
+        }
+
+        let Some(client) = weak_client.get() else {
+            break;
+        };
+
+        if let Err(error) = client.encryption().dehydrated_devices().upload(&pickle_key).await {
+            // The current dehydrated device is still usable, try again at the
+            // next period.
+            warn!("Failed to rotate the dehydrated device: {error}");
+        }
+    }
+
+    debug!("Stopping the dehydrated device rotation task");
+}
@@ -89,7 +89,7 @@ mod users;
 
 pub use devices::{Device, UserDevices};
 pub use matrix_sdk_base::crypto::types::MasterPubkey;
-pub use users::UserIdentity;
+pub use users::{IdentityUpdates, UserIdentity};
 
 /// Error for the manual verification step, when we manually sign users or
 /// devices.
@@ -12,11 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use matrix_sdk_base::{
     crypto::{
-        types::MasterPubkey, OwnUserIdentity as InnerOwnUserIdentity,
+        store::CryptoStoreError, types::MasterPubkey, OwnUserIdentity as InnerOwnUserIdentity,
         UserIdentity as InnerUserIdentity,
     },
     RoomMemberships,
@@ -26,7 +26,7 @@ use ruma::{
         key::verification::VerificationMethod,
         room::message::{MessageType, RoomMessageEventContent},
     },
-    UserId,
+    OwnedUserId, UserId,
 };
 use tokio::sync::RwLock;
 
@@ -395,6 +395,65 @@ impl UserIdentity {
             UserIdentities::Other(i) => i.inner.master_key(),
         }
     }
+
+    /// Has this identity changed its master key since we first saw it, without
+    /// the change having been acknowledged?
+    ///
+    /// When this returns `true`, the user most likely reset their identity and
+    /// the application should warn about it. The change can be acknowledged
+    /// using [`UserIdentity::acknowledge_identity_change()`].
+    ///
+    /// Our own identity never needs approval, this always returns `false` for
+    /// it.
+    pub fn identity_needs_user_approval(&self) -> bool {
+        match &self.inner {
+            UserIdentities::Own(_) => false,
+            UserIdentities::Other(i) => i.inner.identity_needs_user_approval(),
+        }
+    }
+
+    /// Acknowledge that the master key of this identity changed, and pin the
+    /// new master key.
+    ///
+    /// This is a no-op for our own identity.
+    pub async fn acknowledge_identity_change(&self) -> Result<(), CryptoStoreError> {
+        match &self.inner {
+            UserIdentities::Own(_) => Ok(()),
+            UserIdentities::Other(i) => i.inner.acknowledge_identity_change().await,
+        }
+    }
+
+    /// Was this identity verified at some point in the past?
+    ///
+    /// Always returns `false` for our own identity.
+    pub fn was_previously_verified(&self) -> bool {
+        match &self.inner {
+            UserIdentities::Own(_) => false,
+            UserIdentities::Other(i) => i.inner.was_previously_verified(),
+        }
+    }
+
+    /// Forget that this identity was previously verified.
+    ///
+    /// This is a no-op for our own identity.
+    pub async fn withdraw_verification(&self) -> Result<(), CryptoStoreError> {
+        match &self.inner {
+            UserIdentities::Own(_) => Ok(()),
+            UserIdentities::Other(i) => i.inner.withdraw_verification().await,
+        }
+    }
+}
+
+/// Updates to the user identities, as sent by
+/// [`Encryption::user_identities_stream()`].
+///
+/// [`Encryption::user_identities_stream()`]: crate::encryption::Encryption::user_identities_stream
+#[derive(Debug, Clone, Default)]
+pub struct IdentityUpdates {
+    /// Identities that we saw for the first time.
+    pub new: BTreeMap<OwnedUserId, UserIdentity>,
+    /// Identities that we already knew about, and that were updated.
+    pub changed: BTreeMap<OwnedUserId, UserIdentity>,
 }
 
 #[derive(Debug, Clone)]
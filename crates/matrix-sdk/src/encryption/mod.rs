@@ -24,13 +24,16 @@ use std::{
 };
 
 use eyeball::SharedObservable;
+use futures_core::Stream;
 use futures_util::{
-    future::try_join,
+    future::{select, try_join, Either},
+    pin_mut,
     stream::{self, StreamExt},
 };
 use matrix_sdk_base::crypto::{
-    store::locks::CryptoStoreLockGuard, OlmMachine, OutgoingRequest, RoomMessageRequest,
-    ToDeviceRequest,
+    store::{locks::CryptoStoreLockGuard, RoomKeyInfo},
+    OlmMachine, OutgoingRequest, RoomMessageRequest, ToDeviceRequest,
+    UserIdentities as CryptoUserIdentities,
 };
 use ruma::{
     api::client::{
@@ -52,23 +55,32 @@ use ruma::{
         },
         ImageInfo, MediaSource, ThumbnailInfo,
     },
-    DeviceId, OwnedDeviceId, OwnedUserId, TransactionId, UserId,
+    DeviceId, OwnedDeviceId, OwnedRoomId, OwnedUserId, TransactionId, UserId,
 };
 use tokio::sync::RwLockReadGuard;
 use tracing::{debug, instrument, trace, warn};
 
 use crate::{
     attachment::{AttachmentInfo, Thumbnail},
+    client::WeakClient,
     encryption::{
-        identities::{Device, UserDevices},
+        backups::Backups,
+        dehydrated_devices::DehydratedDevices,
+        identities::{Device, IdentityUpdates, UserDevices, UserIdentity},
+        recovery::Recovery,
+        secret_storage::SecretStorage,
         verification::{SasVerification, Verification, VerificationRequest},
     },
     error::HttpResult,
     room, Client, Error, Result, TransmissionProgress,
 };
 
+pub mod backups;
+pub mod dehydrated_devices;
 mod futures;
 pub mod identities;
+pub mod recovery;
+pub mod secret_storage;
 pub mod verification;
 
 pub use matrix_sdk_base::crypto::{
@@ -76,9 +88,10 @@ pub use matrix_sdk_base::crypto::{
         SessionCreationError as MegolmSessionCreationError,
         SessionExportError as OlmSessionExportError,
     },
-    vodozemac, CrossSigningStatus, CryptoStoreError, DecryptorError, EventError, KeyExportError,
-    LocalTrust, MediaEncryptionInfo, MegolmError, OlmError, RoomKeyImportResult, SecretImportError,
-    SessionCreationError, SignatureError, VERSION,
+    vodozemac, CollectStrategy, CrossSigningStatus, CryptoStoreError, DecryptorError, EventError,
+    KeyExportError, LocalTrust, MediaEncryptionInfo, MegolmError, OlmError, RoomKeyImportResult,
+    SecretImportError, SessionCreationError, SessionRecipientCollectionError, SignatureError,
+    UnwedgingPolicy, WedgedDevicesStats, VERSION,
 };
 
 pub use self::futures::PrepareEncryptedFile;
@@ -448,8 +461,80 @@ impl Client {
             })
             .await;
 
+        self.encryption().backups().maybe_trigger_backup().await;
+        self.encryption().recovery().update_state_after_sync().await;
+
         Ok(())
     }
+
+    /// Get the users that invited us to the given rooms, for the ones we're
+    /// currently invited to.
+    ///
+    /// The invites are dropped once we join the rooms, so this must be called
+    /// before a sync response is processed, see
+    /// [`Client::handle_room_key_history()`].
+    pub(crate) async fn room_key_history_inviters(
+        &self,
+        room_ids: &[OwnedRoomId],
+    ) -> BTreeMap<OwnedRoomId, OwnedUserId> {
+        let mut inviters = BTreeMap::new();
+
+        for room_id in room_ids {
+            let Some(room) = self.get_invited_room(room_id) else { continue };
+
+            match room.invitee().await {
+                Ok(invitee) => {
+                    inviters.insert(room_id.clone(), invitee.event().sender().to_owned());
+                }
+                Err(e) => warn!(?room_id, "Failed to get the sender of the invite: {e}"),
+            }
+        }
+
+        inviters
+    }
+
+    /// Import the room key history that was shared with us for the given rooms
+    /// once we joined them, or discard it once we left them, as defined in
+    /// [MSC3061].
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub(crate) async fn handle_room_key_history(
+        &self,
+        room_ids: &[OwnedRoomId],
+        inviters: BTreeMap<OwnedRoomId, OwnedUserId>,
+    ) {
+        let olm = self.olm_machine().await;
+        let Some(olm) = olm.as_ref() else { return };
+
+        for room_id in room_ids {
+            let Some(room) = self.get_room(room_id) else { continue };
+
+            let result = match room {
+                room::Room::Joined(_) => match inviters.get(room_id) {
+                    Some(inviter) => {
+                        olm.import_room_key_history(room_id, inviter).await.map(|_| ())
+                    }
+                    None => continue,
+                },
+                room::Room::Left(_) => olm.discard_room_key_history(room_id).await,
+                _ => continue,
+            };
+
+            if let Err(e) = result {
+                warn!(?room_id, "Failed to handle the room key history: {e}");
+            }
+        }
+    }
+}
+
+/// An update sent by [`Encryption::room_keys_received_stream()`].
+#[derive(Debug, Clone)]
+pub enum RoomKeysUpdate {
+    /// These room keys were received or updated.
+    Received(Vec<RoomKeyInfo>),
+    /// Some updates might have been missed, any room key might have been
+    /// received or updated.
+    Unknown,
 }
 
 /// A high-level API to manage the client's encryption.
@@ -494,6 +579,105 @@ impl Encryption {
         }
     }
 
+    /// Get the strategy used to select the devices that receive room keys,
+    /// for the rooms that don't have a strategy of their own.
+    ///
+    /// Defaults to [`CollectStrategy::AllDevices`].
+    pub async fn room_key_sharing_strategy(&self) -> Result<CollectStrategy> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.store().get_sharing_strategy().await?)
+    }
+
+    /// Set the strategy used to select the devices that receive room keys,
+    /// for the rooms that don't have a strategy of their own.
+    ///
+    /// The strategy is persisted in the crypto store. If a strategy prevents
+    /// a room key from being shared, sending a message fails with an
+    /// [`OlmError::SessionRecipientCollectionError`].
+    ///
+    /// See [`Joined::set_room_key_sharing_strategy()`] to override the
+    /// strategy of a single room.
+    ///
+    /// [`Joined::set_room_key_sharing_strategy()`]: crate::room::Joined::set_room_key_sharing_strategy
+    pub async fn set_room_key_sharing_strategy(&self, strategy: CollectStrategy) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.store().set_sharing_strategy(strategy).await?)
+    }
+
+    /// Is the history of encrypted rooms shared with the users we invite to
+    /// them, as defined in [MSC3061]?
+    ///
+    /// Defaults to `false`.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub async fn is_room_key_history_sharing_enabled(&self) -> Result<bool> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.store().is_room_key_history_sharing_enabled().await?)
+    }
+
+    /// Set whether the history of encrypted rooms is shared with the users we
+    /// invite to them, as defined in [MSC3061].
+    ///
+    /// When enabled, [`Joined::invite_user_by_id()`] sends the room keys that
+    /// were created while the history of the room was visible to all of its
+    /// members to the devices of the invited user. They are imported once the
+    /// invite is accepted with [`Invited::accept_invitation()`]. The setting is
+    /// persisted in the crypto store.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    /// [`Joined::invite_user_by_id()`]: crate::room::Joined::invite_user_by_id
+    /// [`Invited::accept_invitation()`]: crate::room::Invited::accept_invitation
+    pub async fn set_room_key_history_sharing_enabled(&self, enabled: bool) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.store().set_room_key_history_sharing_enabled(enabled).await?)
+    }
+
+    /// Get the policy deciding whether new Olm sessions are created
+    /// automatically with the devices that can't decrypt our to-device
+    /// messages anymore.
+    ///
+    /// Defaults to being enabled, with at most one new session per device and
+    /// hour.
+    pub async fn unwedging_policy(&self) -> Result<UnwedgingPolicy> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.store().get_unwedging_policy().await?)
+    }
+
+    /// Set the policy deciding whether new Olm sessions are created
+    /// automatically with the devices that can't decrypt our to-device
+    /// messages anymore.
+    ///
+    /// The policy is persisted in the crypto store.
+    pub async fn set_unwedging_policy(&self, policy: UnwedgingPolicy) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.store().set_unwedging_policy(policy).await?)
+    }
+
+    /// Get statistics about the devices we had a wedged Olm session with,
+    /// grouped by user.
+    ///
+    /// The statistics are kept in memory only, and are reset whenever the
+    /// encryption state is reloaded.
+    pub async fn wedged_devices_stats(&self) -> BTreeMap<OwnedUserId, WedgedDevicesStats> {
+        if let Some(machine) = self.client.olm_machine().await.as_ref() {
+            machine.wedged_devices_stats()
+        } else {
+            BTreeMap::new()
+        }
+    }
+
     /// Get a verification object with the given flow id.
     pub async fn get_verification(&self, user_id: &UserId, flow_id: &str) -> Option<Verification> {
         let olm = self.client.olm_machine().await;
@@ -639,9 +823,7 @@ impl Encryption {
     pub async fn get_user_identity(
         &self,
         user_id: &UserId,
-    ) -> Result<Option<crate::encryption::identities::UserIdentity>, CryptoStoreError> {
-        use crate::encryption::identities::UserIdentity;
-
+    ) -> Result<Option<UserIdentity>, CryptoStoreError> {
         let olm = self.client.olm_machine().await;
         let Some(olm) = olm.as_ref() else { return Ok(None) };
         let identity = olm.get_identity(user_id, None).await?;
@@ -656,6 +838,123 @@ impl Encryption {
         }))
     }
 
+    /// Receive notifications of room keys being received as a [`Stream`].
+    ///
+    /// Each time a room key is updated in any way, an update will be sent to
+    /// the stream. Updates that happen at the same time are batched into a
+    /// [`Vec`]. This includes room keys received from other users or our own
+    /// devices, imported with [`Encryption::import_room_keys()`] or
+    /// downloaded from the key backup.
+    ///
+    /// This can be used to retry decrypting the events that couldn't be
+    /// decrypted before the room key arrived.
+    ///
+    /// The stream keeps working when the [`OlmMachine`] is recreated, e.g.
+    /// because another process changed the crypto store. When updates might
+    /// have been missed, because the reader lagged too far behind or the
+    /// `OlmMachine` was recreated, [`RoomKeysUpdate::Unknown`] is sent to the
+    /// stream.
+    ///
+    /// The stream ends when the [`Client`] is dropped.
+    pub fn room_keys_received_stream(&self) -> impl Stream<Item = RoomKeysUpdate> {
+        let weak_client = WeakClient::from_client(&self.client);
+        let mut olm_machine_changes = self.client.base_client().subscribe_to_olm_machine_changes();
+
+        async_stream::stream! {
+            // Whether updates might have been missed while we were switching to a
+            // new `OlmMachine`.
+            let mut missed_updates = false;
+
+            loop {
+                let Some(client) = weak_client.get() else {
+                    break;
+                };
+
+                let room_keys = client
+                    .olm_machine()
+                    .await
+                    .as_ref()
+                    .map(|olm| olm.store().room_keys_received_stream_with_lags());
+
+                // Don't keep the client alive while we're waiting for updates.
+                drop(client);
+
+                // Only tell the reader once we're subscribed to the new store, so the
+                // updates it triggers aren't missed too.
+                if missed_updates {
+                    yield RoomKeysUpdate::Unknown;
+                }
+
+                let Some(room_keys) = room_keys else {
+                    // Wait for the `OlmMachine` to be created.
+                    if olm_machine_changes.next().await.is_none() {
+                        break;
+                    }
+
+                    missed_updates = true;
+                    continue;
+                };
+
+                pin_mut!(room_keys);
+
+                loop {
+                    match select(room_keys.next(), olm_machine_changes.next()).await {
+                        Either::Left((Some(Ok(room_keys)), _)) => {
+                            yield RoomKeysUpdate::Received(room_keys);
+                        }
+                        Either::Left((Some(Err(error)), _)) => {
+                            warn!("room_keys_received_stream missed updates: {error}");
+                            yield RoomKeysUpdate::Unknown;
+                        }
+                        // The `OlmMachine` was recreated and its store doesn't
+                        // send updates anymore, subscribe to the new one.
+                        Either::Left((None, _)) | Either::Right((Some(()), _)) => break,
+                        // The client was dropped.
+                        Either::Right((None, _)) => return,
+                    }
+                }
+
+                missed_updates = true;
+            }
+        }
+    }
+
+    /// Receive notifications of user identities being created or updated as a
+    /// [`Stream`].
+    ///
+    /// Each time identities are received from the server or changed locally,
+    /// an update will be sent to the stream. This can be used to notice that
+    /// a user has reset their identity, see
+    /// [`UserIdentity::identity_needs_user_approval()`].
+    ///
+    /// If the reader of the stream lags too far behind, a warning will be
+    /// logged and items will be dropped.
+    pub async fn user_identities_stream(&self) -> Result<impl Stream<Item = IdentityUpdates>> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let client = self.client.clone();
+
+        Ok(olm.store().user_identities_stream().map(move |updates| {
+            let wrap = |(user_id, identity): (OwnedUserId, CryptoUserIdentities)| {
+                let identity = match identity {
+                    CryptoUserIdentities::Own(i) => UserIdentity::new_own(client.clone(), i),
+                    CryptoUserIdentities::Other(i) => {
+                        let room = client.get_dm_room(&user_id);
+                        UserIdentity::new(client.clone(), i, room)
+                    }
+                };
+
+                (user_id, identity)
+            };
+
+            IdentityUpdates {
+                new: updates.new.into_iter().map(wrap).collect(),
+                changed: updates.changed.into_iter().map(wrap).collect(),
+            }
+        }))
+    }
+
     /// Create and upload a new cross signing identity.
     ///
     /// # Arguments
@@ -713,6 +1012,26 @@ impl Encryption {
         Ok(())
     }
 
+    /// Get the backups manager of the client.
+    pub fn backups(&self) -> Backups {
+        Backups::new(self.client.clone())
+    }
+
+    /// Get the secret storage manager of the client.
+    pub fn secret_storage(&self) -> SecretStorage {
+        SecretStorage::new(self.client.clone())
+    }
+
+    /// Get the recovery manager of the client.
+    pub fn recovery(&self) -> Recovery {
+        Recovery::new(self.client.clone())
+    }
+
+    /// Get the manager of the dehydrated devices of our own user.
+    pub fn dehydrated_devices(&self) -> DehydratedDevices {
+        DehydratedDevices::new(self.client.clone())
+    }
+
     /// Export E2EE keys that match the given predicate encrypting them with the
     /// given passphrase.
     ///
@@ -878,7 +1197,7 @@ impl Encryption {
         // If we don't get the lock immediately, then it is already acquired by another
         // process, and we'll get to reload next time we acquire the lock.
         {
-            let guard = lock.try_lock_once().await?;
+            let guard = lock.try_lock_once().await.map_err(CryptoStoreError::from)?;
             if guard.is_some() {
                 olm_machine.initialize_crypto_store_generation().await?;
             }
@@ -919,7 +1238,7 @@ impl Encryption {
         max_backoff: Option<u32>,
     ) -> Result<Option<CryptoStoreLockGuard>, Error> {
         if let Some(lock) = self.client.inner.cross_process_crypto_store_lock.get() {
-            let guard = lock.spin_lock(max_backoff).await?;
+            let guard = lock.spin_lock(max_backoff).await.map_err(CryptoStoreError::from)?;
 
             self.on_lock_newly_acquired().await?;
 
@@ -935,7 +1254,7 @@ impl Encryption {
     /// Returns a guard to the lock, if it was obtained.
     pub async fn try_lock_store_once(&self) -> Result<Option<CryptoStoreLockGuard>, Error> {
         if let Some(lock) = self.client.inner.cross_process_crypto_store_lock.get() {
-            let maybe_guard = lock.try_lock_once().await?;
+            let maybe_guard = lock.try_lock_once().await.map_err(CryptoStoreError::from)?;
 
             if maybe_guard.is_some() {
                 self.on_lock_newly_acquired().await?;
@@ -952,7 +1271,9 @@ impl Encryption {
 mod tests {
     use std::time::Duration;
 
-    use matrix_sdk_base::SessionMeta;
+    use assert_matches::assert_matches;
+    use futures_util::{pin_mut, StreamExt};
+    use matrix_sdk_base::{crypto::EncryptionSettings, SessionMeta};
     use matrix_sdk_test::{
         async_test, test_json, GlobalAccountDataTestEvent, JoinedRoomBuilder, StateTestEvent,
         SyncResponseBuilder,
@@ -960,7 +1281,7 @@ mod tests {
     use ruma::{
         device_id, event_id,
         events::{reaction::ReactionEventContent, relation::Annotation},
-        user_id,
+        room_id, user_id,
     };
     use serde_json::json;
     use wiremock::{
@@ -968,6 +1289,7 @@ mod tests {
         Mock, MockServer, ResponseTemplate,
     };
 
+    use super::RoomKeysUpdate;
     use crate::{
         config::RequestConfig,
         matrix_auth::{Session, SessionTokens},
@@ -1094,11 +1416,14 @@ mod tests {
         assert!(found_room.get_member_no_sync(user_id).await.unwrap().is_some());
     }
 
+    /// Environment variable used to pass the path of the shared database to the
+    /// child process spawned by `test_cross_process_lock_reloads_olm_machine`.
     #[cfg(feature = "sqlite")]
-    #[async_test]
-    async fn test_generation_counter_invalidates_olm_machine() {
-        // Create two clients using the same sqlite database.
-        let sqlite_path = std::env::temp_dir().join("generation_counter_sqlite.db");
+    const CROSS_PROCESS_DB_ENV: &str = "MATRIX_SDK_CROSS_PROCESS_TEST_DB";
+
+    /// Create a logged-in client using a sqlite database at the given path.
+    #[cfg(feature = "sqlite")]
+    async fn sqlite_client(sqlite_path: impl AsRef<std::path::Path>) -> Client {
         let session = Session {
             meta: SessionMeta {
                 user_id: user_id!("@example:localhost").to_owned(),
@@ -1107,23 +1432,59 @@ mod tests {
             tokens: SessionTokens { access_token: "1234".to_owned(), refresh_token: None },
         };
 
-        let client1 = Client::builder()
+        let client = Client::builder()
             .homeserver_url("http://localhost:1234")
             .request_config(RequestConfig::new().disable_retry())
-            .sqlite_store(&sqlite_path, None)
+            .sqlite_store(sqlite_path, None)
             .build()
             .await
             .unwrap();
-        client1.matrix_auth().restore_session(session.clone()).await.unwrap();
+        client.matrix_auth().restore_session(session).await.unwrap();
 
-        let client2 = Client::builder()
-            .homeserver_url("http://localhost:1234")
-            .request_config(RequestConfig::new().disable_retry())
-            .sqlite_store(sqlite_path, None)
-            .build()
+        client
+    }
+
+    #[async_test]
+    async fn test_room_keys_received_stream_survives_olm_machine_regeneration() {
+        let client = logged_in_client(None).await;
+        let room_id = room_id!("!test:localhost");
+
+        let room_keys_stream = client.encryption().room_keys_received_stream();
+        pin_mut!(room_keys_stream);
+
+        // Recreate the `OlmMachine`, like when another process wrote into the crypto
+        // store.
+        client.base_client().regenerate_olm().await.unwrap();
+
+        // We're told that updates might have been missed.
+        assert_matches!(room_keys_stream.next().await, Some(RoomKeysUpdate::Unknown));
+
+        // The room keys received by the new `OlmMachine` are sent to the stream.
+        client
+            .olm_machine()
+            .await
+            .as_ref()
+            .unwrap()
+            .share_room_key(room_id, std::iter::empty(), EncryptionSettings::default())
             .await
             .unwrap();
-        client2.matrix_auth().restore_session(session).await.unwrap();
+
+        assert_matches!(
+            room_keys_stream.next().await,
+            Some(RoomKeysUpdate::Received(room_keys)) => {
+                assert_eq!(room_keys.len(), 1);
+                assert_eq!(room_keys[0].room_id, room_id);
+            }
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[async_test]
+    async fn test_generation_counter_invalidates_olm_machine() {
+        // Create two clients using the same sqlite database.
+        let sqlite_path = std::env::temp_dir().join("generation_counter_sqlite.db");
+        let client1 = sqlite_client(&sqlite_path).await;
+        let client2 = sqlite_client(&sqlite_path).await;
 
         // When the lock isn't enabled, any attempt at locking won't return a guard.
         let guard = client1.encryption().try_lock_store_once().await.unwrap();
@@ -1176,4 +1537,68 @@ mod tests {
         let olm_machine = client1.olm_machine().await.clone().expect("must have an olm machine");
         assert!(!initial_olm_machine.same_as(&olm_machine));
     }
+
+    /// The half of `test_cross_process_lock_reloads_olm_machine` that runs in a
+    /// child process.
+    #[cfg(feature = "sqlite")]
+    #[async_test]
+    #[ignore = "only run as a child process of test_cross_process_lock_reloads_olm_machine"]
+    async fn test_cross_process_lock_child() {
+        let Ok(sqlite_path) = std::env::var(CROSS_PROCESS_DB_ENV) else { return };
+
+        let client = sqlite_client(sqlite_path).await;
+        client.encryption().enable_cross_process_store_lock("child".to_owned()).await.unwrap();
+
+        let guard = client.encryption().spin_lock_store(None).await.unwrap();
+        assert!(guard.is_some());
+
+        // Write something into the store that the parent process has cached.
+        let olm_machine = client.olm_machine().await.clone().expect("must have an olm machine");
+        olm_machine.update_tracked_users([user_id!("@bob:localhost")]).await.unwrap();
+
+        // Release the lock before exiting, so the parent doesn't have to wait for the
+        // lease to expire.
+        drop(guard);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[async_test]
+    async fn test_cross_process_lock_reloads_olm_machine() {
+        let sqlite_dir = tempfile::tempdir().unwrap();
+
+        let client = sqlite_client(sqlite_dir.path()).await;
+        client.encryption().enable_cross_process_store_lock("parent".to_owned()).await.unwrap();
+
+        {
+            let guard = client.encryption().spin_lock_store(None).await.unwrap();
+            assert!(guard.is_some());
+
+            // Load the tracked users into the in-memory caches.
+            assert!(client.encryption().tracked_users().await.unwrap().is_empty());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Run another process against the same database, which takes the lock and
+        // writes into the crypto store.
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "encryption::tests::test_cross_process_lock_child",
+                "--ignored",
+                "--nocapture",
+            ])
+            .env(CROSS_PROCESS_DB_ENV, sqlite_dir.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        // When taking the lock again, the generation counter tells us that the other
+        // process wrote into the store, so we don't see stale data from our caches.
+        let guard = client.encryption().spin_lock_store(None).await.unwrap();
+        assert!(guard.is_some());
+
+        let tracked_users = client.encryption().tracked_users().await.unwrap();
+        assert!(tracked_users.contains(user_id!("@bob:localhost")));
+    }
 }
@@ -0,0 +1,272 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Account recovery, built on top of the server-side key backups and the
+//! secret storage.
+//!
+//! Enabling recovery creates a backup of the room keys, and stores the backup
+//! recovery key and the private cross-signing keys in the secret storage. A
+//! single recovery key, or the passphrase it was derived from, is then enough
+//! to restore all the secrets of the account on a new session.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use matrix_sdk::{encryption::recovery::RecoveryState, Client};
+//! # use url::Url;
+//! # async {
+//! # let homeserver = Url::parse("http://example.com")?;
+//! # let client = Client::new(homeserver).await?;
+//! let recovery = client.encryption().recovery();
+//!
+//! match recovery.state() {
+//!     RecoveryState::Disabled => {
+//!         let recovery_key = recovery.enable(None, None).await?;
+//!         println!("Your recovery key is {recovery_key}");
+//!     }
+//!     RecoveryState::Incomplete => {
+//!         // Restore the secrets with the recovery key the user entered.
+//!         recovery.recover("EsTj 3yST y93F SLpB jJsz eAXc 2XzA ygD3 w69H fGaN TKBj jXEd").await?;
+//!     }
+//!     _ => {}
+//! }
+//! # anyhow::Ok(()) };
+//! ```
+
+use eyeball::Subscriber;
+use matrix_sdk_base::crypto::secret_storage::DEFAULT_KEY_EVENT_TYPE;
+use ruma::{api::client::uiaa::AuthData, serde::Raw};
+use serde_json::json;
+use tracing::{info, instrument, trace, warn};
+
+use super::backups::BackupError;
+use crate::{Client, Error};
+
+mod types;
+
+pub use self::types::{RecoveryError, RecoveryState};
+
+/// Convenience type alias for the results of the recovery.
+pub type Result<T, E = RecoveryError> = std::result::Result<T, E>;
+
+/// A high-level API to manage the recovery of the account.
+///
+/// To get this, use [`Encryption::recovery()`].
+///
+/// [`Encryption::recovery()`]: crate::encryption::Encryption::recovery
+#[derive(Debug, Clone)]
+pub struct Recovery {
+    /// The underlying client.
+    client: Client,
+}
+
+impl Recovery {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn set_state(&self, state: RecoveryState) {
+        trace!(?state, "Recovery state changed");
+        self.client.inner.recovery_state.set_if_not_eq(state);
+    }
+
+    /// Get the current state of the recovery.
+    pub fn state(&self) -> RecoveryState {
+        self.client.inner.recovery_state.get()
+    }
+
+    /// Get a stream of updates of the state of the recovery.
+    ///
+    /// The current state can be retrieved with [`Recovery::state()`].
+    pub fn state_stream(&self) -> Subscriber<RecoveryState> {
+        self.client.inner.recovery_state.subscribe()
+    }
+
+    /// Enable recovery for this account.
+    ///
+    /// This bootstraps cross-signing if needed, creates a new backup version
+    /// if backups aren't enabled, then stores the private cross-signing keys
+    /// and the backup recovery key in the secret storage, with a new secret
+    /// storage key.
+    ///
+    /// Returns the base58 encoded recovery key, that should be shown to the
+    /// user.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase to derive the recovery key from. If
+    /// this is `None`, a random key is created.
+    ///
+    /// * `auth_data` - The authentication data to upload the cross-signing
+    /// keys, if they need to be bootstrapped. See
+    /// [`Encryption::bootstrap_cross_signing()`].
+    ///
+    /// [`Encryption::bootstrap_cross_signing()`]: crate::encryption::Encryption::bootstrap_cross_signing
+    #[instrument(skip_all)]
+    pub async fn enable(
+        &self,
+        passphrase: Option<&str>,
+        auth_data: Option<AuthData>,
+    ) -> Result<String> {
+        let encryption = self.client.encryption();
+
+        let status = encryption.cross_signing_status().await.ok_or(Error::NoOlmMachine)?;
+        if !status.has_master && !status.has_self_signing && !status.has_user_signing {
+            info!("Bootstrapping cross-signing");
+            encryption.bootstrap_cross_signing(auth_data).await?;
+        }
+
+        let backups = encryption.backups();
+        if !backups.are_enabled().await {
+            backups.create().await?;
+        }
+
+        let recovery_key = self.create_secret_store(passphrase).await?;
+        info!("Recovery is enabled");
+
+        Ok(recovery_key)
+    }
+
+    /// Restore the secrets of the account on this session, with the recovery
+    /// key or the passphrase it was derived from.
+    ///
+    /// The private cross-signing keys and the backup recovery key are
+    /// imported from the secret storage, and room keys start being backed up
+    /// if there is a backup version on the homeserver.
+    #[instrument(skip_all)]
+    pub async fn recover(&self, recovery_key: &str) -> Result<()> {
+        let encryption = self.client.encryption();
+
+        let secret_store = encryption.secret_storage().open_secret_store(recovery_key).await?;
+        secret_store.import_secrets().await?;
+
+        let backups = encryption.backups();
+        if !backups.are_enabled().await && backups.exists_on_server().await? {
+            match backups.enable().await {
+                Ok(()) => {}
+                // The secret storage doesn't contain a usable backup recovery
+                // key, the state will be reported as incomplete.
+                Err(Error::Backup(
+                    error @ (BackupError::MissingRecoveryKey | BackupError::RecoveryKeyMismatch),
+                )) => {
+                    warn!("Couldn't enable backups with the recovered secrets: {error}");
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        self.update_state(true).await;
+
+        Ok(())
+    }
+
+    /// Replace the recovery key with a new one.
+    ///
+    /// The secrets known by this session are stored again in the secret
+    /// storage, with the new key. The previous recovery key can't be used
+    /// anymore.
+    ///
+    /// Returns the new base58 encoded recovery key, that should be shown to
+    /// the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase to derive the new recovery key from. If
+    /// this is `None`, a random key is created.
+    #[instrument(skip_all)]
+    pub async fn reset_key(&self, passphrase: Option<&str>) -> Result<String> {
+        let recovery_key = self.create_secret_store(passphrase).await?;
+        info!("The recovery key was reset");
+
+        Ok(recovery_key)
+    }
+
+    /// Disable recovery for this account.
+    ///
+    /// This deletes the current backup version from the homeserver, and
+    /// removes the default secret storage key, so the secrets stored with it
+    /// can't be found anymore.
+    #[instrument(skip_all)]
+    pub async fn disable(&self) -> Result<()> {
+        self.client.encryption().backups().delete().await?;
+
+        // Account data can't be deleted, so we replace the default key with an
+        // empty content instead, like other clients do.
+        let content = Raw::new(&json!({})).map_err(Error::from)?.cast();
+        self.client.account().set_account_data_raw(DEFAULT_KEY_EVENT_TYPE.into(), content).await?;
+
+        self.set_state(RecoveryState::Disabled);
+        info!("Recovery is disabled");
+
+        Ok(())
+    }
+
+    /// Create a new default secret storage key and export our secrets with
+    /// it.
+    ///
+    /// Returns the base58 encoded secret storage key.
+    async fn create_secret_store(&self, passphrase: Option<&str>) -> Result<String> {
+        let secret_store =
+            self.client.encryption().secret_storage().create_secret_store(passphrase).await?;
+        secret_store.export_secrets().await?;
+
+        self.update_state(true).await;
+
+        Ok(secret_store.secret_storage_key())
+    }
+
+    /// Update the state from the default secret storage key we received in the
+    /// account data during the sync.
+    pub(crate) async fn update_state_after_sync(&self) {
+        let content =
+            match self.client.account().account_data_raw(DEFAULT_KEY_EVENT_TYPE.into()).await {
+                Ok(content) => content,
+                Err(error) => {
+                    warn!("Failed to load the default secret storage key: {error}");
+                    return;
+                }
+            };
+
+        let secret_storage_enabled = match content {
+            Some(content) => matches!(content.get_field::<String>("key"), Ok(Some(_))),
+            // The account data might not have been received yet, only consider
+            // recovery as disabled if we don't know anything better.
+            None if self.state() == RecoveryState::Unknown => false,
+            None => return,
+        };
+
+        self.update_state(secret_storage_enabled).await;
+    }
+
+    async fn update_state(&self, secret_storage_enabled: bool) {
+        let state = if secret_storage_enabled {
+            let encryption = self.client.encryption();
+
+            let has_cross_signing_keys =
+                encryption.cross_signing_status().await.is_some_and(|status| {
+                    status.has_master && status.has_self_signing && status.has_user_signing
+                });
+
+            if has_cross_signing_keys && encryption.backups().are_enabled().await {
+                RecoveryState::Enabled
+            } else {
+                RecoveryState::Incomplete
+            }
+        } else {
+            RecoveryState::Disabled
+        };
+
+        self.set_state(state);
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use thiserror::Error;
+
+use crate::encryption::secret_storage::SecretStorageError;
+
+/// The states the recovery support of the account can be in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecoveryState {
+    /// The state of recovery is not known yet, it is computed after the first
+    /// sync.
+    #[default]
+    Unknown,
+
+    /// Recovery is enabled, and this session has all the secrets it needs to
+    /// back up room keys and to verify other devices.
+    Enabled,
+
+    /// Recovery is not enabled for this account.
+    Disabled,
+
+    /// Recovery is enabled for this account, but this session doesn't have
+    /// all the secrets. It can be completed with [`Recovery::recover()`].
+    ///
+    /// [`Recovery::recover()`]: super::Recovery::recover
+    Incomplete,
+}
+
+/// Error type for the recovery.
+#[derive(Debug, Error)]
+pub enum RecoveryError {
+    /// A request or another operation of the client failed.
+    #[error(transparent)]
+    Sdk(#[from] crate::Error),
+
+    /// The secret storage failed to store or retrieve the secrets.
+    #[error(transparent)]
+    SecretStorage(#[from] SecretStorageError),
+}
@@ -0,0 +1,209 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for the server-side secret storage, also known as SSSS.
+//!
+//! The secret storage allows clients to store secrets, like the private
+//! cross-signing keys or the backup recovery key, encrypted in the account
+//! data of the user. A new device can then fetch and decrypt those secrets
+//! with the secret storage key, or with the passphrase the key was derived
+//! from, without needing another device to be online.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use matrix_sdk::Client;
+//! # use url::Url;
+//! # async {
+//! # let homeserver = Url::parse("http://example.com")?;
+//! # let client = Client::new(homeserver).await?;
+//! let secret_storage = client.encryption().secret_storage();
+//!
+//! if secret_storage.is_enabled().await? {
+//!     // Unlock the secret storage with the key the user entered, and import
+//!     // the secrets it contains into our crypto store.
+//!     let secret_store = secret_storage
+//!         .open_secret_store("It's a secret to everybody")
+//!         .await?;
+//!     secret_store.import_secrets().await?;
+//! } else {
+//!     // Create a new secret storage key, and store our secrets with it.
+//!     let secret_store = secret_storage.create_secret_store(None).await?;
+//!     secret_store.export_secrets().await?;
+//!
+//!     println!(
+//!         "Your secret storage key is {}",
+//!         secret_store.secret_storage_key()
+//!     );
+//! }
+//! # anyhow::Ok(()) };
+//! ```
+
+use matrix_sdk_base::crypto::{
+    backups::DecodeError as RecoveryKeyDecodeError,
+    secret_storage::{
+        DecodeError, DecryptionError, SecretStorageDefaultKeyEventContent, SecretStorageKey,
+        SecretStorageKeyEventContent, DEFAULT_KEY_EVENT_TYPE, KEY_EVENT_TYPE_PREFIX,
+    },
+    CryptoStoreError, SecretImportError,
+};
+use ruma::{events::GlobalAccountDataEventType, serde::Raw};
+use thiserror::Error;
+
+use crate::Client;
+
+mod secret_store;
+
+pub use self::secret_store::SecretStore;
+
+/// Error type for the secret storage.
+#[derive(Debug, Error)]
+pub enum SecretStorageError {
+    /// A request or another operation of the client failed.
+    #[error(transparent)]
+    Sdk(#[from] crate::Error),
+
+    /// The secret storage isn't set up, there is no default secret storage
+    /// key.
+    #[error("the secret storage isn't set up, there is no default secret storage key")]
+    NotEnabled,
+
+    /// The description of the secret storage key is missing from the account
+    /// data.
+    #[error("the description of the secret storage key {0} is missing")]
+    MissingKeyInfo(String),
+
+    /// The given secret storage key or passphrase is invalid, or doesn't match
+    /// the default secret storage key.
+    #[error(transparent)]
+    SecretStorageKey(#[from] DecodeError),
+
+    /// A secret couldn't be decrypted.
+    #[error(transparent)]
+    Decryption(#[from] DecryptionError),
+
+    /// A decrypted secret isn't valid UTF-8.
+    #[error("the decrypted secret isn't valid UTF-8")]
+    InvalidSecret,
+
+    /// An account data event couldn't be serialized or deserialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The cross-signing keys couldn't be imported.
+    #[error(transparent)]
+    SecretImport(#[from] SecretImportError),
+
+    /// The backup recovery key couldn't be decoded.
+    #[error(transparent)]
+    RecoveryKey(#[from] RecoveryKeyDecodeError),
+
+    /// The crypto store failed to load or save a secret.
+    #[error(transparent)]
+    CryptoStore(#[from] CryptoStoreError),
+}
+
+/// Convenience type alias for the results of the secret storage.
+pub type Result<T, E = SecretStorageError> = std::result::Result<T, E>;
+
+/// A high-level API to manage the secret storage.
+///
+/// To get this, use [`Encryption::secret_storage()`].
+///
+/// [`Encryption::secret_storage()`]: crate::encryption::Encryption::secret_storage
+#[derive(Debug, Clone)]
+pub struct SecretStorage {
+    /// The underlying client.
+    client: Client,
+}
+
+impl SecretStorage {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Check whether the secret storage is set up for this account, that is
+    /// whether there is a default secret storage key.
+    pub async fn is_enabled(&self) -> Result<bool> {
+        Ok(self.fetch_default_key_id().await?.is_some())
+    }
+
+    /// Fetch the ID of the default secret storage key from the account data
+    /// on the homeserver.
+    ///
+    /// Returns `None` if there is no default key, or if it was removed by
+    /// replacing it with an empty content.
+    pub async fn fetch_default_key_id(&self) -> Result<Option<String>> {
+        let Some(content) =
+            self.client.account().fetch_account_data(DEFAULT_KEY_EVENT_TYPE.into()).await?
+        else {
+            return Ok(None);
+        };
+
+        Ok(content.get_field("key")?)
+    }
+
+    /// Open the secret store with the default secret storage key.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_storage_key` - Either the base58 encoded secret storage key,
+    /// as returned by [`SecretStore::secret_storage_key()`], or the passphrase
+    /// it was derived from.
+    pub async fn open_secret_store(&self, secret_storage_key: &str) -> Result<SecretStore> {
+        let key_id = self.fetch_default_key_id().await?.ok_or(SecretStorageError::NotEnabled)?;
+
+        let event_type: GlobalAccountDataEventType =
+            format!("{KEY_EVENT_TYPE_PREFIX}{key_id}").as_str().into();
+        let content = self
+            .client
+            .account()
+            .fetch_account_data(event_type)
+            .await?
+            .ok_or_else(|| SecretStorageError::MissingKeyInfo(key_id.clone()))?
+            .deserialize_as::<SecretStorageKeyEventContent>()?;
+
+        let key = SecretStorageKey::from_account_data(secret_storage_key, key_id, content)?;
+
+        Ok(SecretStore::new(self.client.clone(), key))
+    }
+
+    /// Create a new secret storage key and make it the default one.
+    ///
+    /// Secrets that were stored with the previous default key, if any, are
+    /// not re-encrypted with the new key, use
+    /// [`SecretStore::export_secrets()`] to store our secrets with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase to derive the key from. If this is
+    /// `None`, a random key is created.
+    pub async fn create_secret_store(&self, passphrase: Option<&str>) -> Result<SecretStore> {
+        let key = match passphrase {
+            Some(passphrase) => SecretStorageKey::new_from_passphrase(passphrase),
+            None => SecretStorageKey::new(),
+        };
+
+        let account = self.client.account();
+
+        let content = Raw::new(key.event_content())?.cast();
+        account.set_account_data_raw(key.event_type().as_str().into(), content).await?;
+
+        let default_key = SecretStorageDefaultKeyEventContent { key: key.key_id().to_owned() };
+        let content = Raw::new(&default_key)?.cast();
+        account.set_account_data_raw(DEFAULT_KEY_EVENT_TYPE.into(), content).await?;
+
+        Ok(SecretStore::new(self.client.clone(), key))
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use matrix_sdk_base::crypto::{
+    secret_storage::{SecretEventContent, SecretStorageKey},
+    store::{CrossSigningKeyExport, RecoveryKey},
+};
+use ruma::{events::secret::request::SecretName, serde::Raw};
+use tracing::{debug, info, instrument};
+use zeroize::Zeroize;
+
+use super::{Result, SecretStorageError};
+use crate::{Client, Error};
+
+/// The secrets that are exported to and imported from the secret storage.
+const SECRETS: [SecretName; 4] = [
+    SecretName::CrossSigningMasterKey,
+    SecretName::CrossSigningSelfSigningKey,
+    SecretName::CrossSigningUserSigningKey,
+    SecretName::RecoveryKey,
+];
+
+/// A secret store, unlocked with a secret storage key.
+///
+/// It can be used to store and retrieve secrets from the account data of the
+/// user.
+///
+/// To get this, use [`SecretStorage::open_secret_store()`] or
+/// [`SecretStorage::create_secret_store()`].
+///
+/// [`SecretStorage::open_secret_store()`]: super::SecretStorage::open_secret_store
+/// [`SecretStorage::create_secret_store()`]: super::SecretStorage::create_secret_store
+#[derive(Debug)]
+pub struct SecretStore {
+    client: Client,
+    key: SecretStorageKey,
+}
+
+impl SecretStore {
+    pub(super) fn new(client: Client, key: SecretStorageKey) -> Self {
+        Self { client, key }
+    }
+
+    /// The ID of the secret storage key used by this store.
+    pub fn key_id(&self) -> &str {
+        self.key.key_id()
+    }
+
+    /// Export the secret storage key as a base58 encoded string, to be shown
+    /// to the user.
+    ///
+    /// It can be used to open the secret store later, with
+    /// [`SecretStorage::open_secret_store()`].
+    ///
+    /// [`SecretStorage::open_secret_store()`]: super::SecretStorage::open_secret_store
+    pub fn secret_storage_key(&self) -> String {
+        self.key.to_base58()
+    }
+
+    /// Retrieve a secret from the account data and decrypt it.
+    ///
+    /// Returns `None` if the secret isn't stored, or if it isn't encrypted
+    /// with the key of this store.
+    pub async fn get_secret(&self, secret_name: SecretName) -> Result<Option<String>> {
+        let Some(content) =
+            self.client.account().fetch_account_data(secret_name.as_ref().into()).await?
+        else {
+            return Ok(None);
+        };
+
+        let content = content.deserialize_as::<SecretEventContent>()?;
+
+        let Some(encrypted) = content.encrypted.get(self.key_id()) else {
+            debug!(?secret_name, "The secret isn't encrypted with our secret storage key");
+            return Ok(None);
+        };
+
+        let decrypted = self.key.decrypt(encrypted, secret_name.as_ref())?;
+
+        Ok(Some(String::from_utf8(decrypted.to_vec()).map_err(|e| {
+            e.into_bytes().zeroize();
+            SecretStorageError::InvalidSecret
+        })?))
+    }
+
+    /// Encrypt a secret and store it in the account data.
+    ///
+    /// This replaces any previous value of the secret, including the ones
+    /// encrypted with other secret storage keys.
+    pub async fn put_secret(&self, secret_name: SecretName, secret: &str) -> Result<()> {
+        let encrypted = self.key.encrypt(secret.as_bytes(), secret_name.as_ref());
+        let content = SecretEventContent {
+            encrypted: BTreeMap::from([(self.key_id().to_owned(), encrypted)]),
+        };
+
+        self.client
+            .account()
+            .set_account_data_raw(secret_name.as_ref().into(), Raw::new(&content)?.cast())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Retrieve the private cross-signing keys and the backup recovery key from
+    /// the secret storage, and import them into the crypto store.
+    ///
+    /// The cross-signing keys are only imported if they match the public
+    /// cross-signing keys of our user identity.
+    ///
+    /// The backup recovery key is imported without checking that it matches
+    /// the current backup version on the homeserver.
+    #[instrument(skip_all)]
+    pub async fn import_secrets(&self) -> Result<()> {
+        // Fetch the secrets before locking the `OlmMachine`, so it isn't blocked
+        // while we wait for the homeserver.
+        let export = CrossSigningKeyExport {
+            master_key: self.get_secret(SecretName::CrossSigningMasterKey).await?,
+            self_signing_key: self.get_secret(SecretName::CrossSigningSelfSigningKey).await?,
+            user_signing_key: self.get_secret(SecretName::CrossSigningUserSigningKey).await?,
+        };
+        let recovery_key =
+            self.get_secret(SecretName::RecoveryKey).await?.map(|mut recovery_key| {
+                let key = RecoveryKey::from_base64(&recovery_key);
+                recovery_key.zeroize();
+                key
+            });
+
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        if export.master_key.is_some()
+            || export.self_signing_key.is_some()
+            || export.user_signing_key.is_some()
+        {
+            let status = olm.import_cross_signing_keys(export).await?;
+            info!(?status, "Imported the private cross-signing keys from the secret storage");
+        }
+
+        if let Some(key) = recovery_key {
+            olm.backup_machine().save_recovery_key(Some(key?), None).await?;
+            info!("Imported the backup recovery key from the secret storage");
+        }
+
+        Ok(())
+    }
+
+    /// Export the private cross-signing keys and the backup recovery key from
+    /// the crypto store, and put them in the secret storage.
+    ///
+    /// Secrets that aren't known by the crypto store are left untouched.
+    #[instrument(skip_all)]
+    pub async fn export_secrets(&self) -> Result<()> {
+        // Collect the secrets first, so the `OlmMachine` isn't locked while we
+        // upload them.
+        let mut secrets = Vec::new();
+
+        {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+            for secret_name in SECRETS {
+                match olm.store().export_secret(&secret_name).await {
+                    Ok(Some(secret)) => secrets.push((secret_name, secret)),
+                    Ok(None) => {}
+                    Err(e) => {
+                        secrets.iter_mut().for_each(|(_, secret)| secret.zeroize());
+                        return Err(e.into());
+                    }
+                }
+            }
+        }
+
+        let mut result = Ok(());
+
+        for (secret_name, mut secret) in secrets {
+            if result.is_ok() {
+                result = self.put_secret(secret_name.clone(), &secret).await;
+
+                if result.is_ok() {
+                    debug!(?secret_name, "Exported a secret to the secret storage");
+                }
+            }
+
+            secret.zeroize();
+        }
+
+        result
+    }
+}
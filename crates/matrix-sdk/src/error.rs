@@ -215,6 +215,11 @@ pub enum Error {
     #[error(transparent)]
     DecryptorError(#[from] DecryptorError),
 
+    /// An error occurred while managing the backups.
+    #[cfg(feature = "e2e-encryption")]
+    #[error(transparent)]
+    Backup(#[from] crate::encryption::backups::BackupError),
+
     /// An error occurred in the state store.
     #[error(transparent)]
     StateStore(#[from] StoreError),
@@ -246,6 +251,24 @@ pub enum Error {
     #[error(transparent)]
     SlidingSync(#[from] crate::sliding_sync::Error),
 
+    /// An error occurred with the OpenID Connect API.
+    #[cfg(feature = "experimental-oidc")]
+    #[error(transparent)]
+    Oidc(#[from] crate::oidc::OidcError),
+
+    /// An error occurred with the send queue.
+    #[error(transparent)]
+    SendQueue(#[from] crate::send_queue::RoomSendQueueError),
+
+    /// A space child or parent was added without the servers that can be used
+    /// to join it.
+    #[error("the `via` servers of a space child or parent can't be empty")]
+    EmptySpaceVia,
+
+    /// A poll was created with an invalid number of answers.
+    #[error("the number of answers of a poll is invalid")]
+    InvalidPollAnswers,
+
     /// The client is in inconsistent state. This happens when we set a room to
     /// a specific type, but then cannot get it in this type.
     #[error("The internal client state is inconsistent.")]
@@ -422,6 +445,12 @@ pub enum RefreshTokenError {
     /// not be forwarded.
     #[error("the access token could not be refreshed")]
     UnableToRefreshToken,
+
+    /// An error occurred while refreshing the access token with the OpenID
+    /// Connect API.
+    #[cfg(feature = "experimental-oidc")]
+    #[error(transparent)]
+    Oidc(#[from] std::sync::Arc<crate::oidc::OidcError>),
 }
 
 /// Errors that can occur when manipulating push notification settings.
@@ -0,0 +1,344 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A persistent cache of the timeline events of a room.
+//!
+//! The events received via sync and back-pagination are stored as linked
+//! chunks in the state store. When the history of a room isn't known between
+//! two chunks, for example after a limited sync, a gap is inserted, which is
+//! only filled with a `/messages` request when a consumer paginates over it.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex as StdMutex},
+};
+
+use dashmap::DashMap;
+pub use matrix_sdk_base::event_cache::{Chunk, ChunkIdentifier, Gap};
+use matrix_sdk_base::{deserialized_responses::SyncTimelineEvent, event_cache::RoomEvents};
+use ruma::{assign, OwnedRoomId, RoomId, UInt};
+use tokio::sync::Mutex;
+use tracing::{debug, error, instrument, trace};
+
+use crate::{
+    room::{self, MessagesOptions},
+    sync::Timeline,
+    Result,
+};
+
+/// The state of the event cache of a room, lazily loaded from the store.
+pub(crate) type RoomEventsState = Arc<Mutex<Option<RoomEvents>>>;
+
+/// The maximum number of rooms whose event cache is kept in memory, unless a
+/// [`RoomEventCache`] is still alive for them.
+const MAX_LOADED_ROOMS: usize = 16;
+
+/// The event caches of all the rooms of a client.
+///
+/// Only the rooms that were used the most recently are kept in memory, the
+/// other ones are loaded again from the store when they are needed.
+#[derive(Debug, Default)]
+pub(crate) struct EventCaches {
+    states: DashMap<OwnedRoomId, RoomEventsState>,
+    /// The rooms in `states`, from the least to the most recently used.
+    recently_used: StdMutex<VecDeque<OwnedRoomId>>,
+}
+
+impl EventCaches {
+    /// Get the state of the event cache of the given room.
+    fn get(&self, room_id: &RoomId) -> RoomEventsState {
+        let state = self.states.entry(room_id.to_owned()).or_default().clone();
+
+        let mut recently_used = self.recently_used.lock().unwrap();
+        recently_used.retain(|r| r != room_id);
+        recently_used.push_back(room_id.to_owned());
+
+        // Forget the least recently used rooms that nobody is using anymore.
+        // `remove_if()` locks the map, so their state can't be shared again in
+        // the meantime.
+        let mut excess = recently_used.len().saturating_sub(MAX_LOADED_ROOMS);
+        recently_used.retain(|r| {
+            if excess == 0 {
+                return true;
+            }
+
+            let removed =
+                self.states.remove_if(r, |_, state| Arc::strong_count(state) == 1).is_some();
+            if removed {
+                excess -= 1;
+            }
+            !removed
+        });
+
+        state
+    }
+}
+
+/// The position of a consumer in the event cache of a room, to paginate
+/// backwards.
+///
+/// It points to the next chunk to load, or to nothing if the start of the room
+/// was reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BackPaginationCursor(Option<ChunkIdentifier>);
+
+/// The result of a back-pagination in the event cache of a room.
+#[derive(Debug)]
+pub enum BackPaginationOutcome {
+    /// Events were retrieved, either from the cache or from the server.
+    Events {
+        /// The events, in topological order.
+        ///
+        /// This can be empty if the server returned no events but the start
+        /// of the room hasn't been reached yet.
+        events: Vec<SyncTimelineEvent>,
+
+        /// The cursor to use for the next back-pagination.
+        cursor: BackPaginationCursor,
+    },
+
+    /// The start of the room was reached, there are no events to load.
+    StartReached,
+}
+
+/// A handle to the event cache of a room.
+///
+/// Get one with [`Common::event_cache()`](room::Common::event_cache).
+#[derive(Clone, Debug)]
+pub struct RoomEventCache {
+    room: room::Common,
+    state: RoomEventsState,
+}
+
+impl RoomEventCache {
+    pub(crate) fn new(room: room::Common) -> Self {
+        let state = room.client().inner.event_caches.get(room.room_id());
+
+        Self { room, state }
+    }
+
+    /// Get the newest events of the room that are known without a gap, in
+    /// topological order.
+    ///
+    /// Returns the events along with the cursor to use to paginate backwards
+    /// with [`RoomEventCache::back_paginate()`], or `None` if nothing is known
+    /// about the room yet.
+    pub async fn latest_events(
+        &self,
+    ) -> Result<Option<(Vec<SyncTimelineEvent>, BackPaginationCursor)>> {
+        let mut guard = self.state.lock().await;
+        let room_events = self.load(&mut guard).await?;
+
+        if room_events.is_empty() {
+            return Ok(None);
+        }
+
+        let chunks = room_events.chunks();
+        let num_latest = chunks.iter().rev().take_while(|chunk| !chunk.is_gap()).count();
+        let first_latest = chunks.len() - num_latest;
+
+        let cursor = BackPaginationCursor(
+            first_latest.checked_sub(1).map(|position| chunks[position].identifier),
+        );
+        let events = chunks[first_latest..]
+            .iter()
+            .flat_map(|chunk| chunk.events().iter().cloned())
+            .collect();
+
+        Ok(Some((events, cursor)))
+    }
+
+    /// Load the events of the chunk the given cursor points to.
+    ///
+    /// If the chunk is a gap, it is first filled with a `/messages` request of
+    /// at most `limit` events, and the result is saved to the store.
+    ///
+    /// Returns `None` if the cursor doesn't point into the cache anymore, or if
+    /// the gap changed while waiting for the server. In that case the caller
+    /// must paginate without the cache.
+    #[instrument(skip(self), fields(room_id = ?self.room.room_id()))]
+    pub async fn back_paginate(
+        &self,
+        cursor: BackPaginationCursor,
+        limit: u16,
+    ) -> Result<Option<BackPaginationOutcome>> {
+        let Some(identifier) = cursor.0 else {
+            return Ok(Some(BackPaginationOutcome::StartReached));
+        };
+
+        let mut guard = self.state.lock().await;
+        let room_events = self.load(&mut guard).await?;
+
+        let Some(chunk) = room_events.chunk(identifier) else {
+            debug!(?identifier, "Cursor not found in the event cache");
+            return Ok(None);
+        };
+
+        let Some(gap) = chunk.gap() else {
+            trace!(?identifier, "Returning events from the cache");
+            return Ok(Some(outcome_for_chunk(Some(chunk))));
+        };
+
+        let next_chunk = chunk.next;
+        let prev_token = gap.prev_token.clone();
+
+        // Don't block the sync while waiting for the server.
+        drop(guard);
+
+        let messages = self
+            .room
+            .messages(assign!(MessagesOptions::backward(), {
+                from: Some(prev_token.clone()),
+                limit: UInt::from(limit),
+            }))
+            .await?;
+
+        debug!(?identifier, num_events = messages.chunk.len(), "Filling a gap of the event cache");
+
+        // The events of a backwards `/messages` response are in reverse
+        // topological order.
+        let events: Vec<SyncTimelineEvent> =
+            messages.chunk.into_iter().rev().map(Into::into).collect();
+        let new_gap = messages.end.map(|prev_token| Gap { prev_token });
+
+        let mut guard = self.state.lock().await;
+        let room_events = self.load(&mut guard).await?;
+
+        // The gap may have been filled, or the cache cleared, while waiting for
+        // the server, in which case the response is outdated.
+        if room_events.find_gap(&prev_token) != Some(identifier) {
+            debug!(?identifier, "The gap is gone from the event cache, dropping the response");
+            return Ok(None);
+        }
+
+        room_events.replace_gap(identifier, events, new_gap);
+
+        // The new chunks took the place of the gap, so the newest one comes
+        // right before the chunk that followed the gap.
+        let outcome = match next_chunk {
+            Some(next_chunk) if room_events.chunk(next_chunk).is_none() => {
+                debug!(?identifier, "Lost the position of the gap in the event cache");
+                None
+            }
+            Some(next_chunk) => Some(outcome_for_chunk(room_events.chunk_before(next_chunk))),
+            None => Some(outcome_for_chunk(room_events.last_chunk())),
+        };
+
+        self.save(&mut guard).await?;
+
+        Ok(outcome)
+    }
+
+    /// Add the events of a sync response to the cache.
+    ///
+    /// This is called for the responses of both `/sync` and sliding sync, see
+    /// [`Client::handle_sync_response()`](crate::Client::handle_sync_response).
+    pub(crate) async fn handle_sync_timeline(&self, timeline: &Timeline) -> Result<()> {
+        let mut guard = self.state.lock().await;
+        let room_events = self.load(&mut guard).await?;
+
+        if timeline.limited || room_events.is_empty() {
+            if let Some(prev_token) = &timeline.prev_batch {
+                room_events.push_gap(Gap { prev_token: prev_token.clone() });
+            }
+        }
+
+        room_events.push_events(timeline.events.iter().cloned());
+
+        self.save(&mut guard).await
+    }
+
+    /// Load the events of the room from the store, if that wasn't done yet.
+    async fn load<'a>(&self, state: &'a mut Option<RoomEvents>) -> Result<&'a mut RoomEvents> {
+        if state.is_none() {
+            let chunks =
+                self.room.client().store().get_event_cache_chunks(self.room.room_id()).await?;
+            trace!(num_chunks = chunks.len(), "Loaded the event cache from the store");
+            *state = Some(RoomEvents::from_chunks(chunks));
+        }
+
+        Ok(state.get_or_insert_with(RoomEvents::new))
+    }
+
+    /// Persist the pending changes of the event cache.
+    async fn save(&self, state: &mut Option<RoomEvents>) -> Result<()> {
+        let Some(room_events) = state else {
+            return Ok(());
+        };
+
+        let updates = room_events.take_updates();
+        if updates.is_empty() {
+            return Ok(());
+        }
+
+        let result = self
+            .room
+            .client()
+            .store()
+            .handle_event_cache_updates(self.room.room_id(), updates)
+            .await;
+
+        if let Err(e) = &result {
+            // The in-memory state doesn't match the store anymore, reload it
+            // the next time it is needed.
+            error!("Failed to save the event cache: {e}");
+            *state = None;
+        }
+
+        Ok(result?)
+    }
+}
+
+/// Get the outcome of a back-pagination that reached the given chunk.
+fn outcome_for_chunk(chunk: Option<&Chunk>) -> BackPaginationOutcome {
+    match chunk {
+        None => BackPaginationOutcome::StartReached,
+        // The server didn't return any event, but the start of the room wasn't
+        // reached yet.
+        Some(chunk) if chunk.is_gap() => BackPaginationOutcome::Events {
+            events: Vec::new(),
+            cursor: BackPaginationCursor(Some(chunk.identifier)),
+        },
+        Some(chunk) => BackPaginationOutcome::Events {
+            events: chunk.events().to_vec(),
+            cursor: BackPaginationCursor(chunk.previous),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ruma::{room_id, RoomId};
+
+    use super::{EventCaches, MAX_LOADED_ROOMS};
+
+    #[test]
+    fn test_least_recently_used_event_caches_are_forgotten() {
+        let caches = EventCaches::default();
+        let used_room_id = room_id!("!used:localhost");
+        let used_state = caches.get(used_room_id);
+
+        for i in 0..MAX_LOADED_ROOMS * 2 {
+            caches.get(&RoomId::parse(format!("!room{i}:localhost")).unwrap());
+        }
+
+        assert_eq!(caches.states.len(), MAX_LOADED_ROOMS);
+        assert_eq!(caches.recently_used.lock().unwrap().len(), MAX_LOADED_ROOMS);
+
+        // The state of a room that is still used is kept.
+        assert!(Arc::ptr_eq(&used_state, &caches.get(used_room_id)));
+    }
+}
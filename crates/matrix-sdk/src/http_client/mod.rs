@@ -27,6 +27,7 @@ use bytesize::ByteSize;
 use eyeball::SharedObservable;
 use ruma::{
     api::{
+        client::error::{ErrorBody as ClientApiErrorBody, ErrorKind as ClientApiErrorKind},
         error::{FromHttpResponseError, IntoHttpError},
         AuthScheme, MatrixVersion, OutgoingRequest, OutgoingRequestAppserviceExt, SendAccessToken,
     },
@@ -34,15 +35,21 @@ use ruma::{
 };
 use tracing::{debug, field::debug, instrument, trace};
 
-use crate::{config::RequestConfig, error::HttpError};
+use crate::{config::RequestConfig, error::HttpError, RumaApiError};
 
 #[cfg(not(target_arch = "wasm32"))]
 mod native;
+mod scheduler;
 #[cfg(target_arch = "wasm32")]
 mod wasm;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub(crate) use native::HttpSettings;
+pub use scheduler::{EndpointClass, RateLimitState};
+pub(crate) use scheduler::{
+    RequestScheduler, DEFAULT_MAX_CONCURRENT_MEDIA_REQUESTS,
+    DEFAULT_MAX_CONCURRENT_TO_DEVICE_REQUESTS,
+};
 
 pub(crate) const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 
@@ -50,12 +57,17 @@ pub(crate) const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 pub(crate) struct HttpClient {
     pub(crate) inner: reqwest::Client,
     pub(crate) request_config: RequestConfig,
+    pub(crate) scheduler: RequestScheduler,
     next_request_id: Arc<AtomicU64>,
 }
 
 impl HttpClient {
-    pub(crate) fn new(inner: reqwest::Client, request_config: RequestConfig) -> Self {
-        HttpClient { inner, request_config, next_request_id: AtomicU64::new(0).into() }
+    pub(crate) fn new(
+        inner: reqwest::Client,
+        request_config: RequestConfig,
+        scheduler: RequestScheduler,
+    ) -> Self {
+        HttpClient { inner, request_config, scheduler, next_request_id: AtomicU64::new(0).into() }
     }
 
     fn get_request_id(&self) -> String {
@@ -239,3 +251,18 @@ async fn response_to_http_response(
 
     Ok(http_builder.body(body).expect("Can't construct a response using the given body"))
 }
+
+/// Get the delay the homeserver asked us to wait for, if the error is a rate
+/// limit.
+fn rate_limit_retry_after(error: &HttpError) -> Option<Duration> {
+    match error.as_ruma_api_error()? {
+        RumaApiError::ClientApi(e) => match e.body {
+            ClientApiErrorBody::Standard {
+                kind: ClientApiErrorKind::LimitExceeded { retry_after_ms },
+                ..
+            } => retry_after_ms,
+            _ => None,
+        },
+        _ => None,
+    }
+}
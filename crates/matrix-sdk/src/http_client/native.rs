@@ -23,14 +23,13 @@ use backoff::{future::retry, Error as RetryError, ExponentialBackoff};
 use bytes::Bytes;
 use bytesize::ByteSize;
 use eyeball::SharedObservable;
-use ruma::api::{
-    client::error::{ErrorBody as ClientApiErrorBody, ErrorKind as ClientApiErrorKind},
-    error::FromHttpResponseError,
-    IncomingResponse, OutgoingRequest,
-};
+use ruma::api::{error::FromHttpResponseError, IncomingResponse, OutgoingRequest};
 use tracing::{info, warn};
 
-use super::{response_to_http_response, HttpClient, TransmissionProgress, DEFAULT_REQUEST_TIMEOUT};
+use super::{
+    rate_limit_retry_after, response_to_http_response, EndpointClass, HttpClient,
+    TransmissionProgress, DEFAULT_REQUEST_TIMEOUT,
+};
 use crate::{config::RequestConfig, error::HttpError, RumaApiError};
 
 impl HttpClient {
@@ -47,6 +46,7 @@ impl HttpClient {
         let backoff =
             ExponentialBackoff { max_elapsed_time: config.retry_timeout, ..Default::default() };
         let retry_count = AtomicU64::new(1);
+        let endpoint_class = EndpointClass::from_request(request.method(), request.uri().path());
 
         let send_request = || {
             let send_progress = send_progress.clone();
@@ -57,40 +57,45 @@ impl HttpClient {
                     false
                 };
 
-                // Turn errors into permanent errors when the retry limit is reached
-                let error_type = if stop {
-                    RetryError::Permanent
-                } else {
-                    |err: HttpError| {
-                        if let Some(api_error) = err.as_ruma_api_error() {
-                            let status_code = match api_error {
-                                RumaApiError::ClientApi(e) => match e.body {
-                                    ClientApiErrorBody::Standard {
-                                        kind: ClientApiErrorKind::LimitExceeded { retry_after_ms },
-                                        ..
-                                    } => {
-                                        return RetryError::Transient {
-                                            err,
-                                            retry_after: retry_after_ms,
-                                        };
-                                    }
-                                    _ => Some(e.status_code),
-                                },
-                                RumaApiError::Uiaa(_) => None,
-                                RumaApiError::Other(e) => Some(e.status_code),
-                            };
-
-                            if let Some(status_code) = status_code {
-                                if status_code.is_server_error() {
-                                    return RetryError::Transient { err, retry_after: None };
-                                }
-                            }
-                        }
-
-                        RetryError::Permanent(err)
+                let error_type = |err: HttpError| {
+                    let retry_after = rate_limit_retry_after(&err);
+
+                    // Hold back the other requests to the same class of endpoints too, even if
+                    // we give up on this one.
+                    if let (Some(class), Some(retry_after)) = (endpoint_class, retry_after) {
+                        self.scheduler.rate_limited(class, retry_after);
+                    }
+
+                    // Turn errors into permanent errors when the retry limit is reached
+                    if stop {
+                        return RetryError::Permanent(err);
                     }
+
+                    if retry_after.is_some() {
+                        return RetryError::Transient { err, retry_after };
+                    }
+
+                    let status_code = match err.as_ruma_api_error() {
+                        Some(RumaApiError::ClientApi(e)) => Some(e.status_code),
+                        Some(RumaApiError::Other(e)) => Some(e.status_code),
+                        Some(RumaApiError::Uiaa(_)) | None => None,
+                    };
+
+                    // Rate limits without a delay are retried with the default backoff.
+                    if status_code.is_some_and(|status_code| {
+                        status_code.is_server_error()
+                            || status_code == http::StatusCode::TOO_MANY_REQUESTS
+                    }) {
+                        return RetryError::Transient { err, retry_after: None };
+                    }
+
+                    RetryError::Permanent(err)
                 };
 
+                let _permit = match endpoint_class {
+                    Some(class) => self.scheduler.acquire(class).await,
+                    None => None,
+                };
                 let response = send_request(&self.inner, &request, config.timeout, send_progress)
                     .await
                     .map_err(error_type)?;
@@ -0,0 +1,349 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scheduling of the requests sent to the homeserver, shared by all the
+//! requests of a client.
+//!
+//! Requests are sorted into classes of endpoints. When the homeserver tells us
+//! that we're rate-limited, no request of the same class is sent until the
+//! delay it asked for has elapsed, and some classes have a cap on the number of
+//! requests that are in flight at the same time.
+//!
+//! Sync requests aren't scheduled: they are long-polling requests that the
+//! sync loops already space out, and they must not hold back the other
+//! requests.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex as StdMutex, MutexGuard},
+    time::Duration,
+};
+
+use eyeball::{SharedObservable, Subscriber};
+use http::Method;
+use matrix_sdk_base::instant::Instant;
+use matrix_sdk_common::{
+    executor::{spawn, JoinHandle},
+    sleep::sleep,
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::debug;
+
+/// The default number of media requests that can be in flight at the same
+/// time.
+pub(crate) const DEFAULT_MAX_CONCURRENT_MEDIA_REQUESTS: usize = 4;
+
+/// The default number of to-device requests that can be in flight at the same
+/// time.
+pub(crate) const DEFAULT_MAX_CONCURRENT_TO_DEVICE_REQUESTS: usize = 4;
+
+/// A class of endpoints that are scheduled together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EndpointClass {
+    /// Uploads and downloads of media, including thumbnails.
+    Media,
+    /// Sending to-device messages.
+    ToDevice,
+    /// Sending events to rooms, including state events and redactions.
+    RoomSend,
+    /// Joining rooms.
+    Join,
+    /// Logging in and refreshing the access token.
+    Login,
+    /// All the other endpoints, except the sync ones.
+    Other,
+}
+
+impl EndpointClass {
+    /// Get the class of the endpoint of a request with the given method and
+    /// path.
+    ///
+    /// The homeserver might be served under a path prefix, so only the end of
+    /// the path is looked at. Requests that only read data, like getting the
+    /// state of a room, are never sorted into the classes of requests that
+    /// write it. Returns `None` for the sync endpoints, which are never held
+    /// back.
+    pub(crate) fn from_request(method: &Method, path: &str) -> Option<Self> {
+        let path = path.trim_end_matches('/');
+
+        if path.ends_with("/sync") {
+            return None;
+        }
+
+        let is_write = *method != Method::GET && *method != Method::HEAD;
+
+        let class = if path.contains("/_matrix/media/")
+            || path.contains("/_matrix/client/v1/media/")
+        {
+            Self::Media
+        } else if !is_write {
+            Self::Other
+        } else if path.contains("/sendToDevice/") {
+            Self::ToDevice
+        } else if path.contains("/rooms/")
+            && (path.contains("/send/") || path.contains("/state/") || path.contains("/redact/"))
+        {
+            Self::RoomSend
+        } else if path.contains("/join/") || path.ends_with("/join") {
+            Self::Join
+        } else if path.ends_with("/login") || path.ends_with("/refresh") {
+            Self::Login
+        } else {
+            Self::Other
+        };
+
+        Some(class)
+    }
+}
+
+/// The classes of endpoints for which requests are held back, because the
+/// homeserver rate-limited us.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RateLimitState {
+    limited: BTreeMap<EndpointClass, Instant>,
+}
+
+impl RateLimitState {
+    /// Whether the requests to any class of endpoints are held back.
+    pub fn is_rate_limited(&self) -> bool {
+        !self.limited.is_empty()
+    }
+
+    /// When the requests to the given class of endpoints will be sent again, if
+    /// they are held back.
+    pub fn retry_at(&self, class: EndpointClass) -> Option<Instant> {
+        self.limited.get(&class).copied()
+    }
+
+    /// The classes of endpoints for which requests are held back.
+    pub fn limited_classes(&self) -> impl Iterator<Item = EndpointClass> + '_ {
+        self.limited.keys().copied()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RequestScheduler {
+    permits: BTreeMap<EndpointClass, Semaphore>,
+    state: SharedObservable<RateLimitState>,
+    /// The tasks that forget the rate limits once they expire, so the
+    /// subscribers are notified even if no other request is sent.
+    expiry_tasks: StdMutex<BTreeMap<EndpointClass, JoinHandle<()>>>,
+}
+
+impl RequestScheduler {
+    /// Create a new scheduler that caps the number of concurrent requests of
+    /// the classes of endpoints with a limit.
+    pub(crate) fn new(concurrency_limits: &BTreeMap<EndpointClass, usize>) -> Self {
+        let permits = concurrency_limits
+            .iter()
+            .map(|(class, limit)| {
+                // Always let at least one request through.
+                let limit = (*limit).clamp(1, Semaphore::MAX_PERMITS);
+                (*class, Semaphore::new(limit))
+            })
+            .collect();
+
+        Self { permits, state: Default::default(), expiry_tasks: Default::default() }
+    }
+
+    /// Wait until a request to the given class of endpoints can be sent.
+    ///
+    /// The returned permit must be held until the response is received.
+    pub(crate) async fn acquire(&self, class: EndpointClass) -> Option<SemaphorePermit<'_>> {
+        let permit = match self.permits.get(&class) {
+            Some(semaphore) => {
+                Some(semaphore.acquire().await.expect("The semaphore is never closed"))
+            }
+            None => None,
+        };
+
+        // Another request may have been rate-limited while we were waiting for the
+        // permit, so check again after sleeping.
+        while let Some(retry_at) = self.state.get().retry_at(class) {
+            let now = Instant::now();
+            if retry_at <= now {
+                clear_expired(&self.state, class);
+                break;
+            }
+
+            debug!(?class, "Waiting for the rate limit to expire before sending the request");
+            sleep(retry_at - now).await;
+        }
+
+        permit
+    }
+
+    /// Hold back the requests to the given class of endpoints for the given
+    /// delay.
+    pub(crate) fn rate_limited(&self, class: EndpointClass, retry_after: Duration) {
+        let until = Instant::now() + retry_after;
+
+        let mut extended = false;
+        self.state.update(|state| {
+            let retry_at = state.limited.entry(class).or_insert(until);
+            if *retry_at <= until {
+                *retry_at = until;
+                extended = true;
+            }
+        });
+
+        // The previous task would fire too early, replace it.
+        if extended {
+            let state = self.state.clone();
+            let task = spawn(async move {
+                sleep(retry_after).await;
+                clear_expired(&state, class);
+            });
+            self.expiry_tasks().insert(class, task);
+        }
+    }
+
+    fn expiry_tasks(&self) -> MutexGuard<'_, BTreeMap<EndpointClass, JoinHandle<()>>> {
+        self.expiry_tasks.lock().unwrap()
+    }
+
+    pub(crate) fn rate_limit_state(&self) -> RateLimitState {
+        self.state.get()
+    }
+
+    pub(crate) fn subscribe(&self) -> Subscriber<RateLimitState> {
+        self.state.subscribe()
+    }
+}
+
+/// Forget the rate limit of the given class of endpoints if it has expired.
+fn clear_expired(state: &SharedObservable<RateLimitState>, class: EndpointClass) {
+    let is_expired = |state: &RateLimitState| {
+        state.retry_at(class).is_some_and(|retry_at| retry_at <= Instant::now())
+    };
+
+    // Only notify the subscribers if the state changes.
+    if is_expired(&state.get()) {
+        state.update(|state| {
+            if is_expired(state) {
+                state.limited.remove(&class);
+            }
+        });
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{collections::BTreeMap, time::Duration};
+
+    use http::Method;
+    use matrix_sdk_base::instant::Instant;
+    use matrix_sdk_test::async_test;
+
+    use super::{EndpointClass, RequestScheduler};
+
+    #[test]
+    fn test_endpoint_classes() {
+        let class = |path: &str| EndpointClass::from_request(&Method::PUT, path);
+        let get = |path: &str| EndpointClass::from_request(&Method::GET, path);
+
+        assert_eq!(class("/_matrix/media/v3/upload"), Some(EndpointClass::Media));
+        assert_eq!(
+            get("/_matrix/client/v1/media/download/localhost/abc"),
+            Some(EndpointClass::Media)
+        );
+        assert_eq!(
+            class("/_matrix/client/r0/sendToDevice/m.room.encrypted/1"),
+            Some(EndpointClass::ToDevice)
+        );
+        assert_eq!(
+            class("/_matrix/client/v3/rooms/!a:localhost/send/m.room.message/1"),
+            Some(EndpointClass::RoomSend)
+        );
+        assert_eq!(
+            class("/_matrix/client/v3/rooms/!a:localhost/state/m.room.topic/"),
+            Some(EndpointClass::RoomSend)
+        );
+        assert_eq!(
+            class("/_matrix/client/v3/rooms/!a:localhost/redact/$ev/1"),
+            Some(EndpointClass::RoomSend)
+        );
+        assert_eq!(class("/_matrix/client/v3/join/#a:localhost"), Some(EndpointClass::Join));
+        assert_eq!(class("/_matrix/client/v3/rooms/!a:localhost/join"), Some(EndpointClass::Join));
+        assert_eq!(class("/_matrix/client/v3/login"), Some(EndpointClass::Login));
+        assert_eq!(class("/_matrix/client/v3/refresh"), Some(EndpointClass::Login));
+        assert_eq!(class("/_matrix/client/v3/publicRooms"), Some(EndpointClass::Other));
+
+        // Reading data isn't held back with the requests that write it.
+        assert_eq!(
+            get("/_matrix/client/v3/rooms/!a:localhost/state/m.room.topic/"),
+            Some(EndpointClass::Other)
+        );
+        assert_eq!(get("/_matrix/client/v3/login"), Some(EndpointClass::Other));
+
+        // The sync endpoints aren't scheduled.
+        assert_eq!(get("/_matrix/client/r0/sync"), None);
+        assert_eq!(class("/_matrix/client/unstable/org.matrix.msc3575/sync"), None);
+
+        // The homeserver can be served under a path prefix.
+        assert_eq!(class("/matrix/_matrix/media/v3/upload"), Some(EndpointClass::Media));
+        assert_eq!(get("/matrix/_matrix/client/r0/sync"), None);
+    }
+
+    #[async_test]
+    async fn test_concurrency_limit() {
+        let scheduler = RequestScheduler::new(&BTreeMap::from([(EndpointClass::Media, 1)]));
+
+        let permit = scheduler.acquire(EndpointClass::Media).await;
+        assert!(permit.is_some());
+        assert!(scheduler.permits[&EndpointClass::Media].try_acquire().is_err());
+
+        // Other classes of endpoints aren't limited.
+        assert!(scheduler.acquire(EndpointClass::Other).await.is_none());
+
+        drop(permit);
+        assert!(scheduler.permits[&EndpointClass::Media].try_acquire().is_ok());
+    }
+
+    #[async_test]
+    async fn test_rate_limit() {
+        let scheduler = RequestScheduler::new(&BTreeMap::new());
+        let mut subscriber = scheduler.subscribe();
+
+        let before = Instant::now();
+        scheduler.rate_limited(EndpointClass::ToDevice, Duration::from_millis(100));
+
+        let state = subscriber.next().await.unwrap();
+        assert!(state.is_rate_limited());
+        assert_eq!(state.limited_classes().collect::<Vec<_>>(), [EndpointClass::ToDevice]);
+        assert!(state.retry_at(EndpointClass::Other).is_none());
+
+        // Requests to other classes of endpoints are sent right away.
+        scheduler.acquire(EndpointClass::Other).await;
+        assert!(scheduler.rate_limit_state().is_rate_limited());
+
+        scheduler.acquire(EndpointClass::ToDevice).await;
+        assert!(before.elapsed() >= Duration::from_millis(100));
+        assert!(!scheduler.rate_limit_state().is_rate_limited());
+        assert!(!subscriber.next().await.unwrap().is_rate_limited());
+    }
+
+    #[async_test]
+    async fn test_rate_limit_is_cleared_when_it_expires() {
+        let scheduler = RequestScheduler::new(&BTreeMap::new());
+        let mut subscriber = scheduler.subscribe();
+
+        scheduler.rate_limited(EndpointClass::RoomSend, Duration::from_millis(100));
+        assert!(subscriber.next().await.unwrap().is_rate_limited());
+
+        // The subscribers are notified without sending another request.
+        assert!(!subscriber.next().await.unwrap().is_rate_limited());
+        assert!(!scheduler.rate_limit_state().is_rate_limited());
+    }
+}
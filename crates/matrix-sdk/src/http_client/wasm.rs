@@ -19,7 +19,10 @@ use bytesize::ByteSize;
 use eyeball::SharedObservable;
 use ruma::api::{error::FromHttpResponseError, IncomingResponse, OutgoingRequest};
 
-use super::{response_to_http_response, HttpClient, TransmissionProgress};
+use super::{
+    rate_limit_retry_after, response_to_http_response, EndpointClass, HttpClient,
+    TransmissionProgress,
+};
 use crate::{config::RequestConfig, error::HttpError};
 
 impl HttpClient {
@@ -33,7 +36,13 @@ impl HttpClient {
         R: OutgoingRequest + Debug,
         HttpError: From<FromHttpResponseError<R::EndpointError>>,
     {
+        let endpoint_class = EndpointClass::from_request(request.method(), request.uri().path());
         let request = reqwest::Request::try_from(request)?;
+
+        let _permit = match endpoint_class {
+            Some(class) => self.scheduler.acquire(class).await,
+            None => None,
+        };
         let response = response_to_http_response(self.inner.execute(request).await?).await?;
 
         let status_code = response.status();
@@ -42,6 +51,13 @@ impl HttpClient {
             .record("status", status_code.as_u16())
             .record("response_size", response_size.to_string_as(true));
 
-        Ok(R::IncomingResponse::try_from_http_response(response)?)
+        R::IncomingResponse::try_from_http_response(response).map_err(|e| {
+            let err = HttpError::from(e);
+            if let (Some(class), Some(retry_after)) = (endpoint_class, rate_limit_retry_after(&err))
+            {
+                self.scheduler.rate_limited(class, retry_after);
+            }
+            err
+        })
     }
 }
@@ -35,12 +35,16 @@ mod authentication;
 mod client;
 pub mod config;
 mod error;
+pub mod event_cache;
 pub mod event_handler;
 mod http_client;
 pub mod matrix_auth;
 pub mod media;
 pub mod notification_settings;
+#[cfg(feature = "experimental-oidc")]
+pub mod oidc;
 pub mod room;
+pub mod send_queue;
 pub mod sync;
 
 #[cfg(feature = "experimental-sliding-sync")]
@@ -60,7 +64,7 @@ pub use error::{
     Error, HttpError, HttpResult, NotificationSettingsError, RefreshTokenError, Result,
     RumaApiError,
 };
-pub use http_client::TransmissionProgress;
+pub use http_client::{EndpointClass, RateLimitState, TransmissionProgress};
 #[cfg(all(feature = "e2e-encryption", feature = "sqlite"))]
 pub use matrix_sdk_sqlite::SqliteCryptoStore;
 pub use media::Media;
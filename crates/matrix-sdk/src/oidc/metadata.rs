@@ -0,0 +1,194 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metadata of the OpenID Connect provider and of the client.
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::OidcError;
+
+/// The metadata of an OpenID Connect provider, as returned by its discovery
+/// endpoint.
+///
+/// Only the fields used by the SDK are deserialized.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProviderMetadata {
+    /// The URL of the issuer.
+    pub issuer: String,
+
+    /// The URL of the authorization endpoint.
+    pub authorization_endpoint: Url,
+
+    /// The URL of the token endpoint.
+    pub token_endpoint: Url,
+
+    /// The URL of the dynamic client registration endpoint, if it is
+    /// supported.
+    #[serde(default)]
+    pub registration_endpoint: Option<Url>,
+
+    /// The URL of the token revocation endpoint, if it is supported.
+    #[serde(default)]
+    pub revocation_endpoint: Option<Url>,
+
+    /// The URL where the user can manage their account, if any.
+    #[serde(default, rename = "org.matrix.matrix-authentication-service.account_management_uri")]
+    pub account_management_uri: Option<Url>,
+
+    /// The `response_type` values supported by the provider.
+    #[serde(default)]
+    pub response_types_supported: Vec<String>,
+
+    /// The `grant_type` values supported by the provider.
+    ///
+    /// If this is empty, the provider supports the `authorization_code` and
+    /// `implicit` grant types.
+    #[serde(default)]
+    pub grant_types_supported: Vec<String>,
+
+    /// The PKCE code challenge methods supported by the provider.
+    #[serde(default)]
+    pub code_challenge_methods_supported: Vec<String>,
+}
+
+impl ProviderMetadata {
+    /// Check that the provider supports what is needed to log in with the
+    /// authorization code flow, and that it matches the expected issuer.
+    pub(super) fn validate(&self, issuer: &str) -> Result<(), OidcError> {
+        if self.issuer != issuer {
+            return Err(OidcError::IssuerMismatch {
+                expected: issuer.to_owned(),
+                got: self.issuer.clone(),
+            });
+        }
+
+        if !self.response_types_supported.iter().any(|t| t == "code") {
+            return Err(OidcError::UnsupportedProvider("the `code` response type"));
+        }
+
+        if !self.grant_types_supported.is_empty()
+            && !self.grant_types_supported.iter().any(|t| t == "authorization_code")
+        {
+            return Err(OidcError::UnsupportedProvider("the `authorization_code` grant type"));
+        }
+
+        if !self.code_challenge_methods_supported.iter().any(|m| m == "S256") {
+            return Err(OidcError::UnsupportedProvider("the `S256` PKCE code challenge method"));
+        }
+
+        Ok(())
+    }
+}
+
+/// The type of an application.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApplicationType {
+    /// A web application, the redirect URIs must use `https`.
+    Web,
+
+    /// A native application, the redirect URIs can use a custom scheme or a
+    /// loopback interface.
+    Native,
+}
+
+/// The metadata of the client, that is sent to the provider during dynamic
+/// client registration.
+///
+/// The client is always registered as a public client, without a secret.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientMetadata {
+    /// The type of the application.
+    pub application_type: ApplicationType,
+
+    /// The URIs where the provider can redirect the user after the
+    /// authorization.
+    pub redirect_uris: Vec<Url>,
+
+    /// The name of the client, presented to the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+
+    /// The URL of the home page of the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_uri: Option<Url>,
+
+    /// The URL of the logo of the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<Url>,
+
+    /// The URL of the privacy policy of the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_uri: Option<Url>,
+
+    /// The URL of the terms of service of the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tos_uri: Option<Url>,
+}
+
+impl ClientMetadata {
+    /// Create a new `ClientMetadata` with the given application type and
+    /// redirect URIs.
+    pub fn new(application_type: ApplicationType, redirect_uris: Vec<Url>) -> Self {
+        Self {
+            application_type,
+            redirect_uris,
+            client_name: None,
+            client_uri: None,
+            logo_uri: None,
+            policy_uri: None,
+            tos_uri: None,
+        }
+    }
+}
+
+/// The body of a dynamic client registration request.
+#[derive(Serialize)]
+pub(super) struct ClientRegistrationRequest<'a> {
+    #[serde(flatten)]
+    pub metadata: &'a ClientMetadata,
+    pub grant_types: [&'static str; 2],
+    pub response_types: [&'static str; 1],
+    pub token_endpoint_auth_method: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub software_statement: Option<&'a str>,
+}
+
+impl<'a> ClientRegistrationRequest<'a> {
+    pub(super) fn new(metadata: &'a ClientMetadata, software_statement: Option<&'a str>) -> Self {
+        Self {
+            metadata,
+            grant_types: ["authorization_code", "refresh_token"],
+            response_types: ["code"],
+            token_endpoint_auth_method: "none",
+            software_statement,
+        }
+    }
+}
+
+/// The response of a successful dynamic client registration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ClientRegistrationResponse {
+    /// The ID of the client at the provider.
+    ///
+    /// It must be persisted to restore the registration with
+    /// [`Oidc::restore_registered_client()`](super::Oidc::restore_registered_client).
+    pub client_id: String,
+
+    /// The time at which the client ID was issued, as a number of seconds
+    /// since the Unix epoch.
+    #[serde(default)]
+    pub client_id_issued_at: Option<u64>,
+}
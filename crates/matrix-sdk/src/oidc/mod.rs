@@ -0,0 +1,902 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! High-level OpenID Connect API, as defined in [MSC3861].
+//!
+//! The issuer of the homeserver is discovered from its `.well-known`, the
+//! client registers itself with [dynamic client registration], and the user
+//! logs in with the authorization code flow, protected by [PKCE].
+//!
+//! Only the Matrix scopes are requested, not the `openid` scope, because the
+//! client doesn't need the identity of the user at the provider, so no ID
+//! token is issued that would need to be validated.
+//!
+//! The registration of the client must be persisted by the application,
+//! along with the session, because registering the client again would create
+//! a new client at the provider.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use matrix_sdk::{
+//!     oidc::{ApplicationType, ClientMetadata},
+//!     Client,
+//! };
+//! use url::Url;
+//! # async {
+//! # fn open_in_browser(_: &Url) {}
+//! # async fn wait_for_callback() -> Url { unimplemented!() }
+//!
+//! let client = Client::builder().server_name("example.org".try_into()?).build().await?;
+//! let oidc = client.oidc();
+//!
+//! let redirect_uri = Url::parse("http://127.0.0.1:6789/callback")?;
+//! let metadata = ClientMetadata::new(ApplicationType::Native, vec![redirect_uri.clone()]);
+//!
+//! // Persist the client ID along with the metadata, to avoid registering again.
+//! let registration = oidc.register_client(metadata, None).await?;
+//!
+//! let authorization_data = oidc.url_for_authorization(redirect_uri, None).await?;
+//! open_in_browser(&authorization_data.url);
+//!
+//! // The user is redirected to the redirect URI once they are done.
+//! let callback_url = wait_for_callback().await;
+//! oidc.finish_authorization(&callback_url).await?;
+//!
+//! // Persist the session to be able to restore it later.
+//! let session = oidc.full_session().expect("the client should be logged in");
+//! # anyhow::Ok(()) };
+//! ```
+//!
+//! [MSC3861]: https://github.com/matrix-org/matrix-spec-proposals/pull/3861
+//! [dynamic client registration]: https://openid.net/specs/openid-connect-registration-1_0.html
+//! [PKCE]: https://datatracker.ietf.org/doc/html/rfc7636
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use eyeball::SharedObservable;
+use futures_core::Stream;
+use futures_util::{future, StreamExt};
+use matrix_sdk_base::SessionMeta;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use ruma::{api::client::discovery::discover_homeserver, DeviceId, OwnedDeviceId};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+use tracing::{debug, instrument, trace, warn};
+use url::Url;
+
+use crate::{
+    authentication::AuthData, Client, EndpointClass, HttpError, RefreshTokenError, Result,
+};
+
+mod metadata;
+
+use self::metadata::ClientRegistrationRequest;
+pub use self::metadata::{
+    ApplicationType, ClientMetadata, ClientRegistrationResponse, ProviderMetadata,
+};
+
+/// The scope giving full access to the Matrix client-server API.
+const SCOPE_MATRIX_API: &str = "urn:matrix:org.matrix.msc2967.client:api:*";
+
+/// The prefix of the scope requesting a device ID.
+const SCOPE_MATRIX_DEVICE_PREFIX: &str = "urn:matrix:org.matrix.msc2967.client:device:";
+
+#[derive(Clone)]
+pub(crate) struct OidcAuthData {
+    /// The issuer the client is registered with.
+    pub(crate) issuer: String,
+    /// The ID of the client at the issuer.
+    pub(crate) client_id: String,
+    /// The metadata the client was registered with.
+    pub(crate) metadata: ClientMetadata,
+    /// The tokens of the session, if the user is logged in.
+    pub(crate) tokens: SharedObservable<Option<OidcSessionTokens>>,
+    /// The metadata of the issuer, lazily fetched.
+    provider_metadata: Arc<OnceCell<ProviderMetadata>>,
+    /// The data of the ongoing authorizations, by `state`.
+    authorization_data: Arc<StdMutex<HashMap<String, AuthorizationValidationData>>>,
+}
+
+impl fmt::Debug for OidcAuthData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcAuthData")
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The data needed to finish an authorization, that is not sent to the
+/// provider.
+#[derive(Clone, Debug)]
+struct AuthorizationValidationData {
+    redirect_uri: Url,
+    code_verifier: String,
+    device_id: OwnedDeviceId,
+}
+
+/// A high-level API to interact with an OpenID Connect provider.
+///
+/// To access this API, use [`Client::oidc()`].
+#[derive(Debug, Clone)]
+pub struct Oidc {
+    client: Client,
+}
+
+impl Oidc {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn data(&self) -> Option<&OidcAuthData> {
+        self.client.inner.auth_data.get()?.as_oidc()
+    }
+
+    /// Get the issuer advertised by the homeserver.
+    ///
+    /// The issuer discovered when the client was built is used, otherwise the
+    /// `.well-known` of the homeserver is fetched.
+    ///
+    /// Returns [`OidcError::NotSupported`] if the homeserver doesn't advertise
+    /// an issuer.
+    pub async fn fetch_authentication_issuer(&self) -> Result<String, OidcError> {
+        if let Some(info) = self.client.authentication_server_info() {
+            return Ok(info.issuer.clone());
+        }
+
+        let well_known = self.client.send(discover_homeserver::Request::new(), None).await?;
+        well_known.authentication.map(|info| info.issuer).ok_or(OidcError::NotSupported)
+    }
+
+    /// Fetch the metadata of the given issuer, from its discovery endpoint.
+    ///
+    /// Returns an error if the provider doesn't support the features needed to
+    /// log in.
+    pub async fn given_provider_metadata(
+        &self,
+        issuer: &str,
+    ) -> Result<ProviderMetadata, OidcError> {
+        let url = Url::parse(&format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        ))?;
+        let request = self.http_client().get(url);
+
+        let metadata: ProviderMetadata =
+            serde_json::from_slice(&self.send_request(EndpointClass::Other, request).await?)?;
+        metadata.validate(issuer)?;
+
+        Ok(metadata)
+    }
+
+    /// Get the metadata of the issuer the client is registered with, or of the
+    /// issuer of the homeserver if the client is not registered yet.
+    pub async fn provider_metadata(&self) -> Result<ProviderMetadata, OidcError> {
+        match self.data() {
+            Some(data) => self.registered_provider_metadata(data).await.cloned(),
+            None => self.given_provider_metadata(&self.fetch_authentication_issuer().await?).await,
+        }
+    }
+
+    /// The metadata of the issuer the client is registered with, fetched only
+    /// once.
+    async fn registered_provider_metadata<'a>(
+        &self,
+        data: &'a OidcAuthData,
+    ) -> Result<&'a ProviderMetadata, OidcError> {
+        data.provider_metadata.get_or_try_init(|| self.given_provider_metadata(&data.issuer)).await
+    }
+
+    /// Register the client with the issuer of the homeserver, with dynamic
+    /// client registration.
+    ///
+    /// On success, the registration is used for the following calls of this
+    /// API. The client ID of the response, along with the issuer and the
+    /// metadata, must be persisted and restored with
+    /// [`Oidc::restore_registered_client()`] on the next run, or with the
+    /// whole session.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - The metadata of the client.
+    ///
+    /// * `software_statement` - A JWT containing the metadata of the client,
+    /// signed by a party trusted by the provider, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a client was already registered or restored, or if the client
+    /// is logged in with another authentication API.
+    #[instrument(skip_all)]
+    pub async fn register_client(
+        &self,
+        metadata: ClientMetadata,
+        software_statement: Option<String>,
+    ) -> Result<ClientRegistrationResponse, OidcError> {
+        let issuer = self.fetch_authentication_issuer().await?;
+        let provider_metadata = self.given_provider_metadata(&issuer).await?;
+
+        let registration_endpoint = provider_metadata
+            .registration_endpoint
+            .clone()
+            .ok_or(OidcError::UnsupportedProvider("dynamic client registration"))?;
+
+        debug!(%issuer, "Registering the client");
+
+        let body = serde_json::to_vec(&ClientRegistrationRequest::new(
+            &metadata,
+            software_statement.as_deref(),
+        ))?;
+        let request = self
+            .http_client()
+            .post(registration_endpoint)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(body);
+
+        let response: ClientRegistrationResponse =
+            serde_json::from_slice(&self.send_request(EndpointClass::Login, request).await?)?;
+
+        self.set_registered_client(issuer, response.client_id.clone(), metadata);
+        _ = self
+            .data()
+            .expect("registration was just set")
+            .provider_metadata
+            .set(provider_metadata);
+
+        Ok(response)
+    }
+
+    /// Restore a client registration that was previously obtained with
+    /// [`Oidc::register_client()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a client was already registered or restored, or if the client
+    /// is logged in with another authentication API.
+    pub fn restore_registered_client(
+        &self,
+        issuer: String,
+        client_id: String,
+        metadata: ClientMetadata,
+    ) {
+        self.set_registered_client(issuer, client_id, metadata);
+    }
+
+    fn set_registered_client(&self, issuer: String, client_id: String, metadata: ClientMetadata) {
+        let data = OidcAuthData {
+            issuer,
+            client_id,
+            metadata,
+            tokens: SharedObservable::new(None),
+            provider_metadata: Default::default(),
+            authorization_data: Default::default(),
+        };
+
+        if self.client.inner.auth_data.set(AuthData::Oidc(data)).is_err() {
+            panic!("Cannot register an OpenID Connect client after a client was registered or after logging in with another API");
+        }
+    }
+
+    /// The issuer the client is registered with, if any.
+    pub fn issuer(&self) -> Option<&str> {
+        Some(&self.data()?.issuer)
+    }
+
+    /// The ID of the client at the issuer, if it is registered.
+    pub fn client_id(&self) -> Option<&str> {
+        Some(&self.data()?.client_id)
+    }
+
+    /// The metadata the client was registered with, if it is registered.
+    pub fn client_metadata(&self) -> Option<&ClientMetadata> {
+        Some(&self.data()?.metadata)
+    }
+
+    /// Get the URL where the user can manage their account, if the provider
+    /// advertises one.
+    pub async fn account_management_url(&self) -> Result<Option<Url>, OidcError> {
+        Ok(self.provider_metadata().await?.account_management_uri)
+    }
+
+    /// Build the URL to open in a browser to log in with the authorization
+    /// code flow.
+    ///
+    /// Once the user is done, the provider redirects them to the redirect
+    /// URI, and the URL it was called with must be passed to
+    /// [`Oidc::finish_authorization()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `redirect_uri` - The URI where the user will be redirected, it must be
+    /// one of the URIs the client was registered with.
+    ///
+    /// * `device_id` - The ID of the device to log in with, to reuse an
+    /// existing device. A new one is generated if this is `None`.
+    #[instrument(skip_all)]
+    pub async fn url_for_authorization(
+        &self,
+        redirect_uri: Url,
+        device_id: Option<OwnedDeviceId>,
+    ) -> Result<OidcAuthorizationData, OidcError> {
+        let data = self.data().ok_or(OidcError::NotRegistered)?;
+
+        if !data.metadata.redirect_uris.contains(&redirect_uri) {
+            return Err(OidcError::InvalidRedirectUri);
+        }
+
+        let provider_metadata = self.registered_provider_metadata(data).await?;
+
+        let device_id = device_id.unwrap_or_else(DeviceId::new);
+        let scope = format!("{SCOPE_MATRIX_API} {SCOPE_MATRIX_DEVICE_PREFIX}{device_id}");
+
+        let state = random_string(32);
+        let code_verifier = random_string(64);
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let mut url = provider_metadata.authorization_endpoint.clone();
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &data.client_id)
+            .append_pair("redirect_uri", redirect_uri.as_str())
+            .append_pair("scope", &scope)
+            .append_pair("state", &state)
+            .append_pair("code_challenge", &code_challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        trace!(?device_id, "Starting an authorization");
+
+        data.authorization_data.lock().unwrap().insert(
+            state.clone(),
+            AuthorizationValidationData { redirect_uri, code_verifier, device_id },
+        );
+
+        Ok(OidcAuthorizationData { url, state })
+    }
+
+    /// Finish the login with the authorization code flow.
+    ///
+    /// The authorization code is exchanged for the tokens of the session, and
+    /// the user ID is retrieved from the homeserver.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback_url` - The URL the provider redirected the user to, with
+    /// its query.
+    #[instrument(skip_all)]
+    pub async fn finish_authorization(&self, callback_url: &Url) -> Result<()> {
+        let data = self.data().ok_or(OidcError::NotRegistered)?;
+
+        let params: HashMap<_, _> = callback_url.query_pairs().collect();
+        let state = params.get("state").ok_or(OidcError::InvalidCallbackUrl)?;
+
+        let validation_data = data
+            .authorization_data
+            .lock()
+            .unwrap()
+            .remove(&**state)
+            .ok_or(OidcError::InvalidState)?;
+
+        if let Some(error) = params.get("error") {
+            return Err(OidcError::OAuth(OAuthError {
+                error: error.clone().into_owned(),
+                error_description: params.get("error_description").map(|d| d.clone().into_owned()),
+            })
+            .into());
+        }
+
+        let code = params.get("code").ok_or(OidcError::InvalidCallbackUrl)?;
+        let provider_metadata = self.registered_provider_metadata(data).await?;
+
+        let response: TokenResponse = self
+            .token_request(
+                provider_metadata,
+                &[
+                    ("grant_type", "authorization_code"),
+                    ("code", code.as_ref()),
+                    ("redirect_uri", validation_data.redirect_uri.as_str()),
+                    ("client_id", data.client_id.as_str()),
+                    ("code_verifier", validation_data.code_verifier.as_str()),
+                ],
+            )
+            .await?;
+
+        data.tokens.set(Some(OidcSessionTokens {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+        }));
+
+        let whoami = match self.client.whoami().await {
+            Ok(whoami) => whoami,
+            Err(error) => {
+                data.tokens.set(None);
+                return Err(error.into());
+            }
+        };
+
+        let meta = SessionMeta {
+            user_id: whoami.user_id,
+            device_id: whoami.device_id.unwrap_or(validation_data.device_id),
+        };
+        self.client.base_client().set_session_meta(meta).await?;
+
+        debug!("Logged in with OpenID Connect");
+
+        Ok(())
+    }
+
+    /// Forget the data of an ongoing authorization, for example if the user
+    /// cancelled it.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state of the authorization, returned by
+    /// [`Oidc::url_for_authorization()`].
+    pub fn abort_authorization(&self, state: &str) {
+        if let Some(data) = self.data() {
+            data.authorization_data.lock().unwrap().remove(state);
+        }
+    }
+
+    /// Get the current tokens of the session.
+    ///
+    /// Will be `None` if the client has not been logged in with the OpenID
+    /// Connect API.
+    pub fn session_tokens(&self) -> Option<OidcSessionTokens> {
+        self.data()?.tokens.get()
+    }
+
+    /// Get the current access token of the session.
+    ///
+    /// Will be `None` if the client has not been logged in with the OpenID
+    /// Connect API.
+    pub fn access_token(&self) -> Option<String> {
+        self.session_tokens().map(|tokens| tokens.access_token)
+    }
+
+    /// Get the current refresh token of the session.
+    ///
+    /// Will be `None` if the client has not been logged in with the OpenID
+    /// Connect API, or if the provider didn't return a refresh token.
+    pub fn refresh_token(&self) -> Option<String> {
+        self.session_tokens().and_then(|tokens| tokens.refresh_token)
+    }
+
+    /// Get a stream of the tokens of the session.
+    ///
+    /// The tokens change when the access token is refreshed, so they should be
+    /// persisted every time this stream yields a value.
+    ///
+    /// Will be `None` if no client was registered or restored.
+    pub fn session_tokens_stream(&self) -> Option<impl Stream<Item = OidcSessionTokens>> {
+        Some(self.data()?.tokens.subscribe().filter_map(future::ready))
+    }
+
+    /// Get the user session of this client.
+    ///
+    /// Will be `None` if the client has not been logged in with the OpenID
+    /// Connect API.
+    pub fn user_session(&self) -> Option<UserSession> {
+        let meta = self.client.session_meta()?.to_owned();
+        let tokens = self.session_tokens()?;
+        Some(UserSession { meta, tokens })
+    }
+
+    /// Get the whole session of this client, with the registration of the
+    /// client.
+    ///
+    /// Will be `None` if the client has not been logged in with the OpenID
+    /// Connect API.
+    ///
+    /// Can be used with [`Oidc::restore_session()`] to restore a previously
+    /// logged-in session.
+    pub fn full_session(&self) -> Option<OidcSession> {
+        let data = self.data()?;
+        Some(OidcSession {
+            issuer: data.issuer.clone(),
+            client_id: data.client_id.clone(),
+            metadata: data.metadata.clone(),
+            user: self.user_session()?,
+        })
+    }
+
+    /// Restore a previously logged in session.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a client was already registered or restored, or if the client
+    /// is logged in with another authentication API.
+    #[instrument(skip_all)]
+    pub async fn restore_session(&self, session: OidcSession) -> Result<()> {
+        debug!("Restoring OpenID Connect session");
+
+        let OidcSession { issuer, client_id, metadata, user } = session;
+
+        self.set_registered_client(issuer, client_id, metadata);
+        self.data().expect("registration was just set").tokens.set(Some(user.tokens));
+        self.client.base_client().set_session_meta(user.meta).await?;
+
+        debug!("Done restoring OpenID Connect session");
+
+        Ok(())
+    }
+
+    /// Refresh the access token.
+    ///
+    /// This is called automatically when a request fails with an
+    /// `M_UNKNOWN_TOKEN` error if [`ClientBuilder::handle_refresh_tokens()`]
+    /// was called. The new tokens are published on
+    /// [`Oidc::session_tokens_stream()`].
+    ///
+    /// If a refresh is already in progress, this waits for its result.
+    ///
+    /// [`ClientBuilder::handle_refresh_tokens()`]: crate::ClientBuilder::handle_refresh_tokens
+    #[instrument(skip_all)]
+    pub async fn refresh_access_token(&self) -> Result<(), RefreshTokenError> {
+        let client = &self.client;
+
+        let Ok(mut guard) = client.inner.refresh_token_lock.try_lock() else {
+            return client.inner.refresh_token_lock.lock().await.clone();
+        };
+
+        let (Some(data), Some(refresh_token)) = (self.data(), self.refresh_token()) else {
+            *guard = Err(RefreshTokenError::RefreshTokenRequired);
+            return Err(RefreshTokenError::RefreshTokenRequired);
+        };
+
+        let result = async {
+            let provider_metadata = self.registered_provider_metadata(data).await?;
+
+            self.token_request::<TokenResponse>(
+                provider_metadata,
+                &[
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", refresh_token.as_str()),
+                    ("client_id", data.client_id.as_str()),
+                ],
+            )
+            .await
+        }
+        .await;
+
+        match result {
+            Ok(response) => {
+                trace!("Refreshed the access token");
+
+                data.tokens.update(|tokens| {
+                    if let Some(tokens) = tokens {
+                        tokens.access_token = response.access_token;
+                        if let Some(refresh_token) = response.refresh_token {
+                            tokens.refresh_token = Some(refresh_token);
+                        }
+                    }
+                });
+
+                *guard = Ok(());
+                Ok(())
+            }
+            Err(error) => {
+                let error = RefreshTokenError::Oidc(Arc::new(error));
+                *guard = Err(error.clone());
+                Err(error)
+            }
+        }
+    }
+
+    /// Log out by revoking the tokens of the session at the provider.
+    ///
+    /// The homeserver ends the session once the provider revoked its tokens,
+    /// whether the device is deleted depends on the homeserver. The client
+    /// isn't logged in anymore after this, a new client must be created to log
+    /// in again.
+    ///
+    /// The session is forgotten locally even if revoking the tokens fails, in
+    /// which case the error is returned afterwards.
+    #[instrument(skip_all)]
+    pub async fn logout(&self) -> Result<(), OidcError> {
+        let data = self.data().ok_or(OidcError::NotRegistered)?;
+        let tokens = self.session_tokens().ok_or(OidcError::NotAuthenticated)?;
+
+        let result = self.revoke_tokens(data, tokens).await;
+
+        data.tokens.set(None);
+        self.client.base_client().clear_session_meta();
+
+        debug!("Logged out with OpenID Connect");
+
+        result
+    }
+
+    /// Revoke the given tokens at the provider.
+    ///
+    /// The refresh token is revoked first, because it can be used to get new
+    /// access tokens. All the tokens are revoked even if one of them fails, and
+    /// the first error is returned.
+    async fn revoke_tokens(
+        &self,
+        data: &OidcAuthData,
+        tokens: OidcSessionTokens,
+    ) -> Result<(), OidcError> {
+        let provider_metadata = self.registered_provider_metadata(data).await?;
+
+        let revocation_endpoint = provider_metadata
+            .revocation_endpoint
+            .clone()
+            .ok_or(OidcError::UnsupportedProvider("token revocation"))?;
+
+        let mut revocations = Vec::new();
+        if let Some(refresh_token) = tokens.refresh_token {
+            revocations.push((refresh_token, "refresh_token"));
+        }
+        revocations.push((tokens.access_token, "access_token"));
+
+        let mut result = Ok(());
+
+        for (token, token_type_hint) in revocations {
+            let request = self.http_client().post(revocation_endpoint.clone()).form(&[
+                ("token", token.as_str()),
+                ("token_type_hint", token_type_hint),
+                ("client_id", data.client_id.as_str()),
+            ]);
+
+            if let Err(error) = self.send_request(EndpointClass::Login, request).await {
+                warn!(token_type_hint, "Failed to revoke a token: {error}");
+
+                if result.is_ok() {
+                    result = Err(error);
+                }
+            }
+        }
+
+        result
+    }
+
+    fn http_client(&self) -> &reqwest::Client {
+        &self.client.inner.http_client.inner
+    }
+
+    /// Send the given request to the provider and return the body of the
+    /// response.
+    ///
+    /// The request is scheduled with the requests to the homeserver of the same
+    /// class of endpoints. Returns an error if the response doesn't have a
+    /// successful status.
+    async fn send_request(
+        &self,
+        class: EndpointClass,
+        request: reqwest::RequestBuilder,
+    ) -> Result<bytes::Bytes, OidcError> {
+        let scheduler = &self.client.inner.http_client.scheduler;
+        let _permit = scheduler.acquire(class).await;
+
+        let response = request
+            .header(http::header::ACCEPT, "application/json")
+            .send()
+            .await
+            .map_err(HttpError::from)?;
+
+        let status = response.status();
+
+        if status == http::StatusCode::TOO_MANY_REQUESTS {
+            if let Some(retry_after) = retry_after(response.headers()) {
+                scheduler.rate_limited(class, retry_after);
+            }
+        }
+
+        let body = response.bytes().await.map_err(HttpError::from)?;
+
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(match serde_json::from_slice(&body) {
+                Ok(error) => OidcError::OAuth(error),
+                Err(_) => OidcError::UnexpectedStatus(status.as_u16()),
+            })
+        }
+    }
+
+    async fn token_request<T: DeserializeOwned>(
+        &self,
+        provider_metadata: &ProviderMetadata,
+        form: &[(&str, &str)],
+    ) -> Result<T, OidcError> {
+        let request = self.http_client().post(provider_metadata.token_endpoint.clone()).form(form);
+        Ok(serde_json::from_slice(&self.send_request(EndpointClass::Login, request).await?)?)
+    }
+}
+
+/// Get the delay in the `Retry-After` header of a response, if it is a number
+/// of seconds.
+fn retry_after(headers: &http::HeaderMap) -> Option<Duration> {
+    let seconds = headers.get(http::header::RETRY_AFTER)?.to_str().ok()?.parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+/// Generate a random string of the given length, suitable for the `state` or
+/// the PKCE code verifier.
+fn random_string(len: usize) -> String {
+    thread_rng().sample_iter(Alphanumeric).take(len).map(char::from).collect()
+}
+
+/// A successful response of the token endpoint.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+/// The data to start an authorization with the authorization code flow.
+#[derive(Debug, Clone)]
+pub struct OidcAuthorizationData {
+    /// The URL to open in a browser.
+    pub url: Url,
+
+    /// The unique identifier of this authorization, that can be used with
+    /// [`Oidc::abort_authorization()`].
+    pub state: String,
+}
+
+/// A full session using the OpenID Connect API, with the registration of the
+/// client.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OidcSession {
+    /// The issuer the client is registered with.
+    pub issuer: String,
+
+    /// The ID of the client at the issuer.
+    pub client_id: String,
+
+    /// The metadata the client was registered with.
+    pub metadata: ClientMetadata,
+
+    /// The session of the user.
+    pub user: UserSession,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for OidcSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcSession")
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .field("user", &self.user)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A user session using the OpenID Connect API.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UserSession {
+    /// The Matrix user session info.
+    #[serde(flatten)]
+    pub meta: SessionMeta,
+
+    /// The tokens used for authentication.
+    #[serde(flatten)]
+    pub tokens: OidcSessionTokens,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for UserSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserSession").field("meta", &self.meta).finish_non_exhaustive()
+    }
+}
+
+/// The tokens for a user session obtained with the OpenID Connect API.
+#[derive(Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[allow(missing_debug_implementations)]
+pub struct OidcSessionTokens {
+    /// The access token used for this session.
+    pub access_token: String,
+
+    /// The token used for refreshing the access token, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+/// An error response of an OAuth 2.0 endpoint.
+#[derive(Clone, Debug, Deserialize)]
+pub struct OAuthError {
+    /// The error code.
+    pub error: String,
+
+    /// A human-readable description of the error, if any.
+    #[serde(default)]
+    pub error_description: Option<String>,
+}
+
+impl fmt::Display for OAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error_description {
+            Some(description) => write!(f, "{}: {description}", self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+/// Errors that can occur when using the OpenID Connect API.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum OidcError {
+    /// The homeserver doesn't advertise an OpenID Connect issuer.
+    #[error("the homeserver doesn't support OpenID Connect")]
+    NotSupported,
+
+    /// The provider doesn't support a feature that is required.
+    #[error("the OpenID Connect provider doesn't support {0}")]
+    UnsupportedProvider(&'static str),
+
+    /// The issuer in the metadata of the provider doesn't match the expected
+    /// one.
+    #[error("the issuer of the provider metadata `{got}` doesn't match `{expected}`")]
+    IssuerMismatch {
+        /// The expected issuer.
+        expected: String,
+        /// The issuer in the metadata.
+        got: String,
+    },
+
+    /// No client was registered or restored.
+    #[error("the client is not registered with an OpenID Connect provider")]
+    NotRegistered,
+
+    /// The client is not logged in with the OpenID Connect API.
+    #[error("the client is not logged in with OpenID Connect")]
+    NotAuthenticated,
+
+    /// The redirect URI is not one of the URIs the client was registered
+    /// with.
+    #[error("the redirect URI was not registered for the client")]
+    InvalidRedirectUri,
+
+    /// The callback URL doesn't contain the expected parameters.
+    #[error("the callback URL is missing the `state` or `code` parameter")]
+    InvalidCallbackUrl,
+
+    /// The `state` of the callback URL doesn't match an ongoing
+    /// authorization.
+    #[error("the state of the callback URL doesn't match an ongoing authorization")]
+    InvalidState,
+
+    /// The provider returned an error.
+    #[error("the OpenID Connect provider returned an error: {0}")]
+    OAuth(OAuthError),
+
+    /// The provider returned an unexpected HTTP status, without an error
+    /// response.
+    #[error("the OpenID Connect provider returned an unexpected status: {0}")]
+    UnexpectedStatus(u16),
+
+    /// An HTTP error occurred.
+    #[error(transparent)]
+    Http(#[from] HttpError),
+
+    /// A response couldn't be (de)serialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// A URL couldn't be parsed.
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+}
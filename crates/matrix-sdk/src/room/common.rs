@@ -1,5 +1,7 @@
 use std::{borrow::Borrow, collections::BTreeMap, fmt, ops::Deref, sync::Arc};
 
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_base::crypto::MegolmError;
 use matrix_sdk_base::{
     deserialized_responses::{
         MembersResponse, RawAnySyncOrStrippedState, RawSyncOrStrippedState, SyncOrStrippedState,
@@ -37,6 +39,7 @@ use ruma::{
             power_levels::RoomPowerLevelsEventContent, server_acl::RoomServerAclEventContent,
             MediaSource,
         },
+        space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
         tag::{TagInfo, TagName},
         AnyRoomAccountDataEvent, AnyStateEvent, EmptyStateKey, RedactContent,
         RedactedStateEventContent, RoomAccountDataEvent, RoomAccountDataEventContent,
@@ -51,11 +54,13 @@ use serde::de::DeserializeOwned;
 use tokio::sync::{broadcast, Mutex};
 use tracing::{debug, instrument};
 
-use super::Joined;
+use super::{Joined, SpaceChild, SpaceParent};
 use crate::{
+    event_cache::RoomEventCache,
     event_handler::{EventHandler, EventHandlerHandle, SyncEvent},
     media::{MediaFormat, MediaRequest},
     room::{Left, RoomMember, RoomState},
+    send_queue::RoomSendQueue,
     sync::RoomUpdate,
     BaseRoom, Client, Error, HttpError, HttpResult, Result,
 };
@@ -119,7 +124,7 @@ impl Common {
 
     /// Join this room.
     ///
-    /// Only invited and left rooms can be joined via this method.
+    /// Only invited, knocked and left rooms can be joined via this method.
     pub(crate) async fn join(&self) -> Result<Joined> {
         let request = join_room_by_id::v3::Request::new(self.inner.room_id().to_owned());
         let response = self.client.send(request, None).await?;
@@ -229,12 +234,12 @@ impl Common {
                         AnySyncMessageLikeEvent::RoomEncrypted(SyncMessageLikeEvent::Original(_)),
                     )) = event.deserialize_as::<AnySyncTimelineEvent>()
                     {
-                        if let Ok(event) =
-                            machine.decrypt_room_event(event.cast_ref(), room_id).await
-                        {
-                            event
-                        } else {
-                            TimelineEvent::new(event)
+                        match machine.decrypt_room_event(event.cast_ref(), room_id).await {
+                            Ok(event) => event,
+                            Err(e) => TimelineEvent {
+                                unable_to_decrypt_reason: Some((&e).into()),
+                                ..TimelineEvent::new(event)
+                            },
                         }
                     } else {
                         TimelineEvent::new(event)
@@ -283,6 +288,16 @@ impl Common {
         self.client.subscribe_to_room_updates(self.room_id())
     }
 
+    /// Get the persistent cache of the timeline events of this room.
+    pub fn event_cache(&self) -> RoomEventCache {
+        RoomEventCache::new(self.clone())
+    }
+
+    /// Get the persistent queue of the requests to send to this room.
+    pub fn send_queue(&self) -> RoomSendQueue {
+        self.client.send_queue().for_room(self.room_id())
+    }
+
     /// Fetch the event with the given `EventId` in this room.
     pub async fn event(&self, event_id: &EventId) -> Result<TimelineEvent> {
         let request =
@@ -301,7 +316,12 @@ impl Common {
 
         let push_actions = self.event_push_actions(&event).await?;
 
-        Ok(TimelineEvent { event, encryption_info: None, push_actions })
+        Ok(TimelineEvent {
+            event,
+            encryption_info: None,
+            push_actions,
+            unable_to_decrypt_reason: None,
+        })
     }
 
     pub(crate) async fn request_members(&self) -> Result<Option<MembersResponse>> {
@@ -400,14 +420,17 @@ impl Common {
     }
 
     fn are_events_visible(&self) -> bool {
-        if let RoomState::Invited = self.inner.state() {
-            return matches!(
+        match self.inner.state() {
+            RoomState::Invited => matches!(
                 self.inner.history_visibility(),
                 HistoryVisibility::WorldReadable | HistoryVisibility::Invited
-            );
+            ),
+            // We're not a member of the room yet, only what is public is visible.
+            RoomState::Knocked => {
+                self.inner.history_visibility() == HistoryVisibility::WorldReadable
+            }
+            RoomState::Joined | RoomState::Left => true,
         }
-
-        true
     }
 
     /// Sync the member list with the server.
@@ -883,7 +906,19 @@ impl Common {
         let machine = self.client.olm_machine().await;
         if let Some(machine) = machine.as_ref() {
             let mut event =
-                machine.decrypt_room_event(event.cast_ref(), self.inner.room_id()).await?;
+                match machine.decrypt_room_event(event.cast_ref(), self.inner.room_id()).await {
+                    Ok(event) => event,
+                    Err(error) => {
+                        if matches!(error, MegolmError::MissingRoomKey(_)) {
+                            self.client.encryption().backups().maybe_download_room_key(
+                                self.inner.room_id().to_owned(),
+                                event.clone(),
+                            );
+                        }
+
+                        return Err(error.into());
+                    }
+                };
 
             event.push_actions = self.event_push_actions(&event.event).await?;
 
@@ -952,6 +987,39 @@ impl Common {
             .collect())
     }
 
+    /// Get the children of this room, if it is a space.
+    ///
+    /// The children are read from the `m.space.child` state events of this room
+    /// in the store, and are sorted according to the ordering rules of the
+    /// specification. Children that were removed from the space are ignored.
+    pub async fn space_children(&self) -> Result<Vec<SpaceChild>> {
+        let mut children: Vec<_> = self
+            .get_state_events_static::<SpaceChildEventContent>()
+            .await?
+            .iter()
+            .filter_map(SpaceChild::from_raw)
+            .collect();
+        children.sort_by(SpaceChild::cmp_order);
+
+        Ok(children)
+    }
+
+    /// Get the spaces this room claims to be part of.
+    ///
+    /// The parents are read from the `m.space.parent` state events of this
+    /// room in the store. The canonical parent, if any, comes first.
+    pub async fn space_parents(&self) -> Result<Vec<SpaceParent>> {
+        let mut parents: Vec<_> = self
+            .get_state_events_static::<SpaceParentEventContent>()
+            .await?
+            .iter()
+            .filter_map(SpaceParent::from_raw)
+            .collect();
+        parents.sort_by_key(|parent| !parent.canonical);
+
+        Ok(parents)
+    }
+
     /// Get a `matrix.to` permalink to this room.
     ///
     /// If this room has an alias, we use it. Otherwise, we try to use the
@@ -1,5 +1,7 @@
 use std::ops::Deref;
 
+#[cfg(feature = "e2e-encryption")]
+use ruma::UserId;
 use thiserror::Error;
 use tracing::{instrument, warn};
 
@@ -54,14 +56,52 @@ impl Invited {
     }
 
     /// Reject the invitation.
+    ///
+    /// The room keys that were shared with us as the history of the room, as
+    /// defined in [MSC3061], are discarded.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
     pub async fn reject_invitation(&self) -> Result<Left> {
-        self.inner.leave().await
+        let left = self.inner.leave().await?;
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Some(olm) = self.inner.client.olm_machine().await.as_ref() {
+            if let Err(e) = olm.discard_room_key_history(self.room_id()).await {
+                warn!(room_id = ?self.room_id(), "Failed to discard the room key history: {e}");
+            }
+        }
+
+        Ok(left)
     }
 
     /// Accept the invitation.
+    ///
+    /// If the user that invited us shared the history of the room with us, as
+    /// defined in [MSC3061], the room keys they sent are imported.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
     #[instrument(skip_all)]
     pub async fn accept_invitation(&self) -> Result<Joined> {
+        // The invite membership event is replaced once we join, remember who
+        // invited us beforehand.
+        #[cfg(feature = "e2e-encryption")]
+        let inviter = match self.invitee().await {
+            Ok(invitee) => Some(invitee.event().sender().to_owned()),
+            Err(e) => {
+                warn!(room_id = ?self.room_id(), "Failed to get the sender of the invite: {e}");
+                None
+            }
+        };
+
         let joined = self.inner.join().await?;
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Some(inviter) = inviter {
+            if let Err(e) = self.import_room_key_history(&inviter).await {
+                warn!(room_id = ?self.room_id(), "Failed to import the room key history: {e}");
+            }
+        }
+
         let is_direct_room = self.inner.is_direct().await.unwrap_or_else(|e| {
             warn!(room_id = ?self.room_id(), "is_direct() failed: {e}");
             false
@@ -74,18 +114,34 @@ impl Invited {
         Ok(joined)
     }
 
-    /// The membership details of the (latest) invite for this room.
-    pub async fn invite_details(&self) -> Result<Invite> {
+    /// Our own member, as it was invited to this room.
+    pub(crate) async fn invitee(&self) -> Result<RoomMember> {
         let user_id = self
             .inner
             .client
             .user_id()
             .ok_or_else(|| Error::UnknownError(Box::new(InvitationError::NotAuthenticated)))?;
-        let invitee = self
-            .inner
+        self.inner
             .get_member_no_sync(user_id)
             .await?
-            .ok_or_else(|| Error::UnknownError(Box::new(InvitationError::EventMissing)))?;
+            .ok_or_else(|| Error::UnknownError(Box::new(InvitationError::EventMissing)))
+    }
+
+    /// Import the room keys that the given user shared with us as the history
+    /// of this room.
+    #[cfg(feature = "e2e-encryption")]
+    async fn import_room_key_history(&self, inviter: &UserId) -> Result<()> {
+        let olm = self.inner.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        olm.import_room_key_history(self.room_id(), inviter).await?;
+
+        Ok(())
+    }
+
+    /// The membership details of the (latest) invite for this room.
+    pub async fn invite_details(&self) -> Result<Invite> {
+        let invitee = self.invitee().await?;
         let event = invitee.event();
         let inviter_id = event.sender();
         let inviter = self.inner.get_member_no_sync(inviter_id).await?;
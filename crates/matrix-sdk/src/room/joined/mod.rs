@@ -1,9 +1,8 @@
 #[cfg(feature = "e2e-encryption")]
 use std::sync::Arc;
-use std::{borrow::Borrow, ops::Deref};
+use std::{borrow::Borrow, iter, ops::Deref};
 
 use eyeball::SharedObservable;
-#[cfg(feature = "e2e-encryption")]
 use matrix_sdk_base::RoomMemberships;
 use matrix_sdk_common::instant::{Duration, Instant};
 use mime::{self, Mime};
@@ -23,6 +22,15 @@ use ruma::{
     },
     assign,
     events::{
+        poll::{
+            start::PollKind,
+            unstable_end::UnstablePollEndEventContent,
+            unstable_response::UnstablePollResponseEventContent,
+            unstable_start::{
+                UnstablePollAnswer, UnstablePollAnswers, UnstablePollStartContentBlock,
+                UnstablePollStartEventContent,
+            },
+        },
         receipt::ReceiptThread,
         room::{
             avatar::{ImageInfo, RoomAvatarEventContent},
@@ -31,17 +39,23 @@ use ruma::{
             power_levels::RoomPowerLevelsEventContent,
             topic::RoomTopicEventContent,
         },
-        EmptyStateKey, MessageLikeEventContent, StateEventContent,
+        space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
+        AnyMessageLikeEventContent, EmptyStateKey, MessageLikeEventContent, StateEventContent,
     },
     serde::Raw,
-    EventId, Int, MxcUri, OwnedEventId, OwnedTransactionId, TransactionId, UserId,
+    EventId, Int, MxcUri, OwnedEventId, OwnedServerName, OwnedTransactionId, RoomId, TransactionId,
+    UserId,
 };
-use serde_json::Value;
+use serde_json::{json, Value};
 #[cfg(feature = "e2e-encryption")]
 use tokio::sync::Mutex;
+#[cfg(feature = "e2e-encryption")]
+use tracing::warn;
 use tracing::{debug, instrument};
 
-use super::Left;
+use super::{Left, RoomMember};
+#[cfg(feature = "e2e-encryption")]
+use crate::encryption::CollectStrategy;
 use crate::{
     attachment::AttachmentConfig,
     error::{Error, HttpResult},
@@ -133,9 +147,18 @@ impl Joined {
 
     /// Invite the specified user by `UserId` to this room.
     ///
+    /// If the room is encrypted, its history is visible to all of its members
+    /// and room key history sharing is enabled, the room keys that may be
+    /// shared with new members are sent to the devices of the invited user, as
+    /// defined in [MSC3061]. See
+    /// [`Encryption::set_room_key_history_sharing_enabled()`].
+    ///
     /// # Arguments
     ///
     /// * `user_id` - The `UserId` of the user to invite to the room.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    /// [`Encryption::set_room_key_history_sharing_enabled()`]: crate::encryption::Encryption::set_room_key_history_sharing_enabled
     #[instrument(skip_all)]
     pub async fn invite_user_by_id(&self, user_id: &UserId) -> Result<()> {
         let recipient = InvitationRecipient::UserId { user_id: user_id.to_owned() };
@@ -143,6 +166,50 @@ impl Joined {
         let request = invite_user::v3::Request::new(self.inner.room_id().to_owned(), recipient);
         self.client.send(request, None).await?;
 
+        // The invite went through, failing to share the history shouldn't be
+        // reported as a failed invite.
+        #[cfg(feature = "e2e-encryption")]
+        if let Err(e) = self.share_room_key_history(user_id).await {
+            warn!("Failed to share the room key history with the invited user: {e}");
+        }
+
+        Ok(())
+    }
+
+    /// Share the room keys of this room that may be shared with new members
+    /// with the devices of the given invited user, if enabled.
+    #[cfg(feature = "e2e-encryption")]
+    async fn share_room_key_history(&self, user_id: &UserId) -> Result<()> {
+        use ruma::events::room::history_visibility::HistoryVisibility;
+
+        if !matches!(
+            self.history_visibility(),
+            HistoryVisibility::Shared | HistoryVisibility::WorldReadable
+        ) || !self.is_encrypted().await?
+            || !self.client.encryption().is_room_key_history_sharing_enabled().await?
+        {
+            return Ok(());
+        }
+
+        // Make sure we know the devices of the invited user and that we have
+        // Olm sessions with them.
+        let (request_id, request) = {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+            olm.query_keys_for_users(iter::once(user_id))
+        };
+        self.client.keys_query(&request_id, request.device_keys).await?;
+        self.client.claim_one_time_keys(iter::once(user_id)).await?;
+
+        let requests =
+            self.client.base_client().share_room_key_history(self.inner.room_id(), user_id).await?;
+
+        for request in requests {
+            let response = self.client.send_to_device(&request).await?;
+
+            self.client.mark_request_as_sent(&request.txn_id, &response).await?;
+        }
+
         Ok(())
     }
 
@@ -160,6 +227,37 @@ impl Joined {
         Ok(())
     }
 
+    /// Get the users that asked to join this room, as defined in [MSC2403],
+    /// and are waiting for an answer.
+    ///
+    /// [MSC2403]: https://github.com/matrix-org/matrix-spec-proposals/pull/2403
+    pub async fn knock_requests(&self) -> Result<Vec<RoomMember>> {
+        self.members(RoomMemberships::KNOCK).await
+    }
+
+    /// Accept the request of the given user to join this room.
+    ///
+    /// This invites the user to the room, see
+    /// [`invite_user_by_id()`](Self::invite_user_by_id).
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The `UserId` of the user that knocked on the room.
+    pub async fn accept_knock_request(&self, user_id: &UserId) -> Result<()> {
+        self.invite_user_by_id(user_id).await
+    }
+
+    /// Deny the request of the given user to join this room.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The `UserId` of the user that knocked on the room.
+    ///
+    /// * `reason` - Optional reason why the request is denied.
+    pub async fn deny_knock_request(&self, user_id: &UserId, reason: Option<&str>) -> Result<()> {
+        self.kick_user(user_id, reason).await
+    }
+
     /// Activate typing notice for this room.
     ///
     /// The typing notice remains active for 4s. It can be deactivate at any
@@ -354,6 +452,60 @@ impl Joined {
         Ok(())
     }
 
+    /// Get the strategy used to select the devices that receive the room keys
+    /// of this room, if it overrides the one of the client.
+    ///
+    /// See [`Encryption::room_key_sharing_strategy()`] for the strategy used
+    /// when this returns `None`.
+    ///
+    /// [`Encryption::room_key_sharing_strategy()`]: crate::encryption::Encryption::room_key_sharing_strategy
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn room_key_sharing_strategy(&self) -> Result<Option<CollectStrategy>> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let settings = olm.store().get_room_settings(self.room_id()).await?;
+        Ok(settings.and_then(|s| s.sharing_strategy))
+    }
+
+    /// Set the strategy used to select the devices that receive the room keys
+    /// of this room.
+    ///
+    /// The strategy is persisted in the crypto store. If it is `None`, the
+    /// strategy of the client is used. If the new strategy excludes devices
+    /// that already received the current room key, the room key is rotated
+    /// before the next message is sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `strategy` - The strategy to use for this room, or `None` to use the
+    ///   strategy of the client.
+    #[cfg(feature = "e2e-encryption")]
+    #[instrument(skip(self), fields(room_id = ?self.room_id()))]
+    pub async fn set_room_key_sharing_strategy(
+        &self,
+        strategy: Option<CollectStrategy>,
+    ) -> Result<()> {
+        use std::collections::HashMap;
+
+        use matrix_sdk_base::crypto::store::Changes;
+
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let mut settings = olm.store().get_room_settings(self.room_id()).await?.unwrap_or_default();
+        settings.sharing_strategy = strategy;
+
+        olm.store()
+            .save_changes(Changes {
+                room_settings: HashMap::from([(self.room_id().to_owned(), settings)]),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(())
+    }
+
     /// Share a room key with users in the given room.
     ///
     /// This will create Olm sessions with all the users/device pairs in the
@@ -667,6 +819,115 @@ impl Joined {
         Ok(response)
     }
 
+    /// Queue a poll to be sent to this room, with the [send queue].
+    ///
+    /// Returns the transaction ID of the poll start event.
+    ///
+    /// # Arguments
+    ///
+    /// * `question` - The question of the poll.
+    ///
+    /// * `answers` - The possible answers of the poll. Their number is limited,
+    ///   see [`UnstablePollAnswers`].
+    ///
+    /// * `max_selections` - The maximum number of answers a user can select.
+    ///
+    /// * `kind` - Whether the results are visible before the poll is ended.
+    ///
+    /// * `txn_id` - A locally-unique ID describing a message transaction with
+    ///   the homeserver.
+    ///
+    /// [send queue]: crate::send_queue::RoomSendQueue
+    pub async fn create_poll(
+        &self,
+        question: String,
+        answers: Vec<String>,
+        max_selections: u8,
+        kind: PollKind,
+        txn_id: Option<&TransactionId>,
+    ) -> Result<OwnedTransactionId> {
+        let fallback_text = iter::once(question.clone())
+            .chain(answers.iter().enumerate().map(|(i, answer)| format!("{}. {answer}", i + 1)))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let answers = answers
+            .into_iter()
+            .enumerate()
+            .map(|(i, answer)| UnstablePollAnswer::new(i.to_string(), answer))
+            .collect::<Vec<_>>();
+        let answers =
+            UnstablePollAnswers::try_from(answers).map_err(|_| Error::InvalidPollAnswers)?;
+
+        let mut poll_start = UnstablePollStartContentBlock::new(question, answers);
+        poll_start.kind = kind;
+        poll_start.max_selections = max_selections.into();
+
+        let content = UnstablePollStartEventContent::plain_text(fallback_text, poll_start);
+        self.queue_event(content.into(), txn_id).await
+    }
+
+    /// Queue a vote in a poll to be sent to this room, with the [send queue].
+    ///
+    /// Returns the transaction ID of the poll response event.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_start_id` - The ID of the start event of the poll.
+    ///
+    /// * `answers` - The IDs of the selected answers.
+    ///
+    /// * `txn_id` - A locally-unique ID describing a message transaction with
+    ///   the homeserver.
+    ///
+    /// [send queue]: crate::send_queue::RoomSendQueue
+    pub async fn send_poll_response(
+        &self,
+        poll_start_id: &EventId,
+        answers: Vec<String>,
+        txn_id: Option<&TransactionId>,
+    ) -> Result<OwnedTransactionId> {
+        let content = UnstablePollResponseEventContent::new(answers, poll_start_id.to_owned());
+        self.queue_event(content.into(), txn_id).await
+    }
+
+    /// Queue the end of a poll to be sent to this room, with the [send queue].
+    ///
+    /// Only the sender of the poll can end it.
+    ///
+    /// Returns the transaction ID of the poll end event.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_start_id` - The ID of the start event of the poll.
+    ///
+    /// * `text` - A textual fallback for clients that don't support polls.
+    ///
+    /// * `txn_id` - A locally-unique ID describing a message transaction with
+    ///   the homeserver.
+    ///
+    /// [send queue]: crate::send_queue::RoomSendQueue
+    pub async fn end_poll(
+        &self,
+        poll_start_id: &EventId,
+        text: &str,
+        txn_id: Option<&TransactionId>,
+    ) -> Result<OwnedTransactionId> {
+        let content = UnstablePollEndEventContent::new(text, poll_start_id.to_owned());
+        self.queue_event(content.into(), txn_id).await
+    }
+
+    /// Queue an event with the send queue of the room.
+    async fn queue_event(
+        &self,
+        content: AnyMessageLikeEventContent,
+        txn_id: Option<&TransactionId>,
+    ) -> Result<OwnedTransactionId> {
+        let txn_id = txn_id.map_or_else(TransactionId::new, ToOwned::to_owned);
+        self.send_queue().send_with_transaction_id(content, &txn_id).await?;
+        Ok(txn_id)
+    }
+
     /// Send an attachment to this room.
     ///
     /// This will upload the given data that the reader produces using the
@@ -886,6 +1147,86 @@ impl Joined {
         self.set_avatar_url(&upload_response.content_uri, Some(info)).await
     }
 
+    /// Add a child to this room, which should be a space.
+    ///
+    /// This sends an `m.space.child` state event to this room. To also declare
+    /// this room as a parent of the child room, see
+    /// [`Joined::add_space_parent()`] on the child room.
+    ///
+    /// # Arguments
+    ///
+    /// * `child_id` - The ID of the child room.
+    ///
+    /// * `via` - The servers that can be used to join the child room. It must
+    ///   not be empty, otherwise the child would be considered removed, and an
+    ///   [`Error::EmptySpaceVia`] is returned. The child room's
+    ///   [`route()`](Common::route) is a good candidate.
+    ///
+    /// * `suggested` - Whether the child room should be suggested to the
+    ///   members of the space.
+    pub async fn add_space_child(
+        &self,
+        child_id: &RoomId,
+        via: Vec<OwnedServerName>,
+        suggested: bool,
+    ) -> Result<send_state_event::v3::Response> {
+        if via.is_empty() {
+            return Err(Error::EmptySpaceVia);
+        }
+
+        let content = assign!(SpaceChildEventContent::new(via), { suggested });
+        self.send_state_event_for_key(child_id, content).await
+    }
+
+    /// Remove a child from this room, which should be a space.
+    ///
+    /// This replaces the `m.space.child` state event of the child in this room
+    /// with an empty one.
+    pub async fn remove_space_child(
+        &self,
+        child_id: &RoomId,
+    ) -> Result<send_state_event::v3::Response> {
+        self.send_state_event_raw(json!({}), "m.space.child", child_id.as_str()).await
+    }
+
+    /// Declare the given space as a parent of this room.
+    ///
+    /// This sends an `m.space.parent` state event to this room.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent_id` - The ID of the parent space.
+    ///
+    /// * `via` - The servers that can be used to join the parent space. It must
+    ///   not be empty, otherwise the parent would be considered removed, and an
+    ///   [`Error::EmptySpaceVia`] is returned.
+    ///
+    /// * `canonical` - Whether this is the main parent of this room.
+    pub async fn add_space_parent(
+        &self,
+        parent_id: &RoomId,
+        via: Vec<OwnedServerName>,
+        canonical: bool,
+    ) -> Result<send_state_event::v3::Response> {
+        if via.is_empty() {
+            return Err(Error::EmptySpaceVia);
+        }
+
+        let content = assign!(SpaceParentEventContent::new(via), { canonical });
+        self.send_state_event_for_key(parent_id, content).await
+    }
+
+    /// Remove the given space from the parents of this room.
+    ///
+    /// This replaces the `m.space.parent` state event of the parent in this
+    /// room with an empty one.
+    pub async fn remove_space_parent(
+        &self,
+        parent_id: &RoomId,
+    ) -> Result<send_state_event::v3::Response> {
+        self.send_state_event_raw(json!({}), "m.space.parent", parent_id.as_str()).await
+    }
+
     /// Send a state event with an empty state key to the homeserver.
     ///
     /// For state events with a non-empty state key, see
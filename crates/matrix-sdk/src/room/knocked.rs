@@ -0,0 +1,44 @@
+use std::ops::Deref;
+
+use super::Left;
+use crate::{room::Common, BaseRoom, Client, Result, RoomState};
+
+/// A room in the knocked state.
+///
+/// This struct contains all methods specific to a `Room` with
+/// `RoomState::Knocked`. Operations may fail once the underlying `Room` changes
+/// `RoomState`.
+#[derive(Debug, Clone)]
+pub struct Knocked {
+    pub(crate) inner: Common,
+}
+
+impl Knocked {
+    /// Create a new `room::Knocked` if the underlying `Room` has
+    /// `RoomState::Knocked`.
+    ///
+    /// # Arguments
+    /// * `client` - The client used to make requests.
+    ///
+    /// * `room` - The underlying room.
+    pub(crate) fn new(client: &Client, room: BaseRoom) -> Option<Self> {
+        if room.state() == RoomState::Knocked {
+            Some(Self { inner: Common::new(client.clone(), room) })
+        } else {
+            None
+        }
+    }
+
+    /// Withdraw the request to join this room.
+    pub async fn cancel_knock(&self) -> Result<Left> {
+        self.inner.leave().await
+    }
+}
+
+impl Deref for Knocked {
+    type Target = Common;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
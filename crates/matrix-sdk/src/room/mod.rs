@@ -7,15 +7,19 @@ use crate::RoomState;
 mod common;
 mod invited;
 mod joined;
+mod knocked;
 mod left;
 mod member;
+mod space;
 
 pub use self::{
     common::{Common, Messages, MessagesOptions},
     invited::{Invite, Invited},
     joined::{Joined, Receipts},
+    knocked::Knocked,
     left::Left,
     member::RoomMember,
+    space::{SpaceChild, SpaceParent},
 };
 
 /// An enum that abstracts over the different states a room can be in.
@@ -27,6 +31,8 @@ pub enum Room {
     Left(Left),
     /// The room in the `invited` state.
     Invited(Invited),
+    /// The room in the `knocked` state.
+    Knocked(Knocked),
 }
 
 impl Deref for Room {
@@ -37,6 +43,7 @@ impl Deref for Room {
             Self::Joined(room) => room,
             Self::Left(room) => room,
             Self::Invited(room) => room,
+            Self::Knocked(room) => room,
         }
     }
 }
@@ -47,6 +54,7 @@ impl From<Common> for Room {
             RoomState::Joined => Self::Joined(Joined { inner: room }),
             RoomState::Left => Self::Left(Left { inner: room }),
             RoomState::Invited => Self::Invited(Invited { inner: room }),
+            RoomState::Knocked => Self::Knocked(Knocked { inner: room }),
         }
     }
 }
@@ -58,6 +66,7 @@ impl From<Joined> for Room {
             RoomState::Joined => Self::Joined(Joined { inner: room }),
             RoomState::Left => Self::Left(Left { inner: room }),
             RoomState::Invited => Self::Invited(Invited { inner: room }),
+            RoomState::Knocked => Self::Knocked(Knocked { inner: room }),
         }
     }
 }
@@ -69,6 +78,7 @@ impl From<Left> for Room {
             RoomState::Joined => Self::Joined(Joined { inner: room }),
             RoomState::Left => Self::Left(Left { inner: room }),
             RoomState::Invited => Self::Invited(Invited { inner: room }),
+            RoomState::Knocked => Self::Knocked(Knocked { inner: room }),
         }
     }
 }
@@ -80,6 +90,19 @@ impl From<Invited> for Room {
             RoomState::Joined => Self::Joined(Joined { inner: room }),
             RoomState::Left => Self::Left(Left { inner: room }),
             RoomState::Invited => Self::Invited(Invited { inner: room }),
+            RoomState::Knocked => Self::Knocked(Knocked { inner: room }),
+        }
+    }
+}
+
+impl From<Knocked> for Room {
+    fn from(room: Knocked) -> Self {
+        let room = (*room).clone();
+        match room.state() {
+            RoomState::Joined => Self::Joined(Joined { inner: room }),
+            RoomState::Left => Self::Left(Left { inner: room }),
+            RoomState::Invited => Self::Invited(Invited { inner: room }),
+            RoomState::Knocked => Self::Knocked(Knocked { inner: room }),
         }
     }
 }
@@ -0,0 +1,259 @@
+//! Types describing the relationships between spaces and rooms.
+//!
+//! See the [spaces section] of the Matrix specification.
+//!
+//! [spaces section]: https://spec.matrix.org/v1.8/client-server-api/#spaces
+
+use std::cmp::Ordering;
+
+use matrix_sdk_base::deserialized_responses::RawSyncOrStrippedState;
+use ruma::{
+    events::{
+        space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
+        RedactContent, RedactedStateEventContent, StaticStateEventContent,
+    },
+    OwnedRoomId, OwnedServerName,
+};
+use serde::Deserialize;
+
+/// A child room of a space, as declared by an `m.space.child` state event in
+/// the space.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpaceChild {
+    /// The ID of the child room.
+    pub room_id: OwnedRoomId,
+
+    /// The servers that can be used to join the child room.
+    ///
+    /// Never empty.
+    pub via: Vec<OwnedServerName>,
+
+    /// The string used to order the children of the space, if any.
+    ///
+    /// Only valid orders, according to the specification, are kept.
+    pub order: Option<String>,
+
+    /// Whether the child room is suggested to the members of the space.
+    pub suggested: bool,
+}
+
+impl SpaceChild {
+    /// Compare two children of the same space according to the ordering
+    /// rules of the specification.
+    ///
+    /// Children with an `order` come first, sorted by `order`. The room ID is
+    /// used as a tie-breaker.
+    pub fn cmp_order(&self, other: &Self) -> Ordering {
+        match (&self.order, &other.order) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+        .then_with(|| self.room_id.cmp(&other.room_id))
+    }
+
+    pub(super) fn from_raw(raw: &RawSyncOrStrippedState<SpaceChildEventContent>) -> Option<Self> {
+        let event: SpaceStateEvent<ChildContent> = deserialize_raw(raw)?;
+
+        if event.content.via.is_empty() {
+            // A child without `via` servers has been removed from the space.
+            return None;
+        }
+
+        Some(Self {
+            room_id: event.state_key,
+            via: event.content.via,
+            order: event.content.order.filter(|order| is_valid_order(order)),
+            suggested: event.content.suggested,
+        })
+    }
+}
+
+/// A parent space of a room, as declared by an `m.space.parent` state event in
+/// the room.
+///
+/// Note that this claim is not verified: the parent space might not list the
+/// room as one of its children.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpaceParent {
+    /// The ID of the parent space.
+    pub room_id: OwnedRoomId,
+
+    /// The servers that can be used to join the parent space.
+    ///
+    /// Never empty.
+    pub via: Vec<OwnedServerName>,
+
+    /// Whether this is the main parent of the room.
+    pub canonical: bool,
+}
+
+impl SpaceParent {
+    pub(super) fn from_raw(raw: &RawSyncOrStrippedState<SpaceParentEventContent>) -> Option<Self> {
+        let event: SpaceStateEvent<ParentContent> = deserialize_raw(raw)?;
+
+        if event.content.via.is_empty() {
+            // A parent without `via` servers is not valid anymore.
+            return None;
+        }
+
+        Some(Self {
+            room_id: event.state_key,
+            via: event.content.via,
+            canonical: event.content.canonical,
+        })
+    }
+}
+
+/// The parts of a space state event we care about.
+///
+/// Redacted events have an empty content, which deserializes to an empty
+/// `via` list.
+#[derive(Deserialize)]
+struct SpaceStateEvent<C> {
+    state_key: OwnedRoomId,
+    content: C,
+}
+
+#[derive(Deserialize)]
+struct ChildContent {
+    #[serde(default)]
+    via: Vec<OwnedServerName>,
+    #[serde(default)]
+    order: Option<String>,
+    #[serde(default)]
+    suggested: bool,
+}
+
+#[derive(Deserialize)]
+struct ParentContent {
+    #[serde(default)]
+    via: Vec<OwnedServerName>,
+    #[serde(default)]
+    canonical: bool,
+}
+
+fn deserialize_raw<C, T>(raw: &RawSyncOrStrippedState<C>) -> Option<SpaceStateEvent<T>>
+where
+    C: StaticStateEventContent + RedactContent,
+    C::Redacted: RedactedStateEventContent,
+    T: for<'de> Deserialize<'de>,
+{
+    match raw {
+        RawSyncOrStrippedState::Sync(raw) => raw.deserialize_as().ok(),
+        RawSyncOrStrippedState::Stripped(raw) => raw.deserialize_as().ok(),
+    }
+}
+
+/// Whether the given `order` of a space child is valid, i.e. it is at most 50
+/// characters long and only contains printable ASCII characters.
+fn is_valid_order(order: &str) -> bool {
+    order.len() <= 50 && order.bytes().all(|b| (0x20..=0x7E).contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_base::deserialized_responses::RawSyncOrStrippedState;
+    use ruma::{
+        events::{RedactContent, RedactedStateEventContent, StaticStateEventContent},
+        owned_room_id,
+        serde::Raw,
+        server_name,
+    };
+    use serde_json::json;
+
+    use super::{SpaceChild, SpaceParent};
+
+    fn raw_sync<C>(event: serde_json::Value) -> RawSyncOrStrippedState<C>
+    where
+        C: StaticStateEventContent + RedactContent,
+        C::Redacted: RedactedStateEventContent,
+    {
+        RawSyncOrStrippedState::Sync(Raw::new(&event).unwrap().cast())
+    }
+
+    #[test]
+    fn test_space_child_from_raw() {
+        let child = SpaceChild::from_raw(&raw_sync(json!({
+            "type": "m.space.child",
+            "state_key": "!child:example.org",
+            "content": { "via": ["example.org"], "order": "a", "suggested": true },
+            "event_id": "$1",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 0,
+        })))
+        .unwrap();
+
+        assert_eq!(child.room_id, owned_room_id!("!child:example.org"));
+        assert_eq!(child.via, vec![server_name!("example.org").to_owned()]);
+        assert_eq!(child.order.as_deref(), Some("a"));
+        assert!(child.suggested);
+
+        // Invalid orders are ignored.
+        let child = SpaceChild::from_raw(&raw_sync(json!({
+            "type": "m.space.child",
+            "state_key": "!child:example.org",
+            "content": { "via": ["example.org"], "order": "\n" },
+            "event_id": "$1",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 0,
+        })))
+        .unwrap();
+        assert_eq!(child.order, None);
+        assert!(!child.suggested);
+
+        // A child without `via` has been removed.
+        assert_eq!(
+            SpaceChild::from_raw(&raw_sync(json!({
+                "type": "m.space.child",
+                "state_key": "!child:example.org",
+                "content": {},
+                "event_id": "$1",
+                "sender": "@alice:example.org",
+                "origin_server_ts": 0,
+            }))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_space_children_order() {
+        let child = |room_id: &str, order: Option<&str>| SpaceChild {
+            room_id: room_id.try_into().unwrap(),
+            via: vec![server_name!("example.org").to_owned()],
+            order: order.map(ToOwned::to_owned),
+            suggested: false,
+        };
+
+        let mut children = vec![
+            child("!a:example.org", None),
+            child("!b:example.org", Some("b")),
+            child("!c:example.org", Some("a")),
+            child("!d:example.org", Some("a")),
+        ];
+        children.sort_by(SpaceChild::cmp_order);
+
+        let room_ids: Vec<_> = children.iter().map(|child| child.room_id.as_str()).collect();
+        assert_eq!(
+            room_ids,
+            ["!c:example.org", "!d:example.org", "!b:example.org", "!a:example.org"]
+        );
+    }
+
+    #[test]
+    fn test_space_parent_from_raw() {
+        let parent = SpaceParent::from_raw(&raw_sync(json!({
+            "type": "m.space.parent",
+            "state_key": "!space:example.org",
+            "content": { "via": ["example.org"], "canonical": true },
+            "event_id": "$1",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 0,
+        })))
+        .unwrap();
+
+        assert_eq!(parent.room_id, owned_room_id!("!space:example.org"));
+        assert!(parent.canonical);
+    }
+}
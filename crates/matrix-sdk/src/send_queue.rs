@@ -0,0 +1,725 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A persistent queue of the requests to send to rooms.
+//!
+//! Every room has its own queue, accessible with
+//! [`Common::send_queue()`](crate::room::Common::send_queue). The requests of
+//! a room are sent one after the other, in the order they were queued, by a
+//! background task. They are saved in the state store until they are sent, so
+//! they survive restarts of the application: call
+//! [`SendQueue::respawn_tasks_for_rooms_with_unsent_requests()`] after a
+//! restart to resume sending them.
+//!
+//! When sending a request fails with an error that is likely temporary, like
+//! a network error or a server error, it is retried with an exponential
+//! backoff. Other errors *wedge* the request: it blocks the requests after it
+//! in the same room until it is retried with [`RoomSendQueue::unwedge()`] or
+//! cancelled with [`RoomSendQueue::cancel()`].
+//!
+//! Requests can only be sent to joined rooms. When the room isn't joined
+//! anymore, the request is removed from the queue and a
+//! [`RoomSendQueueError::RoomNotJoined`] error is sent to the subscribers.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::Duration,
+};
+
+use futures_util::future::select;
+pub use matrix_sdk_base::store::{QueuedRequest, QueuedRequestKind, SerializableEventContent};
+use matrix_sdk_common::sleep::sleep;
+#[cfg(not(target_arch = "wasm32"))]
+use mime::Mime;
+use ruma::{
+    api::client::error::ErrorKind, events::AnyMessageLikeEventContent, EventId, OwnedEventId,
+    OwnedRoomId, OwnedTransactionId, RoomId, TransactionId,
+};
+use thiserror::Error;
+use tokio::sync::{broadcast, Mutex, Notify};
+use tracing::{debug, info, instrument, trace, warn};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::attachment::AttachmentConfig;
+use crate::{client::WeakClient, executor::spawn, Client, Error, HttpError, Result, RumaApiError};
+
+/// The maximum delay between two attempts to send a request.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// The send queues of all the rooms of a client.
+///
+/// Get one with [`Client::send_queue()`].
+#[derive(Clone, Debug)]
+pub struct SendQueue {
+    client: Client,
+}
+
+impl SendQueue {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn data(&self) -> &SendQueueData {
+        &self.client.inner.send_queue_data
+    }
+
+    /// Get the send queue of the given room, spawning its background task if
+    /// needed.
+    pub(crate) fn for_room(&self, room_id: &RoomId) -> RoomSendQueue {
+        let data = self.data();
+        let mut rooms = data.rooms.lock().unwrap();
+
+        let inner = rooms
+            .entry(room_id.to_owned())
+            .or_insert_with(|| {
+                Arc::new(RoomSendQueueInner::new(
+                    WeakClient::from_client(&self.client),
+                    room_id.to_owned(),
+                    data.globally_enabled.clone(),
+                ))
+            })
+            .clone();
+
+        RoomSendQueue { client: self.client.clone(), inner }
+    }
+
+    /// Resume sending the requests of the rooms that still have unsent
+    /// requests in the store.
+    ///
+    /// This should be called once after the client is restored from a
+    /// previous session.
+    pub async fn respawn_tasks_for_rooms_with_unsent_requests(&self) -> Result<()> {
+        let room_ids = self.client.store().load_rooms_with_unsent_requests().await?;
+
+        for room_id in room_ids {
+            debug!(?room_id, "Respawning the send queue of a room");
+            self.for_room(&room_id);
+        }
+
+        Ok(())
+    }
+
+    /// Enable or disable sending the requests of all the rooms.
+    ///
+    /// When disabled, requests are still queued, but they are only sent once
+    /// the send queue is enabled again. It is enabled by default.
+    pub fn set_enabled(&self, enabled: bool) {
+        let data = self.data();
+        data.globally_enabled.store(enabled, Ordering::SeqCst);
+
+        if enabled {
+            for inner in data.rooms.lock().unwrap().values() {
+                inner.notifier.notify_one();
+            }
+        }
+    }
+
+    /// Whether sending the requests is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.data().globally_enabled.load(Ordering::SeqCst)
+    }
+}
+
+/// The state of the send queues, shared by all the clones of a client.
+pub(crate) struct SendQueueData {
+    rooms: StdMutex<BTreeMap<OwnedRoomId, Arc<RoomSendQueueInner>>>,
+    globally_enabled: Arc<AtomicBool>,
+}
+
+impl Default for SendQueueData {
+    fn default() -> Self {
+        Self { rooms: Default::default(), globally_enabled: Arc::new(AtomicBool::new(true)) }
+    }
+}
+
+/// An update of the send queue of a room.
+#[derive(Clone, Debug)]
+pub enum RoomSendQueueUpdate {
+    /// A new request was added to the queue.
+    NewLocalEvent(QueuedRequest),
+
+    /// A request was removed from the queue before it was sent.
+    CancelledLocalEvent {
+        /// The transaction ID of the request.
+        transaction_id: OwnedTransactionId,
+    },
+
+    /// Sending a request failed.
+    ///
+    /// If the error is not recoverable, the request is wedged and the
+    /// following requests are blocked until it is unwedged or cancelled. If
+    /// the error is [`RoomSendQueueError::RoomNotJoined`], the request is
+    /// removed from the queue instead.
+    SendError {
+        /// The transaction ID of the request.
+        transaction_id: OwnedTransactionId,
+
+        /// The error that happened.
+        error: Arc<Error>,
+
+        /// Whether the request will be retried automatically.
+        is_recoverable: bool,
+    },
+
+    /// A wedged request was unwedged, to be sent again.
+    RetryEvent {
+        /// The transaction ID of the request.
+        transaction_id: OwnedTransactionId,
+    },
+
+    /// A request was sent successfully.
+    SentEvent {
+        /// The transaction ID of the request.
+        transaction_id: OwnedTransactionId,
+
+        /// The ID of the event that was sent.
+        event_id: OwnedEventId,
+    },
+}
+
+/// An error specific to the send queue.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum RoomSendQueueError {
+    /// The room isn't joined, so the request can't be sent.
+    ///
+    /// The request is removed from the queue, it needs to be queued again
+    /// once the room is joined.
+    #[error("The room isn't joined, the request can't be sent")]
+    RoomNotJoined,
+
+    /// The request failed to be sent in a previous session of the client.
+    ///
+    /// The original error isn't persisted, only the fact that the request is
+    /// wedged.
+    #[error("The request failed to be sent in a previous session")]
+    FailedInPreviousSession,
+
+    /// The request failed to be sent, but the error was missed.
+    ///
+    /// This happens when a subscriber lagged behind the updates of the queue.
+    #[error("The request failed to be sent with an unknown error")]
+    UnknownFailure,
+}
+
+/// The send queue of a room.
+///
+/// Get one with [`Common::send_queue()`](crate::room::Common::send_queue).
+#[derive(Clone)]
+pub struct RoomSendQueue {
+    client: Client,
+    inner: Arc<RoomSendQueueInner>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for RoomSendQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoomSendQueue").field("room_id", &self.inner.room_id).finish()
+    }
+}
+
+impl RoomSendQueue {
+    /// Queue a message-like event to be sent to the room.
+    ///
+    /// Returns the transaction ID of the request.
+    pub async fn send(&self, content: AnyMessageLikeEventContent) -> Result<OwnedTransactionId> {
+        let transaction_id = TransactionId::new();
+        self.send_with_transaction_id(content, &transaction_id).await?;
+        Ok(transaction_id)
+    }
+
+    /// Queue a message-like event to be sent to the room, with the given
+    /// transaction ID.
+    pub async fn send_with_transaction_id(
+        &self,
+        content: AnyMessageLikeEventContent,
+        transaction_id: &TransactionId,
+    ) -> Result<()> {
+        let content = SerializableEventContent::new(&content)?;
+        self.push(QueuedRequest::new(
+            transaction_id.to_owned(),
+            QueuedRequestKind::Event { content },
+        ))
+        .await
+    }
+
+    /// Queue the redaction of an event of the room.
+    ///
+    /// Returns the transaction ID of the request.
+    pub async fn send_redaction(
+        &self,
+        event_id: &EventId,
+        reason: Option<&str>,
+    ) -> Result<OwnedTransactionId> {
+        let transaction_id = TransactionId::new();
+        let kind = QueuedRequestKind::Redaction {
+            redacts: event_id.to_owned(),
+            reason: reason.map(ToOwned::to_owned),
+        };
+        self.push(QueuedRequest::new(transaction_id.clone(), kind)).await?;
+        Ok(transaction_id)
+    }
+
+    /// Queue a file to be uploaded and sent as a message to the room.
+    ///
+    /// The file is only read when the request is sent, so it must not be
+    /// removed before that.
+    ///
+    /// Returns the transaction ID of the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - A textual representation of the file, usually its name.
+    ///
+    /// * `content_type` - The MIME type of the file.
+    ///
+    /// * `path` - The path of the local file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn send_attachment(
+        &self,
+        body: &str,
+        content_type: &Mime,
+        path: PathBuf,
+    ) -> Result<OwnedTransactionId> {
+        let transaction_id = TransactionId::new();
+        let kind = QueuedRequestKind::Attachment {
+            body: body.to_owned(),
+            content_type: content_type.to_string(),
+            path,
+        };
+        self.push(QueuedRequest::new(transaction_id.clone(), kind)).await?;
+        Ok(transaction_id)
+    }
+
+    async fn push(&self, request: QueuedRequest) -> Result<()> {
+        self.inner.queue.push(&self.client, request.clone()).await?;
+
+        let _ = self.inner.updates.send(RoomSendQueueUpdate::NewLocalEvent(request));
+        self.inner.notifier.notify_one();
+
+        Ok(())
+    }
+
+    /// Get the requests of the queue that were not sent yet, in the order they
+    /// will be sent.
+    ///
+    /// This can be used to restore the local echoes of the room.
+    pub async fn local_echoes(&self) -> Result<Vec<QueuedRequest>> {
+        self.inner.queue.requests(&self.client).await
+    }
+
+    /// Subscribe to the updates of the queue.
+    pub fn subscribe(&self) -> broadcast::Receiver<RoomSendQueueUpdate> {
+        self.inner.updates.subscribe()
+    }
+
+    /// Remove a request from the queue.
+    ///
+    /// Returns `false` if the request wasn't found, or if it is being sent and
+    /// can't be cancelled anymore.
+    pub async fn cancel(&self, transaction_id: &TransactionId) -> Result<bool> {
+        let cancelled = self.inner.queue.cancel(&self.client, transaction_id).await?;
+
+        if cancelled {
+            let _ = self.inner.updates.send(RoomSendQueueUpdate::CancelledLocalEvent {
+                transaction_id: transaction_id.to_owned(),
+            });
+            // The request might have been blocking the queue.
+            self.inner.notifier.notify_one();
+        }
+
+        Ok(cancelled)
+    }
+
+    /// Whether the request with the given transaction ID is being sent.
+    ///
+    /// Such a request can't be cancelled or unwedged anymore, and must not be
+    /// queued again.
+    pub async fn is_being_sent(&self, transaction_id: &TransactionId) -> bool {
+        self.inner.queue.is_being_sent(transaction_id).await
+    }
+
+    /// Mark a request that failed to be sent as not wedged anymore, to send it
+    /// again.
+    ///
+    /// The request keeps its position in the queue, so it is sent before the
+    /// requests that were queued after it.
+    ///
+    /// Returns `false` if the request wasn't found, or if it is being sent.
+    pub async fn unwedge(&self, transaction_id: &TransactionId) -> Result<bool> {
+        let unwedged = self.inner.queue.unwedge(&self.client, transaction_id).await?;
+
+        if unwedged {
+            let _ = self.inner.updates.send(RoomSendQueueUpdate::RetryEvent {
+                transaction_id: transaction_id.to_owned(),
+            });
+            self.inner.notifier.notify_one();
+        }
+
+        Ok(unwedged)
+    }
+}
+
+struct RoomSendQueueInner {
+    room_id: OwnedRoomId,
+    updates: broadcast::Sender<RoomSendQueueUpdate>,
+    queue: QueueStorage,
+    /// Wakes up the sending task when there is something new to do.
+    notifier: Arc<Notify>,
+}
+
+impl RoomSendQueueInner {
+    fn new(client: WeakClient, room_id: OwnedRoomId, globally_enabled: Arc<AtomicBool>) -> Self {
+        let (updates, _) = broadcast::channel(32);
+        let queue = QueueStorage::new(room_id.clone());
+        let notifier = Arc::new(Notify::new());
+
+        spawn(sending_task(
+            client,
+            room_id.clone(),
+            queue.clone(),
+            notifier.clone(),
+            updates.clone(),
+            globally_enabled,
+        ));
+
+        Self { room_id, updates, queue, notifier }
+    }
+}
+
+impl Drop for RoomSendQueueInner {
+    fn drop(&mut self) {
+        // Wake up the sending task so it notices that the client is gone.
+        self.notifier.notify_one();
+    }
+}
+
+/// The requests of the queue of a room, persisted in the state store.
+#[derive(Clone)]
+struct QueueStorage {
+    room_id: OwnedRoomId,
+    /// The transaction ID of the request that is being sent, if any.
+    ///
+    /// The lock is also held during every access to the store, to apply the
+    /// changes to the queue one at a time.
+    being_sent: Arc<Mutex<Option<OwnedTransactionId>>>,
+}
+
+impl QueueStorage {
+    fn new(room_id: OwnedRoomId) -> Self {
+        Self { room_id, being_sent: Default::default() }
+    }
+
+    async fn push(&self, client: &Client, request: QueuedRequest) -> Result<()> {
+        let _being_sent = self.being_sent.lock().await;
+        Ok(client.store().save_send_queue_request(&self.room_id, request).await?)
+    }
+
+    async fn requests(&self, client: &Client) -> Result<Vec<QueuedRequest>> {
+        let _being_sent = self.being_sent.lock().await;
+        Ok(client.store().load_send_queue_requests(&self.room_id).await?)
+    }
+
+    /// Get the next request to send and mark it as being sent.
+    ///
+    /// Returns `None` if the queue is empty or blocked by a wedged request.
+    async fn peek_next_to_send(&self, client: &Client) -> Result<Option<QueuedRequest>> {
+        let mut being_sent = self.being_sent.lock().await;
+
+        let requests = client.store().load_send_queue_requests(&self.room_id).await?;
+        let next = requests.into_iter().next().filter(|request| !request.is_wedged);
+
+        if let Some(request) = &next {
+            *being_sent = Some(request.transaction_id.clone());
+        }
+
+        Ok(next)
+    }
+
+    async fn is_being_sent(&self, transaction_id: &TransactionId) -> bool {
+        self.being_sent.lock().await.as_deref() == Some(transaction_id)
+    }
+
+    async fn mark_as_not_being_sent(&self) {
+        *self.being_sent.lock().await = None;
+    }
+
+    /// Remove the request that was being sent from the queue, because it was
+    /// sent or because it can never be sent.
+    async fn remove_being_sent(
+        &self,
+        client: &Client,
+        transaction_id: &TransactionId,
+    ) -> Result<()> {
+        let mut being_sent = self.being_sent.lock().await;
+        *being_sent = None;
+
+        client.store().remove_send_queue_request(&self.room_id, transaction_id).await?;
+        Ok(())
+    }
+
+    async fn mark_as_wedged(&self, client: &Client, transaction_id: &TransactionId) -> Result<()> {
+        let mut being_sent = self.being_sent.lock().await;
+        *being_sent = None;
+
+        client
+            .store()
+            .update_send_queue_request_status(&self.room_id, transaction_id, true)
+            .await?;
+        Ok(())
+    }
+
+    async fn cancel(&self, client: &Client, transaction_id: &TransactionId) -> Result<bool> {
+        let being_sent = self.being_sent.lock().await;
+        if being_sent.as_deref() == Some(transaction_id) {
+            return Ok(false);
+        }
+
+        Ok(client.store().remove_send_queue_request(&self.room_id, transaction_id).await?)
+    }
+
+    async fn unwedge(&self, client: &Client, transaction_id: &TransactionId) -> Result<bool> {
+        let being_sent = self.being_sent.lock().await;
+        if being_sent.as_deref() == Some(transaction_id) {
+            return Ok(false);
+        }
+
+        // Keep the request where it is, so it is still sent before the requests
+        // that were queued after it.
+        Ok(client
+            .store()
+            .update_send_queue_request_status(&self.room_id, transaction_id, false)
+            .await?)
+    }
+}
+
+/// The background task sending the requests of the queue of a room.
+#[instrument(skip_all, fields(room_id = ?room_id))]
+async fn sending_task(
+    weak_client: WeakClient,
+    room_id: OwnedRoomId,
+    queue: QueueStorage,
+    notifier: Arc<Notify>,
+    updates: broadcast::Sender<RoomSendQueueUpdate>,
+    globally_enabled: Arc<AtomicBool>,
+) {
+    info!("Starting the sending task");
+
+    let mut num_failed_attempts = 0;
+
+    loop {
+        let Some(client) = weak_client.get() else {
+            break;
+        };
+
+        if !globally_enabled.load(Ordering::SeqCst) {
+            trace!("The send queue is disabled, waiting");
+            drop(client);
+            notifier.notified().await;
+            continue;
+        }
+
+        let request = match queue.peek_next_to_send(&client).await {
+            Ok(Some(request)) => request,
+            Ok(None) => {
+                trace!("Nothing to send, waiting");
+                drop(client);
+                notifier.notified().await;
+                continue;
+            }
+            Err(error) => {
+                warn!("Failed to load the next request to send: {error}");
+                drop(client);
+                notifier.notified().await;
+                continue;
+            }
+        };
+
+        let transaction_id = request.transaction_id.clone();
+        trace!(?transaction_id, "Sending a request");
+
+        match send_request(&client, &room_id, request).await {
+            Ok(event_id) => {
+                debug!(?transaction_id, ?event_id, "Request sent");
+                num_failed_attempts = 0;
+
+                if let Err(error) = queue.remove_being_sent(&client, &transaction_id).await {
+                    // The request will be sent again, with the same transaction ID, so the
+                    // server won't create a duplicate event.
+                    warn!(
+                        ?transaction_id,
+                        "Failed to remove a sent request from the store: {error}"
+                    );
+                }
+
+                let _ = updates.send(RoomSendQueueUpdate::SentEvent { transaction_id, event_id });
+            }
+
+            Err(error @ Error::SendQueue(RoomSendQueueError::RoomNotJoined)) => {
+                // The request would block the queue until the room is joined again, which
+                // might never happen, so drop it.
+                warn!(?transaction_id, "The room isn't joined, dropping the request");
+                num_failed_attempts = 0;
+
+                if let Err(error) = queue.remove_being_sent(&client, &transaction_id).await {
+                    warn!(?transaction_id, "Failed to remove a request from the store: {error}");
+                }
+
+                let _ = updates.send(RoomSendQueueUpdate::SendError {
+                    transaction_id,
+                    error: Arc::new(error),
+                    is_recoverable: false,
+                });
+            }
+
+            Err(error) if is_recoverable_error(&error) => {
+                queue.mark_as_not_being_sent().await;
+
+                num_failed_attempts += 1;
+                let delay = retry_delay(num_failed_attempts);
+                warn!(?transaction_id, ?delay, "Failed to send a request, retrying: {error}");
+
+                let _ = updates.send(RoomSendQueueUpdate::SendError {
+                    transaction_id,
+                    error: Arc::new(error),
+                    is_recoverable: true,
+                });
+
+                // Wait before retrying, unless something new happens in the queue.
+                drop(client);
+                select(Box::pin(sleep(delay)), Box::pin(notifier.notified())).await;
+            }
+
+            Err(error) => {
+                warn!(?transaction_id, "Failed to send a request, wedging it: {error}");
+                num_failed_attempts = 0;
+
+                let result = queue.mark_as_wedged(&client, &transaction_id).await;
+
+                let _ = updates.send(RoomSendQueueUpdate::SendError {
+                    transaction_id,
+                    error: Arc::new(error),
+                    is_recoverable: false,
+                });
+
+                if let Err(error) = result {
+                    // Don't try again right away, the request would fail the same way.
+                    warn!("Failed to mark a request as wedged: {error}");
+                    drop(client);
+                    notifier.notified().await;
+                }
+            }
+        }
+    }
+
+    info!("The client is gone, stopping the sending task");
+}
+
+/// Send a request of the queue.
+///
+/// Returns the ID of the event that was sent.
+async fn send_request(
+    client: &Client,
+    room_id: &RoomId,
+    request: QueuedRequest,
+) -> Result<OwnedEventId> {
+    let Some(room) = client.get_joined_room(room_id) else {
+        return Err(RoomSendQueueError::RoomNotJoined.into());
+    };
+
+    let transaction_id = request.transaction_id;
+
+    match request.kind {
+        QueuedRequestKind::Event { content } => {
+            let json = content.raw().deserialize_as()?;
+            let response = room.send_raw(json, content.event_type(), Some(&transaction_id)).await?;
+            Ok(response.event_id)
+        }
+
+        QueuedRequestKind::Redaction { redacts, reason } => {
+            let response = room.redact(&redacts, reason.as_deref(), Some(transaction_id)).await?;
+            Ok(response.event_id)
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        QueuedRequestKind::Attachment { body, content_type, path } => {
+            let content_type: Mime =
+                content_type.parse().map_err(|e| Error::UnknownError(Box::new(e)))?;
+            let data = tokio::fs::read(&path).await?;
+            let config = AttachmentConfig::new().txn_id(&transaction_id);
+
+            let response = room.send_attachment(&body, &content_type, data, config).await?;
+            Ok(response.event_id)
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        QueuedRequestKind::Attachment { .. } => {
+            Err(Error::UnknownError("Attachments can't be sent on this platform".into()))
+        }
+    }
+}
+
+/// Whether the given error is likely temporary, so the request can be retried
+/// later.
+fn is_recoverable_error(error: &Error) -> bool {
+    let Error::Http(http_error) = error else {
+        return false;
+    };
+
+    match http_error {
+        // Most likely a network error.
+        HttpError::Reqwest(_) => true,
+        _ => match http_error.as_ruma_api_error() {
+            Some(RumaApiError::ClientApi(e)) => {
+                e.status_code.is_server_error()
+                    || matches!(
+                        http_error.client_api_error_kind(),
+                        Some(ErrorKind::LimitExceeded { .. })
+                    )
+            }
+            Some(RumaApiError::Other(e)) => e.status_code.is_server_error(),
+            _ => false,
+        },
+    }
+}
+
+/// The delay before retrying to send a request after the given number of
+/// failed attempts.
+fn retry_delay(num_failed_attempts: u32) -> Duration {
+    let exponent = num_failed_attempts.saturating_sub(1).min(6);
+    Duration::from_secs(2u64.pow(exponent)).min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::retry_delay;
+
+    #[test]
+    fn retry_delay_is_capped() {
+        assert_eq!(retry_delay(1), Duration::from_secs(1));
+        assert_eq!(retry_delay(2), Duration::from_secs(2));
+        assert_eq!(retry_delay(5), Duration::from_secs(16));
+        assert_eq!(retry_delay(7), Duration::from_secs(60));
+        assert_eq!(retry_delay(100), Duration::from_secs(60));
+    }
+}
@@ -17,8 +17,17 @@ impl Client {
     /// Handle all the information provided in a sliding sync response
     #[instrument(skip(self, response))]
     pub async fn process_sliding_sync(&self, response: &v4::Response) -> Result<SyncResponse> {
+        #[cfg(feature = "e2e-encryption")]
+        let room_ids: Vec<_> = response.rooms.keys().cloned().collect();
+        #[cfg(feature = "e2e-encryption")]
+        let inviters = self.room_key_history_inviters(&room_ids).await;
+
         let response = self.base_client().process_sliding_sync(response).await?;
         debug!("done processing on base_client");
+
+        #[cfg(feature = "e2e-encryption")]
+        self.handle_room_key_history(&room_ids, inviters).await;
+
         self.handle_sync_response(&response).await?;
 
         Ok(response)
@@ -2,8 +2,7 @@ use ruma::{OwnedRoomId, RoomId};
 use serde::{Deserialize, Serialize};
 
 /// Represent a room entry in the [`SlidingSyncList`][super::SlidingSyncList].
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-#[cfg_attr(any(test, feature = "testing"), derive(PartialEq))]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RoomListEntry {
     /// This entry isn't known at this point and thus considered `Empty`.
     #[default]
@@ -826,7 +826,9 @@ mod tests {
     use assert_matches::assert_matches;
     use futures_util::{pin_mut, StreamExt};
     use matrix_sdk_test::async_test;
-    use ruma::{api::client::sync::sync_events::v4::ToDeviceConfig, room_id, TransactionId};
+    use ruma::{
+        api::client::sync::sync_events::v4::ToDeviceConfig, event_id, room_id, TransactionId,
+    };
     use serde_json::json;
     use wiremock::{http::Method, Match, Mock, MockServer, Request, ResponseTemplate};
 
@@ -1303,6 +1305,52 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    async fn test_sliding_sync_feeds_event_cache() -> Result<()> {
+        let (server, sliding_sync) = new_sliding_sync(vec![SlidingSyncList::builder("foo")
+            .sync_mode(SlidingSyncMode::new_selective().add_range(0..=10))])
+        .await?;
+
+        let stream = sliding_sync.sync();
+        pin_mut!(stream);
+
+        let room_id = room_id!("!r0:bar.org");
+
+        {
+            let _mock_guard = Mock::given(SlidingSyncMatcher)
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "pos": "1",
+                    "lists": {},
+                    "rooms": {
+                        room_id: {
+                            "name": "Room #0",
+                            "initial": true,
+                            "limited": true,
+                            "prev_batch": "t0",
+                            "timeline": [{
+                                "content": { "body": "hi", "msgtype": "m.text" },
+                                "event_id": "$a:bar.org",
+                                "origin_server_ts": 0,
+                                "sender": "@alice:bar.org",
+                                "type": "m.room.message",
+                            }],
+                        },
+                    }
+                })))
+                .mount_as_scoped(&server)
+                .await;
+
+            let _ = stream.next().await.unwrap()?;
+        }
+
+        let room = sliding_sync.inner.client.get_room(room_id).unwrap();
+        let (events, _cursor) = room.event_cache().latest_events().await?.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_id().as_deref(), Some(event_id!("$a:bar.org")));
+
+        Ok(())
+    }
+
     #[async_test]
     async fn test_stop_sync_loop() -> Result<()> {
         let (_server, sliding_sync) = new_sliding_sync(vec![SlidingSyncList::builder("foo")
@@ -23,7 +23,7 @@ use std::{
 use eyeball::Observable;
 pub use matrix_sdk_base::sync::*;
 use matrix_sdk_base::{
-    debug::{DebugInvitedRoom, DebugListOfRawEventsNoId, DebugNotificationMap},
+    debug::{DebugInvitedRoom, DebugKnockedRoom, DebugListOfRawEventsNoId, DebugNotificationMap},
     deserialized_responses::AmbiguityChanges,
     instant::Instant,
     sync::SyncResponse as BaseSyncResponse,
@@ -31,7 +31,11 @@ use matrix_sdk_base::{
 use ruma::{
     api::client::{
         push::get_notifications::v3::Notification,
-        sync::sync_events::{self, v3::InvitedRoom, DeviceLists},
+        sync::sync_events::{
+            self,
+            v3::{InvitedRoom, KnockedRoom},
+            DeviceLists,
+        },
     },
     events::{presence::PresenceEvent, AnyGlobalAccountDataEvent, AnyToDeviceEvent},
     serde::Raw,
@@ -134,6 +138,13 @@ pub enum RoomUpdate {
         /// Updates to the room.
         updates: InvitedRoom,
     },
+    /// Updates to a room the user knocked on.
+    Knocked {
+        /// Room object with general information on the room.
+        room: room::Knocked,
+        /// Updates to the room.
+        updates: KnockedRoom,
+    },
 }
 
 impl fmt::Debug for RoomUpdate {
@@ -150,6 +161,11 @@ impl fmt::Debug for RoomUpdate {
                 .field("room", room)
                 .field("updates", &DebugInvitedRoom(updates))
                 .finish(),
+            Self::Knocked { room, updates } => f
+                .debug_struct("Knocked")
+                .field("room", room)
+                .field("updates", &DebugKnockedRoom(updates))
+                .finish(),
         }
     }
 }
@@ -161,7 +177,17 @@ impl Client {
         &self,
         response: sync_events::v3::Response,
     ) -> Result<BaseSyncResponse> {
+        #[cfg(feature = "e2e-encryption")]
+        let room_ids: Vec<OwnedRoomId> =
+            response.rooms.join.keys().chain(response.rooms.leave.keys()).cloned().collect();
+        #[cfg(feature = "e2e-encryption")]
+        let inviters = self.room_key_history_inviters(&room_ids).await;
+
         let response = Box::pin(self.base_client().receive_sync_response(response)).await?;
+
+        #[cfg(feature = "e2e-encryption")]
+        self.handle_room_key_history(&room_ids, inviters).await;
+
         self.handle_sync_response(&response).await?;
         Ok(response)
     }
@@ -194,6 +220,10 @@ impl Client {
                 continue;
             };
 
+            if let Err(e) = room.event_cache().handle_sync_timeline(&room_info.timeline).await {
+                error!(?room_id, "Failed to update the event cache: {e}");
+            }
+
             self.send_room_update(room_id, || RoomUpdate::Joined {
                 room: room.clone(),
                 updates: room_info.clone(),
@@ -222,6 +252,10 @@ impl Client {
                 continue;
             };
 
+            if let Err(e) = room.event_cache().handle_sync_timeline(&room_info.timeline).await {
+                error!(?room_id, "Failed to update the event cache: {e}");
+            }
+
             self.send_room_update(room_id, || RoomUpdate::Left {
                 room: room.clone(),
                 updates: room_info.clone(),
@@ -254,6 +288,23 @@ impl Client {
             self.handle_sync_events(HandlerKind::StrippedState, room, invite_state).await?;
         }
 
+        for (room_id, room_info) in &rooms.knock {
+            let Some(room) = self.get_knocked_room(room_id) else {
+                error!(?room_id, "Can't call event handler, room not found");
+                continue;
+            };
+
+            self.send_room_update(room_id, || RoomUpdate::Knocked {
+                room: room.clone(),
+                updates: room_info.clone(),
+            });
+
+            let knocked = room::Room::Knocked(room);
+            let room = Some(&knocked);
+            let knock_state = &room_info.knock_state.events;
+            self.handle_sync_events(HandlerKind::StrippedState, room, knock_state).await?;
+        }
+
         debug!("Ran event handlers in {:?}", now.elapsed());
 
         let now = Instant::now();
@@ -34,17 +34,17 @@ pub async fn run<T: Client>(client: T, mut widget: widget::Widget) -> Result<()>
     let (outgoing_req_tx, outgoing_req_rx) = unbounded_channel();
     tokio::spawn(forward(outgoing_req_rx, widget.comm.sink()));
 
-    // Create a message handler (handles all incoming requests and generates outgoing requests).
-    let handler = {
-        let widget = WidgetSink::new(widget.info, outgoing_req_tx, state.clone());
-        MessageHandler::new(client, widget).await?
-    };
-
-    // Spawn a task that receives requests from a widget, and passes them
-    // to the handler, waits for response from a handler and sends it back.
+    // Spawn a task that creates a message handler (handles all incoming requests and generates
+    // outgoing requests), then receives requests from a widget, and passes them to the handler,
+    // waits for response from a handler and sends it back. The handler may negotiate the
+    // capabilities when it's created, so the widget's responses must already be processed by then.
     let (req_tx, req_rx) = unbounded_channel();
     let (req_reply_tx, req_reply_rx) = unbounded_channel();
-    tokio::spawn(process_requests(req_rx, req_reply_tx, handler));
+    let sink = WidgetSink::new(widget.info.clone(), outgoing_req_tx, state.clone());
+    tokio::spawn(async move {
+        let handler = MessageHandler::new(client, sink).await?;
+        process_requests(req_rx, req_reply_tx, handler).await
+    });
     tokio::spawn(forward(req_reply_rx, widget.comm.sink()));
 
     // Spawn a task that receives responses from a widget (for our outgoing requests),
@@ -82,7 +82,18 @@ async fn process_requests<C: Client, W: HandlerSink>(
     responses: Sender<FromWidgetAction>,
     mut handler: MessageHandler<C, W>,
 ) -> Result<()> {
-    while let Some(raw) = requests.recv().await {
+    loop {
+        let raw = tokio::select! {
+            raw = requests.recv() => match raw {
+                Some(raw) => raw,
+                None => break,
+            },
+            () = handler.capabilities_revoked() => {
+                handler.revoke_capabilities().await?;
+                continue;
+            }
+        };
+
         let response = handle_incoming! {
             chain = { raw(msg) -> handler -> resp },
 
@@ -98,11 +109,17 @@ async fn process_requests<C: Client, W: HandlerSink>(
             SendEvent (msg.request.clone())
                 -> SendEvent (resp),
 
-            ReadEvent (msg.request.clone().into())
+            ReadEvent (msg.request.clone())
                 -> ReadEvents (resp),
 
-            ReadRelations (msg.request.clone().into())
-                -> ReadEvents (resp),
+            ReadRelations (msg.request.clone())
+                -> ReadRelations (resp),
+
+            SendToDevice (msg.request.clone())
+                -> SendToDevice (resp),
+
+            RequestCapabilities (msg.request.clone())
+                -> RequestCapabilities (resp),
         };
 
         responses.send(response).map_err(|_| Error::WidgetDied)?;
@@ -136,6 +153,7 @@ async fn forward(mut receiver: Receiver<impl Into<WidgetMessage>>, sink: Sender<
     }
 }
 
+#[derive(Clone)]
 struct WidgetSink {
     info: widget::Info,
     sink: Sender<ToWidgetAction>,
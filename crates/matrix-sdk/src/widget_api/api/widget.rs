@@ -6,9 +6,11 @@ pub struct Widget {
     pub comm: Comm,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Info {
     pub id: String,
+    /// The origin of the URL of the widget, the capabilities granted to a widget are tied to it.
+    pub origin: String,
     pub init_on_load: bool,
 }
 
@@ -10,11 +10,14 @@ use super::{
 #[async_trait]
 pub trait Client: Send + Sync + 'static {
     async fn initialise(&mut self, req: CapabilitiesReq) -> Result<Capabilities>;
+    /// Resolves once the capabilities granted to the widget were revoked, the ones returned by
+    /// `initialise` must not be used anymore.
+    async fn capabilities_revoked(&mut self);
     async fn get_openid(&self, req: openid::Request) -> OpenIDState;
 }
 
 #[async_trait]
-pub trait Widget: Send + Sync + 'static {
+pub trait Widget: Clone + Send + Sync + 'static {
     async fn send<T: OutgoingMessage>(&self, message: T) -> Result<T::Response>;
     fn init_on_load(&self) -> bool;
 }
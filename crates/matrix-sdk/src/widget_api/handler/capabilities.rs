@@ -1,11 +1,17 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 use tokio::sync::mpsc::UnboundedReceiver;
 
 use crate::widget_api::{
     messages::{
-        capabilities::{Filter, Options},
-        from_widget::{ReadEventRequest, ReadEventResponse, SendEventRequest, SendEventResponse},
-        MatrixEvent,
+        capabilities::{Filter, Options, ToDeviceFilter},
+        from_widget::{
+            ReadEventRequest, ReadEventResponse, ReadRelationsRequest, ReadRelationsResponse,
+            SendEventRequest, SendEventResponse, SendToDeviceRequest,
+        },
+        to_widget::TurnServers,
+        MatrixEvent, ToDeviceEvent,
     },
     Result,
 };
@@ -17,11 +23,24 @@ pub struct Capabilities {
     pub listener: Option<UnboundedReceiver<MatrixEvent>>,
     pub reader: Option<Box<dyn EventReader>>,
     pub sender: Option<Box<dyn EventSender>>,
+    pub to_device_listener: Option<ToDeviceListener>,
+    pub to_device_sender: Option<Box<dyn ToDeviceSender>>,
+    pub turn_servers: Option<Box<dyn TurnServerProvider>>,
+}
+
+/// Incoming to-device events that passed the filters approved for the widget.
+#[allow(missing_debug_implementations)]
+pub struct ToDeviceListener {
+    pub filters: Vec<ToDeviceFilter>,
+    pub events: UnboundedReceiver<ToDeviceEvent>,
 }
 
 #[async_trait]
 pub trait EventReader: Filtered + Send {
     async fn read(&self, req: ReadEventRequest) -> Result<ReadEventResponse>;
+    async fn read_relations(&self, req: ReadRelationsRequest) -> Result<ReadRelationsResponse>;
+    /// The current room state that the widget is allowed to read.
+    async fn read_current_state(&self) -> Result<Vec<MatrixEvent>>;
 }
 
 #[async_trait]
@@ -29,6 +48,18 @@ pub trait EventSender: Filtered + Send {
     async fn send(&self, req: SendEventRequest) -> Result<SendEventResponse>;
 }
 
+#[async_trait]
+pub trait ToDeviceSender: Send {
+    fn filters(&self) -> &[ToDeviceFilter];
+    async fn send(&self, req: SendToDeviceRequest) -> Result<()>;
+}
+
+#[async_trait]
+pub trait TurnServerProvider: Send + Sync {
+    /// The TURN servers the widget can use, and for how long their credentials stay valid.
+    async fn turn_servers(&self) -> Result<(TurnServers, Duration)>;
+}
+
 pub trait Filtered {
     fn filters(&self) -> &[Filter];
 }
@@ -38,6 +69,17 @@ impl<'t> From<&'t Capabilities> for Options {
         Self {
             send_filter: c.sender.as_ref().map(|e| e.filters().to_owned()).unwrap_or_default(),
             read_filter: c.reader.as_ref().map(|e| e.filters().to_owned()).unwrap_or_default(),
+            send_to_device_filter: c
+                .to_device_sender
+                .as_ref()
+                .map(|e| e.filters().to_owned())
+                .unwrap_or_default(),
+            read_to_device_filter: c
+                .to_device_listener
+                .as_ref()
+                .map(|l| l.filters.clone())
+                .unwrap_or_default(),
+            turn_servers: c.turn_servers.is_some(),
             ..Options::default()
         }
     }
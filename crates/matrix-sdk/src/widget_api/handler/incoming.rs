@@ -1,6 +1,9 @@
 use super::{
     super::messages::{
-        from_widget::{ReadEventRequest, ReadEventResponse, SendEventRequest, SendEventResponse},
+        from_widget::{
+            ReadEventRequest, ReadEventResponse, ReadRelationsRequest, ReadRelationsResponse,
+            RequestCapabilitiesRequest, SendEventRequest, SendEventResponse, SendToDeviceRequest,
+        },
         openid, SupportedVersions,
     },
     Request,
@@ -12,5 +15,8 @@ pub enum Message {
     ContentLoaded(Request<(), ()>),
     GetOpenID(Request<openid::Request, openid::State>),
     ReadEvents(Request<ReadEventRequest, ReadEventResponse>),
+    ReadRelations(Request<ReadRelationsRequest, ReadRelationsResponse>),
     SendEvent(Request<SendEventRequest, SendEventResponse>),
+    SendToDevice(Request<SendToDeviceRequest, ()>),
+    RequestCapabilities(Request<RequestCapabilitiesRequest, ()>),
 }
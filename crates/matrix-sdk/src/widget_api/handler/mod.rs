@@ -6,32 +6,56 @@ mod request;
 
 pub use self::{
     api::{Client, OpenIDState, Widget},
-    capabilities::{Capabilities, EventReader, EventSender, Filtered},
+    capabilities::{
+        Capabilities, EventReader, EventSender, Filtered, ToDeviceListener, ToDeviceSender,
+        TurnServerProvider,
+    },
     incoming::Message as Incoming,
     outgoing::OutgoingMessage,
     request::Request,
 };
 
+use std::time::Duration;
+
+use futures_util::future::OptionFuture;
+use matrix_sdk_common::sleep::sleep;
+use tokio::{sync::mpsc::UnboundedReceiver, task::JoinHandle};
+use tracing::warn;
+
 use super::{
     messages::{
         capabilities::Options as CapabilitiesReq,
-        from_widget::{ReadEventRequest, ReadEventResponse, SendEventRequest, SendEventResponse},
+        from_widget::{
+            ReadEventRequest, ReadEventResponse, ReadRelationsRequest, ReadRelationsResponse,
+            SendEventRequest, SendEventResponse, SendToDeviceRequest,
+        },
         to_widget::CapabilitiesUpdatedRequest as CapabilitiesUpdated,
-        SupportedVersions, SUPPORTED_API_VERSIONS,
+        MatrixEvent, SupportedVersions, ToDeviceEvent, SUPPORTED_API_VERSIONS,
     },
     Error, Result,
 };
 
+/// How long to wait before fetching the TURN servers again when it failed.
+const TURN_SERVERS_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// The minimum delay between two refreshes of the TURN servers, in case the
+/// homeserver sends a very short TTL.
+const MIN_TURN_SERVERS_REFRESH_DELAY: Duration = Duration::from_secs(1);
+
 #[allow(missing_debug_implementations)]
 pub struct MessageHandler<C, W> {
     capabilities: Option<Capabilities>,
+    requested: Option<CapabilitiesReq>,
     client: C,
     widget: W,
+    /// The task keeping the TURN servers of the widget up to date.
+    turn_servers_task: Option<JoinHandle<()>>,
 }
 
 impl<C: Client, W: Widget> MessageHandler<C, W> {
     pub async fn new(client: C, widget: W) -> Result<Self> {
-        let mut handler = Self { client, widget, capabilities: None };
+        let mut handler =
+            Self { client, widget, capabilities: None, requested: None, turn_servers_task: None };
         if !handler.widget.init_on_load() {
             handler.initialise().await?;
         }
@@ -74,25 +98,95 @@ impl<C: Client, W: Widget> MessageHandler<C, W> {
                 r.reply(response)?;
             }
 
+            Incoming::ReadRelations(r) => {
+                let response = self.read_relations(&*r).await;
+                r.reply(response)?;
+            }
+
             Incoming::SendEvent(r) => {
                 let response = self.send_event(&*r).await;
                 r.reply(response)?;
             }
+
+            Incoming::SendToDevice(r) => {
+                let response = self.send_to_device(&*r).await;
+                r.reply(response)?;
+            }
+
+            Incoming::RequestCapabilities(r) => {
+                // The widget asks for more capabilities, on top of the ones it requested before.
+                let requested =
+                    self.requested.as_ref().map(|current| current.union(&r.capabilities));
+                r.reply(requested.as_ref().map(|_| ()).ok_or(Error::InvalidPermissions))?;
+                if let Some(requested) = requested {
+                    self.negotiate(requested).await?;
+                }
+            }
         }
 
         Ok(())
     }
 
+    /// Resolves once the capabilities granted to the widget were revoked.
+    pub async fn capabilities_revoked(&mut self) {
+        self.client.capabilities_revoked().await
+    }
+
+    /// Drops the capabilities of the widget and lets it know that none of the ones it requested
+    /// are approved anymore.
+    pub async fn revoke_capabilities(&mut self) -> Result<()> {
+        if self.capabilities.take().is_none() {
+            return Ok(());
+        }
+        if let Some(task) = self.turn_servers_task.take() {
+            task.abort();
+        }
+
+        let requested = self.requested.clone().unwrap_or_default();
+        let update = CapabilitiesUpdated { requested, approved: CapabilitiesReq::default() };
+        self.widget.send(outgoing::CapabilitiesUpdated(update)).await
+    }
+
     async fn initialise(&mut self) -> Result<()> {
         let requested = self.widget.send(outgoing::SendMeCapabilities).await?;
+        self.negotiate(requested).await
+    }
 
+    /// Gets the capabilities approved for the requested ones and lets the widget know about them.
+    async fn negotiate(&mut self, requested: CapabilitiesReq) -> Result<()> {
         let capabilities = self.client.initialise(requested.clone()).await?;
         self.capabilities = Some(capabilities);
+        self.requested = Some(requested.clone());
 
         let approved: CapabilitiesReq = self.capabilities.as_ref().unwrap().into();
         let update = CapabilitiesUpdated { requested, approved };
         self.widget.send(outgoing::CapabilitiesUpdated(update)).await?;
 
+        // Let the widget know about the current state before pushing the updates.
+        let fut = self.caps()?.reader.as_ref().map(|reader| reader.read_current_state());
+        if let Some(Ok(state)) = OptionFuture::from(fut).await {
+            if !state.is_empty() {
+                self.widget.send(outgoing::StateUpdated(state)).await?;
+            }
+        }
+
+        if let Some(listener) = self.caps()?.listener.take() {
+            tokio::spawn(forward_events(listener, self.widget.clone()));
+        }
+
+        if let Some(listener) = self.caps()?.to_device_listener.take() {
+            tokio::spawn(forward_to_device(listener.events, self.widget.clone()));
+        }
+
+        // The TURN servers of the previous negotiation are replaced by the new ones, if any.
+        if let Some(task) = self.turn_servers_task.take() {
+            task.abort();
+        }
+        if let Some(provider) = self.caps()?.turn_servers.take() {
+            let task = tokio::spawn(refresh_turn_servers(provider, self.widget.clone()));
+            self.turn_servers_task = Some(task);
+        }
+
         Ok(())
     }
 
@@ -101,12 +195,91 @@ impl<C: Client, W: Widget> MessageHandler<C, W> {
         fut.await
     }
 
+    async fn read_relations(
+        &mut self,
+        req: &ReadRelationsRequest,
+    ) -> Result<ReadRelationsResponse> {
+        let fut = self
+            .caps()?
+            .reader
+            .as_ref()
+            .ok_or(Error::InvalidPermissions)?
+            .read_relations(req.clone());
+        fut.await
+    }
+
     async fn send_event(&mut self, req: &SendEventRequest) -> Result<SendEventResponse> {
         let fut = self.caps()?.sender.as_ref().ok_or(Error::InvalidPermissions)?.send(req.clone());
         fut.await
     }
 
+    async fn send_to_device(&mut self, req: &SendToDeviceRequest) -> Result<()> {
+        let fut = self
+            .caps()?
+            .to_device_sender
+            .as_ref()
+            .ok_or(Error::InvalidPermissions)?
+            .send(req.clone());
+        fut.await
+    }
+
     fn caps(&mut self) -> Result<&mut Capabilities> {
         self.capabilities.as_mut().ok_or(Error::InvalidPermissions)
     }
 }
+
+impl<C, W> Drop for MessageHandler<C, W> {
+    fn drop(&mut self) {
+        if let Some(task) = self.turn_servers_task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Passes the room events that the widget is allowed to receive to the widget, state events are
+/// pushed as state updates.
+async fn forward_events<W: Widget>(mut events: UnboundedReceiver<MatrixEvent>, widget: W) {
+    while let Some(event) = events.recv().await {
+        let result = if event.state_key.is_some() {
+            widget.send(outgoing::StateUpdated(vec![event])).await
+        } else {
+            widget.send(outgoing::EventReceived(event)).await
+        };
+
+        if let Err(Error::WidgetDied) = result {
+            break;
+        }
+    }
+}
+
+/// Passes the to-device events that the widget is allowed to receive to the widget.
+async fn forward_to_device<W: Widget>(mut events: UnboundedReceiver<ToDeviceEvent>, widget: W) {
+    while let Some(event) = events.recv().await {
+        if let Err(Error::WidgetDied) = widget.send(outgoing::ToDeviceReceived(event)).await {
+            break;
+        }
+    }
+}
+
+/// Pushes the TURN servers to the widget, and pushes them again before their credentials expire.
+async fn refresh_turn_servers<W: Widget>(provider: Box<dyn TurnServerProvider>, widget: W) {
+    loop {
+        let delay = match provider.turn_servers().await {
+            Ok((servers, ttl)) => {
+                let result = widget.send(outgoing::TurnServersUpdated(servers)).await;
+                if let Err(Error::WidgetDied) = result {
+                    break;
+                }
+
+                // Leave some time to the widget to switch to the new credentials.
+                (ttl - ttl / 10).max(MIN_TURN_SERVERS_REFRESH_DELAY)
+            }
+            Err(error) => {
+                warn!("Couldn't get the TURN servers for the widget, retrying later: {error}");
+                TURN_SERVERS_RETRY_DELAY
+            }
+        };
+
+        sleep(delay).await;
+    }
+}
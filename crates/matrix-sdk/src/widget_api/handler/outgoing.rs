@@ -1,8 +1,8 @@
 use crate::widget_api::messages::{
     capabilities::Options,
     openid::State as OpenIDState,
-    to_widget::{CapabilitiesUpdatedRequest, ToWidgetMessage},
-    Header, MessageBody,
+    to_widget::{CapabilitiesUpdatedRequest, ToWidgetMessage, TurnServers, UpdateStateRequest},
+    Header, MatrixEvent, MessageBody, ToDeviceEvent,
 };
 
 use super::{Error, Result};
@@ -61,3 +61,68 @@ impl OutgoingMessage for OpenIDUpdated {
         }
     }
 }
+
+pub struct EventReceived(pub MatrixEvent);
+impl OutgoingMessage for EventReceived {
+    type Response = ();
+
+    fn into_message(self, header: Header) -> ToWidgetMessage {
+        ToWidgetMessage::SendEvent(MessageBody::request(header, self.0))
+    }
+
+    fn extract_response(msg: ToWidgetMessage) -> Result<Self::Response> {
+        match msg {
+            ToWidgetMessage::SendEvent(body) => Ok(body.response()?),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+}
+
+pub struct StateUpdated(pub Vec<MatrixEvent>);
+impl OutgoingMessage for StateUpdated {
+    type Response = ();
+
+    fn into_message(self, header: Header) -> ToWidgetMessage {
+        let request = UpdateStateRequest { state: self.0 };
+        ToWidgetMessage::UpdateState(MessageBody::request(header, request))
+    }
+
+    fn extract_response(msg: ToWidgetMessage) -> Result<Self::Response> {
+        match msg {
+            ToWidgetMessage::UpdateState(body) => Ok(body.response()?),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+}
+
+pub struct ToDeviceReceived(pub ToDeviceEvent);
+impl OutgoingMessage for ToDeviceReceived {
+    type Response = ();
+
+    fn into_message(self, header: Header) -> ToWidgetMessage {
+        ToWidgetMessage::SendToDevice(MessageBody::request(header, self.0))
+    }
+
+    fn extract_response(msg: ToWidgetMessage) -> Result<Self::Response> {
+        match msg {
+            ToWidgetMessage::SendToDevice(body) => Ok(body.response()?),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+}
+
+pub struct TurnServersUpdated(pub TurnServers);
+impl OutgoingMessage for TurnServersUpdated {
+    type Response = ();
+
+    fn into_message(self, header: Header) -> ToWidgetMessage {
+        ToWidgetMessage::UpdateTurnServers(MessageBody::request(header, self.0))
+    }
+
+    fn extract_response(msg: ToWidgetMessage) -> Result<Self::Response> {
+        match msg {
+            ToWidgetMessage::UpdateTurnServers(body) => Ok(body.response()?),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+}
@@ -1,24 +1,53 @@
-use std::result::Result as StdResult;
+use std::{collections::BTreeSet, time::Duration};
 
 use async_trait::async_trait;
+use matrix_sdk_base::deserialized_responses::RawAnySyncOrStrippedState;
 use ruma::{
-    api::client::{
-        account::request_openid_token::v3::Request as OpenIDRequest, filter::RoomEventFilter,
+    api::{
+        client::{
+            account::request_openid_token::v3::Request as OpenIDRequest,
+            filter::RoomEventFilter,
+            relations::{
+                get_relating_events, get_relating_events_with_rel_type,
+                get_relating_events_with_rel_type_and_event_type,
+            },
+            voip::get_turn_server_info::v3::Request as TurnServerRequest,
+        },
+        Direction,
     },
-    events::AnySyncTimelineEvent,
+    assign,
+    events::{AnySyncStateEvent, AnySyncTimelineEvent, AnyToDeviceEvent, StateEventType},
     serde::Raw,
+    EventId, RoomId,
 };
-use tokio::sync::mpsc;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use tracing::warn;
+
+mod permissions;
+mod to_device;
 
+pub use self::permissions::{granted_capabilities, revoke_capabilities, CapabilitiesRequest};
+use self::{
+    permissions::{capabilities_key, save_granted_capabilities},
+    to_device::{IncomingToDeviceEvent, ToDeviceProxy},
+};
 use super::{
+    api::widget::Info,
     handler::{
-        Capabilities, Client, Filtered as Handler, EventReader as Reader,
-        EventSender as Sender, OpenIDState,
+        Capabilities, Client, EventReader as Reader, EventSender as Sender, Filtered as Handler,
+        OpenIDState, ToDeviceListener, ToDeviceSender, TurnServerProvider,
     },
     messages::{
-        capabilities::{EventFilter, Filter, FilterInput, Options},
-        from_widget::{ReadEventRequest, ReadEventResponse, SendEventRequest, SendEventResponse},
-        {openid, MatrixEvent},
+        capabilities::{EventFilter, Filter, FilterInput, Options, ToDeviceFilter},
+        from_widget::{
+            ReadEventRequest, ReadEventResponse, ReadRelationsRequest, ReadRelationsResponse,
+            SendEventRequest, SendEventResponse, StateKeySelector,
+        },
+        to_widget::TurnServers,
+        {openid, MatrixEvent, ReadRelationsDirection, ToDeviceEvent},
     },
     {Error, Result},
 };
@@ -29,28 +58,65 @@ use crate::{
 
 #[async_trait]
 pub trait PermissionProvider: Send + Sync + 'static {
-    async fn acquire_permissions(&self, cap: Options) -> Result<Options>;
+    /// Asks the user which of the requested capabilities the widget is granted. Not called when
+    /// all of them were granted to the widget in this room before.
+    async fn acquire_permissions(&self, request: CapabilitiesRequest) -> Result<Options>;
 }
 
 #[derive(Debug)]
 pub struct Driver<W> {
     room: Joined,
+    info: Info,
     widget: W,
-    event_handler_handle: Option<EventHandlerDropGuard>,
+    event_handler_handles: Vec<EventHandlerDropGuard>,
+    /// The keys of the capabilities revoked with `revoke_capabilities`.
+    revocations: broadcast::Receiver<Vec<u8>>,
 }
 
 impl<W> Driver<W> {
-    pub fn new(room: Joined, widget: W) -> Self {
-        Self { room, widget, event_handler_handle: None }
+    pub fn new(room: Joined, info: Info, widget: W) -> Self {
+        let revocations = room.client.inner.revoked_widget_capabilities.subscribe();
+        Self { room, info, widget, event_handler_handles: Vec::new(), revocations }
     }
 }
 
 #[async_trait]
 impl<W: PermissionProvider> Client for Driver<W> {
     async fn initialise(&mut self, options: Options) -> Result<Capabilities> {
-        let options = self.widget.acquire_permissions(options).await?;
+        let granted =
+            granted_capabilities(&self.room, &self.info).await.map_err(|_| Error::Other)?;
+        let request = CapabilitiesRequest::new(
+            self.info.id.clone(),
+            self.room.room_id().to_owned(),
+            options,
+            granted,
+        );
+
+        let options = if request.is_granted() {
+            request.requested().clone()
+        } else {
+            let options = self.widget.acquire_permissions(request).await?;
+            save_granted_capabilities(&self.room, &self.info, &options)
+                .await
+                .map_err(|_| Error::Other)?;
+            options
+        };
+
+        // The capabilities may be renegotiated, stop listening with the previous ones.
+        self.event_handler_handles.clear();
+
+        let to_device_listener = (!options.read_to_device_filter.is_empty())
+            .then(|| self.setup_to_device_listener(options.read_to_device_filter.clone()));
 
         Ok(Capabilities {
+            to_device_listener,
+            to_device_sender: (!options.send_to_device_filter.is_empty()).then(|| {
+                let filters = options.send_to_device_filter.clone();
+                Box::new(ToDeviceProxy::new(self.room.clone(), filters)) as Box<dyn ToDeviceSender>
+            }),
+            turn_servers: options.turn_servers.then(|| {
+                Box::new(TurnServerProxy::new(self.room.clone())) as Box<dyn TurnServerProvider>
+            }),
             listener: Filters::new(options.read_filter.clone())
                 .map(|filters| self.setup_event_listener(filters)),
             reader: Filters::new(options.read_filter).map(|filters| {
@@ -62,6 +128,28 @@ impl<W: PermissionProvider> Client for Driver<W> {
         })
     }
 
+    async fn capabilities_revoked(&mut self) {
+        let key = capabilities_key(self.room.room_id(), &self.info);
+        loop {
+            match self.revocations.recv().await {
+                Ok(revoked) if revoked == key => break,
+                Ok(_) => {}
+                // We may have missed the revocation, check whether the capabilities are still
+                // granted.
+                Err(RecvError::Lagged(_)) => {
+                    if let Ok(None) = granted_capabilities(&self.room, &self.info).await {
+                        break;
+                    }
+                }
+                // The client holds the sender, it can't be dropped while we hold the room.
+                Err(RecvError::Closed) => std::future::pending().await,
+            }
+        }
+
+        // Stop forwarding the events to the widget.
+        self.event_handler_handles.clear();
+    }
+
     async fn get_openid(&self, req: openid::Request) -> OpenIDState {
         let user_id = self.room.own_user_id();
 
@@ -81,18 +169,82 @@ impl<W: PermissionProvider> Client for Driver<W> {
 impl<W> Driver<W> {
     fn setup_event_listener(&mut self, filter: Filters) -> mpsc::UnboundedReceiver<MatrixEvent> {
         let (tx, rx) = mpsc::unbounded_channel();
-        let callback = move |ev: Raw<AnySyncTimelineEvent>| {
-            let (filter, tx) = (filter.clone(), tx.clone());
-            if let Ok(msg) = ev.deserialize_as::<MatrixEvent>() {
+        let room_id = self.room.room_id().to_owned();
+
+        // State events are received by the state event handler below, no matter if they are part
+        // of the timeline or of the state of the sync response.
+        let timeline_callback = {
+            let (filter, tx, room_id) = (filter.clone(), tx.clone(), room_id.clone());
+            move |ev: Raw<AnySyncTimelineEvent>| {
+                if let Some(msg) = matrix_event(&ev, &room_id) {
+                    (msg.state_key.is_none() && filter.allow(&msg)).then(|| tx.send(msg));
+                }
+                async {}
+            }
+        };
+        let state_callback = move |ev: Raw<AnySyncStateEvent>| {
+            if let Some(msg) = matrix_event(&ev, &room_id) {
                 filter.allow(&msg).then(|| tx.send(msg));
             }
             async {}
         };
 
-        let handle = self.room.add_event_handler(callback);
+        let handles = [
+            self.room.add_event_handler(timeline_callback),
+            self.room.add_event_handler(state_callback),
+        ];
+        for handle in handles {
+            let drop_guard = self.room.client().event_handler_drop_guard(handle);
+            self.event_handler_handles.push(drop_guard);
+        }
+
+        rx
+    }
+
+    fn setup_to_device_listener(&mut self, filters: Vec<ToDeviceFilter>) -> ToDeviceListener {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let allowed = filters.clone();
+        let callback = move |ev: Raw<AnyToDeviceEvent>| {
+            if let Ok(ev) = ev.deserialize_as::<IncomingToDeviceEvent>() {
+                let ev: ToDeviceEvent = ev.into();
+                allowed.iter().any(|f| f.allow(&ev.event_type)).then(|| tx.send(ev));
+            }
+            async {}
+        };
+
+        let handle = self.room.client().add_event_handler(callback);
         let drop_guard = self.room.client().event_handler_drop_guard(handle);
-        self.event_handler_handle.replace(drop_guard);
-        return rx;
+        self.event_handler_handles.push(drop_guard);
+        ToDeviceListener { filters, events: rx }
+    }
+}
+
+#[derive(Debug)]
+pub struct TurnServerProxy {
+    room: Joined,
+}
+
+impl TurnServerProxy {
+    fn new(room: Joined) -> Self {
+        Self { room }
+    }
+}
+
+#[async_trait]
+impl TurnServerProvider for TurnServerProxy {
+    async fn turn_servers(&self) -> Result<(TurnServers, Duration)> {
+        let request = TurnServerRequest::new();
+        let response = self.room.client.send(request, None).await.map_err(|error| {
+            warn!("Failed to fetch the TURN servers: {error}");
+            Error::Other
+        })?;
+
+        let servers = TurnServers {
+            uris: response.uris,
+            username: response.username,
+            password: response.password,
+        };
+        Ok((servers, response.ttl))
     }
 }
 
@@ -108,37 +260,141 @@ impl EventServerProxy {
     }
 }
 
-#[async_trait]
-impl Reader for EventServerProxy {
-    async fn read(&self, req: ReadEventRequest) -> Result<ReadEventResponse> {
-        let options = {
-            let mut o = MessagesOptions::backward();
-            o.limit = req.limit.into();
-            o.filter = {
-                let mut f = RoomEventFilter::default();
-                f.types = Some(vec![req.message_type]);
-                f
-            };
-            o
-        };
+impl EventServerProxy {
+    /// Reads the state events from the local state store, we don't need to ask the homeserver.
+    async fn read_state(
+        &self,
+        event_type: &str,
+        state_key: &StateKeySelector,
+    ) -> Result<Vec<MatrixEvent>> {
+        let event_type = StateEventType::from(event_type);
+        let events = match state_key {
+            StateKeySelector::Any => self.room.get_state_events(event_type).await,
+            StateKeySelector::Key(key) => {
+                self.room.get_state_event(event_type, key).await.map(|e| e.into_iter().collect())
+            }
+        }
+        .map_err(|_| Error::Other)?;
+
+        Ok(events
+            .into_iter()
+            .filter_map(|event| match event {
+                RawAnySyncOrStrippedState::Sync(raw) => matrix_event(&raw, self.room.room_id()),
+                RawAnySyncOrStrippedState::Stripped(_) => None,
+            })
+            .collect())
+    }
+
+    /// Reads the latest timeline events of the given type from the homeserver.
+    async fn read_timeline(&self, event_type: String, limit: u32) -> Result<Vec<MatrixEvent>> {
+        let options = assign!(MessagesOptions::backward(), {
+            limit: limit.into(),
+            filter: assign!(RoomEventFilter::default(), { types: Some(vec![event_type]) }),
+        });
 
         let messages = self.room.messages(options).await.map_err(|_| Error::Other)?;
 
-        // Iterator over deserialized state messages.
-        let state = messages.state.into_iter().map(|s| s.deserialize_as());
+        Ok(messages
+            .chunk
+            .into_iter()
+            .filter_map(|m| matrix_event(&m.event, self.room.room_id()))
+            .filter(|m| m.state_key.is_none())
+            .collect())
+    }
+}
 
-        // Iterator over deserialized timeline messages.
-        let timeline = messages.chunk.into_iter().map(|m| m.event.deserialize_as());
+#[async_trait]
+impl Reader for EventServerProxy {
+    async fn read(&self, req: ReadEventRequest) -> Result<ReadEventResponse> {
+        let events = match &req.state_key {
+            Some(state_key) => self.read_state(&req.message_type, state_key).await?,
+            None => self.read_timeline(req.message_type, req.limit).await?,
+        };
 
-        // Chain two iterators together and run them through the filter.
         Ok(ReadEventResponse {
-            events: state
-                .chain(timeline)
-                .filter_map(StdResult::ok)
+            events: events
+                .into_iter()
                 .filter(|m| self.filter.allow(m))
+                .take(req.limit as usize)
                 .collect(),
         })
     }
+
+    async fn read_relations(&self, req: ReadRelationsRequest) -> Result<ReadRelationsResponse> {
+        let room_id = self.room.room_id().to_owned();
+        // Widgets can only read the relations of the room they live in.
+        if req.room_id.as_ref().is_some_and(|r| r != room_id.as_str()) {
+            return Err(Error::InvalidPermissions);
+        }
+
+        let event_id = EventId::parse(&req.event_id).map_err(|_| Error::InvalidJSON)?;
+        let dir = match req.direction {
+            Some(ReadRelationsDirection::Forwards) => Direction::Forward,
+            Some(ReadRelationsDirection::Backwards) | None => Direction::Backward,
+        };
+        let (from, to, limit) = (req.from, req.to, req.limit.map(Into::into));
+
+        let client = &self.room.client;
+        let (chunk, next_batch, prev_batch) = match (req.rel_type, &req.event_type) {
+            (Some(rel_type), Some(event_type)) => {
+                let request = assign!(
+                    get_relating_events_with_rel_type_and_event_type::v1::Request::new(
+                        room_id,
+                        event_id,
+                        rel_type.into(),
+                        event_type.as_str().into(),
+                    ),
+                    { from, to, limit, dir }
+                );
+                let response = client.send(request, None).await.map_err(|_| Error::Other)?;
+                (response.chunk, response.next_batch, response.prev_batch)
+            }
+            (Some(rel_type), None) => {
+                let request = assign!(
+                    get_relating_events_with_rel_type::v1::Request::new(
+                        room_id,
+                        event_id,
+                        rel_type.into(),
+                    ),
+                    { from, to, limit, dir }
+                );
+                let response = client.send(request, None).await.map_err(|_| Error::Other)?;
+                (response.chunk, response.next_batch, response.prev_batch)
+            }
+            (None, _) => {
+                let request = assign!(
+                    get_relating_events::v1::Request::new(room_id, event_id),
+                    { from, to, limit, dir }
+                );
+                let response = client.send(request, None).await.map_err(|_| Error::Other)?;
+                (response.chunk, response.next_batch, response.prev_batch)
+            }
+        };
+
+        // The homeserver can only filter by event type together with a relation type.
+        let chunk = chunk
+            .into_iter()
+            .filter_map(|raw| matrix_event(&raw, self.room.room_id()))
+            .filter(|m| req.event_type.as_ref().map_or(true, |t| *t == m.event_type))
+            .filter(|m| self.filter.allow(m))
+            .collect();
+
+        Ok(ReadRelationsResponse { chunk, next_batch, prev_batch })
+    }
+
+    async fn read_current_state(&self) -> Result<Vec<MatrixEvent>> {
+        // We can't list the state of every event type from the store, so the widget only gets
+        // the initial state of the event types it explicitly asked for.
+        let event_types: BTreeSet<_> =
+            self.filter.filters.iter().filter_map(Filter::state_event_type).collect();
+
+        let mut state = Vec::new();
+        for event_type in event_types {
+            state.extend(self.read_state(event_type, &StateKeySelector::Any).await?);
+        }
+
+        Ok(state.into_iter().filter(|m| self.filter.allow(m)).collect())
+    }
 }
 
 #[async_trait]
@@ -181,6 +437,14 @@ impl Handler for EventServerProxy {
     }
 }
 
+/// Deserializes an event for the widget, the events we get from the sync and the state store
+/// don't contain the ID of the room they belong to.
+fn matrix_event<T>(raw: &Raw<T>, room_id: &RoomId) -> Option<MatrixEvent> {
+    let mut event = raw.deserialize_as::<MatrixEvent>().ok()?;
+    event.room_id = room_id.to_string();
+    Some(event)
+}
+
 #[derive(Debug, Clone)]
 struct Filters {
     filters: Vec<Filter>,
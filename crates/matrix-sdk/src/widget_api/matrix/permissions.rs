@@ -0,0 +1,114 @@
+use ruma::{OwnedRoomId, RoomId};
+
+use super::super::{api::widget::Info, messages::capabilities::Options};
+use crate::{room::Joined, Result};
+
+/// A request of a widget for capabilities, that the user is prompted for.
+#[derive(Debug, Clone)]
+pub struct CapabilitiesRequest {
+    widget_id: String,
+    room_id: OwnedRoomId,
+    requested: Options,
+    granted: Option<Options>,
+}
+
+impl CapabilitiesRequest {
+    pub(super) fn new(
+        widget_id: String,
+        room_id: OwnedRoomId,
+        requested: Options,
+        granted: Option<Options>,
+    ) -> Self {
+        Self { widget_id, room_id, requested, granted }
+    }
+
+    /// The ID of the widget that requests the capabilities.
+    pub fn widget_id(&self) -> &str {
+        &self.widget_id
+    }
+
+    /// The room the widget runs in.
+    pub fn room_id(&self) -> &RoomId {
+        &self.room_id
+    }
+
+    /// All the capabilities requested by the widget.
+    pub fn requested(&self) -> &Options {
+        &self.requested
+    }
+
+    /// The capabilities granted to the widget in this room the last time it
+    /// asked for them, if any.
+    pub fn previously_granted(&self) -> Option<&Options> {
+        self.granted.as_ref()
+    }
+
+    /// The requested capabilities that weren't granted before, that's what the
+    /// user should be asked about.
+    pub fn new_capabilities(&self) -> Vec<String> {
+        let granted = self.granted.as_ref().map(Options::capability_list).unwrap_or_default();
+        self.requested.capability_list().into_iter().filter(|c| !granted.contains(c)).collect()
+    }
+
+    /// The capabilities that were granted before, but that the widget doesn't
+    /// request anymore.
+    pub fn dropped_capabilities(&self) -> Vec<String> {
+        let requested = self.requested.capability_list();
+        let granted = self.granted.as_ref().map(Options::capability_list).unwrap_or_default();
+        granted.into_iter().filter(|c| !requested.contains(c)).collect()
+    }
+
+    /// Whether all the requested capabilities have already been granted, in
+    /// which case the user doesn't need to be asked again.
+    pub fn is_granted(&self) -> bool {
+        self.granted.is_some() && self.new_capabilities().is_empty()
+    }
+}
+
+/// The key of the capabilities granted to the given widget in the given room.
+///
+/// The origin of the widget is part of the key, so a widget that is replaced by
+/// one served from somewhere else with the same ID doesn't get the
+/// capabilities granted to the previous one.
+pub(super) fn capabilities_key(room_id: &RoomId, widget: &Info) -> Vec<u8> {
+    format!("widget_api.capabilities.{room_id}.{}.{}", widget.origin, widget.id).into_bytes()
+}
+
+/// Get the capabilities that were granted to the given widget in the given
+/// room, if the user was asked for them already.
+pub async fn granted_capabilities(room: &Joined, widget: &Info) -> Result<Option<Options>> {
+    let key = capabilities_key(room.room_id(), widget);
+    let Some(value) = room.client().store().get_custom_value(&key).await? else {
+        return Ok(None);
+    };
+
+    Ok(Some(serde_json::from_slice(&value)?))
+}
+
+/// Remember the capabilities granted to the given widget in the given room, so
+/// the user isn't asked for them again.
+pub async fn save_granted_capabilities(
+    room: &Joined,
+    widget: &Info,
+    capabilities: &Options,
+) -> Result<()> {
+    let key = capabilities_key(room.room_id(), widget);
+    room.client().store().set_custom_value(&key, serde_json::to_vec(capabilities)?).await?;
+
+    Ok(())
+}
+
+/// Revoke the capabilities granted to the given widget in the given room.
+///
+/// If the widget is running, it loses its capabilities right away and is
+/// notified about it. The user will be asked for them again the next time the
+/// widget negotiates its capabilities.
+pub async fn revoke_capabilities(room: &Joined, widget: &Info) -> Result<()> {
+    let key = capabilities_key(room.room_id(), widget);
+    room.client().store().remove_custom_value(&key).await?;
+
+    // There are no receivers if no widget is running, that's fine.
+    let _ = room.client().inner.revoked_widget_capabilities.send(key);
+
+    Ok(())
+}
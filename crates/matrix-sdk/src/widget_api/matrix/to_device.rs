@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use ruma::{
+    api::client::to_device::send_event_to_device::v3::Request as ToDeviceRequest,
+    events::AnyToDeviceEventContent, serde::Raw, to_device::DeviceIdOrAllDevices, OwnedUserId,
+    TransactionId, UserId,
+};
+use serde::{de::IgnoredAny, Deserialize};
+#[cfg(feature = "e2e-encryption")]
+use tracing::warn;
+
+use super::super::{
+    handler::ToDeviceSender,
+    messages::{capabilities::ToDeviceFilter, from_widget::SendToDeviceRequest, ToDeviceEvent},
+    {Error, Result},
+};
+#[cfg(feature = "e2e-encryption")]
+use crate::encryption::OlmError;
+use crate::room::Joined;
+
+/// A to-device event as we get it from the sync, after the decryption.
+#[derive(Debug, Deserialize)]
+pub(super) struct IncomingToDeviceEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    sender: String,
+    content: serde_json::Value,
+    // Only events that were decrypted from an `m.room.encrypted` event contain the keys of the
+    // recipient.
+    recipient_keys: Option<IgnoredAny>,
+}
+
+impl From<IncomingToDeviceEvent> for ToDeviceEvent {
+    fn from(event: IncomingToDeviceEvent) -> Self {
+        Self {
+            event_type: event.event_type,
+            sender: event.sender,
+            encrypted: event.recipient_keys.is_some(),
+            content: event.content,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ToDeviceProxy {
+    room: Joined,
+    filters: Vec<ToDeviceFilter>,
+}
+
+impl ToDeviceProxy {
+    pub(super) fn new(room: Joined, filters: Vec<ToDeviceFilter>) -> Self {
+        Self { room, filters }
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    async fn encrypt(&self, event_type: &str, messages: Recipients) -> Result<Messages> {
+        let client = &self.room.client;
+
+        // Make sure that we have an Olm session with every device we're sending the message to.
+        client
+            .claim_one_time_keys(messages.keys().map(|user_id| &**user_id))
+            .await
+            .map_err(|_| Error::Other)?;
+
+        let mut encrypted = Messages::new();
+
+        for (user_id, devices) in messages {
+            for (device, content) in devices {
+                let encryption = client.encryption();
+                let targets: Vec<_> = if let DeviceIdOrAllDevices::DeviceId(device_id) = device {
+                    let device = encryption.get_device(&user_id, &device_id).await;
+                    device.map_err(|_| Error::Other)?.into_iter().collect()
+                } else {
+                    let devices = encryption.get_user_devices(&user_id).await;
+                    devices.map_err(|_| Error::Other)?.devices().collect()
+                };
+
+                // Never send anything to a device the user has blacklisted.
+                for target in targets.into_iter().filter(|target| !target.is_blacklisted()) {
+                    let content = match target.encrypt_event_raw(event_type, content.clone()).await
+                    {
+                        Ok(content) => content,
+                        // We couldn't claim a one-time key for this device, skip it rather than
+                        // failing the whole request for the other recipients.
+                        Err(OlmError::MissingSession) => {
+                            warn!(
+                                user_id = ?target.user_id(),
+                                device_id = ?target.device_id(),
+                                "Not sending a to-device message to a device without an Olm session"
+                            );
+                            continue;
+                        }
+                        Err(_) => return Err(Error::Other),
+                    };
+
+                    encrypted.entry(user_id.clone()).or_default().insert(
+                        DeviceIdOrAllDevices::DeviceId(target.device_id().to_owned()),
+                        content.cast(),
+                    );
+                }
+            }
+        }
+
+        Ok(encrypted)
+    }
+
+    #[cfg(not(feature = "e2e-encryption"))]
+    async fn encrypt(&self, _event_type: &str, _messages: Recipients) -> Result<Messages> {
+        Err(Error::InvalidPermissions)
+    }
+}
+
+#[async_trait]
+impl ToDeviceSender for ToDeviceProxy {
+    fn filters(&self) -> &[ToDeviceFilter] {
+        &self.filters
+    }
+
+    async fn send(&self, req: SendToDeviceRequest) -> Result<()> {
+        // Run the request through the filter.
+        if !self.filters.iter().any(|f| f.allow(&req.event_type)) {
+            return Err(Error::InvalidPermissions);
+        }
+
+        let recipients = parse_recipients(req.messages)?;
+
+        let (event_type, messages) = if req.encrypted {
+            ("m.room.encrypted".to_owned(), self.encrypt(&req.event_type, recipients).await?)
+        } else {
+            let messages = recipients
+                .into_iter()
+                .map(|(user_id, devices)| {
+                    let devices = devices
+                        .into_iter()
+                        .map(|(device, content)| {
+                            let content = Raw::new(&content).map_err(|_| Error::InvalidJSON)?;
+                            Ok((device, content.cast::<AnyToDeviceEventContent>()))
+                        })
+                        .collect::<Result<_>>()?;
+                    Ok((user_id, devices))
+                })
+                .collect::<Result<_>>()?;
+
+            (req.event_type, messages)
+        };
+
+        // None of the recipients could receive the message, e.g. because we don't have an Olm
+        // session with any of their devices.
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let request = ToDeviceRequest::new_raw(event_type.into(), TransactionId::new(), messages);
+        self.room.client.send(request, None).await.map_err(|_| Error::Other)?;
+
+        Ok(())
+    }
+}
+
+type Messages = BTreeMap<OwnedUserId, BTreeMap<DeviceIdOrAllDevices, Raw<AnyToDeviceEventContent>>>;
+type Recipients = BTreeMap<OwnedUserId, BTreeMap<DeviceIdOrAllDevices, serde_json::Value>>;
+
+fn parse_recipients(
+    messages: BTreeMap<String, BTreeMap<String, serde_json::Value>>,
+) -> Result<Recipients> {
+    messages
+        .into_iter()
+        .map(|(user_id, devices)| {
+            let user_id = UserId::parse(user_id).map_err(|_| Error::InvalidJSON)?;
+            let devices = devices
+                .into_iter()
+                .map(|(device_id, content)| {
+                    let device = match device_id.as_str() {
+                        "*" => DeviceIdOrAllDevices::AllDevices,
+                        _ => DeviceIdOrAllDevices::DeviceId(device_id.into()),
+                    };
+                    (device, content)
+                })
+                .collect();
+            Ok((user_id, devices))
+        })
+        .collect()
+}
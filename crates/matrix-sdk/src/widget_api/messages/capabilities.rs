@@ -1,28 +1,33 @@
 use std::fmt::Debug;
 
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
 
-use super::{MatrixEvent, from_widget::SendEventRequest};
+use super::{from_widget::SendEventRequest, MatrixEvent};
 
 const SEND_EVENT: &str = "org.matrix.msc2762.m.send.event";
 const READ_EVENT: &str = "org.matrix.msc2762.m.receive.event";
 const SEND_STATE: &str = "org.matrix.msc2762.m.send.state_event";
 const READ_STATE: &str = "org.matrix.msc2762.m.receive.state_event";
+const SEND_TO_DEVICE: &str = "org.matrix.msc3819.send.to_device";
+const READ_TO_DEVICE: &str = "org.matrix.msc3819.receive.to_device";
+const TURN_SERVERS: &str = "town.robin.msc3846.turn_servers";
 
 #[derive(Debug, Default, Clone)]
 pub struct Options {
     pub send_filter: Vec<Filter>,
     pub read_filter: Vec<Filter>,
+    pub send_to_device_filter: Vec<ToDeviceFilter>,
+    pub read_to_device_filter: Vec<ToDeviceFilter>,
+    pub turn_servers: bool,
     pub screenshot: bool,
     pub always_on_screen: bool,
     pub requires_client: bool,
 }
 
-impl Serialize for Options {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
+impl Options {
+    /// The capabilities as they are sent to the widget, e.g.
+    /// `org.matrix.msc2762.m.receive.event:m.reaction`.
+    pub fn capability_list(&self) -> Vec<String> {
         let mut capability_list: Vec<String> = vec![];
         if self.screenshot {
             capability_list.push("m.capability.screenshot".to_owned());
@@ -30,12 +35,12 @@ impl Serialize for Options {
         if self.always_on_screen {
             capability_list.push("m.always_on_screen".to_owned());
         }
-        if self.always_on_screen {
-            capability_list.push("m.always_on_screen".to_owned());
-        }
         if self.requires_client {
             capability_list.push("io.element.requires_client".to_owned());
         }
+        if self.turn_servers {
+            capability_list.push(TURN_SERVERS.to_owned());
+        }
 
         let all_filter = vec![
             self.send_filter
@@ -57,16 +62,19 @@ impl Serialize for Options {
                 Err(_) => continue,
             }
         }
-        capability_list.serialize(serializer)
+
+        for filter in &self.send_to_device_filter {
+            capability_list.push(format!("{SEND_TO_DEVICE}:{}", filter.event_type));
+        }
+        for filter in &self.read_to_device_filter {
+            capability_list.push(format!("{READ_TO_DEVICE}:{}", filter.event_type));
+        }
+
+        capability_list
     }
-}
 
-impl<'de> Deserialize<'de> for Options {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let capability_list = Vec::<String>::deserialize(deserializer)?;
+    /// Parses the capabilities sent by the widget, the unknown ones are ignored.
+    pub fn from_capability_list(capability_list: Vec<String>) -> Self {
         let mut capabilities = Options::default();
 
         for capability in capability_list {
@@ -79,49 +87,85 @@ impl<'de> Deserialize<'de> for Options {
             if capability == "io.element.requires_client" {
                 capabilities.requires_client = true;
             }
-            if capability.starts_with(SEND_EVENT) {
-                let cap_split: Vec<&str> = capability.split(":").collect();
-                if cap_split.len() > 1 {
-                    capabilities
-                        .send_filter
-                        .push(Filter::Timeline(serde_json::from_str(cap_split[1]).unwrap()));
-                } else {
-                    capabilities.send_filter.push(Filter::AllowAllTimeline);
-                }
+            if capability == TURN_SERVERS {
+                capabilities.turn_servers = true;
+            }
+            if let Some(filter) = ToDeviceFilter::from_capability(&capability, SEND_TO_DEVICE) {
+                capabilities.send_to_device_filter.push(filter);
+            }
+            if let Some(filter) = ToDeviceFilter::from_capability(&capability, READ_TO_DEVICE) {
+                capabilities.read_to_device_filter.push(filter);
             }
-            if capability.starts_with(READ_EVENT) {
-                let cap_split: Vec<&str> = capability.split(":").collect();
-                if cap_split.len() > 1 {
-                    capabilities
-                        .read_filter
-                        .push(Filter::Timeline(serde_json::from_str(cap_split[1]).unwrap()));
-                } else {
-                    capabilities.read_filter.push(Filter::AllowAllTimeline);
-                }
+            if let Some(filter) =
+                parse_filter(&capability, SEND_EVENT, Filter::Timeline, Filter::AllowAllTimeline)
+            {
+                capabilities.send_filter.push(filter);
             }
-            if capability.starts_with(SEND_STATE) {
-                let cap_split: Vec<&str> = capability.split(":").collect();
-                if cap_split.len() > 1 {
-                    capabilities
-                        .send_filter
-                        .push(Filter::State(serde_json::from_str(cap_split[1]).unwrap()));
-                } else {
-                    capabilities.send_filter.push(Filter::AllowAllState);
-                }
+            if let Some(filter) =
+                parse_filter(&capability, READ_EVENT, Filter::Timeline, Filter::AllowAllTimeline)
+            {
+                capabilities.read_filter.push(filter);
             }
-            if capability.starts_with(READ_STATE) {
-                let cap_split: Vec<&str> = capability.split(":").collect();
-                if cap_split.len() > 1 {
-                    capabilities
-                        .read_filter
-                        .push(Filter::State(serde_json::from_str(cap_split[1]).unwrap()));
-                } else {
-                    capabilities.read_filter.push(Filter::AllowAllState);
-                }
+            if let Some(filter) =
+                parse_filter(&capability, SEND_STATE, Filter::State, Filter::AllowAllState)
+            {
+                capabilities.send_filter.push(filter);
+            }
+            if let Some(filter) =
+                parse_filter(&capability, READ_STATE, Filter::State, Filter::AllowAllState)
+            {
+                capabilities.read_filter.push(filter);
+            }
+        }
+
+        capabilities
+    }
+
+    /// The capabilities of both `self` and `other`.
+    pub fn union(&self, other: &Options) -> Self {
+        let mut capability_list = self.capability_list();
+        for capability in other.capability_list() {
+            if !capability_list.contains(&capability) {
+                capability_list.push(capability);
             }
         }
 
-        Ok(capabilities)
+        Self::from_capability_list(capability_list)
+    }
+}
+
+impl Serialize for Options {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.capability_list().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Options {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Self::from_capability_list(Vec::<String>::deserialize(deserializer)?))
+    }
+}
+
+/// Parses a `base` or a `base:<filter>` capability, e.g.
+/// `org.matrix.msc2762.m.receive.state_event:m.room.member#@alice:example.org`.
+fn parse_filter<T: DeserializeOwned>(
+    capability: &str,
+    base: &str,
+    filter: impl FnOnce(T) -> Filter,
+    allow_all: Filter,
+) -> Option<Filter> {
+    match capability.strip_prefix(base)? {
+        "" => Some(allow_all),
+        extension => {
+            let extension = extension.strip_prefix(':')?;
+            serde_json::from_value(extension.into()).ok().map(filter)
+        }
     }
 }
 
@@ -153,13 +197,23 @@ impl Filter {
         }
     }
 
-    fn capability_extension(&self) -> Result<String, serde_json::Error> {
+    /// The event type of the state events that this filter allows, if it is restricted to a
+    /// single event type.
+    pub fn state_event_type(&self) -> Option<&str> {
         match self {
-            Filter::State(s_filter) => serde_json::to_string(s_filter),
-            Filter::Timeline(t_filter) => serde_json::to_string(t_filter),
-            Filter::AllowAllTimeline | Filter::AllowAllState => Ok("".to_owned()),
+            Filter::State(s_filter) => Some(&s_filter.event_type),
+            Filter::Timeline(_) | Filter::AllowAllTimeline | Filter::AllowAllState => None,
         }
     }
+
+    fn capability_extension(&self) -> Result<String, serde_json::Error> {
+        let extension = match self {
+            Filter::State(s_filter) => serde_json::to_value(s_filter)?,
+            Filter::Timeline(t_filter) => serde_json::to_value(t_filter)?,
+            Filter::AllowAllTimeline | Filter::AllowAllState => return Ok("".to_owned()),
+        };
+        Ok(format!(":{}", extension.as_str().unwrap_or_default()))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -189,7 +243,6 @@ impl<'a> From<&'a SendEventRequest> for FilterInput<'a> {
     }
 }
 
-
 pub trait EventFilter {
     fn allow(&self, input: FilterInput) -> bool;
 }
@@ -214,7 +267,7 @@ impl EventFilter for TimelineFilter {
             return false;
         }
 
-        input.content.get("msgtype").map(|t| &t.to_string() == allowed_type).unwrap_or(false)
+        input.content.get("msgtype").and_then(|t| t.as_str()) == Some(allowed_type.as_str())
     }
 }
 
@@ -230,11 +283,34 @@ impl EventFilter for StateFilter {
             return false;
         }
 
-        self.state_key
-            .as_ref()
-            .zip(input.state_key)
-            .map(|(expected, passed)| expected == passed)
-            .unwrap_or(false)
+        match (&self.state_key, input.state_key) {
+            // Only state events match a state filter.
+            (_, None) => false,
+            // A filter without a state key allows any state key.
+            (None, Some(_)) => true,
+            (Some(expected), Some(passed)) => expected == passed,
+        }
+    }
+}
+
+// MSC3819: to-device events are only filtered by their event type.
+#[derive(Debug, Default, Clone)]
+pub struct ToDeviceFilter {
+    event_type: String,
+}
+
+impl ToDeviceFilter {
+    pub fn new(event_type: impl Into<String>) -> Self {
+        Self { event_type: event_type.into() }
+    }
+
+    pub fn allow(&self, event_type: &str) -> bool {
+        self.event_type == event_type
+    }
+
+    fn from_capability(capability: &str, base: &str) -> Option<Self> {
+        let event_type = capability.strip_prefix(base)?.strip_prefix(':')?;
+        (!event_type.is_empty()).then(|| Self::new(event_type))
     }
 }
 
@@ -250,7 +326,7 @@ impl Serialize for TimelineFilter {
 
 impl Serialize for StateFilter {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut string = format!(":{}", self.event_type);
+        let mut string = format!("{}", self.event_type);
         if let Some(state_key) = &self.state_key {
             string = format!("{}#{}", string, state_key);
         }
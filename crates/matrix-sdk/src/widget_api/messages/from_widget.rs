@@ -1,6 +1,11 @@
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-use super::{openid, MatrixEvent, MessageBody, ReadRelationsDirection, SupportedVersions};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    capabilities::Options, openid, MatrixEvent, MessageBody, ReadRelationsDirection,
+    SupportedVersions,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "action")]
@@ -16,7 +21,11 @@ pub enum FromWidgetMessage {
     #[serde(rename = "org.matrix.msc2876.read_events")]
     ReadEvent(MessageBody<ReadEventRequest, ReadEventResponse>),
     #[serde(rename = "org.matrix.msc3869.read_relations")]
-    ReadRelations(MessageBody<ReadEventRequest, ReadEventResponse>),
+    ReadRelations(MessageBody<ReadRelationsRequest, ReadRelationsResponse>),
+    #[serde(rename = "send_to_device")]
+    SendToDevice(MessageBody<SendToDeviceRequest, ()>),
+    #[serde(rename = "org.matrix.msc2974.request_capabilities")]
+    RequestCapabilities(MessageBody<RequestCapabilitiesRequest, ()>),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -37,10 +46,47 @@ pub struct SendEventResponse {
 pub struct ReadEventRequest {
     #[serde(rename = "type")]
     pub message_type: String,
-    pub state_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_key: Option<StateKeySelector>,
     pub limit: u32,
 }
 
+/// The state events that a widget wants to read: the ones with a given state key, or all of
+/// them (`"state_key": true`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateKeySelector {
+    Key(String),
+    Any,
+}
+
+impl Serialize for StateKeySelector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            StateKeySelector::Key(key) => key.serialize(serializer),
+            StateKeySelector::Any => true.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StateKeySelector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Selector {
+            Key(String),
+            Any(bool),
+        }
+
+        match Selector::deserialize(deserializer)? {
+            Selector::Key(key) => Ok(StateKeySelector::Key(key)),
+            Selector::Any(true) => Ok(StateKeySelector::Any),
+            Selector::Any(false) => {
+                Err(de::Error::custom("`state_key` must be a string or `true`"))
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ReadEventResponse {
     pub events: Vec<MatrixEvent>,
@@ -49,19 +95,37 @@ pub struct ReadEventResponse {
 // MSC3869
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ReadRelationsRequest {
-    event_id: String,
-    room_id: Option<String>,
-    rel_type: Option<String>,
-    event_type: Option<String>,
-    limit: Option<u32>,
-    from: Option<String>,
-    to: Option<String>,
-    direction: Option<ReadRelationsDirection>,
+    pub event_id: String,
+    pub room_id: Option<String>,
+    pub rel_type: Option<String>,
+    pub event_type: Option<String>,
+    pub limit: Option<u32>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub direction: Option<ReadRelationsDirection>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ReadRelationsResponse {
-    chunk: Vec<MatrixEvent>,
-    next_batch: String,
-    prev_batch: String,
+    pub chunk: Vec<MatrixEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_batch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev_batch: Option<String>,
+}
+
+// MSC3819
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SendToDeviceRequest {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub encrypted: bool,
+    // user_id -> device_id (or `*` for all devices) -> content
+    pub messages: BTreeMap<String, BTreeMap<String, serde_json::Value>>,
+}
+
+// MSC2974
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RequestCapabilitiesRequest {
+    pub capabilities: Options,
 }
@@ -93,12 +93,14 @@ pub struct SupportedVersions {
     pub versions: Vec<ApiVersion>,
 }
 
-pub static SUPPORTED_API_VERSIONS: [ApiVersion; 6] = [
+pub static SUPPORTED_API_VERSIONS: [ApiVersion; 8] = [
     ApiVersion::V0_0_1,
     ApiVersion::V0_0_2,
     ApiVersion::MSC2762,
     ApiVersion::MSC2871,
+    ApiVersion::MSC2974,
     ApiVersion::MSC3819,
+    ApiVersion::MSC3846,
     ApiVersion::MSC3869,
 ];
 
@@ -116,7 +118,8 @@ pub enum ApiVersion {
     MSC2931,
     #[serde(rename = "org.matrix.msc2974")] // Widgets: Capabilities re-exchange
     MSC2974,
-    #[serde(rename = "org.matrix.msc2876")] // Allowing widgets to read events in a room (Closed/Deprecated)
+    #[serde(rename = "org.matrix.msc2876")]
+    // Allowing widgets to read events in a room (Closed/Deprecated)
     MSC2876,
     #[serde(rename = "org.matrix.msc3819")] // Allowing widgets to send/receive to-device messages
     MSC3819,
@@ -132,16 +135,31 @@ pub struct MatrixEvent {
     pub event_type: String,
     pub sender: String,
     pub event_id: String,
+    // Events from the sync and from the state store don't contain the room ID.
+    #[serde(default)]
     pub room_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub state_key: Option<String>,
-    pub origin_server_ts: u32,
+    pub origin_server_ts: u64,
     pub content: serde_json::Value,
+    #[serde(default)]
     pub unsigned: Unsigned,
 }
 
+// MSC3819
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ToDeviceEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub sender: String,
+    pub encrypted: bool,
+    pub content: serde_json::Value,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Unsigned {
-    age: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
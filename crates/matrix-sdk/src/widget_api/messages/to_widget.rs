@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use super::{capabilities::Options, openid, MatrixEvent, MessageBody};
+use super::{capabilities::Options, openid, MatrixEvent, MessageBody, ToDeviceEvent};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "action")]
@@ -13,6 +13,12 @@ pub enum ToWidgetMessage {
     OpenIdCredentials(MessageBody<openid::State, ()>),
     #[serde(rename = "send_event")]
     SendEvent(MessageBody<MatrixEvent, ()>),
+    #[serde(rename = "update_state")]
+    UpdateState(MessageBody<UpdateStateRequest, ()>),
+    #[serde(rename = "send_to_device")]
+    SendToDevice(MessageBody<ToDeviceEvent, ()>),
+    #[serde(rename = "update_turn_servers")]
+    UpdateTurnServers(MessageBody<TurnServers, ()>),
 }
 
 impl ToWidgetMessage {
@@ -21,7 +27,10 @@ impl ToWidgetMessage {
             ToWidgetMessage::SendMeCapabilities(MessageBody { header, .. })
             | ToWidgetMessage::CapabilitiesUpdated(MessageBody { header, .. })
             | ToWidgetMessage::OpenIdCredentials(MessageBody { header, .. })
-            | ToWidgetMessage::SendEvent(MessageBody { header, .. }) => &header.request_id,
+            | ToWidgetMessage::SendEvent(MessageBody { header, .. })
+            | ToWidgetMessage::UpdateState(MessageBody { header, .. })
+            | ToWidgetMessage::SendToDevice(MessageBody { header, .. })
+            | ToWidgetMessage::UpdateTurnServers(MessageBody { header, .. }) => &header.request_id,
         }
     }
 }
@@ -36,3 +45,16 @@ pub struct CapabilitiesUpdatedRequest {
     pub requested: Options,
     pub approved: Options,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateStateRequest {
+    pub state: Vec<MatrixEvent>,
+}
+
+// MSC3846
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TurnServers {
+    pub uris: Vec<String>,
+    pub username: String,
+    pub password: String,
+}
@@ -6,11 +6,16 @@ pub mod error;
 pub mod handler;
 pub mod matrix;
 pub mod messages;
+pub mod settings;
 
 pub use self::{
     api::{run, widget::Widget},
     error::{Error, Result},
-    matrix::{Driver as MatrixDriver, PermissionProvider},
+    matrix::{
+        granted_capabilities, revoke_capabilities, CapabilitiesRequest, Driver as MatrixDriver,
+        PermissionProvider,
+    },
+    settings::{ClientProperties, VirtualElementCallWidgetOptions, WidgetSettings},
 };
 use crate::room::Joined as JoinedRoom;
 
@@ -21,5 +26,6 @@ pub async fn run_client_widget_api(
     permission_manager: impl PermissionProvider,
     room: JoinedRoom,
 ) -> Result<()> {
-    run(MatrixDriver::new(room, permission_manager), widget).await
+    let driver = MatrixDriver::new(room, widget.info.clone(), permission_manager);
+    run(driver, widget).await
 }
@@ -0,0 +1,86 @@
+use url::Url;
+
+use super::{
+    url_params::{self, encode},
+    WidgetSettings,
+};
+
+/// Properties to create a new virtual Element Call widget.
+#[derive(Debug, Default, Clone)]
+pub struct VirtualElementCallWidgetOptions {
+    /// The URL of the Element Call app, e.g. `https://call.element.io`.
+    pub element_call_url: String,
+    /// The ID of the widget.
+    pub widget_id: String,
+    /// The URL of the page that embeds the widget, if it runs in a web page.
+    pub parent_url: Option<String>,
+    /// Whether Element Call should hide its own header.
+    pub hide_header: bool,
+    /// Whether Element Call should load the call without joining it yet.
+    pub preload: bool,
+    /// The scale of the font used by Element Call.
+    pub font_scale: Option<f64>,
+    /// Whether Element Call should skip its lobby and join the call directly.
+    pub skip_lobby: bool,
+    /// Whether Element Call should stay in the room of the widget.
+    pub confine_to_room: bool,
+    /// The font used by Element Call.
+    pub font: Option<String>,
+    /// The ID used for analytics, if the user opted in.
+    pub analytics_id: Option<String>,
+}
+
+impl WidgetSettings {
+    /// Creates the settings of a widget for Element Call that doesn't exist in
+    /// the room state.
+    ///
+    /// The URL of the widget uses the template variables that are replaced by
+    /// [`WidgetSettings::generate_webview_url()`].
+    pub fn new_virtual_element_call_widget(
+        options: VirtualElementCallWidgetOptions,
+    ) -> Result<Self, url::ParseError> {
+        let mut raw_url = Url::parse(&options.element_call_url)?.join("room")?;
+
+        let mut params = vec![
+            ("widgetId", url_params::WIDGET_ID.to_owned()),
+            ("embed", "true".to_owned()),
+            ("userId", url_params::USER_ID.to_owned()),
+            ("deviceId", url_params::DEVICE_ID.to_owned()),
+            ("roomId", url_params::ROOM_ID.to_owned()),
+            ("lang", url_params::LANGUAGE.to_owned()),
+            ("theme", url_params::CLIENT_THEME.to_owned()),
+            ("baseUrl", url_params::HOMESERVER_URL.to_owned()),
+        ];
+        if let Some(parent_url) = &options.parent_url {
+            params.push(("parentUrl", encode(parent_url)));
+        }
+        if options.hide_header {
+            params.push(("hideHeader", "true".to_owned()));
+        }
+        if options.preload {
+            params.push(("preload", "true".to_owned()));
+        }
+        if let Some(font_scale) = options.font_scale {
+            params.push(("fontScale", font_scale.to_string()));
+        }
+        if options.skip_lobby {
+            params.push(("skipLobby", "true".to_owned()));
+        }
+        if options.confine_to_room {
+            params.push(("confineToRoom", "true".to_owned()));
+        }
+        if let Some(font) = &options.font {
+            params.push(("font", encode(font)));
+        }
+        if let Some(analytics_id) = &options.analytics_id {
+            params.push(("analyticsID", encode(analytics_id)));
+        }
+
+        // Element Call reads its parameters from the fragment.
+        let query: Vec<_> = params.iter().map(|(key, value)| format!("{key}={value}")).collect();
+        raw_url.set_fragment(Some(&format!("?{}", query.join("&"))));
+
+        // Element Call tells us when it's ready to negotiate the capabilities.
+        Ok(Self::new(options.widget_id, true, raw_url))
+    }
+}
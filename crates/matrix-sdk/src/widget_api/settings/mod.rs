@@ -0,0 +1,261 @@
+use matrix_sdk_base::deserialized_responses::RawAnySyncOrStrippedState;
+use ruma::{
+    events::{AnySyncStateEvent, StateEventType},
+    serde::Raw,
+};
+use serde::Deserialize;
+use url::Url;
+
+use self::url_params::{replace_properties, QueryProperties};
+use super::api::widget::Info;
+use crate::{room::Joined, Result};
+
+mod element_call;
+mod url_params;
+
+pub use self::element_call::VirtualElementCallWidgetOptions;
+
+/// The state event types that describe the widgets of a room.
+const WIDGET_EVENT_TYPES: [&str; 2] = ["m.widget", "im.vector.modular.widgets"];
+
+/// The settings of a widget, as found in the room state or created for a
+/// virtual widget.
+#[derive(Debug, Clone)]
+pub struct WidgetSettings {
+    id: String,
+    init_on_load: bool,
+    raw_url: Url,
+}
+
+impl WidgetSettings {
+    /// Creates the settings of a widget.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the widget.
+    ///
+    /// * `init_on_load` - Whether we should wait for the widget to tell us it's
+    ///   loaded before negotiating its capabilities.
+    ///
+    /// * `raw_url` - The URL of the widget, with template variables like
+    ///   `$matrix_user_id`.
+    pub fn new(id: String, init_on_load: bool, raw_url: Url) -> Self {
+        Self { id, init_on_load, raw_url }
+    }
+
+    /// The ID of the widget.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Whether we wait for the widget to tell us it's loaded before
+    /// negotiating its capabilities.
+    pub fn init_on_load(&self) -> bool {
+        self.init_on_load
+    }
+
+    /// The URL of the widget, with the template variables.
+    pub fn raw_url(&self) -> &Url {
+        &self.raw_url
+    }
+
+    /// The information needed to run the widget API with this widget.
+    pub fn info(&self) -> Info {
+        Info {
+            id: self.id.clone(),
+            origin: self.raw_url.origin().ascii_serialization(),
+            init_on_load: self.init_on_load,
+        }
+    }
+
+    /// Parses a `m.widget` or `im.vector.modular.widgets` state event.
+    ///
+    /// Returns `None` if the event doesn't describe a widget, e.g. because the
+    /// widget was removed from the room.
+    pub fn from_state_event(event: &Raw<AnySyncStateEvent>) -> Option<Self> {
+        let event = event.deserialize_as::<WidgetStateEvent>().ok()?;
+        if !WIDGET_EVENT_TYPES.contains(&event.event_type.as_str()) {
+            return None;
+        }
+
+        let content = event.content;
+        let id = content.id.unwrap_or(event.state_key);
+        let raw_url = Url::parse(&content.url).ok()?;
+
+        // Widgets that don't want us to wait for the iframe to load tell us
+        // when they're ready with `content_loaded`.
+        Some(Self::new(id, !content.wait_for_iframe_load, raw_url))
+    }
+
+    /// Get the settings of all the widgets of the given room, from the room
+    /// state in the store.
+    pub async fn from_room(room: &Joined) -> Result<Vec<Self>> {
+        let mut widgets = Vec::new();
+
+        for event_type in WIDGET_EVENT_TYPES {
+            let events = room.get_state_events(StateEventType::from(event_type)).await?;
+            widgets.extend(events.iter().filter_map(|event| match event {
+                RawAnySyncOrStrippedState::Sync(raw) => Self::from_state_event(raw),
+                RawAnySyncOrStrippedState::Stripped(_) => None,
+            }));
+        }
+
+        Ok(widgets)
+    }
+
+    /// Builds the URL of the widget that should be loaded in the webview, by
+    /// replacing the template variables of the raw URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - The room the widget runs in.
+    ///
+    /// * `props` - The properties of the client that runs the widget.
+    pub async fn generate_webview_url(
+        &self,
+        room: &Joined,
+        props: ClientProperties,
+    ) -> Result<Url> {
+        let own_user_id = room.own_user_id();
+        let member = room.get_member_no_sync(own_user_id).await?;
+        let display_name = member.as_ref().and_then(|m| m.display_name().map(ToOwned::to_owned));
+        let avatar_url = member.as_ref().and_then(|m| m.avatar_url().map(ToString::to_string));
+
+        let client = room.client();
+        let query_props = QueryProperties {
+            widget_id: self.id.clone(),
+            avatar_url: avatar_url.unwrap_or_default(),
+            display_name: display_name.unwrap_or_default(),
+            user_id: own_user_id.to_string(),
+            room_id: room.room_id().to_string(),
+            language: props.language,
+            client_theme: props.theme,
+            client_id: props.client_id,
+            device_id: client.device_id().map(ToString::to_string).unwrap_or_default(),
+            homeserver_url: client.homeserver().await.to_string(),
+        };
+
+        Ok(replace_properties(&self.raw_url, &query_props)?)
+    }
+}
+
+/// The properties of the client that are passed to widgets.
+#[derive(Debug, Clone)]
+pub struct ClientProperties {
+    client_id: String,
+    language: String,
+    theme: String,
+}
+
+impl ClientProperties {
+    /// Creates the properties of the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The identifier of the client, in reverse domain name
+    ///   notation, e.g. `org.example.client`.
+    ///
+    /// * `language` - The language of the client, as a BCP 47 tag. Defaults to
+    ///   `en-US`.
+    ///
+    /// * `theme` - The theme of the client, e.g. `light` or `dark`. Defaults to
+    ///   `light`.
+    pub fn new(client_id: &str, language: Option<String>, theme: Option<String>) -> Self {
+        Self {
+            client_id: client_id.to_owned(),
+            language: language.unwrap_or_else(|| "en-US".to_owned()),
+            theme: theme.unwrap_or_else(|| "light".to_owned()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct WidgetStateEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    state_key: String,
+    content: WidgetEventContent,
+}
+
+#[derive(Deserialize)]
+struct WidgetEventContent {
+    id: Option<String>,
+    url: String,
+    #[serde(rename = "waitForIframeLoad", default = "default_true")]
+    wait_for_iframe_load: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{events::AnySyncStateEvent, serde::Raw};
+    use serde_json::json;
+
+    use super::{VirtualElementCallWidgetOptions, WidgetSettings};
+
+    #[test]
+    fn parse_widget_state_event() {
+        let event: Raw<AnySyncStateEvent> = Raw::new(&json!({
+            "content": {
+                "type": "m.custom",
+                "url": "https://example.org/widget?user=$matrix_user_id",
+                "name": "Widget",
+                "data": {},
+                "waitForIframeLoad": false,
+            },
+            "event_id": "$widget",
+            "origin_server_ts": 152037280,
+            "sender": "@alice:example.org",
+            "state_key": "widget",
+            "type": "im.vector.modular.widgets",
+        }))
+        .unwrap()
+        .cast();
+
+        let widget = WidgetSettings::from_state_event(&event).unwrap();
+        assert_eq!(widget.id(), "widget");
+        assert!(widget.init_on_load());
+        assert_eq!(widget.raw_url().as_str(), "https://example.org/widget?user=$matrix_user_id");
+
+        // A widget that was removed from the room.
+        let event: Raw<AnySyncStateEvent> = Raw::new(&json!({
+            "content": {},
+            "event_id": "$removed",
+            "origin_server_ts": 152037290,
+            "sender": "@alice:example.org",
+            "state_key": "widget",
+            "type": "m.widget",
+        }))
+        .unwrap()
+        .cast();
+        assert!(WidgetSettings::from_state_event(&event).is_none());
+    }
+
+    #[test]
+    fn virtual_element_call_widget() {
+        let widget =
+            WidgetSettings::new_virtual_element_call_widget(VirtualElementCallWidgetOptions {
+                element_call_url: "https://call.element.io".to_owned(),
+                widget_id: "call".to_owned(),
+                parent_url: Some("https://app.example.org/#/room".to_owned()),
+                hide_header: true,
+                preload: true,
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(widget.id(), "call");
+        assert!(widget.init_on_load());
+        assert_eq!(
+            widget.raw_url().as_str(),
+            "https://call.element.io/room#?widgetId=$matrix_widget_id&embed=true\
+             &userId=$matrix_user_id&deviceId=$org.matrix.msc3819.matrix_device_id\
+             &roomId=$matrix_room_id&lang=$org.matrix.msc2873.client_language\
+             &theme=$org.matrix.msc2873.client_theme&baseUrl=$org.matrix.msc4039.matrix_base_url\
+             &parentUrl=https%3A%2F%2Fapp.example.org%2F%23%2Froom&hideHeader=true&preload=true"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use url::Url;
+
+pub const USER_ID: &str = "$matrix_user_id";
+pub const ROOM_ID: &str = "$matrix_room_id";
+pub const WIDGET_ID: &str = "$matrix_widget_id";
+pub const AVATAR_URL: &str = "$matrix_avatar_url";
+pub const DISPLAY_NAME: &str = "$matrix_display_name";
+pub const LANGUAGE: &str = "$org.matrix.msc2873.client_language";
+pub const CLIENT_THEME: &str = "$org.matrix.msc2873.client_theme";
+pub const CLIENT_ID: &str = "$org.matrix.msc2873.client_id";
+pub const DEVICE_ID: &str = "$org.matrix.msc3819.matrix_device_id";
+pub const HOMESERVER_URL: &str = "$org.matrix.msc4039.matrix_base_url";
+
+/// The values of the template variables that can be used in a widget URL.
+#[derive(Debug, Default, Clone)]
+pub struct QueryProperties {
+    pub widget_id: String,
+    pub avatar_url: String,
+    pub display_name: String,
+    pub user_id: String,
+    pub room_id: String,
+    pub language: String,
+    pub client_theme: String,
+    pub client_id: String,
+    pub device_id: String,
+    pub homeserver_url: String,
+}
+
+/// Replaces the template variables in the given URL with their URL-encoded values.
+pub fn replace_properties(url: &Url, props: &QueryProperties) -> Result<Url, url::ParseError> {
+    let replacements = [
+        (USER_ID, &props.user_id),
+        (ROOM_ID, &props.room_id),
+        (WIDGET_ID, &props.widget_id),
+        (AVATAR_URL, &props.avatar_url),
+        (DISPLAY_NAME, &props.display_name),
+        (LANGUAGE, &props.language),
+        (CLIENT_THEME, &props.client_theme),
+        (CLIENT_ID, &props.client_id),
+        (DEVICE_ID, &props.device_id),
+        (HOMESERVER_URL, &props.homeserver_url),
+    ];
+
+    // The values are encoded, so they can't contain a template variable themselves.
+    let mut url = url.as_str().to_owned();
+    for (placeholder, value) in replacements {
+        url = url.replace(placeholder, &encode(value));
+    }
+
+    Url::parse(&url)
+}
+
+/// Percent-encodes everything but the unreserved characters, like JavaScript's
+/// `encodeURIComponent()`, which is what widgets expect.
+pub fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'!'
+            | b'~'
+            | b'*'
+            | b'\''
+            | b'('
+            | b')' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{encode, replace_properties, QueryProperties};
+
+    #[test]
+    fn encode_values() {
+        assert_eq!(encode("@alice:example.org"), "%40alice%3Aexample.org");
+        assert_eq!(encode("Alice Smith"), "Alice%20Smith");
+        assert_eq!(encode("$matrix_user_id"), "%24matrix_user_id");
+        assert_eq!(encode("😀"), "%F0%9F%98%80");
+    }
+
+    #[test]
+    fn replace_all_properties() {
+        let url = Url::parse(
+            "https://example.org/widget?user=$matrix_user_id&room=$matrix_room_id\
+             &widget=$matrix_widget_id&name=$matrix_display_name&avatar=$matrix_avatar_url\
+             #?lang=$org.matrix.msc2873.client_language&theme=$org.matrix.msc2873.client_theme\
+             &client=$org.matrix.msc2873.client_id&device=$org.matrix.msc3819.matrix_device_id\
+             &hs=$org.matrix.msc4039.matrix_base_url",
+        )
+        .unwrap();
+
+        let props = QueryProperties {
+            widget_id: "widget".to_owned(),
+            avatar_url: "mxc://example.org/avatar".to_owned(),
+            display_name: "Alice Smith".to_owned(),
+            user_id: "@alice:example.org".to_owned(),
+            room_id: "!room:example.org".to_owned(),
+            language: "en-US".to_owned(),
+            client_theme: "dark".to_owned(),
+            client_id: "io.example.client".to_owned(),
+            device_id: "DEVICE".to_owned(),
+            homeserver_url: "https://matrix.example.org/".to_owned(),
+        };
+
+        assert_eq!(
+            replace_properties(&url, &props).unwrap().as_str(),
+            "https://example.org/widget?user=%40alice%3Aexample.org&room=%21room%3Aexample.org\
+             &widget=widget&name=Alice%20Smith&avatar=mxc%3A%2F%2Fexample.org%2Favatar\
+             #?lang=en-US&theme=dark&client=io.example.client&device=DEVICE\
+             &hs=https%3A%2F%2Fmatrix.example.org%2F"
+        );
+    }
+}
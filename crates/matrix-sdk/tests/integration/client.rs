@@ -1,4 +1,7 @@
-use std::{collections::BTreeMap, time::Duration};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
 
 use assert_matches::assert_matches;
 use futures_util::FutureExt;
@@ -6,6 +9,7 @@ use matrix_sdk::{
     config::SyncSettings,
     media::{MediaFormat, MediaRequest, MediaThumbnailSize},
     sync::RoomUpdate,
+    EndpointClass,
 };
 use matrix_sdk_test::{async_test, test_json};
 use ruma::{
@@ -379,3 +383,41 @@ async fn room_update_channel() {
     assert_eq!(updates.unread_notifications.highlight_count, 0);
     assert_eq!(updates.unread_notifications.notification_count, 11);
 }
+
+#[async_test]
+async fn rate_limited_requests_are_held_back() {
+    let (client, server) = no_retry_test_client().await;
+    let mut rate_limit_state = client.subscribe_to_rate_limit_state();
+    assert!(!client.rate_limit_state().is_rate_limited());
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/publicRooms"))
+        .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+            "errcode": "M_LIMIT_EXCEEDED",
+            "error": "Too many requests",
+            "retry_after_ms": 500,
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/publicRooms"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::PUBLIC_ROOMS))
+        .mount(&server)
+        .await;
+
+    client.public_rooms(Some(10), None, None).await.unwrap_err();
+
+    let state = rate_limit_state.next().await.unwrap();
+    assert!(state.is_rate_limited());
+    assert_eq!(state.limited_classes().collect::<Vec<_>>(), [EndpointClass::Other]);
+    assert!(state.retry_at(EndpointClass::Media).is_none());
+
+    // The next request waits for the delay asked by the homeserver.
+    let start = Instant::now();
+    client.public_rooms(Some(10), None, None).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(400));
+
+    assert!(!client.rate_limit_state().is_rate_limited());
+    assert!(!rate_limit_state.next().await.unwrap().is_rate_limited());
+}
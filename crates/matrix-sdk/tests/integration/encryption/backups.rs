@@ -0,0 +1,113 @@
+use assert_matches::assert_matches;
+use futures_util::StreamExt;
+use matrix_sdk::{
+    encryption::backups::{BackupError, BackupState},
+    Error,
+};
+use matrix_sdk_test::{async_test, test_json};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::logged_in_client;
+
+#[async_test]
+async fn create_backup() {
+    let (client, server) = logged_in_client().await;
+    let backups = client.encryption().backups();
+
+    assert_eq!(backups.state(), BackupState::Unknown);
+    assert!(!backups.are_enabled().await);
+
+    let mut state_stream = backups.state_stream();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/room_keys/version"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "version": "1" })))
+        .expect(1)
+        .named("create_backup_version")
+        .mount(&server)
+        .await;
+
+    backups.create().await.unwrap();
+
+    // The subscriber only yields the latest state.
+    assert_eq!(state_stream.next().await, Some(BackupState::Enabled));
+    assert_eq!(backups.state(), BackupState::Enabled);
+    assert!(backups.are_enabled().await);
+
+    server.verify().await;
+}
+
+#[async_test]
+async fn enable_without_backup_on_server() {
+    let (client, server) = logged_in_client().await;
+    let backups = client.encryption().backups();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/room_keys/version"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(&*test_json::NOT_FOUND))
+        .mount(&server)
+        .await;
+
+    assert!(!backups.exists_on_server().await.unwrap());
+    assert_matches!(backups.enable().await, Err(Error::Backup(BackupError::NoBackupOnServer)));
+    assert_eq!(backups.state(), BackupState::Unknown);
+}
+
+#[async_test]
+async fn enable_without_recovery_key() {
+    let (client, server) = logged_in_client().await;
+    let backups = client.encryption().backups();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/room_keys/version"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "algorithm": "m.megolm_backup.v1.curve25519-aes-sha2",
+            "auth_data": {
+                "public_key": "XjhWTCjW7l59pbfx9tlCBQolfnIQWARoKOzjTOPSlWM",
+                "signatures": {},
+            },
+            "count": 0,
+            "etag": "0",
+            "version": "1",
+        })))
+        .mount(&server)
+        .await;
+
+    assert!(backups.exists_on_server().await.unwrap());
+    assert_matches!(backups.enable().await, Err(Error::Backup(BackupError::MissingRecoveryKey)));
+    assert_eq!(backups.state(), BackupState::Unknown);
+}
+
+#[async_test]
+async fn delete_backup() {
+    let (client, server) = logged_in_client().await;
+    let backups = client.encryption().backups();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/room_keys/version"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "version": "1" })))
+        .mount(&server)
+        .await;
+
+    backups.create().await.unwrap();
+    assert!(backups.are_enabled().await);
+
+    Mock::given(method("DELETE"))
+        .and(path("/_matrix/client/r0/room_keys/version/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(1)
+        .named("delete_backup_version")
+        .mount(&server)
+        .await;
+
+    backups.delete().await.unwrap();
+
+    assert!(!backups.are_enabled().await);
+    assert_eq!(backups.state(), BackupState::Unknown);
+
+    server.verify().await;
+}
@@ -0,0 +1,106 @@
+use std::sync::{Arc, Mutex};
+
+use assert_matches::assert_matches;
+use matrix_sdk::encryption::dehydrated_devices::DehydratedDeviceError;
+use matrix_sdk_test::{async_test, test_json};
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, MockServer, Request, ResponseTemplate,
+};
+
+use super::secret_storage::mock_account_data;
+use crate::logged_in_client;
+
+const DEHYDRATED_DEVICE_PATH: &str = r"/org\.matrix\.msc3814\.v1/dehydrated_device$";
+
+/// Mount mocks on the given server that store the dehydrated device in
+/// memory, like a homeserver would.
+async fn mock_dehydrated_device(server: &MockServer) {
+    let dehydrated_device = Arc::new(Mutex::new(None::<JsonValue>));
+
+    Mock::given(method("PUT"))
+        .and(path_regex(DEHYDRATED_DEVICE_PATH))
+        .respond_with({
+            let dehydrated_device = dehydrated_device.clone();
+            move |request: &Request| {
+                let body: JsonValue = request.body_json().unwrap();
+                let device_id = body["device_id"].clone();
+                *dehydrated_device.lock().unwrap() = Some(body);
+                ResponseTemplate::new(200).set_body_json(json!({ "device_id": device_id }))
+            }
+        })
+        .mount(server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(DEHYDRATED_DEVICE_PATH))
+        .respond_with(move |_: &Request| match &*dehydrated_device.lock().unwrap() {
+            Some(device) => ResponseTemplate::new(200).set_body_json(json!({
+                "device_id": device["device_id"],
+                "device_data": device["device_data"],
+            })),
+            None => ResponseTemplate::new(404).set_body_json(&*test_json::NOT_FOUND),
+        })
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"/org\.matrix\.msc3814\.v1/dehydrated_device/.*/events$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "events": [] })))
+        .mount(server)
+        .await;
+}
+
+/// Mount the mocks needed to bootstrap cross-signing, the device keys of the
+/// dehydrated device are signed with our self-signing key.
+async fn mock_cross_signing(server: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path_regex(r"/keys/(device_signing|signatures)/upload$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .mount(server)
+        .await;
+}
+
+#[async_test]
+async fn rehydrate_without_dehydrated_device() {
+    let (client, server) = logged_in_client().await;
+    mock_account_data(&server).await;
+    mock_dehydrated_device(&server).await;
+
+    let secret_store =
+        client.encryption().secret_storage().create_secret_store(None).await.unwrap();
+
+    let room_key_count =
+        client.encryption().dehydrated_devices().rehydrate(&secret_store).await.unwrap();
+    assert_eq!(room_key_count, None);
+}
+
+#[async_test]
+async fn create_and_rehydrate_dehydrated_device() {
+    let (client, server) = logged_in_client().await;
+    mock_account_data(&server).await;
+    mock_dehydrated_device(&server).await;
+    mock_cross_signing(&server).await;
+
+    client.encryption().bootstrap_cross_signing(None).await.unwrap();
+
+    let secret_storage = client.encryption().secret_storage();
+    let secret_store = secret_storage.create_secret_store(None).await.unwrap();
+    let dehydrated_devices = client.encryption().dehydrated_devices();
+
+    let device_id = dehydrated_devices.create(&secret_store).await.unwrap();
+    assert_ne!(device_id, client.device_id().unwrap());
+
+    // The pickle key was stored in the secret storage, and is reused.
+    assert!(secret_store.get_secret("org.matrix.msc3814".into()).await.unwrap().is_some());
+    let room_key_count = dehydrated_devices.rehydrate(&secret_store).await.unwrap();
+    assert_eq!(room_key_count, Some(0));
+
+    // Another secret store doesn't have the pickle key.
+    let other_secret_store = secret_storage.create_secret_store(None).await.unwrap();
+    assert_matches!(
+        dehydrated_devices.rehydrate(&other_secret_store).await,
+        Err(DehydratedDeviceError::MissingPickleKey)
+    );
+}
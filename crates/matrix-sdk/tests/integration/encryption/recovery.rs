@@ -0,0 +1,141 @@
+use assert_matches::assert_matches;
+use matrix_sdk::{
+    encryption::{
+        recovery::{RecoveryError, RecoveryState},
+        secret_storage::SecretStorageError,
+    },
+    Client,
+};
+use matrix_sdk_test::{async_test, test_json};
+use ruma::events::secret::request::SecretName;
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use super::secret_storage::mock_account_data;
+use crate::logged_in_client;
+
+/// Mount the mocks needed to enable recovery for a new account.
+async fn mock_enable_recovery(server: &MockServer) {
+    mock_account_data(server).await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"/keys/device_signing/upload$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(1)
+        .named("upload_signing_keys")
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"/keys/signatures/upload$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(1)
+        .named("upload_signatures")
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/room_keys/version"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "version": "1" })))
+        .expect(1)
+        .named("create_backup_version")
+        .mount(server)
+        .await;
+}
+
+async fn enabled_client() -> (Client, MockServer, String) {
+    let (client, server) = logged_in_client().await;
+    mock_enable_recovery(&server).await;
+
+    let recovery_key = client.encryption().recovery().enable(None, None).await.unwrap();
+
+    (client, server, recovery_key)
+}
+
+#[async_test]
+async fn enable_recovery() {
+    let (client, server) = logged_in_client().await;
+    mock_enable_recovery(&server).await;
+
+    let recovery = client.encryption().recovery();
+    assert_eq!(recovery.state(), RecoveryState::Unknown);
+
+    let recovery_key = recovery.enable(Some("It's a secret to everybody"), None).await.unwrap();
+
+    assert_eq!(recovery.state(), RecoveryState::Enabled);
+    assert!(client.encryption().backups().are_enabled().await);
+
+    let status = client.encryption().cross_signing_status().await.unwrap();
+    assert!(status.has_master && status.has_self_signing && status.has_user_signing);
+
+    // The secrets can be retrieved with the recovery key or the passphrase.
+    let secret_storage = client.encryption().secret_storage();
+    for key in [recovery_key.as_str(), "It's a secret to everybody"] {
+        let secret_store = secret_storage.open_secret_store(key).await.unwrap();
+        assert!(secret_store.get_secret(SecretName::RecoveryKey).await.unwrap().is_some());
+        assert!(secret_store
+            .get_secret(SecretName::CrossSigningMasterKey)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    recovery.recover("It's a secret to everybody").await.unwrap();
+    assert_eq!(recovery.state(), RecoveryState::Enabled);
+
+    server.verify().await;
+}
+
+#[async_test]
+async fn recover_with_wrong_key() {
+    let (client, _server, _) = enabled_client().await;
+    let recovery = client.encryption().recovery();
+
+    assert_matches!(
+        recovery.recover("wrong passphrase").await,
+        Err(RecoveryError::SecretStorage(SecretStorageError::SecretStorageKey(_)))
+    );
+    assert_eq!(recovery.state(), RecoveryState::Enabled);
+}
+
+#[async_test]
+async fn reset_recovery_key() {
+    let (client, _server, recovery_key) = enabled_client().await;
+    let recovery = client.encryption().recovery();
+
+    let new_recovery_key = recovery.reset_key(None).await.unwrap();
+    assert_ne!(new_recovery_key, recovery_key);
+    assert_eq!(recovery.state(), RecoveryState::Enabled);
+
+    assert_matches!(recovery.recover(&recovery_key).await, Err(RecoveryError::SecretStorage(_)));
+    recovery.recover(&new_recovery_key).await.unwrap();
+}
+
+#[async_test]
+async fn disable_recovery() {
+    let (client, server, recovery_key) = enabled_client().await;
+    let recovery = client.encryption().recovery();
+
+    Mock::given(method("DELETE"))
+        .and(path("/_matrix/client/r0/room_keys/version/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(1)
+        .named("delete_backup_version")
+        .mount(&server)
+        .await;
+
+    recovery.disable().await.unwrap();
+
+    assert_eq!(recovery.state(), RecoveryState::Disabled);
+    assert!(!client.encryption().backups().are_enabled().await);
+    assert!(!client.encryption().secret_storage().is_enabled().await.unwrap());
+    assert_matches!(
+        recovery.recover(&recovery_key).await,
+        Err(RecoveryError::SecretStorage(SecretStorageError::NotEnabled))
+    );
+
+    server.verify().await;
+}
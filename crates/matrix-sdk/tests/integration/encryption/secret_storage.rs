@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use assert_matches::assert_matches;
+use matrix_sdk::encryption::secret_storage::SecretStorageError;
+use matrix_sdk_test::{async_test, test_json};
+use ruma::events::secret::request::SecretName;
+use serde_json::Value as JsonValue;
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, MockServer, Request, ResponseTemplate,
+};
+
+use crate::logged_in_client;
+
+const ACCOUNT_DATA_PATH: &str = r"^/_matrix/client/r0/user/.*/account_data/";
+
+/// Mount mocks on the given server that store the global account data in
+/// memory, like a homeserver would.
+pub(super) async fn mock_account_data(server: &MockServer) {
+    let account_data = Arc::new(Mutex::new(HashMap::<String, JsonValue>::new()));
+
+    fn event_type(request: &Request) -> String {
+        request.url.path_segments().unwrap().last().unwrap().to_owned()
+    }
+
+    Mock::given(method("PUT"))
+        .and(path_regex(ACCOUNT_DATA_PATH))
+        .respond_with({
+            let account_data = account_data.clone();
+            move |request: &Request| {
+                let content = request.body_json().unwrap();
+                account_data.lock().unwrap().insert(event_type(request), content);
+                ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY)
+            }
+        })
+        .mount(server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(ACCOUNT_DATA_PATH))
+        .respond_with(move |request: &Request| {
+            match account_data.lock().unwrap().get(&event_type(request)) {
+                Some(content) => ResponseTemplate::new(200).set_body_json(content),
+                None => ResponseTemplate::new(404).set_body_json(&*test_json::NOT_FOUND),
+            }
+        })
+        .mount(server)
+        .await;
+}
+
+#[async_test]
+async fn secret_storage_not_enabled() {
+    let (client, server) = logged_in_client().await;
+    mock_account_data(&server).await;
+
+    let secret_storage = client.encryption().secret_storage();
+
+    assert!(!secret_storage.is_enabled().await.unwrap());
+    assert_matches!(
+        secret_storage.open_secret_store("It's a secret to everybody").await,
+        Err(SecretStorageError::NotEnabled)
+    );
+}
+
+#[async_test]
+async fn create_and_open_secret_store() {
+    let (client, server) = logged_in_client().await;
+    mock_account_data(&server).await;
+
+    let secret_storage = client.encryption().secret_storage();
+
+    let secret_store =
+        secret_storage.create_secret_store(Some("It's a secret to everybody")).await.unwrap();
+    assert!(secret_storage.is_enabled().await.unwrap());
+    assert_eq!(
+        secret_storage.fetch_default_key_id().await.unwrap().as_deref(),
+        Some(secret_store.key_id())
+    );
+
+    secret_store.put_secret(SecretName::CrossSigningMasterKey, "master key").await.unwrap();
+    assert_eq!(
+        secret_store.get_secret(SecretName::CrossSigningMasterKey).await.unwrap().as_deref(),
+        Some("master key")
+    );
+    assert_eq!(secret_store.get_secret(SecretName::RecoveryKey).await.unwrap(), None);
+
+    // The secret store can be opened with the passphrase.
+    let reopened = secret_storage.open_secret_store("It's a secret to everybody").await.unwrap();
+    assert_eq!(
+        reopened.get_secret(SecretName::CrossSigningMasterKey).await.unwrap().as_deref(),
+        Some("master key")
+    );
+
+    // Or with the base58 encoded secret storage key.
+    let reopened =
+        secret_storage.open_secret_store(&secret_store.secret_storage_key()).await.unwrap();
+    assert_eq!(reopened.key_id(), secret_store.key_id());
+
+    assert_matches!(
+        secret_storage.open_secret_store("wrong passphrase").await,
+        Err(SecretStorageError::SecretStorageKey(_))
+    );
+
+    // Secrets stored with a previous key can't be read with a new one.
+    let new_secret_store = secret_storage.create_secret_store(None).await.unwrap();
+    assert_ne!(new_secret_store.key_id(), secret_store.key_id());
+    assert_eq!(new_secret_store.get_secret(SecretName::CrossSigningMasterKey).await.unwrap(), None);
+}
@@ -0,0 +1,175 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use matrix_sdk::{
+    config::SyncSettings,
+    crypto::{OlmMachine, OutgoingRequests},
+    encryption::UnwedgingPolicy,
+};
+use matrix_sdk_test::{
+    async_test, response_from_file, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder,
+};
+use ruma::{
+    api::{
+        client::keys::{claim_keys, get_keys},
+        IncomingResponse,
+    },
+    device_id, room_id, user_id, TransactionId,
+};
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{body_partial_json, method, path_regex},
+    Mock, Request, ResponseTemplate,
+};
+
+use crate::{logged_in_client, mock_sync};
+
+#[async_test]
+async fn unwedge_device() {
+    let (client, server) = logged_in_client().await;
+    let own_user_id = user_id!("@example:localhost");
+    let own_device_id = device_id!("DEVICEID");
+    let bob = OlmMachine::new(user_id!("@bob:localhost"), device_id!("BOBDEVICE")).await;
+
+    // Every Olm session is old enough to be replaced.
+    let policy = UnwedgingPolicy { enabled: true, min_interval: Duration::ZERO };
+    client.encryption().set_unwedging_policy(policy).await.unwrap();
+
+    let bob_keys = bob
+        .outgoing_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .find_map(|r| match r.request() {
+            OutgoingRequests::KeysUpload(request) => Some(request.clone()),
+            _ => None,
+        })
+        .unwrap();
+    let (bob_key_id, bob_one_time_key) = bob_keys.one_time_keys.into_iter().next().unwrap();
+
+    // Remember the keys we upload, so Bob can create an Olm session with us.
+    let uploaded_keys = Arc::new(Mutex::new(None::<JsonValue>));
+    Mock::given(method("POST"))
+        .and(path_regex(r"/keys/upload$"))
+        .respond_with({
+            let uploaded_keys = uploaded_keys.clone();
+            move |request: &Request| {
+                *uploaded_keys.lock().unwrap() = Some(request.body_json().unwrap());
+                ResponseTemplate::new(200).set_body_json(json!({
+                    "one_time_key_counts": { "signed_curve25519": 50 }
+                }))
+            }
+        })
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"/keys/query$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_keys": { "@bob:localhost": { "BOBDEVICE": bob_keys.device_keys.unwrap() } },
+            "failures": {},
+        })))
+        .mount(&server)
+        .await;
+
+    // The one-time key is only claimed to unwedge Bob's device.
+    Mock::given(method("POST"))
+        .and(path_regex(r"/keys/claim$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "one_time_keys": {
+                "@bob:localhost": { "BOBDEVICE": { bob_key_id.to_string(): bob_one_time_key } }
+            },
+            "failures": {},
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The `m.dummy` event sent over the new Olm session.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"/sendToDevice/m\.room\.encrypted/"))
+        .and(body_partial_json(json!({ "messages": { "@bob:localhost": { "BOBDEVICE": {} } } })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // Share an encrypted room with Bob, so we track his devices.
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id!("!test:localhost"))
+            .add_state_event(StateTestEvent::Encryption)
+            .add_state_event(StateTestEvent::Custom(json!({
+                "content": { "membership": "join" },
+                "event_id": "$bob_join",
+                "origin_server_ts": 152037280,
+                "sender": "@bob:localhost",
+                "state_key": "@bob:localhost",
+                "type": "m.room.member",
+            }))),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let response = client.sync_once(SyncSettings::new()).await.unwrap();
+    let sync_token = response.next_batch;
+
+    assert!(client
+        .encryption()
+        .get_device(bob.user_id(), bob.device_id())
+        .await
+        .unwrap()
+        .is_some());
+
+    // Bob creates an Olm session with us, and sends us two messages over it.
+    let uploaded_keys = uploaded_keys.lock().unwrap().take().unwrap();
+    let curve_key = uploaded_keys["device_keys"]["keys"]["curve25519:DEVICEID"].clone();
+    let (key_id, one_time_key) =
+        uploaded_keys["one_time_keys"].as_object().unwrap().iter().next().unwrap();
+
+    let keys_query = get_keys::v3::Response::try_from_http_response(response_from_file(&json!({
+        "device_keys": { own_user_id: { own_device_id: uploaded_keys["device_keys"] } },
+        "failures": {},
+    })))
+    .unwrap();
+    bob.mark_request_as_sent(&TransactionId::new(), &keys_query).await.unwrap();
+
+    let keys_claim = claim_keys::v3::Response::try_from_http_response(response_from_file(&json!({
+        "one_time_keys": { own_user_id: { own_device_id: { key_id: one_time_key } } },
+        "failures": {},
+    })))
+    .unwrap();
+    bob.mark_request_as_sent(&TransactionId::new(), &keys_claim).await.unwrap();
+
+    let own_device = bob.get_device(own_user_id, own_device_id, None).await.unwrap().unwrap();
+    let mut messages = Vec::new();
+    for _ in 0..2 {
+        let content = own_device.encrypt_event_raw("m.dummy", json!({})).await.unwrap();
+        messages.push(serde_json::to_value(content).unwrap());
+    }
+
+    // Flip a character of the MAC at the end of the second message, so it can't
+    // be decrypted with the session created by the first one.
+    let message = &mut messages[1]["ciphertext"][curve_key.as_str().unwrap()];
+    let mut body = message["body"].as_str().unwrap().to_owned();
+    let position = body.len() - 5;
+    let replacement = if &body[position..=position] == "A" { "B" } else { "A" };
+    body.replace_range(position..=position, replacement);
+    message["body"] = body.into();
+
+    let mut sync = SyncResponseBuilder::new().build_json_sync_response();
+    sync["to_device"]["events"] = messages
+        .into_iter()
+        .map(|content| {
+            json!({ "content": content, "sender": "@bob:localhost", "type": "m.room.encrypted" })
+        })
+        .collect();
+    mock_sync(&server, sync, Some(sync_token.clone())).await;
+    client.sync_once(SyncSettings::new().token(sync_token)).await.unwrap();
+
+    // Bob's device got unwedged during the sync.
+    let stats = client.encryption().wedged_devices_stats().await.remove(bob.user_id()).unwrap();
+    assert_eq!(stats.wedged, 1);
+    assert_eq!(stats.unwedged, 1);
+    assert!(stats.pending.is_empty());
+}
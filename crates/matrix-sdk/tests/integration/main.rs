@@ -16,9 +16,16 @@ use wiremock::{
 };
 
 mod client;
+#[cfg(feature = "e2e-encryption")]
+mod encryption;
 mod matrix_auth;
+#[cfg(feature = "experimental-oidc")]
+mod oidc;
 mod refresh_token;
 mod room;
+mod send_queue;
+#[cfg(feature = "experimental-widget-api")]
+mod widget;
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 #[ctor::ctor]
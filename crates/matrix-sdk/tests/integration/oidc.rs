@@ -0,0 +1,398 @@
+use assert_matches::assert_matches;
+use futures_util::StreamExt;
+use matrix_sdk::{
+    oidc::{
+        ApplicationType, ClientMetadata, OidcError, OidcSession, OidcSessionTokens, UserSession,
+    },
+    AuthApi, AuthSession, Client, Error,
+};
+use matrix_sdk_base::SessionMeta;
+use matrix_sdk_test::async_test;
+use ruma::{device_id, user_id};
+use serde_json::json;
+use url::Url;
+use wiremock::{
+    matchers::{body_partial_json, body_string_contains, header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::no_retry_test_client;
+
+const CLIENT_ID: &str = "01GYCFCB0VYD8ZHH9ZNNR0RFJN";
+
+fn redirect_uri() -> Url {
+    Url::parse("http://127.0.0.1:6789/callback").unwrap()
+}
+
+fn client_metadata() -> ClientMetadata {
+    let mut metadata = ClientMetadata::new(ApplicationType::Native, vec![redirect_uri()]);
+    metadata.client_name = Some("matrix-rust-sdk tests".to_owned());
+    metadata
+}
+
+/// Mount a mock issuer on the given server, advertised by the `.well-known` of
+/// the homeserver, and return its URL.
+async fn mock_issuer(server: &MockServer) -> String {
+    let issuer = format!("{}/", server.uri());
+
+    Mock::given(method("GET"))
+        .and(path("/.well-known/matrix/client"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "m.homeserver": { "base_url": server.uri() },
+            "org.matrix.msc2965.authentication": { "issuer": issuer },
+        })))
+        .mount(server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/.well-known/openid-configuration"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{issuer}oauth2/authorize"),
+            "token_endpoint": format!("{issuer}oauth2/token"),
+            "registration_endpoint": format!("{issuer}oauth2/registration"),
+            "revocation_endpoint": format!("{issuer}oauth2/revoke"),
+            "response_types_supported": ["code"],
+            "grant_types_supported": ["authorization_code", "refresh_token"],
+            "code_challenge_methods_supported": ["plain", "S256"],
+        })))
+        .mount(server)
+        .await;
+
+    issuer
+}
+
+fn session(issuer: String) -> OidcSession {
+    OidcSession {
+        issuer,
+        client_id: CLIENT_ID.to_owned(),
+        metadata: client_metadata(),
+        user: UserSession {
+            meta: SessionMeta {
+                user_id: user_id!("@example:localhost").to_owned(),
+                device_id: device_id!("DEVICEID").to_owned(),
+            },
+            tokens: OidcSessionTokens {
+                access_token: "1234".to_owned(),
+                refresh_token: Some("abcd".to_owned()),
+            },
+        },
+    }
+}
+
+async fn registered_client() -> (Client, MockServer) {
+    let (client, server) = no_retry_test_client().await;
+    let issuer = mock_issuer(&server).await;
+
+    client.oidc().restore_registered_client(issuer, CLIENT_ID.to_owned(), client_metadata());
+
+    (client, server)
+}
+
+#[async_test]
+async fn register_client() {
+    let (client, server) = no_retry_test_client().await;
+    let issuer = mock_issuer(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/oauth2/registration"))
+        .and(body_partial_json(json!({
+            "application_type": "native",
+            "redirect_uris": [redirect_uri().as_str()],
+            "client_name": "matrix-rust-sdk tests",
+            "token_endpoint_auth_method": "none",
+            "grant_types": ["authorization_code", "refresh_token"],
+        })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+            "client_id": CLIENT_ID,
+            "client_id_issued_at": 1_690_000_000,
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let oidc = client.oidc();
+    let response = oidc.register_client(client_metadata(), None).await.unwrap();
+
+    assert_eq!(response.client_id, CLIENT_ID);
+    assert_eq!(oidc.issuer(), Some(issuer.as_str()));
+    assert_eq!(oidc.client_id(), Some(CLIENT_ID));
+    assert_eq!(oidc.client_metadata(), Some(&client_metadata()));
+    assert!(!client.logged_in());
+}
+
+#[async_test]
+async fn no_issuer() {
+    let (client, server) = no_retry_test_client().await;
+
+    Mock::given(method("GET"))
+        .and(path("/.well-known/matrix/client"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "m.homeserver": { "base_url": server.uri() },
+        })))
+        .mount(&server)
+        .await;
+
+    let error = client.oidc().register_client(client_metadata(), None).await.unwrap_err();
+    assert_matches!(error, OidcError::NotSupported);
+}
+
+#[async_test]
+async fn login() {
+    let (client, server) = registered_client().await;
+    let oidc = client.oidc();
+
+    let authorization_data = oidc
+        .url_for_authorization(redirect_uri(), Some(device_id!("DEVICEID").to_owned()))
+        .await
+        .unwrap();
+
+    let url = authorization_data.url;
+    assert_eq!(url.path(), "/oauth2/authorize");
+
+    let query: Vec<_> = url.query_pairs().into_owned().collect();
+    let param = |name: &str| {
+        query.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str()).unwrap()
+    };
+    assert_eq!(param("response_type"), "code");
+    assert_eq!(param("client_id"), CLIENT_ID);
+    assert_eq!(param("redirect_uri"), redirect_uri().as_str());
+    assert_eq!(param("state"), authorization_data.state);
+    assert_eq!(param("code_challenge_method"), "S256");
+    assert_eq!(
+        param("scope"),
+        "urn:matrix:org.matrix.msc2967.client:api:* \
+         urn:matrix:org.matrix.msc2967.client:device:DEVICEID"
+    );
+
+    Mock::given(method("POST"))
+        .and(path("/oauth2/token"))
+        .and(body_string_contains("grant_type=authorization_code"))
+        .and(body_string_contains("code=AUTHCODE"))
+        .and(body_string_contains("code_verifier="))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "1234",
+            "token_type": "Bearer",
+            "expires_in": 300,
+            "refresh_token": "abcd",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "user_id": "@example:localhost",
+            "device_id": "DEVICEID",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut callback_url = redirect_uri();
+    callback_url
+        .query_pairs_mut()
+        .append_pair("state", &authorization_data.state)
+        .append_pair("code", "AUTHCODE");
+    oidc.finish_authorization(&callback_url).await.unwrap();
+
+    assert!(client.logged_in());
+    assert_eq!(client.user_id(), Some(user_id!("@example:localhost")));
+    assert_eq!(client.device_id(), Some(device_id!("DEVICEID")));
+    assert_eq!(client.access_token().as_deref(), Some("1234"));
+    assert_eq!(oidc.refresh_token().as_deref(), Some("abcd"));
+
+    assert_matches!(client.auth_api(), Some(AuthApi::Oidc(_)));
+    assert_matches!(client.session(), Some(AuthSession::Oidc(_)));
+
+    // The same authorization can't be finished twice.
+    let error = oidc.finish_authorization(&callback_url).await.unwrap_err();
+    assert_matches!(error, Error::Oidc(OidcError::InvalidState));
+}
+
+#[async_test]
+async fn login_invalid_redirect_uri() {
+    let (client, _server) = registered_client().await;
+
+    let error = client
+        .oidc()
+        .url_for_authorization(Url::parse("http://127.0.0.1:1234/elsewhere").unwrap(), None)
+        .await
+        .unwrap_err();
+    assert_matches!(error, OidcError::InvalidRedirectUri);
+}
+
+#[async_test]
+async fn login_denied() {
+    let (client, _server) = registered_client().await;
+    let oidc = client.oidc();
+
+    let authorization_data = oidc.url_for_authorization(redirect_uri(), None).await.unwrap();
+
+    let mut callback_url = redirect_uri();
+    callback_url
+        .query_pairs_mut()
+        .append_pair("state", &authorization_data.state)
+        .append_pair("error", "access_denied");
+
+    let error = oidc.finish_authorization(&callback_url).await.unwrap_err();
+    assert_matches!(error, Error::Oidc(OidcError::OAuth(error)) => {
+        assert_eq!(error.error, "access_denied");
+    });
+    assert!(!client.logged_in());
+}
+
+#[async_test]
+async fn login_aborted() {
+    let (client, _server) = registered_client().await;
+    let oidc = client.oidc();
+
+    let authorization_data = oidc.url_for_authorization(redirect_uri(), None).await.unwrap();
+    oidc.abort_authorization(&authorization_data.state);
+
+    let mut callback_url = redirect_uri();
+    callback_url
+        .query_pairs_mut()
+        .append_pair("state", &authorization_data.state)
+        .append_pair("code", "AUTHCODE");
+
+    let error = oidc.finish_authorization(&callback_url).await.unwrap_err();
+    assert_matches!(error, Error::Oidc(OidcError::InvalidState));
+}
+
+#[async_test]
+async fn restore_session() {
+    let (client, server) = no_retry_test_client().await;
+    let issuer = mock_issuer(&server).await;
+
+    client.restore_session(session(issuer.clone())).await.unwrap();
+
+    assert!(client.logged_in());
+    assert_eq!(client.access_token().as_deref(), Some("1234"));
+    assert_matches!(client.auth_api(), Some(AuthApi::Oidc(_)));
+
+    let restored = client.oidc().full_session().unwrap();
+    assert_eq!(restored.issuer, issuer);
+    assert_eq!(restored.client_id, CLIENT_ID);
+    assert_eq!(restored.metadata, client_metadata());
+    assert_eq!(restored.user.meta.user_id, user_id!("@example:localhost"));
+    assert_eq!(restored.user.tokens.refresh_token.as_deref(), Some("abcd"));
+}
+
+#[async_test]
+async fn refresh_access_token() {
+    let (client, server) = no_retry_test_client().await;
+    let issuer = mock_issuer(&server).await;
+    client.restore_session(session(issuer)).await.unwrap();
+
+    let oidc = client.oidc();
+    let mut tokens_stream = oidc.session_tokens_stream().unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/oauth2/token"))
+        .and(body_string_contains("grant_type=refresh_token"))
+        .and(body_string_contains("refresh_token=abcd"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "5678",
+            "token_type": "Bearer",
+            "expires_in": 300,
+            "refresh_token": "efgh",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client.refresh_access_token().await.unwrap();
+
+    let tokens = tokens_stream.next().await.unwrap();
+    assert_eq!(tokens.access_token, "5678");
+    assert_eq!(tokens.refresh_token.as_deref(), Some("efgh"));
+    assert_eq!(client.access_token().as_deref(), Some("5678"));
+}
+
+#[async_test]
+async fn refresh_access_token_error() {
+    let (client, server) = no_retry_test_client().await;
+    let issuer = mock_issuer(&server).await;
+    client.restore_session(session(issuer)).await.unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/oauth2/token"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "error": "invalid_grant",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client.refresh_access_token().await.unwrap_err();
+
+    // The tokens are untouched.
+    assert_eq!(client.access_token().as_deref(), Some("1234"));
+}
+
+#[async_test]
+async fn logout() {
+    let (client, server) = no_retry_test_client().await;
+    let issuer = mock_issuer(&server).await;
+    client.restore_session(session(issuer)).await.unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/oauth2/revoke"))
+        .and(body_string_contains("token=1234"))
+        .and(body_string_contains("token_type_hint=access_token"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/oauth2/revoke"))
+        .and(body_string_contains("token=abcd"))
+        .and(body_string_contains("token_type_hint=refresh_token"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let oidc = client.oidc();
+    oidc.logout().await.unwrap();
+
+    assert_eq!(oidc.access_token(), None);
+    assert!(oidc.full_session().is_none());
+    assert!(!client.logged_in());
+    assert_eq!(client.user_id(), None);
+}
+
+#[async_test]
+async fn logout_forgets_the_session_when_revocation_fails() {
+    let (client, server) = no_retry_test_client().await;
+    let issuer = mock_issuer(&server).await;
+    client.restore_session(session(issuer)).await.unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/oauth2/revoke"))
+        .and(body_string_contains("token=abcd"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The access token is still revoked.
+    Mock::given(method("POST"))
+        .and(path("/oauth2/revoke"))
+        .and(body_string_contains("token=1234"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let oidc = client.oidc();
+    assert_matches!(oidc.logout().await, Err(OidcError::UnexpectedStatus(500)));
+
+    assert_eq!(oidc.access_token(), None);
+    assert!(oidc.full_session().is_none());
+    assert!(!client.logged_in());
+}
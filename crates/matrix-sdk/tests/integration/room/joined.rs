@@ -147,6 +147,61 @@ async fn kick_user() {
     room.kick_user(user, None).await.unwrap();
 }
 
+#[async_test]
+async fn knock_requests() {
+    let (client, server) = synced_client().await;
+    let knocker = user_id!("@knocker:localhost");
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/members"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [{
+                "content": {
+                    "displayname": "Knocker",
+                    "membership": "knock",
+                    "reason": "Let me in",
+                },
+                "event_id": "$knock:localhost",
+                "origin_server_ts": 1432735824653u64,
+                "room_id": *test_json::DEFAULT_SYNC_ROOM_ID,
+                "sender": knocker,
+                "state_key": knocker,
+                "type": "m.room.member",
+            }],
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/invite$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({ "user_id": knocker })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/kick$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({ "user_id": knocker, "reason": "Not today" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.get_joined_room(&test_json::DEFAULT_SYNC_ROOM_ID).unwrap();
+
+    let requests = room.knock_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].user_id(), knocker);
+    assert_eq!(requests[0].display_name(), Some("Knocker"));
+
+    room.accept_knock_request(knocker).await.unwrap();
+    room.deny_knock_request(knocker, Some("Not today")).await.unwrap();
+}
+
 #[async_test]
 async fn send_single_receipt() {
     let (client, server) = logged_in_client().await;
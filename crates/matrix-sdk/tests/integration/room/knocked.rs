@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use matrix_sdk::config::SyncSettings;
+use matrix_sdk_test::{async_test, test_json, KnockedRoomBuilder, SyncResponseBuilder};
+use ruma::{room_id, server_name};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path_regex, query_param},
+    Mock, ResponseTemplate,
+};
+
+use crate::{logged_in_client, mock_sync};
+
+#[async_test]
+async fn knock_room() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!knock:localhost");
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"/knock/"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("server_name", "localhost"))
+        .and(body_partial_json(json!({ "reason": "Let me in" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_id })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client
+        .knock(
+            room_id.into(),
+            Some("Let me in".to_owned()),
+            &[server_name!("localhost").to_owned()],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(room.room_id(), room_id);
+    assert!(client.get_knocked_room(room_id).is_some());
+    assert_eq!(client.knocked_rooms().len(), 1);
+}
+
+#[async_test]
+async fn knocked_room_sync() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!knock:localhost");
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_knocked_room(KnockedRoomBuilder::new(room_id));
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+    let response = client.sync_once(sync_settings).await.unwrap();
+    assert!(response.rooms.knock.contains_key(room_id));
+
+    let room = client.get_knocked_room(room_id).unwrap();
+    assert!(client.get_joined_room(room_id).is_none());
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/leave$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // Withdrawing the knock leaves the room.
+    room.cancel_knock().await.unwrap();
+    assert!(client.get_knocked_room(room_id).is_none());
+    assert!(client.get_left_room(room_id).is_some());
+}
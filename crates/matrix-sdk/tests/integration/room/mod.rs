@@ -1,3 +1,4 @@
 mod common;
 mod joined;
+mod knocked;
 mod left;
@@ -0,0 +1,193 @@
+use assert_matches::assert_matches;
+use matrix_sdk::{
+    config::SyncSettings,
+    send_queue::{RoomSendQueueError, RoomSendQueueUpdate},
+    Error,
+};
+use matrix_sdk_test::{async_test, test_json};
+use ruma::{event_id, events::room::message::RoomMessageEventContent};
+use serde_json::json;
+use wiremock::{
+    matchers::{header, method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+use crate::{logged_in_client, mock_encryption_state, mock_sync, synced_client};
+
+#[async_test]
+async fn queued_event_is_sent() {
+    let (client, server) = synced_client().await;
+    mock_encryption_state(&server, false).await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EVENT_ID))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.get_joined_room(&test_json::DEFAULT_SYNC_ROOM_ID).unwrap();
+    let send_queue = room.send_queue();
+    let mut updates = send_queue.subscribe();
+
+    let txn_id =
+        send_queue.send(RoomMessageEventContent::text_plain("Hello").into()).await.unwrap();
+
+    assert_matches!(
+        updates.recv().await.unwrap(),
+        RoomSendQueueUpdate::NewLocalEvent(request) => {
+            assert_eq!(request.transaction_id, txn_id);
+        }
+    );
+    assert_matches!(
+        updates.recv().await.unwrap(),
+        RoomSendQueueUpdate::SentEvent { transaction_id, event_id } => {
+            assert_eq!(transaction_id, txn_id);
+            assert_eq!(event_id, event_id!("$h29iv0s8:example.com"));
+        }
+    );
+
+    assert!(send_queue.local_echoes().await.unwrap().is_empty());
+}
+
+#[async_test]
+async fn failed_event_is_retried_when_unwedged() {
+    let (client, server) = synced_client().await;
+    mock_encryption_state(&server, false).await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "You shall not pass",
+        })))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EVENT_ID))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let room = client.get_joined_room(&test_json::DEFAULT_SYNC_ROOM_ID).unwrap();
+    let send_queue = room.send_queue();
+    let mut updates = send_queue.subscribe();
+
+    let txn_id =
+        send_queue.send(RoomMessageEventContent::text_plain("Hello").into()).await.unwrap();
+    assert_matches!(updates.recv().await.unwrap(), RoomSendQueueUpdate::NewLocalEvent(_));
+
+    // The error can't be recovered from, so the request is wedged.
+    assert_matches!(
+        updates.recv().await.unwrap(),
+        RoomSendQueueUpdate::SendError { transaction_id, is_recoverable: false, .. } => {
+            assert_eq!(transaction_id, txn_id);
+        }
+    );
+
+    // A request queued after the wedged one is blocked by it.
+    let other_txn_id =
+        send_queue.send(RoomMessageEventContent::text_plain("World").into()).await.unwrap();
+    assert_matches!(updates.recv().await.unwrap(), RoomSendQueueUpdate::NewLocalEvent(_));
+
+    let local_echoes = send_queue.local_echoes().await.unwrap();
+    assert_eq!(local_echoes.len(), 2);
+    assert!(local_echoes[0].is_wedged);
+    assert!(!local_echoes[1].is_wedged);
+
+    // Retry it.
+    assert!(send_queue.unwedge(&txn_id).await.unwrap());
+
+    assert_matches!(
+        updates.recv().await.unwrap(),
+        RoomSendQueueUpdate::RetryEvent { transaction_id } => {
+            assert_eq!(transaction_id, txn_id);
+        }
+    );
+    // The request kept its position in the queue, so it is sent first.
+    assert_matches!(
+        updates.recv().await.unwrap(),
+        RoomSendQueueUpdate::SentEvent { transaction_id, .. } => {
+            assert_eq!(transaction_id, txn_id);
+        }
+    );
+    assert_matches!(
+        updates.recv().await.unwrap(),
+        RoomSendQueueUpdate::SentEvent { transaction_id, .. } => {
+            assert_eq!(transaction_id, other_txn_id);
+        }
+    );
+
+    assert!(send_queue.local_echoes().await.unwrap().is_empty());
+}
+
+#[async_test]
+async fn queued_event_is_cancelled() {
+    let (client, server) = synced_client().await;
+    mock_encryption_state(&server, false).await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EVENT_ID))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    // Hold the requests back while we cancel it.
+    client.send_queue().set_enabled(false);
+
+    let room = client.get_joined_room(&test_json::DEFAULT_SYNC_ROOM_ID).unwrap();
+    let send_queue = room.send_queue();
+    let mut updates = send_queue.subscribe();
+
+    let txn_id =
+        send_queue.send(RoomMessageEventContent::text_plain("Hello").into()).await.unwrap();
+    assert_matches!(updates.recv().await.unwrap(), RoomSendQueueUpdate::NewLocalEvent(_));
+
+    assert!(send_queue.cancel(&txn_id).await.unwrap());
+    assert_matches!(
+        updates.recv().await.unwrap(),
+        RoomSendQueueUpdate::CancelledLocalEvent { transaction_id } => {
+            assert_eq!(transaction_id, txn_id);
+        }
+    );
+
+    // It can't be cancelled twice.
+    assert!(!send_queue.cancel(&txn_id).await.unwrap());
+
+    client.send_queue().set_enabled(true);
+    assert!(send_queue.local_echoes().await.unwrap().is_empty());
+}
+
+#[async_test]
+async fn event_is_dropped_when_room_is_not_joined() {
+    let (client, server) = logged_in_client().await;
+    mock_sync(&server, &*test_json::LEAVE_SYNC, None).await;
+    client.sync_once(SyncSettings::new()).await.unwrap();
+
+    let room = client.get_left_room(&test_json::DEFAULT_SYNC_ROOM_ID).unwrap();
+    let send_queue = room.send_queue();
+    let mut updates = send_queue.subscribe();
+
+    let txn_id =
+        send_queue.send(RoomMessageEventContent::text_plain("Hello").into()).await.unwrap();
+    assert_matches!(updates.recv().await.unwrap(), RoomSendQueueUpdate::NewLocalEvent(_));
+
+    assert_matches!(
+        updates.recv().await.unwrap(),
+        RoomSendQueueUpdate::SendError { transaction_id, error, is_recoverable: false } => {
+            assert_eq!(transaction_id, txn_id);
+            assert_matches!(*error, Error::SendQueue(RoomSendQueueError::RoomNotJoined));
+        }
+    );
+
+    // The request doesn't block the queue.
+    assert!(send_queue.local_echoes().await.unwrap().is_empty());
+}
@@ -0,0 +1,743 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use matrix_sdk::{
+    config::SyncSettings,
+    room::Joined,
+    widget_api::{
+        api::widget::{Comm, Info, Widget},
+        messages::capabilities::Options,
+        revoke_capabilities, run_client_widget_api, CapabilitiesRequest, ClientProperties,
+        PermissionProvider, Result as WidgetResult, WidgetSettings,
+    },
+    Client,
+};
+use matrix_sdk_test::{
+    async_test, test_json, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder,
+    TimelineTestEvent,
+};
+use ruma::{room_id, RoomId};
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use wiremock::{
+    matchers::{body_partial_json, header, method, path_regex, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::{logged_in_client, mock_sync};
+
+const WIDGET_ID: &str = "test-widget";
+
+fn widget_info() -> Info {
+    Info { id: WIDGET_ID.to_owned(), origin: "https://example.org".to_owned(), init_on_load: true }
+}
+
+/// Grants every capability the widget asks for, and remembers the prompts.
+#[derive(Clone, Default)]
+struct AllowAll {
+    prompts: Arc<Mutex<Vec<CapabilitiesRequest>>>,
+}
+
+impl AllowAll {
+    fn prompts(&self) -> Vec<CapabilitiesRequest> {
+        self.prompts.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl PermissionProvider for AllowAll {
+    async fn acquire_permissions(&self, request: CapabilitiesRequest) -> WidgetResult<Options> {
+        let capabilities = request.requested().clone();
+        self.prompts.lock().unwrap().push(request);
+        Ok(capabilities)
+    }
+}
+
+/// The widget side of the widget API, driven by the tests.
+struct MockWidget {
+    to_client: UnboundedSender<String>,
+    from_client: UnboundedReceiver<String>,
+    next_request_id: u32,
+    /// The state that was pushed to the widget once the capabilities were negotiated.
+    initial_state: Option<JsonValue>,
+    /// The messages of the client that were received while waiting for another one.
+    pending: VecDeque<JsonValue>,
+}
+
+impl MockWidget {
+    /// Starts the widget API for the given room and negotiates the given capabilities.
+    async fn start(room: Joined, capabilities: JsonValue) -> Self {
+        Self::start_with_permissions(room, capabilities, AllowAll::default()).await
+    }
+
+    /// Starts the widget API for the given room and negotiates the given capabilities with the
+    /// given permission provider.
+    async fn start_with_permissions(
+        room: Joined,
+        capabilities: JsonValue,
+        permissions: AllowAll,
+    ) -> Self {
+        Self::start_with_info(room, widget_info(), capabilities, permissions).await
+    }
+
+    /// Starts the widget API for the widget with the given info.
+    async fn start_with_info(
+        room: Joined,
+        info: Info,
+        capabilities: JsonValue,
+        permissions: AllowAll,
+    ) -> Self {
+        let (to_client, from) = unbounded_channel();
+        let (to, from_client) = unbounded_channel();
+        let widget = Widget { info, comm: Comm { from, to } };
+        tokio::spawn(run_client_widget_api(widget, permissions, room));
+
+        let mut widget = Self {
+            to_client,
+            from_client,
+            next_request_id: 0,
+            initial_state: None,
+            pending: VecDeque::new(),
+        };
+        let content_loaded = widget.send_request("content_loaded", json!({}));
+
+        let request = widget.recv_request("capabilities").await;
+        widget.reply(request, json!({ "capabilities": capabilities }));
+
+        let request = widget.recv_request("notify_capabilities").await;
+        assert_eq!(request["data"]["approved"], capabilities);
+        widget.reply(request, json!({}));
+
+        // Some capabilities are served by background tasks, e.g. the TURN servers, so their
+        // requests can come before or after the response.
+        let mut pending = VecDeque::new();
+        loop {
+            let message = widget.recv().await;
+            if message["api"] == "fromWidget" {
+                assert_eq!(message["header"]["request_id"], content_loaded);
+                break;
+            }
+
+            if message["action"] == "update_state" && widget.initial_state.is_none() {
+                widget.initial_state = Some(message["data"]["state"].clone());
+                widget.reply(message, json!({}));
+            } else {
+                pending.push_back(message);
+            }
+        }
+        widget.pending = pending;
+
+        widget
+    }
+
+    fn send(&self, message: JsonValue) {
+        self.to_client.send(message.to_string()).unwrap();
+    }
+
+    async fn recv(&mut self) -> JsonValue {
+        if let Some(message) = self.pending.pop_front() {
+            return message;
+        }
+
+        let raw = self.from_client.recv().await.expect("The widget API should still be running");
+        serde_json::from_str(&raw).unwrap()
+    }
+
+    /// Sends a `fromWidget` request and returns its ID.
+    fn send_request(&mut self, action: &str, data: JsonValue) -> String {
+        self.next_request_id += 1;
+        let request_id = format!("widget-{}", self.next_request_id);
+        self.send(json!({
+            "api": "fromWidget",
+            "action": action,
+            "header": { "request_id": request_id, "widget_id": WIDGET_ID },
+            "data": data,
+        }));
+
+        request_id
+    }
+
+    /// Sends a `fromWidget` request and waits for the response of the client.
+    async fn request(&mut self, action: &str, data: JsonValue) -> JsonValue {
+        let request_id = self.send_request(action, data);
+
+        let message = self.recv().await;
+        assert_eq!(message["api"], "fromWidget");
+        assert_eq!(message["action"], action);
+        assert_eq!(message["header"]["request_id"], request_id);
+
+        message["response"].clone()
+    }
+
+    /// Waits for a `toWidget` request of the client.
+    async fn recv_request(&mut self, action: &str) -> JsonValue {
+        let message = self.recv().await;
+        assert_eq!(message["api"], "toWidget");
+        assert_eq!(message["action"], action);
+
+        message
+    }
+
+    fn reply(&self, mut request: JsonValue, response: JsonValue) {
+        request["response"] = response;
+        self.send(request);
+    }
+}
+
+async fn synced_room(client: &Client, server: &MockServer, room_id: &RoomId) -> (Joined, String) {
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_state_event(StateTestEvent::Member)
+            .add_state_event(StateTestEvent::RoomTopic),
+    );
+    mock_sync(server, ev_builder.build_json_sync_response(), None).await;
+
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+    let sync_token = client.sync_once(sync_settings).await.unwrap().next_batch;
+
+    (client.get_joined_room(room_id).unwrap(), sync_token)
+}
+
+#[async_test]
+async fn read_state_events_from_store() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+    let (room, _) = synced_room(&client, &server, room_id).await;
+
+    // The state is read from the store, not from the homeserver.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let capabilities = json!(["org.matrix.msc2762.m.receive.state_event:m.room.topic"]);
+    let mut widget = MockWidget::start(room, capabilities).await;
+
+    let initial_state = widget.initial_state.clone().unwrap();
+    assert_eq!(initial_state.as_array().unwrap().len(), 1);
+    assert_eq!(initial_state[0]["type"], "m.room.topic");
+    assert_eq!(initial_state[0]["room_id"], room_id.as_str());
+    assert_eq!(initial_state[0]["content"]["topic"], "😀");
+
+    let response = widget
+        .request(
+            "org.matrix.msc2876.read_events",
+            json!({ "type": "m.room.topic", "state_key": true, "limit": 10 }),
+        )
+        .await;
+    let events = response["events"].as_array().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["content"]["topic"], "😀");
+
+    let response = widget
+        .request(
+            "org.matrix.msc2876.read_events",
+            json!({ "type": "m.room.topic", "state_key": "", "limit": 10 }),
+        )
+        .await;
+    assert_eq!(response["events"].as_array().unwrap().len(), 1);
+
+    // The widget isn't allowed to read the members of the room.
+    let response = widget
+        .request(
+            "org.matrix.msc2876.read_events",
+            json!({ "type": "m.room.member", "state_key": true, "limit": 10 }),
+        )
+        .await;
+    assert_eq!(response["events"].as_array().unwrap().len(), 0);
+}
+
+#[async_test]
+async fn push_state_updates() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+    let (room, sync_token) = synced_room(&client, &server, room_id).await;
+
+    let capabilities = json!(["org.matrix.msc2762.m.receive.state_event:m.room.topic"]);
+    let mut widget = MockWidget::start(room, capabilities).await;
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(TimelineTestEvent::Custom(json!({
+                "content": { "name": "Not for the widget" },
+                "event_id": "$name",
+                "origin_server_ts": 152037290,
+                "sender": "@example:localhost",
+                "state_key": "",
+                "type": "m.room.name",
+            })))
+            .add_timeline_event(TimelineTestEvent::Custom(json!({
+                "content": { "topic": "New topic" },
+                "event_id": "$topic",
+                "origin_server_ts": 152037280,
+                "sender": "@example:localhost",
+                "state_key": "",
+                "type": "m.room.topic",
+            }))),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), Some(sync_token.clone())).await;
+    client.sync_once(SyncSettings::new().token(sync_token)).await.unwrap();
+
+    let request = widget.recv_request("update_state").await;
+    let state = request["data"]["state"].as_array().unwrap();
+    assert_eq!(state.len(), 1);
+    assert_eq!(state[0]["event_id"], "$topic");
+    assert_eq!(state[0]["room_id"], room_id.as_str());
+    assert_eq!(state[0]["content"]["topic"], "New topic");
+    widget.reply(request, json!({}));
+
+    // The store has been updated as well.
+    let response = widget
+        .request(
+            "org.matrix.msc2876.read_events",
+            json!({ "type": "m.room.topic", "state_key": "", "limit": 1 }),
+        )
+        .await;
+    assert_eq!(response["events"][0]["content"]["topic"], "New topic");
+}
+
+#[async_test]
+async fn read_relations() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+    let (room, _) = synced_room(&client, &server, room_id).await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/rooms/.*/relations/.*/m\.annotation$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("dir", "f"))
+        .and(query_param("from", "prev_token"))
+        .and(query_param("limit", "10"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [
+                {
+                    "content": {
+                        "m.relates_to": {
+                            "event_id": "$parent",
+                            "key": "👍",
+                            "rel_type": "m.annotation",
+                        },
+                    },
+                    "event_id": "$reaction",
+                    "origin_server_ts": 152037280,
+                    "room_id": room_id,
+                    "sender": "@bob:localhost",
+                    "type": "m.reaction",
+                },
+                {
+                    "content": {
+                        "body": "Not for the widget",
+                        "msgtype": "m.text",
+                        "m.relates_to": {
+                            "event_id": "$parent",
+                            "rel_type": "m.annotation",
+                        },
+                    },
+                    "event_id": "$message",
+                    "origin_server_ts": 152037290,
+                    "room_id": room_id,
+                    "sender": "@bob:localhost",
+                    "type": "m.room.message",
+                },
+            ],
+            "next_batch": "next_token",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let capabilities = json!(["org.matrix.msc2762.m.receive.event:m.reaction"]);
+    let mut widget = MockWidget::start(room, capabilities).await;
+    assert!(widget.initial_state.is_none());
+
+    let response = widget
+        .request(
+            "org.matrix.msc3869.read_relations",
+            json!({
+                "event_id": "$parent",
+                "rel_type": "m.annotation",
+                "direction": "f",
+                "from": "prev_token",
+                "limit": 10,
+            }),
+        )
+        .await;
+
+    let chunk = response["chunk"].as_array().unwrap();
+    assert_eq!(chunk.len(), 1);
+    assert_eq!(chunk[0]["event_id"], "$reaction");
+    assert_eq!(response["next_batch"], "next_token");
+    assert!(response.get("prev_batch").is_none());
+
+    // Widgets can't read the relations of other rooms.
+    let response = widget
+        .request(
+            "org.matrix.msc3869.read_relations",
+            json!({ "event_id": "$parent", "room_id": "!other:localhost" }),
+        )
+        .await;
+    assert!(response["error"]["message"].is_string());
+}
+
+#[async_test]
+async fn widget_settings_from_room() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Member).add_state_event(
+            StateTestEvent::Custom(json!({
+                "content": {
+                    "type": "m.custom",
+                    "url": "https://example.org/widget?user=$matrix_user_id&room=$matrix_room_id\
+                            &name=$matrix_display_name&avatar=$matrix_avatar_url\
+                            &client=$org.matrix.msc2873.client_id\
+                            #?theme=$org.matrix.msc2873.client_theme\
+                            &lang=$org.matrix.msc2873.client_language",
+                    "name": "Widget",
+                    "data": {},
+                },
+                "event_id": "$widget",
+                "origin_server_ts": 152037280,
+                "sender": "@example:localhost",
+                "state_key": "widget-1",
+                "type": "m.widget",
+            })),
+        ),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::new()).await.unwrap();
+    let room = client.get_joined_room(room_id).unwrap();
+
+    let widgets = WidgetSettings::from_room(&room).await.unwrap();
+    assert_eq!(widgets.len(), 1);
+    let widget = &widgets[0];
+    assert_eq!(widget.id(), "widget-1");
+    assert!(!widget.init_on_load());
+
+    let props = ClientProperties::new("org.example.client", None, Some("dark".to_owned()));
+    let url = widget.generate_webview_url(&room, props).await.unwrap();
+    assert_eq!(
+        url.as_str(),
+        "https://example.org/widget?user=%40example%3Alocalhost&room=%21widget%3Alocalhost\
+         &name=example&avatar=&client=org.example.client#?theme=dark&lang=en-US"
+    );
+}
+
+#[async_test]
+async fn granted_capabilities_are_persisted() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+    let (room, _) = synced_room(&client, &server, room_id).await;
+
+    let permissions = AllowAll::default();
+    let capabilities = json!(["org.matrix.msc2762.m.receive.event:m.reaction"]);
+    MockWidget::start_with_permissions(room.clone(), capabilities.clone(), permissions.clone())
+        .await;
+
+    let prompts = permissions.prompts();
+    assert_eq!(prompts.len(), 1);
+    assert_eq!(prompts[0].widget_id(), WIDGET_ID);
+    assert_eq!(prompts[0].room_id(), room_id);
+    assert!(prompts[0].previously_granted().is_none());
+    assert_eq!(prompts[0].new_capabilities(), ["org.matrix.msc2762.m.receive.event:m.reaction"]);
+
+    // The user isn't asked again for the same capabilities.
+    MockWidget::start_with_permissions(room.clone(), capabilities.clone(), permissions.clone())
+        .await;
+    assert_eq!(permissions.prompts().len(), 1);
+
+    // Only the capabilities that weren't granted yet are new.
+    let capabilities = json!([
+        "org.matrix.msc2762.m.receive.state_event:m.room.topic",
+        "org.matrix.msc2762.m.receive.event:m.reaction",
+    ]);
+    MockWidget::start_with_permissions(room.clone(), capabilities.clone(), permissions.clone())
+        .await;
+    let prompts = permissions.prompts();
+    assert_eq!(prompts.len(), 2);
+    assert!(prompts[1].previously_granted().is_some());
+    assert_eq!(
+        prompts[1].new_capabilities(),
+        ["org.matrix.msc2762.m.receive.state_event:m.room.topic"]
+    );
+    assert!(prompts[1].dropped_capabilities().is_empty());
+
+    // A widget with the same ID served from another origin doesn't get the capabilities.
+    let info = Info { origin: "https://example.com".to_owned(), ..widget_info() };
+    MockWidget::start_with_info(room.clone(), info, capabilities.clone(), permissions.clone())
+        .await;
+    let prompts = permissions.prompts();
+    assert_eq!(prompts.len(), 3);
+    assert!(prompts[2].previously_granted().is_none());
+
+    // Once revoked, the user is asked again.
+    revoke_capabilities(&room, &widget_info()).await.unwrap();
+    MockWidget::start_with_permissions(room, capabilities, permissions.clone()).await;
+    let prompts = permissions.prompts();
+    assert_eq!(prompts.len(), 4);
+    assert!(prompts[3].previously_granted().is_none());
+}
+
+#[async_test]
+async fn revoke_capabilities_of_running_widget() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+    let (room, _) = synced_room(&client, &server, room_id).await;
+
+    let capabilities = json!(["org.matrix.msc2762.m.receive.state_event:m.room.topic"]);
+    let mut widget = MockWidget::start(room.clone(), capabilities.clone()).await;
+
+    revoke_capabilities(&room, &widget_info()).await.unwrap();
+
+    // The widget is told that none of its capabilities are approved anymore.
+    let request = widget.recv_request("notify_capabilities").await;
+    assert_eq!(request["data"]["requested"], capabilities);
+    assert_eq!(request["data"]["approved"], json!([]));
+    widget.reply(request, json!({}));
+
+    let response = widget
+        .request(
+            "org.matrix.msc2876.read_events",
+            json!({ "type": "m.room.topic", "state_key": true, "limit": 10 }),
+        )
+        .await;
+    assert!(response["error"]["message"].is_string());
+}
+
+#[async_test]
+async fn request_capabilities() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+    let (room, _) = synced_room(&client, &server, room_id).await;
+
+    let permissions = AllowAll::default();
+    let capabilities = json!(["org.matrix.msc2762.m.receive.event:m.reaction"]);
+    let mut widget =
+        MockWidget::start_with_permissions(room, capabilities, permissions.clone()).await;
+
+    let request_id = widget.send_request(
+        "org.matrix.msc2974.request_capabilities",
+        json!({ "capabilities": ["org.matrix.msc2762.m.receive.state_event:m.room.topic"] }),
+    );
+
+    // The capabilities are renegotiated with both the previous and the new ones.
+    let request = widget.recv_request("notify_capabilities").await;
+    let all_capabilities = json!([
+        "org.matrix.msc2762.m.receive.event:m.reaction",
+        "org.matrix.msc2762.m.receive.state_event:m.room.topic",
+    ]);
+    assert_eq!(request["data"]["requested"], all_capabilities);
+    assert_eq!(request["data"]["approved"], all_capabilities);
+    widget.reply(request, json!({}));
+
+    let prompts = permissions.prompts();
+    assert_eq!(prompts.len(), 2);
+    assert_eq!(
+        prompts[1].new_capabilities(),
+        ["org.matrix.msc2762.m.receive.state_event:m.room.topic"]
+    );
+
+    // The widget is allowed to read the topic now.
+    let request = widget.recv_request("update_state").await;
+    assert_eq!(request["data"]["state"][0]["content"]["topic"], "😀");
+    widget.reply(request, json!({}));
+
+    let message = widget.recv().await;
+    assert_eq!(message["api"], "fromWidget");
+    assert_eq!(message["header"]["request_id"], request_id);
+    assert_eq!(message["response"], json!({}));
+}
+
+#[async_test]
+async fn send_to_device() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+    let (room, _) = synced_room(&client, &server, room_id).await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/sendToDevice/org\.example\.ping/"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({
+            "messages": { "@bob:localhost": { "*": { "ping": 1 } } },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let capabilities = json!(["org.matrix.msc3819.send.to_device:org.example.ping"]);
+    let mut widget = MockWidget::start(room, capabilities).await;
+
+    let response = widget
+        .request(
+            "send_to_device",
+            json!({
+                "type": "org.example.ping",
+                "encrypted": false,
+                "messages": { "@bob:localhost": { "*": { "ping": 1 } } },
+            }),
+        )
+        .await;
+    assert_eq!(response, json!({}));
+
+    // The widget isn't allowed to send other types of to-device messages.
+    let response = widget
+        .request(
+            "send_to_device",
+            json!({
+                "type": "org.example.other",
+                "encrypted": false,
+                "messages": { "@bob:localhost": { "*": { "ping": 1 } } },
+            }),
+        )
+        .await;
+    assert!(response["error"]["message"].is_string());
+}
+
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn send_encrypted_to_device_skips_devices_without_session() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/keys/upload"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::KEYS_UPLOAD))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/keys/query"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::KEYS_QUERY))
+        .mount(&server)
+        .await;
+    // The homeserver doesn't have any one-time key for the device of Alice.
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/keys/claim"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "one_time_keys": {}, "failures": {} })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+    // So there's nothing to send.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/sendToDevice/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    // Alice is a member of the encrypted room, so we learn about her devices.
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_state_event(StateTestEvent::Member)
+            .add_state_event(StateTestEvent::Encryption)
+            .add_state_event(StateTestEvent::Custom(json!({
+                "content": { "membership": "join" },
+                "event_id": "$alice",
+                "origin_server_ts": 151800140,
+                "sender": "@alice:example.org",
+                "state_key": "@alice:example.org",
+                "type": "m.room.member",
+            }))),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::new()).await.unwrap();
+    let room = client.get_joined_room(room_id).unwrap();
+
+    let capabilities = json!(["org.matrix.msc3819.send.to_device:org.example.ping"]);
+    let mut widget = MockWidget::start(room, capabilities).await;
+
+    // The device without an Olm session doesn't make the whole request fail.
+    let response = widget
+        .request(
+            "send_to_device",
+            json!({
+                "type": "org.example.ping",
+                "encrypted": true,
+                "messages": { "@alice:example.org": { "JLAFKJWSCS": { "ping": 1 } } },
+            }),
+        )
+        .await;
+    assert_eq!(response, json!({}));
+}
+
+#[async_test]
+async fn receive_to_device() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+    let (room, sync_token) = synced_room(&client, &server, room_id).await;
+
+    let capabilities = json!(["org.matrix.msc3819.receive.to_device:org.example.ping"]);
+    let mut widget = MockWidget::start(room, capabilities).await;
+
+    let mut sync = SyncResponseBuilder::new().build_json_sync_response();
+    sync["to_device"]["events"] = json!([
+        {
+            "content": { "secret": "Not for the widget" },
+            "sender": "@bob:localhost",
+            "type": "org.example.other",
+        },
+        {
+            "content": { "ping": 1 },
+            "sender": "@bob:localhost",
+            "type": "org.example.ping",
+        },
+    ]);
+    mock_sync(&server, sync, Some(sync_token.clone())).await;
+    client.sync_once(SyncSettings::new().token(sync_token)).await.unwrap();
+
+    let request = widget.recv_request("send_to_device").await;
+    assert_eq!(request["data"]["type"], "org.example.ping");
+    assert_eq!(request["data"]["sender"], "@bob:localhost");
+    assert_eq!(request["data"]["encrypted"], false);
+    assert_eq!(request["data"]["content"]["ping"], 1);
+    widget.reply(request, json!({}));
+}
+
+#[async_test]
+async fn push_turn_servers() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!widget:localhost");
+    let (room, _) = synced_room(&client, &server, room_id).await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/voip/turnServer"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uris": ["turn:turn.example.org:3478?transport=udp"],
+            "username": "1443779631:@example:localhost",
+            "password": "JlKfBy1QwLrO20385QyAtEyIv0=",
+            "ttl": 2,
+        })))
+        .mount(&server)
+        .await;
+
+    let capabilities = json!(["town.robin.msc3846.turn_servers"]);
+    let mut widget = MockWidget::start(room, capabilities).await;
+
+    let request = widget.recv_request("update_turn_servers").await;
+    assert_eq!(request["data"]["uris"], json!(["turn:turn.example.org:3478?transport=udp"]));
+    assert_eq!(request["data"]["username"], "1443779631:@example:localhost");
+    assert_eq!(request["data"]["password"], "JlKfBy1QwLrO20385QyAtEyIv0=");
+    widget.reply(request, json!({}));
+
+    // The credentials expire after two seconds, so they're pushed again before that.
+    let request =
+        tokio::time::timeout(Duration::from_secs(2), widget.recv_request("update_turn_servers"))
+            .await
+            .expect("The TURN servers should be refreshed before they expire");
+    widget.reply(request, json!({}));
+}
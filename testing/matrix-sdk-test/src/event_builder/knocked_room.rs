@@ -0,0 +1,43 @@
+use ruma::{
+    api::client::sync::sync_events::v3::KnockedRoom, events::AnyStrippedStateEvent, serde::Raw,
+    OwnedRoomId,
+};
+
+use super::StrippedStateTestEvent;
+use crate::test_json;
+
+pub struct KnockedRoomBuilder {
+    pub(super) room_id: OwnedRoomId,
+    pub(super) inner: KnockedRoom,
+}
+
+impl KnockedRoomBuilder {
+    /// Create a new `KnockedRoomBuilder` for the given room ID.
+    ///
+    /// If the room ID is [`test_json::DEFAULT_SYNC_ROOM_ID`],
+    /// [`KnockedRoomBuilder::default()`] can be used instead.
+    pub fn new(room_id: impl Into<OwnedRoomId>) -> Self {
+        Self { room_id: room_id.into(), inner: Default::default() }
+    }
+
+    /// Add an event to the state.
+    pub fn add_state_event(mut self, event: StrippedStateTestEvent) -> Self {
+        self.inner.knock_state.events.push(event.into_raw_event());
+        self
+    }
+
+    /// Add events to the state in bulk.
+    pub fn add_state_bulk<I>(mut self, events: I) -> Self
+    where
+        I: IntoIterator<Item = Raw<AnyStrippedStateEvent>>,
+    {
+        self.inner.knock_state.events.extend(events);
+        self
+    }
+}
+
+impl Default for KnockedRoomBuilder {
+    fn default() -> Self {
+        Self::new(test_json::DEFAULT_SYNC_ROOM_ID.to_owned())
+    }
+}
@@ -10,8 +10,9 @@ pub mod test_json;
 
 pub use event_builder::{
     bulk_room_members, EphemeralTestEvent, GlobalAccountDataTestEvent, InvitedRoomBuilder,
-    JoinedRoomBuilder, LeftRoomBuilder, PresenceTestEvent, RoomAccountDataTestEvent,
-    StateTestEvent, StrippedStateTestEvent, SyncResponseBuilder, TimelineTestEvent,
+    JoinedRoomBuilder, KnockedRoomBuilder, LeftRoomBuilder, PresenceTestEvent,
+    RoomAccountDataTestEvent, StateTestEvent, StrippedStateTestEvent, SyncResponseBuilder,
+    TimelineTestEvent,
 };
 
 /// Embedded sync response files
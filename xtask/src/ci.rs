@@ -70,6 +70,7 @@ enum FeatureSet {
     Markdown,
     Socks,
     SsoLogin,
+    ExperimentalOidc,
 }
 
 #[derive(Subcommand, PartialEq, Eq, PartialOrd, Ord)]
@@ -208,6 +209,7 @@ fn run_feature_tests(cmd: Option<FeatureSet>) -> Result<()> {
         (FeatureSet::Markdown, "--features markdown,testing"),
         (FeatureSet::Socks, "--features socks,testing"),
         (FeatureSet::SsoLogin, "--features sso-login,testing"),
+        (FeatureSet::ExperimentalOidc, "--features experimental-oidc,testing"),
     ]);
 
     let run = |arg_set: &str| {